@@ -86,6 +86,7 @@ pub async fn run(config: Arc<Config>) -> anyhow::Result<Bound> {
 		std::mem::drop(state_mgr_task);
 	});
 	let stores = state_mgr.stores();
+	let local_client = state_mgr.local_client();
 	// Run the XDS state manager in the current tokio worker pool.
 	tokio::spawn(state_mgr.run());
 
@@ -97,6 +98,9 @@ pub async fn run(config: Arc<Config>) -> anyhow::Result<Bound> {
 	)
 	.await
 	.context("admin server starts")?;
+	if let Some(local_client) = local_client {
+		admin_server.set_local_client(local_client);
+	}
 	#[cfg(feature = "ui")]
 	admin_server.set_admin_handler(Arc::new(crate::ui::UiHandler::new(config.clone())));
 
@@ -109,6 +113,7 @@ pub async fn run(config: Arc<Config>) -> anyhow::Result<Bound> {
 		metrics: Arc::new(crate::metrics::Metrics::new(sub_registry)),
 		upstream: client.clone(),
 		ca,
+		coalescer: Default::default(),
 
 		mcp_state: mcp::sse::App::new(
 			stores.clone(),
@@ -39,12 +39,18 @@ impl<T: Copy> CircularBuffer<T> {
 		let current = self.index.fetch_add(1, Ordering::Relaxed);
 		Some(self.data[current % self.data.len()])
 	}
+
+	fn all(&self) -> Box<[T]> {
+		self.data.clone()
+	}
 }
 
 #[derive(Debug, Clone)]
 pub struct CachedResolver {
 	dns: Arc<Resolver>,
 	entries: Arc<Mutex<HashMap<Strng, Arc<CacheEntry>>>>,
+	// How long past their expiry records are still served when refreshing them fails.
+	serve_stale: Duration,
 }
 
 #[derive(Debug)]
@@ -63,9 +69,12 @@ impl CacheEntry {
 		name: Strng,
 		resolver: Arc<Resolver>,
 		cache: Arc<Mutex<HashMap<Strng, Arc<CacheEntry>>>>,
+		serve_stale: Duration,
 	) {
 		self.active.store(true, Ordering::Relaxed);
 
+		// Expiry of the last successful answer
+		let mut last_expiry: Option<Instant> = None;
 		loop {
 			// Mark this is inactive, so we can see if there are any request before the next refresh timer.
 			let was_active = self.active.swap(false, Ordering::Relaxed);
@@ -81,15 +90,23 @@ impl CacheEntry {
 				Ok((ips, expiry)) => {
 					let cb = CircularBuffer::new(ips);
 					self.entries.store(Some(Arc::new(cb)));
+					last_expiry = Some(expiry);
 					expiry
 				},
 				Err(e) => {
-					let cb = CircularBuffer::new(Default::default());
-					// We got a result, its just empty
-					self.entries.store(Some(Arc::new(cb)));
-					// if we got an error, retain the last state
 					debug!("resolution failed: {e:?}");
-					Instant::now() + ERROR_BACKOFF
+					// Use tokio's clock, so this follows paused time in tests
+					let now = tokio::time::Instant::now().into_std();
+					let stale_ok = last_expiry.is_some_and(|expiry| now < expiry + serve_stale);
+					if stale_ok {
+						// Keep serving the last answer, it is likely still better than nothing
+						debug!("serving stale records for {name}");
+					} else {
+						let cb = CircularBuffer::new(Default::default());
+						// We got a result, its just empty
+						self.entries.store(Some(Arc::new(cb)));
+					}
+					now + ERROR_BACKOFF
 				},
 			};
 			// NB: this will run even on error, so the first fetch for a failed response will hit this and
@@ -100,21 +117,25 @@ impl CacheEntry {
 	}
 
 	pub async fn next(&self) -> Option<IpAddr> {
+		self.load(|cb| cb.get_and_advance()).await.flatten()
+	}
+
+	pub async fn all(&self) -> Option<Box<[IpAddr]>> {
+		self.load(|cb| cb.all()).await
+	}
+
+	async fn load<R>(&self, f: impl Fn(&CircularBuffer<IpAddr>) -> R) -> Option<R> {
 		// Mark as active
 		self.active.store(true, Ordering::Relaxed);
 		// Is there an entry right now? If so return it.
 		let notify = self.notify.notified();
 		if let Some(entry) = self.entries.load().as_ref() {
-			return entry.get_and_advance();
+			return Some(f(entry));
 		}
 		// Wait until a change happens
 		notify.await;
 		// Now attempt to load or return None if there is nothing available
-		self
-			.entries
-			.load()
-			.as_ref()
-			.and_then(|cb| cb.get_and_advance())
+		self.entries.load().as_ref().map(|cb| f(cb))
 	}
 }
 
@@ -141,7 +162,7 @@ impl Resolver {
 }
 
 impl CachedResolver {
-	pub fn new(config: ResolverConfig, mut opts: ResolverOpts) -> Self {
+	pub fn new(config: ResolverConfig, mut opts: ResolverOpts, serve_stale: Duration) -> Self {
 		let mut rb =
 			hickory_resolver::Resolver::builder_with_config(config, TokioConnectionProvider::default());
 		*rb.options_mut() = opts;
@@ -149,43 +170,52 @@ impl CachedResolver {
 		CachedResolver {
 			entries: Arc::new(Mutex::new(HashMap::new())),
 			dns: Arc::new(Resolver::Real(dns_resolver)),
+			serve_stale,
 		}
 	}
 
 	pub async fn resolve(&self, name: Strng) -> anyhow::Result<IpAddr> {
-		// Check if we already have an entry
-		let entry = {
-			let mut cache = self.entries.lock().unwrap();
-			let existing_entry = cache.get(&name).cloned();
-			if let Some(entry) = existing_entry {
-				// Mark as active and return next IP
-				entry.active.store(true, Ordering::Relaxed);
-				entry
-			} else {
-				let entry = Arc::new(CacheEntry {
-					active: AtomicBool::new(false),
-					entries: Default::default(),
-					notify: Default::default(),
-					background_task: Default::default(),
-					valid_after: arc_swap::ArcSwap::from_pointee(Instant::now()),
-				});
-
-				cache.insert(name.clone(), entry.clone());
-				// Start background task
-				let bg_entry = entry.clone();
-				let dns = self.dns.clone();
-				let cache = self.entries.clone();
-				let handle = tokio::task::spawn(async move {
-					bg_entry.background(name, dns, cache).await;
-				});
-				entry.background_task.store(Some(Arc::new(handle)));
-
-				entry
-			}
-		};
-
 		// Return next IP
-		entry.next().await.ok_or(anyhow!("no ip"))
+		self.entry(name).next().await.ok_or(anyhow!("no ip"))
+	}
+
+	/// Resolves every address for the name, in the order the resolver returned them. Unlike resolve,
+	/// this does not advance the round robin.
+	pub async fn resolve_all(&self, name: Strng) -> anyhow::Result<Box<[IpAddr]>> {
+		match self.entry(name).all().await {
+			Some(ips) if !ips.is_empty() => Ok(ips),
+			_ => Err(anyhow!("no ip")),
+		}
+	}
+
+	fn entry(&self, name: Strng) -> Arc<CacheEntry> {
+		// Check if we already have an entry
+		let mut cache = self.entries.lock().unwrap();
+		if let Some(entry) = cache.get(&name) {
+			// Mark as active
+			entry.active.store(true, Ordering::Relaxed);
+			return entry.clone();
+		}
+		let entry = Arc::new(CacheEntry {
+			active: AtomicBool::new(false),
+			entries: Default::default(),
+			notify: Default::default(),
+			background_task: Default::default(),
+			valid_after: arc_swap::ArcSwap::from_pointee(Instant::now()),
+		});
+
+		cache.insert(name.clone(), entry.clone());
+		// Start background task
+		let bg_entry = entry.clone();
+		let dns = self.dns.clone();
+		let cache = self.entries.clone();
+		let serve_stale = self.serve_stale;
+		let handle = tokio::task::spawn(async move {
+			bg_entry.background(name, dns, cache, serve_stale).await;
+		});
+		entry.background_task.store(Some(Arc::new(handle)));
+
+		entry
 	}
 }
 
@@ -25,6 +25,10 @@ impl Mock {
 		responses.insert(host.to_string(), (ips.into_boxed_slice(), expiry));
 	}
 
+	pub fn remove_response(&self, host: &str) {
+		self.responses.lock().unwrap().remove(host);
+	}
+
 	pub async fn resolve(&self, host: &str) -> Result<(Box<[IpAddr]>, Instant), ResolveError> {
 		let responses = self.responses.lock().unwrap();
 		responses
@@ -47,6 +51,7 @@ async fn test_basic_resolution() {
 	let resolver = CachedResolver {
 		dns: Arc::new(Resolver::Mock(mock)),
 		entries: Arc::new(Mutex::new(HashMap::new())),
+		serve_stale: Duration::ZERO,
 	};
 
 	// First resolution should work
@@ -61,6 +66,25 @@ async fn test_basic_resolution() {
 	assert_eq!(ip1, ip3);
 }
 
+#[tokio::test]
+async fn test_resolve_all() {
+	let mock = Arc::new(Mock::new());
+	mock.add_response("example.com", vec![IP1, IP2, IP3], 60);
+
+	let resolver = CachedResolver {
+		dns: Arc::new(Resolver::Mock(mock)),
+		entries: Arc::new(Mutex::new(HashMap::new())),
+		serve_stale: Duration::ZERO,
+	};
+
+	let all = resolver.resolve_all("example.com".into()).await.unwrap();
+	assert_eq!(all.as_ref(), &[IP1, IP2, IP3]);
+	// Listing the addresses does not consume a turn of the round robin
+	assert_eq!(resolver.resolve("example.com".into()).await.unwrap(), IP1);
+	assert_eq!(resolver.resolve("example.com".into()).await.unwrap(), IP2);
+	assert_matches!(resolver.resolve_all("missing.com".into()).await, Err(_));
+}
+
 #[tokio::test(start_paused = true)]
 async fn test_ip_change() {
 	agent_core::telemetry::testing::setup_test_logging();
@@ -70,6 +94,7 @@ async fn test_ip_change() {
 	let resolver = CachedResolver {
 		dns: Arc::new(Resolver::Mock(mock.clone())),
 		entries: Arc::new(Mutex::new(HashMap::new())),
+		serve_stale: Duration::ZERO,
 	};
 
 	// First resolution should work
@@ -97,6 +122,7 @@ async fn test_ip_error() {
 	let resolver = CachedResolver {
 		dns: Arc::new(Resolver::Mock(mock.clone())),
 		entries: Arc::new(Mutex::new(HashMap::new())),
+		serve_stale: Duration::ZERO,
 	};
 
 	// We should get an error, no IPs yet
@@ -111,6 +137,67 @@ async fn test_ip_error() {
 	assert_eq!(resolver.resolve("example.com".into()).await.unwrap(), IP3);
 }
 
+#[tokio::test(start_paused = true)]
+async fn test_ttl_expiry() {
+	let mock = Arc::new(Mock::new());
+	mock.add_response("example.com", vec![IP1], 10);
+
+	let resolver = CachedResolver {
+		dns: Arc::new(Resolver::Mock(mock.clone())),
+		entries: Arc::new(Mutex::new(HashMap::new())),
+		serve_stale: Duration::ZERO,
+	};
+
+	assert_eq!(resolver.resolve("example.com".into()).await.unwrap(), IP1);
+	mock.add_response("example.com", vec![IP2], 10);
+	// The record is still valid, so the cached answer is used
+	tokio::time::sleep(Duration::from_secs(9)).await;
+	assert_eq!(resolver.resolve("example.com".into()).await.unwrap(), IP1);
+	// Once the TTL passes, the name is resolved again
+	tokio::time::sleep(Duration::from_secs(2)).await;
+	assert_eq!(resolver.resolve("example.com".into()).await.unwrap(), IP2);
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_serve_stale() {
+	let mock = Arc::new(Mock::new());
+	mock.add_response("example.com", vec![IP1], 10);
+
+	let resolver = CachedResolver {
+		dns: Arc::new(Resolver::Mock(mock.clone())),
+		entries: Arc::new(Mutex::new(HashMap::new())),
+		serve_stale: Duration::from_secs(30),
+	};
+
+	assert_eq!(resolver.resolve("example.com".into()).await.unwrap(), IP1);
+	mock.remove_response("example.com");
+	// Resolution fails once the record expires at 10s, but it keeps being served until 30s later.
+	// Keep requesting it, so the entry stays in the cache.
+	for _ in 0..19 {
+		tokio::time::sleep(Duration::from_secs(2)).await;
+		assert_eq!(resolver.resolve("example.com".into()).await.unwrap(), IP1);
+	}
+	tokio::time::sleep(Duration::from_secs(4)).await;
+	assert_matches!(resolver.resolve("example.com".into()).await, Err(_));
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_serve_stale_disabled() {
+	let mock = Arc::new(Mock::new());
+	mock.add_response("example.com", vec![IP1], 10);
+
+	let resolver = CachedResolver {
+		dns: Arc::new(Resolver::Mock(mock.clone())),
+		entries: Arc::new(Mutex::new(HashMap::new())),
+		serve_stale: Duration::ZERO,
+	};
+
+	assert_eq!(resolver.resolve("example.com".into()).await.unwrap(), IP1);
+	mock.remove_response("example.com");
+	tokio::time::sleep(Duration::from_secs(11)).await;
+	assert_matches!(resolver.resolve("example.com".into()).await, Err(_));
+}
+
 #[tokio::test]
 async fn test_multiple_hostnames() {
 	let mock = Arc::new(Mock::new());
@@ -120,6 +207,7 @@ async fn test_multiple_hostnames() {
 	let resolver = CachedResolver {
 		dns: Arc::new(Resolver::Mock(mock)),
 		entries: Arc::new(Mutex::new(HashMap::new())),
+		serve_stale: Duration::ZERO,
 	};
 
 	let ip1 = resolver.resolve("host1.com".into()).await.unwrap();
@@ -137,6 +225,7 @@ async fn test_resolution_failure() {
 	let resolver = CachedResolver {
 		dns: Arc::new(Resolver::Mock(mock)),
 		entries: Arc::new(Mutex::new(HashMap::new())),
+		serve_stale: Duration::ZERO,
 	};
 
 	let result = resolver.resolve("nonexistent.com".into()).await;
@@ -151,6 +240,7 @@ async fn test_concurrent_resolution() {
 	let resolver = Arc::new(CachedResolver {
 		dns: Arc::new(Resolver::Mock(mock)),
 		entries: Arc::new(Mutex::new(HashMap::new())),
+		serve_stale: Duration::ZERO,
 	});
 
 	// Spawn multiple concurrent resolutions
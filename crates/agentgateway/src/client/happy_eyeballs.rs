@@ -0,0 +1,64 @@
+use std::future::Future;
+
+use futures_util::StreamExt;
+use futures_util::stream::FuturesUnordered;
+use itertools::Itertools;
+
+use crate::*;
+
+/// How long to wait for an attempt before starting the next one in parallel. RFC 8305 recommends 250ms.
+pub const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// Orders addresses so the address families alternate, starting with the family of the first address.
+/// This way a broken family only costs one attempt delay before the other one is tried.
+pub fn interleave(addrs: impl IntoIterator<Item = SocketAddr>) -> Vec<SocketAddr> {
+	let mut addrs = addrs.into_iter().peekable();
+	let Some(first_v6) = addrs.peek().map(SocketAddr::is_ipv6) else {
+		return vec![];
+	};
+	let (preferred, other): (Vec<_>, Vec<_>) = addrs.partition(|a| a.is_ipv6() == first_v6);
+	preferred.into_iter().interleave(other).collect()
+}
+
+/// Connects to `first`, following RFC 8305 ("Happy Eyeballs") by racing it against `fallbacks` when it is slow. Attempts are started in order,
+/// each one once the previous attempt failed or `delay` has passed, and the rest are dropped when one
+/// succeeds. If every attempt fails, the last error is returned.
+pub async fn connect<F, Fut, T, E>(
+	first: SocketAddr,
+	fallbacks: Vec<SocketAddr>,
+	delay: Duration,
+	dial: F,
+) -> Result<T, E>
+where
+	F: Fn(SocketAddr) -> Fut,
+	Fut: Future<Output = Result<T, E>>,
+{
+	let mut pending = fallbacks.into_iter();
+	let mut attempts = FuturesUnordered::new();
+	attempts.push(dial(first));
+	loop {
+		tokio::select! {
+			Some(res) = attempts.next() => match res {
+				Ok(conn) => return Ok(conn),
+				Err(e) => {
+					// Start the next attempt right away, rather than waiting out the delay
+					match pending.next() {
+						Some(addr) => attempts.push(dial(addr)),
+						None if attempts.is_empty() => return Err(e),
+						None => {},
+					}
+				},
+			},
+			_ = tokio::time::sleep(delay), if !pending.as_slice().is_empty() => {
+				if let Some(addr) = pending.next() {
+					trace!(%addr, "connection attempt delay passed, racing next address");
+					attempts.push(dial(addr));
+				}
+			},
+		}
+	}
+}
+
+#[cfg(test)]
+#[path = "happy_eyeballs_tests.rs"]
+mod tests;
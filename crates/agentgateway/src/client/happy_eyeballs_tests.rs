@@ -0,0 +1,87 @@
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::time::Instant;
+
+use tokio::net::{TcpListener, TcpStream};
+
+use super::*;
+
+fn v4(last: u8) -> SocketAddr {
+	SocketAddr::from((Ipv4Addr::new(192, 0, 2, last), 80))
+}
+
+fn v6(last: u16) -> SocketAddr {
+	SocketAddr::from((Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, last), 80))
+}
+
+#[test]
+fn test_interleave() {
+	assert_eq!(
+		interleave([v6(1), v6(2), v6(3), v4(1), v4(2)]),
+		vec![v6(1), v4(1), v6(2), v4(2), v6(3)]
+	);
+	assert_eq!(interleave([v4(1), v6(1), v6(2)]), vec![v4(1), v6(1), v6(2)]);
+	assert_eq!(interleave([v4(1), v4(2)]), vec![v4(1), v4(2)]);
+	assert_eq!(interleave([]), vec![]);
+}
+
+#[tokio::test]
+async fn test_unreachable_first_address() {
+	let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+	let reachable = listener.local_addr().unwrap();
+	// An address that never answers, like a black-holed IPv6 route
+	let unreachable = v6(1);
+
+	let start = Instant::now();
+	let conn = connect(
+		unreachable,
+		vec![reachable],
+		CONNECTION_ATTEMPT_DELAY,
+		|addr| async move {
+			if addr == unreachable {
+				std::future::pending::<()>().await;
+			}
+			TcpStream::connect(addr).await
+		},
+	)
+	.await
+	.unwrap();
+	assert_eq!(conn.peer_addr().unwrap(), reachable);
+	// We only had to wait out one attempt delay, rather than a connect timeout
+	assert!(start.elapsed() < CONNECTION_ATTEMPT_DELAY * 4);
+}
+
+#[tokio::test]
+async fn test_failed_attempt_starts_next_immediately() {
+	let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+	let reachable = listener.local_addr().unwrap();
+	let refused = v4(1);
+
+	let start = Instant::now();
+	let conn = connect(
+		refused,
+		vec![reachable],
+		Duration::from_secs(60),
+		|addr| async move {
+			if addr == refused {
+				return Err(std::io::Error::from(std::io::ErrorKind::ConnectionRefused));
+			}
+			TcpStream::connect(addr).await
+		},
+	)
+	.await
+	.unwrap();
+	assert_eq!(conn.peer_addr().unwrap(), reachable);
+	assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[tokio::test]
+async fn test_all_attempts_fail() {
+	let res: Result<(), _> = connect(v6(1), vec![v4(1)], CONNECTION_ATTEMPT_DELAY, |_| async {
+		Err(std::io::Error::from(std::io::ErrorKind::ConnectionRefused))
+	})
+	.await;
+	assert_eq!(
+		res.unwrap_err().kind(),
+		std::io::ErrorKind::ConnectionRefused
+	);
+}
@@ -1,16 +1,23 @@
 mod dns;
+mod happy_eyeballs;
 mod hyperrustls;
 
+use std::collections::HashMap;
 use std::fmt::Display;
 use std::str::FromStr;
+use std::sync::OnceLock;
 use std::task;
 
 use ::http::Uri;
 use ::http::uri::{Authority, Scheme};
 use axum::body::to_bytes;
+use http_body::{Body, SizeHint};
 use hyper_util_fork::rt::TokioIo;
+use pin_project_lite::pin_project;
 use rand::prelude::IteratorRandom;
 use rustls_pki_types::{DnsName, ServerName};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tower::Service;
 use tracing::event;
 
 use crate::http::backendtls::BackendTLS;
@@ -23,10 +30,16 @@ use crate::types::agent::ListenerProtocol::TLS;
 use crate::types::agent::Target;
 use crate::*;
 
+type HyperClient = hyper_util_fork::client::legacy::Client<Connector, http::Body, PoolKey>;
+
 #[derive(Clone)]
 pub struct Client {
 	resolver: Arc<dns::CachedResolver>,
-	client: hyper_util_fork::client::legacy::Client<Connector, http::Body, PoolKey>,
+	connector: Connector,
+	client: HyperClient,
+	// Backends that customize idle connection handling get their own pool, shared between all backends
+	// with the same settings.
+	pooled_clients: Arc<Mutex<HashMap<PoolSettings, HyperClient>>>,
 }
 
 impl Debug for Client {
@@ -39,6 +52,78 @@ pub struct Call {
 	pub req: http::Request,
 	pub target: Target,
 	pub transport: Transport,
+	pub connection_pool: Option<ConnectionPool>,
+}
+
+/// Connection pool settings for a backend.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+pub struct ConnectionPool {
+	/// Maximum number of idle connections kept open to each endpoint.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub max_idle_per_host: Option<usize>,
+	/// Maximum number of requests to the backend in flight at once, which bounds the connections in
+	/// use. Further requests wait for one to complete. Zero means no limit.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub max_connections: Option<usize>,
+	/// How long a request waits for `maxConnections` to allow it before failing. Defaults to 10s.
+	#[serde(
+		default,
+		skip_serializing_if = "Option::is_none",
+		with = "serde_dur_option"
+	)]
+	#[cfg_attr(feature = "schema", schemars(with = "Option<String>"))]
+	pub max_connections_timeout: Option<Duration>,
+	/// How long an unused connection is kept open before it is closed.
+	#[serde(
+		default,
+		skip_serializing_if = "Option::is_none",
+		with = "serde_dur_option"
+	)]
+	#[cfg_attr(feature = "schema", schemars(with = "Option<String>"))]
+	pub idle_timeout: Option<Duration>,
+	/// Fail the request if a connection to the backend cannot be established within this duration.
+	#[serde(
+		default,
+		skip_serializing_if = "Option::is_none",
+		with = "serde_dur_option"
+	)]
+	#[cfg_attr(feature = "schema", schemars(with = "Option<String>"))]
+	pub connect_timeout: Option<Duration>,
+	// Shared by all clones of the policy, so the limit applies across every request to the backend.
+	#[serde(skip)]
+	pub(crate) limiter: Arc<OnceLock<Arc<Semaphore>>>,
+}
+
+const DEFAULT_MAX_CONNECTIONS_TIMEOUT: Duration = Duration::from_secs(10);
+
+impl ConnectionPool {
+	fn limiter(&self) -> Option<Arc<Semaphore>> {
+		let max = self.max_connections.filter(|m| *m > 0)?;
+		Some(
+			self
+				.limiter
+				.get_or_init(|| Arc::new(Semaphore::new(max)))
+				.clone(),
+		)
+	}
+
+	fn settings(&self) -> Option<PoolSettings> {
+		if self.max_idle_per_host.is_none() && self.idle_timeout.is_none() {
+			return None;
+		}
+		Some(PoolSettings {
+			max_idle_per_host: self.max_idle_per_host,
+			idle_timeout: self.idle_timeout,
+		})
+	}
+}
+
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+struct PoolSettings {
+	max_idle_per_host: Option<usize>,
+	idle_timeout: Option<Duration>,
 }
 
 #[derive(Default, Debug, Clone, Hash, PartialEq, Eq)]
@@ -69,7 +154,14 @@ impl From<Option<BackendTLS>> for Transport {
 }
 
 #[derive(Debug, Clone, Hash, PartialEq, Eq)]
-struct PoolKey(Target, SocketAddr, Transport, ::http::Version);
+struct PoolKey(
+	Target,
+	SocketAddr,
+	Transport,
+	::http::Version,
+	// Connect timeout
+	Option<Duration>,
+);
 
 impl Transport {
 	pub fn scheme(&self) -> Scheme {
@@ -91,6 +183,7 @@ impl Transport {
 
 #[derive(Debug, Clone)]
 struct Connector {
+	resolver: Arc<dns::CachedResolver>,
 	hbone_pool: Option<agent_hbone::pool::WorkloadHBONEPool<hbone::WorkloadKey>>,
 }
 
@@ -104,88 +197,146 @@ impl tower::Service<::http::Extensions> for Connector {
 	}
 
 	fn call(&mut self, mut dst: ::http::Extensions) -> Self::Future {
-		let mut it = self.clone();
+		let it = self.clone();
 
 		Box::pin(async move {
-			let PoolKey(target, ep, transport, ver) =
+			let PoolKey(target, ep, transport, _, connect_timeout) =
 				dst.remove::<PoolKey>().expect("pool key must be set");
-
-			match transport {
-				Transport::Plaintext => {
-					let mut res = Socket::dial(ep)
-						.await
-						.context("http call failed")
-						.map_err(crate::http::Error::new)?;
-					res.with_logging(LoggingMode::Upstream);
-					Ok(TokioIo::new(res))
-				},
-				Transport::Tls(tls) => {
-					let server_name = match target {
-						Target::Address(_) => ServerName::IpAddress(ep.ip().into()),
-						Target::Hostname(host, _) => ServerName::DnsName(
-							DnsName::try_from(host.to_string()).expect("TODO: hostname conversion failed"),
-						),
-					};
-
-					let mut https = self::hyperrustls::HttpsConnector {
-						tls_config: tls.config.clone(),
-						server_name,
-					};
-
-					let mut res = https.call(ep).await.map_err(crate::http::Error::new)?;
-					res.with_logging(LoggingMode::Upstream);
-					Ok(TokioIo::new(res))
-				},
-				Transport::Hbone(inner, identity) => {
-					if inner.is_some() {
-						return Err(crate::http::Error::new(anyhow::anyhow!(
-							"todo: inner TLS is not currently supported"
-						)));
-					}
-					let uri = Uri::builder()
-						.scheme(Scheme::HTTPS)
-						.authority(ep.to_string())
-						.path_and_query("/")
-						.build()
-						.expect("todo");
-					tracing::debug!("will use HBONE");
-					let req = ::http::Request::builder()
-						.uri(uri)
-						.method(hyper::Method::CONNECT)
-						.version(hyper::Version::HTTP_2)
-						.body(())
-						.expect("builder with known status code should not fail");
-
-					let pool_key = Box::new(WorkloadKey {
-						dst_id: vec![identity],
-						dst: SocketAddr::from((ep.ip(), 15008)),
-					});
-					let mut pool = it
-						.hbone_pool
-						.clone()
-						.ok_or_else(|| crate::http::Error::new(anyhow::anyhow!("hbone pool disabled")))?;
-
-					let upgraded = Box::pin(pool.send_request_pooled(&pool_key, req))
-						.await
-						.map_err(crate::http::Error::new)?;
-					let rw = agent_hbone::RWStream {
-						stream: upgraded,
-						buf: Default::default(),
-					};
-					let mut socket = Socket::from_hbone(Arc::new(stream::Extension::new()), pool_key.dst, rw);
-					socket.with_logging(LoggingMode::Upstream);
-					Ok(TokioIo::new(socket))
-				},
-			}
+			let start = std::time::Instant::now();
+			let fallbacks = it.fallbacks(&target, ep).await;
+			let connect = async {
+				let mut io = happy_eyeballs::connect(
+					ep,
+					fallbacks,
+					happy_eyeballs::CONNECTION_ATTEMPT_DELAY,
+					|ep| it.clone().connect(target.clone(), ep, transport.clone()),
+				)
+				.await?;
+				io.inner_mut().set_connect_duration(start.elapsed());
+				Ok::<_, crate::http::Error>(io)
+			};
+			let Some(connect_timeout) = connect_timeout else {
+				return connect.await;
+			};
+			tokio::time::timeout(connect_timeout, connect)
+				.await
+				.map_err(|_| {
+					crate::http::Error::new(anyhow::anyhow!(
+						"connection to {ep} timed out after {connect_timeout:?}"
+					))
+				})?
 		})
 	}
 }
 
+impl Connector {
+	// Other addresses of the target to race against `ep` if it is slow to connect, such as the other
+	// address family of a dual-stack host.
+	async fn fallbacks(&self, target: &Target, ep: SocketAddr) -> Vec<SocketAddr> {
+		let Target::Hostname(host, port) = target else {
+			return vec![];
+		};
+		// The hostname was just resolved to pick `ep`, so this is served from the cache
+		let Ok(ips) = self.resolver.resolve_all(host.clone()).await else {
+			return vec![];
+		};
+		let others = ips
+			.iter()
+			.map(|ip| SocketAddr::from((*ip, *port)))
+			.filter(|addr| *addr != ep);
+		// Interleave with `ep` in front, so the first fallback is from the other address family
+		happy_eyeballs::interleave(std::iter::once(ep).chain(others))
+			.into_iter()
+			.skip(1)
+			.collect()
+	}
+
+	async fn connect(
+		self,
+		target: Target,
+		ep: SocketAddr,
+		transport: Transport,
+	) -> Result<TokioIo<Socket>, crate::http::Error> {
+		match transport {
+			Transport::Plaintext => {
+				let mut res = Socket::dial(ep)
+					.await
+					.context("http call failed")
+					.map_err(crate::http::Error::new)?;
+				res.with_logging(LoggingMode::Upstream);
+				Ok(TokioIo::new(res))
+			},
+			Transport::Tls(tls) => {
+				let server_name = match (&tls.hostname, target) {
+					(Some(hostname), _) => hostname.clone(),
+					(None, Target::Address(_)) => ServerName::IpAddress(ep.ip().into()),
+					(None, Target::Hostname(host, _)) => ServerName::DnsName(
+						DnsName::try_from(host.to_string()).expect("TODO: hostname conversion failed"),
+					),
+				};
+
+				let mut https = self::hyperrustls::HttpsConnector {
+					tls_config: tls.config.clone(),
+					server_name,
+				};
+
+				let mut res = https.call(ep).await.map_err(crate::http::Error::new)?;
+				res.with_logging(LoggingMode::Upstream);
+				Ok(TokioIo::new(res))
+			},
+			Transport::Hbone(inner, identity) => {
+				if inner.is_some() {
+					return Err(crate::http::Error::new(anyhow::anyhow!(
+						"todo: inner TLS is not currently supported"
+					)));
+				}
+				let uri = Uri::builder()
+					.scheme(Scheme::HTTPS)
+					.authority(ep.to_string())
+					.path_and_query("/")
+					.build()
+					.expect("todo");
+				tracing::debug!("will use HBONE");
+				let req = ::http::Request::builder()
+					.uri(uri)
+					.method(hyper::Method::CONNECT)
+					.version(hyper::Version::HTTP_2)
+					.body(())
+					.expect("builder with known status code should not fail");
+
+				let pool_key = Box::new(WorkloadKey {
+					dst_id: vec![identity],
+					dst: SocketAddr::from((ep.ip(), 15008)),
+				});
+				let mut pool = self
+					.hbone_pool
+					.clone()
+					.ok_or_else(|| crate::http::Error::new(anyhow::anyhow!("hbone pool disabled")))?;
+
+				let upgraded = Box::pin(pool.send_request_pooled(&pool_key, req))
+					.await
+					.map_err(crate::http::Error::new)?;
+				let rw = agent_hbone::RWStream {
+					stream: upgraded,
+					buf: Default::default(),
+				};
+				let mut socket = Socket::from_hbone(Arc::new(stream::Extension::new()), pool_key.dst, rw);
+				socket.with_logging(LoggingMode::Upstream);
+				Ok(TokioIo::new(socket))
+			},
+		}
+	}
+}
+
 #[derive(serde::Serialize, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Config {
 	pub resolver_cfg: ResolverConfig,
 	pub resolver_opts: ResolverOpts,
+	// How long to keep using a hostname's last resolved addresses once they expire, if resolving it
+	// again fails. Zero disables this.
+	#[serde(with = "serde_dur")]
+	pub serve_stale: Duration,
 }
 
 impl Client {
@@ -193,17 +344,35 @@ impl Client {
 		cfg: &Config,
 		hbone_pool: Option<agent_hbone::pool::WorkloadHBONEPool<hbone::WorkloadKey>>,
 	) -> Client {
-		let resolver = dns::CachedResolver::new(cfg.resolver_cfg.clone(), cfg.resolver_opts.clone());
-		let client =
-			::hyper_util_fork::client::legacy::Client::builder(::hyper_util::rt::TokioExecutor::new())
-				.timer(hyper_util::rt::tokio::TokioTimer::new())
-				.build_with_pool_key(Connector { hbone_pool });
+		let resolver = Arc::new(dns::CachedResolver::new(
+			cfg.resolver_cfg.clone(),
+			cfg.resolver_opts.clone(),
+			cfg.serve_stale,
+		));
+		let connector = Connector {
+			resolver: resolver.clone(),
+			hbone_pool,
+		};
+		let client = build_client(connector.clone(), None);
 		Client {
-			resolver: Arc::new(resolver),
+			resolver,
+			connector,
 			client,
+			pooled_clients: Default::default(),
 		}
 	}
 
+	fn client_for(&self, pool: Option<&ConnectionPool>) -> HyperClient {
+		let Some(settings) = pool.and_then(ConnectionPool::settings) else {
+			return self.client.clone();
+		};
+		let mut clients = self.pooled_clients.lock().unwrap();
+		clients
+			.entry(settings)
+			.or_insert_with(|| build_client(self.connector.clone(), Some(settings)))
+			.clone()
+	}
+
 	pub async fn simple_call(&self, req: http::Request) -> Result<http::Response, ProxyError> {
 		let host = req
 			.uri()
@@ -230,6 +399,7 @@ impl Client {
 				req,
 				target,
 				transport,
+				connection_pool: None,
 			})
 			.await
 	}
@@ -240,7 +410,25 @@ impl Client {
 			mut req,
 			target,
 			transport,
+			connection_pool,
 		} = call;
+		// Hold a permit until the response body is complete, so it covers the whole time the
+		// connection is in use.
+		let permit = match connection_pool
+			.as_ref()
+			.and_then(|cp| Some((cp, cp.limiter()?)))
+		{
+			Some((cp, limiter)) => {
+				let timeout = cp
+					.max_connections_timeout
+					.unwrap_or(DEFAULT_MAX_CONNECTIONS_TIMEOUT);
+				let permit = tokio::time::timeout(timeout, limiter.acquire_owned())
+					.await
+					.map_err(|_| ProxyError::ConnectionPoolTimeout)?;
+				Some(permit.expect("connection limiter is never closed"))
+			},
+			None => None,
+		};
 		let dest = match &target {
 			Target::Address(addr) => *addr,
 			Target::Hostname(hostname, port) => {
@@ -272,15 +460,16 @@ impl Client {
 		let version = req.version();
 		let transport_name = transport.name();
 		let target_name = target.to_string();
+		let connect_timeout = connection_pool.as_ref().and_then(|p| p.connect_timeout);
 		req
 			.extensions_mut()
-			.insert(PoolKey(target, dest, transport, version));
+			.insert(PoolKey(target, dest, transport, version, connect_timeout));
 		trace!(?req, "sending request");
 		let method = req.method().clone();
 		let uri = req.uri().clone();
 		let path = uri.path();
 		let host = uri.authority().to_owned();
-		let resp = self.client.request(req).await;
+		let resp = self.client_for(connection_pool.as_ref()).request(req).await;
 		let dur = format!("{}ms", start.elapsed().as_millis());
 		event!(
 			target: "upstream request",
@@ -299,10 +488,62 @@ impl Client {
 
 			duration = dur,
 		);
-		Ok(
-			resp
-				.map_err(ProxyError::UpstreamCallFailed)?
-				.map(http::Body::new),
-		)
+		let resp = resp.map_err(ProxyError::UpstreamCallFailed)?;
+		Ok(match permit {
+			Some(permit) => resp.map(|body| {
+				http::Body::new(PermitBody {
+					body,
+					_permit: permit,
+				})
+			}),
+			None => resp.map(http::Body::new),
+		})
+	}
+}
+
+fn build_client(connector: Connector, settings: Option<PoolSettings>) -> HyperClient {
+	let mut builder =
+		::hyper_util_fork::client::legacy::Client::builder(::hyper_util::rt::TokioExecutor::new());
+	builder.timer(hyper_util::rt::tokio::TokioTimer::new());
+	if let Some(settings) = settings {
+		if let Some(max) = settings.max_idle_per_host {
+			builder.pool_max_idle_per_host(max);
+		}
+		if let Some(timeout) = settings.idle_timeout {
+			// Idle connections are only reaped if the pool has a timer
+			builder
+				.pool_idle_timeout(timeout)
+				.pool_timer(hyper_util::rt::tokio::TokioTimer::new());
+		}
+	}
+	builder.build_with_pool_key(connector)
+}
+
+pin_project! {
+	// Releases the connection permit once the response body is dropped.
+	struct PermitBody<B> {
+		#[pin]
+		body: B,
+		_permit: OwnedSemaphorePermit,
+	}
+}
+
+impl<B: Body> Body for PermitBody<B> {
+	type Data = B::Data;
+	type Error = B::Error;
+
+	fn poll_frame(
+		self: Pin<&mut Self>,
+		cx: &mut task::Context<'_>,
+	) -> Poll<Option<Result<http_body::Frame<Self::Data>, Self::Error>>> {
+		self.project().body.poll_frame(cx)
+	}
+
+	fn is_end_stream(&self) -> bool {
+		self.body.is_end_stream()
+	}
+
+	fn size_hint(&self) -> SizeHint {
+		self.body.size_hint()
 	}
 }
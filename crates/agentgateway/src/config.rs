@@ -150,6 +150,14 @@ pub fn parse_config(contents: String, filename: Option<PathBuf>) -> anyhow::Resu
 		parse_duration("CONNECTION_TERMINATION_DEADLINE")?.or(raw.connection_min_termination_deadline);
 	let otlp = empty_to_none(parse("OTLP_ENDPOINT")?)
 		.or(raw.tracing.as_ref().map(|t| t.otlp_endpoint.clone()));
+	let sampling_ratio = raw
+		.tracing
+		.as_ref()
+		.and_then(|t| t.sampling_ratio)
+		.unwrap_or(1.0);
+	if !(0.0..=1.0).contains(&sampling_ratio) {
+		anyhow::bail!("tracing samplingRatio must be between 0 and 1, got {sampling_ratio}");
+	}
 	// Parse admin_addr from environment variable or config file
 	let admin_addr = parse::<String>("ADMIN_ADDR")?
 		.or(raw.admin_addr)
@@ -208,6 +216,17 @@ pub fn parse_config(contents: String, filename: Option<PathBuf>) -> anyhow::Resu
 		},
 		tracing: trc::Config {
 			endpoint: otlp,
+			always_trace: raw
+				.tracing
+				.as_ref()
+				.map(|t| t.always_trace)
+				.unwrap_or_default(),
+			sampling_ratio,
+			resource_attributes: raw
+				.tracing
+				.as_ref()
+				.and_then(|t| t.resource_attributes.clone())
+				.unwrap_or_default(),
 			fields: Arc::new(
 				raw
 					.tracing
@@ -215,6 +234,7 @@ pub fn parse_config(contents: String, filename: Option<PathBuf>) -> anyhow::Resu
 					.map(|fields| {
 						Ok::<_, anyhow::Error>(LoggingFields {
 							remove: fields.remove.into_iter().collect(),
+							include: fields.include.map(|i| i.into_iter().collect()),
 							add: fields
 								.add
 								.iter()
@@ -234,6 +254,7 @@ pub fn parse_config(contents: String, filename: Option<PathBuf>) -> anyhow::Resu
 				.map(cel::Expression::new)
 				.transpose()?
 				.map(Arc::new),
+			format: raw.logging.as_ref().and_then(|l| l.format),
 			fields: Arc::new(
 				raw
 					.logging
@@ -241,6 +262,7 @@ pub fn parse_config(contents: String, filename: Option<PathBuf>) -> anyhow::Resu
 					.map(|fields| {
 						Ok::<_, anyhow::Error>(LoggingFields {
 							remove: fields.remove.into_iter().collect(),
+							include: fields.include.map(|i| i.into_iter().collect()),
 							add: fields
 								.add
 								.iter()
@@ -252,10 +274,14 @@ pub fn parse_config(contents: String, filename: Option<PathBuf>) -> anyhow::Resu
 					.unwrap_or_default(),
 			),
 		},
+		trusted_proxies: raw.trusted_proxies.unwrap_or_default(),
 		dns: client::Config {
 			// TODO: read from file
 			resolver_cfg,
 			resolver_opts,
+			serve_stale: parse_duration("DNS_SERVE_STALE")?
+				.or(raw.dns.as_ref().and_then(|d| d.serve_stale))
+				.unwrap_or_default(),
 		},
 		proxy_metadata: crate::ProxyMetadata {
 			instance_ip: std::env::var("INSTANCE_IP").unwrap_or_else(|_| "1.1.1.1".to_string()),
@@ -162,6 +162,7 @@ impl WorkloadCertificate {
 		// cc.enable_sni = false;
 		Ok(BackendTLS {
 			config: Arc::new(cc),
+			hostname: None,
 		})
 	}
 	pub fn hbone_mtls(&self, identity: Vec<Identity>) -> Result<BackendTLS, Error> {
@@ -182,6 +183,7 @@ impl WorkloadCertificate {
 		cc.enable_sni = false;
 		Ok(BackendTLS {
 			config: Arc::new(cc),
+			hostname: None,
 		})
 	}
 	pub fn hbone_termination(&self) -> Result<ServerConfig, Error> {
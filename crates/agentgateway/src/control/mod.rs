@@ -51,6 +51,7 @@ impl RootCert {
 		ccb.alpn_protocols = vec![b"h2".to_vec()];
 		Ok(BackendTLS {
 			config: Arc::new(ccb),
+			hostname: None,
 		})
 	}
 }
@@ -162,6 +163,7 @@ impl tower::Service<::http::Request<tonic::body::Body>> for GrpcChannel {
 						req,
 						target,
 						transport,
+						connection_pool: None,
 					})
 					.await?,
 			)
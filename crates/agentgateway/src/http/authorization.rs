@@ -0,0 +1,62 @@
+use std::collections::BTreeMap;
+
+use crate::http::Request;
+use crate::http::jwt::Claims;
+use crate::proxy::ProxyError;
+use crate::*;
+
+/// Authorization requires requests to be authenticated with a JWT that carries the configured roles
+/// or claims. Requests that match none of the rules are rejected with a 403.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+pub struct Authorization {
+	/// Requests are allowed if any rule matches.
+	pub rules: Vec<Rule>,
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+pub struct Rule {
+	/// Roles the subject must all have, from the `role` or `roles` claim.
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub roles: Vec<String>,
+	/// Claims that must all have the given value. Nested claims are separated by '.', and array
+	/// claims match if they contain the value.
+	#[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+	pub claims: BTreeMap<String, String>,
+}
+
+impl Authorization {
+	pub fn apply(&self, req: &Request) -> Result<(), ProxyError> {
+		let claims = req.extensions().get::<Claims>();
+		if self.rules.iter().any(|r| r.matches(claims)) {
+			Ok(())
+		} else {
+			debug!("request did not match any authorization rule");
+			Err(ProxyError::AuthorizationFailed)
+		}
+	}
+}
+
+impl Rule {
+	fn matches(&self, claims: Option<&Claims>) -> bool {
+		// An unauthenticated request never matches, even a rule without requirements.
+		let Some(claims) = claims else {
+			return false;
+		};
+		self
+			.roles
+			.iter()
+			.all(|want| claims.roles().any(|r| r == want))
+			&& self
+				.claims
+				.iter()
+				.all(|(path, want)| claims.has_value(path, want))
+	}
+}
+
+#[cfg(test)]
+#[path = "authorization_tests.rs"]
+mod tests;
@@ -0,0 +1,93 @@
+use serde_json::json;
+
+use super::*;
+
+fn request(claims: Option<serde_json::Value>) -> Request {
+	let mut req = ::http::Request::builder()
+		.uri("http://example.com/admin")
+		.body(crate::http::Body::empty())
+		.unwrap();
+	if let Some(serde_json::Value::Object(inner)) = claims {
+		req.extensions_mut().insert(Claims {
+			inner,
+			jwt: Default::default(),
+		});
+	}
+	req
+}
+
+fn authz(rules: serde_json::Value) -> Authorization {
+	serde_json::from_value(json!({ "rules": rules })).unwrap()
+}
+
+#[test]
+fn requires_role() {
+	let a = authz(json!([{"roles": ["admin"]}]));
+	assert!(
+		a.apply(&request(Some(json!({"sub": "alice", "roles": ["admin"]}))))
+			.is_ok()
+	);
+	assert!(
+		a.apply(&request(Some(json!({"sub": "alice", "role": "admin"}))))
+			.is_ok()
+	);
+	assert!(matches!(
+		a.apply(&request(Some(json!({"sub": "bob", "roles": ["viewer"]})))),
+		Err(ProxyError::AuthorizationFailed)
+	));
+	assert!(matches!(
+		a.apply(&request(None)),
+		Err(ProxyError::AuthorizationFailed)
+	));
+}
+
+#[test]
+fn roles_within_rule_are_all_required() {
+	let a = authz(json!([{"roles": ["admin", "billing"]}]));
+	assert!(
+		a.apply(&request(Some(json!({"roles": ["billing", "admin"]}))))
+			.is_ok()
+	);
+	assert!(
+		a.apply(&request(Some(json!({"roles": ["admin"]}))))
+			.is_err()
+	);
+}
+
+#[test]
+fn any_rule_may_match() {
+	let a = authz(json!([
+		{"roles": ["admin"]},
+		{"roles": ["editor"], "claims": {"org.team": "platform"}}
+	]));
+	assert!(a.apply(&request(Some(json!({"roles": ["admin"]})))).is_ok());
+	assert!(
+		a.apply(&request(Some(
+			json!({"roles": ["editor"], "org": {"team": "platform"}})
+		)))
+		.is_ok()
+	);
+	assert!(
+		a.apply(&request(Some(
+			json!({"roles": ["editor"], "org": {"team": "sales"}})
+		)))
+		.is_err()
+	);
+}
+
+#[test]
+fn claims_match_arrays() {
+	let a = authz(json!([{"claims": {"groups": "ops", "email_verified": "true"}}]));
+	assert!(
+		a.apply(&request(Some(
+			json!({"groups": ["dev", "ops"], "email_verified": true})
+		)))
+		.is_ok()
+	);
+	assert!(
+		a.apply(&request(Some(
+			json!({"groups": ["dev"], "email_verified": true})
+		)))
+		.is_err()
+	);
+}
@@ -2,8 +2,10 @@ use std::io::Cursor;
 use std::path::PathBuf;
 use std::sync::Arc;
 
+use agent_core::strng::Strng;
 use once_cell::sync::Lazy;
 use rustls::ClientConfig;
+use rustls_pki_types::ServerName;
 use serde::Serializer;
 
 use crate::transport;
@@ -19,27 +21,31 @@ pub static INSECURE_TRUST: Lazy<BackendTLS> = Lazy::new(|| {
 		root: None,
 		insecure: true,
 		insecure_host: false,
+		hostname: None,
 	}
 	.try_into()
 	.unwrap()
 });
 
-// TODO: xds support
 #[derive(Debug, Clone)]
 pub struct BackendTLS {
 	pub config: Arc<ClientConfig>,
+	// Overrides the server name sent in SNI and verified against the backend's certificate, which is
+	// otherwise the backend's hostname (or IP address).
+	pub hostname: Option<ServerName<'static>>,
 }
 
 impl std::hash::Hash for BackendTLS {
 	fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
 		// Hash the pointer address
 		Arc::as_ptr(&self.config).hash(state);
+		self.hostname.hash(state);
 	}
 }
 
 impl PartialEq for BackendTLS {
 	fn eq(&self, other: &Self) -> bool {
-		Arc::ptr_eq(&self.config, &other.config)
+		Arc::ptr_eq(&self.config, &other.config) && self.hostname == other.hostname
 	}
 }
 impl Eq for BackendTLS {}
@@ -67,13 +73,56 @@ pub struct LocalBackendTLS {
 	insecure: bool,
 	#[serde(default)]
 	insecure_host: bool,
+	/// Server name to send in SNI and to verify the backend's certificate against, instead of the
+	/// backend's hostname.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	hostname: Option<Strng>,
 }
 
 impl LocalBackendTLS {
 	pub fn try_into(self) -> anyhow::Result<BackendTLS> {
+		let root = self.root.map(fs_err::read).transpose()?;
+		let client_cert = match (self.cert, self.key) {
+			(Some(cert), Some(key)) => Some((fs_err::read(cert)?, fs_err::read(key)?)),
+			_ => None,
+		};
+		BackendTLSConfig {
+			root,
+			client_cert,
+			insecure: self.insecure,
+			insecure_host: self.insecure_host,
+			hostname: self.hostname,
+		}
+		.build()
+	}
+}
+
+/// Backend TLS settings with the certificates already loaded, as PEM.
+#[derive(Debug, Default)]
+pub struct BackendTLSConfig {
+	/// Roots to verify the backend's certificate against. If unset, the system roots are used.
+	pub root: Option<Vec<u8>>,
+	/// Certificate chain and private key to present to the backend, for mutual TLS.
+	pub client_cert: Option<(Vec<u8>, Vec<u8>)>,
+	/// Skip verifying the backend's certificate entirely.
+	pub insecure: bool,
+	/// Verify the backend's certificate, but not that it matches the hostname.
+	pub insecure_host: bool,
+	/// Server name to use for SNI and verification, instead of the backend's hostname.
+	pub hostname: Option<Strng>,
+}
+
+impl BackendTLSConfig {
+	pub fn build(self) -> anyhow::Result<BackendTLS> {
+		let hostname = self
+			.hostname
+			.map(|h| {
+				ServerName::try_from(h.to_string())
+					.map_err(|e| anyhow::anyhow!("invalid backend TLS hostname {h:?}: {e}"))
+			})
+			.transpose()?;
 		let mut roots = rustls::RootCertStore::empty();
 		if let Some(root) = self.root {
-			let root = fs_err::read(root)?;
 			let mut reader = std::io::BufReader::new(Cursor::new(root));
 			let certs = rustls_pemfile::certs(&mut reader).collect::<Result<Vec<_>, _>>()?;
 			roots.add_parsable_certificates(certs);
@@ -90,15 +139,13 @@ impl LocalBackendTLS {
 			.expect("server config must be valid")
 			.with_root_certificates(roots.clone());
 
-		let mut cc = match (self.cert, self.key) {
-			(Some(cert), Some(key)) => {
-				let cert = fs_err::read(cert)?;
+		let mut cc = match self.client_cert {
+			Some((cert, key)) => {
 				let cert_chain = parse_cert(&cert)?;
-				let key = fs_err::read(key)?;
 				let private_key = parse_key(&key)?;
 				ccb.with_client_auth_cert(cert_chain, private_key)?
 			},
-			_ => ccb.with_no_client_auth(),
+			None => ccb.with_no_client_auth(),
 		};
 		if self.insecure_host {
 			let inner = rustls::client::WebPkiServerVerifier::builder_with_provider(
@@ -117,6 +164,7 @@ impl LocalBackendTLS {
 		// cc.alpn_protocols = vec![b"http/1.1".into()];
 		Ok(BackendTLS {
 			config: Arc::new(cc),
+			hostname,
 		})
 	}
 }
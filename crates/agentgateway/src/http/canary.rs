@@ -0,0 +1,128 @@
+use itertools::Itertools;
+use rand::Rng;
+
+use crate::http::loadbalancer::{HashOn, cookie};
+use crate::http::{HeaderValue, Request};
+use crate::types::agent::{BackendName, RouteBackendReference};
+use crate::*;
+
+// Users are assigned to the canary in steps of 0.01%.
+const BUCKETS: u64 = 10_000;
+
+/// Canary sends a percentage of users to a canary backend, and keeps each user on the variant they
+/// were first assigned. The variant is recorded in a cookie, which is honored on later requests.
+/// Within a variant, backends are picked by weight.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+pub struct Canary {
+	/// The backend receiving canary traffic. The other backends of the route serve everyone else.
+	pub backend: BackendName,
+	/// Percentage of users, from 0 to 100, assigned to the canary.
+	pub percentage: f64,
+	/// Assign users by hashing this request key rather than at random, so a user gets the same
+	/// variant even before the cookie is set.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub sticky_key: Option<HashOn>,
+	/// The cookie recording the assigned variant.
+	#[serde(default = "default_cookie_name")]
+	pub cookie_name: Strng,
+}
+
+pub fn default_cookie_name() -> Strng {
+	strng::literal!("agentgateway-canary")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variant {
+	Stable,
+	Canary,
+}
+
+impl Variant {
+	fn as_str(&self) -> &'static str {
+		match self {
+			Variant::Stable => "stable",
+			Variant::Canary => "canary",
+		}
+	}
+
+	fn parse(s: &str) -> Option<Variant> {
+		match s {
+			"stable" => Some(Variant::Stable),
+			"canary" => Some(Variant::Canary),
+			_ => None,
+		}
+	}
+}
+
+impl Canary {
+	/// Returns the variant for the request, and the Set-Cookie header recording it if the request was
+	/// not already assigned one.
+	pub fn assign(&self, req: &Request) -> (Variant, Option<HeaderValue>) {
+		if let Some(v) = cookie(req, &self.cookie_name).and_then(Variant::parse) {
+			return (v, None);
+		}
+		let bucket = match self.sticky_key.as_ref().and_then(|k| k.key(req)) {
+			Some(key) => key % BUCKETS,
+			None => rand::rng().random_range(0..BUCKETS),
+		};
+		let variant = if (bucket as f64) < self.percentage * (BUCKETS as f64) / 100.0 {
+			Variant::Canary
+		} else {
+			Variant::Stable
+		};
+		(variant, self.cookie(variant))
+	}
+
+	/// Checks the percentage is in range, and the canary backend is one of the route's backends.
+	pub fn validate(&self, backends: &[RouteBackendReference]) -> anyhow::Result<()> {
+		if !(0.0..=100.0).contains(&self.percentage) {
+			anyhow::bail!(
+				"canary percentage {} must be between 0 and 100",
+				self.percentage
+			);
+		}
+		if !backends.iter().any(|b| b.backend.name() == self.backend) {
+			anyhow::bail!(
+				"canary backend {} is not a backend of the route (have: {})",
+				self.backend,
+				backends.iter().map(|b| b.backend.name()).join(", ")
+			);
+		}
+		Ok(())
+	}
+
+	/// Returns the backends serving the variant. If the variant has none, for example because the
+	/// canary backend was removed from the route, the other variant's backends are returned so users
+	/// holding a cookie for it are still served.
+	pub fn backends(
+		&self,
+		variant: Variant,
+		backends: &[RouteBackendReference],
+	) -> Vec<RouteBackendReference> {
+		let (canary, stable): (Vec<_>, Vec<_>) = backends
+			.iter()
+			.cloned()
+			.partition(|b| b.backend.name() == self.backend);
+		match variant {
+			Variant::Canary if !canary.is_empty() => canary,
+			Variant::Stable if !stable.is_empty() => stable,
+			Variant::Canary => stable,
+			Variant::Stable => canary,
+		}
+	}
+
+	fn cookie(&self, variant: Variant) -> Option<HeaderValue> {
+		let cookie = format!(
+			"{}={}; Path=/; HttpOnly",
+			self.cookie_name,
+			variant.as_str()
+		);
+		HeaderValue::try_from(cookie).ok()
+	}
+}
+
+#[cfg(test)]
+#[path = "canary_tests.rs"]
+mod tests;
@@ -0,0 +1,110 @@
+use super::*;
+use crate::http::tests_common::*;
+use crate::types::agent::BackendReference;
+
+fn canary(percentage: f64) -> Canary {
+	Canary {
+		backend: strng::new("canary"),
+		percentage,
+		sticky_key: Some(HashOn::Header(http::HeaderName::from_static("x-user"))),
+		cookie_name: default_cookie_name(),
+	}
+}
+
+fn assign(c: &Canary, headers: &[(&str, &str)]) -> (Variant, Option<HeaderValue>) {
+	c.assign(&request("http://example.com", http::Method::GET, headers))
+}
+
+#[test]
+fn percentage_split() {
+	let c = canary(20.0);
+	let assigned = (0..5000)
+		.filter(|i| assign(&c, &[("x-user", &format!("user-{i}"))]).0 == Variant::Canary)
+		.count();
+	// 20% of 5000, within a few percent
+	assert!((850..=1150).contains(&assigned), "{assigned}");
+
+	assert!(
+		(0..1000)
+			.all(|i| assign(&canary(0.0), &[("x-user", &format!("user-{i}"))]).0 == Variant::Stable)
+	);
+	assert!(
+		(0..1000)
+			.all(|i| assign(&canary(100.0), &[("x-user", &format!("user-{i}"))]).0 == Variant::Canary)
+	);
+}
+
+#[test]
+fn sticky_key() {
+	let c = canary(50.0);
+	let (first, cookie) = assign(&c, &[("x-user", "alice")]);
+	assert_eq!(
+		cookie.unwrap(),
+		format!("agentgateway-canary={}; Path=/; HttpOnly", first.as_str())
+	);
+	for _ in 0..20 {
+		assert_eq!(assign(&c, &[("x-user", "alice")]).0, first);
+	}
+}
+
+#[test]
+fn cookie_is_honored() {
+	let c = canary(0.0);
+	let (variant, cookie) = assign(
+		&c,
+		&[
+			("x-user", "alice"),
+			("cookie", "a=b; agentgateway-canary=canary"),
+		],
+	);
+	assert_eq!(variant, Variant::Canary);
+	assert!(cookie.is_none());
+
+	// Unknown values are reassigned
+	let (variant, cookie) = assign(&c, &[("cookie", "agentgateway-canary=other")]);
+	assert_eq!(variant, Variant::Stable);
+	assert!(cookie.is_some());
+}
+
+#[test]
+fn variant_backends() {
+	let c = canary(10.0);
+	let backends = ["stable-a", "canary", "stable-b"]
+		.iter()
+		.map(|n| RouteBackendReference {
+			weight: 1,
+			backend: BackendReference::Backend(strng::new(n)),
+			filters: vec![],
+		})
+		.collect::<Vec<_>>();
+	let names = |v| {
+		c.backends(v, &backends)
+			.iter()
+			.map(|b| b.backend.name().to_string())
+			.collect::<Vec<_>>()
+	};
+	assert_eq!(names(Variant::Canary), vec!["canary"]);
+	assert_eq!(names(Variant::Stable), vec!["stable-a", "stable-b"]);
+}
+
+#[test]
+fn missing_variant_falls_back() {
+	let c = canary(10.0);
+	let backends = vec![RouteBackendReference {
+		weight: 1,
+		backend: BackendReference::Backend(strng::new("stable")),
+		filters: vec![],
+	}];
+	// The canary backend is not on the route; users with a canary cookie are still served
+	assert_eq!(c.backends(Variant::Canary, &backends).len(), 1);
+	assert!(c.validate(&backends).is_err());
+
+	let backends = vec![RouteBackendReference {
+		weight: 1,
+		backend: BackendReference::Backend(strng::new("canary")),
+		filters: vec![],
+	}];
+	assert!(c.validate(&backends).is_ok());
+	assert!(canary(100.5).validate(&backends).is_err());
+	assert!(canary(-1.0).validate(&backends).is_err());
+}
@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Mutex;
+
+use bytes::Bytes;
+use tokio::sync::watch;
+
+use crate::client::Transport;
+use crate::http::{Body, HeaderMap, HeaderName, HeaderValue, Method, Response, StatusCode, Uri};
+use crate::proxy::{ProxyError, SharedError};
+use crate::telemetry::trc::{TRACEPARENT_HEADER, TRACESTATE_HEADER};
+use crate::types::agent::{BackendName, Target};
+use crate::*;
+
+// Shared responses are buffered; this matches the limit of the callers, which buffer them anyway.
+const MAX_BUFFERED_BYTES: usize = 2_097_152;
+
+// Headers identifying a single request or hop rather than its content. They differ between otherwise
+// identical requests, for example the traceparent written for each request, so are not part of the key.
+const PER_REQUEST_HEADERS: &[&str] = &[
+	TRACEPARENT_HEADER,
+	TRACESTATE_HEADER,
+	"x-request-id",
+	"x-b3-traceid",
+	"x-b3-spanid",
+	"x-b3-parentspanid",
+	"x-b3-sampled",
+	"x-b3-flags",
+	"b3",
+	"x-amzn-trace-id",
+	"x-cloud-trace-context",
+];
+
+#[derive(Debug, Clone)]
+struct SharedResponse {
+	status: StatusCode,
+	headers: HeaderMap,
+	body: Bytes,
+}
+
+impl SharedResponse {
+	fn to_response(&self) -> Response {
+		let mut resp = Response::new(Body::from(self.body.clone()));
+		*resp.status_mut() = self.status;
+		*resp.headers_mut() = self.headers.clone();
+		resp
+	}
+}
+
+type Outcome = Option<Result<SharedResponse, SharedError>>;
+
+/// Coalescer shares one upstream call between concurrent identical requests: the first request is
+/// sent, and the others wait for its response. Only idempotent requests may be coalesced.
+#[derive(Debug, Clone, Default)]
+pub struct Coalescer {
+	inflight: Arc<Mutex<HashMap<RequestKey, Arc<watch::Sender<Outcome>>>>>,
+}
+
+/// Coalesce is a request extension marking an idempotent request whose identical concurrent copies
+/// may share one upstream call. The key is computed once backend policies have been applied.
+#[derive(Debug, Clone, Copy)]
+pub struct Coalesce;
+
+/// RequestKey identifies identical requests to the same backend. It holds the full request, rather
+/// than a hash of it, so distinct requests can never share a response. Per request headers, such as
+/// trace context, are left out.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RequestKey {
+	backend: BackendName,
+	target: Target,
+	transport: Transport,
+	method: Method,
+	uri: Uri,
+	headers: Vec<(HeaderName, HeaderValue)>,
+	body: Bytes,
+}
+
+impl RequestKey {
+	pub fn new(
+		backend: BackendName,
+		target: Target,
+		transport: Transport,
+		parts: &::http::request::Parts,
+		body: Bytes,
+	) -> Self {
+		RequestKey {
+			backend,
+			target,
+			transport,
+			method: parts.method.clone(),
+			uri: parts.uri.clone(),
+			headers: parts
+				.headers
+				.iter()
+				.filter(|(k, _)| !PER_REQUEST_HEADERS.contains(&k.as_str()))
+				.map(|(k, v)| (k.clone(), v.clone()))
+				.collect(),
+			body,
+		}
+	}
+}
+
+impl Coalescer {
+	/// Runs `f`, unless a request with the same key is already in flight, in which case its response
+	/// is returned instead. Errors are shared as well, keeping their status and retryability.
+	pub async fn call<F, Fut>(&self, key: RequestKey, f: F) -> Result<Response, ProxyError>
+	where
+		F: FnOnce() -> Fut,
+		Fut: Future<Output = Result<Response, ProxyError>>,
+	{
+		let joined = {
+			let mut inflight = self.inflight.lock().expect("mutex poisoned");
+			match inflight.get(&key) {
+				Some(tx) => Some(tx.subscribe()),
+				None => {
+					inflight.insert(key.clone(), Arc::new(watch::channel(None).0));
+					None
+				},
+			}
+		};
+		if let Some(mut rx) = joined {
+			let outcome = rx
+				.wait_for(Option::is_some)
+				.await
+				.ok()
+				.and_then(|o| o.clone());
+			return match outcome {
+				Some(Ok(resp)) => Ok(resp.to_response()),
+				Some(Err(e)) => Err(ProxyError::Shared(e)),
+				// The first request was cancelled before it completed
+				None => f().await,
+			};
+		}
+
+		let mut guard = InflightGuard {
+			coalescer: self,
+			key,
+			done: false,
+		};
+		let result = match f().await {
+			Ok(resp) => buffer(resp).await,
+			Err(e) => Err(e),
+		};
+		if let Some(tx) = guard.finish() {
+			let outcome = match &result {
+				Ok(resp) => Ok(resp.clone()),
+				Err(e) => Err(e.to_shared()),
+			};
+			tx.send_replace(Some(outcome));
+		}
+		result.map(|resp| resp.to_response())
+	}
+}
+
+async fn buffer(resp: Response) -> Result<SharedResponse, ProxyError> {
+	let (parts, body) = resp.into_parts();
+	let body = axum::body::to_bytes(body, MAX_BUFFERED_BYTES)
+		.await
+		.map_err(|e| ProxyError::Processing(e.into()))?;
+	Ok(SharedResponse {
+		status: parts.status,
+		headers: parts.headers,
+		body,
+	})
+}
+
+/// Removes the in flight entry once the first request completes, or is dropped before completing.
+struct InflightGuard<'a> {
+	coalescer: &'a Coalescer,
+	key: RequestKey,
+	done: bool,
+}
+
+impl InflightGuard<'_> {
+	fn finish(&mut self) -> Option<Arc<watch::Sender<Outcome>>> {
+		self.done = true;
+		self
+			.coalescer
+			.inflight
+			.lock()
+			.expect("mutex poisoned")
+			.remove(&self.key)
+	}
+}
+
+impl Drop for InflightGuard<'_> {
+	fn drop(&mut self) {
+		if !self.done {
+			self.finish();
+		}
+	}
+}
+
+#[cfg(test)]
+#[path = "coalesce_tests.rs"]
+mod tests;
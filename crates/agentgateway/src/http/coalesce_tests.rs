@@ -0,0 +1,67 @@
+use std::time::Duration;
+
+use super::*;
+
+fn key(headers: &[(&str, &str)]) -> RequestKey {
+	let mut req = ::http::Request::builder().uri("http://lo/v1/chat/completions");
+	for (k, v) in headers {
+		req = req.header(*k, *v);
+	}
+	let (parts, _) = req.body(()).unwrap().into_parts();
+	RequestKey::new(
+		strng::new("backend"),
+		Target::Address("127.0.0.1:8080".parse().unwrap()),
+		Transport::Plaintext,
+		&parts,
+		Bytes::from_static(b"{}"),
+	)
+}
+
+#[test]
+fn key_ignores_per_request_headers() {
+	let a = key(&[
+		("authorization", "Bearer a"),
+		(
+			"traceparent",
+			"00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01",
+		),
+		("tracestate", "a=1"),
+		("x-request-id", "1"),
+	]);
+	let b = key(&[
+		("authorization", "Bearer a"),
+		(
+			"traceparent",
+			"00-0af7651916cd43dd8448eb211c80319c-00f067aa0ba902b7-01",
+		),
+		("tracestate", "a=2"),
+		("x-request-id", "2"),
+	]);
+	assert_eq!(a, b);
+	assert_ne!(a, key(&[("authorization", "Bearer b")]));
+}
+
+#[tokio::test]
+async fn waiters_keep_error_status() {
+	let coalescer = Coalescer::default();
+	let leader = coalescer.call(key(&[]), || async {
+		tokio::time::sleep(Duration::from_millis(100)).await;
+		Err(ProxyError::RequestTimeout)
+	});
+	let waiter = async {
+		tokio::time::sleep(Duration::from_millis(10)).await;
+		// A waiter does not make its own call, so gets the leader's error rather than this one
+		coalescer
+			.call(key(&[]), || async { Err(ProxyError::InvalidRequest) })
+			.await
+	};
+	let (leader, waiter) = tokio::join!(leader, waiter);
+	for err in [leader.unwrap_err(), waiter.unwrap_err()] {
+		assert_eq!(err.as_response().status(), StatusCode::GATEWAY_TIMEOUT);
+		assert!(err.is_retryable());
+		assert_eq!(
+			err.as_grpc_response().headers().get("grpc-status").unwrap(),
+			&(tonic::Code::DeadlineExceeded as i32).to_string()
+		);
+	}
+}
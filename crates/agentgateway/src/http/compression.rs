@@ -0,0 +1,323 @@
+use std::convert::Infallible;
+use std::io::Write;
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+
+use flate2::write::{GzDecoder, GzEncoder};
+use http_body::Frame;
+use http_body_util::BodyExt;
+use pin_project_lite::pin_project;
+
+use crate::http::filters::Error;
+use crate::http::{HeaderMap, HeaderValue, Request, Response, StatusCode, header};
+use crate::*;
+
+const DEFAULT_CONTENT_TYPES: &[&str] = &[
+	"application/javascript",
+	"application/json",
+	"application/xml",
+	"image/svg+xml",
+	"text/css",
+	"text/html",
+	"text/javascript",
+	"text/plain",
+	"text/xml",
+];
+
+/// Compression gzip compresses responses for clients that accept it. Only responses of compressible
+/// content types at least `minSize` bytes long are compressed; responses the backend already
+/// encoded are left as-is.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+pub struct Compression {
+	/// Responses smaller than this are not worth compressing. Defaults to 1024 bytes.
+	#[serde(default = "default_min_size")]
+	pub min_size: usize,
+	/// Content types to compress. Defaults to common text types, such as JSON, HTML and plain text.
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub content_types: Vec<Strng>,
+	/// Decompress gzip encoded request bodies before they are sent to the backend.
+	#[serde(default)]
+	pub decompress_requests: bool,
+	/// The largest request body decompression may produce; larger requests fail. Defaults to 10MiB.
+	#[serde(default = "default_max_decompressed_size")]
+	pub max_decompressed_size: usize,
+}
+
+fn default_min_size() -> usize {
+	1024
+}
+
+pub(crate) fn default_max_decompressed_size() -> usize {
+	10_485_760
+}
+
+// Compressed data is decoded in chunks of this size, so a small, highly compressed frame cannot
+// expand far past the limit before it is checked.
+const DECODE_CHUNK_SIZE: usize = 8192;
+
+/// Recorded on requests handled by [`Compression`], so the response can be compressed.
+#[derive(Debug, Clone)]
+pub struct CompressionRequest {
+	filter: Compression,
+	accepts_gzip: bool,
+}
+
+impl Compression {
+	pub fn apply(&self, req: &mut Request) {
+		req.extensions_mut().insert(CompressionRequest {
+			filter: self.clone(),
+			accepts_gzip: accepts_gzip(req.headers()),
+		});
+		if self.decompress_requests && is_gzip(req.headers()) {
+			let headers = req.headers_mut();
+			headers.remove(header::CONTENT_ENCODING);
+			headers.remove(header::CONTENT_LENGTH);
+			let body = std::mem::take(req.body_mut());
+			let mut body = GzipBody::new(body, Coder::Decode(GzDecoder::new(vec![])));
+			body.limit = Some(self.max_decompressed_size);
+			*req.body_mut() = crate::http::Body::new(body);
+		}
+	}
+
+	fn is_compressible(&self, headers: &HeaderMap) -> bool {
+		let Some(content_type) = headers
+			.get(header::CONTENT_TYPE)
+			.and_then(|ct| ct.to_str().ok())
+			.and_then(|ct| ct.split(';').next())
+			.map(str::trim)
+		else {
+			return false;
+		};
+		if self.content_types.is_empty() {
+			DEFAULT_CONTENT_TYPES
+				.iter()
+				.any(|ct| ct.eq_ignore_ascii_case(content_type))
+		} else {
+			self
+				.content_types
+				.iter()
+				.any(|ct| ct.eq_ignore_ascii_case(content_type))
+		}
+	}
+}
+
+impl CompressionRequest {
+	/// Compress the response, if the client accepts gzip. Unless the response declares its length,
+	/// up to `minSize` bytes of the body are read first to decide whether it is worth compressing.
+	pub async fn apply_response(&self, resp: &mut Response) -> Result<(), Error> {
+		if !self.filter.is_compressible(resp.headers())
+			|| matches!(
+				resp.status(),
+				StatusCode::NO_CONTENT | StatusCode::NOT_MODIFIED
+			) {
+			return Ok(());
+		}
+		// The response differs depending on Accept-Encoding, so caches must take it into account
+		let varies = resp
+			.headers()
+			.get_all(header::VARY)
+			.iter()
+			.filter_map(|v| v.to_str().ok())
+			.flat_map(|v| v.split(','))
+			.any(|v| v.trim().eq_ignore_ascii_case("accept-encoding"));
+		if !varies {
+			resp
+				.headers_mut()
+				.append(header::VARY, HeaderValue::from_static("accept-encoding"));
+		}
+		if !self.accepts_gzip || resp.headers().contains_key(header::CONTENT_ENCODING) {
+			return Ok(());
+		}
+		let min_size = self.filter.min_size;
+		let content_length = resp
+			.headers()
+			.get(header::CONTENT_LENGTH)
+			.and_then(|l| l.to_str().ok())
+			.and_then(|l| l.parse::<usize>().ok());
+		let mut prefix = vec![];
+		if let Some(len) = content_length {
+			if len < min_size {
+				return Ok(());
+			}
+		} else {
+			let mut read = 0;
+			let mut ended = true;
+			while let Some(frame) = resp.body_mut().frame().await {
+				let frame = frame.map_err(|e| Error::InvalidBody(e.to_string()))?;
+				read += frame.data_ref().map(|d| d.len()).unwrap_or_default();
+				prefix.push(frame);
+				if read >= min_size {
+					ended = false;
+					break;
+				}
+			}
+			if ended && read < min_size {
+				// The whole body was read, and is too small to compress; send it as it was.
+				let frames = futures_util::stream::iter(prefix.into_iter().map(Ok::<_, Infallible>));
+				*resp.body_mut() = crate::http::Body::new(http_body_util::StreamBody::new(frames));
+				return Ok(());
+			}
+		}
+
+		let mut encoder = GzEncoder::new(vec![], flate2::Compression::default());
+		let mut trailers = None;
+		for frame in prefix {
+			match frame.into_data() {
+				Ok(data) => encoder
+					.write_all(&data)
+					.map_err(|e| Error::InvalidBody(e.to_string()))?,
+				Err(frame) => trailers = frame.into_trailers().ok(),
+			}
+		}
+		let headers = resp.headers_mut();
+		headers.insert(header::CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+		// The compressed length is not known until the whole body has been compressed
+		headers.remove(header::CONTENT_LENGTH);
+		let body = std::mem::take(resp.body_mut());
+		let mut body = GzipBody::new(body, Coder::Encode(encoder));
+		body.trailers = trailers;
+		*resp.body_mut() = crate::http::Body::new(body);
+		Ok(())
+	}
+}
+
+fn accepts_gzip(headers: &HeaderMap) -> bool {
+	headers
+		.get_all(header::ACCEPT_ENCODING)
+		.iter()
+		.filter_map(|v| v.to_str().ok())
+		.flat_map(|v| v.split(','))
+		.any(|coding| {
+			let mut params = coding.split(';');
+			let name = params.next().unwrap_or_default().trim();
+			let q = params
+				.find_map(|p| p.trim().strip_prefix("q="))
+				.and_then(|q| q.parse::<f32>().ok())
+				.unwrap_or(1.0);
+			(name.eq_ignore_ascii_case("gzip") || name == "*") && q > 0.0
+		})
+}
+
+fn is_gzip(headers: &HeaderMap) -> bool {
+	headers
+		.get(header::CONTENT_ENCODING)
+		.and_then(|e| e.to_str().ok())
+		.is_some_and(|e| e.trim().eq_ignore_ascii_case("gzip"))
+}
+
+enum Coder {
+	Encode(GzEncoder<Vec<u8>>),
+	Decode(GzDecoder<Vec<u8>>),
+}
+
+impl Coder {
+	fn write(&mut self, data: &[u8]) -> std::io::Result<()> {
+		match self {
+			Coder::Encode(e) => e.write_all(data),
+			Coder::Decode(d) => d.write_all(data),
+		}
+	}
+
+	fn finish(&mut self) -> std::io::Result<()> {
+		match self {
+			Coder::Encode(e) => e.try_finish(),
+			Coder::Decode(d) => d.try_finish(),
+		}
+	}
+
+	/// The output produced but not yet taken.
+	fn pending(&self) -> usize {
+		match self {
+			Coder::Encode(e) => e.get_ref().len(),
+			Coder::Decode(d) => d.get_ref().len(),
+		}
+	}
+
+	fn take(&mut self) -> Bytes {
+		let out = match self {
+			Coder::Encode(e) => e.get_mut(),
+			Coder::Decode(d) => d.get_mut(),
+		};
+		Bytes::from(std::mem::take(out))
+	}
+}
+
+pin_project! {
+	struct GzipBody {
+		#[pin]
+		body: crate::http::Body,
+		coder: Coder,
+		trailers: Option<HeaderMap>,
+		finished: bool,
+		// The most output the body may produce, if limited.
+		limit: Option<usize>,
+		written: usize,
+	}
+}
+
+impl GzipBody {
+	fn new(body: crate::http::Body, coder: Coder) -> Self {
+		GzipBody {
+			body,
+			coder,
+			trailers: None,
+			finished: false,
+			limit: None,
+			written: 0,
+		}
+	}
+}
+
+fn exceeds_limit(limit: Option<usize>, written: usize) -> std::io::Result<()> {
+	match limit {
+		Some(limit) if written > limit => Err(std::io::Error::other(format!(
+			"decompressed body exceeds the limit of {limit} bytes"
+		))),
+		_ => Ok(()),
+	}
+}
+
+impl http_body::Body for GzipBody {
+	type Data = Bytes;
+	type Error = Box<dyn std::error::Error + Send + Sync>;
+
+	fn poll_frame(
+		self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+	) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+		let mut this = self.project();
+		loop {
+			if *this.finished {
+				return Poll::Ready(this.trailers.take().map(|t| Ok(Frame::trailers(t))));
+			}
+			match ready!(this.body.as_mut().poll_frame(cx)) {
+				Some(frame) => match frame?.into_data() {
+					Ok(data) => {
+						for chunk in data.chunks(DECODE_CHUNK_SIZE) {
+							this.coder.write(chunk)?;
+							exceeds_limit(*this.limit, *this.written + this.coder.pending())?;
+						}
+					},
+					// Trailers are sent once the rest of the body has been flushed
+					Err(frame) => *this.trailers = frame.into_trailers().ok(),
+				},
+				None => {
+					this.coder.finish()?;
+					*this.finished = true;
+				},
+			}
+			let out = this.coder.take();
+			*this.written += out.len();
+			exceeds_limit(*this.limit, *this.written)?;
+			if !out.is_empty() {
+				return Poll::Ready(Some(Ok(Frame::data(out))));
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+#[path = "compression_tests.rs"]
+mod tests;
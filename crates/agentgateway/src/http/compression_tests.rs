@@ -0,0 +1,167 @@
+use std::io::Read;
+
+use flate2::read::GzDecoder as GzReader;
+use http_body_util::BodyExt;
+
+use super::*;
+
+fn compression(min_size: usize) -> Compression {
+	Compression {
+		min_size,
+		content_types: vec![],
+		decompress_requests: true,
+		max_decompressed_size: 1024,
+	}
+}
+
+fn request(accept_encoding: Option<&str>) -> Request {
+	let mut req = ::http::Request::builder().uri("http://lo/");
+	if let Some(ae) = accept_encoding {
+		req = req.header(header::ACCEPT_ENCODING, ae);
+	}
+	req.body(crate::http::Body::empty()).unwrap()
+}
+
+fn response(content_type: &str, body: &str, with_length: bool) -> Response {
+	let mut resp = ::http::Response::builder().header(header::CONTENT_TYPE, content_type);
+	if with_length {
+		resp = resp.header(header::CONTENT_LENGTH, body.len());
+	}
+	resp
+		.body(crate::http::Body::from(body.to_string()))
+		.unwrap()
+}
+
+async fn respond(filter: &Compression, req: &mut Request, mut resp: Response) -> Response {
+	filter.apply(req);
+	let cr = req.extensions().get::<CompressionRequest>().unwrap();
+	cr.apply_response(&mut resp).await.unwrap();
+	resp
+}
+
+async fn body_string(resp: Response) -> String {
+	let body = resp.into_body().collect().await.unwrap().to_bytes();
+	String::from_utf8(body.to_vec()).unwrap()
+}
+
+fn gunzip(b: &[u8]) -> String {
+	let mut out = String::new();
+	GzReader::new(b).read_to_string(&mut out).unwrap();
+	out
+}
+
+fn gzip(s: &str) -> Vec<u8> {
+	let mut e = GzEncoder::new(vec![], flate2::Compression::default());
+	e.write_all(s.as_bytes()).unwrap();
+	e.finish().unwrap()
+}
+
+#[test]
+fn accept_encoding() {
+	let accepts = |v: &str| {
+		let mut h = HeaderMap::new();
+		h.insert(header::ACCEPT_ENCODING, v.parse().unwrap());
+		accepts_gzip(&h)
+	};
+	assert!(accepts("gzip"));
+	assert!(accepts("br, GZIP;q=0.5"));
+	assert!(accepts("*"));
+	assert!(!accepts("br"));
+	assert!(!accepts("gzip;q=0"));
+	assert!(!accepts_gzip(&HeaderMap::new()));
+}
+
+#[tokio::test]
+async fn gzip_negotiated() {
+	let body = "{\"message\": \"hello\"}".repeat(100);
+	for with_length in [true, false] {
+		let mut req = request(Some("gzip, deflate"));
+		let resp = response("application/json", &body, with_length);
+		let resp = respond(&compression(1024), &mut req, resp).await;
+		assert_eq!(resp.headers()[header::CONTENT_ENCODING], "gzip");
+		assert_eq!(resp.headers()[header::VARY], "accept-encoding");
+		assert!(resp.headers().get(header::CONTENT_LENGTH).is_none());
+		let compressed = resp.into_body().collect().await.unwrap().to_bytes();
+		assert!(compressed.len() < body.len());
+		assert_eq!(gunzip(&compressed), body);
+	}
+}
+
+#[tokio::test]
+async fn small_body_not_compressed() {
+	let body = "{\"message\": \"hello\"}";
+	for with_length in [true, false] {
+		let mut req = request(Some("gzip"));
+		let resp = response("application/json", body, with_length);
+		let resp = respond(&compression(1024), &mut req, resp).await;
+		assert!(resp.headers().get(header::CONTENT_ENCODING).is_none());
+		assert_eq!(body_string(resp).await, body);
+	}
+}
+
+#[tokio::test]
+async fn not_accepted_or_not_compressible() {
+	let body = "x".repeat(2048);
+
+	let mut req = request(None);
+	let resp = response("text/plain", &body, true);
+	let resp = respond(&compression(10), &mut req, resp).await;
+	assert!(resp.headers().get(header::CONTENT_ENCODING).is_none());
+	// Still varies, as a client that accepts gzip would get a different response
+	assert_eq!(resp.headers()[header::VARY], "accept-encoding");
+	assert_eq!(body_string(resp).await, body);
+
+	let mut req = request(Some("gzip"));
+	let resp = response("image/png", &body, true);
+	let resp = respond(&compression(10), &mut req, resp).await;
+	assert!(resp.headers().get(header::CONTENT_ENCODING).is_none());
+	assert!(resp.headers().get(header::VARY).is_none());
+}
+
+#[tokio::test]
+async fn already_encoded() {
+	let mut req = request(Some("gzip"));
+	let mut resp = response("text/plain", "compressed elsewhere", true);
+	resp
+		.headers_mut()
+		.insert(header::CONTENT_ENCODING, HeaderValue::from_static("br"));
+	let resp = respond(&compression(0), &mut req, resp).await;
+	assert_eq!(resp.headers()[header::CONTENT_ENCODING], "br");
+	assert_eq!(body_string(resp).await, "compressed elsewhere");
+}
+
+#[tokio::test]
+async fn decompress_request() {
+	let mut req = ::http::Request::builder()
+		.uri("http://lo/")
+		.header(header::CONTENT_ENCODING, "gzip")
+		.body(crate::http::Body::from(gzip("hello world")))
+		.unwrap();
+	compression(1024).apply(&mut req);
+	assert!(req.headers().get(header::CONTENT_ENCODING).is_none());
+	let body = req.into_body().collect().await.unwrap().to_bytes();
+	assert_eq!(body.as_ref(), b"hello world");
+}
+
+#[tokio::test]
+async fn decompress_request_limit() {
+	// Highly compressible, so the compressed request is far below the limit
+	let body = "a".repeat(1025);
+	let mut req = ::http::Request::builder()
+		.uri("http://lo/")
+		.header(header::CONTENT_ENCODING, "gzip")
+		.body(crate::http::Body::from(gzip(&body)))
+		.unwrap();
+	compression(1024).apply(&mut req);
+	let err = req.into_body().collect().await.unwrap_err();
+	assert!(err.to_string().contains("exceeds the limit"), "{err}");
+
+	let mut req = ::http::Request::builder()
+		.uri("http://lo/")
+		.header(header::CONTENT_ENCODING, "gzip")
+		.body(crate::http::Body::from(gzip(&body[..1024])))
+		.unwrap();
+	compression(1024).apply(&mut req);
+	let got = req.into_body().collect().await.unwrap().to_bytes();
+	assert_eq!(got.len(), 1024);
+}
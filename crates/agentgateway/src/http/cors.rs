@@ -76,6 +76,71 @@ where
 	}
 }
 
+/// OriginMatcher matches a single allowed origin entry. Entries are exact origins, origins with a
+/// single `*` wildcard (such as `https://*.example.com`), or a regex prefixed with `regex:`.
+#[derive(Debug, Clone)]
+enum OriginMatcher {
+	Exact(Strng),
+	Wildcard { prefix: Strng, suffix: Strng },
+	Regex(regex::Regex),
+}
+
+const REGEX_PREFIX: &str = "regex:";
+
+impl OriginMatcher {
+	fn matches(&self, origin: &str) -> bool {
+		match self {
+			OriginMatcher::Exact(want) => want.as_str() == origin,
+			OriginMatcher::Wildcard { prefix, suffix } => {
+				origin.len() > prefix.len() + suffix.len()
+					&& origin.starts_with(prefix.as_str())
+					&& origin.ends_with(suffix.as_str())
+			},
+			OriginMatcher::Regex(re) => re.is_match(origin),
+		}
+	}
+}
+
+impl FromStr for OriginMatcher {
+	type Err = anyhow::Error;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		if let Some(re) = s.strip_prefix(REGEX_PREFIX) {
+			// Anchor the expression so it must match the entire origin
+			return Ok(OriginMatcher::Regex(regex::Regex::new(&format!(
+				"^(?:{re})$"
+			))?));
+		}
+		match s.split_once('*') {
+			None => Ok(OriginMatcher::Exact(strng::new(s))),
+			Some((_, suffix)) if suffix.contains('*') => {
+				anyhow::bail!("origin {s} may contain at most one wildcard")
+			},
+			Some((prefix, suffix)) => Ok(OriginMatcher::Wildcard {
+				prefix: strng::new(prefix),
+				suffix: strng::new(suffix),
+			}),
+		}
+	}
+}
+
+impl Display for OriginMatcher {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			OriginMatcher::Exact(o) => write!(f, "{o}"),
+			OriginMatcher::Wildcard { prefix, suffix } => write!(f, "{prefix}*{suffix}"),
+			OriginMatcher::Regex(re) => {
+				let re = re.as_str();
+				let re = re
+					.strip_prefix("^(?:")
+					.and_then(|r| r.strip_suffix(")$"))
+					.unwrap_or(re);
+				write!(f, "{REGEX_PREFIX}{re}")
+			},
+		}
+	}
+}
+
 #[derive(Debug, Clone, serde::Serialize)]
 #[serde(rename_all = "camelCase")]
 #[cfg_attr(feature = "schema", derive(JsonSchema))]
@@ -87,7 +152,7 @@ pub struct Cors {
 	#[serde(skip_serializing_if = "WildcardOrList::is_none")]
 	allow_methods: WildcardOrList<::http::Method>,
 	#[serde(skip_serializing_if = "WildcardOrList::is_none")]
-	allow_origins: WildcardOrList<Strng>,
+	allow_origins: WildcardOrList<OriginMatcher>,
 	#[serde(skip_serializing_if = "WildcardOrList::is_none")]
 	expose_headers: WildcardOrList<http::HeaderName>,
 	#[serde(serialize_with = "ser_string_or_bytes_option")]
@@ -125,6 +190,9 @@ struct CorsSerde {
 impl TryFrom<CorsSerde> for Cors {
 	type Error = anyhow::Error;
 	fn try_from(value: CorsSerde) -> Result<Self, Self::Error> {
+		if value.allow_credentials && value.allow_origins.iter().any(|o| o == "*") {
+			anyhow::bail!("allowOrigins cannot be '*' when allowCredentials is true");
+		}
 		Ok(Cors {
 			allow_credentials: value.allow_credentials,
 			allow_headers: WildcardOrList::try_from(value.allow_headers)?,
@@ -143,31 +211,38 @@ impl Cors {
 	/// Apply applies the CORS header. It seems a lot of implementations handle this differently wrt when
 	/// to add or not add headers, and when to forward the request.
 	/// We follow Envoy semantics here (with forwardNotMatchingPreflights=true)
+	/// Responses always carry `Vary: Origin`, even when no CORS headers are added, so shared caches do
+	/// not serve one origin's CORS headers to another.
 	pub fn apply(&self, req: &mut Request) -> Result<CorsResponse, filters::Error> {
 		// If no origin, return immediately
 		let Some(origin) = req.headers().get(header::ORIGIN) else {
-			return Ok(Default::default());
+			return Ok(CorsResponse::vary_only());
 		};
 
 		let allowed = match &self.allow_origins {
 			WildcardOrList::None => false,
 			WildcardOrList::Wildcard => true,
 			WildcardOrList::List(origins) => {
-				// TODO: allow wildcards
-				let os = origin.as_bytes();
-				origins.iter().any(|want| want.as_bytes() == os)
+				let Ok(os) = origin.to_str() else {
+					return Ok(CorsResponse::vary_only());
+				};
+				origins.iter().any(|want| want.matches(os))
 			},
 		};
 		if !allowed {
 			// None matching origin, return
-			return Ok(Default::default());
+			return Ok(CorsResponse::vary_only());
 		}
 
 		if req.method() == Method::OPTIONS {
 			// Handle preflight request
 			let mut rb = ::http::Response::builder()
 				.status(StatusCode::OK)
+				.header(header::VARY, VARY_PREFLIGHT)
 				.header(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+			if self.allow_credentials {
+				rb = rb.header(header::ACCESS_CONTROL_ALLOW_CREDENTIALS, HEADER_VALUE_TRUE);
+			}
 			if let Some(h) = self.allow_methods.to_header_value() {
 				rb = rb.header(header::ACCESS_CONTROL_ALLOW_METHODS, h);
 			}
@@ -184,7 +259,8 @@ impl Cors {
 			});
 		}
 
-		let mut response_headers = http::HeaderMap::with_capacity(3);
+		let mut response_headers = http::HeaderMap::with_capacity(4);
+		response_headers.insert(header::VARY, VARY_ORIGIN);
 		response_headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin.clone());
 		if self.allow_credentials {
 			response_headers.insert(header::ACCESS_CONTROL_ALLOW_CREDENTIALS, HEADER_VALUE_TRUE);
@@ -202,9 +278,27 @@ impl Cors {
 }
 
 const HEADER_VALUE_TRUE: http::HeaderValue = HeaderValue::from_static("true");
+const VARY_ORIGIN: http::HeaderValue = HeaderValue::from_static("Origin");
+const VARY_PREFLIGHT: http::HeaderValue =
+	HeaderValue::from_static("Origin, Access-Control-Request-Method, Access-Control-Request-Headers");
 
 #[derive(Debug, Default)]
 pub struct CorsResponse {
 	pub direct_response: Option<Response>,
 	pub response_headers: Option<http::HeaderMap>,
 }
+
+impl CorsResponse {
+	fn vary_only() -> Self {
+		let mut response_headers = http::HeaderMap::with_capacity(1);
+		response_headers.insert(header::VARY, VARY_ORIGIN);
+		CorsResponse {
+			direct_response: None,
+			response_headers: Some(response_headers),
+		}
+	}
+}
+
+#[cfg(test)]
+#[path = "cors_test.rs"]
+mod tests;
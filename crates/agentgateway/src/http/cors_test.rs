@@ -0,0 +1,143 @@
+use super::*;
+
+fn cors() -> Cors {
+	Cors::try_from(CorsSerde {
+		allow_credentials: true,
+		allow_headers: vec!["x-custom".to_string()],
+		allow_methods: vec!["GET".to_string(), "POST".to_string()],
+		allow_origins: vec!["https://example.com".to_string()],
+		expose_headers: vec![],
+		max_age: Some(Duration::from_secs(600)),
+	})
+	.unwrap()
+}
+
+fn request(method: Method, origin: Option<&str>) -> Request {
+	let mut rb = ::http::Request::builder()
+		.method(method)
+		.uri("http://example.com/");
+	if let Some(origin) = origin {
+		rb = rb
+			.header(header::ORIGIN, origin)
+			.header(header::ACCESS_CONTROL_REQUEST_METHOD, "POST");
+	}
+	rb.body(crate::http::Body::empty()).unwrap()
+}
+
+#[test]
+fn preflight_allowed_origin() {
+	let mut req = request(Method::OPTIONS, Some("https://example.com"));
+	let res = cors().apply(&mut req).unwrap();
+	let resp = res
+		.direct_response
+		.expect("preflight should be answered directly");
+	let h = resp.headers();
+	assert_eq!(h.get(header::ACCESS_CONTROL_MAX_AGE).unwrap(), "600");
+	assert_eq!(
+		h.get(header::VARY).unwrap(),
+		"Origin, Access-Control-Request-Method, Access-Control-Request-Headers"
+	);
+	assert_eq!(
+		h.get(header::ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(),
+		"https://example.com"
+	);
+	assert_eq!(
+		h.get(header::ACCESS_CONTROL_ALLOW_CREDENTIALS).unwrap(),
+		"true"
+	);
+}
+
+#[test]
+fn preflight_disallowed_origin() {
+	let mut req = request(Method::OPTIONS, Some("https://evil.com"));
+	let res = cors().apply(&mut req).unwrap();
+	// Not answered; the preflight is forwarded without CORS headers
+	assert!(res.direct_response.is_none());
+	let h = res.response_headers.unwrap();
+	assert_eq!(h.get(header::VARY).unwrap(), "Origin");
+	assert!(h.get(header::ACCESS_CONTROL_MAX_AGE).is_none());
+	assert!(h.get(header::ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+}
+
+#[test]
+fn simple_request_vary() {
+	let mut req = request(Method::GET, Some("https://example.com"));
+	let h = cors().apply(&mut req).unwrap().response_headers.unwrap();
+	assert_eq!(h.get(header::VARY).unwrap(), "Origin");
+	assert!(h.get(header::ACCESS_CONTROL_MAX_AGE).is_none());
+
+	let mut req = request(Method::GET, None);
+	let h = cors().apply(&mut req).unwrap().response_headers.unwrap();
+	assert_eq!(h.get(header::VARY).unwrap(), "Origin");
+	assert!(h.get(header::ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+}
+
+#[test]
+fn vary_is_merged() {
+	let mut dest = http::HeaderMap::new();
+	dest.insert(header::VARY, HeaderValue::from_static("Accept-Encoding"));
+	let mut req = request(Method::GET, Some("https://example.com"));
+	let h = cors().apply(&mut req).unwrap().response_headers;
+	http::merge_in_headers(h.clone(), &mut dest);
+	http::merge_in_headers(h, &mut dest);
+	assert_eq!(
+		dest.get_all(header::VARY).iter().collect::<Vec<_>>(),
+		vec!["Accept-Encoding", "Origin"]
+	);
+}
+
+fn cors_with_origins(origins: &[&str], allow_credentials: bool) -> anyhow::Result<Cors> {
+	Cors::try_from(CorsSerde {
+		allow_credentials,
+		allow_headers: vec![],
+		allow_methods: vec![],
+		allow_origins: origins.iter().map(|o| o.to_string()).collect(),
+		expose_headers: vec![],
+		max_age: None,
+	})
+}
+
+fn allowed_origin(cors: &Cors, origin: &str) -> Option<HeaderValue> {
+	let mut req = request(Method::GET, Some(origin));
+	cors
+		.apply(&mut req)
+		.unwrap()
+		.response_headers
+		.and_then(|h| h.get(header::ACCESS_CONTROL_ALLOW_ORIGIN).cloned())
+}
+
+#[test]
+fn wildcard_origin() {
+	let cors = cors_with_origins(&["https://*.example.com"], true).unwrap();
+	assert_eq!(
+		allowed_origin(&cors, "https://api.example.com").unwrap(),
+		"https://api.example.com"
+	);
+	assert_eq!(
+		allowed_origin(&cors, "https://a.b.example.com").unwrap(),
+		"https://a.b.example.com"
+	);
+	assert!(allowed_origin(&cors, "https://example.com").is_none());
+	assert!(allowed_origin(&cors, "https://evil.com").is_none());
+	assert!(allowed_origin(&cors, "https://api.example.com.evil.com").is_none());
+	assert!(allowed_origin(&cors, "http://api.example.com").is_none());
+}
+
+#[test]
+fn regex_origin() {
+	let cors = cors_with_origins(&[r"regex:https://(dev|prod)-\d+\.example\.com"], false).unwrap();
+	assert_eq!(
+		allowed_origin(&cors, "https://dev-1.example.com").unwrap(),
+		"https://dev-1.example.com"
+	);
+	assert!(allowed_origin(&cors, "https://test-1.example.com").is_none());
+	// The expression is anchored
+	assert!(allowed_origin(&cors, "https://dev-1.example.com.evil.com").is_none());
+}
+
+#[test]
+fn wildcard_with_credentials_rejected() {
+	assert!(cors_with_origins(&["*"], true).is_err());
+	assert!(cors_with_origins(&["*"], false).is_ok());
+	assert!(cors_with_origins(&["https://*.*.example.com"], false).is_err());
+}
@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 use std::convert::Infallible;
+use std::net::SocketAddr;
 use std::time::SystemTime;
 
 use ::http::uri::Authority;
@@ -17,11 +18,12 @@ use tokio_stream::wrappers::ReceiverStream;
 use crate::client::{Client, Transport};
 use crate::control::AuthSource;
 use crate::http::backendtls::BackendTLS;
-use crate::http::ext_authz::proto::attribute_context::HttpRequest;
+use crate::http::ext_authz::proto::attribute_context::{HttpRequest, Peer};
 use crate::http::ext_authz::proto::authorization_client::AuthorizationClient;
 use crate::http::ext_authz::proto::check_response::HttpResponse;
 use crate::http::ext_authz::proto::{
-	AttributeContext, CheckRequest, DeniedHttpResponse, HeaderValueOption, OkHttpResponse,
+	Address, AttributeContext, CheckRequest, DeniedHttpResponse, HeaderValueOption, OkHttpResponse,
+	SocketAddress, address, socket_address,
 };
 use crate::http::ext_proc::GrpcChannel;
 use crate::http::ext_proc::proto::{
@@ -33,9 +35,14 @@ use crate::http::{HeaderName, HeaderValue, PolicyResponse, Request, Response};
 use crate::proxy::ProxyError;
 use crate::transport::stream::{TCPConnectionInfo, TLSConnectionInfo};
 use crate::types::agent;
-use crate::types::agent::{Backend, Target};
+use crate::types::agent::{Backend, BackendName, Target};
 use crate::*;
 
+const X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+/// Carries the authenticated identity of the client, when it connected with mutual TLS.
+const X_FORWARDED_CLIENT_IDENTITY: HeaderName =
+	HeaderName::from_static("x-forwarded-client-identity");
+
 #[allow(warnings)]
 #[allow(clippy::derive_partial_eq_without_eq)]
 pub mod proto {
@@ -45,23 +52,155 @@ pub mod proto {
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ExtAuthz {
+	/// The authorization service. Calls use the TLS settings of the backend named after this target.
 	pub target: Target,
 	#[serde(default, skip_serializing_if = "Option::is_none")]
-	pub context: Option<HashMap<String, String>>, // TODO: gRPC vs HTTP, fail open, include body,
+	pub context: Option<HashMap<String, String>>, // TODO: fail open, include body,
+	#[serde(default)]
+	pub protocol: Protocol,
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Protocol {
+	/// Call the Envoy `envoy.service.auth.v3.Authorization` gRPC service.
+	#[default]
+	Grpc,
+	/// Send the request headers to an HTTP service. A 2xx response allows the request; any other
+	/// response is returned to the client as-is.
+	Http(HttpProtocol),
+}
+
+#[serde_with::serde_as]
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HttpProtocol {
+	/// Prepended to the request path when calling the authorization service.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub path_prefix: Option<String>,
+	/// Headers from an allowing response that are set on the upstream request.
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	#[serde_as(as = "Vec<serde_with::DisplayFromStr>")]
+	pub allowed_upstream_headers: Vec<HeaderName>,
 }
 
 impl ExtAuthz {
+	/// The backend whose policies (such as BackendTLS) apply to calls to the authorization service.
+	pub fn backend_name(&self) -> BackendName {
+		strng::format!("{}", self.target)
+	}
+
 	pub async fn check(
 		&self,
 		client: Client,
+		transport: Transport,
+		req: &mut Request,
+	) -> Result<PolicyResponse, ProxyError> {
+		match &self.protocol {
+			Protocol::Grpc => self.check_grpc(client, transport, req).await,
+			Protocol::Http(h) => self.check_http(h, client, transport, req).await,
+		}
+	}
+
+	async fn check_http(
+		&self,
+		cfg: &HttpProtocol,
+		client: Client,
+		transport: Transport,
+		req: &mut Request,
+	) -> Result<PolicyResponse, ProxyError> {
+		trace!("calling {}", self.target);
+		let path = req
+			.uri()
+			.path_and_query()
+			.map(|p| p.as_str())
+			.unwrap_or("/");
+		let uri = format!(
+			"{}://{}{}{}",
+			transport.scheme(),
+			self.target,
+			cfg.path_prefix.as_deref().unwrap_or_default(),
+			path
+		);
+		let mut rb = ::http::Request::builder()
+			.method(req.method().clone())
+			.uri(uri);
+		if let Some(hm) = rb.headers_mut() {
+			for (k, v) in req.headers() {
+				// The body is not sent, and the authorization service has its own host
+				if k == http::header::CONTENT_LENGTH
+					|| k == http::header::TRANSFER_ENCODING
+					|| k == http::header::HOST
+				{
+					continue;
+				}
+				hm.append(k.clone(), v.clone());
+			}
+			// Only the identity we verified ourselves may be forwarded
+			hm.remove(X_FORWARDED_CLIENT_IDENTITY);
+			if let Some(tcp_info) = req.extensions().get::<TCPConnectionInfo>() {
+				if let Ok(ip) = HeaderValue::try_from(tcp_info.peer_addr.ip().to_string()) {
+					hm.append(X_FORWARDED_FOR, ip);
+				}
+			}
+			if let Some(id) = req
+				.extensions()
+				.get::<TLSConnectionInfo>()
+				.and_then(|t| t.src_identity.as_ref())
+			{
+				if let Ok(id) = HeaderValue::try_from(id.to_string()) {
+					hm.insert(X_FORWARDED_CLIENT_IDENTITY, id);
+				}
+			}
+		}
+		let authz_req = rb
+			.body(http::Body::empty())
+			.map_err(|e| ProxyError::Processing(e.into()))?;
+		let resp = client
+			.call(client::Call {
+				req: authz_req,
+				target: self.target.clone(),
+				transport,
+				connection_pool: None,
+			})
+			.await
+			.map_err(|e| {
+				debug!("authorization request failed: {e}");
+				ProxyError::AuthorizationFailed
+			})?;
+		trace!("check response: {:?}", resp);
+
+		let mut res = PolicyResponse::default();
+		if resp.status().is_success() {
+			for h in &cfg.allowed_upstream_headers {
+				req.headers_mut().remove(h);
+				for v in resp.headers().get_all(h) {
+					req.headers_mut().append(h.clone(), v.clone());
+				}
+			}
+			return Ok(res);
+		}
+		debug!("status denied: {}", resp.status());
+		let (mut parts, body) = resp.into_parts();
+		let body = axum::body::to_bytes(body, 2_097_152)
+			.await
+			.map_err(|_| ProxyError::AuthorizationFailed)?;
+		parts.headers.remove(http::header::TRANSFER_ENCODING);
+		parts.headers.remove(http::header::CONTENT_LENGTH);
+		res.direct_response = Some(Response::from_parts(parts, http::Body::from(body)));
+		Ok(res)
+	}
+
+	async fn check_grpc(
+		&self,
+		client: Client,
+		transport: Transport,
 		req: &mut Request,
 	) -> Result<PolicyResponse, ProxyError> {
 		trace!("connecting to {}", self.target);
 		let chan = GrpcChannel {
 			target: self.target.clone(),
-			// TODO: lookup policy, don't hardcode
-			// transport: Transport::Tls(agent::INSECURE_TRUST.clone()),
-			transport: Transport::Plaintext,
+			transport,
 			client,
 		};
 		let mut client = AuthorizationClient::new(chan);
@@ -109,15 +248,24 @@ impl ExtAuthz {
 			}),
 		};
 
-		// Build source and destination information - simplified for now
-		let source = None; // TODO: Implement proper source address mapping
-		let destination = None; // TODO: Implement proper destination address mapping
+		let source = Some(Peer {
+			address: Some(socket_address(tcp_info.peer_addr)),
+			principal: tls_info
+				.and_then(|t| t.src_identity.as_ref())
+				.map(|id| id.to_string())
+				.unwrap_or_default(),
+			..Default::default()
+		});
+		let destination = Some(Peer {
+			address: Some(socket_address(tcp_info.local_addr)),
+			..Default::default()
+		});
 
 		// Build TLS session info if available
 		let tls_session =
 			tls_info.map(
-				|_tls_info| crate::http::ext_authz::proto::attribute_context::TlsSession {
-					sni: "".to_string(), // Could be extracted from TLS info if available
+				|tls_info| crate::http::ext_authz::proto::attribute_context::TlsSession {
+					sni: tls_info.server_name.clone().unwrap_or_default(),
 				},
 			);
 
@@ -193,6 +341,16 @@ impl ExtAuthz {
 	}
 }
 
+fn socket_address(addr: SocketAddr) -> Address {
+	Address {
+		address: Some(address::Address::SocketAddress(SocketAddress {
+			address: addr.ip().to_string(),
+			port_specifier: Some(socket_address::PortSpecifier::PortValue(addr.port() as u32)),
+			..Default::default()
+		})),
+	}
+}
+
 fn process_headers(hm: &mut HeaderMap, headers: Vec<HeaderValueOption>) {
 	for header in headers {
 		let Some(h) = header.header else { continue };
@@ -441,6 +441,7 @@ impl tower::Service<::http::Request<tonic::body::Body>> for GrpcChannel {
 						req,
 						target,
 						transport,
+						connection_pool: None,
 					})
 					.await?,
 			)
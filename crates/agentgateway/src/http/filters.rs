@@ -1,10 +1,19 @@
+use std::str::FromStr;
+
 use ::http::header::InvalidHeaderName;
 use ::http::response;
 use ::http::uri::InvalidUri;
 use anyhow::anyhow;
+use axum::body::to_bytes;
+use serde_json::Value;
 
+use crate::http::jwt::Claims;
 use crate::http::uri::Scheme;
-use crate::http::{HeaderMap, HeaderName, HeaderValue, Request, Response, StatusCode, Uri};
+use crate::http::{
+	HeaderMap, HeaderName, HeaderValue, Request, Response, StatusCode, Uri, WellKnownContentTypes,
+	classify_content_type, header,
+};
+use crate::transport::stream::TLSConnectionInfo;
 use crate::types::agent::{
 	Backend, HostRedirect, PathMatch, PathRedirect, SimpleBackend, SimpleBackendReference,
 };
@@ -44,6 +53,145 @@ impl HeaderModifier {
 	}
 }
 
+/// DynamicHeader sets request headers from templates that reference the request context. A template
+/// may contain the placeholders `%REMOTE_ADDR%` (the client IP), `%DOWNSTREAM_PEER_CN%` (the common
+/// name of the client certificate) and `%JWT(claim)%` (a claim of the verified JWT, where nested
+/// claims are separated by '.'). `%%` is a literal '%'.
+///
+/// Any copy of the header sent by the client is removed. If the template renders to an empty value,
+/// for example because the request has no JWT, the header is not set.
+#[serde_with::serde_as]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+pub struct DynamicHeader {
+	#[serde_as(as = "serde_with::Map<_, serde_with::DisplayFromStr>")]
+	#[cfg_attr(
+		feature = "schema",
+		schemars(with = "std::collections::BTreeMap<String, String>")
+	)]
+	pub set: Vec<(Strng, HeaderTemplate)>,
+}
+
+impl DynamicHeader {
+	pub fn apply(&self, req: &mut Request) -> Result<(), Error> {
+		for (k, template) in &self.set {
+			let name = HeaderName::from_bytes(k.as_bytes())?;
+			let value = template.render(req);
+			req.headers_mut().remove(&name);
+			if !value.is_empty() {
+				req.headers_mut().insert(name, value.parse()?);
+			}
+		}
+		Ok(())
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TemplatePart {
+	Literal(String),
+	RemoteAddr,
+	DownstreamPeerCn,
+	JwtClaim(String),
+}
+
+/// HeaderTemplate is a header value with `%PLACEHOLDER%`s that are filled in from the request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeaderTemplate {
+	raw: String,
+	parts: Vec<TemplatePart>,
+}
+
+impl FromStr for HeaderTemplate {
+	type Err = Error;
+
+	fn from_str(raw: &str) -> Result<Self, Self::Err> {
+		let mut parts = Vec::new();
+		let mut literal = String::new();
+		let mut rest = raw;
+		while let Some(start) = rest.find('%') {
+			literal.push_str(&rest[..start]);
+			let after = &rest[start + 1..];
+			let Some(end) = after.find('%') else {
+				// An unterminated placeholder is kept as-is.
+				literal.push_str(&rest[start..]);
+				rest = "";
+				break;
+			};
+			let placeholder = &after[..end];
+			rest = &after[end + 1..];
+			let part = match placeholder {
+				"" => {
+					literal.push('%');
+					continue;
+				},
+				"REMOTE_ADDR" => TemplatePart::RemoteAddr,
+				"DOWNSTREAM_PEER_CN" => TemplatePart::DownstreamPeerCn,
+				p => match p.strip_prefix("JWT(").and_then(|c| c.strip_suffix(')')) {
+					Some(claim) if !claim.is_empty() => TemplatePart::JwtClaim(claim.to_string()),
+					_ => {
+						warn!("dropping unknown header template placeholder %{p}%");
+						continue;
+					},
+				},
+			};
+			if !literal.is_empty() {
+				parts.push(TemplatePart::Literal(std::mem::take(&mut literal)));
+			}
+			parts.push(part);
+		}
+		literal.push_str(rest);
+		if !literal.is_empty() {
+			parts.push(TemplatePart::Literal(literal));
+		}
+		Ok(HeaderTemplate {
+			raw: raw.to_string(),
+			parts,
+		})
+	}
+}
+
+impl Display for HeaderTemplate {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_str(&self.raw)
+	}
+}
+
+impl HeaderTemplate {
+	fn render(&self, req: &Request) -> String {
+		let mut out = String::new();
+		for part in &self.parts {
+			match part {
+				TemplatePart::Literal(l) => out.push_str(l),
+				TemplatePart::RemoteAddr => {
+					if let Some(ip) = http::ClientIp::get(req) {
+						out.push_str(&ip.to_string());
+					}
+				},
+				TemplatePart::DownstreamPeerCn => {
+					if let Some(cn) = req
+						.extensions()
+						.get::<TLSConnectionInfo>()
+						.and_then(|tls| tls.peer_common_name.as_ref())
+					{
+						out.push_str(cn);
+					}
+				},
+				TemplatePart::JwtClaim(claim) => {
+					if let Some(v) = req
+						.extensions()
+						.get::<Claims>()
+						.and_then(|c| c.scalar(claim))
+					{
+						out.push_str(&v);
+					}
+				},
+			}
+		}
+		out
+	}
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
 #[cfg_attr(feature = "schema", derive(JsonSchema))]
@@ -157,6 +305,181 @@ pub struct RequestMirror {
 	pub backend: SimpleBackendReference,
 	// 0.0-1.0
 	pub percentage: f64,
+	// Compare the mirrored response to the primary one
+	pub compare: bool,
+}
+
+/// ResponseBodyTransform removes or masks fields in JSON responses. Fields are selected with
+/// JSONPath-like paths such as `user.ssn`, `$.items[*].email` or `data.*.token`, where `*` matches
+/// every key of an object or element of an array. Responses that are not JSON are passed through
+/// untouched, while a JSON response that cannot be parsed is rejected rather than returned as-is.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+pub struct ResponseBodyTransform {
+	/// Fields to remove from the response.
+	#[serde(default, skip_serializing_if = "is_default")]
+	pub remove: Vec<Strng>,
+	/// Fields to replace with `maskValue`.
+	#[serde(default, skip_serializing_if = "is_default")]
+	pub mask: Vec<Strng>,
+	/// The value masked fields are replaced with. Defaults to `****`.
+	#[serde(default, skip_serializing_if = "is_default")]
+	pub mask_value: Option<Strng>,
+	/// The largest response body that is buffered to be transformed; larger responses fail. Defaults
+	/// to 2MiB.
+	#[serde(default = "default_max_body_size")]
+	pub max_body_size: usize,
+}
+
+pub(crate) fn default_max_body_size() -> usize {
+	2_097_152
+}
+
+enum FieldAction<'a> {
+	Remove,
+	Mask(&'a str),
+}
+
+impl ResponseBodyTransform {
+	/// Drop any Accept-Encoding from the request, so the upstream responds with a body we are able to
+	/// transform. Requests that cannot get a JSON response, or when there is nothing to transform,
+	/// keep their encoding.
+	pub fn apply_request(&self, req: &mut Request) {
+		if self.is_noop() || !accepts_json(req.headers()) {
+			return;
+		}
+		req.headers_mut().remove(header::ACCEPT_ENCODING);
+	}
+
+	fn is_noop(&self) -> bool {
+		self.remove.is_empty() && self.mask.is_empty()
+	}
+
+	/// Transform the response body. Only JSON responses are buffered; anything else is streamed
+	/// through as-is.
+	pub async fn apply(&self, resp: &mut Response) -> Result<(), Error> {
+		if self.is_noop() {
+			return Ok(());
+		}
+		if !matches!(
+			classify_content_type(resp.headers()),
+			WellKnownContentTypes::Json
+		) {
+			return Ok(());
+		}
+		if resp
+			.headers()
+			.get(header::CONTENT_ENCODING)
+			.is_some_and(|e| e != "identity")
+		{
+			warn!("skipping response body transform of encoded response");
+			return Ok(());
+		}
+		let body = std::mem::replace(resp.body_mut(), http::Body::empty());
+		let bytes = to_bytes(body, self.max_body_size)
+			.await
+			.map_err(|e| Error::InvalidBody(e.to_string()))?;
+		let bytes = self.transform(bytes)?;
+		resp.headers_mut().remove(header::CONTENT_LENGTH);
+		*resp.body_mut() = http::Body::from(bytes);
+		Ok(())
+	}
+
+	fn transform(&self, body: Bytes) -> Result<Bytes, Error> {
+		if body.is_empty() {
+			return Ok(body);
+		}
+		let mut value: Value =
+			serde_json::from_slice(&body).map_err(|e| Error::InvalidBody(e.to_string()))?;
+		for path in &self.remove {
+			apply_field_action(&mut value, &field_path(path), &FieldAction::Remove);
+		}
+		let mask = self.mask_value.as_deref().unwrap_or("****");
+		for path in &self.mask {
+			apply_field_action(&mut value, &field_path(path), &FieldAction::Mask(mask));
+		}
+		serde_json::to_vec(&value)
+			.map(Bytes::from)
+			.map_err(|e| Error::InvalidBody(e.to_string()))
+	}
+}
+
+// Whether the Accept header allows a JSON response. A request without one accepts anything.
+fn accepts_json(headers: &HeaderMap) -> bool {
+	let mut accept = headers
+		.get_all(header::ACCEPT)
+		.iter()
+		.filter_map(|v| v.to_str().ok())
+		.flat_map(|v| v.split(','))
+		.map(|v| {
+			v.split(';')
+				.next()
+				.unwrap_or_default()
+				.trim()
+				.to_ascii_lowercase()
+		})
+		.peekable();
+	if accept.peek().is_none() {
+		return true;
+	}
+	accept.any(|mt| {
+		mt == "*/*" || mt == "application/*" || mt == "application/json" || mt.ends_with("+json")
+	})
+}
+
+// Split a path such as `$.items[*].email` into `["items", "*", "email"]`.
+fn field_path(path: &str) -> Vec<String> {
+	let path = path.strip_prefix('$').unwrap_or(path);
+	path
+		.replace('[', ".")
+		.replace(']', "")
+		.split('.')
+		.filter(|s| !s.is_empty())
+		.map(str::to_string)
+		.collect()
+}
+
+fn apply_field_action(value: &mut Value, path: &[String], action: &FieldAction) {
+	let Some((head, rest)) = path.split_first() else {
+		return;
+	};
+	let wildcard = head == "*";
+	if rest.is_empty() {
+		match (value, action) {
+			(Value::Object(map), FieldAction::Remove) if wildcard => map.clear(),
+			(Value::Object(map), FieldAction::Remove) => {
+				map.remove(head.as_str());
+			},
+			(Value::Array(list), FieldAction::Remove) if wildcard => list.clear(),
+			(Value::Array(list), FieldAction::Remove) => {
+				if let Some(idx) = head.parse::<usize>().ok().filter(|i| *i < list.len()) {
+					list.remove(idx);
+				}
+			},
+			(value, FieldAction::Mask(mask)) => {
+				for_each_child(value, head, |v| *v = Value::String(mask.to_string()))
+			},
+			_ => {},
+		}
+		return;
+	}
+	for_each_child(value, head, |v| apply_field_action(v, rest, action));
+}
+
+fn for_each_child(value: &mut Value, key: &str, mut f: impl FnMut(&mut Value)) {
+	match value {
+		Value::Object(map) if key == "*" => map.values_mut().for_each(f),
+		Value::Object(map) => map.get_mut(key).into_iter().for_each(f),
+		Value::Array(list) if key == "*" => list.iter_mut().for_each(f),
+		Value::Array(list) => key
+			.parse::<usize>()
+			.ok()
+			.and_then(|i| list.get_mut(i))
+			.into_iter()
+			.for_each(f),
+		_ => {},
+	}
 }
 
 fn rewrite_host(
@@ -257,6 +580,8 @@ pub enum Error {
 	InvalidHeaderValue(#[from] http::header::InvalidHeaderValue),
 	#[error("invalid filter configuration: {0}")]
 	InvalidFilterConfiguration(String),
+	#[error("invalid body: {0}")]
+	InvalidBody(String),
 	#[error("http error: {0}")]
 	Http(#[from] ::http::Error),
 }
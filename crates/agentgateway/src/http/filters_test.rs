@@ -2,7 +2,9 @@ use std::num::NonZeroU16;
 
 use regex;
 
-use crate::http::filters::{RequestRedirect, UrlRewrite};
+use crate::http::filters::{
+	DynamicHeader, HeaderTemplate, RequestRedirect, ResponseBodyTransform, UrlRewrite,
+};
 use crate::http::tests_common::*;
 use crate::http::{Body, HeaderName, Request, Response, StatusCode, Uri};
 use crate::types::agent::{HostRedirect, PathMatch, PathRedirect};
@@ -853,3 +855,222 @@ fn rewrite_test() {
 		assert_eq!(got, want, "{name}");
 	}
 }
+
+fn body_transform() -> ResponseBodyTransform {
+	ResponseBodyTransform {
+		remove: vec!["ssn".into(), "$.accounts[*].number".into()],
+		mask: vec!["email".into(), "accounts.0.owner".into()],
+		mask_value: None,
+		max_body_size: 1024,
+	}
+}
+
+async fn transform_response(
+	t: &ResponseBodyTransform,
+	content_type: &str,
+	body: &str,
+) -> (Response, String) {
+	let mut resp = ::http::Response::builder()
+		.header(http::header::CONTENT_TYPE, content_type)
+		.header(http::header::CONTENT_LENGTH, body.len())
+		.body(Body::from(body.to_string()))
+		.unwrap();
+	t.apply(&mut resp).await.unwrap();
+	let body = crate::http::inspect_body(resp.body_mut()).await.unwrap();
+	(resp, String::from_utf8(body.to_vec()).unwrap())
+}
+
+#[tokio::test]
+async fn response_body_transform_test() {
+	let body = serde_json::json!({
+		"name": "alice",
+		"ssn": "123-45-6789",
+		"email": "alice@example.com",
+		"accounts": [
+			{"number": "1111", "owner": "alice", "balance": 10},
+			{"number": "2222", "owner": "bob", "balance": 20},
+		],
+	});
+	let (resp, got) =
+		transform_response(&body_transform(), "application/json", &body.to_string()).await;
+	assert_eq!(
+		serde_json::from_str::<serde_json::Value>(&got).unwrap(),
+		serde_json::json!({
+			"name": "alice",
+			"email": "****",
+			"accounts": [
+				{"owner": "****", "balance": 10},
+				{"owner": "bob", "balance": 20},
+			],
+		})
+	);
+	// The length changed, so it must not be forwarded.
+	assert!(resp.headers().get(http::header::CONTENT_LENGTH).is_none());
+
+	let custom = ResponseBodyTransform {
+		remove: vec![],
+		mask: vec!["items[*]".into()],
+		mask_value: Some("[redacted]".into()),
+		max_body_size: 1024,
+	};
+	let (_, got) = transform_response(
+		&custom,
+		"application/json; charset=utf-8",
+		r#"{"items":["a","b"]}"#,
+	)
+	.await;
+	assert_eq!(got, r#"{"items":["[redacted]","[redacted]"]}"#);
+}
+
+#[tokio::test]
+async fn response_body_transform_passthrough_test() {
+	let body = r#"{"ssn":"123-45-6789"}"#;
+	let (resp, got) = transform_response(&body_transform(), "text/plain", body).await;
+	assert_eq!(got, body);
+	assert_eq!(
+		resp.headers().get(http::header::CONTENT_LENGTH).unwrap(),
+		&body.len().to_string()
+	);
+
+	// Invalid JSON is rejected, rather than returned unmodified.
+	let mut resp = ::http::Response::builder()
+		.header(http::header::CONTENT_TYPE, "application/json")
+		.body(Body::from("not json"))
+		.unwrap();
+	assert!(body_transform().apply(&mut resp).await.is_err());
+
+	// Bodies over the limit are rejected, rather than returned unmodified.
+	let mut resp = ::http::Response::builder()
+		.header(http::header::CONTENT_TYPE, "application/json")
+		.body(Body::from(format!(r#"{{"ssn":"{}"}}"#, "1".repeat(1024))))
+		.unwrap();
+	assert!(body_transform().apply(&mut resp).await.is_err());
+}
+
+#[test]
+fn response_body_transform_request_test() {
+	let encoding = |t: &ResponseBodyTransform, accept: Option<&str>| {
+		let mut headers = vec![("accept-encoding", "gzip")];
+		if let Some(accept) = accept {
+			headers.push(("accept", accept));
+		}
+		let mut req = request("http://example.com/", http::Method::GET, &headers);
+		t.apply_request(&mut req);
+		req.headers().contains_key(http::header::ACCEPT_ENCODING)
+	};
+	let t = body_transform();
+	assert!(!encoding(&t, None));
+	assert!(!encoding(&t, Some("application/json")));
+	assert!(!encoding(&t, Some("text/html, */*;q=0.8")));
+	assert!(!encoding(&t, Some("application/problem+json")));
+	// A JSON response is not acceptable, so it will not be transformed
+	assert!(encoding(&t, Some("text/event-stream")));
+	assert!(encoding(&t, Some("image/png, image/*")));
+
+	// Nothing to transform
+	let noop = ResponseBodyTransform {
+		remove: vec![],
+		mask: vec![],
+		mask_value: None,
+		max_body_size: 1024,
+	};
+	assert!(encoding(&noop, None));
+}
+
+fn dynamic_header(template: &str) -> DynamicHeader {
+	DynamicHeader {
+		set: vec![("x-injected".into(), template.parse().unwrap())],
+	}
+}
+
+fn injected(dh: &DynamicHeader, req: &mut Request) -> Option<String> {
+	dh.apply(req).unwrap();
+	req
+		.headers()
+		.get("x-injected")
+		.map(|v| v.to_str().unwrap().to_string())
+}
+
+#[test]
+fn dynamic_header_test() {
+	let mut req = request(
+		"http://example.com/",
+		http::Method::GET,
+		&[("x-injected", "spoofed")],
+	);
+	req
+		.extensions_mut()
+		.insert(crate::transport::stream::TCPConnectionInfo {
+			peer_addr: "10.0.0.1:12345".parse().unwrap(),
+			local_addr: "10.0.0.2:8080".parse().unwrap(),
+			start: std::time::Instant::now(),
+		});
+	req
+		.extensions_mut()
+		.insert(crate::transport::stream::TLSConnectionInfo {
+			src_identity: None,
+			server_name: None,
+			negotiated_alpn: None,
+			peer_common_name: Some("client.example.com".to_string()),
+		});
+	let serde_json::Value::Object(inner) = serde_json::json!({
+		"sub": "alice",
+		"realm": {"level": 3},
+	}) else {
+		unreachable!()
+	};
+	req.extensions_mut().insert(crate::http::jwt::Claims {
+		inner,
+		jwt: Default::default(),
+	});
+
+	assert_eq!(
+		injected(&dynamic_header("%REMOTE_ADDR%"), &mut req).as_deref(),
+		Some("10.0.0.1")
+	);
+	// The client IP resolved from trusted proxies is used over the peer
+	req
+		.extensions_mut()
+		.insert(crate::http::ClientIp("203.0.113.9".parse().unwrap()));
+	assert_eq!(
+		injected(&dynamic_header("%REMOTE_ADDR%"), &mut req).as_deref(),
+		Some("203.0.113.9")
+	);
+	assert_eq!(
+		injected(&dynamic_header("CN=%DOWNSTREAM_PEER_CN%"), &mut req).as_deref(),
+		Some("CN=client.example.com")
+	);
+	assert_eq!(
+		injected(&dynamic_header("%JWT(sub)%/%JWT(realm.level)%"), &mut req).as_deref(),
+		Some("alice/3")
+	);
+	assert_eq!(
+		injected(&dynamic_header("100%% %JWT(sub)%"), &mut req).as_deref(),
+		Some("100% alice")
+	);
+	// Unknown placeholders are dropped, rather than sent literally.
+	assert_eq!(
+		injected(&dynamic_header("a%UNKNOWN%b"), &mut req).as_deref(),
+		Some("ab")
+	);
+	// A missing value never leaves the client's copy of the header in place.
+	assert_eq!(injected(&dynamic_header("%JWT(email)%"), &mut req), None);
+}
+
+#[test]
+fn dynamic_header_without_context_test() {
+	let mut req = request(
+		"http://example.com/",
+		http::Method::GET,
+		&[("x-injected", "spoofed")],
+	);
+	let dh = dynamic_header("%REMOTE_ADDR%%DOWNSTREAM_PEER_CN%%JWT(sub)%");
+	assert_eq!(injected(&dh, &mut req), None);
+	assert_eq!(
+		"%JWT()% and %OTHER%"
+			.parse::<HeaderTemplate>()
+			.unwrap()
+			.to_string(),
+		"%JWT()% and %OTHER%"
+	);
+}
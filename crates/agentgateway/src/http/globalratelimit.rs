@@ -0,0 +1,286 @@
+use std::collections::BTreeMap;
+
+use async_trait::async_trait;
+use serde::de::Error;
+use tokio::sync::OnceCell;
+
+use crate::http::Request;
+use crate::http::localratelimit::{
+	MAX_KEYED_BUCKETS, RateLimit, RateLimitKey, RateLimitSerde, RateLimitType,
+};
+use crate::http::remoteratelimit::Descriptor;
+use crate::llm::LLMRequest;
+use crate::proxy::ProxyError;
+use crate::*;
+
+const KEY_PREFIX: &str = "agentgateway:ratelimit";
+const STORE_TIMEOUT: Duration = Duration::from_millis(500);
+
+// Token bucket kept as a hash of {tokens, ts}. The refill is computed from the Redis server clock so
+// that replicas with skewed clocks still share a single view of the bucket.
+// KEYS[1]: bucket key
+// ARGV: max_tokens, tokens_per_fill, fill_interval_ms, cost
+const TOKEN_BUCKET_SCRIPT: &str = r#"
+local max_tokens = tonumber(ARGV[1])
+local tokens_per_fill = tonumber(ARGV[2])
+local fill_interval = tonumber(ARGV[3])
+local cost = tonumber(ARGV[4])
+local now = redis.call('TIME')
+local now_ms = tonumber(now[1]) * 1000 + math.floor(tonumber(now[2]) / 1000)
+local state = redis.call('HMGET', KEYS[1], 'tokens', 'ts')
+local tokens = tonumber(state[1])
+local ts = tonumber(state[2])
+if tokens == nil or ts == nil then
+	tokens = max_tokens
+	ts = now_ms
+end
+local intervals = math.floor((now_ms - ts) / fill_interval)
+if intervals > 0 then
+	tokens = math.min(max_tokens, tokens + intervals * tokens_per_fill)
+	ts = ts + intervals * fill_interval
+end
+local allowed = 0
+if cost <= tokens then
+	tokens = tokens - cost
+	allowed = 1
+end
+redis.call('HSET', KEYS[1], 'tokens', tokens, 'ts', ts)
+local ttl = fill_interval * 2
+if tokens_per_fill > 0 then
+	ttl = ttl + math.ceil(max_tokens / tokens_per_fill) * fill_interval
+end
+redis.call('PEXPIRE', KEYS[1], ttl)
+return allowed
+"#;
+
+/// A RateLimitStore holds token buckets that are shared across proxy instances.
+#[async_trait]
+pub trait RateLimitStore: Send + Sync {
+	/// Atomically take `cost` tokens from the bucket identified by `key`. Either all tokens are
+	/// taken or none. Returns whether the tokens were available.
+	async fn try_acquire(&self, key: &str, limit: &RateLimitSerde, cost: u64)
+	-> anyhow::Result<bool>;
+}
+
+pub struct RedisStore {
+	client: redis::Client,
+	conn: OnceCell<redis::aio::ConnectionManager>,
+	script: redis::Script,
+}
+
+impl RedisStore {
+	pub fn new(url: &str) -> anyhow::Result<Self> {
+		Ok(RedisStore {
+			client: redis::Client::open(url)?,
+			conn: OnceCell::new(),
+			script: redis::Script::new(TOKEN_BUCKET_SCRIPT),
+		})
+	}
+
+	async fn connection(&self) -> anyhow::Result<redis::aio::ConnectionManager> {
+		let conn = self
+			.conn
+			.get_or_try_init(|| redis::aio::ConnectionManager::new(self.client.clone()))
+			.await?;
+		Ok(conn.clone())
+	}
+}
+
+#[async_trait]
+impl RateLimitStore for RedisStore {
+	async fn try_acquire(
+		&self,
+		key: &str,
+		limit: &RateLimitSerde,
+		cost: u64,
+	) -> anyhow::Result<bool> {
+		let mut conn = self.connection().await?;
+		let allowed: i64 = self
+			.script
+			.key(key)
+			.arg(limit.max_tokens)
+			.arg(limit.tokens_per_fill)
+			.arg(limit.fill_interval.as_millis().max(1) as u64)
+			.arg(cost)
+			.invoke_async(&mut conn)
+			.await?;
+		Ok(allowed == 1)
+	}
+}
+
+/// GlobalRateLimit is a token bucket rate limit whose state is shared between all proxy instances
+/// through a RateLimitStore.
+#[derive(Clone)]
+pub struct GlobalRateLimit {
+	config: GlobalRateLimitSerde,
+	store: Arc<dyn RateLimitStore>,
+	// Used when the store cannot be reached and failure_mode is Local. Buckets are keyed like the
+	// store's, so each set of descriptor values keeps its own local bucket.
+	local: Arc<Mutex<lru::LruCache<String, RateLimit>>>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct GlobalRateLimitSerde {
+	/// Redis connection URL, such as `redis://127.0.0.1:6379`.
+	pub redis: String,
+	/// Namespace for the bucket keys. Policies sharing a domain and descriptor values share a bucket.
+	#[serde(default)]
+	pub domain: Strng,
+	#[serde(default)]
+	pub descriptors: BTreeMap<String, Descriptor>,
+	#[serde(default)]
+	pub failure_mode: FailureMode,
+	pub limit: RateLimitSerde,
+}
+
+#[derive(Default, Debug, Eq, PartialEq, Clone, Copy, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FailureMode {
+	/// Enforce the limit with a local, per-instance bucket while the store is unavailable.
+	#[default]
+	Local,
+	/// Allow the request while the store is unavailable.
+	Open,
+	/// Reject the request while the store is unavailable.
+	Closed,
+}
+
+impl serde::Serialize for GlobalRateLimit {
+	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+	where
+		S: serde::Serializer,
+	{
+		self.config.serialize(serializer)
+	}
+}
+
+impl<'de> serde::Deserialize<'de> for GlobalRateLimit {
+	fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+	where
+		D: serde::Deserializer<'de>,
+	{
+		let ratelimit = GlobalRateLimitSerde::deserialize(deserializer)?;
+		GlobalRateLimit::try_from(ratelimit).map_err(D::Error::custom)
+	}
+}
+
+impl Debug for GlobalRateLimit {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("GlobalRateLimit")
+			.field("config", &self.config)
+			.finish()
+	}
+}
+
+impl TryFrom<GlobalRateLimitSerde> for GlobalRateLimit {
+	type Error = anyhow::Error;
+	fn try_from(value: GlobalRateLimitSerde) -> Result<Self, Self::Error> {
+		let store = Arc::new(RedisStore::new(&value.redis)?);
+		GlobalRateLimit::new(value, store)
+	}
+}
+
+impl GlobalRateLimit {
+	pub fn new(config: GlobalRateLimitSerde, store: Arc<dyn RateLimitStore>) -> anyhow::Result<Self> {
+		if config.limit.key != RateLimitKey::Global {
+			anyhow::bail!("global rate limits are keyed by descriptors, not limit.key");
+		}
+		// Validate the parameters up front, so the local buckets cannot fail to build later.
+		RateLimit::try_from(config.limit.clone())?;
+		Ok(GlobalRateLimit {
+			config,
+			store,
+			local: Arc::new(Mutex::new(lru::LruCache::new(MAX_KEYED_BUCKETS))),
+		})
+	}
+
+	pub fn limit_type(&self) -> &RateLimitType {
+		&self.config.limit.limit_type
+	}
+
+	pub async fn check_request(&self, req: &Request) -> Result<bool, ProxyError> {
+		if self.config.limit.limit_type != RateLimitType::Requests {
+			return Ok(true);
+		}
+		let Some(key) = self.key(req)? else {
+			return Ok(true);
+		};
+		Ok(self.acquire(&key, 1).await)
+	}
+
+	// TODO: add true-up for the response token usage
+	pub async fn check_llm_request(
+		&self,
+		req: &Request,
+		llm: &LLMRequest,
+	) -> Result<bool, ProxyError> {
+		if self.config.limit.limit_type != RateLimitType::Tokens {
+			return Ok(true);
+		}
+		let Some(key) = self.key(req)? else {
+			return Ok(true);
+		};
+		Ok(self.acquire(&key, llm.input_tokens).await)
+	}
+
+	async fn acquire(&self, key: &str, cost: u64) -> bool {
+		let res = tokio::time::timeout(
+			STORE_TIMEOUT,
+			self.store.try_acquire(key, &self.config.limit, cost),
+		)
+		.await
+		.map_err(anyhow::Error::from)
+		.and_then(|r| r);
+		match res {
+			Ok(allowed) => allowed,
+			Err(e) => {
+				warn!(
+					"global rate limit store failed, using {:?} mode: {e}",
+					self.config.failure_mode
+				);
+				match self.config.failure_mode {
+					FailureMode::Local => self.local_acquire(key, cost),
+					FailureMode::Open => true,
+					FailureMode::Closed => false,
+				}
+			},
+		}
+	}
+
+	fn local_acquire(&self, key: &str, cost: u64) -> bool {
+		let rl = self
+			.local
+			.lock()
+			.unwrap()
+			.get_or_insert(key.to_string(), || {
+				RateLimit::try_from(self.config.limit.clone())
+					.expect("parameters validated on construction")
+			})
+			.clone();
+		rl.try_acquire(cost)
+	}
+
+	/// Build the bucket key from the descriptors. If a descriptor does not match the request, the
+	/// limit does not apply and None is returned.
+	fn key(&self, req: &Request) -> Result<Option<String>, ProxyError> {
+		let mut key = format!("{KEY_PREFIX}:{}", self.config.domain);
+		for (k, lookup) in &self.config.descriptors {
+			let value = match lookup {
+				Descriptor::RequestHeader(h) => {
+					let Some(hv) = req.headers().get(h) else {
+						return Ok(None);
+					};
+					hv.to_str().map_err(|_| ProxyError::InvalidRequest)?
+				},
+				Descriptor::Static(v) => v.as_str(),
+			};
+			key.push_str(&format!(":{k}={value}"));
+		}
+		Ok(Some(key))
+	}
+}
+
+#[cfg(test)]
+#[path = "globalratelimit_test.rs"]
+mod tests;
@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+
+use itertools::Itertools;
+
+use super::*;
+use crate::http::HeaderName;
+
+#[derive(Default)]
+struct MemoryStore {
+	buckets: std::sync::Mutex<HashMap<String, u64>>,
+}
+
+#[async_trait]
+impl RateLimitStore for MemoryStore {
+	async fn try_acquire(
+		&self,
+		key: &str,
+		limit: &RateLimitSerde,
+		cost: u64,
+	) -> anyhow::Result<bool> {
+		let mut buckets = self.buckets.lock().unwrap();
+		let available = buckets.entry(key.to_string()).or_insert(limit.max_tokens);
+		if *available < cost {
+			return Ok(false);
+		}
+		*available -= cost;
+		Ok(true)
+	}
+}
+
+struct FailingStore;
+
+#[async_trait]
+impl RateLimitStore for FailingStore {
+	async fn try_acquire(&self, _: &str, _: &RateLimitSerde, _: u64) -> anyhow::Result<bool> {
+		anyhow::bail!("store unavailable")
+	}
+}
+
+fn config(failure_mode: FailureMode) -> GlobalRateLimitSerde {
+	GlobalRateLimitSerde {
+		redis: "redis://127.0.0.1:6379".to_string(),
+		domain: strng::new("test"),
+		descriptors: BTreeMap::from([(
+			"user".to_string(),
+			Descriptor::RequestHeader(HeaderName::from_static("x-user")),
+		)]),
+		failure_mode,
+		limit: RateLimitSerde {
+			max_tokens: 2,
+			tokens_per_fill: 1,
+			fill_interval: Duration::from_secs(60),
+			limit_type: RateLimitType::Requests,
+			key: Default::default(),
+		},
+	}
+}
+
+fn request(user: Option<&str>) -> Request {
+	let mut rb = ::http::Request::builder().uri("http://example.com/");
+	if let Some(user) = user {
+		rb = rb.header("x-user", user);
+	}
+	rb.body(crate::http::Body::empty()).unwrap()
+}
+
+#[tokio::test]
+async fn test_shared_bucket() {
+	let store = Arc::new(MemoryStore::default());
+	// Two "replicas" sharing a store share the bucket
+	let a = GlobalRateLimit::new(config(FailureMode::Local), store.clone()).unwrap();
+	let b = GlobalRateLimit::new(config(FailureMode::Local), store.clone()).unwrap();
+	assert!(a.check_request(&request(Some("alice"))).await.unwrap());
+	assert!(b.check_request(&request(Some("alice"))).await.unwrap());
+	assert!(!a.check_request(&request(Some("alice"))).await.unwrap());
+	// Other descriptor values get their own bucket
+	assert!(b.check_request(&request(Some("bob"))).await.unwrap());
+	// No match on the descriptor means the limit does not apply
+	for _ in 0..5 {
+		assert!(a.check_request(&request(None)).await.unwrap());
+	}
+	assert_eq!(
+		store
+			.buckets
+			.lock()
+			.unwrap()
+			.keys()
+			.sorted()
+			.cloned()
+			.collect_vec(),
+		vec![
+			"agentgateway:ratelimit:test:user=alice".to_string(),
+			"agentgateway:ratelimit:test:user=bob".to_string(),
+		]
+	);
+}
+
+#[tokio::test]
+async fn test_failure_mode() {
+	let req = request(Some("alice"));
+
+	let open = GlobalRateLimit::new(config(FailureMode::Open), Arc::new(FailingStore)).unwrap();
+	for _ in 0..5 {
+		assert!(open.check_request(&req).await.unwrap());
+	}
+
+	let closed = GlobalRateLimit::new(config(FailureMode::Closed), Arc::new(FailingStore)).unwrap();
+	assert!(!closed.check_request(&req).await.unwrap());
+
+	let local = GlobalRateLimit::new(config(FailureMode::Local), Arc::new(FailingStore)).unwrap();
+	assert!(local.check_request(&req).await.unwrap());
+	assert!(local.check_request(&req).await.unwrap());
+	assert!(!local.check_request(&req).await.unwrap());
+}
+
+#[test]
+fn test_deserialize() {
+	let rl: GlobalRateLimit = serde_json::from_value(serde_json::json!({
+		"redis": "redis://127.0.0.1:6379",
+		"descriptors": {"user": {"requestHeader": "x-user"}},
+		"failureMode": "closed",
+		"limit": {"maxTokens": 10, "tokensPerFill": 1, "fillInterval": "1s", "type": "tokens"},
+	}))
+	.unwrap();
+	assert_eq!(rl.limit_type(), &RateLimitType::Tokens);
+	assert_eq!(rl.config.failure_mode, FailureMode::Closed);
+
+	let err = serde_json::from_value::<GlobalRateLimit>(serde_json::json!({
+		"redis": "not a url",
+		"limit": {"maxTokens": 10, "tokensPerFill": 1, "fillInterval": "1s"},
+	}));
+	assert!(err.is_err());
+}
+
+#[tokio::test]
+async fn test_local_failure_mode_is_keyed() {
+	let local = GlobalRateLimit::new(config(FailureMode::Local), Arc::new(FailingStore)).unwrap();
+	let alice = request(Some("alice"));
+	assert!(local.check_request(&alice).await.unwrap());
+	assert!(local.check_request(&alice).await.unwrap());
+	assert!(!local.check_request(&alice).await.unwrap());
+	// Other descriptor values are not limited by alice's local bucket
+	let bob = request(Some("bob"));
+	assert!(local.check_request(&bob).await.unwrap());
+	assert!(local.check_request(&bob).await.unwrap());
+	assert!(!local.check_request(&bob).await.unwrap());
+	// Clones share the local buckets
+	assert!(!local.clone().check_request(&alice).await.unwrap());
+}
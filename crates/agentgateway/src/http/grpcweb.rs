@@ -0,0 +1,244 @@
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use bytes::{BufMut, BytesMut};
+use http_body::Frame;
+use pin_project_lite::pin_project;
+
+use crate::http::{HeaderMap, HeaderValue, Request, Response, header};
+use crate::*;
+
+const GRPC_WEB: &str = "application/grpc-web";
+const GRPC_WEB_TEXT: &str = "application/grpc-web-text";
+const GRPC: &str = "application/grpc";
+
+/// Flag set on the final gRPC-Web frame, which carries the trailers instead of a message.
+const TRAILER_FLAG: u8 = 0x80;
+
+/// GrpcWeb translates gRPC-Web requests, as sent by browsers, into standard gRPC so the backend
+/// does not need to understand gRPC-Web. Both the binary (`application/grpc-web`) and base64
+/// (`application/grpc-web-text`) encodings are supported. Other requests are left untouched.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+pub struct GrpcWeb {}
+
+/// Recorded on requests translated by [`GrpcWeb`], so the response can be translated back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GrpcWebRequest {
+	text: bool,
+}
+
+impl GrpcWeb {
+	pub fn apply(&self, req: &mut Request) {
+		let Some((text, suffix)) = req
+			.headers()
+			.get(header::CONTENT_TYPE)
+			.and_then(|ct| ct.to_str().ok())
+			.and_then(parse_content_type)
+		else {
+			return;
+		};
+		let Ok(content_type) = HeaderValue::from_str(&format!("{GRPC}{suffix}")) else {
+			return;
+		};
+		let headers = req.headers_mut();
+		headers.insert(header::CONTENT_TYPE, content_type);
+		headers.insert(header::TE, HeaderValue::from_static("trailers"));
+		// gRPC requires HTTP/2 to the backend, even though browsers may send gRPC-Web over HTTP/1.1.
+		*req.version_mut() = ::http::Version::HTTP_2;
+		req.extensions_mut().insert(GrpcWebRequest { text });
+		if text {
+			// The decoded body is smaller than what the client sent
+			req.headers_mut().remove(header::CONTENT_LENGTH);
+			let body = std::mem::take(req.body_mut());
+			*req.body_mut() = crate::http::Body::new(Base64DecodeBody {
+				body,
+				pending: BytesMut::new(),
+			});
+		}
+	}
+}
+
+impl GrpcWebRequest {
+	/// Translate a gRPC response back into gRPC-Web. Responses that are not gRPC, such as errors
+	/// from the backend, are left untouched.
+	pub fn apply_response(&self, resp: &mut Response) {
+		let Some(suffix) = resp
+			.headers()
+			.get(header::CONTENT_TYPE)
+			.and_then(|ct| ct.to_str().ok())
+			.and_then(|ct| ct.strip_prefix(GRPC))
+			.filter(|suffix| is_content_type_suffix(suffix))
+		else {
+			return;
+		};
+		let prefix = if self.text { GRPC_WEB_TEXT } else { GRPC_WEB };
+		let Ok(content_type) = HeaderValue::from_str(&format!("{prefix}{suffix}")) else {
+			return;
+		};
+		let headers = resp.headers_mut();
+		headers.insert(header::CONTENT_TYPE, content_type);
+		headers.remove(header::CONTENT_LENGTH);
+		let body = std::mem::take(resp.body_mut());
+		*resp.body_mut() = crate::http::Body::new(GrpcWebResponseBody {
+			body,
+			text: self.text,
+			pending: BytesMut::new(),
+			done: false,
+		});
+	}
+}
+
+/// Returns whether the request is base64 encoded, and the suffix (such as `+proto`) to keep.
+fn parse_content_type(ct: &str) -> Option<(bool, &str)> {
+	let (text, suffix) = if let Some(suffix) = ct.strip_prefix(GRPC_WEB_TEXT) {
+		(true, suffix)
+	} else {
+		(false, ct.strip_prefix(GRPC_WEB)?)
+	};
+	is_content_type_suffix(suffix).then_some((text, suffix))
+}
+
+fn is_content_type_suffix(suffix: &str) -> bool {
+	suffix.is_empty() || suffix.starts_with('+') || suffix.starts_with(';')
+}
+
+/// Encode trailers as a gRPC-Web trailer frame.
+fn trailer_frame(trailers: &HeaderMap) -> Bytes {
+	let mut block = Vec::new();
+	for (name, value) in trailers {
+		block.extend_from_slice(name.as_str().as_bytes());
+		block.extend_from_slice(b": ");
+		block.extend_from_slice(value.as_bytes());
+		block.extend_from_slice(b"\r\n");
+	}
+	let mut frame = BytesMut::with_capacity(5 + block.len());
+	frame.put_u8(TRAILER_FLAG);
+	frame.put_u32(block.len() as u32);
+	frame.extend_from_slice(&block);
+	frame.freeze()
+}
+
+/// Decode as much of `pending` as possible, leaving any incomplete quad for the next call.
+/// Clients may encode each message separately, so padding can appear in the middle of the stream.
+fn decode_base64(pending: &mut BytesMut) -> Result<Bytes, base64::DecodeError> {
+	let complete = pending.len() - pending.len() % 4;
+	let input = pending.split_to(complete);
+	let mut out = Vec::with_capacity(complete / 4 * 3);
+	let mut start = 0;
+	for end in (4..=input.len()).step_by(4) {
+		if input[end - 1] == b'=' || end == input.len() {
+			STANDARD.decode_vec(&input[start..end], &mut out)?;
+			start = end;
+		}
+	}
+	Ok(Bytes::from(out))
+}
+
+/// Encode as much of `pending` as possible without padding, unless this is the end of the stream.
+fn encode_base64(pending: &mut BytesMut, flush: bool) -> Bytes {
+	let complete = if flush {
+		pending.len()
+	} else {
+		pending.len() - pending.len() % 3
+	};
+	let input = pending.split_to(complete);
+	Bytes::from(STANDARD.encode(input))
+}
+
+pin_project! {
+	struct Base64DecodeBody {
+		#[pin]
+		body: crate::http::Body,
+		pending: BytesMut,
+	}
+}
+
+impl http_body::Body for Base64DecodeBody {
+	type Data = Bytes;
+	type Error = Box<dyn std::error::Error + Send + Sync>;
+
+	fn poll_frame(
+		self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+	) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+		let mut this = self.project();
+		loop {
+			let Some(frame) = ready!(this.body.as_mut().poll_frame(cx)) else {
+				if !this.pending.is_empty() {
+					return Poll::Ready(Some(Err("truncated grpc-web-text body".into())));
+				}
+				return Poll::Ready(None);
+			};
+			let data = match frame?.into_data() {
+				Ok(data) => data,
+				Err(frame) => return Poll::Ready(Some(Ok(frame))),
+			};
+			this.pending.extend_from_slice(&data);
+			let decoded = decode_base64(this.pending)?;
+			if !decoded.is_empty() {
+				return Poll::Ready(Some(Ok(Frame::data(decoded))));
+			}
+		}
+	}
+}
+
+pin_project! {
+	struct GrpcWebResponseBody {
+		#[pin]
+		body: crate::http::Body,
+		text: bool,
+		pending: BytesMut,
+		done: bool,
+	}
+}
+
+impl http_body::Body for GrpcWebResponseBody {
+	type Data = Bytes;
+	type Error = Box<dyn std::error::Error + Send + Sync>;
+
+	fn poll_frame(
+		self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+	) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+		let mut this = self.project();
+		loop {
+			if *this.done {
+				return Poll::Ready(None);
+			}
+			let (data, flush) = match ready!(this.body.as_mut().poll_frame(cx)) {
+				Some(frame) => match frame?.into_data() {
+					Ok(data) => (data, false),
+					// Browsers cannot read HTTP trailers, so they are sent as the final frame of the body.
+					Err(frame) => match frame.into_trailers() {
+						Ok(trailers) => {
+							*this.done = true;
+							(trailer_frame(&trailers), true)
+						},
+						Err(_) => continue,
+					},
+				},
+				None => {
+					*this.done = true;
+					(Bytes::new(), true)
+				},
+			};
+			let data = if *this.text {
+				this.pending.extend_from_slice(&data);
+				encode_base64(this.pending, flush)
+			} else {
+				data
+			};
+			if !data.is_empty() {
+				return Poll::Ready(Some(Ok(Frame::data(data))));
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+#[path = "grpcweb_tests.rs"]
+mod tests;
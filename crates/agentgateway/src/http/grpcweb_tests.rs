@@ -0,0 +1,173 @@
+use http_body_util::{BodyExt, StreamBody};
+
+use super::*;
+
+// A gRPC length-prefixed message.
+fn message(payload: &[u8]) -> Vec<u8> {
+	let mut m = vec![0];
+	m.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+	m.extend_from_slice(payload);
+	m
+}
+
+fn body_of(frames: Vec<http_body::Frame<Bytes>>) -> crate::http::Body {
+	let stream = futures_util::stream::iter(frames.into_iter().map(Ok::<_, std::io::Error>));
+	crate::http::Body::new(StreamBody::new(stream))
+}
+
+fn grpc_web_request(content_type: &str, chunks: &[&[u8]]) -> Request {
+	let frames = chunks
+		.iter()
+		.map(|c| http_body::Frame::data(Bytes::copy_from_slice(c)))
+		.collect();
+	::http::Request::builder()
+		.method("POST")
+		.uri("http://lo/helloworld.Greeter/SayHello")
+		.header(header::CONTENT_TYPE, content_type)
+		.header(header::CONTENT_LENGTH, "100")
+		.body(body_of(frames))
+		.unwrap()
+}
+
+fn grpc_response(trailers: bool) -> Response {
+	let mut frames = vec![http_body::Frame::data(Bytes::from(message(b"hello")))];
+	if trailers {
+		let mut t = HeaderMap::new();
+		t.insert("grpc-status", HeaderValue::from_static("0"));
+		t.insert("grpc-message", HeaderValue::from_static("ok"));
+		frames.push(http_body::Frame::trailers(t));
+	}
+	::http::Response::builder()
+		.header(header::CONTENT_TYPE, "application/grpc+proto")
+		.body(body_of(frames))
+		.unwrap()
+}
+
+#[test]
+fn content_type() {
+	assert_eq!(
+		parse_content_type("application/grpc-web"),
+		Some((false, ""))
+	);
+	assert_eq!(
+		parse_content_type("application/grpc-web+proto"),
+		Some((false, "+proto"))
+	);
+	assert_eq!(
+		parse_content_type("application/grpc-web-text"),
+		Some((true, ""))
+	);
+	assert_eq!(
+		parse_content_type("application/grpc-web-text+proto"),
+		Some((true, "+proto"))
+	);
+	assert_eq!(parse_content_type("application/grpc"), None);
+	assert_eq!(parse_content_type("application/grpc-webx"), None);
+	assert_eq!(parse_content_type("application/json"), None);
+}
+
+#[tokio::test]
+async fn binary_request() {
+	let msg = message(b"hello");
+	let mut req = grpc_web_request("application/grpc-web+proto", &[&msg]);
+	GrpcWeb {}.apply(&mut req);
+	assert_eq!(req.version(), ::http::Version::HTTP_2);
+	assert_eq!(
+		req.headers()[header::CONTENT_TYPE],
+		"application/grpc+proto"
+	);
+	assert_eq!(req.headers()[header::TE], "trailers");
+	assert_eq!(req.headers()[header::CONTENT_LENGTH], "100");
+	assert_eq!(
+		req.extensions().get::<GrpcWebRequest>(),
+		Some(&GrpcWebRequest { text: false })
+	);
+	let body = req.into_body().collect().await.unwrap().to_bytes();
+	assert_eq!(body.as_ref(), msg.as_slice());
+}
+
+#[tokio::test]
+async fn text_request() {
+	// Each message is encoded on its own, and the stream is split mid-quad.
+	let first = STANDARD.encode(message(b"hello"));
+	let second = STANDARD.encode(message(b"world!"));
+	let encoded = format!("{first}{second}");
+	let (a, b) = encoded.as_bytes().split_at(7);
+	let mut req = grpc_web_request("application/grpc-web-text", &[a, b]);
+	GrpcWeb {}.apply(&mut req);
+	assert_eq!(req.headers()[header::CONTENT_TYPE], "application/grpc");
+	assert!(req.headers().get(header::CONTENT_LENGTH).is_none());
+	let body = req.into_body().collect().await.unwrap().to_bytes();
+	let mut want = message(b"hello");
+	want.extend(message(b"world!"));
+	assert_eq!(body.as_ref(), want.as_slice());
+}
+
+#[tokio::test]
+async fn truncated_text_request() {
+	let mut req = grpc_web_request("application/grpc-web-text", &[b"AAAAB"]);
+	GrpcWeb {}.apply(&mut req);
+	assert!(req.into_body().collect().await.is_err());
+}
+
+#[tokio::test]
+async fn non_grpc_web_request() {
+	let mut req = grpc_web_request("application/grpc", &[b"abc"]);
+	GrpcWeb {}.apply(&mut req);
+	assert_eq!(req.version(), ::http::Version::HTTP_11);
+	assert_eq!(req.headers()[header::CONTENT_TYPE], "application/grpc");
+	assert!(req.extensions().get::<GrpcWebRequest>().is_none());
+}
+
+#[tokio::test]
+async fn binary_response() {
+	let mut resp = grpc_response(true);
+	GrpcWebRequest { text: false }.apply_response(&mut resp);
+	assert_eq!(
+		resp.headers()[header::CONTENT_TYPE],
+		"application/grpc-web+proto"
+	);
+	let collected = resp.into_body().collect().await.unwrap();
+	assert!(collected.trailers().is_none());
+	let mut want = message(b"hello");
+	want.push(TRAILER_FLAG);
+	let block = b"grpc-status: 0\r\ngrpc-message: ok\r\n";
+	want.extend_from_slice(&(block.len() as u32).to_be_bytes());
+	want.extend_from_slice(block);
+	assert_eq!(collected.to_bytes().as_ref(), want.as_slice());
+}
+
+#[tokio::test]
+async fn text_response() {
+	let mut resp = grpc_response(true);
+	GrpcWebRequest { text: true }.apply_response(&mut resp);
+	assert_eq!(
+		resp.headers()[header::CONTENT_TYPE],
+		"application/grpc-web-text+proto"
+	);
+	let body = resp.into_body().collect().await.unwrap().to_bytes();
+	let decoded = STANDARD.decode(&body).unwrap();
+	assert_eq!(&decoded[..10], message(b"hello").as_slice());
+	assert_eq!(decoded[10], TRAILER_FLAG);
+	assert!(decoded.ends_with(b"grpc-message: ok\r\n"));
+}
+
+#[tokio::test]
+async fn response_without_trailers() {
+	let mut resp = grpc_response(false);
+	GrpcWebRequest { text: true }.apply_response(&mut resp);
+	let body = resp.into_body().collect().await.unwrap().to_bytes();
+	assert_eq!(STANDARD.decode(&body).unwrap(), message(b"hello"));
+}
+
+#[tokio::test]
+async fn non_grpc_response() {
+	let mut resp = ::http::Response::builder()
+		.header(header::CONTENT_TYPE, "text/plain")
+		.body(crate::http::Body::from("no healthy upstream"))
+		.unwrap();
+	GrpcWebRequest { text: true }.apply_response(&mut resp);
+	assert_eq!(resp.headers()[header::CONTENT_TYPE], "text/plain");
+	let body = resp.into_body().collect().await.unwrap().to_bytes();
+	assert_eq!(body.as_ref(), b"no healthy upstream");
+}
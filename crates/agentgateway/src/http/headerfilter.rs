@@ -0,0 +1,102 @@
+use std::fmt::Display;
+use std::str::FromStr;
+
+use crate::http::{HeaderMap, HeaderName};
+use crate::*;
+
+/// HeaderFilter removes headers from requests sent to a backend, and from the responses it returns.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+pub struct HeaderFilter {
+	/// Applied to requests before they are sent to the backend.
+	#[serde(default)]
+	pub request: HeaderRules,
+	/// Applied to responses before they are returned to the client.
+	#[serde(default)]
+	pub response: HeaderRules,
+}
+
+#[serde_with::serde_as]
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+pub struct HeaderRules {
+	/// If set, only headers matching one of these names are kept.
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	#[serde_as(as = "Vec<serde_with::DisplayFromStr>")]
+	#[cfg_attr(feature = "schema", schemars(with = "Vec<String>"))]
+	pub allow: Vec<HeaderPattern>,
+	/// Headers matching one of these names are removed. A trailing '*' matches any suffix.
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	#[serde_as(as = "Vec<serde_with::DisplayFromStr>")]
+	#[cfg_attr(feature = "schema", schemars(with = "Vec<String>"))]
+	pub deny: Vec<HeaderPattern>,
+}
+
+impl HeaderRules {
+	pub fn apply(&self, headers: &mut HeaderMap) {
+		if self.allow.is_empty() && self.deny.is_empty() {
+			return;
+		}
+		let remove: Vec<HeaderName> = headers
+			.keys()
+			.filter(|name| !self.keeps(name))
+			.cloned()
+			.collect();
+		for name in remove {
+			headers.remove(name);
+		}
+	}
+
+	fn keeps(&self, name: &HeaderName) -> bool {
+		(self.allow.is_empty() || self.allow.iter().any(|p| p.matches(name)))
+			&& !self.deny.iter().any(|p| p.matches(name))
+	}
+}
+
+/// HeaderPattern matches a header name exactly, or by prefix when it ends with '*'.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HeaderPattern {
+	Exact(HeaderName),
+	Prefix(String),
+}
+
+impl HeaderPattern {
+	pub fn matches(&self, name: &HeaderName) -> bool {
+		match self {
+			HeaderPattern::Exact(h) => h == name,
+			HeaderPattern::Prefix(p) => name.as_str().starts_with(p.as_str()),
+		}
+	}
+}
+
+impl FromStr for HeaderPattern {
+	type Err = anyhow::Error;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s.strip_suffix('*') {
+			Some(prefix) => {
+				// Validate the prefix is made of valid header name characters
+				if !prefix.is_empty() {
+					HeaderName::from_str(prefix)?;
+				}
+				Ok(HeaderPattern::Prefix(prefix.to_ascii_lowercase()))
+			},
+			None => Ok(HeaderPattern::Exact(HeaderName::from_str(s)?)),
+		}
+	}
+}
+
+impl Display for HeaderPattern {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			HeaderPattern::Exact(h) => write!(f, "{h}"),
+			HeaderPattern::Prefix(p) => write!(f, "{p}*"),
+		}
+	}
+}
+
+#[cfg(test)]
+#[path = "headerfilter_tests.rs"]
+mod tests;
@@ -0,0 +1,56 @@
+use serde_json::json;
+
+use super::*;
+
+fn rules(v: serde_json::Value) -> HeaderRules {
+	serde_json::from_value(v).unwrap()
+}
+
+fn headers(names: &[&'static str]) -> HeaderMap {
+	let mut h = HeaderMap::new();
+	for n in names {
+		h.insert(*n, crate::http::HeaderValue::from_static("v"));
+	}
+	h
+}
+
+fn names(h: &HeaderMap) -> Vec<&str> {
+	let mut n: Vec<_> = h.keys().map(|k| k.as_str()).collect();
+	n.sort();
+	n
+}
+
+#[test]
+fn deny() {
+	let r = rules(json!({"deny": ["x-internal-*", "Server"]}));
+	let mut h = headers(&[
+		"x-internal-user",
+		"x-internal-",
+		"server",
+		"x-internal",
+		"accept",
+	]);
+	r.apply(&mut h);
+	assert_eq!(names(&h), vec!["accept", "x-internal"]);
+}
+
+#[test]
+fn allow() {
+	let r = rules(json!({"allow": ["accept", "x-app-*"], "deny": ["x-app-secret"]}));
+	let mut h = headers(&["accept", "x-app-id", "x-app-secret", "cookie"]);
+	r.apply(&mut h);
+	assert_eq!(names(&h), vec!["accept", "x-app-id"]);
+}
+
+#[test]
+fn empty_keeps_everything() {
+	let mut h = headers(&["accept", "cookie"]);
+	HeaderRules::default().apply(&mut h);
+	assert_eq!(names(&h), vec!["accept", "cookie"]);
+}
+
+#[test]
+fn invalid_pattern() {
+	assert!(serde_json::from_value::<HeaderRules>(json!({"deny": ["bad header"]})).is_err());
+	assert!(serde_json::from_value::<HeaderRules>(json!({"deny": ["bad header*"]})).is_err());
+}
@@ -2,19 +2,24 @@
 use std::collections::HashMap;
 use std::str::FromStr;
 
+use arc_swap::ArcSwap;
+use async_trait::async_trait;
 use axum_core::RequestExt;
 use axum_extra::TypedHeader;
 use axum_extra::headers::Authorization;
 use axum_extra::headers::authorization::Bearer;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
 use jsonwebtoken::jwk::{self, AlgorithmParameters, JwkSet, KeyAlgorithm};
 use jsonwebtoken::{DecodingKey, TokenData, Validation, decode, decode_header};
 use secrecy::SecretString;
 use serde::de::Error;
 use serde::ser::SerializeMap;
 use serde_json::{Map, Value};
+use tokio::time::Instant;
 
 use crate::client::Client;
-use crate::http::Request;
+use crate::http::{HeaderName, HeaderValue, Request};
 use crate::telemetry::log::RequestLog;
 use crate::types::agent::{HostRedirect, PathRedirect};
 use crate::*;
@@ -35,6 +40,12 @@ pub enum TokenError {
 
 	#[error("token uses the unknown key {0:?}")]
 	UnknownKeyId(String),
+
+	#[error("the token does not specify an issuer")]
+	MissingIssuer,
+
+	#[error("token issuer {0:?} is not configured")]
+	UnknownIssuer(String),
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -57,9 +68,22 @@ pub enum JwkError {
 	},
 }
 
+// Cached keys are refetched after this long, so removed keys eventually stop being accepted.
+const JWKS_TTL: Duration = Duration::from_secs(300);
+// Keys are refetched at most this often, so tokens with bogus key IDs cannot hammer the JWKS endpoint.
+const JWKS_MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+const JWKS_FETCH_TIMEOUT: Duration = Duration::from_secs(5);
+
 #[derive(Clone)]
 pub struct Jwt {
-	keys: HashMap<String, Jwk>,
+	providers: Vec<Provider>,
+}
+
+/// Provider holds the validation parameters for a single issuer.
+#[derive(Clone)]
+struct Provider {
+	issuer: String,
+	keys: Arc<JwksCache>,
 }
 
 // TODO: can we give anything useful here?
@@ -68,7 +92,11 @@ impl serde::Serialize for Jwt {
 	where
 		S: serde::Serializer,
 	{
-		self.keys.keys().collect::<Vec<_>>().serialize(serializer)
+		let mut m = serializer.serialize_map(Some(self.providers.len()))?;
+		for p in &self.providers {
+			m.serialize_entry(&p.issuer, &p.keys.keys.load().keys().collect::<Vec<_>>())?;
+		}
+		m.end()
 	}
 }
 
@@ -78,23 +106,160 @@ impl Debug for Jwt {
 	}
 }
 
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "schema", derive(JsonSchema), schemars(untagged))]
+pub enum LocalJwtConfig {
+	/// Accept tokens from any of the providers. The provider is selected by the token's `iss` claim.
+	Multi {
+		providers: Vec<LocalJwtProvider>,
+	},
+	Single(LocalJwtProvider),
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LocalJwtProviders {
+	providers: Vec<LocalJwtProvider>,
+}
+
+impl<'de> serde::Deserialize<'de> for LocalJwtConfig {
+	fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+	where
+		D: serde::Deserializer<'de>,
+	{
+		// An untagged enum only reports that no variant matched, so pick the variant by the
+		// `providers` key and surface that variant's error.
+		let v = Value::deserialize(deserializer)?;
+		let res = if v.get("providers").is_some() {
+			serde_json::from_value::<LocalJwtProviders>(v).map(|p| LocalJwtConfig::Multi {
+				providers: p.providers,
+			})
+		} else {
+			serde_json::from_value(v).map(LocalJwtConfig::Single)
+		};
+		res.map_err(D::Error::custom)
+	}
+}
+
 #[derive(Debug, Clone, serde::Deserialize)]
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
 #[cfg_attr(feature = "schema", derive(JsonSchema))]
-pub struct LocalJwtConfig {
+pub struct LocalJwtProvider {
 	pub issuer: String,
 	pub audiences: Vec<String>,
 	pub jwks: serdes::FileInlineOrRemote,
+	/// Signing algorithms accepted from this issuer. If unset, any algorithm supported by the key is
+	/// accepted.
+	#[serde(default)]
+	#[cfg_attr(feature = "schema", schemars(with = "Vec<String>"))]
+	pub algorithms: Vec<jsonwebtoken::Algorithm>,
+	/// Claims to forward to the backend as request headers, once the token is verified.
+	#[serde(default)]
+	pub claim_headers: Vec<ClaimHeader>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+pub struct ClaimHeader {
+	/// The claim to forward. Nested claims are separated by '.', such as `realm_access.roles`.
+	pub claim: String,
+	/// The header to set. Any copy of the header sent by the client is removed.
+	#[serde(deserialize_with = "de_parse")]
+	#[cfg_attr(feature = "schema", schemars(with = "String"))]
+	pub header: HeaderName,
+	/// If set, array claims are joined with this separator. Otherwise each element is sent as a separate header value.
+	#[serde(default)]
+	pub join: Option<String>,
+}
+
+impl ClaimHeader {
+	fn values(&self, claims: &Map<String, Value>) -> Vec<HeaderValue> {
+		let Some(v) = lookup_claim(claims, &self.claim) else {
+			return vec![];
+		};
+		let scalars: Vec<String> = match v {
+			Value::Array(items) => items.iter().filter_map(scalar).collect(),
+			v => scalar(v).into_iter().collect(),
+		};
+		let values = match &self.join {
+			Some(sep) if !scalars.is_empty() => vec![scalars.join(sep)],
+			_ => scalars,
+		};
+		values
+			.into_iter()
+			.filter_map(|v| match HeaderValue::try_from(v) {
+				Ok(hv) => Some(hv),
+				Err(_) => {
+					debug!(claim=%self.claim, "claim is not a valid header value");
+					None
+				},
+			})
+			.collect()
+	}
+}
+
+fn lookup_claim<'a>(claims: &'a Map<String, Value>, path: &str) -> Option<&'a Value> {
+	let mut parts = path.split('.');
+	let mut v = claims.get(parts.next()?)?;
+	for p in parts {
+		v = v.get(p)?;
+	}
+	Some(v)
+}
+
+fn scalar(v: &Value) -> Option<String> {
+	match v {
+		Value::String(s) => Some(s.clone()),
+		Value::Number(n) => Some(n.to_string()),
+		Value::Bool(b) => Some(b.to_string()),
+		_ => None,
+	}
 }
 
 impl LocalJwtConfig {
 	pub async fn try_into(self, client: Client) -> Result<Jwt, JwkError> {
-		let jwks: JwkSet = self
-			.jwks
-			.load::<JwkSet>(client)
-			.await
-			.map_err(JwkError::JwkLoadError)?;
+		let providers = match self {
+			LocalJwtConfig::Multi { providers } => providers,
+			LocalJwtConfig::Single(p) => vec![p],
+		};
+		let mut res = Vec::with_capacity(providers.len());
+		for p in providers {
+			let jwks: JwkSet = p
+				.jwks
+				.load::<JwkSet>(client.clone())
+				.await
+				.map_err(JwkError::JwkLoadError)?;
+			// Only remote keys are refreshed; file and inline keys are fixed for the life of the config.
+			let source: Option<Arc<dyn JwksSource>> = match &p.jwks {
+				serdes::FileInlineOrRemote::Remote { .. } => Some(Arc::new(RemoteJwks {
+					jwks: p.jwks.clone(),
+					client: client.clone(),
+				})),
+				_ => None,
+			};
+			res.push(p.build(jwks, source)?);
+		}
+		Ok(Jwt { providers: res })
+	}
+}
+
+impl LocalJwtProvider {
+	fn build(self, jwks: JwkSet, source: Option<Arc<dyn JwksSource>>) -> Result<Provider, JwkError> {
+		let keys = self.keys(jwks)?;
+		Ok(Provider {
+			issuer: self.issuer.clone(),
+			keys: Arc::new(JwksCache {
+				keys: ArcSwap::from_pointee(keys),
+				fetched: ArcSwap::from_pointee(Instant::now()),
+				last_refresh: Default::default(),
+				source,
+				settings: self,
+			}),
+		})
+	}
 
+	fn keys(&self, jwks: JwkSet) -> Result<HashMap<String, Jwk>, JwkError> {
 		let mut keys = HashMap::new();
 		let to_supported_alg = |key_algorithm: Option<KeyAlgorithm>| match key_algorithm {
 			Some(key_alg) => jsonwebtoken::Algorithm::from_str(key_alg.to_string().as_str()).ok(),
@@ -124,8 +289,14 @@ impl LocalJwtConfig {
 					},
 				};
 
+				if !self.algorithms.is_empty() && !self.algorithms.contains(&key_alg) {
+					debug!(%kid, ?key_alg, "skipping key with an algorithm that is not allowed");
+					continue;
+				}
+
 				let mut validation = Validation::new(key_alg);
 				validation.set_audience(self.audiences.as_slice());
+				validation.set_issuer(&[self.issuer.as_str()]);
 
 				keys.insert(
 					kid,
@@ -142,7 +313,74 @@ impl LocalJwtConfig {
 			}
 		}
 
-		Ok(Jwt { keys })
+		Ok(keys)
+	}
+}
+
+/// JwksSource fetches the current key set for a provider.
+#[async_trait]
+pub trait JwksSource: Send + Sync {
+	async fn fetch(&self) -> anyhow::Result<JwkSet>;
+}
+
+struct RemoteJwks {
+	jwks: serdes::FileInlineOrRemote,
+	client: Client,
+}
+
+#[async_trait]
+impl JwksSource for RemoteJwks {
+	async fn fetch(&self) -> anyhow::Result<JwkSet> {
+		self.jwks.load::<JwkSet>(self.client.clone()).await
+	}
+}
+
+/// JwksCache holds the keys of a provider, shared across requests. Keys are refetched once they
+/// expire, or when a token refers to a key that is not in the cache (such as after a key rotation).
+/// Failed fetches keep the previously cached keys.
+struct JwksCache {
+	settings: LocalJwtProvider,
+	source: Option<Arc<dyn JwksSource>>,
+	keys: ArcSwap<HashMap<String, Jwk>>,
+	fetched: ArcSwap<Instant>,
+	// Time of the last fetch attempt. Held for the duration of a fetch, so concurrent requests
+	// wait for a single fetch rather than each starting their own.
+	last_refresh: tokio::sync::Mutex<Option<Instant>>,
+}
+
+impl JwksCache {
+	fn contains(&self, kid: &str) -> bool {
+		self.keys.load().contains_key(kid)
+	}
+
+	fn is_stale(&self) -> bool {
+		self.source.is_some() && self.fetched.load().elapsed() >= JWKS_TTL
+	}
+
+	async fn refresh(&self) {
+		let Some(source) = &self.source else {
+			return;
+		};
+		let mut last_refresh = self.last_refresh.lock().await;
+		if last_refresh.is_some_and(|t| t.elapsed() < JWKS_MIN_REFRESH_INTERVAL) {
+			return;
+		}
+		*last_refresh = Some(Instant::now());
+		let res = match tokio::time::timeout(JWKS_FETCH_TIMEOUT, source.fetch()).await {
+			Ok(res) => res.map_err(JwkError::JwkLoadError),
+			Err(_) => Err(JwkError::JwkLoadError(anyhow::anyhow!("timed out"))),
+		}
+		.and_then(|jwks| self.settings.keys(jwks));
+		match res {
+			Ok(keys) => {
+				debug!(issuer=%self.settings.issuer, keys=keys.len(), "refreshed JWKS");
+				self.keys.store(Arc::new(keys));
+				self.fetched.store(Arc::new(Instant::now()));
+			},
+			Err(e) => {
+				warn!(issuer=%self.settings.issuer, "failed to refresh JWKS, keeping cached keys: {e}");
+			},
+		}
 	}
 }
 
@@ -160,6 +398,36 @@ pub struct Claims {
 	pub jwt: SecretString,
 }
 
+impl Claims {
+	/// Returns the value of a scalar claim. Nested claims are separated by '.', such as
+	/// `realm_access.role`.
+	pub fn scalar(&self, path: &str) -> Option<String> {
+		lookup_claim(&self.inner, path).and_then(scalar)
+	}
+
+	/// Returns the roles of the subject, from the `role` claim or the `roles` array claim.
+	pub fn roles(&self) -> impl Iterator<Item = &str> {
+		let role = self.inner.get("role").and_then(Value::as_str);
+		let roles = self
+			.inner
+			.get("roles")
+			.and_then(Value::as_array)
+			.into_iter()
+			.flatten()
+			.filter_map(Value::as_str);
+		role.into_iter().chain(roles)
+	}
+
+	/// Returns true if the claim at `path` has the value `want`, or is an array containing it.
+	pub fn has_value(&self, path: &str, want: &str) -> bool {
+		match lookup_claim(&self.inner, path) {
+			Some(Value::Array(values)) => values.iter().filter_map(scalar).any(|v| v == want),
+			Some(v) => scalar(v).is_some_and(|v| v == want),
+			None => false,
+		}
+	}
+}
+
 impl Serialize for Claims {
 	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
 	where
@@ -171,40 +439,95 @@ impl Serialize for Claims {
 
 impl Jwt {
 	pub async fn apply(&self, log: &mut RequestLog, req: &mut Request) -> Result<(), TokenError> {
-		let Ok(TypedHeader(Authorization(bearer))) = req
-			.extract_parts::<TypedHeader<Authorization<Bearer>>>()
-			.await
-		else {
-			// No token, so don't attempt to authenticate.
-			// TODO: we need authorization policies to allow requiring it
+		let Some(claims) = self.authenticate(req).await? else {
 			return Ok(());
 		};
-		let claims = self.validate_claims(bearer.token())?;
 		if let Some(serde_json::Value::String(sub)) = claims.inner.get("sub") {
 			log.jwt_sub = Some(sub.to_string());
 		};
 		log.cel.ctx().with_jwt(&claims);
-		// Remove the token. TODO: allow keep it
-		req.headers_mut().remove(http::header::AUTHORIZATION);
 		// Insert the claims into extensions so we can reference it later
 		req.extensions_mut().insert(claims);
 		Ok(())
 	}
 
-	pub fn validate_claims(&self, token: &str) -> Result<Claims, TokenError>
-where {
+	async fn authenticate(&self, req: &mut Request) -> Result<Option<Claims>, TokenError> {
+		// Headers we derive from claims must never be taken from the client.
+		for p in &self.providers {
+			for ch in &p.keys.settings.claim_headers {
+				req.headers_mut().remove(&ch.header);
+			}
+		}
+		let Ok(TypedHeader(Authorization(bearer))) = req
+			.extract_parts::<TypedHeader<Authorization<Bearer>>>()
+			.await
+		else {
+			// No token, so don't attempt to authenticate.
+			// TODO: we need authorization policies to allow requiring it
+			return Ok(None);
+		};
+		let (provider, claims) = self.validate_with_provider(bearer.token()).await?;
+		for ch in &provider.keys.settings.claim_headers {
+			for v in ch.values(&claims.inner) {
+				req.headers_mut().append(ch.header.clone(), v);
+			}
+		}
+		// Remove the token. TODO: allow keep it
+		req.headers_mut().remove(http::header::AUTHORIZATION);
+		Ok(Some(claims))
+	}
+
+	/// Validate the token, refreshing the issuer's keys if they are stale or the token uses an
+	/// unknown key.
+	pub async fn validate(&self, token: &str) -> Result<Claims, TokenError> {
+		self.validate_with_provider(token).await.map(|(_, c)| c)
+	}
+
+	async fn validate_with_provider(&self, token: &str) -> Result<(&Provider, Claims), TokenError> {
+		let (provider, kid) = self.select(token)?;
+		if provider.keys.is_stale() || !provider.keys.contains(&kid) {
+			provider.keys.refresh().await;
+		}
+		Ok((provider, provider.validate(token, &kid)?))
+	}
+
+	/// Validate the token against the currently cached keys.
+	pub fn validate_claims(&self, token: &str) -> Result<Claims, TokenError> {
+		let (provider, kid) = self.select(token)?;
+		provider.validate(token, &kid)
+	}
+
+	fn select(&self, token: &str) -> Result<(&Provider, String), TokenError> {
 		let header = decode_header(token).map_err(|error| {
 			debug!(?error, "Received token with invalid header.");
 
 			TokenError::InvalidHeader(error)
 		})?;
-		let kid = header.kid.as_ref().ok_or_else(|| {
-			debug!(?header, "Header is missing the `kid` attribute.");
+		let kid = header.kid.ok_or_else(|| {
+			debug!("Header is missing the `kid` attribute.");
 
 			TokenError::MissingKeyId
 		})?;
 
-		let key = self.keys.get(kid).ok_or_else(|| {
+		// The signature is verified later, against the keys of the issuer the token claims.
+		let issuer = unverified_issuer(token)?;
+		let provider = self
+			.providers
+			.iter()
+			.find(|p| p.issuer == issuer)
+			.ok_or_else(|| {
+				debug!(%issuer, "Token is from an unknown issuer.");
+
+				TokenError::UnknownIssuer(issuer.clone())
+			})?;
+		Ok((provider, kid))
+	}
+}
+
+impl Provider {
+	fn validate(&self, token: &str, kid: &str) -> Result<Claims, TokenError> {
+		let keys = self.keys.keys.load();
+		let key = keys.get(kid).ok_or_else(|| {
 			debug!(%kid, "Token refers to an unknown key.");
 
 			TokenError::UnknownKeyId(kid.to_owned())
@@ -224,3 +547,20 @@ where {
 		Ok(claims)
 	}
 }
+
+/// Read the `iss` claim without verifying the token.
+fn unverified_issuer(token: &str) -> Result<String, TokenError> {
+	#[derive(serde::Deserialize)]
+	struct Issuer {
+		iss: Option<String>,
+	}
+	let invalid = || TokenError::Invalid(jsonwebtoken::errors::ErrorKind::InvalidToken.into());
+	let payload = token.split('.').nth(1).ok_or_else(invalid)?;
+	let payload = URL_SAFE_NO_PAD.decode(payload).map_err(|_| invalid())?;
+	let claims: Issuer = serde_json::from_slice(&payload).map_err(|_| invalid())?;
+	claims.iss.ok_or(TokenError::MissingIssuer)
+}
+
+#[cfg(test)]
+#[path = "jwt_tests.rs"]
+mod tests;
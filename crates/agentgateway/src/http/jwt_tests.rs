@@ -0,0 +1,361 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use jsonwebtoken::{EncodingKey, Header, encode};
+use serde_json::json;
+
+use super::*;
+
+struct TestIssuer {
+	issuer: String,
+	kid: String,
+	encoding: EncodingKey,
+	jwks: JwkSet,
+}
+
+impl TestIssuer {
+	fn new(issuer: &str, kid: &str) -> Self {
+		let kp = rcgen::KeyPair::generate_for(&rcgen::PKCS_ECDSA_P256_SHA256).unwrap();
+		// Uncompressed point: 0x04 || x || y
+		let raw = kp.public_key_raw();
+		let jwks = serde_json::from_value(json!({
+			"keys": [{
+				"kty": "EC",
+				"kid": kid,
+				"crv": "P-256",
+				"alg": "ES256",
+				"x": URL_SAFE_NO_PAD.encode(&raw[1..33]),
+				"y": URL_SAFE_NO_PAD.encode(&raw[33..65]),
+			}]
+		}))
+		.unwrap();
+		TestIssuer {
+			issuer: issuer.to_string(),
+			kid: kid.to_string(),
+			encoding: EncodingKey::from_ec_pem(kp.serialize_pem().as_bytes()).unwrap(),
+			jwks,
+		}
+	}
+
+	fn provider(&self) -> Provider {
+		LocalJwtProvider {
+			issuer: self.issuer.clone(),
+			audiences: vec!["test.agentgateway.dev".to_string()],
+			jwks: serdes::FileInlineOrRemote::Inline(String::new()),
+			algorithms: vec![],
+			claim_headers: vec![],
+		}
+		.build(self.jwks.clone(), None)
+		.unwrap()
+	}
+
+	fn token(&self, iss: &str, sub: &str) -> String {
+		self.sign(json!({
+			"iss": iss,
+			"sub": sub,
+			"aud": "test.agentgateway.dev",
+			"exp": 1900650294,
+		}))
+	}
+
+	fn sign(&self, claims: serde_json::Value) -> String {
+		let mut header = Header::new(jsonwebtoken::Algorithm::ES256);
+		header.kid = Some(self.kid.clone());
+		encode(&header, &claims, &self.encoding).unwrap()
+	}
+}
+
+fn sub(claims: &Claims) -> &str {
+	claims.inner.get("sub").and_then(|s| s.as_str()).unwrap()
+}
+
+#[test]
+fn multiple_issuers() {
+	let a = TestIssuer::new("https://a.example.com", "a-key");
+	let b = TestIssuer::new("https://b.example.com", "b-key");
+	let jwt = Jwt {
+		providers: vec![a.provider(), b.provider()],
+	};
+
+	let claims = jwt
+		.validate_claims(&a.token("https://a.example.com", "alice"))
+		.unwrap();
+	assert_eq!(sub(&claims), "alice");
+	let claims = jwt
+		.validate_claims(&b.token("https://b.example.com", "bob"))
+		.unwrap();
+	assert_eq!(sub(&claims), "bob");
+
+	// Issuer that is not configured
+	let c = TestIssuer::new("https://c.example.com", "c-key");
+	assert_eq!(
+		jwt.validate_claims(&c.token("https://c.example.com", "carol")),
+		Err(TokenError::UnknownIssuer(
+			"https://c.example.com".to_string()
+		))
+	);
+}
+
+#[test]
+fn issuer_must_match_signing_key() {
+	let a = TestIssuer::new("https://a.example.com", "a-key");
+	let b = TestIssuer::new("https://b.example.com", "b-key");
+	let jwt = Jwt {
+		providers: vec![a.provider(), b.provider()],
+	};
+	// Signed by a, but claiming to be from b: b does not have a's key
+	assert_eq!(
+		jwt.validate_claims(&a.token("https://b.example.com", "mallory")),
+		Err(TokenError::UnknownKeyId("a-key".to_string()))
+	);
+}
+
+#[test]
+fn missing_issuer() {
+	let a = TestIssuer::new("https://a.example.com", "a-key");
+	let jwt = Jwt {
+		providers: vec![a.provider()],
+	};
+	let mut header = Header::new(jsonwebtoken::Algorithm::ES256);
+	header.kid = Some(a.kid.clone());
+	let claims = json!({"sub": "alice", "aud": "test.agentgateway.dev", "exp": 1900650294});
+	let token = encode(&header, &claims, &a.encoding).unwrap();
+	assert_eq!(jwt.validate_claims(&token), Err(TokenError::MissingIssuer));
+}
+
+#[test]
+fn algorithms_restrict_keys() {
+	let a = TestIssuer::new("https://a.example.com", "a-key");
+	let provider = LocalJwtProvider {
+		issuer: a.issuer.clone(),
+		audiences: vec!["test.agentgateway.dev".to_string()],
+		jwks: serdes::FileInlineOrRemote::Inline(String::new()),
+		algorithms: vec![jsonwebtoken::Algorithm::RS256],
+		claim_headers: vec![],
+	}
+	.build(a.jwks.clone(), None)
+	.unwrap();
+	let jwt = Jwt {
+		providers: vec![provider],
+	};
+	assert_eq!(
+		jwt.validate_claims(&a.token("https://a.example.com", "alice")),
+		Err(TokenError::UnknownKeyId("a-key".to_string()))
+	);
+}
+
+#[test]
+fn config_formats() {
+	let single: LocalJwtConfig = serde_json::from_value(json!({
+		"issuer": "https://a.example.com",
+		"audiences": ["aud"],
+		"jwks": {"file": "/jwks.json"},
+	}))
+	.unwrap();
+	assert!(matches!(single, LocalJwtConfig::Single(_)));
+	let multi: LocalJwtConfig = serde_json::from_value(json!({
+		"providers": [
+			{"issuer": "https://a.example.com", "audiences": ["aud"], "jwks": {"file": "/a.json"}},
+			{"issuer": "https://b.example.com", "audiences": ["aud"], "jwks": {"file": "/b.json"}, "algorithms": ["ES256"]},
+		]
+	}))
+	.unwrap();
+	let LocalJwtConfig::Multi { providers } = multi else {
+		panic!("expected multiple providers");
+	};
+	assert_eq!(providers.len(), 2);
+	assert_eq!(
+		providers[1].algorithms,
+		vec![jsonwebtoken::Algorithm::ES256]
+	);
+
+	// Errors come from the selected format, rather than a generic untagged enum error
+	let err = serde_json::from_value::<LocalJwtConfig>(json!({
+		"issuer": "https://a.example.com",
+		"audience": ["aud"],
+		"jwks": {"file": "/jwks.json"},
+	}))
+	.unwrap_err()
+	.to_string();
+	assert!(err.contains("unknown field `audience`"), "{err}");
+	let err = serde_json::from_value::<LocalJwtConfig>(json!({
+		"providers": [{"issuer": "https://a.example.com", "jwks": {"file": "/a.json"}}],
+	}))
+	.unwrap_err()
+	.to_string();
+	assert!(err.contains("missing field `audiences`"), "{err}");
+}
+
+#[derive(Default)]
+struct TestSource {
+	jwks: std::sync::Mutex<Option<JwkSet>>,
+	fetches: AtomicUsize,
+}
+
+impl TestSource {
+	fn serve(&self, jwks: Option<JwkSet>) {
+		*self.jwks.lock().unwrap() = jwks;
+	}
+
+	fn fetches(&self) -> usize {
+		self.fetches.load(Ordering::SeqCst)
+	}
+}
+
+#[async_trait]
+impl JwksSource for TestSource {
+	async fn fetch(&self) -> anyhow::Result<JwkSet> {
+		self.fetches.fetch_add(1, Ordering::SeqCst);
+		self
+			.jwks
+			.lock()
+			.unwrap()
+			.clone()
+			.ok_or_else(|| anyhow::anyhow!("jwks unavailable"))
+	}
+}
+
+fn cached(issuer: &TestIssuer, source: Arc<TestSource>) -> Jwt {
+	let provider = LocalJwtProvider {
+		issuer: issuer.issuer.clone(),
+		audiences: vec!["test.agentgateway.dev".to_string()],
+		jwks: serdes::FileInlineOrRemote::Inline(String::new()),
+		algorithms: vec![],
+		claim_headers: vec![],
+	}
+	.build(issuer.jwks.clone(), Some(source))
+	.unwrap();
+	Jwt {
+		providers: vec![provider],
+	}
+}
+
+#[tokio::test(start_paused = true)]
+async fn key_rotation_refreshes_once() {
+	let old = TestIssuer::new("https://a.example.com", "key-1");
+	let source = Arc::new(TestSource::default());
+	let jwt = cached(&old, source.clone());
+
+	assert!(jwt.validate(&old.token(&old.issuer, "alice")).await.is_ok());
+	assert_eq!(source.fetches(), 0);
+
+	// The issuer rotates to a new key; tokens signed by it trigger a single refresh
+	let new = TestIssuer::new("https://a.example.com", "key-2");
+	source.serve(Some(new.jwks.clone()));
+	let token = new.token(&new.issuer, "bob");
+	let results = futures_util::future::join_all((0..5).map(|_| jwt.validate(&token))).await;
+	assert!(results.iter().all(|r| r.is_ok()));
+	assert_eq!(source.fetches(), 1);
+
+	// Unknown keys do not refetch until the minimum refresh interval has passed
+	let bogus = TestIssuer::new("https://a.example.com", "bogus");
+	let token = bogus.token(&bogus.issuer, "mallory");
+	assert_eq!(
+		jwt.validate(&token).await,
+		Err(TokenError::UnknownKeyId("bogus".to_string()))
+	);
+	assert_eq!(source.fetches(), 1);
+	tokio::time::advance(JWKS_MIN_REFRESH_INTERVAL).await;
+	assert!(jwt.validate(&token).await.is_err());
+	assert_eq!(source.fetches(), 2);
+}
+
+#[tokio::test(start_paused = true)]
+async fn fetch_failure_keeps_cached_keys() {
+	let a = TestIssuer::new("https://a.example.com", "a-key");
+	let source = Arc::new(TestSource::default());
+	let jwt = cached(&a, source.clone());
+
+	// Once the keys expire a refresh is attempted, but its failure does not reject known keys
+	tokio::time::advance(JWKS_TTL).await;
+	let claims = jwt.validate(&a.token(&a.issuer, "alice")).await.unwrap();
+	assert_eq!(sub(&claims), "alice");
+	assert_eq!(source.fetches(), 1);
+
+	// Once the endpoint recovers the keys are replaced
+	let b = TestIssuer::new("https://a.example.com", "b-key");
+	source.serve(Some(b.jwks.clone()));
+	tokio::time::advance(JWKS_MIN_REFRESH_INTERVAL).await;
+	assert!(jwt.validate(&b.token(&b.issuer, "bob")).await.is_ok());
+	assert_eq!(source.fetches(), 2);
+	assert_eq!(
+		jwt.validate_claims(&a.token(&a.issuer, "alice")),
+		Err(TokenError::UnknownKeyId("a-key".to_string()))
+	);
+}
+
+fn claim_header(claim: &str, header: &str, join: Option<&str>) -> ClaimHeader {
+	ClaimHeader {
+		claim: claim.to_string(),
+		header: HeaderName::try_from(header).unwrap(),
+		join: join.map(str::to_string),
+	}
+}
+
+fn request(token: Option<&str>, spoofed: &[(&str, &str)]) -> Request {
+	let mut rb = ::http::Request::builder().uri("http://example.com/");
+	if let Some(token) = token {
+		rb = rb.header(http::header::AUTHORIZATION, format!("Bearer {token}"));
+	}
+	for (k, v) in spoofed {
+		rb = rb.header(*k, *v);
+	}
+	rb.body(crate::http::Body::empty()).unwrap()
+}
+
+fn header_values<'a>(req: &'a Request, name: &str) -> Vec<&'a str> {
+	req
+		.headers()
+		.get_all(name)
+		.iter()
+		.map(|v| v.to_str().unwrap())
+		.collect()
+}
+
+#[tokio::test]
+async fn claims_to_headers() {
+	let a = TestIssuer::new("https://a.example.com", "a-key");
+	let provider = LocalJwtProvider {
+		issuer: a.issuer.clone(),
+		audiences: vec!["test.agentgateway.dev".to_string()],
+		jwks: serdes::FileInlineOrRemote::Inline(String::new()),
+		algorithms: vec![],
+		claim_headers: vec![
+			claim_header("sub", "x-user", None),
+			claim_header("email", "x-email", None),
+			claim_header("groups", "x-groups", Some(",")),
+			claim_header("realm_access.roles", "x-role", None),
+			claim_header("missing", "x-missing", None),
+		],
+	}
+	.build(a.jwks.clone(), None)
+	.unwrap();
+	let jwt = Jwt {
+		providers: vec![provider],
+	};
+	let token = a.sign(json!({
+		"iss": a.issuer,
+		"sub": "alice",
+		"email": "alice@example.com",
+		"groups": ["admin", "dev"],
+		"realm_access": {"roles": ["reader", "writer"]},
+		"aud": "test.agentgateway.dev",
+		"exp": 1900650294,
+	}));
+
+	let mut req = request(
+		Some(&token),
+		&[("x-user", "mallory"), ("x-missing", "spoofed")],
+	);
+	jwt.authenticate(&mut req).await.unwrap().unwrap();
+	assert_eq!(header_values(&req, "x-user"), vec!["alice"]);
+	assert_eq!(header_values(&req, "x-email"), vec!["alice@example.com"]);
+	assert_eq!(header_values(&req, "x-groups"), vec!["admin,dev"]);
+	assert_eq!(header_values(&req, "x-role"), vec!["reader", "writer"]);
+	assert!(header_values(&req, "x-missing").is_empty());
+	assert!(req.headers().get(http::header::AUTHORIZATION).is_none());
+
+	// Spoofed headers are removed even when there is no token
+	let mut req = request(None, &[("x-user", "mallory")]);
+	assert!(jwt.authenticate(&mut req).await.unwrap().is_none());
+	assert!(header_values(&req, "x-user").is_empty());
+}
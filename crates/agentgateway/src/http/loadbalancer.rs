@@ -0,0 +1,226 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::OnceLock;
+
+use itertools::Itertools;
+
+use crate::http::Request;
+use crate::types::agent::RouteBackendReference;
+use crate::*;
+
+// Number of points on the ring for the heaviest backend. Others get points in proportion to their
+// weight.
+const RING_POINTS: usize = 1024;
+// Size of the Maglev lookup table. This must be prime.
+const MAGLEV_TABLE_SIZE: usize = 65537;
+
+/// Select backends by hashing part of the request, so requests with the same key consistently reach
+/// the same backend. Requests without the key fall back to weighted random selection.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+pub struct Policy {
+	#[serde(default)]
+	pub algorithm: Algorithm,
+	pub hash_on: HashOn,
+	// Built on first use, since it depends on the backends of the route the policy is attached to.
+	#[serde(skip)]
+	table: Arc<OnceLock<Table>>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+pub enum Algorithm {
+	/// Place backends on a hash ring, with each key going to the next backend on the ring.
+	#[default]
+	RingHash,
+	/// Use a Maglev lookup table, which spreads keys more evenly and is faster to look up than a ring.
+	Maglev,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+pub enum HashOn {
+	/// Hash on the value of the header.
+	Header(
+		#[serde(serialize_with = "ser_display", deserialize_with = "de_parse")]
+		#[cfg_attr(feature = "schema", schemars(with = "String"))]
+		http::HeaderName,
+	),
+	/// Hash on the value of the cookie.
+	Cookie(Strng),
+	/// Hash on the IP address of the client.
+	SourceIp,
+}
+
+enum Table {
+	// Sorted by hash
+	Ring(Vec<(u64, usize)>),
+	Maglev(Vec<usize>),
+}
+
+// The tables are large, so keep them out of debug output.
+impl Debug for Table {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Table::Ring(ring) => write!(f, "Ring({} points)", ring.len()),
+			Table::Maglev(table) => write!(f, "Maglev({} entries)", table.len()),
+		}
+	}
+}
+
+impl Policy {
+	pub fn new(algorithm: Algorithm, hash_on: HashOn) -> Self {
+		Policy {
+			algorithm,
+			hash_on,
+			table: Default::default(),
+		}
+	}
+
+	/// Returns the backend for the request's key, or None if the request has no key.
+	pub fn select<'a>(
+		&self,
+		backends: &'a [RouteBackendReference],
+		req: &Request,
+	) -> Option<&'a RouteBackendReference> {
+		let key = self.hash_on.key(req)?;
+		let table = self
+			.table
+			.get_or_init(|| Table::build(self.algorithm, backends));
+		table.lookup(key).map(|i| &backends[i])
+	}
+}
+
+impl HashOn {
+	/// Returns the hash of the request's key, or None if the request has no key.
+	pub fn key(&self, req: &Request) -> Option<u64> {
+		match self {
+			HashOn::Header(name) => req.headers().get(name).map(|v| hash(v.as_bytes())),
+			HashOn::Cookie(name) => cookie(req, name).map(|v| hash(v.as_bytes())),
+			HashOn::SourceIp => http::ClientIp::get(req).map(hash),
+		}
+	}
+}
+
+/// Returns the value of the named cookie sent with the request.
+pub fn cookie<'a>(req: &'a Request, name: &str) -> Option<&'a str> {
+	req
+		.headers()
+		.get_all(http::header::COOKIE)
+		.iter()
+		.filter_map(|v| v.to_str().ok())
+		.flat_map(|v| v.split(';'))
+		.filter_map(|c| c.trim().split_once('='))
+		.find(|(k, _)| *k == name)
+		.map(|(_, v)| v)
+}
+
+impl Table {
+	fn build(algorithm: Algorithm, backends: &[RouteBackendReference]) -> Self {
+		// Backends are identified by name rather than position, so that adding or removing a backend
+		// only moves the keys that belonged to it.
+		let mut seen: HashMap<Strng, usize> = HashMap::new();
+		let members = backends
+			.iter()
+			.enumerate()
+			.filter_map(|(i, b)| {
+				let name = b.backend.name();
+				let n = seen.entry(name.clone()).or_default();
+				*n += 1;
+				(b.weight > 0).then_some((i, (name, *n), b.weight))
+			})
+			.collect_vec();
+		match algorithm {
+			Algorithm::RingHash => Table::ring(&members),
+			Algorithm::Maglev => Table::maglev(&members),
+		}
+	}
+
+	fn ring(members: &[(usize, (Strng, usize), usize)]) -> Self {
+		let max_weight = members.iter().map(|(_, _, w)| *w).max().unwrap_or_default();
+		let mut ring = Vec::with_capacity(RING_POINTS * members.len());
+		for (idx, id, weight) in members {
+			let points = (RING_POINTS * weight).div_ceil(max_weight);
+			for point in 0..points {
+				ring.push((hash((id, point)), *idx));
+			}
+		}
+		ring.sort_unstable();
+		Table::Ring(ring)
+	}
+
+	fn maglev(members: &[(usize, (Strng, usize), usize)]) -> Self {
+		let m = MAGLEV_TABLE_SIZE as u64;
+		let Some(max_weight) = members.iter().map(|(_, _, w)| *w).max() else {
+			return Table::Maglev(vec![]);
+		};
+		let permutations = members
+			.iter()
+			.map(|(_, id, _)| {
+				let offset = hash((id, "offset")) % m;
+				let skip = hash((id, "skip")) % (m - 1) + 1;
+				(offset, skip)
+			})
+			.collect_vec();
+		let mut next = vec![0u64; members.len()];
+		let mut credit = vec![0usize; members.len()];
+		let mut table = vec![usize::MAX; MAGLEV_TABLE_SIZE];
+		let mut filled = 0;
+		while filled < MAGLEV_TABLE_SIZE {
+			for (i, (idx, _, weight)) in members.iter().enumerate() {
+				// Backends take turns in proportion to their weight
+				credit[i] += weight;
+				if credit[i] < max_weight {
+					continue;
+				}
+				credit[i] -= max_weight;
+				let (offset, skip) = permutations[i];
+				loop {
+					let slot = ((offset + next[i] * skip) % m) as usize;
+					next[i] += 1;
+					if table[slot] == usize::MAX {
+						table[slot] = *idx;
+						filled += 1;
+						break;
+					}
+				}
+				if filled == MAGLEV_TABLE_SIZE {
+					break;
+				}
+			}
+		}
+		Table::Maglev(table)
+	}
+
+	fn lookup(&self, key: u64) -> Option<usize> {
+		match self {
+			Table::Ring(ring) => {
+				if ring.is_empty() {
+					return None;
+				}
+				let i = ring.partition_point(|(h, _)| *h < key);
+				Some(ring[i % ring.len()].1)
+			},
+			Table::Maglev(table) => {
+				if table.is_empty() {
+					return None;
+				}
+				Some(table[(key % table.len() as u64) as usize])
+			},
+		}
+	}
+}
+
+fn hash(v: impl Hash) -> u64 {
+	let mut h = DefaultHasher::new();
+	v.hash(&mut h);
+	h.finish()
+}
+
+#[cfg(test)]
+#[path = "loadbalancer_tests.rs"]
+mod tests;
@@ -0,0 +1,119 @@
+use super::*;
+use crate::http::tests_common::*;
+use crate::types::agent::BackendReference;
+
+fn backends(names: &[&str]) -> Vec<RouteBackendReference> {
+	names
+		.iter()
+		.map(|n| RouteBackendReference {
+			weight: 1,
+			backend: BackendReference::Backend(strng::new(n)),
+			filters: vec![],
+		})
+		.collect()
+}
+
+fn select<'a>(
+	policy: &Policy,
+	backends: &'a [RouteBackendReference],
+	headers: &[(&str, &str)],
+) -> Option<&'a str> {
+	let req = request("http://example.com", http::Method::GET, headers);
+	policy.select(backends, &req).map(|b| match &b.backend {
+		BackendReference::Backend(n) => n.as_str(),
+		_ => unreachable!(),
+	})
+}
+
+fn header_policy(algorithm: Algorithm) -> Policy {
+	Policy::new(
+		algorithm,
+		HashOn::Header(http::HeaderName::from_static("x-user")),
+	)
+}
+
+// Maps 3000 users, returning the backend for each
+fn assign<'a>(policy: &Policy, backends: &'a [RouteBackendReference]) -> Vec<&'a str> {
+	(0..3000)
+		.map(|i| select(policy, backends, &[("x-user", &format!("user-{i}"))]).unwrap())
+		.collect()
+}
+
+#[test]
+fn same_key_same_backend() {
+	for algorithm in [Algorithm::RingHash, Algorithm::Maglev] {
+		let policy = header_policy(algorithm);
+		let be = backends(&["a", "b", "c"]);
+		let first = select(&policy, &be, &[("x-user", "alice")]);
+		assert!(first.is_some());
+		for _ in 0..10 {
+			assert_eq!(select(&policy, &be, &[("x-user", "alice")]), first);
+		}
+		// A new policy, as built after a config update, keeps the mapping
+		let rebuilt = header_policy(algorithm);
+		assert_eq!(select(&rebuilt, &be, &[("x-user", "alice")]), first);
+	}
+}
+
+#[test]
+fn missing_key() {
+	let policy = header_policy(Algorithm::RingHash);
+	let be = backends(&["a", "b"]);
+	assert_eq!(select(&policy, &be, &[]), None);
+}
+
+#[test]
+fn cookie_key() {
+	let policy = Policy::new(Algorithm::Maglev, HashOn::Cookie(strng::new("session")));
+	let be = backends(&["a", "b", "c"]);
+	let first = select(&policy, &be, &[("cookie", "theme=dark; session=abc")]);
+	assert!(first.is_some());
+	assert_eq!(
+		select(&policy, &be, &[("cookie", "session=abc; other=1")]),
+		first
+	);
+	assert_eq!(select(&policy, &be, &[("cookie", "theme=dark")]), None);
+}
+
+#[test]
+fn even_distribution() {
+	for algorithm in [Algorithm::RingHash, Algorithm::Maglev] {
+		let policy = header_policy(algorithm);
+		let be = backends(&["a", "b", "c"]);
+		let counts = assign(&policy, &be).into_iter().counts();
+		for name in ["a", "b", "c"] {
+			let n = counts[name];
+			assert!((800..1200).contains(&n), "{algorithm:?}: {name} got {n}");
+		}
+	}
+}
+
+#[test]
+fn weighted_distribution() {
+	for algorithm in [Algorithm::RingHash, Algorithm::Maglev] {
+		let policy = header_policy(algorithm);
+		let mut be = backends(&["a", "b"]);
+		be[0].weight = 2;
+		let counts = assign(&policy, &be).into_iter().counts();
+		assert!(
+			(1800..2200).contains(&counts["a"]),
+			"{algorithm:?}: {counts:?}"
+		);
+	}
+}
+
+#[test]
+fn removing_backend_only_moves_its_keys() {
+	for algorithm in [Algorithm::RingHash, Algorithm::Maglev] {
+		let all = backends(&["a", "b", "c", "d"]);
+		let before = assign(&header_policy(algorithm), &all);
+		let after = assign(&header_policy(algorithm), &all[..3]);
+		let moved = before
+			.iter()
+			.zip(after.iter())
+			.filter(|(b, a)| **b != "d" && b != a)
+			.count();
+		// Ring hash moves none of them; Maglev trades a few for a more even spread.
+		assert!(moved < 150, "{algorithm:?}: {moved} keys moved");
+	}
+}
@@ -1,4 +1,8 @@
-use serde::de::Error;
+use std::hash::{BuildHasher, RandomState};
+use std::num::NonZeroUsize;
+use std::sync::LazyLock;
+
+use serde::de::Error as _;
 use serde::ser::SerializeMap;
 
 use crate::http::Request;
@@ -8,10 +12,23 @@ use crate::*;
 
 #[derive(Clone)]
 pub struct RateLimit {
-	ratelimit: Arc<ratelimit::Ratelimiter>,
+	buckets: Arc<Buckets>,
 	pub limit_type: RateLimitType,
 }
 
+enum Buckets {
+	Global(ratelimit::Ratelimiter),
+	Keyed {
+		key: RateLimitKey,
+		params: RateLimitSerde,
+		buckets: Mutex<lru::LruCache<Strng, Arc<ratelimit::Ratelimiter>>>,
+	},
+}
+
+// Bounds the memory used by keyed rate limits. Once full, the bucket that was used least recently is
+// dropped; a client returning after that starts with a full bucket again.
+pub(crate) const MAX_KEYED_BUCKETS: NonZeroUsize = NonZeroUsize::new(10_000).unwrap();
+
 impl serde::Serialize for RateLimit {
 	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
 	where
@@ -49,6 +66,8 @@ pub struct RateLimitSerde {
 	#[serde(default)]
 	#[serde(rename = "type")]
 	pub limit_type: RateLimitType,
+	#[serde(default)]
+	pub key: RateLimitKey,
 }
 
 #[derive(Default, Debug, Eq, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
@@ -61,33 +80,123 @@ pub enum RateLimitType {
 	Tokens,
 }
 
+/// RateLimitKey selects which bucket a request is counted against.
+#[derive(Default, Debug, Eq, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RateLimitKey {
+	/// A single bucket shared by all requests.
+	#[default]
+	Global,
+	/// A bucket per client IP. When the connection comes from one of the trusted proxies, the client IP
+	/// is read from X-Forwarded-For. If no trusted proxies are set, the global ones are used.
+	/// Connections over a Unix domain socket have no peer IP and are reported as `0.0.0.0`, so they
+	/// share a single bucket unless `0.0.0.0/32` is trusted to report the client IP.
+	#[serde(rename_all = "camelCase")]
+	ClientIp {
+		#[serde(default)]
+		trusted_proxies: Vec<ipnet::IpNet>,
+	},
+	/// A bucket per value of the header. Requests without the header are not limited.
+	Header(#[serde(serialize_with = "ser_display", deserialize_with = "de_parse")] http::HeaderName),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error(transparent)]
+	Ratelimit(#[from] ratelimit::Error),
+	#[error("a rate limit key is only supported for request limits")]
+	KeyedTokenLimit,
+}
+
 impl TryFrom<RateLimitSerde> for RateLimit {
-	type Error = ratelimit::Error;
+	type Error = Error;
 	fn try_from(value: RateLimitSerde) -> Result<Self, Self::Error> {
-		let rl = ratelimit::Ratelimiter::builder(value.tokens_per_fill, value.fill_interval)
-			.initial_available(value.max_tokens)
-			.max_tokens(value.max_tokens)
-			.build()?;
+		let buckets = match &value.key {
+			RateLimitKey::Global => Buckets::Global(build_ratelimiter(&value)?),
+			_ if value.limit_type == RateLimitType::Tokens => return Err(Error::KeyedTokenLimit),
+			key => {
+				// Validate the parameters up front, so per-key construction cannot fail later.
+				build_ratelimiter(&value)?;
+				Buckets::Keyed {
+					key: key.clone(),
+					params: value.clone(),
+					buckets: Mutex::new(lru::LruCache::new(MAX_KEYED_BUCKETS)),
+				}
+			},
+		};
 		Ok(RateLimit {
-			ratelimit: Arc::new(rl),
+			buckets: Arc::new(buckets),
 			limit_type: value.limit_type,
 		})
 	}
 }
 
+fn build_ratelimiter(value: &RateLimitSerde) -> Result<ratelimit::Ratelimiter, ratelimit::Error> {
+	ratelimit::Ratelimiter::builder(value.tokens_per_fill, value.fill_interval)
+		.initial_available(value.max_tokens)
+		.max_tokens(value.max_tokens)
+		.build()
+}
+
 impl RateLimit {
 	pub fn check_request(&self, req: &Request) -> bool {
 		if self.limit_type != RateLimitType::Requests {
 			return true;
 		}
-		self.ratelimit.try_wait().is_ok()
+		match self.buckets.as_ref() {
+			Buckets::Global(rl) => rl.try_wait().is_ok(),
+			Buckets::Keyed {
+				key,
+				params,
+				buckets,
+			} => {
+				let k = match key {
+					RateLimitKey::Global => unreachable!("global keys use a single bucket"),
+					RateLimitKey::ClientIp { trusted_proxies } => {
+						// Without trusted proxies of its own, the client IP resolved from the global ones is used
+						let ip = if trusted_proxies.is_empty() {
+							http::ClientIp::get(req)
+						} else {
+							http::client_ip(req, trusted_proxies)
+						};
+						let Some(ip) = ip else {
+							return true;
+						};
+						strng::new(ip.to_string())
+					},
+					RateLimitKey::Header(h) => {
+						let Some(v) = req.headers().get(h).and_then(|v| v.to_str().ok()) else {
+							return true;
+						};
+						strng::new(v)
+					},
+				};
+				let rl = buckets
+					.lock()
+					.unwrap()
+					.get_or_insert(k, || {
+						Arc::new(build_ratelimiter(params).expect("parameters validated on construction"))
+					})
+					.clone();
+				rl.try_wait().is_ok()
+			},
+		}
 	}
 	// TODO: add true-up for the response toke usage
 	pub fn check_llm_request(&self, req: &LLMRequest) -> bool {
 		if self.limit_type != RateLimitType::Tokens {
 			return true;
 		}
-		self.ratelimit.try_wait_n(req.input_tokens).is_ok()
+		self.try_acquire(req.input_tokens)
+	}
+
+	/// Take `n` tokens from the bucket regardless of the limit type.
+	pub(crate) fn try_acquire(&self, n: u64) -> bool {
+		match self.buckets.as_ref() {
+			Buckets::Global(rl) => rl.try_wait_n(n).is_ok(),
+			// Keyed limits are only allowed for requests; there is no single bucket to take from.
+			Buckets::Keyed { .. } => true,
+		}
 	}
 
 	/// Remove tokens from the rate limiter after the fact. This is useful for true-up
@@ -96,8 +205,70 @@ impl RateLimit {
 	/// If there are fewer tokens available than requested to remove, the bucket
 	/// will be set to 0.
 	pub fn amend_tokens(&self, tokens_to_remove: i64) {
-		self.ratelimit.amend_tokens(tokens_to_remove);
+		if let Buckets::Global(rl) = self.buckets.as_ref() {
+			rl.amend_tokens(tokens_to_remove);
+		}
 	}
+
+	/// Snapshot the current state of the buckets.
+	pub fn status(&self) -> Status {
+		let bucket = |key, rl: &ratelimit::Ratelimiter| {
+			let (remaining, next_refill) = rl.current();
+			BucketStatus {
+				key,
+				remaining,
+				// Sub-millisecond precision only makes the output harder to read
+				next_refill: Duration::from_millis(next_refill.as_millis() as u64),
+			}
+		};
+		let (max_tokens, buckets) = match self.buckets.as_ref() {
+			Buckets::Global(rl) => (rl.max_tokens(), vec![bucket(None, rl)]),
+			Buckets::Keyed {
+				params, buckets, ..
+			} => (
+				params.max_tokens,
+				buckets
+					.lock()
+					.unwrap()
+					.iter()
+					.map(|(k, rl)| bucket(Some(hash_key(k)), rl))
+					.collect(),
+			),
+		};
+		Status {
+			limit_type: self.limit_type.clone(),
+			max_tokens,
+			buckets,
+		}
+	}
+}
+
+/// Status is a point in time view of a rate limit, for debugging.
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Status {
+	#[serde(rename = "type")]
+	pub limit_type: RateLimitType,
+	pub max_tokens: u64,
+	pub buckets: Vec<BucketStatus>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BucketStatus {
+	/// For keyed limits, a hash of the key; the key itself may identify a client, so it is never exposed.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub key: Option<Strng>,
+	pub remaining: u64,
+	#[serde(with = "serde_dur")]
+	pub next_refill: Duration,
+}
+
+// Seeded once per process, so a key hashes the same way on every call but cannot be reversed offline.
+static KEY_HASHER: LazyLock<RandomState> = LazyLock::new(RandomState::new);
+
+fn hash_key(key: &str) -> Strng {
+	strng::format!("{:016x}", KEY_HASHER.hash_one(key))
 }
 
 // Forked from https://github.com/pelikan-io/rustcommon/tree/main/ratelimit to provide some additional functions
@@ -181,6 +352,17 @@ mod ratelimit {
 			self.refill_at.load(Ordering::Relaxed)
 		}
 
+		/// Returns the number of tokens available now, and the time until the next
+		/// refill. Unlike `available()`, this accounts for refills that are due but
+		/// have not happened yet because the bucket was not used.
+		pub fn current(&self) -> (u64, core::time::Duration) {
+			let now = Instant::now();
+			let _ = self.refill(now);
+			// The bucket is now refilled up to `now`, so this reports the time until the next refill.
+			let next_refill = self.refill(now).err().unwrap_or_default();
+			(self.available(), next_refill)
+		}
+
 		/// Returns the number of tokens that have been dropped due to bucket
 		/// overflowing.
 		pub fn dropped(&self) -> u64 {
@@ -753,3 +935,89 @@ mod ratelimit {
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::transport::stream::TCPConnectionInfo;
+
+	fn request(peer: &str, xff: Option<&str>) -> Request {
+		let mut rb = ::http::Request::builder().uri("http://example.com/");
+		if let Some(xff) = xff {
+			rb = rb.header("x-forwarded-for", xff);
+		}
+		let mut req = rb.body(crate::http::Body::empty()).unwrap();
+		req.extensions_mut().insert(TCPConnectionInfo {
+			peer_addr: SocketAddr::new(peer.parse().unwrap(), 12345),
+			local_addr: "127.0.0.1:8080".parse().unwrap(),
+			start: Instant::now(),
+		});
+		req
+	}
+
+	fn limit(key: RateLimitKey) -> RateLimit {
+		RateLimitSerde {
+			max_tokens: 1,
+			tokens_per_fill: 1,
+			fill_interval: Duration::from_secs(60),
+			limit_type: RateLimitType::Requests,
+			key,
+		}
+		.try_into()
+		.unwrap()
+	}
+
+	#[test]
+	fn per_client_ip() {
+		let rl = limit(RateLimitKey::ClientIp {
+			trusted_proxies: vec![],
+		});
+		assert!(rl.check_request(&request("10.0.0.1", None)));
+		assert!(!rl.check_request(&request("10.0.0.1", None)));
+		// A different client has its own budget
+		assert!(rl.check_request(&request("10.0.0.2", None)));
+		assert!(!rl.check_request(&request("10.0.0.2", None)));
+		// X-Forwarded-For is ignored from untrusted peers
+		assert!(!rl.check_request(&request("10.0.0.1", Some("10.0.0.3"))));
+	}
+
+	#[test]
+	fn per_client_ip_trusted_proxy() {
+		let rl = limit(RateLimitKey::ClientIp {
+			trusted_proxies: vec!["192.168.0.0/16".parse().unwrap()],
+		});
+		let proxy = "192.168.1.1";
+		assert!(rl.check_request(&request(proxy, Some("10.0.0.1, 192.168.1.2"))));
+		assert!(!rl.check_request(&request(proxy, Some("10.0.0.1"))));
+		// A spoofed leftmost entry does not change the client
+		assert!(!rl.check_request(&request(proxy, Some("10.0.0.9, 10.0.0.1"))));
+		assert!(rl.check_request(&request(proxy, Some("10.0.0.2"))));
+	}
+
+	#[test]
+	fn per_header() {
+		let rl = limit(RateLimitKey::Header(http::HeaderName::from_static(
+			"x-forwarded-for",
+		)));
+		assert!(rl.check_request(&request("10.0.0.1", Some("a"))));
+		assert!(!rl.check_request(&request("10.0.0.1", Some("a"))));
+		assert!(rl.check_request(&request("10.0.0.1", Some("b"))));
+		// No header, no limit
+		assert!(rl.check_request(&request("10.0.0.1", None)));
+		assert!(rl.check_request(&request("10.0.0.1", None)));
+	}
+
+	#[test]
+	fn keyed_token_limit_rejected() {
+		let res = RateLimit::try_from(RateLimitSerde {
+			max_tokens: 1,
+			tokens_per_fill: 1,
+			fill_interval: Duration::from_secs(60),
+			limit_type: RateLimitType::Tokens,
+			key: RateLimitKey::ClientIp {
+				trusted_proxies: vec![],
+			},
+		});
+		assert!(matches!(res, Err(Error::KeyedTokenLimit)));
+	}
+}
@@ -0,0 +1,47 @@
+use bytes::Bytes;
+
+use crate::http::{Response, StatusCode, header};
+use crate::*;
+
+/// MaintenancePage is returned in place of proxying requests while a route is in maintenance mode.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct MaintenancePage {
+	#[serde(default = "default_status", with = "http_serde::status_code")]
+	pub status: StatusCode,
+	#[serde(default = "default_body")]
+	pub body: Bytes,
+	/// Sent to clients in the Retry-After header, rounded to seconds.
+	#[serde(default, with = "serde_dur_option")]
+	pub retry_after: Option<Duration>,
+}
+
+impl Default for MaintenancePage {
+	fn default() -> Self {
+		MaintenancePage {
+			status: default_status(),
+			body: default_body(),
+			retry_after: None,
+		}
+	}
+}
+
+fn default_status() -> StatusCode {
+	StatusCode::SERVICE_UNAVAILABLE
+}
+
+fn default_body() -> Bytes {
+	Bytes::from_static(b"This service is down for maintenance. Please try again later.\n")
+}
+
+impl MaintenancePage {
+	pub fn apply(&self) -> Response {
+		let mut resp = ::http::Response::builder().status(self.status);
+		if let Some(retry_after) = self.retry_after {
+			resp = resp.header(header::RETRY_AFTER, retry_after.as_secs());
+		}
+		resp
+			.body(http::Body::from(self.body.clone()))
+			.expect("builder with known status code should not fail")
+	}
+}
@@ -4,20 +4,30 @@ pub mod timeout;
 mod buflist;
 pub mod cors;
 pub mod jwt;
+pub mod loadbalancer;
 pub mod localratelimit;
 pub mod retry;
 pub mod route;
 
 pub mod auth;
+pub mod authorization;
+pub mod canary;
+pub mod coalesce;
 #[cfg(any(test, feature = "internal_benches"))]
 mod tests_common;
 #[allow(dead_code)]
 mod transformation;
 // Do not warn is it is WIP
 pub mod backendtls;
+pub mod compression;
 pub mod ext_authz;
 pub mod ext_proc;
+pub mod globalratelimit;
+pub mod grpcweb;
+pub mod headerfilter;
+pub mod maintenance;
 pub mod remoteratelimit;
+pub mod servertiming;
 pub mod transformation_cel;
 
 pub type Error = axum_core::Error;
@@ -28,6 +38,8 @@ pub use ::http::uri::{Authority, Scheme};
 pub use ::http::{
 	HeaderMap, HeaderName, HeaderValue, Method, StatusCode, Uri, header, status, uri,
 };
+use std::net::IpAddr;
+
 use axum::body::to_bytes;
 use bytes::Bytes;
 use serde::de::DeserializeOwned;
@@ -103,6 +115,91 @@ pub fn get_host(req: &Request) -> Result<&str, ProxyError> {
 	Ok(host)
 }
 
+/// The IP of the client that originated the request, as resolved when the request arrived from the
+/// globally configured trusted proxies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientIp(pub IpAddr);
+
+impl ClientIp {
+	/// Returns the resolved client IP of the request, or the IP of the direct peer if it was not
+	/// resolved.
+	pub fn get(req: &Request) -> Option<IpAddr> {
+		match req.extensions().get::<ClientIp>() {
+			Some(ip) => Some(ip.0),
+			None => client_ip(req, &[]),
+		}
+	}
+}
+
+/// Determine the IP of the client that originated the request. If the direct peer is one of the
+/// `trusted_proxies`, X-Forwarded-For (or Forwarded, if there is no X-Forwarded-For) is walked from
+/// the right, skipping trusted hops, and the first untrusted address is used.
+pub fn client_ip(req: &Request, trusted_proxies: &[ipnet::IpNet]) -> Option<IpAddr> {
+	let peer = req
+		.extensions()
+		.get::<crate::transport::stream::TCPConnectionInfo>()?
+		.peer_addr
+		.ip()
+		.to_canonical();
+	let is_trusted = |ip: &IpAddr| trusted_proxies.iter().any(|n| n.contains(ip));
+	if !is_trusted(&peer) {
+		return Some(peer);
+	}
+	let mut client = peer;
+	let mut hops = req
+		.headers()
+		.get_all(X_FORWARDED_FOR)
+		.iter()
+		.filter_map(|v| v.to_str().ok())
+		.flat_map(|v| v.split(','))
+		.map(|hop| hop.trim())
+		.collect::<Vec<_>>();
+	if hops.is_empty() {
+		hops = req
+			.headers()
+			.get_all(header::FORWARDED)
+			.iter()
+			.filter_map(|v| v.to_str().ok())
+			.flat_map(|v| v.split(','))
+			.map(forwarded_for)
+			.collect();
+	}
+	for hop in hops.into_iter().rev() {
+		let Ok(ip) = hop.parse::<IpAddr>() else {
+			// Anything left of an unparsable entry cannot be trusted
+			break;
+		};
+		client = ip.to_canonical();
+		if !is_trusted(&client) {
+			break;
+		}
+	}
+	Some(client)
+}
+
+// Returns the address of a Forwarded element's `for` parameter, without quotes or port, such as
+// `192.0.2.1` from `for=192.0.2.1;proto=http` or `2001:db8::1` from `for="[2001:db8::1]:4711"`.
+// Elements without one return an empty string, which does not parse as an address.
+fn forwarded_for(element: &str) -> &str {
+	let Some(node) = element.split(';').find_map(|pair| {
+		let (k, v) = pair.trim().split_once('=')?;
+		k.eq_ignore_ascii_case("for")
+			.then_some(v.trim().trim_matches('"'))
+	}) else {
+		return "";
+	};
+	if let Some(v6) = node.strip_prefix('[') {
+		return v6.split_once(']').map(|(ip, _)| ip).unwrap_or(v6);
+	}
+	// IPv4 with a port. Bare IPv6 addresses are not valid here, as they must be bracketed.
+	match node.split_once(':') {
+		Some((ip, _)) => ip,
+		None => node,
+	}
+}
+
+pub const X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+
 pub async fn inspect_body(body: &mut Body) -> anyhow::Result<Bytes> {
 	let orig = std::mem::replace(body, Body::empty());
 	let bytes = to_bytes(orig, 2_097_152).await?;
@@ -165,7 +262,23 @@ pub fn merge_in_headers(additional_headers: Option<HeaderMap>, dest: &mut Header
 	if let Some(rh) = additional_headers {
 		for (k, v) in rh.into_iter() {
 			let Some(k) = k else { continue };
+			// Vary lists accumulate; replacing it would drop the backend's own entries.
+			if k == header::VARY {
+				if !dest.get_all(&k).iter().any(|existing| existing == v) {
+					dest.append(k, v);
+				}
+				continue;
+			}
+			// Each Set-Cookie sets a separate cookie, so they must not replace the backend's.
+			if k == header::SET_COOKIE {
+				dest.append(k, v);
+				continue;
+			}
 			dest.insert(k, v);
 		}
 	}
 }
+
+#[cfg(test)]
+#[path = "tests.rs"]
+mod tests;
@@ -0,0 +1,128 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+
+use crate::*;
+
+#[cfg(test)]
+#[path = "budget_tests.rs"]
+mod tests;
+
+// The window is tracked in this many buckets, so old traffic ages out gradually rather than all at
+// once.
+const BUCKETS: u32 = 10;
+
+/// Budget caps retries to a percentage of the original requests seen over a sliding window, so a
+/// failing backend does not receive a multiple of its normal load in retries.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+pub struct Budget {
+	/// The maximum retries, as a percentage of requests in the window.
+	#[serde(default = "default_percent")]
+	pub percent: f64,
+	/// Retries allowed in the window regardless of the percentage.
+	#[serde(default = "default_min_retries")]
+	pub min_retries: u32,
+	#[serde(default = "default_window", with = "serde_dur")]
+	#[cfg_attr(feature = "schema", schemars(with = "String"))]
+	pub window: Duration,
+	#[serde(skip)]
+	#[cfg_attr(feature = "schema", schemars(skip))]
+	state: Arc<Mutex<Window>>,
+}
+
+pub(crate) fn default_percent() -> f64 {
+	20.0
+}
+
+pub(crate) fn default_min_retries() -> u32 {
+	3
+}
+
+pub(crate) fn default_window() -> Duration {
+	Duration::from_secs(10)
+}
+
+#[derive(Debug, Default)]
+struct Window {
+	buckets: VecDeque<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+	start: Instant,
+	requests: u64,
+	retries: u64,
+}
+
+impl Budget {
+	pub fn new(percent: f64, min_retries: u32, window: Duration) -> Self {
+		Budget {
+			percent,
+			min_retries,
+			window,
+			state: Default::default(),
+		}
+	}
+
+	/// Record an original (non-retry) request.
+	pub fn record_request(&self) {
+		self.record_request_at(Instant::now())
+	}
+
+	fn record_request_at(&self, now: Instant) {
+		let mut state = self.state.lock();
+		self.expire(&mut state, now);
+		Self::bucket(&mut state, now, self.bucket_width()).requests += 1;
+	}
+
+	/// Returns whether a retry is allowed, and if so counts it against the budget.
+	pub fn try_retry(&self) -> bool {
+		self.try_retry_at(Instant::now())
+	}
+
+	fn try_retry_at(&self, now: Instant) -> bool {
+		let mut state = self.state.lock();
+		self.expire(&mut state, now);
+		let (requests, retries) = state
+			.buckets
+			.iter()
+			.fold((0, 0), |(req, ret), b| (req + b.requests, ret + b.retries));
+		let allowed = (requests as f64 * self.percent / 100.0) as u64;
+		if retries >= allowed.max(self.min_retries as u64) {
+			return false;
+		}
+		Self::bucket(&mut state, now, self.bucket_width()).retries += 1;
+		true
+	}
+
+	fn bucket_width(&self) -> Duration {
+		self.window / BUCKETS
+	}
+
+	fn expire(&self, state: &mut Window, now: Instant) {
+		while let Some(b) = state.buckets.front() {
+			if now.duration_since(b.start) < self.window {
+				break;
+			}
+			state.buckets.pop_front();
+		}
+	}
+
+	fn bucket(state: &mut Window, now: Instant, width: Duration) -> &mut Bucket {
+		let fresh = match state.buckets.back() {
+			Some(b) => now.duration_since(b.start) >= width,
+			None => true,
+		};
+		if fresh {
+			state.buckets.push_back(Bucket {
+				start: now,
+				requests: 0,
+				retries: 0,
+			});
+		}
+		state.buckets.back_mut().expect("bucket was just ensured")
+	}
+}
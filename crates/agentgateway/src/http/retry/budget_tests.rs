@@ -0,0 +1,54 @@
+use super::*;
+
+#[test]
+fn retries_throttled_under_failure_flood() {
+	let budget = Budget::new(20.0, 3, Duration::from_secs(10));
+	let now = Instant::now();
+	let mut allowed = 0;
+	// Every request fails and wants a retry
+	for _ in 0..100 {
+		budget.record_request_at(now);
+		if budget.try_retry_at(now) {
+			allowed += 1;
+		}
+	}
+	assert_eq!(allowed, 20);
+	assert!(!budget.try_retry_at(now));
+}
+
+#[test]
+fn min_retries_allowed_without_traffic() {
+	let budget = Budget::new(20.0, 3, Duration::from_secs(10));
+	let now = Instant::now();
+	assert!(budget.try_retry_at(now));
+	assert!(budget.try_retry_at(now));
+	assert!(budget.try_retry_at(now));
+	assert!(!budget.try_retry_at(now));
+}
+
+#[test]
+fn budget_recovers_after_window() {
+	let budget = Budget::new(10.0, 0, Duration::from_secs(10));
+	let now = Instant::now();
+	for _ in 0..10 {
+		budget.record_request_at(now);
+	}
+	assert!(budget.try_retry_at(now));
+	assert!(!budget.try_retry_at(now));
+
+	// Once the old traffic ages out, new requests earn new retries
+	let later = now + Duration::from_secs(11);
+	for _ in 0..10 {
+		budget.record_request_at(later);
+	}
+	assert!(budget.try_retry_at(later));
+	assert!(!budget.try_retry_at(later));
+}
+
+#[test]
+fn clones_share_state() {
+	let budget = Budget::new(0.0, 1, Duration::from_secs(10));
+	let clone = budget.clone();
+	assert!(budget.try_retry());
+	assert!(!clone.try_retry());
+}
@@ -1,9 +1,11 @@
 mod body;
+pub(crate) mod budget;
 
 use std::num::NonZeroU8;
 use std::time::Duration;
 
 pub use body::ReplayBody;
+pub use budget::Budget;
 
 use crate::*;
 
@@ -23,6 +25,9 @@ pub struct Policy {
 	#[serde(serialize_with = "ser_display_iter", deserialize_with = "de_codes")]
 	#[cfg_attr(feature = "schema", schemars(with = "Vec<std::num::NonZeroU8>"))]
 	pub codes: Box<[http::StatusCode]>,
+	/// Limit retries to a share of the overall traffic.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub budget: Option<Budget>,
 }
 
 pub fn de_codes<'de: 'a, 'a, D>(deserializer: D) -> Result<Box<[http::StatusCode]>, D::Error>
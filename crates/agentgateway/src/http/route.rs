@@ -160,26 +160,21 @@ pub fn select_best_route(
 				.map(|q| url::form_urlencoded::parse(q.as_bytes()).collect::<HashMap<_, _>>())
 				.unwrap_or_default();
 			for agent::QueryMatch { name, value } in &m.query {
-				let Some(have) = query.get(name.as_str()) else {
-					return false;
-				};
-
-				match value {
-					QueryValueMatch::Exact(want) => {
-						if have.as_ref() != want.as_str() {
-							return false;
-						}
-					},
-					QueryValueMatch::Regex(want) => {
-						// Must be a valid string to do regex match
-						let Some(m) = want.find(have) else {
-							return false;
-						};
+				let have = query.get(name.as_str());
+				let matches = match (value, have) {
+					(QueryValueMatch::Present, have) => have.is_some(),
+					(QueryValueMatch::Absent, have) => have.is_none(),
+					(_, None) => false,
+					(QueryValueMatch::Exact(want), Some(have)) => have.as_ref() == want.as_str(),
+					(QueryValueMatch::Regex(want), Some(have)) => {
 						// Make sure we matched the entire thing
-						if !(m.start() == 0 && m.end() == have.len()) {
-							return false;
-						}
+						want
+							.find(have)
+							.is_some_and(|m| m.start() == 0 && m.end() == have.len())
 					},
+				};
+				if !matches {
+					return false;
 				}
 			}
 			true
@@ -189,5 +184,9 @@ pub fn select_best_route(
 			return Some((Arc::new(route.clone()), matcher.path.clone()));
 		}
 	}
-	None
+	// Nothing matched, so fall back to the listener default, if there is one
+	listener
+		.default_route
+		.as_ref()
+		.map(|r| (Arc::new(r.clone()), PathMatch::PathPrefix(strng::new("/"))))
 }
@@ -11,8 +11,8 @@ use crate::http::filters::RequestRedirect;
 use crate::http::tests_common::*;
 use crate::store::Stores;
 use crate::types::agent::{
-	HeaderMatch, HeaderValueMatch, Listener, ListenerProtocol, MethodMatch, PathMatch, QueryMatch,
-	QueryValueMatch, Route, RouteKey, RouteMatch, RouteSet,
+	GrpcRouteMatch, HeaderMatch, HeaderValueMatch, Listener, ListenerProtocol, MethodMatch,
+	PathMatch, QueryMatch, QueryValueMatch, Route, RouteKey, RouteMatch, RouteSet,
 };
 use crate::*;
 
@@ -53,6 +53,8 @@ fn setup_listener(routes: &[(&str, Vec<&str>, Vec<RouteMatch>)]) -> Arc<Listener
 		hostname: Default::default(),
 		protocol: ListenerProtocol::HTTP,
 		tcp_routes: Default::default(),
+		default_route: None,
+		log_sampling: None,
 		routes: RouteSet::from_list(
 			routes
 				.iter()
@@ -194,16 +196,16 @@ fn test_path_matching() {
 			path: "/api/v1/users/123",
 			expected_route: Some("prefix-path"),
 		},
-		// Test regex path matching
+		// Test regex path matching. Both also match the /api/ prefix, which takes precedence.
 		TestCase {
 			name: "regex path match",
 			path: "/api/v2/users",
-			expected_route: Some("regex-path"),
+			expected_route: Some("prefix-path"),
 		},
 		TestCase {
 			name: "regex path match v3",
 			path: "/api/v3/users",
-			expected_route: Some("regex-path"),
+			expected_route: Some("prefix-path"),
 		},
 		// Test root prefix fallback
 		TestCase {
@@ -245,6 +247,56 @@ fn test_path_matching() {
 	}
 }
 
+#[test]
+fn test_path_match_precedence() {
+	let routes = vec![
+		("exact", PathMatch::Exact("/a".into())),
+		("prefix", PathMatch::PathPrefix("/a".into())),
+		(
+			"regex",
+			PathMatch::Regex(Regex::new(r"^/a/.*$").unwrap(), 7),
+		),
+		(
+			"other-regex",
+			PathMatch::Regex(Regex::new(r"^/b/\d+$").unwrap(), 9),
+		),
+	];
+	let cases = [
+		("/a", Some("exact")),
+		("/a/b", Some("prefix")),
+		("/b/1", Some("other-regex")),
+		("/b/x", None),
+	];
+	let to_routes = |routes: Vec<(&'static str, PathMatch)>| {
+		routes
+			.into_iter()
+			.map(|(name, path)| {
+				(
+					name,
+					vec![],
+					vec![RouteMatch {
+						headers: vec![],
+						path,
+						method: None,
+						query: vec![],
+					}],
+				)
+			})
+			.collect_vec()
+	};
+	// The result must not depend on the order the routes were added in
+	for routes in [
+		to_routes(routes.clone()),
+		to_routes(routes.into_iter().rev().collect()),
+	] {
+		for (path, expected) in cases {
+			let req = request(&format!("http://example.com{path}"), http::Method::GET, &[]);
+			let result = run_test(&req, routes.as_slice());
+			assert_eq!(result.as_deref(), expected, "{path}");
+		}
+	}
+}
+
 #[test]
 fn test_method_matching() {
 	let routes = vec![
@@ -526,6 +578,156 @@ fn test_query_parameter_matching() {
 	}
 }
 
+#[test]
+fn test_query_parameter_presence() {
+	let beta = |value: QueryValueMatch| {
+		vec![RouteMatch {
+			headers: vec![],
+			path: PathMatch::PathPrefix("/".into()),
+			method: None,
+			query: vec![QueryMatch {
+				name: "beta".into(),
+				value,
+			}],
+		}]
+	};
+	let routes = vec![
+		("beta-1", vec![], beta(QueryValueMatch::Exact("1".into()))),
+		("beta-flag", vec![], beta(QueryValueMatch::Present)),
+		("stable", vec![], beta(QueryValueMatch::Absent)),
+	];
+
+	let cases = [
+		("valueless key is present", "?beta", "beta-flag"),
+		("exact value takes precedence", "?beta=1", "beta-1"),
+		("any other value is present", "?beta=2", "beta-flag"),
+		("empty value is present", "?beta=", "beta-flag"),
+		("no query is absent", "", "stable"),
+		("other keys are absent", "?gamma=1", "stable"),
+	];
+	for (name, query, expected) in cases {
+		let req = request(
+			&format!("http://example.com/{query}"),
+			http::Method::GET,
+			&[],
+		);
+		let result = run_test(&req, routes.as_slice());
+		assert_eq!(result, Some(expected.to_string()), "{name}");
+	}
+}
+
+#[test]
+fn test_grpc_matching() {
+	let grpc = |service: Option<&str>, method: Option<&str>| {
+		vec![
+			GrpcRouteMatch {
+				service: service.map(strng::new),
+				method: method.map(strng::new),
+				headers: vec![],
+			}
+			.into_route_match()
+			.unwrap(),
+		]
+	};
+	let routes = vec![
+		(
+			"say-hello",
+			vec![],
+			grpc(Some("helloworld.Greeter"), Some("SayHello")),
+		),
+		("greeter", vec![], grpc(Some("helloworld.Greeter"), None)),
+		("any-check", vec![], grpc(None, Some("Check"))),
+		(
+			"fallback",
+			vec![],
+			vec![RouteMatch {
+				headers: vec![],
+				path: PathMatch::PathPrefix("/".into()),
+				method: None,
+				query: vec![],
+			}],
+		),
+	];
+
+	struct TestCase {
+		name: &'static str,
+		path: &'static str,
+		method: http::Method,
+		content_type: &'static str,
+		expected_route: &'static str,
+	}
+
+	let cases = vec![
+		TestCase {
+			name: "exact service and method",
+			path: "/helloworld.Greeter/SayHello",
+			method: http::Method::POST,
+			content_type: "application/grpc",
+			expected_route: "say-hello",
+		},
+		TestCase {
+			name: "service wildcard",
+			path: "/helloworld.Greeter/SayGoodbye",
+			method: http::Method::POST,
+			content_type: "application/grpc+proto",
+			expected_route: "greeter",
+		},
+		TestCase {
+			name: "service name is not a prefix match",
+			path: "/helloworld.GreeterV2/SayHello",
+			method: http::Method::POST,
+			content_type: "application/grpc",
+			expected_route: "fallback",
+		},
+		TestCase {
+			name: "method on any service",
+			path: "/grpc.health.v1.Health/Check",
+			method: http::Method::POST,
+			content_type: "application/grpc",
+			expected_route: "any-check",
+		},
+		TestCase {
+			name: "non-gRPC content type",
+			path: "/helloworld.Greeter/SayHello",
+			method: http::Method::POST,
+			content_type: "application/json",
+			expected_route: "fallback",
+		},
+		TestCase {
+			name: "non-POST method",
+			path: "/helloworld.Greeter/SayHello",
+			method: http::Method::GET,
+			content_type: "application/grpc",
+			expected_route: "fallback",
+		},
+	];
+
+	for case in cases {
+		let req = request(
+			&format!("http://example.com{}", case.path),
+			case.method,
+			&[("content-type", case.content_type)],
+		);
+		let result = run_test(&req, routes.as_slice());
+		assert_eq!(
+			result,
+			Some(case.expected_route.to_string()),
+			"{}",
+			case.name
+		);
+	}
+
+	assert!(
+		GrpcRouteMatch {
+			service: Some(strng::new("helloworld.Greeter/SayHello")),
+			method: None,
+			headers: vec![],
+		}
+		.into_route_match()
+		.is_err()
+	);
+}
+
 #[test]
 fn test_route_precedence() {
 	let routes = vec![
@@ -717,6 +919,8 @@ fn bench(b: Bencher, (host, route): (u64, u64)) {
 		hostname: Default::default(),
 		protocol: ListenerProtocol::HTTP,
 		tcp_routes: Default::default(),
+		default_route: None,
+		log_sampling: None,
 		routes: RouteSet::from_list(
 			routes
 				.into_iter()
@@ -0,0 +1,43 @@
+use std::fmt::Write;
+use std::sync::Mutex;
+
+use crate::http::{HeaderName, HeaderValue, Response};
+use crate::*;
+
+/// Request header that asks for a Server-Timing header on the response. It is not forwarded to
+/// the backend.
+pub const DEBUG_HEADER: HeaderName = HeaderName::from_static("x-agentgateway-server-timing");
+const SERVER_TIMING: HeaderName = HeaderName::from_static("server-timing");
+
+/// ServerTiming records how long each phase of a request took, so it can be reported to the client
+/// in a Server-Timing header. It is only created when the client asked for it, and is shared
+/// through the request extensions.
+#[derive(Debug, Clone, Default)]
+pub struct ServerTiming(Arc<Mutex<Vec<(&'static str, Duration)>>>);
+
+impl ServerTiming {
+	/// Enables timing for the request if the client asked for it.
+	pub fn from_request<B>(req: &mut ::http::Request<B>) -> Option<ServerTiming> {
+		req.headers_mut().remove(DEBUG_HEADER)?;
+		let timing = ServerTiming::default();
+		req.extensions_mut().insert(timing.clone());
+		Some(timing)
+	}
+
+	pub fn record(&self, phase: &'static str, dur: Duration) {
+		self.0.lock().expect("mutex poisoned").push((phase, dur));
+	}
+
+	pub fn apply(&self, resp: &mut Response) {
+		let mut value = String::new();
+		for (phase, dur) in self.0.lock().expect("mutex poisoned").iter() {
+			if !value.is_empty() {
+				value.push_str(", ");
+			}
+			let _ = write!(value, "{phase};dur={:.3}", dur.as_secs_f64() * 1000.0);
+		}
+		if let Ok(hv) = HeaderValue::try_from(value) {
+			resp.headers_mut().append(SERVER_TIMING, hv);
+		}
+	}
+}
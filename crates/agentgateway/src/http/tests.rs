@@ -0,0 +1,105 @@
+use std::net::SocketAddr;
+
+use super::*;
+use crate::transport::stream::TCPConnectionInfo;
+use crate::*;
+
+fn request(peer: &str, headers: &[(&str, &str)]) -> Request {
+	let mut rb = ::http::Request::builder().uri("http://example.com/");
+	for (k, v) in headers {
+		rb = rb.header(*k, *v);
+	}
+	let mut req = rb.body(Body::empty()).unwrap();
+	req.extensions_mut().insert(TCPConnectionInfo {
+		peer_addr: SocketAddr::new(peer.parse().unwrap(), 12345),
+		local_addr: "127.0.0.1:8080".parse().unwrap(),
+		start: Instant::now(),
+	});
+	req
+}
+
+fn trusted() -> Vec<ipnet::IpNet> {
+	vec![
+		"192.168.0.0/16".parse().unwrap(),
+		"172.16.0.0/12".parse().unwrap(),
+	]
+}
+
+fn ip(s: &str) -> Option<IpAddr> {
+	Some(s.parse().unwrap())
+}
+
+#[test]
+fn client_ip_without_trusted_proxies() {
+	let req = request("10.0.0.1", &[("x-forwarded-for", "203.0.113.9")]);
+	assert_eq!(client_ip(&req, &[]), ip("10.0.0.1"));
+	// The peer is not trusted, so its X-Forwarded-For is ignored
+	assert_eq!(client_ip(&req, &trusted()), ip("10.0.0.1"));
+}
+
+#[test]
+fn client_ip_multi_hop() {
+	let req = request(
+		"192.168.1.1",
+		&[
+			("x-forwarded-for", "203.0.113.9, 10.0.0.1"),
+			("x-forwarded-for", "172.16.0.5, 192.168.2.2"),
+		],
+	);
+	// Trusted hops are skipped, and the spoofed leftmost entry added by the client is ignored
+	assert_eq!(client_ip(&req, &trusted()), ip("10.0.0.1"));
+
+	// Every hop is trusted, so the leftmost is the client
+	let req = request(
+		"192.168.1.1",
+		&[("x-forwarded-for", "172.16.0.5, 192.168.2.2")],
+	);
+	assert_eq!(client_ip(&req, &trusted()), ip("172.16.0.5"));
+
+	// Entries left of one that cannot be parsed are not trusted
+	let req = request(
+		"192.168.1.1",
+		&[("x-forwarded-for", "10.0.0.1, garbage, 192.168.2.2")],
+	);
+	assert_eq!(client_ip(&req, &trusted()), ip("192.168.2.2"));
+}
+
+#[test]
+fn client_ip_forwarded() {
+	let req = request(
+		"192.168.1.1",
+		&[(
+			"forwarded",
+			"for=203.0.113.9, for=\"[2001:db8::1]:4711\";proto=https, For=172.16.0.5:80;by=192.168.1.1",
+		)],
+	);
+	assert_eq!(client_ip(&req, &trusted()), ip("2001:db8::1"));
+
+	let req = request("192.168.1.1", &[("forwarded", "for=unknown, for=10.0.0.1")]);
+	assert_eq!(client_ip(&req, &trusted()), ip("10.0.0.1"));
+	let req = request(
+		"192.168.1.1",
+		&[("forwarded", "for=10.0.0.1;proto=http, for=_hidden")],
+	);
+	assert_eq!(client_ip(&req, &trusted()), ip("192.168.1.1"));
+
+	// X-Forwarded-For takes precedence
+	let req = request(
+		"192.168.1.1",
+		&[
+			("forwarded", "for=10.0.0.1"),
+			("x-forwarded-for", "10.0.0.2"),
+		],
+	);
+	assert_eq!(client_ip(&req, &trusted()), ip("10.0.0.2"));
+}
+
+#[test]
+fn resolved_client_ip() {
+	let mut req = request("192.168.1.1", &[("x-forwarded-for", "10.0.0.1")]);
+	assert_eq!(ClientIp::get(&req), ip("192.168.1.1"));
+	req
+		.extensions_mut()
+		.insert(ClientIp("10.0.0.1".parse().unwrap()));
+	assert_eq!(ClientIp::get(&req), ip("10.0.0.1"));
+}
@@ -1,15 +1,24 @@
 use std::cmp;
 use std::future::Future;
+use std::net::IpAddr;
 use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::task::{Context, Poll, ready};
 use std::time::Duration;
 
+use ::http::HeaderName;
 use http_body::{Body, SizeHint};
 use pin_project_lite::pin_project;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
 use tokio::time::{Instant, Sleep, sleep, sleep_until};
 
+use crate::http::jwt::Claims;
+use crate::transport::stream::TCPConnectionInfo;
 use crate::*;
 
+/// Header trusted clients can set to override the request timeout, in milliseconds.
+pub const TIMEOUT_HEADER: HeaderName = HeaderName::from_static("x-agentgateway-timeout-ms");
+
 #[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
 #[cfg_attr(feature = "schema", derive(JsonSchema))]
@@ -28,14 +37,87 @@ pub struct Policy {
 	)]
 	#[cfg_attr(feature = "schema", schemars(with = "Option<String>"))]
 	pub backend_request_timeout: Option<Duration>,
+	/// Fail the request if no body data is sent or received for this long. Unlike the other timeouts,
+	/// this does not limit the overall duration, so slow streams are allowed as long as they progress.
+	#[serde(
+		default,
+		skip_serializing_if = "Option::is_none",
+		with = "serde_dur_option"
+	)]
+	#[cfg_attr(feature = "schema", schemars(with = "Option<String>"))]
+	pub idle_timeout: Option<Duration>,
+	/// Allow trusted clients to override the request timeout with the `x-agentgateway-timeout-ms`
+	/// header. The header is removed from all requests, trusted or not.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub header_override: Option<HeaderOverride>,
 }
 
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+pub struct HeaderOverride {
+	/// The longest timeout a client may request. Larger values are clamped to this.
+	#[serde(with = "serde_dur")]
+	#[cfg_attr(feature = "schema", schemars(with = "String"))]
+	pub max: Duration,
+	/// Client addresses that are allowed to set the header.
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	#[cfg_attr(feature = "schema", schemars(with = "Vec<String>"))]
+	pub trusted_sources: Vec<ipnet::IpNet>,
+	/// Roles that are allowed to set the header, matched against the `role` or `roles` claim of an
+	/// authenticated JWT.
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub trusted_roles: Vec<String>,
+}
+
+impl HeaderOverride {
+	fn is_trusted(&self, peer: Option<IpAddr>, claims: Option<&Claims>) -> bool {
+		if let Some(peer) = peer {
+			if self.trusted_sources.iter().any(|n| n.contains(&peer)) {
+				return true;
+			}
+		}
+		claims.is_some_and(|c| c.roles().any(|r| self.trusted_roles.iter().any(|t| t == r)))
+	}
+}
+
+/// The request timeout requested by a trusted client, stored in the request extensions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeoutOverride(pub Duration);
+
 impl Policy {
+	/// Strip the timeout header from the request, recording the requested timeout if the client is
+	/// trusted to set it. Must run for every request, even without an override configured, so the
+	/// header is never forwarded, and after authentication, so role claims are available.
+	pub fn apply_header_override(&self, req: &mut crate::http::Request) {
+		let Some(value) = req.headers_mut().remove(&TIMEOUT_HEADER) else {
+			return;
+		};
+		let Some(ho) = &self.header_override else {
+			return;
+		};
+		let peer = req
+			.extensions()
+			.get::<TCPConnectionInfo>()
+			.map(|c| c.peer_addr.ip());
+		if !ho.is_trusted(peer, req.extensions().get::<Claims>()) {
+			debug!("ignoring timeout header from untrusted client");
+			return;
+		}
+		let Some(ms) = value.to_str().ok().and_then(|v| v.parse::<u64>().ok()) else {
+			debug!("ignoring invalid timeout header {:?}", value);
+			return;
+		};
+		let timeout = cmp::min(Duration::from_millis(ms), ho.max);
+		req.extensions_mut().insert(TimeoutOverride(timeout));
+	}
+
 	pub fn effective_timeout(&self) -> Option<Duration> {
 		match self {
 			Policy {
 				request_timeout: Some(request_timeout),
 				backend_request_timeout: Some(backend_request_timeout),
+				..
 			} => {
 				// We do not distinguish these yet, so just take min
 				// TODO: one should apply to per-request attempt
@@ -56,6 +138,8 @@ impl Policy {
 
 pub enum BodyTimeout {
 	Deadline(Instant),
+	/// Fails if no frame arrives within the duration; the timer restarts on every frame.
+	Idle(Duration),
 	None,
 }
 
@@ -63,6 +147,10 @@ impl BodyTimeout {
 	pub fn apply(self, r: crate::http::Response) -> crate::http::Response {
 		r.map(|b| crate::http::Body::new(TimeoutBody::new(self, b)))
 	}
+
+	pub fn apply_request(self, r: crate::http::Request) -> crate::http::Request {
+		r.map(|b| crate::http::Body::new(TimeoutBody::new(self, b)))
+	}
 }
 
 pin_project! {
@@ -100,6 +188,25 @@ where
 	) -> Poll<Option<Result<http_body::Frame<Self::Data>, Self::Error>>> {
 		let mut this = self.project();
 
+		if let BodyTimeout::Idle(idle) = this.timeout {
+			// Poll the body first, so data that is ready is never lost to an expired timer.
+			if let Poll::Ready(frame) = this.body.poll_frame(cx) {
+				// Progress was made; restart the idle timer on the next poll.
+				this.sleep.set(None);
+				return Poll::Ready(frame.transpose().map_err(Into::into).transpose());
+			}
+			let sleep_pinned = if let Some(some) = this.sleep.as_mut().as_pin_mut() {
+				some
+			} else {
+				this.sleep.set(Some(sleep(*idle)));
+				this.sleep.as_mut().as_pin_mut().unwrap()
+			};
+			if let Poll::Ready(()) = sleep_pinned.poll(cx) {
+				return Poll::Ready(Some(Err(Box::new(TimeoutError(())))));
+			}
+			return Poll::Pending;
+		}
+
 		// Start the `Sleep` if not active.
 		if let BodyTimeout::Deadline(d) = this.timeout {
 			// Start the `Sleep` if not active.
@@ -130,6 +237,101 @@ where
 	}
 }
 
+/// IdleTracker records when an IO stream last made progress, for connections that are not proxied
+/// as HTTP bodies, such as upgraded (WebSocket) connections.
+#[derive(Debug, Clone)]
+pub struct IdleTracker {
+	start: Instant,
+	// Milliseconds since `start` of the last read or write.
+	last: Arc<AtomicU64>,
+}
+
+impl Default for IdleTracker {
+	fn default() -> Self {
+		IdleTracker {
+			start: Instant::now(),
+			last: Default::default(),
+		}
+	}
+}
+
+impl IdleTracker {
+	pub fn wrap<T>(&self, inner: T) -> IdleIo<T> {
+		IdleIo {
+			inner,
+			tracker: self.clone(),
+		}
+	}
+
+	fn touch(&self) {
+		let since = self.start.elapsed().as_millis() as u64;
+		self.last.store(since, Ordering::Relaxed);
+	}
+
+	fn last_activity(&self) -> Instant {
+		self.start + Duration::from_millis(self.last.load(Ordering::Relaxed))
+	}
+
+	/// Completes once the stream has not made progress for `idle`.
+	pub async fn idle(&self, idle: Duration) {
+		loop {
+			let deadline = self.last_activity() + idle;
+			if deadline <= Instant::now() {
+				return;
+			}
+			sleep_until(deadline).await;
+		}
+	}
+}
+
+pin_project! {
+	/// An IO stream that reports reads and writes to an [`IdleTracker`].
+	pub struct IdleIo<T> {
+		#[pin]
+		inner: T,
+		tracker: IdleTracker,
+	}
+}
+
+impl<T: AsyncRead> AsyncRead for IdleIo<T> {
+	fn poll_read(
+		self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+		buf: &mut ReadBuf<'_>,
+	) -> Poll<std::io::Result<()>> {
+		let this = self.project();
+		let before = buf.filled().len();
+		let res = ready!(this.inner.poll_read(cx, buf));
+		if buf.filled().len() > before {
+			this.tracker.touch();
+		}
+		Poll::Ready(res)
+	}
+}
+
+impl<T: AsyncWrite> AsyncWrite for IdleIo<T> {
+	fn poll_write(
+		self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+		buf: &[u8],
+	) -> Poll<std::io::Result<usize>> {
+		let this = self.project();
+		let res = ready!(this.inner.poll_write(cx, buf));
+		if matches!(res, Ok(n) if n > 0) {
+			this.tracker.touch();
+		}
+		Poll::Ready(res)
+	}
+
+	fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		self.project().inner.poll_flush(cx)
+	}
+
+	fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		self.project().inner.poll_shutdown(cx)
+	}
+}
+
 /// Error for [`TimeoutBody`].
 #[derive(Debug)]
 pub struct TimeoutError(());
@@ -141,3 +343,7 @@ impl std::fmt::Display for TimeoutError {
 		write!(f, "data was not received within the designated timeout")
 	}
 }
+
+#[cfg(test)]
+#[path = "timeout_test.rs"]
+mod tests;
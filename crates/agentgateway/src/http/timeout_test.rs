@@ -0,0 +1,143 @@
+use http_body_util::{BodyExt, StreamBody};
+
+use super::*;
+
+// Build a body that emits `chunks` frames, waiting `delay` before each one.
+fn trickle(chunks: usize, delay: Duration) -> crate::http::Body {
+	let stream = futures_util::stream::unfold(0, move |i| async move {
+		if i == chunks {
+			return None;
+		}
+		tokio::time::sleep(delay).await;
+		Some((
+			Ok::<_, std::io::Error>(http_body::Frame::data(Bytes::from_static(b"x"))),
+			i + 1,
+		))
+	});
+	crate::http::Body::new(StreamBody::new(stream))
+}
+
+#[tokio::test(start_paused = true)]
+async fn idle_timeout_allows_steady_trickle() {
+	// 10 chunks every 50ms is far longer than the idle timeout in total, but never idle for long.
+	let body = TimeoutBody::new(
+		BodyTimeout::Idle(Duration::from_millis(100)),
+		trickle(10, Duration::from_millis(50)),
+	);
+	let collected = body.collect().await.unwrap().to_bytes();
+	assert_eq!(collected.len(), 10);
+}
+
+#[tokio::test(start_paused = true)]
+async fn idle_timeout_fails_on_stall() {
+	let body = TimeoutBody::new(
+		BodyTimeout::Idle(Duration::from_millis(100)),
+		trickle(2, Duration::from_millis(200)),
+	);
+	let err = body.collect().await.unwrap_err();
+	assert!(err.is::<TimeoutError>());
+}
+
+#[test]
+fn idle_timeout_is_not_a_deadline() {
+	let p = Policy {
+		idle_timeout: Some(Duration::from_secs(1)),
+		..Default::default()
+	};
+	assert_eq!(p.effective_timeout(), None);
+}
+
+fn header_override_policy() -> Policy {
+	Policy {
+		request_timeout: Some(Duration::from_secs(1)),
+		header_override: Some(HeaderOverride {
+			max: Duration::from_secs(10),
+			trusted_sources: vec!["10.0.0.0/8".parse().unwrap()],
+			trusted_roles: vec!["batch".to_string()],
+		}),
+		..Default::default()
+	}
+}
+
+fn timeout_request(peer: &str, timeout: &str) -> crate::http::Request {
+	let mut req = ::http::Request::builder()
+		.uri("http://example.com/")
+		.header(TIMEOUT_HEADER, timeout)
+		.body(crate::http::Body::empty())
+		.unwrap();
+	req.extensions_mut().insert(TCPConnectionInfo {
+		peer_addr: format!("{peer}:12345").parse().unwrap(),
+		local_addr: "10.0.0.2:8080".parse().unwrap(),
+		start: std::time::Instant::now(),
+	});
+	req
+}
+
+#[test]
+fn header_override_trusted_source() {
+	let mut req = timeout_request("10.1.2.3", "5000");
+	header_override_policy().apply_header_override(&mut req);
+	assert_eq!(
+		req.extensions().get::<TimeoutOverride>(),
+		Some(&TimeoutOverride(Duration::from_secs(5)))
+	);
+	assert!(!req.headers().contains_key(TIMEOUT_HEADER));
+}
+
+#[test]
+fn header_override_clamped() {
+	let mut req = timeout_request("10.1.2.3", "60000");
+	header_override_policy().apply_header_override(&mut req);
+	assert_eq!(
+		req.extensions().get::<TimeoutOverride>(),
+		Some(&TimeoutOverride(Duration::from_secs(10)))
+	);
+}
+
+#[test]
+fn header_override_untrusted() {
+	let mut req = timeout_request("192.168.1.1", "5000");
+	header_override_policy().apply_header_override(&mut req);
+	assert_eq!(req.extensions().get::<TimeoutOverride>(), None);
+	// The header is stripped, so it cannot be forwarded to the backend
+	assert!(!req.headers().contains_key(TIMEOUT_HEADER));
+}
+
+#[test]
+fn header_override_not_configured() {
+	// Without any override configured, the header is ignored, but still stripped
+	let mut req = timeout_request("10.1.2.3", "5000");
+	Policy::default().apply_header_override(&mut req);
+	assert_eq!(req.extensions().get::<TimeoutOverride>(), None);
+	assert!(!req.headers().contains_key(TIMEOUT_HEADER));
+}
+
+#[test]
+fn header_override_trusted_role() {
+	let claims = |v: serde_json::Value| {
+		let serde_json::Value::Object(inner) = v else {
+			unreachable!()
+		};
+		Claims {
+			inner,
+			jwt: Default::default(),
+		}
+	};
+
+	let mut req = timeout_request("192.168.1.1", "5000");
+	req.extensions_mut().insert(claims(
+		serde_json::json!({"sub": "job", "roles": ["reader", "batch"]}),
+	));
+	header_override_policy().apply_header_override(&mut req);
+	assert_eq!(
+		req.extensions().get::<TimeoutOverride>(),
+		Some(&TimeoutOverride(Duration::from_secs(5)))
+	);
+
+	let mut req = timeout_request("192.168.1.1", "5000");
+	req
+		.extensions_mut()
+		.insert(claims(serde_json::json!({"sub": "user", "role": "reader"})));
+	header_override_policy().apply_header_override(&mut req);
+	assert_eq!(req.extensions().get::<TimeoutOverride>(), None);
+}
@@ -88,10 +88,24 @@ pub struct RawConfig {
 
 	worker_threads: Option<StringOrInt>,
 
+	// Proxies, in CIDR notation, trusted to report the client IP in X-Forwarded-For or Forwarded.
+	trusted_proxies: Option<Vec<ipnet::IpNet>>,
+
 	tracing: Option<RawTracing>,
 	logging: Option<RawLogging>,
 
 	http2: Option<RawHTTP2>,
+
+	dns: Option<RawDNS>,
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct RawDNS {
+	// Keep serving a hostname's last resolved addresses for this long past their TTL if resolving it
+	// fails.
+	#[serde(default, with = "serde_dur_option")]
+	serve_stale: Option<Duration>,
 }
 
 #[derive(serde::Deserialize, Clone, Debug)]
@@ -108,6 +122,10 @@ pub struct RawHTTP2 {
 #[serde(rename_all = "camelCase")]
 pub struct RawTracing {
 	otlp_endpoint: String,
+	#[serde(default)]
+	always_trace: bool,
+	sampling_ratio: Option<f64>,
+	resource_attributes: Option<BTreeMap<String, String>>,
 	fields: Option<RawLoggingFields>,
 }
 
@@ -116,6 +134,7 @@ pub struct RawTracing {
 pub struct RawLogging {
 	filter: Option<String>,
 	fields: Option<RawLoggingFields>,
+	format: Option<crate::telemetry::log::Format>,
 }
 
 #[derive(serde::Deserialize, Clone, Debug)]
@@ -123,6 +142,7 @@ pub struct RawLogging {
 pub struct RawLoggingFields {
 	#[serde(default)]
 	remove: Vec<String>,
+	include: Option<Vec<String>>,
 	#[serde(default)]
 	add: IndexMap<String, String>,
 }
@@ -181,6 +201,9 @@ pub struct Config {
 	pub ca: Option<caclient::Config>,
 	pub tracing: trc::Config,
 	pub logging: crate::telemetry::log::Config,
+	/// Proxies trusted to report the client IP in X-Forwarded-For or Forwarded. If empty, the client
+	/// IP is the IP of the direct peer.
+	pub trusted_proxies: Vec<ipnet::IpNet>,
 	pub dns: client::Config,
 	pub proxy_metadata: ProxyMetadata,
 	pub threading_mode: ThreadingMode,
@@ -257,6 +280,7 @@ pub struct ProxyInputs {
 
 	mcp_state: mcp::sse::App,
 	ca: Option<Arc<CaClient>>,
+	coalescer: http::coalesce::Coalescer,
 }
 
 #[derive(Debug, Clone, Copy, serde::Serialize)]
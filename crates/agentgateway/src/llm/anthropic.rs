@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use agent_core::prelude::Strng;
 use agent_core::strng;
 use bytes::Bytes;
@@ -19,7 +21,7 @@ use crate::{llm, parse, *};
 #[cfg_attr(feature = "schema", derive(JsonSchema))]
 pub struct Provider {
 	#[serde(default, skip_serializing_if = "Option::is_none")]
-	model: Option<Strng>,
+	pub model: Option<Strng>,
 }
 
 impl super::Provider for Provider {
@@ -56,8 +58,9 @@ impl Provider {
 			let mut model = String::new();
 			let mut created = chrono::Utc::now().timestamp();
 			let mut current_content = String::new();
-			let mut finish_reason = None;
 			let mut input_tokens = 0;
+			// Maps the index of tool_use content blocks to the index of their OpenAI tool call
+			let mut tool_calls = HashMap::<usize, i64>::new();
 			// https://docs.anthropic.com/en/docs/build-with-claude/streaming
 			parse::sse::json_transform::<MessagesStreamEvent, universal::ChatCompletionStreamResponse>(
 				b,
@@ -91,12 +94,32 @@ impl Provider {
 							None
 						},
 
+						MessagesStreamEvent::ContentBlockStart {
+							index,
+							content_block: types::ContentBlock::ToolUse { id, name, .. },
+						} => {
+							// The input is always empty here, and is streamed in the following deltas
+							let tool_index = tool_calls.len() as i64;
+							tool_calls.insert(index, tool_index);
+							let call = universal::ToolCallDelta {
+								index: tool_index,
+								id: Some(id),
+								r#type: Some("function".to_string()),
+								function: Some(universal::ToolCallFunction {
+									name: Some(name),
+									arguments: Some(String::new()),
+								}),
+							};
+							mk(vec![tool_call_choice(call)], None)
+						},
 						MessagesStreamEvent::ContentBlockStart { .. } => {
-							// There is never(?) any content here
+							// Text blocks start empty, with the text in the following deltas
 							None
 						},
-						MessagesStreamEvent::ContentBlockDelta { delta, .. } => {
-							let ContentBlockDelta::TextDelta { text } = delta;
+						MessagesStreamEvent::ContentBlockDelta {
+							delta: ContentBlockDelta::TextDelta { text },
+							..
+						} => {
 							let choice = universal::ChatCompletionChoiceStream {
 								index: 0,
 								delta: universal::ChatCompletionMessageForResponseDelta {
@@ -110,20 +133,45 @@ impl Provider {
 							};
 							mk(vec![choice], None)
 						},
+						MessagesStreamEvent::ContentBlockDelta {
+							index,
+							delta: ContentBlockDelta::InputJsonDelta { partial_json },
+						} => {
+							let call = universal::ToolCallDelta {
+								index: *tool_calls.get(&index)?,
+								id: None,
+								r#type: None,
+								function: Some(universal::ToolCallFunction {
+									name: None,
+									arguments: Some(partial_json),
+								}),
+							};
+							mk(vec![tool_call_choice(call)], None)
+						},
 						MessagesStreamEvent::MessageDelta { usage, delta } => {
-							finish_reason = delta.stop_reason.map(|reason| match reason {
-								types::StopReason::EndTurn => universal::FinishReason::stop,
-								types::StopReason::MaxTokens => universal::FinishReason::length,
-								types::StopReason::StopSequence => universal::FinishReason::stop,
-							});
+							let finish_reason = delta.stop_reason.map(translate_stop_reason);
 							log.non_atomic_mutate(|r| {
 								r.output_tokens = Some(usage.output_tokens as u64);
 								if let Some(inp) = r.input_tokens_from_response {
 									r.total_tokens = Some(inp + usage.output_tokens as u64)
 								}
 							});
+							let choices = finish_reason
+								.map(|finish_reason| universal::ChatCompletionChoiceStream {
+									index: 0,
+									delta: universal::ChatCompletionMessageForResponseDelta {
+										role: None,
+										content: None,
+										refusal: None,
+										name: None,
+										tool_calls: None,
+									},
+									finish_reason: Some(finish_reason),
+								})
+								.into_iter()
+								.collect();
 							mk(
-								vec![],
+								choices,
 								Some(universal::Usage {
 									prompt_tokens: usage.output_tokens as i32,
 									completion_tokens: input_tokens as i32,
@@ -150,6 +198,20 @@ impl Provider {
 	}
 }
 
+fn tool_call_choice(call: universal::ToolCallDelta) -> ChatCompletionChoiceStream {
+	ChatCompletionChoiceStream {
+		index: 0,
+		delta: universal::ChatCompletionMessageForResponseDelta {
+			role: None,
+			content: None,
+			refusal: None,
+			name: None,
+			tool_calls: Some(vec![call]),
+		},
+		finish_reason: None,
+	}
+}
+
 pub(super) fn translate_error(
 	resp: MessagesErrorResponse,
 ) -> Result<universal::ChatCompletionErrorResponse, AIError> {
@@ -165,38 +227,49 @@ pub(super) fn translate_error(
 	})
 }
 
+fn translate_stop_reason(reason: types::StopReason) -> universal::FinishReason {
+	match reason {
+		types::StopReason::EndTurn => universal::FinishReason::stop,
+		types::StopReason::MaxTokens => universal::FinishReason::length,
+		types::StopReason::StopSequence => universal::FinishReason::stop,
+		types::StopReason::ToolUse => universal::FinishReason::tool_calls,
+	}
+}
+
 pub(super) fn translate_response(resp: MessagesResponse) -> universal::ChatCompletionResponse {
-	// Convert Anthropic content blocks to OpenAI message content
-	let choices = resp
-		.content
-		.iter()
-		.filter_map(|block| {
-			let text = match block {
-				types::ContentBlock::Text { text } => Some(text.clone()),
-				types::ContentBlock::Image { .. } => return None, // Skip images in response for now
-			};
-			let message = universal::ChatCompletionMessageForResponse {
-				role: universal::MessageRole::assistant,
-				content: text,
-				reasoning_content: None,
-				name: None,
-				tool_calls: None,
-			};
-			let finish_reason = resp.stop_reason.map(|reason| match reason {
-				types::StopReason::EndTurn => universal::FinishReason::stop,
-				types::StopReason::MaxTokens => universal::FinishReason::length,
-				types::StopReason::StopSequence => universal::FinishReason::stop,
-			});
-			// Only one choice for anthropic
-			let choice = universal::ChatCompletionChoice {
-				index: 0,
-				message,
-				finish_reason,
-				finish_details: None,
-			};
-			Some(choice)
-		})
-		.collect::<Vec<_>>();
+	// Anthropic splits the message into content blocks, while OpenAI has a single message with the
+	// text and any tool calls.
+	let mut text = None::<String>;
+	let mut tool_calls = vec![];
+	for block in resp.content {
+		match block {
+			types::ContentBlock::Text { text: t } => text.get_or_insert_default().push_str(&t),
+			types::ContentBlock::ToolUse { id, name, input } => tool_calls.push(universal::ToolCall {
+				id,
+				r#type: "function".to_string(),
+				function: universal::ToolCallFunction {
+					name: Some(name),
+					arguments: Some(input.to_string()),
+				},
+			}),
+			// Skip images in response for now. Tool results are only sent in requests.
+			types::ContentBlock::Image { .. } | types::ContentBlock::ToolResult { .. } => {},
+		}
+	}
+	let message = universal::ChatCompletionMessageForResponse {
+		role: universal::MessageRole::assistant,
+		content: text,
+		reasoning_content: None,
+		name: None,
+		tool_calls: (!tool_calls.is_empty()).then_some(tool_calls),
+	};
+	// Only one choice for anthropic
+	let choices = vec![universal::ChatCompletionChoice {
+		index: 0,
+		message,
+		finish_reason: resp.stop_reason.map(translate_stop_reason),
+		finish_details: None,
+	}];
 
 	// Convert usage from Anthropic format to OpenAI format
 	let usage = universal::Usage {
@@ -236,44 +309,62 @@ pub(super) fn translate_request(req: ChatCompletionRequest) -> types::MessagesRe
 		.join("\n");
 
 	// Convert messages to Anthropic format
-	let messages = req
-		.messages
-		.iter()
-		.filter(|msg| msg.role != universal::MessageRole::system)
-		.map(|msg| {
-			let role = match msg.role {
-				universal::MessageRole::user => types::Role::User,
-				universal::MessageRole::assistant => types::Role::Assistant,
-				_ => types::Role::User, // Default to user for other roles
-			};
-
-			let content = match &msg.content {
-				universal::Content::Text(text) => {
-					vec![types::ContentBlock::Text { text: text.clone() }]
-				},
-				universal::Content::ImageUrl(urls) => {
-					urls
-						.iter()
-						.map(|img_url| {
-							if let Some(url) = &img_url.image_url {
-								types::ContentBlock::Image {
-									source: url.url.clone(),
-									media_type: "image/jpeg".to_string(), // Default to JPEG
-									data: "".to_string(),                 // Base64 data would go here if using base64
-								}
-							} else {
-								types::ContentBlock::Text {
-									text: img_url.text.clone().unwrap_or_default(),
-								}
-							}
-						})
-						.collect()
-				},
-			};
+	let mut messages: Vec<types::Message> = vec![];
+	for msg in req.messages.iter() {
+		let (role, content) = match msg.role {
+			universal::MessageRole::system => continue,
+			universal::MessageRole::tool => {
+				// Tool results are sent back as part of a user message
+				let result = types::ContentBlock::ToolResult {
+					tool_use_id: msg.tool_call_id.clone().unwrap_or_default(),
+					content: content_text(&msg.content),
+				};
+				(types::Role::User, vec![result])
+			},
+			universal::MessageRole::assistant => {
+				let mut content = translate_content(&msg.content);
+				content.extend(msg.tool_calls.iter().flatten().map(|tc| {
+					types::ContentBlock::ToolUse {
+						id: tc.id.clone(),
+						name: tc.function.name.clone().unwrap_or_default(),
+						input: tc
+							.function
+							.arguments
+							.as_deref()
+							.and_then(|a| serde_json::from_str(a).ok())
+							.unwrap_or_else(|| Value::Object(Default::default())),
+					}
+				}));
+				(types::Role::Assistant, content)
+			},
+			_ => (types::Role::User, translate_content(&msg.content)), // Default to user for other roles
+		};
+		// Anthropic requires roles to alternate, so consecutive messages (such as multiple tool results)
+		// are merged.
+		match messages.last_mut() {
+			Some(last) if last.role == role => last.content.extend(content),
+			_ => messages.push(types::Message { role, content }),
+		}
+	}
 
-			types::Message { role, content }
+	let tools = req
+		.tools
+		.unwrap_or_default()
+		.into_iter()
+		.map(|t| types::Tool {
+			name: t.function.name,
+			description: t.function.description,
+			input_schema: t.function.parameters,
 		})
 		.collect();
+	let tool_choice = req.tool_choice.map(|tc| match tc {
+		universal::ToolChoiceType::None => types::ToolChoice::None,
+		universal::ToolChoiceType::Auto => types::ToolChoice::Auto,
+		universal::ToolChoiceType::Required => types::ToolChoice::Any,
+		universal::ToolChoiceType::ToolChoice { tool } => types::ToolChoice::Tool {
+			name: tool.function.name,
+		},
+	});
 
 	types::MessagesRequest {
 		messages,
@@ -285,6 +376,41 @@ pub(super) fn translate_request(req: ChatCompletionRequest) -> types::MessagesRe
 		temperature: req.temperature,
 		top_p: req.top_p,
 		top_k: None, // OpenAI doesn't have top_k
+		tools,
+		tool_choice,
+	}
+}
+
+fn content_text(content: &universal::Content) -> String {
+	match content {
+		universal::Content::Text(text) => text.clone(),
+		universal::Content::ImageUrl(parts) => parts.iter().filter_map(|p| p.text.as_deref()).join(""),
+	}
+}
+
+fn translate_content(content: &universal::Content) -> Vec<types::ContentBlock> {
+	match content {
+		// Anthropic rejects empty text blocks, which OpenAI uses for tool call only messages
+		universal::Content::Text(text) if text.is_empty() => vec![],
+		universal::Content::Text(text) => {
+			vec![types::ContentBlock::Text { text: text.clone() }]
+		},
+		universal::Content::ImageUrl(urls) => urls
+			.iter()
+			.map(|img_url| {
+				if let Some(url) = &img_url.image_url {
+					types::ContentBlock::Image {
+						source: url.url.clone(),
+						media_type: "image/jpeg".to_string(), // Default to JPEG
+						data: "".to_string(),                 // Base64 data would go here if using base64
+					}
+				} else {
+					types::ContentBlock::Text {
+						text: img_url.text.clone().unwrap_or_default(),
+					}
+				}
+			})
+			.collect(),
 	}
 }
 
@@ -312,6 +438,39 @@ pub(super) mod types {
 			media_type: String,
 			data: String,
 		},
+		ToolUse {
+			id: String,
+			name: String,
+			input: serde_json::Value,
+		},
+		ToolResult {
+			tool_use_id: String,
+			content: String,
+		},
+	}
+
+	/// A tool the model may use.
+	#[derive(Clone, Serialize, Debug, PartialEq)]
+	pub struct Tool {
+		pub name: String,
+		#[serde(skip_serializing_if = "Option::is_none")]
+		pub description: Option<String>,
+		/// JSON schema for the tool input.
+		pub input_schema: serde_json::Value,
+	}
+
+	/// How the model should use the provided tools.
+	#[derive(Clone, Serialize, Debug, PartialEq, Eq)]
+	#[serde(rename_all = "snake_case", tag = "type")]
+	pub enum ToolChoice {
+		/// The model decides whether to use tools.
+		Auto,
+		/// The model must use one of the tools.
+		Any,
+		/// The model must use the named tool.
+		Tool { name: String },
+		/// The model must not use tools.
+		None,
 	}
 
 	#[derive(Clone, Serialize, Debug, PartialEq, Eq)]
@@ -358,6 +517,12 @@ pub(super) mod types {
 		/// Recommended for advanced use cases only. You usually only need to use temperature.
 		#[serde(skip_serializing_if = "Option::is_none")]
 		pub top_k: Option<usize>,
+		/// Definitions of tools that the model may use.
+		#[serde(skip_serializing_if = "Vec::is_empty")]
+		pub tools: Vec<Tool>,
+		/// How the model should use the provided tools.
+		#[serde(skip_serializing_if = "Option::is_none")]
+		pub tool_choice: Option<ToolChoice>,
 	}
 
 	/// Response body for the Messages API.
@@ -449,6 +614,7 @@ pub(super) mod types {
 	#[serde(rename_all = "snake_case", tag = "type")]
 	pub enum ContentBlockDelta {
 		TextDelta { text: String },
+		InputJsonDelta { partial_json: String },
 	}
 
 	#[derive(Clone, Serialize, Deserialize, Debug, Eq, PartialEq)]
@@ -498,6 +664,8 @@ pub(super) mod types {
 		MaxTokens,
 		/// One of the provided custom stop_sequences was generated.
 		StopSequence,
+		/// The model invoked one or more tools.
+		ToolUse,
 	}
 
 	/// Billing and rate-limit usage.
@@ -1,12 +1,22 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use ::http::uri::PathAndQuery;
+use ::http::{HeaderValue, header};
 use agent_core::prelude::Strng;
 use agent_core::strng;
 use bytes::Bytes;
 use chrono;
 use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
 
+use crate::http::Response;
 use crate::llm::bedrock::types::{ConverseErrorResponse, ConverseRequest, ConverseResponse};
 use crate::llm::universal::ChatCompletionRequest;
-use crate::llm::{AIError, universal};
+use crate::llm::{AIError, LLMResponse, amend_tokens, universal};
+use crate::parse::eventstream;
+use crate::telemetry::log::AsyncLog;
 use crate::*;
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -32,6 +42,21 @@ impl Provider {
 		Ok(bedrock_request)
 	}
 
+	/// Streaming requests use the ConverseStream API, which shares the request format with Converse.
+	pub async fn process_streaming_request(
+		&self,
+		req: universal::ChatCompletionRequest,
+		parts: &mut ::http::request::Parts,
+	) -> Result<ConverseRequest, AIError> {
+		let path = self.get_streaming_path_for_model();
+		http::modify_uri(parts, |uri| {
+			uri.path_and_query = Some(PathAndQuery::from_str(&path)?);
+			Ok(())
+		})
+		.map_err(|_| AIError::UnknownModel)?;
+		self.process_request(req).await
+	}
+
 	pub async fn process_response(
 		&self,
 		bytes: &Bytes,
@@ -41,6 +66,163 @@ impl Provider {
 		translate_response(resp, &self.model)
 	}
 
+	/// Translates the ConverseStream response, which uses the AWS event stream encoding, into OpenAI
+	/// style SSE chunks.
+	pub async fn process_streaming(
+		&self,
+		log: AsyncLog<LLMResponse>,
+		include_completion_in_log: bool,
+		rate_limit: Vec<http::localratelimit::RateLimit>,
+		resp: Response,
+	) -> Response {
+		if !resp.status().is_success() {
+			// Errors are plain JSON rather than an event stream, so they are returned as is
+			return resp;
+		}
+		let model = self.model.clone();
+		let created = chrono::Utc::now().timestamp();
+		// Generate a unique ID since it's not provided in the response
+		let id = format!("bedrock-{}", chrono::Utc::now().timestamp_millis());
+		let mut completion = include_completion_in_log.then(String::new);
+		// Maps the index of tool use content blocks to the index of their OpenAI tool call
+		let mut tool_calls = HashMap::<usize, i64>::new();
+		log.non_atomic_mutate(|r| r.provider_model = Some(model.clone()));
+		let (mut parts, body) = resp.into_parts();
+		parts.headers.insert(
+			header::CONTENT_TYPE,
+			HeaderValue::from_static("text/event-stream"),
+		);
+		parts.headers.remove(header::CONTENT_LENGTH);
+		let body = eventstream::transform_to_sse(body, move |msg| {
+			let mk = |delta: universal::ChatCompletionMessageForResponseDelta,
+			          finish_reason: Option<universal::FinishReason>| {
+				StreamEvent::Chunk(universal::ChatCompletionStreamResponse {
+					id: Some(id.clone()),
+					object: "chat.completion.chunk".to_string(),
+					created,
+					model: model.to_string(),
+					choices: vec![universal::ChatCompletionChoiceStream {
+						index: 0,
+						delta,
+						finish_reason,
+					}],
+					usage: None,
+					system_fingerprint: None,
+				})
+			};
+			let delta = |role, content| universal::ChatCompletionMessageForResponseDelta {
+				role,
+				content,
+				refusal: None,
+				name: None,
+				tool_calls: None,
+			};
+			let tool_call_delta = |call| universal::ChatCompletionMessageForResponseDelta {
+				role: None,
+				content: None,
+				refusal: None,
+				name: None,
+				tool_calls: Some(vec![call]),
+			};
+			if msg.header(":message-type") != Some("event") {
+				// Errors are sent as exceptions, with the message in the payload
+				let message = serde_json::from_slice::<ConverseErrorResponse>(&msg.payload)
+					.map(|e| e.message)
+					.unwrap_or_else(|_| String::from_utf8_lossy(&msg.payload).to_string());
+				return Some(StreamEvent::Error(universal::ChatCompletionErrorResponse {
+					event_id: None,
+					error: universal::ChatCompletionError {
+						r#type: "invalid_request_error".to_string(),
+						message,
+						param: None,
+						code: msg.header(":exception-type").map(str::to_string),
+						event_id: None,
+					},
+				}));
+			}
+			match msg.header(":event-type")? {
+				"messageStart" => Some(mk(
+					delta(Some(universal::MessageRole::assistant), None),
+					None,
+				)),
+				"contentBlockStart" => {
+					// Only tool use blocks have a start event; the input is streamed in the following deltas
+					let event = decode_event::<types::ContentBlockStartEvent>(&msg.payload)?;
+					let tool_use = event.start.tool_use?;
+					let index = tool_calls.len() as i64;
+					tool_calls.insert(event.content_block_index, index);
+					let call = universal::ToolCallDelta {
+						index,
+						id: Some(tool_use.tool_use_id),
+						r#type: Some("function".to_string()),
+						function: Some(universal::ToolCallFunction {
+							name: Some(tool_use.name),
+							arguments: Some(String::new()),
+						}),
+					};
+					Some(mk(tool_call_delta(call), None))
+				},
+				"contentBlockDelta" => {
+					let event = decode_event::<types::ContentBlockDeltaEvent>(&msg.payload)?;
+					if let Some(tool_use) = event.delta.tool_use {
+						let call = universal::ToolCallDelta {
+							index: *tool_calls.get(&event.content_block_index)?,
+							id: None,
+							r#type: None,
+							function: Some(universal::ToolCallFunction {
+								name: None,
+								arguments: Some(tool_use.input),
+							}),
+						};
+						return Some(mk(tool_call_delta(call), None));
+					}
+					let text = event.delta.text?;
+					if let Some(c) = completion.as_mut() {
+						c.push_str(&text);
+					}
+					Some(mk(delta(None, Some(text)), None))
+				},
+				"messageStop" => {
+					let event = decode_event::<types::MessageStopEvent>(&msg.payload)?;
+					Some(mk(
+						delta(None, None),
+						Some(translate_stop_reason(event.stop_reason)),
+					))
+				},
+				"metadata" => {
+					// Usage comes in a final event after the message stops
+					let usage = decode_event::<types::MetadataEvent>(&msg.payload)?.usage?;
+					log.non_atomic_mutate(|r| {
+						r.input_tokens_from_response = Some(usage.input_tokens as u64);
+						r.output_tokens = Some(usage.output_tokens as u64);
+						r.total_tokens = Some(usage.total_tokens as u64);
+						if let Some(c) = completion.take() {
+							r.completion = Some(vec![c]);
+						}
+						amend_tokens(rate_limit.as_slice(), r);
+					});
+					Some(StreamEvent::Chunk(
+						universal::ChatCompletionStreamResponse {
+							id: Some(id.clone()),
+							object: "chat.completion.chunk".to_string(),
+							created,
+							model: model.to_string(),
+							choices: vec![],
+							usage: Some(universal::Usage {
+								prompt_tokens: usage.input_tokens as i32,
+								completion_tokens: usage.output_tokens as i32,
+								total_tokens: usage.total_tokens as i32,
+							}),
+							system_fingerprint: None,
+						},
+					))
+				},
+				_ => None,
+			}
+		});
+		Response::from_parts(parts, body)
+	}
+
 	pub async fn process_error(
 		&self,
 		bytes: &Bytes,
@@ -53,11 +235,39 @@ impl Provider {
 	pub fn get_path_for_model(&self) -> Strng {
 		strng::format!("/model/{}/converse", self.model)
 	}
+	pub fn get_streaming_path_for_model(&self) -> Strng {
+		strng::format!("/model/{}/converse-stream", self.model)
+	}
 	pub fn get_host(&self) -> Strng {
 		strng::format!("bedrock-runtime.{}.amazonaws.com", self.region)
 	}
 }
 
+#[derive(Serialize)]
+#[serde(untagged)]
+enum StreamEvent {
+	Chunk(universal::ChatCompletionStreamResponse),
+	Error(universal::ChatCompletionErrorResponse),
+}
+
+fn decode_event<T: DeserializeOwned>(payload: &Bytes) -> Option<T> {
+	serde_json::from_slice(payload)
+		.inspect_err(|e| debug!("failed to parse streaming event: {e}"))
+		.ok()
+}
+
+fn translate_stop_reason(reason: types::StopReason) -> universal::FinishReason {
+	match reason {
+		types::StopReason::EndTurn => universal::FinishReason::stop,
+		types::StopReason::MaxTokens => universal::FinishReason::length,
+		types::StopReason::StopSequence => universal::FinishReason::stop,
+		types::StopReason::ToolUse => universal::FinishReason::tool_calls,
+		types::StopReason::GuardrailIntervened | types::StopReason::ContentFiltered => {
+			universal::FinishReason::content_filter
+		},
+	}
+}
+
 pub(super) fn translate_error(
 	resp: ConverseErrorResponse,
 ) -> Result<universal::ChatCompletionErrorResponse, AIError> {
@@ -86,37 +296,39 @@ pub(super) fn translate_response(
 		types::ConverseOutput::Unknown => return Err(AIError::IncompleteResponse),
 	};
 
-	// Convert Bedrock content blocks to OpenAI message content
-	let choices = message
-		.content
-		.iter()
-		.filter_map(|block| {
-			let text = match block {
-				types::ContentBlock::Text(text) => Some(text.clone()),
-				types::ContentBlock::Image { .. } => return None, // Skip images in response for now
-			};
-			let message = universal::ChatCompletionMessageForResponse {
-				role: universal::MessageRole::assistant,
-				content: text,
-				reasoning_content: None,
-				name: None,
-				tool_calls: None,
-			};
-			let finish_reason = Some(match resp.stop_reason {
-				types::StopReason::EndTurn => universal::FinishReason::stop,
-				types::StopReason::MaxTokens => universal::FinishReason::length,
-				types::StopReason::StopSequence => universal::FinishReason::stop,
-			});
-			// Only one choice for Bedrock
-			let choice = universal::ChatCompletionChoice {
-				index: 0,
-				message,
-				finish_reason,
-				finish_details: None,
-			};
-			Some(choice)
-		})
-		.collect::<Vec<_>>();
+	// Bedrock splits the message into content blocks, while OpenAI has a single message with the text
+	// and any tool calls.
+	let mut text = None::<String>;
+	let mut tool_calls = vec![];
+	for block in message.content {
+		match block {
+			types::ContentBlock::Text(t) => text.get_or_insert_default().push_str(&t),
+			types::ContentBlock::ToolUse(tool_use) => tool_calls.push(universal::ToolCall {
+				id: tool_use.tool_use_id,
+				r#type: "function".to_string(),
+				function: universal::ToolCallFunction {
+					name: Some(tool_use.name),
+					arguments: Some(tool_use.input.to_string()),
+				},
+			}),
+			// Skip images in response for now. Tool results are only sent in requests.
+			types::ContentBlock::Image { .. } | types::ContentBlock::ToolResult(_) => {},
+		}
+	}
+	let message = universal::ChatCompletionMessageForResponse {
+		role: universal::MessageRole::assistant,
+		content: text,
+		reasoning_content: None,
+		name: None,
+		tool_calls: (!tool_calls.is_empty()).then_some(tool_calls),
+	};
+	// Only one choice for Bedrock
+	let choices = vec![universal::ChatCompletionChoice {
+		index: 0,
+		message,
+		finish_reason: Some(translate_stop_reason(resp.stop_reason)),
+		finish_details: None,
+	}];
 
 	// Convert usage from Bedrock format to OpenAI format
 	let usage = if let Some(token_usage) = resp.usage {
@@ -167,42 +379,45 @@ pub(super) fn translate_request(req: ChatCompletionRequest) -> types::ConverseRe
 		.join("\n");
 
 	// Convert messages to Bedrock format
-	let messages = req
-		.messages
-		.iter()
-		.filter(|msg| msg.role != universal::MessageRole::system)
-		.map(|msg| {
-			let role = match msg.role {
-				universal::MessageRole::user => types::Role::User,
-				universal::MessageRole::assistant => types::Role::Assistant,
-				_ => types::Role::User, // Default to user for other roles
-			};
-
-			let content = match &msg.content {
-				universal::Content::Text(text) => {
-					vec![types::ContentBlock::Text(text.clone())]
-				},
-				universal::Content::ImageUrl(urls) => {
-					urls
-						.iter()
-						.map(|img_url| {
-							if let Some(url) = &img_url.image_url {
-								types::ContentBlock::Image {
-									source: url.url.clone(),
-									media_type: "image/jpeg".to_string(), // Default to JPEG
-									data: "".to_string(),                 // Base64 data would go here if using base64
-								}
-							} else {
-								types::ContentBlock::Text(img_url.text.clone().unwrap_or_default())
-							}
-						})
-						.collect()
-				},
-			};
-
-			types::Message { role, content }
-		})
-		.collect();
+	let mut messages: Vec<types::Message> = vec![];
+	for msg in req.messages.iter() {
+		let (role, content) = match msg.role {
+			universal::MessageRole::system => continue,
+			universal::MessageRole::tool => {
+				// Tool results are sent back as part of a user message
+				let result = types::ContentBlock::ToolResult(types::ToolResultBlock {
+					tool_use_id: msg.tool_call_id.clone().unwrap_or_default(),
+					content: vec![types::ToolResultContentBlock::Text(content_text(
+						&msg.content,
+					))],
+				});
+				(types::Role::User, vec![result])
+			},
+			universal::MessageRole::assistant => {
+				let mut content = translate_content(&msg.content);
+				content.extend(msg.tool_calls.iter().flatten().map(|tc| {
+					types::ContentBlock::ToolUse(types::ToolUseBlock {
+						tool_use_id: tc.id.clone(),
+						name: tc.function.name.clone().unwrap_or_default(),
+						input: tc
+							.function
+							.arguments
+							.as_deref()
+							.and_then(|a| serde_json::from_str(a).ok())
+							.unwrap_or_else(|| Value::Object(Default::default())),
+					})
+				}));
+				(types::Role::Assistant, content)
+			},
+			_ => (types::Role::User, translate_content(&msg.content)), // Default to user for other roles
+		};
+		// Bedrock requires roles to alternate, so consecutive messages (such as multiple tool results)
+		// are merged.
+		match messages.last_mut() {
+			Some(last) if last.role == role => last.content.extend(content),
+			_ => messages.push(types::Message { role, content }),
+		}
+	}
 
 	// Build inference configuration
 	let inference_config = types::InferenceConfiguration {
@@ -213,6 +428,31 @@ pub(super) fn translate_request(req: ChatCompletionRequest) -> types::ConverseRe
 		anthropic_version: None, // Not used for Bedrock
 	};
 
+	let tools = req
+		.tools
+		.unwrap_or_default()
+		.into_iter()
+		.map(|t| {
+			types::Tool::ToolSpec(types::ToolSpecification {
+				name: t.function.name,
+				description: t.function.description,
+				input_schema: types::ToolInputSchema::Json(t.function.parameters),
+			})
+		})
+		.collect::<Vec<_>>();
+	// Bedrock cannot be told not to use tools, so they are not sent at all
+	let tools_disabled = matches!(req.tool_choice, Some(universal::ToolChoiceType::None));
+	let tool_choice = match req.tool_choice {
+		Some(universal::ToolChoiceType::Auto) => Some(types::ToolChoice::Auto {}),
+		Some(universal::ToolChoiceType::Required) => Some(types::ToolChoice::Any {}),
+		Some(universal::ToolChoiceType::ToolChoice { tool }) => Some(types::ToolChoice::Tool {
+			name: tool.function.name,
+		}),
+		Some(universal::ToolChoiceType::None) | None => None,
+	};
+	let tool_config =
+		(!tools.is_empty() && !tools_disabled).then(|| types::ToolConfiguration { tools, tool_choice });
+
 	types::ConverseRequest {
 		model_id: req.model,
 		messages,
@@ -222,7 +462,7 @@ pub(super) fn translate_request(req: ChatCompletionRequest) -> types::ConverseRe
 			Some(vec![types::SystemContentBlock::Text { text: system }])
 		},
 		inference_config: Some(inference_config),
-		tool_config: None,      // TODO: Add tool support
+		tool_config,
 		guardrail_config: None, // TODO: Add guardrail support
 		additional_model_request_fields: None,
 		prompt_variables: None,
@@ -232,6 +472,40 @@ pub(super) fn translate_request(req: ChatCompletionRequest) -> types::ConverseRe
 	}
 }
 
+fn content_text(content: &universal::Content) -> String {
+	match content {
+		universal::Content::Text(text) => text.clone(),
+		universal::Content::ImageUrl(parts) => parts
+			.iter()
+			.filter_map(|p| p.text.as_deref())
+			.collect::<String>(),
+	}
+}
+
+fn translate_content(content: &universal::Content) -> Vec<types::ContentBlock> {
+	match content {
+		// Bedrock rejects empty text blocks, which OpenAI uses for tool call only messages
+		universal::Content::Text(text) if text.is_empty() => vec![],
+		universal::Content::Text(text) => {
+			vec![types::ContentBlock::Text(text.clone())]
+		},
+		universal::Content::ImageUrl(urls) => urls
+			.iter()
+			.map(|img_url| {
+				if let Some(url) = &img_url.image_url {
+					types::ContentBlock::Image {
+						source: url.url.clone(),
+						media_type: "image/jpeg".to_string(), // Default to JPEG
+						data: "".to_string(),                 // Base64 data would go here if using base64
+					}
+				} else {
+					types::ContentBlock::Text(img_url.text.clone().unwrap_or_default())
+				}
+			})
+			.collect(),
+	}
+}
+
 pub(super) mod types {
 	use std::collections::HashMap;
 
@@ -254,6 +528,33 @@ pub(super) mod types {
 			media_type: String,
 			data: String,
 		},
+		#[serde(rename = "toolUse")]
+		ToolUse(ToolUseBlock),
+		#[serde(rename = "toolResult")]
+		ToolResult(ToolResultBlock),
+	}
+
+	/// A tool call requested by the model.
+	#[derive(Clone, Deserialize, Serialize, Debug, PartialEq, Eq)]
+	#[serde(rename_all = "camelCase")]
+	pub struct ToolUseBlock {
+		pub tool_use_id: String,
+		pub name: String,
+		pub input: serde_json::Value,
+	}
+
+	/// The result of a tool call, sent back to the model.
+	#[derive(Clone, Deserialize, Serialize, Debug, PartialEq, Eq)]
+	#[serde(rename_all = "camelCase")]
+	pub struct ToolResultBlock {
+		pub tool_use_id: String,
+		pub content: Vec<ToolResultContentBlock>,
+	}
+
+	#[derive(Clone, Deserialize, Serialize, Debug, PartialEq, Eq)]
+	#[serde(rename_all = "snake_case")]
+	pub enum ToolResultContentBlock {
+		Text(String),
 	}
 
 	#[derive(Clone, Deserialize, Serialize, Debug, PartialEq, Eq)]
@@ -333,7 +634,44 @@ pub(super) mod types {
 
 	#[derive(Clone, Serialize, Debug, PartialEq)]
 	pub struct ToolConfiguration {
-		// TODO: Implement tool configuration
+		/// The tools that the model can use.
+		pub tools: Vec<Tool>,
+		/// How the model should use the tools.
+		#[serde(rename = "toolChoice", skip_serializing_if = "Option::is_none")]
+		pub tool_choice: Option<ToolChoice>,
+	}
+
+	#[derive(Clone, Serialize, Debug, PartialEq)]
+	pub enum Tool {
+		#[serde(rename = "toolSpec")]
+		ToolSpec(ToolSpecification),
+	}
+
+	#[derive(Clone, Serialize, Debug, PartialEq)]
+	pub struct ToolSpecification {
+		pub name: String,
+		#[serde(skip_serializing_if = "Option::is_none")]
+		pub description: Option<String>,
+		#[serde(rename = "inputSchema")]
+		pub input_schema: ToolInputSchema,
+	}
+
+	#[derive(Clone, Serialize, Debug, PartialEq)]
+	#[serde(rename_all = "snake_case")]
+	pub enum ToolInputSchema {
+		/// JSON schema for the tool input.
+		Json(serde_json::Value),
+	}
+
+	#[derive(Clone, Serialize, Debug, PartialEq, Eq)]
+	#[serde(rename_all = "snake_case")]
+	pub enum ToolChoice {
+		/// The model decides whether to use tools.
+		Auto {},
+		/// The model must use one of the tools.
+		Any {},
+		/// The model must use the named tool.
+		Tool { name: String },
 	}
 
 	#[derive(Clone, Serialize, Debug, PartialEq)]
@@ -425,5 +763,67 @@ pub(super) mod types {
 		MaxTokens,
 		/// One of the provided custom stop_sequences was generated.
 		StopSequence,
+		/// The model requested a tool call.
+		ToolUse,
+		/// A guardrail intervened.
+		GuardrailIntervened,
+		/// The response was filtered for its content.
+		ContentFiltered,
+	}
+
+	/// A chunk of content in a ConverseStream response.
+	#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+	#[serde(rename_all = "camelCase")]
+	pub struct ContentBlockDeltaEvent {
+		pub content_block_index: usize,
+		pub delta: ContentBlockDelta,
+	}
+
+	#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+	#[serde(rename_all = "camelCase")]
+	pub struct ContentBlockDelta {
+		pub text: Option<String>,
+		pub tool_use: Option<ToolUseDelta>,
+	}
+
+	/// A part of the JSON input of a tool call.
+	#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+	pub struct ToolUseDelta {
+		pub input: String,
+	}
+
+	/// The start of a content block in a ConverseStream response. Only tool use blocks have one.
+	#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+	#[serde(rename_all = "camelCase")]
+	pub struct ContentBlockStartEvent {
+		pub content_block_index: usize,
+		pub start: ContentBlockStart,
+	}
+
+	#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+	#[serde(rename_all = "camelCase")]
+	pub struct ContentBlockStart {
+		pub tool_use: Option<ToolUseStart>,
+	}
+
+	#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+	#[serde(rename_all = "camelCase")]
+	pub struct ToolUseStart {
+		pub tool_use_id: String,
+		pub name: String,
+	}
+
+	/// The end of the message in a ConverseStream response.
+	#[derive(Copy, Clone, Debug, Deserialize, PartialEq, Eq)]
+	#[serde(rename_all = "camelCase")]
+	pub struct MessageStopEvent {
+		pub stop_reason: StopReason,
+	}
+
+	/// The final event in a ConverseStream response.
+	#[derive(Copy, Clone, Debug, Deserialize, PartialEq, Eq)]
+	pub struct MetadataEvent {
+		pub usage: Option<TokenUsage>,
+		pub metrics: Option<ConverseMetrics>,
 	}
 }
@@ -1,7 +1,15 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use ::http::HeaderValue;
+use ::http::uri::PathAndQuery;
 use agent_core::strng;
 use agent_core::strng::Strng;
 use anyhow::anyhow;
+use axum_extra::headers::authorization::Bearer;
 use bytes::Bytes;
+use headers::HeaderMapExt;
+use itertools::Itertools;
 use serde_json::Value;
 use tiktoken_rs::CoreBPE;
 use tiktoken_rs::tokenizer::{Tokenizer, get_tokenizer};
@@ -11,16 +19,17 @@ use crate::http::{Body, Request, Response};
 use crate::llm::bedrock::translate_error;
 use crate::llm::bedrock::types::ConverseErrorResponse;
 use crate::llm::universal::ChatCompletionRequest;
-use crate::llm::{AIError, AIProvider, LLMRequest};
+use crate::llm::{AIError, AIProvider, LLMRequest, amend_tokens};
 use crate::proxy::ProxyError;
+use crate::telemetry::log::AsyncLog;
 use crate::*;
 
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "camelCase")]
 #[cfg_attr(feature = "schema", derive(JsonSchema))]
 pub struct Provider {
 	#[serde(default, skip_serializing_if = "Option::is_none")]
-	model: Option<Strng>,
+	pub model: Option<Strng>,
 }
 
 impl super::Provider for Provider {
@@ -29,6 +38,8 @@ impl super::Provider for Provider {
 pub const DEFAULT_HOST_STR: &str = "generativelanguage.googleapis.com";
 pub const DEFAULT_HOST: Strng = strng::literal!(DEFAULT_HOST_STR);
 pub const DEFAULT_PATH: &str = "/v1beta/openai/chat/completions";
+// All Google API keys start with this prefix
+const API_KEY_PREFIX: &str = "AIza";
 
 impl Provider {
 	pub async fn process_request(
@@ -41,6 +52,159 @@ impl Provider {
 		// Gemini compat mode is the same!
 		Ok(req)
 	}
+
+	/// Streaming requests use the native streamGenerateContent API rather than the OpenAI compatible
+	/// one, as it reports usage on every chunk. This rewrites the path and authentication to match.
+	pub fn process_streaming_request(
+		&self,
+		mut req: universal::ChatCompletionRequest,
+		parts: &mut ::http::request::Parts,
+	) -> Result<types::GenerateContentRequest, AIError> {
+		if let Some(model) = &self.model {
+			req.model = model.to_string();
+		}
+		let model = req.model.strip_prefix("models/").unwrap_or(&req.model);
+		let path = format!("/v1beta/models/{model}:streamGenerateContent?alt=sse");
+		http::modify_uri(parts, |uri| {
+			uri.path_and_query = Some(PathAndQuery::from_str(&path)?);
+			Ok(())
+		})
+		.map_err(|_| AIError::UnknownModel)?;
+		if let Some(authz) = parts
+			.headers
+			.typed_get::<headers::Authorization<Bearer>>()
+			.filter(|a| a.token().starts_with(API_KEY_PREFIX))
+		{
+			// The native API takes API keys in their own header. Other bearer tokens (such as GCP OAuth
+			// access tokens) are accepted as is.
+			parts.headers.remove(http::header::AUTHORIZATION);
+			let mut api_key = HeaderValue::from_str(authz.token())
+				.map_err(|_| AIError::MissingField("api key".into()))?;
+			api_key.set_sensitive(true);
+			parts.headers.insert("x-goog-api-key", api_key);
+		}
+		Ok(translate_request(req))
+	}
+
+	pub async fn process_streaming(
+		&self,
+		request_model: Strng,
+		log: AsyncLog<LLMResponse>,
+		include_completion_in_log: bool,
+		rate_limit: Vec<http::localratelimit::RateLimit>,
+		resp: Response,
+	) -> Response {
+		if !resp.status().is_success() {
+			// Errors are not streamed, so they are returned as is
+			return resp;
+		}
+		let created = chrono::Utc::now().timestamp();
+		let model = request_model.to_string();
+		let mut completion = include_completion_in_log.then(String::new);
+		let mut finished = false;
+		// Function calls are sent whole, each in a single chunk, so they only need a running index
+		let mut tool_calls = 0;
+		resp.map(|b| {
+			parse::sse::json_transform_with_done::<
+				types::GenerateContentResponse,
+				universal::ChatCompletionStreamResponse,
+			>(b, move |f| {
+				let f = match f {
+					Ok(f) => f,
+					Err(e) => {
+						debug!("failed to parse streaming response: {e}");
+						return None;
+					},
+				};
+				let model = f.model_version.unwrap_or_else(|| model.clone());
+				let mut done = false;
+				let choices = f
+					.candidates
+					.into_iter()
+					.map(|c| {
+						let parts = c.content.map(|c| c.parts).unwrap_or_default();
+						let text = parts
+							.iter()
+							.filter_map(|p| p.text.as_deref())
+							.collect::<String>();
+						let text = (!text.is_empty()).then_some(text);
+						if let (Some(completion), Some(text)) = (completion.as_mut(), &text) {
+							completion.push_str(text);
+						}
+						let calls = parts
+							.into_iter()
+							.filter_map(|p| p.function_call)
+							.map(|call| {
+								let index = tool_calls;
+								tool_calls += 1;
+								universal::ToolCallDelta {
+									index,
+									id: Some(call.id.unwrap_or_else(|| format!("call_{index}"))),
+									r#type: Some("function".to_string()),
+									function: Some(universal::ToolCallFunction {
+										name: Some(call.name),
+										arguments: Some(call.args.to_string()),
+									}),
+								}
+							})
+							.collect_vec();
+						// Gemini stops normally after function calls, while OpenAI has a dedicated reason
+						let finish_reason = c.finish_reason.map(|r| match r {
+							types::FinishReason::Stop if tool_calls > 0 => universal::FinishReason::tool_calls,
+							r => translate_finish_reason(r),
+						});
+						done |= finish_reason.is_some();
+						universal::ChatCompletionChoiceStream {
+							index: c.index,
+							delta: universal::ChatCompletionMessageForResponseDelta {
+								role: Some(universal::MessageRole::assistant),
+								content: text,
+								refusal: None,
+								name: None,
+								tool_calls: (!calls.is_empty()).then_some(calls),
+							},
+							finish_reason,
+						}
+					})
+					.collect_vec();
+				// Usage is cumulative, so each chunk replaces what we have so far
+				let usage = f.usage_metadata.map(|u| {
+					log.non_atomic_mutate(|r| {
+						r.provider_model = Some(strng::new(&model));
+						r.input_tokens_from_response = Some(u.prompt_token_count);
+						r.output_tokens = Some(u.candidates_token_count);
+						r.total_tokens = Some(u.total_token_count);
+					});
+					universal::Usage {
+						prompt_tokens: u.prompt_token_count as i32,
+						completion_tokens: u.candidates_token_count as i32,
+						total_tokens: u.total_token_count as i32,
+					}
+				});
+				if done && !finished {
+					// The final chunk has the final usage, so the token counts are now correct
+					finished = true;
+					log.non_atomic_mutate(|r| {
+						if let Some(c) = completion.take() {
+							r.completion = Some(vec![c]);
+						}
+						amend_tokens(rate_limit.as_slice(), r);
+					});
+				}
+				Some(universal::ChatCompletionStreamResponse {
+					id: f.response_id,
+					object: "chat.completion.chunk".to_string(),
+					created,
+					model,
+					choices,
+					// Match OpenAI, which only reports usage at the end of the stream
+					usage: usage.filter(|_| done),
+					system_fingerprint: None,
+				})
+			})
+		})
+	}
+
 	pub async fn process_response(
 		&self,
 		bytes: &Bytes,
@@ -59,3 +223,321 @@ impl Provider {
 		Ok(resp)
 	}
 }
+
+fn translate_finish_reason(reason: types::FinishReason) -> universal::FinishReason {
+	match reason {
+		types::FinishReason::Stop => universal::FinishReason::stop,
+		types::FinishReason::MaxTokens => universal::FinishReason::length,
+		types::FinishReason::Safety
+		| types::FinishReason::Recitation
+		| types::FinishReason::Blocklist
+		| types::FinishReason::ProhibitedContent
+		| types::FinishReason::Spii => universal::FinishReason::content_filter,
+		types::FinishReason::Other => universal::FinishReason::stop,
+	}
+}
+
+pub(super) fn translate_request(req: ChatCompletionRequest) -> types::GenerateContentRequest {
+	let text = |content: &universal::Content| match content {
+		universal::Content::Text(text) => text.clone(),
+		// Only text is supported, which is checked when the request is parsed
+		universal::Content::ImageUrl(_) => String::new(),
+	};
+	// Gemini has all system prompts in a single field. Join them
+	let system = req
+		.messages
+		.iter()
+		.filter(|msg| msg.role == universal::MessageRole::system)
+		.map(|msg| text(&msg.content))
+		.collect::<Vec<String>>()
+		.join("\n");
+	// Function responses are matched to their call by name rather than by id
+	let call_names = req
+		.messages
+		.iter()
+		.flat_map(|msg| msg.tool_calls.iter().flatten())
+		.filter_map(|tc| Some((tc.id.as_str(), tc.function.name.as_deref()?)))
+		.collect::<HashMap<_, _>>();
+	let contents = req
+		.messages
+		.iter()
+		.filter(|msg| msg.role != universal::MessageRole::system)
+		.map(|msg| {
+			let (role, parts) = match msg.role {
+				universal::MessageRole::tool => {
+					let name = msg
+						.tool_call_id
+						.as_deref()
+						.and_then(|id| call_names.get(id))
+						.copied()
+						.unwrap_or_default();
+					let part = types::Part {
+						function_response: Some(types::FunctionResponse {
+							name: name.to_string(),
+							response: serde_json::json!({ "content": text(&msg.content) }),
+						}),
+						..Default::default()
+					};
+					(types::Role::User, vec![part])
+				},
+				universal::MessageRole::assistant => {
+					let calls = msg.tool_calls.iter().flatten().map(|tc| types::Part {
+						function_call: Some(types::FunctionCall {
+							id: None,
+							name: tc.function.name.clone().unwrap_or_default(),
+							args: tc
+								.function
+								.arguments
+								.as_deref()
+								.and_then(|a| serde_json::from_str(a).ok())
+								.unwrap_or_else(|| Value::Object(Default::default())),
+						}),
+						..Default::default()
+					});
+					let text = text(&msg.content);
+					// Tool call only messages have no text
+					let parts = (!text.is_empty() || msg.tool_calls.is_none())
+						.then(|| types::Part {
+							text: Some(text),
+							..Default::default()
+						})
+						.into_iter()
+						.chain(calls)
+						.collect();
+					(types::Role::Model, parts)
+				},
+				_ => {
+					let part = types::Part {
+						text: Some(text(&msg.content)),
+						..Default::default()
+					};
+					(types::Role::User, vec![part]) // Default to user for other roles
+				},
+			};
+			types::Content {
+				role: Some(role),
+				parts,
+			}
+		})
+		.collect();
+	let tools = req
+		.tools
+		.unwrap_or_default()
+		.into_iter()
+		.map(|t| types::FunctionDeclaration {
+			name: t.function.name,
+			description: t.function.description,
+			parameters: t.function.parameters,
+		})
+		.collect_vec();
+	let tool_config = req.tool_choice.map(|tc| {
+		let (mode, allowed_function_names) = match tc {
+			universal::ToolChoiceType::None => (types::FunctionCallingMode::None, vec![]),
+			universal::ToolChoiceType::Auto => (types::FunctionCallingMode::Auto, vec![]),
+			universal::ToolChoiceType::Required => (types::FunctionCallingMode::Any, vec![]),
+			universal::ToolChoiceType::ToolChoice { tool } => {
+				(types::FunctionCallingMode::Any, vec![tool.function.name])
+			},
+		};
+		types::ToolConfig {
+			function_calling_config: types::FunctionCallingConfig {
+				mode,
+				allowed_function_names,
+			},
+		}
+	});
+	types::GenerateContentRequest {
+		contents,
+		system_instruction: (!system.is_empty()).then(|| types::Content {
+			role: None,
+			parts: vec![types::Part {
+				text: Some(system),
+				..Default::default()
+			}],
+		}),
+		generation_config: types::GenerationConfig {
+			temperature: req.temperature,
+			top_p: req.top_p,
+			candidate_count: req.n,
+			max_output_tokens: req.max_tokens,
+			stop_sequences: req.stop.unwrap_or_default(),
+			presence_penalty: req.presence_penalty,
+			frequency_penalty: req.frequency_penalty,
+			seed: req.seed,
+		},
+		tools: (!tools.is_empty())
+			.then(|| types::Tool {
+				function_declarations: tools,
+			})
+			.into_iter()
+			.collect(),
+		tool_config,
+	}
+}
+
+pub(super) mod types {
+	use serde::{Deserialize, Serialize};
+
+	use crate::serdes::is_default;
+
+	// https://ai.google.dev/api/generate-content
+	#[derive(Clone, Serialize, Debug)]
+	#[serde(rename_all = "camelCase")]
+	pub struct GenerateContentRequest {
+		pub contents: Vec<Content>,
+		#[serde(skip_serializing_if = "Option::is_none")]
+		pub system_instruction: Option<Content>,
+		#[serde(skip_serializing_if = "is_default")]
+		pub generation_config: GenerationConfig,
+		#[serde(skip_serializing_if = "Vec::is_empty")]
+		pub tools: Vec<Tool>,
+		#[serde(skip_serializing_if = "Option::is_none")]
+		pub tool_config: Option<ToolConfig>,
+	}
+
+	#[derive(Clone, Serialize, Debug, PartialEq)]
+	#[serde(rename_all = "camelCase")]
+	pub struct Tool {
+		pub function_declarations: Vec<FunctionDeclaration>,
+	}
+
+	#[derive(Clone, Serialize, Debug, PartialEq)]
+	pub struct FunctionDeclaration {
+		pub name: String,
+		#[serde(skip_serializing_if = "Option::is_none")]
+		pub description: Option<String>,
+		/// JSON schema for the function parameters.
+		pub parameters: serde_json::Value,
+	}
+
+	#[derive(Clone, Serialize, Debug, PartialEq)]
+	#[serde(rename_all = "camelCase")]
+	pub struct ToolConfig {
+		pub function_calling_config: FunctionCallingConfig,
+	}
+
+	#[derive(Clone, Serialize, Debug, PartialEq)]
+	#[serde(rename_all = "camelCase")]
+	pub struct FunctionCallingConfig {
+		pub mode: FunctionCallingMode,
+		#[serde(skip_serializing_if = "Vec::is_empty")]
+		pub allowed_function_names: Vec<String>,
+	}
+
+	#[derive(Copy, Clone, Serialize, Debug, PartialEq, Eq)]
+	#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+	pub enum FunctionCallingMode {
+		/// The model decides whether to call functions.
+		Auto,
+		/// The model must call one of the (allowed) functions.
+		Any,
+		/// The model must not call functions.
+		None,
+	}
+
+	#[derive(Copy, Clone, Deserialize, Serialize, Debug, PartialEq, Eq)]
+	#[serde(rename_all = "snake_case")]
+	pub enum Role {
+		User,
+		Model,
+	}
+
+	#[derive(Clone, Deserialize, Serialize, Debug, PartialEq)]
+	pub struct Content {
+		#[serde(default, skip_serializing_if = "Option::is_none")]
+		pub role: Option<Role>,
+		#[serde(default)]
+		pub parts: Vec<Part>,
+	}
+
+	#[derive(Clone, Deserialize, Serialize, Debug, Default, PartialEq)]
+	#[serde(rename_all = "camelCase")]
+	pub struct Part {
+		#[serde(default, skip_serializing_if = "Option::is_none")]
+		pub text: Option<String>,
+		#[serde(default, skip_serializing_if = "Option::is_none")]
+		pub function_call: Option<FunctionCall>,
+		#[serde(default, skip_serializing_if = "Option::is_none")]
+		pub function_response: Option<FunctionResponse>,
+	}
+
+	#[derive(Clone, Deserialize, Serialize, Debug, PartialEq)]
+	pub struct FunctionCall {
+		#[serde(default, skip_serializing_if = "Option::is_none")]
+		pub id: Option<String>,
+		pub name: String,
+		#[serde(default)]
+		pub args: serde_json::Value,
+	}
+
+	#[derive(Clone, Deserialize, Serialize, Debug, PartialEq)]
+	pub struct FunctionResponse {
+		pub name: String,
+		pub response: serde_json::Value,
+	}
+
+	#[derive(Clone, Serialize, Default, Debug, PartialEq)]
+	#[serde(rename_all = "camelCase")]
+	pub struct GenerationConfig {
+		#[serde(skip_serializing_if = "Option::is_none")]
+		pub temperature: Option<f64>,
+		#[serde(skip_serializing_if = "Option::is_none")]
+		pub top_p: Option<f64>,
+		#[serde(skip_serializing_if = "Option::is_none")]
+		pub candidate_count: Option<i64>,
+		#[serde(skip_serializing_if = "Option::is_none")]
+		pub max_output_tokens: Option<i64>,
+		#[serde(skip_serializing_if = "Vec::is_empty")]
+		pub stop_sequences: Vec<String>,
+		#[serde(skip_serializing_if = "Option::is_none")]
+		pub presence_penalty: Option<f64>,
+		#[serde(skip_serializing_if = "Option::is_none")]
+		pub frequency_penalty: Option<f64>,
+		#[serde(skip_serializing_if = "Option::is_none")]
+		pub seed: Option<i64>,
+	}
+
+	#[derive(Clone, Deserialize, Debug)]
+	#[serde(rename_all = "camelCase")]
+	pub struct GenerateContentResponse {
+		#[serde(default)]
+		pub candidates: Vec<Candidate>,
+		pub usage_metadata: Option<UsageMetadata>,
+		pub model_version: Option<String>,
+		pub response_id: Option<String>,
+	}
+
+	#[derive(Clone, Deserialize, Debug)]
+	#[serde(rename_all = "camelCase")]
+	pub struct Candidate {
+		pub content: Option<Content>,
+		pub finish_reason: Option<FinishReason>,
+		#[serde(default)]
+		pub index: i64,
+	}
+
+	#[derive(Copy, Clone, Deserialize, Debug, PartialEq, Eq)]
+	#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+	pub enum FinishReason {
+		Stop,
+		MaxTokens,
+		Safety,
+		Recitation,
+		Blocklist,
+		ProhibitedContent,
+		Spii,
+		#[serde(other)]
+		Other,
+	}
+
+	#[derive(Clone, Deserialize, Debug)]
+	#[serde(rename_all = "camelCase")]
+	pub struct UsageMetadata {
+		#[serde(default)]
+		pub prompt_token_count: u64,
+		#[serde(default)]
+		pub candidates_token_count: u64,
+		#[serde(default)]
+		pub total_token_count: u64,
+	}
+}
@@ -0,0 +1,264 @@
+use ::http::HeaderValue;
+use ::http::header::CONTENT_TYPE;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::client::Client;
+use crate::http::Response;
+use crate::http::auth::BackendAuth;
+use crate::llm::policy::PromptGuardResponse;
+use crate::llm::universal::{ChatCompletionRequest, ChatCompletionResponse, Content};
+use crate::*;
+
+/// The outcome of a guardrail check. Ordered from least to most restrictive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum GuardrailAction {
+	/// The content is allowed as-is.
+	Allow,
+	/// The content is allowed, after the offending parts were redacted in place.
+	Redact,
+	/// The content is not allowed.
+	Block,
+}
+
+/// A Guardrail checks prompts before they are sent to the provider, and completions before they
+/// are returned to the client.
+#[async_trait]
+pub trait Guardrail: Send + Sync {
+	async fn check_request(
+		&self,
+		client: &Client,
+		req: &mut ChatCompletionRequest,
+	) -> anyhow::Result<GuardrailAction>;
+
+	async fn check_response(
+		&self,
+		client: &Client,
+		resp: &mut ChatCompletionResponse,
+	) -> anyhow::Result<GuardrailAction>;
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Guardrails {
+	/// Guardrails to run, in order. Processing stops at the first one that blocks.
+	checks: Vec<GuardrailConfig>,
+	/// The response sent to the client when content is blocked.
+	#[serde(default)]
+	rejection: PromptGuardResponse,
+}
+
+impl Guardrails {
+	/// Runs the guardrails on a request, returning the response to send instead if it is blocked.
+	pub async fn check_request(
+		&self,
+		client: &Client,
+		req: &mut ChatCompletionRequest,
+	) -> anyhow::Result<Option<Response>> {
+		for g in &self.checks {
+			if g.as_guardrail().check_request(client, req).await? == GuardrailAction::Block {
+				return Ok(Some(self.rejection.as_response()));
+			}
+		}
+		Ok(None)
+	}
+
+	/// Runs the guardrails on a response, returning the response to send instead if it is blocked.
+	pub async fn check_response(
+		&self,
+		client: &Client,
+		resp: &mut ChatCompletionResponse,
+	) -> anyhow::Result<Option<Response>> {
+		for g in &self.checks {
+			if g.as_guardrail().check_response(client, resp).await? == GuardrailAction::Block {
+				return Ok(Some(self.rejection.as_response()));
+			}
+		}
+		Ok(None)
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum GuardrailConfig {
+	Denylist(Denylist),
+	Moderation(Moderation),
+}
+
+impl GuardrailConfig {
+	fn as_guardrail(&self) -> &dyn Guardrail {
+		match self {
+			GuardrailConfig::Denylist(d) => d,
+			GuardrailConfig::Moderation(m) => m,
+		}
+	}
+}
+
+/// Denylist matches content against a list of regular expressions.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Denylist {
+	#[serde(with = "serde_regex")]
+	patterns: Vec<regex::Regex>,
+	#[serde(default)]
+	action: DenylistAction,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DenylistAction {
+	/// Block content that matches any pattern.
+	#[default]
+	Block,
+	/// Replace the matches with `<redacted>`.
+	Redact,
+}
+
+impl Denylist {
+	fn check_text(&self, text: &mut String) -> GuardrailAction {
+		let mut action = GuardrailAction::Allow;
+		for p in &self.patterns {
+			if !p.is_match(text) {
+				continue;
+			}
+			match self.action {
+				DenylistAction::Block => return GuardrailAction::Block,
+				DenylistAction::Redact => {
+					*text = p.replace_all(text, "<redacted>").into_owned();
+					action = GuardrailAction::Redact;
+				},
+			}
+		}
+		action
+	}
+}
+
+#[async_trait]
+impl Guardrail for Denylist {
+	async fn check_request(
+		&self,
+		_: &Client,
+		req: &mut ChatCompletionRequest,
+	) -> anyhow::Result<GuardrailAction> {
+		let mut action = GuardrailAction::Allow;
+		for msg in &mut req.messages {
+			// Only text content is supported for LLM requests
+			if let Content::Text(text) = &mut msg.content {
+				action = action.max(self.check_text(text));
+			}
+		}
+		Ok(action)
+	}
+
+	async fn check_response(
+		&self,
+		_: &Client,
+		resp: &mut ChatCompletionResponse,
+	) -> anyhow::Result<GuardrailAction> {
+		let mut action = GuardrailAction::Allow;
+		for choice in &mut resp.choices {
+			if let Some(text) = &mut choice.message.content {
+				action = action.max(self.check_text(text));
+			}
+		}
+		Ok(action)
+	}
+}
+
+/// Moderation sends content to an OpenAI compatible moderation API, and blocks flagged content.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Moderation {
+	/// The moderation API to call. `https` URLs are verified against the system roots.
+	#[serde(default = "default_moderation_url", with = "http_serde::uri")]
+	url: ::http::Uri,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	model: Option<String>,
+	/// Authentication for the moderation API, such as an OpenAI API key.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	auth: Option<BackendAuth>,
+}
+
+fn default_moderation_url() -> ::http::Uri {
+	::http::Uri::from_static("https://api.openai.com/v1/moderations")
+}
+
+#[derive(Debug, Serialize)]
+struct ModerationRequest<'a> {
+	input: Vec<&'a str>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	model: Option<&'a str>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ModerationResponse {
+	results: Vec<ModerationResult>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ModerationResult {
+	flagged: bool,
+}
+
+impl Moderation {
+	async fn check(&self, client: &Client, input: Vec<&str>) -> anyhow::Result<GuardrailAction> {
+		if input.is_empty() {
+			return Ok(GuardrailAction::Allow);
+		}
+		let body = serde_json::to_vec(&ModerationRequest {
+			input,
+			model: self.model.as_deref(),
+		})?;
+		let mut req = ::http::Request::builder()
+			.uri(self.url.clone())
+			.method(http::Method::POST)
+			.header(CONTENT_TYPE, HeaderValue::from_static("application/json"))
+			.body(http::Body::from(body))?;
+		http::auth::apply_backend_auth(self.auth.as_ref(), &mut req).await?;
+		http::auth::apply_late_backend_auth(self.auth.as_ref(), &mut req).await?;
+		let res = client.simple_call(req).await?;
+		if !res.status().is_success() {
+			anyhow::bail!("moderation request failed with status {}", res.status());
+		}
+		let bb = axum::body::to_bytes(res.into_body(), 2_097_152).await?;
+		let parsed = serde_json::from_slice::<ModerationResponse>(&bb)?;
+		Ok(if parsed.results.iter().any(|r| r.flagged) {
+			GuardrailAction::Block
+		} else {
+			GuardrailAction::Allow
+		})
+	}
+}
+
+#[async_trait]
+impl Guardrail for Moderation {
+	async fn check_request(
+		&self,
+		client: &Client,
+		req: &mut ChatCompletionRequest,
+	) -> anyhow::Result<GuardrailAction> {
+		let input = req
+			.messages
+			.iter()
+			.filter_map(|m| match &m.content {
+				Content::Text(t) if !t.is_empty() => Some(t.as_str()),
+				_ => None,
+			})
+			.collect();
+		self.check(client, input).await
+	}
+
+	async fn check_response(
+		&self,
+		client: &Client,
+		resp: &mut ChatCompletionResponse,
+	) -> anyhow::Result<GuardrailAction> {
+		let input = resp
+			.choices
+			.iter()
+			.filter_map(|c| c.message.content.as_deref())
+			.filter(|t| !t.is_empty())
+			.collect();
+		self.check(client, input).await
+	}
+}
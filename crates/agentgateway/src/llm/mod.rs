@@ -1,3 +1,4 @@
+use std::collections::{HashMap, HashSet};
 use std::str::FromStr;
 
 use ::http::uri::{Authority, PathAndQuery};
@@ -7,7 +8,7 @@ use agent_core::strng;
 use axum_extra::headers::authorization::Bearer;
 use headers::{Header, HeaderMapExt};
 use itertools::Itertools;
-pub use policy::Policy;
+pub use policy::{Policy, RateLimitRetry, WAIT_REMAINING_HEADER, reset_after};
 use serde_json::Value;
 use tiktoken_rs::CoreBPE;
 use tiktoken_rs::tokenizer::{Tokenizer, get_tokenizer};
@@ -26,15 +27,16 @@ use crate::telemetry::log::{AsyncLog, RequestLog};
 use crate::types::agent::{BackendName, Target};
 use crate::{client, *};
 
-mod anthropic;
-mod bedrock;
-mod gemini;
-mod openai;
+pub(crate) mod anthropic;
+pub(crate) mod bedrock;
+pub(crate) mod gemini;
+pub(crate) mod guardrail;
+pub(crate) mod openai;
 mod pii;
 mod policy;
 #[cfg(test)]
 mod tests;
-mod vertex;
+pub(crate) mod vertex;
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -65,6 +67,9 @@ pub struct LLMRequest {
 	pub request_model: Strng,
 	pub provider: Strng,
 	pub streaming: bool,
+	/// The request asks for a deterministic completion, so identical concurrent requests may share
+	/// a response.
+	pub deterministic: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -101,6 +106,8 @@ impl AIProvider {
 			a2a: None,
 			llm: None,
 			llm_provider: Some((self.clone(), true)),
+			connection_pool: None,
+			header_filter: None,
 		};
 		match self {
 			AIProvider::OpenAI(_) => (Target::Hostname(openai::DEFAULT_HOST, 443), btls),
@@ -112,6 +119,8 @@ impl AIProvider {
 					a2a: None,
 					llm: None,
 					llm_provider: Some((self.clone(), true)),
+					connection_pool: None,
+					header_filter: None,
 				};
 				(Target::Hostname(p.get_host(), 443), bp)
 			},
@@ -123,6 +132,8 @@ impl AIProvider {
 					a2a: None,
 					llm: None,
 					llm_provider: Some((self.clone(), true)),
+					connection_pool: None,
+					header_filter: None,
 				};
 				(Target::Hostname(p.get_host(), 443), bp)
 			},
@@ -235,9 +246,15 @@ impl AIProvider {
 		}
 		let resp_json = match self {
 			AIProvider::OpenAI(p) => serde_json::to_vec(&p.process_request(req).await?),
+			AIProvider::Gemini(p) if llm_info.streaming => {
+				serde_json::to_vec(&p.process_streaming_request(req, &mut parts)?)
+			},
 			AIProvider::Gemini(p) => serde_json::to_vec(&p.process_request(req).await?),
 			AIProvider::Vertex(p) => serde_json::to_vec(&p.process_request(req).await?),
 			AIProvider::Anthropic(p) => serde_json::to_vec(&p.process_request(req).await?),
+			AIProvider::Bedrock(p) if llm_info.streaming => {
+				serde_json::to_vec(&p.process_streaming_request(req, &mut parts).await?)
+			},
 			AIProvider::Bedrock(p) => serde_json::to_vec(&p.process_request(req).await?),
 		};
 		let body = resp_json.map_err(AIError::RequestMarshal)?;
@@ -249,6 +266,8 @@ impl AIProvider {
 
 	pub async fn process_response(
 		&self,
+		client: client::Client,
+		policies: Option<&Policy>,
 		req: LLMRequest,
 		rate_limit: Vec<http::localratelimit::RateLimit>,
 		log: AsyncLog<llm::LLMResponse>,
@@ -256,14 +275,18 @@ impl AIProvider {
 		resp: Response,
 	) -> Result<Response, AIError> {
 		if req.streaming {
-			return self
+			let resp = self
 				.process_streaming(req, rate_limit, log, include_completion_in_log, resp)
-				.await;
+				.await?;
+			return match policies.filter(|p| p.has_response_guardrails()) {
+				Some(p) => apply_streaming_guardrails(client, p, resp).await,
+				None => Ok(resp),
+			};
 		}
 		// Buffer the body, max 2mb
 		let (mut parts, body) = resp.into_parts();
 		let Ok(bytes) = axum::body::to_bytes(body, 2_097_152).await else {
-			return Err(AIError::RequestTooLarge);
+			return Err(AIError::ResponseTooLarge);
 		};
 		// 3 cases: success, error properly handled, and unexpected error we need to synthesize
 		let openai_response = self
@@ -286,25 +309,34 @@ impl AIProvider {
 				})
 			});
 		let (llm_resp, body) = match openai_response {
-			Ok(success) => {
+			Ok(mut success) => {
+				let rejection = match policies {
+					Some(p) => p.apply_response(client, &mut success).await.map_err(|e| {
+						warn!("failed to apply guardrails: {e}");
+						AIError::PromptWebhookError
+					})?,
+					None => None,
+				};
 				let llm_resp = LLMResponse {
 					request: req,
 					input_tokens_from_response: Some(success.usage.prompt_tokens as u64),
 					output_tokens: Some(success.usage.completion_tokens as u64),
 					total_tokens: Some(success.usage.total_tokens as u64),
 					provider_model: Some(strng::new(&success.model)),
-					completion: if include_completion_in_log {
-						Some(
-							success
-								.choices
-								.iter()
-								.flat_map(|c| c.message.content.clone())
-								.collect_vec(),
-						)
-					} else {
-						None
-					},
+					// Blocked completions are never logged
+					completion: (include_completion_in_log && rejection.is_none()).then(|| {
+						success
+							.choices
+							.iter()
+							.flat_map(|c| c.message.content.clone())
+							.collect_vec()
+					}),
 				};
+				if let Some(dr) = rejection {
+					amend_tokens(&rate_limit, &llm_resp);
+					log.store(Some(llm_resp));
+					return Ok(dr);
+				}
 				let body = Body::from(serde_json::to_vec(&success).map_err(AIError::ResponseMarshal)?);
 				(llm_resp, body)
 			},
@@ -365,6 +397,7 @@ impl AIProvider {
 		include_completion_in_log: bool,
 		resp: Response,
 	) -> Result<Response, AIError> {
+		let request_model = req.request_model.clone();
 		// Store an empty response, as we stream in info we will parse into it
 		let mut llmresp = llm::LLMResponse {
 			request: req,
@@ -377,7 +410,20 @@ impl AIProvider {
 		log.store(Some(llmresp));
 		let resp = match self {
 			AIProvider::Anthropic(p) => p.process_streaming(log, resp).await,
-			AIProvider::Bedrock(p) => return Err(AIError::StreamingUnsupported),
+			AIProvider::Gemini(p) => {
+				p.process_streaming(
+					request_model,
+					log,
+					include_completion_in_log,
+					rate_limit,
+					resp,
+				)
+				.await
+			},
+			AIProvider::Bedrock(p) => {
+				p.process_streaming(log, include_completion_in_log, rate_limit, resp)
+					.await
+			},
 			_ => {
 				self
 					.default_process_streaming(log, include_completion_in_log, rate_limit, resp)
@@ -459,6 +505,7 @@ impl AIProvider {
 			request_model: req.model.as_str().into(),
 			provider: self.provider(),
 			streaming: req.stream.unwrap_or_default(),
+			deterministic: req.temperature == Some(0.0),
 		};
 		Ok(llm)
 	}
@@ -540,6 +587,8 @@ pub enum AIError {
 	UnsupportedContent,
 	#[error("request was too large")]
 	RequestTooLarge,
+	#[error("response was too large")]
+	ResponseTooLarge,
 	#[error("prompt guard failed")]
 	PromptWebhookError,
 	#[error("failed to parse request: {0}")]
@@ -554,6 +603,120 @@ pub enum AIError {
 	JoinError(#[from] tokio::task::JoinError),
 }
 
+/// Guardrails need the whole completion, so a streaming response is buffered, up to 2mb, before it is
+/// checked. The client then receives all of the events at once, with any redactions applied, rather
+/// than as they are generated.
+async fn apply_streaming_guardrails(
+	client: client::Client,
+	policy: &Policy,
+	resp: Response,
+) -> Result<Response, AIError> {
+	if !resp.status().is_success() {
+		return Ok(resp);
+	}
+	let (mut parts, body) = resp.into_parts();
+	let Ok(bytes) = axum::body::to_bytes(body, 2_097_152).await else {
+		return Err(AIError::ResponseTooLarge);
+	};
+	let events = String::from_utf8_lossy(&bytes);
+	let chunk = |line: &str| {
+		let data = line.strip_prefix("data:")?.trim_start();
+		serde_json::from_str::<Value>(data).ok()
+	};
+	let deltas = |chunk: &Value| {
+		let choices = chunk["choices"].as_array().cloned().unwrap_or_default();
+		choices.into_iter().filter_map(|c| {
+			let content = c["delta"]["content"].as_str()?.to_string();
+			Some((c["index"].as_i64().unwrap_or_default(), content))
+		})
+	};
+	// Join the content of each choice, in the order they were first seen
+	let mut contents: Vec<(i64, String)> = vec![];
+	for (index, content) in events
+		.lines()
+		.filter_map(chunk)
+		.flat_map(|c| deltas(&c).collect_vec())
+	{
+		match contents.iter_mut().find(|(i, _)| *i == index) {
+			Some((_, c)) => c.push_str(&content),
+			None => contents.push((index, content)),
+		}
+	}
+	let mut completion = ChatCompletionResponse {
+		id: None,
+		object: "chat.completion".to_string(),
+		created: 0,
+		model: String::new(),
+		choices: contents
+			.iter()
+			.map(|(index, content)| universal::ChatCompletionChoice {
+				index: *index,
+				message: universal::ChatCompletionMessageForResponse {
+					role: universal::MessageRole::assistant,
+					content: Some(content.clone()),
+					reasoning_content: None,
+					name: None,
+					tool_calls: None,
+				},
+				finish_reason: None,
+				finish_details: None,
+			})
+			.collect(),
+		usage: universal::Usage {
+			prompt_tokens: 0,
+			completion_tokens: 0,
+			total_tokens: 0,
+		},
+		system_fingerprint: None,
+	};
+	if let Some(dr) = policy
+		.apply_response(client, &mut completion)
+		.await
+		.map_err(|e| {
+			warn!("failed to apply guardrails: {e}");
+			AIError::PromptWebhookError
+		})? {
+		return Ok(dr);
+	}
+	let redacted = completion
+		.choices
+		.into_iter()
+		.zip(contents)
+		.filter(|(c, (_, original))| c.message.content.as_ref() != Some(original))
+		.map(|(c, (index, _))| (index, c.message.content.unwrap_or_default()))
+		.collect::<HashMap<_, _>>();
+	if redacted.is_empty() {
+		return Ok(Response::from_parts(parts, Body::from(bytes)));
+	}
+	// The redacted content of a choice is sent in its first delta, and removed from the others
+	let mut sent = HashSet::new();
+	let events = events
+		.split('\n')
+		.map(|line| {
+			let Some(mut chunk) = chunk(line) else {
+				return line.to_string();
+			};
+			for c in chunk["choices"].as_array_mut().into_iter().flatten() {
+				let index = c["index"].as_i64().unwrap_or_default();
+				let Some(content) = redacted.get(&index) else {
+					continue;
+				};
+				if c["delta"]["content"].is_string() {
+					let content = if sent.insert(index) {
+						content.clone()
+					} else {
+						String::new()
+					};
+					c["delta"]["content"] = Value::String(content);
+				}
+			}
+			format!("data: {chunk}")
+		})
+		.join("\n");
+	parts.headers.remove(header::CONTENT_LENGTH);
+	Ok(Response::from_parts(parts, Body::from(events)))
+}
+
 fn amend_tokens(rate_limit: &[RateLimit], llm_resp: &LLMResponse) {
 	for lrl in rate_limit {
 		let base = llm_resp.request.input_tokens;
@@ -818,7 +981,7 @@ mod universal {
 		#[serde(skip_serializing_if = "Option::is_none")]
 		pub name: Option<String>,
 		#[serde(skip_serializing_if = "Option::is_none")]
-		pub tool_calls: Option<Vec<ToolCall>>,
+		pub tool_calls: Option<Vec<ToolCallDelta>>,
 	}
 
 	#[derive(Debug, Deserialize, Serialize)]
@@ -900,6 +1063,19 @@ mod universal {
 		pub function: ToolCallFunction,
 	}
 
+	/// A part of a tool call in a streaming response. The first part for a call has its id and name,
+	/// and the arguments are streamed in the following parts with the same index.
+	#[derive(Debug, Deserialize, Serialize, Clone)]
+	pub struct ToolCallDelta {
+		pub index: i64,
+		#[serde(skip_serializing_if = "Option::is_none")]
+		pub id: Option<String>,
+		#[serde(skip_serializing_if = "Option::is_none")]
+		pub r#type: Option<String>,
+		#[serde(skip_serializing_if = "Option::is_none")]
+		pub function: Option<ToolCallFunction>,
+	}
+
 	#[derive(Debug, Deserialize, Serialize, Clone)]
 	pub struct ToolCallFunction {
 		#[serde(skip_serializing_if = "Option::is_none")]
@@ -953,44 +1129,9 @@ mod universal {
 		pub name: String,
 		#[serde(skip_serializing_if = "Option::is_none")]
 		pub description: Option<String>,
-		pub parameters: FunctionParameters,
-	}
-
-	#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
-	pub struct FunctionParameters {
-		#[serde(rename = "type")]
-		pub schema_type: JSONSchemaType,
-		#[serde(skip_serializing_if = "Option::is_none")]
-		pub properties: Option<HashMap<String, Box<JSONSchemaDefine>>>,
-		#[serde(skip_serializing_if = "Option::is_none")]
-		pub required: Option<Vec<String>>,
-	}
-
-	#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
-	#[serde(rename_all = "lowercase")]
-	pub enum JSONSchemaType {
-		Object,
-		Number,
-		String,
-		Array,
-		Null,
-		Boolean,
-	}
-
-	#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq, Eq)]
-	pub struct JSONSchemaDefine {
-		#[serde(rename = "type")]
-		pub schema_type: Option<JSONSchemaType>,
-		#[serde(skip_serializing_if = "Option::is_none")]
-		pub description: Option<String>,
-		#[serde(skip_serializing_if = "Option::is_none")]
-		pub enum_values: Option<Vec<String>>,
-		#[serde(skip_serializing_if = "Option::is_none")]
-		pub properties: Option<HashMap<String, Box<JSONSchemaDefine>>>,
-		#[serde(skip_serializing_if = "Option::is_none")]
-		pub required: Option<Vec<String>>,
-		#[serde(skip_serializing_if = "Option::is_none")]
-		pub items: Option<Box<JSONSchemaDefine>>,
+		/// The JSON schema of the parameters. This is kept as-is, so it can be passed to any provider
+		/// without losing details.
+		pub parameters: Value,
 	}
 
 	#[derive(Debug, Deserialize, Serialize, Clone)]
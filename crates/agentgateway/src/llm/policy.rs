@@ -5,8 +5,11 @@ use ::http::HeaderMap;
 use bytes::Bytes;
 
 use crate::http::{PolicyResponse, Response, StatusCode};
+use crate::llm::guardrail::Guardrails;
 use crate::llm::policy::webhook::{MaskActionBody, RequestAction};
-use crate::llm::universal::{ChatCompletionMessage, ChatCompletionRequest, Content, MessageRole};
+use crate::llm::universal::{
+	ChatCompletionMessage, ChatCompletionRequest, ChatCompletionResponse, Content, MessageRole,
+};
 use crate::llm::{anthropic, bedrock, gemini, openai, pii, vertex};
 use crate::proxy::ProxyError;
 use crate::types::agent::Target;
@@ -16,6 +19,132 @@ use crate::*;
 #[serde(rename_all = "camelCase")]
 pub struct Policy {
 	prompt_guard: Option<PromptGuard>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	guardrails: Option<Guardrails>,
+	/// Retry requests the provider rejected as rate limited, once the limit resets.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	rate_limit_retry: Option<RateLimitRetry>,
+}
+
+/// Response header reporting how much of the rate limit wait budget was left.
+pub const WAIT_REMAINING_HEADER: ::http::HeaderName =
+	::http::HeaderName::from_static("x-agentgateway-ratelimit-wait-remaining-ms");
+
+/// RateLimitRetry retries non-streaming requests that the provider rejected with a 429, waiting as
+/// long as its `retry-after` or rate limit reset headers ask for.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RateLimitRetry {
+	/// The total time to spend waiting across all retries. If the provider asks to wait longer, its
+	/// response is returned as is.
+	#[serde(with = "serde_dur")]
+	pub max_wait: Duration,
+}
+
+impl RateLimitRetry {
+	// Bounds the retries of a provider that keeps asking for no wait at all.
+	const MAX_ATTEMPTS: usize = 5;
+
+	pub async fn call(
+		&self,
+		client: &client::Client,
+		call: client::Call,
+	) -> Result<Response, ProxyError> {
+		use http_body_util::BodyExt;
+
+		let client::Call {
+			req,
+			target,
+			transport,
+			connection_pool,
+		} = call;
+		let (parts, body) = req.into_parts();
+		// The LLM request body is already buffered, so keeping a copy for retries is cheap.
+		let body = body
+			.collect()
+			.await
+			.map_err(|e| ProxyError::Processing(e.into()))?
+			.to_bytes();
+		let mut remaining = self.max_wait;
+		for attempt in 1.. {
+			let call = client::Call {
+				req: ::http::Request::from_parts(parts.clone(), crate::http::Body::from(body.clone())),
+				target: target.clone(),
+				transport: transport.clone(),
+				connection_pool: connection_pool.clone(),
+			};
+			let mut resp = client.call(call).await?;
+			let wait = if resp.status() == StatusCode::TOO_MANY_REQUESTS && attempt < Self::MAX_ATTEMPTS {
+				reset_after(resp.headers()).filter(|wait| *wait <= remaining)
+			} else {
+				None
+			};
+			let Some(wait) = wait else {
+				resp.headers_mut().insert(
+					WAIT_REMAINING_HEADER,
+					::http::HeaderValue::from(remaining.as_millis() as u64),
+				);
+				return Ok(resp);
+			};
+			debug!("provider is rate limited, retrying in {wait:?}");
+			remaining -= wait;
+			tokio::time::sleep(wait).await;
+		}
+		unreachable!()
+	}
+}
+
+/// Returns how long the provider asked to wait before retrying. `retry-after-ms` and `retry-after`
+/// are preferred; otherwise the longest of the OpenAI style `x-ratelimit-reset-*` headers is used.
+pub fn reset_after(headers: &HeaderMap) -> Option<Duration> {
+	let get = |name: &str| {
+		headers
+			.get(name)
+			.and_then(|v| v.to_str().ok())
+			.map(str::trim)
+	};
+	if let Some(ms) = get("retry-after-ms").and_then(|v| v.parse::<f64>().ok()) {
+		return Duration::try_from_secs_f64(ms / 1000.0).ok();
+	}
+	if let Some(secs) = get("retry-after").and_then(|v| v.parse::<f64>().ok()) {
+		return Duration::try_from_secs_f64(secs).ok();
+	}
+	["x-ratelimit-reset-requests", "x-ratelimit-reset-tokens"]
+		.into_iter()
+		.filter_map(|h| get(h).and_then(parse_reset))
+		.max()
+		.or_else(|| {
+			get("x-ratelimit-reset")
+				.and_then(|v| v.parse::<f64>().ok())
+				.and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+		})
+}
+
+/// Parses durations such as `20ms`, `1.5s` or `6m0s`.
+fn parse_reset(v: &str) -> Option<Duration> {
+	let mut total = Duration::ZERO;
+	let mut rest = v;
+	while !rest.is_empty() {
+		let split = rest
+			.find(|c: char| !(c.is_ascii_digit() || c == '.'))
+			.unwrap_or(rest.len());
+		let (num, tail) = rest.split_at(split);
+		let unit_len = tail
+			.find(|c: char| c.is_ascii_digit() || c == '.')
+			.unwrap_or(tail.len());
+		let (unit, tail) = tail.split_at(unit_len);
+		let num: f64 = num.parse().ok()?;
+		let secs = match unit {
+			"ms" => num / 1000.0,
+			"s" => num,
+			"m" => num * 60.0,
+			"h" => num * 3600.0,
+			_ => return None,
+		};
+		total += Duration::try_from_secs_f64(secs).ok()?;
+		rest = tail;
+	}
+	Some(total)
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -24,15 +153,51 @@ pub struct PromptGuard {
 	request: Option<PromptGuardRequest>,
 }
 impl Policy {
+	pub fn rate_limit_retry(&self) -> Option<&RateLimitRetry> {
+		self.rate_limit_retry.as_ref()
+	}
+
 	pub async fn apply(
 		&self,
 		client: client::Client,
 		req: &mut ChatCompletionRequest,
 		http_headers: &HeaderMap,
 	) -> anyhow::Result<Option<Response>> {
-		let Some(g) = self.prompt_guard.as_ref().and_then(|g| g.request.as_ref()) else {
+		if let Some(g) = self.prompt_guard.as_ref().and_then(|g| g.request.as_ref()) {
+			if let Some(resp) = Self::apply_prompt_guard(g, client.clone(), req, http_headers).await? {
+				return Ok(Some(resp));
+			}
+		}
+		if let Some(g) = &self.guardrails {
+			return g.check_request(&client, req).await;
+		}
+		Ok(None)
+	}
+
+	/// Whether completions are checked before they are returned.
+	pub fn has_response_guardrails(&self) -> bool {
+		self.guardrails.is_some()
+	}
+
+	/// Checks a completion before it is returned, returning the response to send instead if it is
+	/// blocked.
+	pub async fn apply_response(
+		&self,
+		client: client::Client,
+		resp: &mut ChatCompletionResponse,
+	) -> anyhow::Result<Option<Response>> {
+		let Some(g) = &self.guardrails else {
 			return Ok(None);
 		};
+		g.check_response(&client, resp).await
+	}
+
+	async fn apply_prompt_guard(
+		g: &PromptGuardRequest,
+		client: client::Client,
+		req: &mut ChatCompletionRequest,
+		http_headers: &HeaderMap,
+	) -> anyhow::Result<Option<Response>> {
 		if let Some(webhook) = &g.webhook {
 			let whr = webhook::send_request(client.clone(), &webhook.target, http_headers, req).await?;
 			match whr.action {
@@ -414,6 +579,7 @@ mod webhook {
 				req: whr,
 				target: target.clone(),
 				transport: Default::default(), // TODO: use policies
+				connection_pool: None,
 			})
 			.await?;
 		let bb = axum::body::to_bytes(res.into_body(), 2_097_152).await?;
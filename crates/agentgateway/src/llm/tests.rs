@@ -2,6 +2,7 @@ use std::fs;
 use std::path::Path;
 
 use serde::de::DeserializeOwned;
+use serde_json::json;
 
 use super::*;
 
@@ -76,6 +77,30 @@ fn test_bedrock() {
 	test_request("bedrock", "full_input", request);
 }
 
+#[tokio::test]
+async fn test_bedrock_streaming_error() {
+	let provider = bedrock::Provider {
+		model: strng::new("fake-model"),
+		region: strng::new("us-east-1"),
+	};
+	let error = r#"{"message":"The security token included in the request is invalid."}"#;
+	let resp = ::http::Response::builder()
+		.status(403)
+		.header(header::CONTENT_TYPE, "application/json")
+		.body(Body::from(error))
+		.unwrap();
+	let resp = provider
+		.process_streaming(Default::default(), false, vec![], resp)
+		.await;
+	assert_eq!(resp.status(), 403);
+	assert_eq!(
+		resp.headers().get(header::CONTENT_TYPE).unwrap(),
+		"application/json"
+	);
+	let body = axum::body::to_bytes(resp.into_body(), 1024).await.unwrap();
+	assert_eq!(body.as_ref(), error.as_bytes());
+}
+
 #[test]
 fn test_anthropic() {
 	let response = |i| Ok(anthropic::translate_response(i));
@@ -85,3 +110,420 @@ fn test_anthropic() {
 	test_request("anthropic", "basic_input", request);
 	test_request("anthropic", "full_input", request);
 }
+
+#[test]
+fn test_anthropic_round_trip() {
+	let weather_call = json!({
+		"id": "toolu_01",
+		"type": "function",
+		"function": {"name": "get_weather", "arguments": "{\"location\":\"Paris\"}"}
+	});
+	let req: universal::ChatCompletionRequest = serde_json::from_value(json!({
+		"model": "claude-3-5-haiku-20241022",
+		"messages": [
+			{"role": "system", "content": "Be brief."},
+			{"role": "user", "content": "What is the weather in Paris and Rome?"},
+			{"role": "assistant", "content": null, "tool_calls": [weather_call]},
+			{"role": "tool", "tool_call_id": "toolu_01", "content": "Sunny"},
+			{"role": "tool", "tool_call_id": "toolu_02", "content": "Rainy"},
+			{"role": "user", "content": "Thanks"}
+		],
+		"tools": [{"type": "function", "function": {
+			"name": "get_weather",
+			"parameters": {"type": "object", "properties": {"location": {"type": "string"}}}
+		}}],
+		"tool_choice": "required"
+	}))
+	.unwrap();
+	let translated = serde_json::to_value(anthropic::translate_request(req)).unwrap();
+	assert_eq!(
+		translated,
+		json!({
+			"messages": [
+				{"role": "user", "content": [{"type": "text", "text": "What is the weather in Paris and Rome?"}]},
+				{"role": "assistant", "content": [
+					{"type": "tool_use", "id": "toolu_01", "name": "get_weather", "input": {"location": "Paris"}}
+				]},
+				// Tool results and the following user message are merged, as roles must alternate
+				{"role": "user", "content": [
+					{"type": "tool_result", "tool_use_id": "toolu_01", "content": "Sunny"},
+					{"type": "tool_result", "tool_use_id": "toolu_02", "content": "Rainy"},
+					{"type": "text", "text": "Thanks"}
+				]}
+			],
+			"system": "Be brief.",
+			"model": "claude-3-5-haiku-20241022",
+			"max_tokens": 4096,
+			"tools": [{
+				"name": "get_weather",
+				"input_schema": {"type": "object", "properties": {"location": {"type": "string"}}}
+			}],
+			"tool_choice": {"type": "any"}
+		})
+	);
+
+	let resp: anthropic::types::MessagesResponse = serde_json::from_value(json!({
+		"id": "msg_01",
+		"type": "message",
+		"role": "assistant",
+		"model": "claude-3-5-haiku-20241022",
+		"content": [
+			{"type": "text", "text": "Checking Rome."},
+			{"type": "tool_use", "id": "toolu_03", "name": "get_weather", "input": {"location": "Rome"}}
+		],
+		"stop_reason": "tool_use",
+		"stop_sequence": null,
+		"usage": {"input_tokens": 30, "output_tokens": 12}
+	}))
+	.unwrap();
+	let translated = serde_json::to_value(anthropic::translate_response(resp)).unwrap();
+	assert_eq!(
+		translated["choices"],
+		json!([{
+			"index": 0,
+			"message": {
+				"role": "assistant",
+				"content": "Checking Rome.",
+				"tool_calls": [{
+					"id": "toolu_03",
+					"type": "function",
+					"function": {"name": "get_weather", "arguments": "{\"location\":\"Rome\"}"}
+				}]
+			},
+			"finish_reason": "tool_calls",
+			"finish_details": null
+		}])
+	);
+	assert_eq!(
+		translated["usage"],
+		json!({"prompt_tokens": 30, "completion_tokens": 12, "total_tokens": 42})
+	);
+
+	// The translated response can be sent back as the next turn
+	let req: universal::ChatCompletionRequest = serde_json::from_value(json!({
+		"model": "claude-3-5-haiku-20241022",
+		"messages": [
+			{"role": "user", "content": "What is the weather in Rome?"},
+			translated["choices"][0]["message"],
+		]
+	}))
+	.unwrap();
+	let translated = serde_json::to_value(anthropic::translate_request(req)).unwrap();
+	assert_eq!(
+		translated["messages"][1],
+		json!({"role": "assistant", "content": [
+			{"type": "text", "text": "Checking Rome."},
+			{"type": "tool_use", "id": "toolu_03", "name": "get_weather", "input": {"location": "Rome"}}
+		]})
+	);
+}
+
+#[test]
+fn test_gemini_tools() {
+	let req: universal::ChatCompletionRequest = serde_json::from_value(json!({
+		"model": "gemini-2.0-flash",
+		"messages": [
+			{"role": "system", "content": "Be brief."},
+			{"role": "user", "content": "What is the weather in Paris?"},
+			{"role": "assistant", "content": null, "tool_calls": [{
+				"id": "call_0",
+				"type": "function",
+				"function": {"name": "get_weather", "arguments": "{\"location\":\"Paris\"}"}
+			}]},
+			{"role": "tool", "tool_call_id": "call_0", "content": "Sunny"},
+			{"role": "user", "content": "Thanks"}
+		],
+		"tools": [{"type": "function", "function": {
+			"name": "get_weather",
+			"parameters": {"type": "object", "properties": {"location": {"type": "string"}}}
+		}}],
+		"tool_choice": "required"
+	}))
+	.unwrap();
+	let translated = serde_json::to_value(gemini::translate_request(req)).unwrap();
+	assert_eq!(
+		translated,
+		json!({
+			"contents": [
+				{"role": "user", "parts": [{"text": "What is the weather in Paris?"}]},
+				{"role": "model", "parts": [
+					{"functionCall": {"name": "get_weather", "args": {"location": "Paris"}}}
+				]},
+				// Function responses are matched to the call by name
+				{"role": "user", "parts": [
+					{"functionResponse": {"name": "get_weather", "response": {"content": "Sunny"}}}
+				]},
+				{"role": "user", "parts": [{"text": "Thanks"}]}
+			],
+			"systemInstruction": {"parts": [{"text": "Be brief."}]},
+			"tools": [{"functionDeclarations": [{
+				"name": "get_weather",
+				"parameters": {"type": "object", "properties": {"location": {"type": "string"}}}
+			}]}],
+			"toolConfig": {"functionCallingConfig": {"mode": "ANY"}}
+		})
+	);
+}
+
+#[test]
+fn test_bedrock_tools() {
+	let req: universal::ChatCompletionRequest = serde_json::from_value(json!({
+		"model": "anthropic.claude-3-haiku",
+		"messages": [
+			{"role": "system", "content": "Be brief."},
+			{"role": "user", "content": "What is the weather in Paris and Rome?"},
+			{"role": "assistant", "content": null, "tool_calls": [{
+				"id": "tooluse_01",
+				"type": "function",
+				"function": {"name": "get_weather", "arguments": "{\"location\":\"Paris\"}"}
+			}]},
+			{"role": "tool", "tool_call_id": "tooluse_01", "content": "Sunny"},
+			{"role": "user", "content": "Thanks"}
+		],
+		"tools": [{"type": "function", "function": {
+			"name": "get_weather",
+			"parameters": {"type": "object", "properties": {"location": {"type": "string"}}}
+		}}],
+		"tool_choice": "required"
+	}))
+	.unwrap();
+	let translated = serde_json::to_value(bedrock::translate_request(req)).unwrap();
+	assert_eq!(
+		translated,
+		json!({
+			"modelId": "anthropic.claude-3-haiku",
+			"messages": [
+				{"role": "user", "content": [{"text": "What is the weather in Paris and Rome?"}]},
+				{"role": "assistant", "content": [
+					{"toolUse": {"toolUseId": "tooluse_01", "name": "get_weather", "input": {"location": "Paris"}}}
+				]},
+				// Tool results and the following user message are merged, as roles must alternate
+				{"role": "user", "content": [
+					{"toolResult": {"toolUseId": "tooluse_01", "content": [{"text": "Sunny"}]}},
+					{"text": "Thanks"}
+				]}
+			],
+			"system": [{"text": "Be brief."}],
+			"inferenceConfig": {"maxTokens": 4096},
+			"toolConfig": {
+				"tools": [{"toolSpec": {
+					"name": "get_weather",
+					"inputSchema": {"json": {"type": "object", "properties": {"location": {"type": "string"}}}}
+				}}],
+				"toolChoice": {"any": {}}
+			}
+		})
+	);
+
+	let resp: bedrock::types::ConverseResponse = serde_json::from_value(json!({
+		"output": {"message": {"role": "assistant", "content": [
+			{"text": "Checking Rome."},
+			{"toolUse": {"toolUseId": "tooluse_02", "name": "get_weather", "input": {"location": "Rome"}}}
+		]}},
+		"stopReason": "tool_use",
+		"usage": {"inputTokens": 30, "outputTokens": 12, "totalTokens": 42}
+	}))
+	.unwrap();
+	let translated =
+		serde_json::to_value(bedrock::translate_response(resp, &strng::new("fake-model")).unwrap())
+			.unwrap();
+	assert_eq!(
+		translated["choices"],
+		json!([{
+			"index": 0,
+			"message": {
+				"role": "assistant",
+				"content": "Checking Rome.",
+				"tool_calls": [{
+					"id": "tooluse_02",
+					"type": "function",
+					"function": {"name": "get_weather", "arguments": "{\"location\":\"Rome\"}"}
+				}]
+			},
+			"finish_reason": "tool_calls",
+			"finish_details": null
+		}])
+	);
+}
+
+/// Returns the chunks of a translated streaming response, without the final `[DONE]`.
+async fn stream_chunks(resp: Response) -> Vec<Value> {
+	let body = axum::body::to_bytes(resp.into_body(), 1_048_576)
+		.await
+		.unwrap();
+	String::from_utf8_lossy(&body)
+		.lines()
+		.filter_map(|l| l.strip_prefix("data: "))
+		.filter(|l| *l != "[DONE]")
+		.map(|l| serde_json::from_str(l).unwrap())
+		.collect()
+}
+
+/// Checks the streamed tool call of get_weather({"location":"Paris"}), with the arguments in any
+/// number of chunks, and the final finish reason.
+fn assert_streamed_tool_call(chunks: &[Value], id: &str) {
+	let calls = chunks
+		.iter()
+		.flat_map(|c| c["choices"].as_array().unwrap())
+		.filter_map(|c| c["delta"]["tool_calls"].as_array())
+		.flatten()
+		.collect_vec();
+	assert!(calls.iter().all(|c| c["index"] == 0), "{calls:?}");
+	assert_eq!(calls[0]["id"], id);
+	assert_eq!(calls[0]["type"], "function");
+	assert_eq!(calls[0]["function"]["name"], "get_weather");
+	let arguments = calls
+		.iter()
+		.filter_map(|c| c["function"]["arguments"].as_str())
+		.collect::<String>();
+	assert_eq!(arguments, r#"{"location":"Paris"}"#);
+	let finish_reasons = chunks
+		.iter()
+		.flat_map(|c| c["choices"].as_array().unwrap())
+		.filter_map(|c| c["finish_reason"].as_str())
+		.collect_vec();
+	assert_eq!(finish_reasons, vec!["tool_calls"]);
+}
+
+#[tokio::test]
+async fn test_anthropic_streaming_tool_calls() {
+	let events = [
+		json!({"type": "message_start", "message": {
+			"id": "msg_01", "type": "message", "role": "assistant", "model": "claude-3-5-haiku",
+			"content": [], "stop_reason": null, "stop_sequence": null,
+			"usage": {"input_tokens": 10, "output_tokens": 1}
+		}}),
+		json!({"type": "content_block_start", "index": 0, "content_block": {
+			"type": "tool_use", "id": "toolu_01", "name": "get_weather", "input": {}
+		}}),
+		json!({"type": "content_block_delta", "index": 0, "delta": {
+			"type": "input_json_delta", "partial_json": "{\"location\":"
+		}}),
+		json!({"type": "content_block_delta", "index": 0, "delta": {
+			"type": "input_json_delta", "partial_json": "\"Paris\"}"
+		}}),
+		json!({"type": "content_block_stop", "index": 0}),
+		json!({"type": "message_delta",
+			"delta": {"stop_reason": "tool_use", "stop_sequence": null},
+			"usage": {"output_tokens": 5}
+		}),
+		json!({"type": "message_stop"}),
+	];
+	let sse = events.map(|e| format!("data: {e}\n\n")).concat();
+	let provider = anthropic::Provider { model: None };
+	let resp = provider
+		.process_streaming(Default::default(), ::http::Response::new(Body::from(sse)))
+		.await;
+	assert_streamed_tool_call(&stream_chunks(resp).await, "toolu_01");
+}
+
+#[tokio::test]
+async fn test_gemini_streaming_tool_calls() {
+	let chunk = json!({
+		"candidates": [{
+			"content": {"role": "model", "parts": [
+				{"functionCall": {"name": "get_weather", "args": {"location": "Paris"}}}
+			]},
+			"finishReason": "STOP",
+			"index": 0
+		}],
+		"usageMetadata": {"promptTokenCount": 10, "candidatesTokenCount": 5, "totalTokenCount": 15},
+		"modelVersion": "gemini-2.0-flash",
+		"responseId": "resp"
+	});
+	let provider = gemini::Provider::default();
+	let resp = provider
+		.process_streaming(
+			strng::new("gemini-2.0-flash"),
+			Default::default(),
+			false,
+			vec![],
+			::http::Response::new(Body::from(format!("data: {chunk}\n\n"))),
+		)
+		.await;
+	assert_streamed_tool_call(&stream_chunks(resp).await, "call_0");
+}
+
+#[tokio::test]
+async fn test_bedrock_streaming_tool_calls() {
+	let events = [
+		("messageStart", json!({"role": "assistant"})),
+		(
+			"contentBlockStart",
+			json!({"contentBlockIndex": 0, "start": {"toolUse": {"toolUseId": "tooluse_01", "name": "get_weather"}}}),
+		),
+		(
+			"contentBlockDelta",
+			json!({"contentBlockIndex": 0, "delta": {"toolUse": {"input": "{\"location\":"}}}),
+		),
+		(
+			"contentBlockDelta",
+			json!({"contentBlockIndex": 0, "delta": {"toolUse": {"input": "\"Paris\"}"}}}),
+		),
+		("contentBlockStop", json!({"contentBlockIndex": 0})),
+		("messageStop", json!({"stopReason": "tool_use"})),
+	];
+	let mut body = bytes::BytesMut::new();
+	for (event, payload) in events {
+		body.extend_from_slice(&crate::parse::eventstream::encode(
+			&[(":message-type", "event"), (":event-type", event)],
+			payload.to_string().as_bytes(),
+		));
+	}
+	let provider = bedrock::Provider {
+		model: strng::new("fake-model"),
+		region: strng::new("us-east-1"),
+	};
+	let resp = provider
+		.process_streaming(
+			Default::default(),
+			false,
+			vec![],
+			::http::Response::new(Body::from(body.freeze())),
+		)
+		.await;
+	assert_streamed_tool_call(&stream_chunks(resp).await, "tooluse_01");
+}
+
+#[test]
+fn test_reset_after() {
+	let headers = |pairs: &[(&'static str, &'static str)]| {
+		let mut h = ::http::HeaderMap::new();
+		for (k, v) in pairs {
+			h.insert(*k, HeaderValue::from_static(v));
+		}
+		h
+	};
+	let ms = Duration::from_millis;
+	assert_eq!(
+		reset_after(&headers(&[("retry-after", "2")])),
+		Some(ms(2000))
+	);
+	assert_eq!(
+		reset_after(&headers(&[("retry-after-ms", "150"), ("retry-after", "2")])),
+		Some(ms(150))
+	);
+	assert_eq!(
+		reset_after(&headers(&[
+			("x-ratelimit-reset-requests", "1.5s"),
+			("x-ratelimit-reset-tokens", "6m0s"),
+		])),
+		Some(ms(360_000))
+	);
+	assert_eq!(
+		reset_after(&headers(&[("x-ratelimit-reset-tokens", "20ms")])),
+		Some(ms(20))
+	);
+	assert_eq!(
+		reset_after(&headers(&[("x-ratelimit-reset", "3")])),
+		Some(ms(3000))
+	);
+	assert_eq!(
+		reset_after(&headers(&[(
+			"retry-after",
+			"Wed, 21 Oct 2015 07:28:00 GMT"
+		)])),
+		None
+	);
+	assert_eq!(reset_after(&headers(&[])), None);
+}
@@ -28,7 +28,93 @@ impl super::Provider for Provider {
 	const NAME: Strng = strng::literal!("vertex");
 }
 
+const GLOBAL_REGION: &str = "global";
+
+// https://cloud.google.com/vertex-ai/generative-ai/docs/learn/locations
+const REGIONS: &[&str] = &[
+	GLOBAL_REGION,
+	"africa-south1",
+	"asia-east1",
+	"asia-east2",
+	"asia-northeast1",
+	"asia-northeast2",
+	"asia-northeast3",
+	"asia-south1",
+	"asia-southeast1",
+	"asia-southeast2",
+	"australia-southeast1",
+	"australia-southeast2",
+	"europe-central2",
+	"europe-north1",
+	"europe-southwest1",
+	"europe-west1",
+	"europe-west2",
+	"europe-west3",
+	"europe-west4",
+	"europe-west6",
+	"europe-west8",
+	"europe-west9",
+	"europe-west12",
+	"me-central1",
+	"me-central2",
+	"me-west1",
+	"northamerica-northeast1",
+	"northamerica-northeast2",
+	"southamerica-east1",
+	"southamerica-west1",
+	"us-central1",
+	"us-east1",
+	"us-east4",
+	"us-east5",
+	"us-south1",
+	"us-west1",
+	"us-west2",
+	"us-west3",
+	"us-west4",
+];
+
 impl Provider {
+	/// Creates a provider, failing if the project or region is invalid.
+	pub fn new(
+		model: Option<Strng>,
+		region: Option<Strng>,
+		project_id: Strng,
+	) -> anyhow::Result<Self> {
+		let p = Provider {
+			model,
+			region,
+			project_id,
+		};
+		p.endpoint()?;
+		Ok(p)
+	}
+
+	/// The full URL requests are sent to. This fails if the project or region is invalid.
+	pub fn endpoint(&self) -> anyhow::Result<::http::Uri> {
+		if self.project_id.is_empty() {
+			anyhow::bail!("vertex projectId is required");
+		}
+		if !self
+			.project_id
+			.chars()
+			.all(|c| c.is_ascii_alphanumeric() || c == '-')
+		{
+			anyhow::bail!("vertex projectId {:?} is invalid", self.project_id);
+		}
+		if let Some(region) = &self.region {
+			if !REGIONS.contains(&region.as_str()) {
+				anyhow::bail!("vertex region {region:?} is not a known region");
+			}
+		}
+		Ok(
+			::http::Uri::builder()
+				.scheme("https")
+				.authority(self.get_host().as_str())
+				.path_and_query(self.get_path_for_model().as_str())
+				.build()?,
+		)
+	}
+
 	pub async fn process_request(
 		&self,
 		mut req: universal::ChatCompletionRequest,
@@ -63,8 +149,8 @@ impl Provider {
 		)
 	}
 	pub fn get_host(&self) -> Strng {
-		match &self.region {
-			None => {
+		match self.region.as_deref() {
+			None | Some(GLOBAL_REGION) => {
 				strng::literal!("aiplatform.googleapis.com")
 			},
 			Some(region) => {
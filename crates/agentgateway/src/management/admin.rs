@@ -12,7 +12,7 @@ use agent_core::version::BuildInfo;
 use agent_core::{signal, telemetry};
 use base64::engine::general_purpose::STANDARD;
 use bytes::Bytes;
-use http_body_util::Full;
+use http_body_util::{BodyExt, Full};
 use hyper::Request;
 use hyper::body::Incoming;
 use hyper::header::{CONTENT_TYPE, HeaderValue};
@@ -23,6 +23,12 @@ use tracing_subscriber::filter;
 use super::hyper_helpers::{Server, empty_response, plaintext_response};
 use crate::Config;
 use crate::http::Response;
+use crate::http::maintenance::MaintenancePage;
+use crate::state_manager::LocalClient;
+
+#[cfg(test)]
+#[path = "admin_test.rs"]
+mod tests;
 
 pub trait ConfigDumpHandler: Sync + Send {
 	fn key(&self) -> &'static str;
@@ -47,6 +53,7 @@ struct State {
 	shutdown_trigger: signal::ShutdownTrigger,
 	config_dump_handlers: Vec<Arc<dyn ConfigDumpHandler>>,
 	admin_fallback: Option<Arc<dyn AdminFallback>>,
+	local_client: Option<LocalClient>,
 }
 
 pub struct Service {
@@ -98,6 +105,7 @@ impl Service {
 				shutdown_trigger,
 				config_dump_handlers: vec![],
 				admin_fallback: None,
+				local_client: None,
 			},
 		)
 		.await
@@ -116,6 +124,10 @@ impl Service {
 		self.s.state_mut().admin_fallback = Some(handler);
 	}
 
+	pub fn set_local_client(&mut self, client: LocalClient) {
+		self.s.state_mut().local_client = Some(client);
+	}
+
 	pub fn spawn(self) {
 		self.s.spawn(|state, req| async move {
 			match req.uri().path() {
@@ -139,10 +151,21 @@ impl Service {
 							version: BuildInfo::new(),
 							config: state.config.clone(),
 						},
+						DumpFormat::from_query(req.uri().query()),
 					)
 					.await
 				},
 				"/logging" => Ok(handle_logging(req).await),
+				"/reload" => match *req.method() {
+					hyper::Method::POST => handle_reload(state.local_client.as_ref()).await,
+					_ => Ok(empty_response(hyper::StatusCode::METHOD_NOT_ALLOWED)),
+				},
+				"/debug/ratelimits" => handle_ratelimits(&state.stores),
+				"/maintenance" => {
+					let (parts, body) = req.into_parts();
+					let body = body.collect().await?.to_bytes();
+					handle_maintenance(&state.stores, &parts.method, parts.uri.query(), body)
+				},
 				_ => {
 					if let Some(h) = &state.admin_fallback {
 						Ok(h.handle(req).await)
@@ -170,6 +193,10 @@ async fn handle_dashboard(_req: Request<Incoming>) -> Response {
 		("quitquitquit", "shut down the server"),
 		("config_dump", "dump the current agentgateway configuration"),
 		("logging", "query/changing logging levels"),
+		(
+			"debug/ratelimits",
+			"dump the remaining tokens of local rate limits",
+		),
 	];
 
 	let mut api_rows = String::new();
@@ -243,9 +270,31 @@ async fn handle_server_shutdown(
 	}
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DumpFormat {
+	Json,
+	Yaml,
+}
+
+impl DumpFormat {
+	fn from_query(query: Option<&str>) -> DumpFormat {
+		let yaml = query.is_some_and(|q| {
+			url::form_urlencoded::parse(q.as_bytes()).any(|(k, v)| k == "format" && v == "yaml")
+		});
+		if yaml {
+			DumpFormat::Yaml
+		} else {
+			DumpFormat::Json
+		}
+	}
+}
+
+// Secrets are never written out: fields holding them serialize through `ser_redact`, and TLS
+// configuration is omitted entirely.
 async fn handle_config_dump(
 	handlers: &[Arc<dyn ConfigDumpHandler>],
-	mut dump: ConfigDump,
+	dump: ConfigDump,
+	format: DumpFormat,
 ) -> anyhow::Result<Response> {
 	let serde_json::Value::Object(mut kv) = serde_json::to_value(&dump)? else {
 		anyhow::bail!("config dump is not a key-value pair")
@@ -255,16 +304,124 @@ async fn handle_config_dump(
 		let x = h.handle()?;
 		kv.insert(h.key().to_string(), x);
 	}
-	let body = serde_json::to_string_pretty(&kv)?;
+	let (body, content_type) = match format {
+		DumpFormat::Json => (serde_json::to_string_pretty(&kv)?, "application/json"),
+		DumpFormat::Yaml => (serde_yaml::to_string(&kv)?, "application/yaml"),
+	};
 	Ok(
 		::http::Response::builder()
 			.status(hyper::StatusCode::OK)
-			.header(hyper::header::CONTENT_TYPE, "application/json")
+			.header(hyper::header::CONTENT_TYPE, content_type)
 			.body(body.into())
 			.expect("builder with known status code should not fail"),
 	)
 }
 
+// Re-reads the local configuration and applies it, returning what changed. If the new configuration
+// is invalid, nothing is applied and the error is returned instead.
+async fn handle_reload(client: Option<&LocalClient>) -> anyhow::Result<Response> {
+	let Some(client) = client else {
+		return Ok(plaintext_response(
+			hyper::StatusCode::NOT_FOUND,
+			"no local configuration to reload\n".into(),
+		));
+	};
+	let changes = match client.reload_config().await {
+		Ok(changes) => changes,
+		Err(e) => {
+			warn!("failed to reload config: {e:#}");
+			return Ok(plaintext_response(
+				hyper::StatusCode::BAD_REQUEST,
+				format!("failed to reload config: {e:#}\n"),
+			));
+		},
+	};
+	info!("config reloaded from admin request");
+	Ok(
+		::http::Response::builder()
+			.status(hyper::StatusCode::OK)
+			.header(hyper::header::CONTENT_TYPE, "application/json")
+			.body(serde_json::to_string_pretty(&changes)?.into())
+			.expect("builder with known status code should not fail"),
+	)
+}
+
+// Keyed buckets are reported by a hash of their key, as keys such as client IPs identify users.
+fn handle_ratelimits(stores: &crate::store::Stores) -> anyhow::Result<Response> {
+	let status = stores.read_binds().local_rate_limits();
+	Ok(
+		::http::Response::builder()
+			.status(hyper::StatusCode::OK)
+			.header(hyper::header::CONTENT_TYPE, "application/json")
+			.body(serde_json::to_string_pretty(&status)?.into())
+			.expect("builder with known status code should not fail"),
+	)
+}
+
+// Toggles maintenance mode for a route, by name: POST puts it in maintenance mode, with an optional
+// maintenance page as the body, and DELETE clears it. As this is runtime state rather than
+// configuration, it is kept across config reloads.
+fn handle_maintenance(
+	stores: &crate::store::Stores,
+	method: &hyper::Method,
+	query: Option<&str>,
+	body: Bytes,
+) -> anyhow::Result<Response> {
+	if method == hyper::Method::GET {
+		let routes = stores.read_binds().maintenance_routes();
+		return Ok(
+			::http::Response::builder()
+				.status(hyper::StatusCode::OK)
+				.header(hyper::header::CONTENT_TYPE, "application/json")
+				.body(serde_json::to_string_pretty(&routes)?.into())
+				.expect("builder with known status code should not fail"),
+		);
+	}
+	let route = query.and_then(|q| {
+		url::form_urlencoded::parse(q.as_bytes())
+			.find(|(k, _)| k == "route")
+			.map(|(_, v)| agent_core::strng::new(v))
+	});
+	let Some(route) = route else {
+		return Ok(plaintext_response(
+			hyper::StatusCode::BAD_REQUEST,
+			"missing route query parameter\n".into(),
+		));
+	};
+	match *method {
+		hyper::Method::POST => {
+			let page: MaintenancePage = if body.is_empty() {
+				MaintenancePage::default()
+			} else {
+				match serde_json::from_slice(&body) {
+					Ok(page) => page,
+					Err(e) => {
+						return Ok(plaintext_response(
+							hyper::StatusCode::BAD_REQUEST,
+							format!("invalid maintenance page: {e}\n"),
+						));
+					},
+				}
+			};
+			info!(%route, "route put in maintenance mode");
+			stores.binds.write().set_maintenance(route, page);
+			Ok(empty_response(hyper::StatusCode::OK))
+		},
+		hyper::Method::DELETE => {
+			if stores.binds.write().clear_maintenance(&route) {
+				info!(%route, "route taken out of maintenance mode");
+				Ok(empty_response(hyper::StatusCode::OK))
+			} else {
+				Ok(plaintext_response(
+					hyper::StatusCode::NOT_FOUND,
+					"route is not in maintenance mode\n".into(),
+				))
+			}
+		},
+		_ => Ok(empty_response(hyper::StatusCode::METHOD_NOT_ALLOWED)),
+	}
+}
+
 // mirror envoy's behavior: https://www.envoyproxy.io/docs/envoy/latest/operations/admin#post--logging
 // NOTE: multiple query parameters is not supported, for example
 // curl -X POST http://127.0.0.1:15000/logging?"tap=debug&router=debug"
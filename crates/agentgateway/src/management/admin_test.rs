@@ -0,0 +1,230 @@
+use agent_core::strng;
+use secrecy::SecretString;
+
+use super::*;
+use crate::http::auth::BackendAuth;
+use crate::types::agent::{Policy, PolicyTarget, TargetedPolicy};
+
+const SECRET: &str = "super-secret-backend-key";
+
+fn dump_with_secret() -> ConfigDump {
+	let stores = crate::store::Stores::new();
+	stores.binds.write().insert_policy(TargetedPolicy {
+		name: strng::new("auth"),
+		target: PolicyTarget::Backend(strng::new("backend")),
+		policy: Policy::BackendAuth(BackendAuth::Key(SecretString::new(SECRET.into()))),
+	});
+	let config = crate::config::parse_config("{}".to_string(), None).unwrap();
+	ConfigDump {
+		stores,
+		version: BuildInfo::new(),
+		config: Arc::new(config),
+	}
+}
+
+async fn dump_body(format: DumpFormat) -> (String, String) {
+	let mut resp = handle_config_dump(&[], dump_with_secret(), format)
+		.await
+		.unwrap();
+	let content_type = resp.headers()[CONTENT_TYPE].to_str().unwrap().to_string();
+	let body = crate::http::inspect_body(resp.body_mut()).await.unwrap();
+	(content_type, String::from_utf8(body.to_vec()).unwrap())
+}
+
+#[tokio::test]
+async fn config_dump_redacts_secrets() {
+	let (content_type, body) = dump_body(DumpFormat::Json).await;
+	assert_eq!(content_type, "application/json");
+	assert!(body.contains("<redacted>"), "{body}");
+	assert!(!body.contains(SECRET), "{body}");
+
+	let (content_type, body) = dump_body(DumpFormat::Yaml).await;
+	assert_eq!(content_type, "application/yaml");
+	assert!(body.contains("<redacted>"), "{body}");
+	assert!(!body.contains(SECRET), "{body}");
+}
+
+#[test]
+fn config_dump_format() {
+	assert_eq!(DumpFormat::from_query(None), DumpFormat::Json);
+	assert_eq!(
+		DumpFormat::from_query(Some("format=json")),
+		DumpFormat::Json
+	);
+	assert_eq!(
+		DumpFormat::from_query(Some("format=yaml")),
+		DumpFormat::Yaml
+	);
+	assert_eq!(
+		DumpFormat::from_query(Some("a=b&format=yaml")),
+		DumpFormat::Yaml
+	);
+}
+
+#[tokio::test]
+async fn ratelimit_status() {
+	use crate::http::localratelimit::{RateLimit, RateLimitKey, RateLimitSerde, RateLimitType};
+	let limit = |key| -> RateLimit {
+		RateLimitSerde {
+			max_tokens: 5,
+			tokens_per_fill: 1,
+			fill_interval: Duration::from_secs(60),
+			limit_type: RateLimitType::Requests,
+			key,
+		}
+		.try_into()
+		.unwrap()
+	};
+	let request = |user: &str| {
+		::http::Request::builder()
+			.header("x-user", user)
+			.body(crate::http::Body::empty())
+			.unwrap()
+	};
+	let global = limit(RateLimitKey::Global);
+	let per_user = limit(RateLimitKey::Header(::http::HeaderName::from_static(
+		"x-user",
+	)));
+	for _ in 0..2 {
+		assert!(global.check_request(&request("alice")));
+	}
+	assert!(per_user.check_request(&request("alice")));
+
+	let stores = crate::store::Stores::new();
+	for (name, rl) in [("global", global), ("per-user", per_user)] {
+		stores.binds.write().insert_policy(TargetedPolicy {
+			name: strng::new(name),
+			target: PolicyTarget::Route(strng::new("route")),
+			policy: Policy::LocalRateLimit(vec![rl]),
+		});
+	}
+	let mut resp = handle_ratelimits(&stores).unwrap();
+	assert_eq!(resp.headers()[CONTENT_TYPE], "application/json");
+	let body = crate::http::inspect_body(resp.body_mut()).await.unwrap();
+	let body = String::from_utf8(body.to_vec()).unwrap();
+	// Client identifiers are only reported as a hash
+	assert!(!body.contains("alice"), "{body}");
+	let status: serde_json::Value = serde_json::from_str(&body).unwrap();
+
+	let global = &status["global"][0];
+	assert_eq!(global["type"], "requests");
+	assert_eq!(global["maxTokens"], 5);
+	assert_eq!(global["buckets"][0]["remaining"], 3);
+	assert!(global["buckets"][0].get("key").is_none());
+
+	let per_user = &status["per-user"][0]["buckets"];
+	assert_eq!(per_user.as_array().unwrap().len(), 1);
+	assert_eq!(per_user[0]["remaining"], 4);
+	assert!(per_user[0]["key"].is_string());
+}
+
+#[tokio::test]
+async fn reload_local_config() {
+	let dir = tempfile::tempdir().unwrap();
+	let path = dir.path().join("config.yaml");
+	let write = |routes: &[&str]| {
+		let routes = routes
+			.iter()
+			.map(|r| format!("    - name: {r}\n      backends:\n      - host: 127.0.0.1:8080\n"))
+			.collect::<String>();
+		fs_err::write(
+			&path,
+			format!("binds:\n- port: 3000\n  listeners:\n  - routes:\n{routes}"),
+		)
+		.unwrap();
+	};
+	let reload = async |client: &LocalClient| {
+		let mut resp = handle_reload(Some(client)).await.unwrap();
+		let body = crate::http::inspect_body(resp.body_mut()).await.unwrap();
+		(resp.status(), String::from_utf8(body.to_vec()).unwrap())
+	};
+
+	let config = crate::config::parse_config("{}".to_string(), None).unwrap();
+	let client = LocalClient {
+		cfg: crate::ConfigSource::File(path.clone()),
+		stores: crate::store::Stores::new(),
+		client: crate::client::Client::new(&config.dns, None),
+		state: Default::default(),
+	};
+	write(&["first", "second"]);
+	client.reload_config().await.unwrap();
+
+	write(&["second", "third"]);
+	let (status, body) = reload(&client).await;
+	assert_eq!(status, hyper::StatusCode::OK);
+	let changes: serde_json::Value = serde_json::from_str(&body).unwrap();
+	let names = |v: &serde_json::Value| {
+		v.as_array()
+			.unwrap()
+			.iter()
+			.map(|n| n.as_str().unwrap().to_string())
+			.collect::<Vec<_>>()
+	};
+	let added = names(&changes["routes"]["added"]);
+	let removed = names(&changes["routes"]["removed"]);
+	assert_eq!(added.len(), 1);
+	assert!(added[0].contains("/third/"), "{body}");
+	assert_eq!(removed.len(), 1);
+	assert!(removed[0].contains("/first/"), "{body}");
+	assert!(names(&changes["listeners"]["added"]).is_empty());
+	assert!(names(&changes["listeners"]["removed"]).is_empty());
+
+	// An invalid config is rejected with the reason
+	fs_err::write(&path, "binds:\n- port: not-a-port\n").unwrap();
+	let (status, body) = reload(&client).await;
+	assert_eq!(status, hyper::StatusCode::BAD_REQUEST);
+	assert!(body.contains("failed to reload config"), "{body}");
+
+	// The running config was left untouched, so reloading the same config again changes nothing
+	write(&["second", "third"]);
+	let (status, body) = reload(&client).await;
+	assert_eq!(status, hyper::StatusCode::OK);
+	let changes: serde_json::Value = serde_json::from_str(&body).unwrap();
+	for kind in ["listeners", "routes", "backends", "policies"] {
+		assert!(names(&changes[kind]["added"]).is_empty(), "{body}");
+		assert!(names(&changes[kind]["removed"]).is_empty(), "{body}");
+	}
+}
+
+#[tokio::test]
+async fn maintenance_toggle() {
+	let stores = crate::store::Stores::new();
+	let route = strng::new("route");
+	let call = |method: hyper::Method, query: Option<&str>, body: &str| {
+		handle_maintenance(
+			&stores,
+			&method,
+			query,
+			Bytes::copy_from_slice(body.as_bytes()),
+		)
+		.unwrap()
+	};
+
+	let resp = call(hyper::Method::POST, None, "");
+	assert_eq!(resp.status(), hyper::StatusCode::BAD_REQUEST);
+	let resp = call(hyper::Method::POST, Some("route=route"), "{\"status\": 42}");
+	assert_eq!(resp.status(), hyper::StatusCode::BAD_REQUEST);
+	assert!(stores.read_binds().maintenance(&route).is_none());
+
+	let resp = call(
+		hyper::Method::POST,
+		Some("route=route"),
+		r#"{"body": "down for a bit", "retryAfter": "2m"}"#,
+	);
+	assert_eq!(resp.status(), hyper::StatusCode::OK);
+	let page = stores.read_binds().maintenance(&route).unwrap();
+	assert_eq!(page.status, hyper::StatusCode::SERVICE_UNAVAILABLE);
+	assert_eq!(page.body, "down for a bit");
+	assert_eq!(page.retry_after, Some(Duration::from_secs(120)));
+
+	let mut resp = call(hyper::Method::GET, None, "");
+	let body = crate::http::inspect_body(resp.body_mut()).await.unwrap();
+	let routes: serde_json::Value = serde_json::from_slice(&body).unwrap();
+	assert_eq!(routes["route"]["status"], 503);
+
+	let resp = call(hyper::Method::DELETE, Some("route=route"), "");
+	assert_eq!(resp.status(), hyper::StatusCode::OK);
+	assert!(stores.read_binds().maintenance(&route).is_none());
+	let resp = call(hyper::Method::DELETE, Some("route=route"), "");
+	assert_eq!(resp.status(), hyper::StatusCode::NOT_FOUND);
+}
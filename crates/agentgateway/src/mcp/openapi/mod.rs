@@ -9,7 +9,7 @@ use http_body_util::BodyExt;
 use hyper_util::rt::TokioIo;
 use openapiv3::{OpenAPI, Parameter, ReferenceOr, RequestBody, Schema, SchemaKind, Type};
 use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
-use rmcp::model::{JsonObject, Tool};
+use rmcp::model::{Content, JsonObject, ResourceContents, Tool};
 use serde::{Deserialize, Serialize};
 use serde_json::{Value, json};
 use tracing::instrument;
@@ -27,6 +27,21 @@ pub struct UpstreamOpenAPICall {
 	// todo: params
 }
 
+/// ArgumentError is returned when the arguments of a tool call or prompt do not satisfy its schema.
+#[derive(Debug, thiserror::Error)]
+pub enum ArgumentError {
+	#[error("missing required argument '{0}'")]
+	MissingGroup(String),
+	#[error("missing required {group} parameter '{name}'")]
+	MissingParameter { group: String, name: String },
+	#[error("missing required prompt argument '{0}'")]
+	MissingPromptArgument(String),
+	#[error("prompt argument '{0}' must be a string, number or boolean")]
+	InvalidPromptArgument(String),
+	#[error("path parameter '{0}' does not match its declared type")]
+	InvalidPathParameter(String),
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum ParseError {
 	#[error("missing fields")]
@@ -101,12 +116,36 @@ fn resolve_schema<'a>(
 	}
 }
 
+// Maximum number of `$ref`s that will be expanded within a single schema.
+const MAX_REF_DEPTH: usize = 32;
+
 /// Recursively resolves all nested schema references (`$ref`) within a given schema,
 /// returning a new `Schema` object with all references replaced by their corresponding items.
 fn resolve_nested_schema<'a>(
 	reference: &'a ReferenceOr<Schema>,
 	doc: &'a OpenAPI,
 ) -> Result<Schema, ParseError> {
+	resolve_nested_schema_with_refs(reference, doc, &mut Vec::new())
+}
+
+/// `refs` holds the chain of references currently being expanded. A reference that is already in the
+/// chain is recursive (for example, a tree `Node` with `children: [Node]`); expanding it again would never
+/// terminate, so it is left as an unconstrained schema instead.
+fn resolve_nested_schema_with_refs<'a>(
+	reference: &'a ReferenceOr<Schema>,
+	doc: &'a OpenAPI,
+	refs: &mut Vec<String>,
+) -> Result<Schema, ParseError> {
+	if let ReferenceOr::Reference { reference } = reference {
+		if refs.contains(reference) || refs.len() >= MAX_REF_DEPTH {
+			return Ok(Schema {
+				schema_data: Default::default(),
+				schema_kind: SchemaKind::Any(Default::default()),
+			});
+		}
+		refs.push(reference.clone());
+	}
+
 	// 1. Resolve the initial reference to get the base Schema object (immutable borrow)
 	let base_schema = resolve_schema(reference, doc)?;
 
@@ -122,7 +161,7 @@ fn resolve_nested_schema<'a>(
 					ReferenceOr::Reference { reference } => ReferenceOr::Reference { reference },
 					ReferenceOr::Item(boxed_item) => ReferenceOr::Item((*boxed_item).clone()),
 				};
-				let resolved_prop = resolve_nested_schema(&temp_prop_ref, doc)?;
+				let resolved_prop = resolve_nested_schema_with_refs(&temp_prop_ref, doc, refs)?;
 				*prop_ref_box = ReferenceOr::Item(Box::new(resolved_prop));
 			}
 		},
@@ -133,7 +172,7 @@ fn resolve_nested_schema<'a>(
 					ReferenceOr::Reference { reference } => ReferenceOr::Reference { reference },
 					ReferenceOr::Item(boxed_item) => ReferenceOr::Item((*boxed_item).clone()),
 				};
-				let resolved_items = resolve_nested_schema(&temp_items_ref, doc)?;
+				let resolved_items = resolve_nested_schema_with_refs(&temp_items_ref, doc, refs)?;
 				*items_ref_box = ReferenceOr::Item(Box::new(resolved_items));
 			}
 		},
@@ -141,27 +180,27 @@ fn resolve_nested_schema<'a>(
 		SchemaKind::OneOf { one_of } => {
 			for ref_or_schema in one_of.iter_mut() {
 				let temp_ref = ref_or_schema.clone();
-				let resolved = resolve_nested_schema(&temp_ref, doc)?;
+				let resolved = resolve_nested_schema_with_refs(&temp_ref, doc, refs)?;
 				*ref_or_schema = ReferenceOr::Item(resolved);
 			}
 		},
 		SchemaKind::AllOf { all_of } => {
 			for ref_or_schema in all_of.iter_mut() {
 				let temp_ref = ref_or_schema.clone();
-				let resolved = resolve_nested_schema(&temp_ref, doc)?;
+				let resolved = resolve_nested_schema_with_refs(&temp_ref, doc, refs)?;
 				*ref_or_schema = ReferenceOr::Item(resolved);
 			}
 		},
 		SchemaKind::AnyOf { any_of } => {
 			for ref_or_schema in any_of.iter_mut() {
 				let temp_ref = ref_or_schema.clone();
-				let resolved = resolve_nested_schema(&temp_ref, doc)?;
+				let resolved = resolve_nested_schema_with_refs(&temp_ref, doc, refs)?;
 				*ref_or_schema = ReferenceOr::Item(resolved);
 			}
 		},
 		SchemaKind::Not { not } => {
 			let temp_ref = (**not).clone();
-			let resolved = resolve_nested_schema(&temp_ref, doc)?;
+			let resolved = resolve_nested_schema_with_refs(&temp_ref, doc, refs)?;
 			*not = Box::new(ReferenceOr::Item(resolved));
 		},
 		SchemaKind::Any(any_schema) => {
@@ -172,7 +211,7 @@ fn resolve_nested_schema<'a>(
 					ReferenceOr::Reference { reference } => ReferenceOr::Reference { reference },
 					ReferenceOr::Item(boxed_item) => ReferenceOr::Item((*boxed_item).clone()),
 				};
-				let resolved_prop = resolve_nested_schema(&temp_prop_ref, doc)?;
+				let resolved_prop = resolve_nested_schema_with_refs(&temp_prop_ref, doc, refs)?;
 				*prop_ref_box = ReferenceOr::Item(Box::new(resolved_prop));
 			}
 			// Items
@@ -182,7 +221,7 @@ fn resolve_nested_schema<'a>(
 					ReferenceOr::Reference { reference } => ReferenceOr::Reference { reference },
 					ReferenceOr::Item(boxed_item) => ReferenceOr::Item((*boxed_item).clone()),
 				};
-				let resolved_items = resolve_nested_schema(&temp_items_ref, doc)?;
+				let resolved_items = resolve_nested_schema_with_refs(&temp_items_ref, doc, refs)?;
 				*items_ref_box = ReferenceOr::Item(Box::new(resolved_items));
 			}
 			// oneOf, allOf, anyOf
@@ -193,14 +232,14 @@ fn resolve_nested_schema<'a>(
 			] {
 				for ref_or_schema in vec_ref.iter_mut() {
 					let temp_ref = ref_or_schema.clone();
-					let resolved = resolve_nested_schema(&temp_ref, doc)?;
+					let resolved = resolve_nested_schema_with_refs(&temp_ref, doc, refs)?;
 					*ref_or_schema = ReferenceOr::Item(resolved);
 				}
 			}
 			// not
 			if let Some(not_box) = any_schema.not.as_mut() {
 				let temp_ref = (**not_box).clone();
-				let resolved = resolve_nested_schema(&temp_ref, doc)?;
+				let resolved = resolve_nested_schema_with_refs(&temp_ref, doc, refs)?;
 				*not_box = Box::new(ReferenceOr::Item(resolved));
 			}
 		},
@@ -208,6 +247,10 @@ fn resolve_nested_schema<'a>(
 		SchemaKind::Type(_) => {}, // Do nothing, already resolved.
 	}
 
+	if matches!(reference, ReferenceOr::Reference { .. }) {
+		refs.pop();
+	}
+
 	// 4. Return the modified owned schema
 	Ok(resolved_schema)
 }
@@ -355,9 +398,13 @@ pub(crate) fn parse_openapi_schema(
 												.push((name, schema, required));
 											Ok(())
 										},
-										_ => Err(ParseError::UnsupportedReference(
-											"parameter type COOKIE is not supported".to_string(),
-										)),
+										Parameter::Cookie { .. } => {
+											param_schemas
+												.entry(ParameterType::Cookie)
+												.or_insert_with(Vec::new)
+												.push((name, schema, required));
+											Ok(())
+										},
 									}
 								})?;
 
@@ -427,15 +474,27 @@ pub(crate) fn parse_openapi_schema(
 lazy_static::lazy_static! {
 	pub static ref BODY_NAME: String = "body".to_string();
 	pub static ref HEADER_NAME: String = "header".to_string();
+	pub static ref COOKIE_NAME: String = "cookie".to_string();
 	pub static ref QUERY_NAME: String = "query".to_string();
 	pub static ref PATH_NAME: String = "path".to_string();
 }
 
+// Cookie values are percent-encoded outside of the RFC 6265 cookie-octet set, so a value cannot
+// add or alter other cookies.
+const COOKIE_VALUE_ENCODE_SET: &percent_encoding::AsciiSet = &percent_encoding::CONTROLS
+	.add(b' ')
+	.add(b'"')
+	.add(b'%')
+	.add(b',')
+	.add(b';')
+	.add(b'\\');
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 enum ParameterType {
 	Header,
 	Query,
 	Path,
+	Cookie,
 }
 
 impl std::fmt::Display for ParameterType {
@@ -447,6 +506,7 @@ impl std::fmt::Display for ParameterType {
 				ParameterType::Header => "header",
 				ParameterType::Query => "query",
 				ParameterType::Path => "path",
+				ParameterType::Cookie => "cookie",
 			}
 		)
 	}
@@ -459,7 +519,7 @@ fn build_schema_property(
 	let p = item.parameter_data_ref();
 	let mut schema = match &p.format {
 		openapiv3::ParameterSchemaOrContent::Schema(reference) => {
-			let resolved_schema = resolve_schema(reference, open_api)?;
+			let resolved_schema = resolve_nested_schema(reference, open_api)?;
 			serde_json::to_value(resolved_schema)
 				.map_err(ParseError::SerdeError)?
 				.as_object()
@@ -507,39 +567,116 @@ pub struct Handler {
 	pub tools: Vec<(Tool, UpstreamOpenAPICall)>,
 	pub default_policies: BackendPolicies,
 	pub backend: SimpleBackend,
+	/// Pretty print JSON responses, rather than returning them compacted.
+	pub pretty_json: bool,
 }
 
 impl Handler {
+	#[instrument(
+		level = "debug",
+		skip_all,
+		fields(
+			name=%name,
+		),
+	)]
+	pub async fn call_tool(
+		&self,
+		name: &str,
+		args: Option<JsonObject>,
+	) -> Result<Content, anyhow::Error> {
+		let mut request = self.build_request(name, args)?;
+		let uri = request.uri().to_string();
+
+		// Make the request. Identical concurrent GETs share a single upstream call.
+		if request.method() == Method::GET {
+			request
+				.extensions_mut()
+				.insert(crate::http::coalesce::Coalesce);
+		}
+		let response = self
+			.client
+			.call_with_default_policies(
+				request.map(Into::into),
+				&self.backend,
+				self.default_policies.clone(),
+			)
+			.await?;
+
+		// Read response body
+		let status = response.status();
+		let content_type = ResponseContentType::from_headers(response.headers());
+		let body = axum::body::to_bytes(response.into_body(), 2_097_152).await?;
+
+		// Check if the request was successful
+		if status.is_success() {
+			Ok(content_type.into_content(uri, body, self.pretty_json))
+		} else {
+			Err(anyhow::anyhow!(
+				"Upstream API call for tool '{}' failed with status {}: {}",
+				name,
+				status,
+				content_type.describe(&body)
+			))
+		}
+	}
+
+	/// Validates the arguments of a tool call and describes the upstream request it would make,
+	/// without sending it.
+	pub fn preview_tool(
+		&self,
+		name: &str,
+		args: Option<JsonObject>,
+	) -> Result<RequestPreview, anyhow::Error> {
+		let request = self.build_request(name, args)?;
+		let headers = request
+			.headers()
+			.iter()
+			.map(|(k, v)| {
+				(
+					k.to_string(),
+					String::from_utf8_lossy(v.as_bytes()).into_owned(),
+				)
+			})
+			.collect();
+		let body = request.body();
+		Ok(RequestPreview {
+			method: request.method().to_string(),
+			url: request.uri().to_string(),
+			headers,
+			body: if body.is_empty() {
+				None
+			} else {
+				Some(serde_json::from_slice(body)?)
+			},
+		})
+	}
+
 	/// We need to use the parse the schema to get the correct args.
 	/// They are in the json schema under the "properties" key.
 	/// Body is under the "body" key.
 	/// Headers are under the "header" key.
+	/// Cookies are under the "cookie" key.
 	/// Query params are under the "query" key.
 	/// Path params are under the "path" key.
 	///
 	/// Query params need to be added to the url as query params.
 	/// Headers need to be added to the request headers.
+	/// Cookies need to be added to the Cookie header.
 	/// Body needs to be added to the request body.
 	/// Path params need to be added to the template params in the path.
-	#[instrument(
-		level = "debug",
-		skip_all,
-		fields(
-			name=%name,
-		),
-	)]
-	pub async fn call_tool(
+	fn build_request(
 		&self,
 		name: &str,
 		args: Option<JsonObject>,
-	) -> Result<String, anyhow::Error> {
-		let (_tool, info) = self
+	) -> Result<http::Request<Vec<u8>>, anyhow::Error> {
+		let (tool, info) = self
 			.tools
 			.iter()
 			.find(|(t, _info)| t.name == name)
 			.ok_or_else(|| anyhow::anyhow!("tool {} not found", name))?;
 
-		let args = args.unwrap_or_default();
+		let mut args = args.unwrap_or_default();
+		apply_parameter_schema(&tool.input_schema, &mut args)?;
 
 		// --- Parameter Extraction ---
 		let path_params = args
@@ -557,27 +694,20 @@ impl Handler {
 			.and_then(Value::as_object)
 			.cloned()
 			.unwrap_or_default();
+		let cookie_params = args
+			.get(&*COOKIE_NAME)
+			.and_then(Value::as_object)
+			.cloned()
+			.unwrap_or_default();
 		let body_value = args.get(&*BODY_NAME).cloned();
 
 		// --- URL Construction ---
 		let mut path = info.path.clone();
 		// Substitute path parameters into the path template
+		// apply_parameter_schema has already turned every path parameter into a string
 		for (key, value) in &path_params {
-			match value {
-				Value::String(s_val) => {
-					path = path.replace(&format!("{{{key}}}"), s_val);
-				},
-				Value::Number(n_val) => {
-					path = path.replace(&format!("{{{key}}}"), n_val.to_string().as_str());
-				},
-				_ => {
-					tracing::warn!(
-						"Path parameter '{}' for tool '{}' is not a string (value: {:?}), skipping substitution",
-						key,
-						name,
-						value
-					);
-				},
+			if let Some(s_val) = value.as_str() {
+				path = path.replace(&format!("{{{key}}}"), s_val);
 			}
 		}
 
@@ -625,9 +755,12 @@ impl Handler {
 
 		let uri = format!("{base_url}{query_string}");
 		let mut headers = HeaderMap::new();
-		let mut rb = http::Request::builder().method(method).uri(uri);
+		let mut rb = http::Request::builder().method(method).uri(&uri);
 
-		rb = rb.header(ACCEPT, HeaderValue::from_static("application/json"));
+		rb = rb.header(
+			ACCEPT,
+			HeaderValue::from_static("application/json, */*;q=0.8"),
+		);
 		for (key, value) in &header_params {
 			if let Some(s_val) = value.as_str() {
 				match (
@@ -658,6 +791,28 @@ impl Handler {
 				);
 			}
 		}
+		let mut cookies = Vec::new();
+		for (key, value) in &cookie_params {
+			if let Some(s_val) = value.as_str() {
+				let s_val = percent_encoding::utf8_percent_encode(s_val, COOKIE_VALUE_ENCODE_SET);
+				cookies.push(format!("{key}={s_val}"));
+			} else {
+				tracing::warn!(
+					"Cookie parameter '{}' for tool '{}' is not a string (value: {:?}), skipping",
+					key,
+					name,
+					value
+				);
+			}
+		}
+		if !cookies.is_empty() {
+			match HeaderValue::from_str(&cookies.join("; ")) {
+				Ok(h_value) => {
+					rb = rb.header(http::header::COOKIE, h_value);
+				},
+				Err(_) => tracing::warn!("Invalid cookie value for tool '{}', skipping", name),
+			}
+		}
 		// Build request body
 		let body = if let Some(body_val) = body_value {
 			rb = rb.header(CONTENT_TYPE, HeaderValue::from_static("application/json"));
@@ -667,39 +822,187 @@ impl Handler {
 		};
 
 		// Build the final request
-		let mut request = rb
-			.body(body.into())
-			.map_err(|e| anyhow::anyhow!("Failed to build request: {}", e))?;
+		rb.body(body)
+			.map_err(|e| anyhow::anyhow!("Failed to build request: {}", e))
+	}
 
-		// Make the request
-		let response = self
-			.client
-			.call_with_default_policies(request, &self.backend, self.default_policies.clone())
-			.await?;
+	pub fn tools(&self) -> Vec<Tool> {
+		self.tools.clone().into_iter().map(|(t, _)| t).collect()
+	}
+}
 
-		// Read response body
-		let status = response.status();
-		let body = String::from_utf8(
-			axum::body::to_bytes(response.into_body(), 2_097_152)
-				.await?
-				.to_vec(),
-		)?;
+/// DRY_RUN_ARGUMENT is a reserved tool call argument. When it is `true`, the arguments are validated
+/// and the upstream request the call would make is returned as a JSON [RequestPreview], instead of
+/// being sent.
+pub const DRY_RUN_ARGUMENT: &str = "dry_run";
+
+/// RequestPreview describes the upstream request a tool call would make.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RequestPreview {
+	pub method: String,
+	pub url: String,
+	pub headers: std::collections::BTreeMap<String, String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub body: Option<Value>,
+}
 
-		// Check if the request was successful
-		if status.is_success() {
-			Ok(body)
+/// Fills in the declared defaults of omitted path, query, header and cookie parameters, checks that all
+/// required arguments are present, and converts path parameters to strings, so a missing or mistyped
+/// parameter is not sent upstream as a malformed request.
+fn apply_parameter_schema(schema: &JsonObject, args: &mut JsonObject) -> Result<(), ArgumentError> {
+	let Some(groups) = schema.get("properties").and_then(Value::as_object) else {
+		return Ok(());
+	};
+	for group in [&*PATH_NAME, &*QUERY_NAME, &*HEADER_NAME, &*COOKIE_NAME] {
+		let Some(props) = groups
+			.get(group)
+			.and_then(|g| g.get("properties"))
+			.and_then(Value::as_object)
+		else {
+			continue;
+		};
+		for (name, prop) in props {
+			let Some(default) = prop.get("default") else {
+				continue;
+			};
+			if let Some(values) = args
+				.entry(group.clone())
+				.or_insert_with(|| Value::Object(Default::default()))
+				.as_object_mut()
+			{
+				values
+					.entry(name.clone())
+					.or_insert_with(|| default.clone());
+			}
+		}
+	}
+
+	if let Some(values) = args.get_mut(&*PATH_NAME).and_then(Value::as_object_mut) {
+		let props = groups
+			.get(&*PATH_NAME)
+			.and_then(|g| g.get("properties"))
+			.and_then(Value::as_object);
+		for (name, value) in values.iter_mut() {
+			let declared = props
+				.and_then(|p| p.get(name))
+				.and_then(|p| p.get("type"))
+				.and_then(Value::as_str);
+			// Numbers and booleans are only accepted where the schema declares them; anything else
+			// would be substituted into the path as JSON.
+			let s_val = match (&*value, declared) {
+				(Value::String(_), _) => continue,
+				(Value::Number(n), Some("integer" | "number")) => n.to_string(),
+				(Value::Bool(b), Some("boolean")) => b.to_string(),
+				_ => return Err(ArgumentError::InvalidPathParameter(name.clone())),
+			};
+			*value = Value::String(s_val);
+		}
+	}
+
+	for group in required(schema) {
+		let Some(values) = args.get(group) else {
+			return Err(ArgumentError::MissingGroup(group.to_string()));
+		};
+		// The request body is validated by the upstream
+		if group == BODY_NAME.as_str() {
+			continue;
+		}
+		let Some(group_schema) = groups.get(group).and_then(Value::as_object) else {
+			continue;
+		};
+		for name in required(group_schema) {
+			if values.get(name).is_none_or(Value::is_null) {
+				return Err(ArgumentError::MissingParameter {
+					group: group.to_string(),
+					name: name.to_string(),
+				});
+			}
+		}
+	}
+	Ok(())
+}
+
+fn required(schema: &JsonObject) -> impl Iterator<Item = &str> {
+	schema
+		.get("required")
+		.and_then(Value::as_array)
+		.into_iter()
+		.flatten()
+		.filter_map(Value::as_str)
+}
+
+/// The media type of an upstream response, which decides how it is returned to the MCP client.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ResponseContentType {
+	Json,
+	Text,
+	Binary(String),
+}
+
+impl ResponseContentType {
+	fn from_headers(headers: &HeaderMap) -> Self {
+		let Some(ct) = headers.get(CONTENT_TYPE).and_then(|v| v.to_str().ok()) else {
+			// Without a content type, we can only guess; the body is inspected when it is rendered.
+			return ResponseContentType::Text;
+		};
+		let mime = ct
+			.split(';')
+			.next()
+			.unwrap_or_default()
+			.trim()
+			.to_ascii_lowercase();
+		let (kind, subtype) = mime.split_once('/').unwrap_or((mime.as_str(), ""));
+		if subtype == "json" || subtype.ends_with("+json") {
+			ResponseContentType::Json
+		} else if kind == "text"
+			|| subtype == "xml"
+			|| subtype.ends_with("+xml")
+			|| subtype == "x-www-form-urlencoded"
+		{
+			ResponseContentType::Text
 		} else {
-			Err(anyhow::anyhow!(
-				"Upstream API call for tool '{}' failed with status {}: {}",
-				name,
-				status,
-				body
-			))
+			ResponseContentType::Binary(mime)
 		}
 	}
 
-	pub fn tools(&self) -> Vec<Tool> {
-		self.tools.clone().into_iter().map(|(t, _)| t).collect()
+	fn into_content(self, uri: String, body: bytes::Bytes, pretty_json: bool) -> Content {
+		use base64::Engine;
+		match self {
+			ResponseContentType::Json => match serde_json::from_slice::<Value>(&body) {
+				Ok(v) if pretty_json => Content::text(serde_json::to_string_pretty(&v).unwrap_or_default()),
+				Ok(v) => Content::text(v.to_string()),
+				// Mislabeled; return it as is
+				Err(_) => ResponseContentType::Text.into_content(uri, body, pretty_json),
+			},
+			ResponseContentType::Text => {
+				match String::from_utf8(body.to_vec()) {
+					Ok(s) => Content::text(s),
+					Err(_) => ResponseContentType::Binary("application/octet-stream".to_string())
+						.into_content(uri, body, pretty_json),
+				}
+			},
+			ResponseContentType::Binary(mime) => {
+				let data = base64::engine::general_purpose::STANDARD.encode(&body);
+				if mime.starts_with("image/") {
+					Content::image(data, mime)
+				} else {
+					Content::resource(ResourceContents::BlobResourceContents {
+						uri,
+						mime_type: Some(mime),
+						blob: data,
+					})
+				}
+			},
+		}
+	}
+
+	/// A readable form of an error response body.
+	fn describe(&self, body: &[u8]) -> String {
+		match (self, std::str::from_utf8(body)) {
+			(ResponseContentType::Json | ResponseContentType::Text, Ok(s)) => s.to_string(),
+			(ResponseContentType::Binary(mime), _) => format!("<{} bytes of {mime}>", body.len()),
+			(_, Err(_)) => format!("<{} bytes of binary data>", body.len()),
+		}
 	}
 }
 
@@ -8,7 +8,7 @@ use crate::{ProxyInputs, mcp};
 use agent_core::{drain, metrics, strng};
 use hickory_resolver::config::{ResolverConfig, ResolverOpts};
 use prometheus_client::registry::Registry;
-use rmcp::model::Tool;
+use rmcp::model::{RawContent, Tool};
 use serde_json::json;
 use wiremock::matchers::{body_json, header, method, path, query_param};
 use wiremock::{Mock, MockServer, ResponseTemplate};
@@ -24,6 +24,7 @@ async fn setup() -> (MockServer, Handler) {
 		&client::Config {
 			resolver_cfg: ResolverConfig::default(),
 			resolver_opts: ResolverOpts::default(),
+			serve_stale: Default::default(),
 		},
 		None,
 	);
@@ -37,6 +38,7 @@ async fn setup() -> (MockServer, Handler) {
 		))),
 		upstream: client.clone(),
 		ca: None,
+		coalescer: Default::default(),
 
 		mcp_state: mcp::sse::App::new(
 			stores.clone(),
@@ -139,6 +141,7 @@ async fn setup() -> (MockServer, Handler) {
 			(test_tool_post, upstream_call_post),
 		],
 		default_policies: BackendPolicies::default(),
+		pretty_json: false,
 		backend: SimpleBackend::Opaque(
 			strng::literal!("dummy"),
 			Target::Hostname(
@@ -151,6 +154,13 @@ async fn setup() -> (MockServer, Handler) {
 	(server, handler)
 }
 
+fn text(c: Content) -> String {
+	match c.raw {
+		RawContent::Text(t) => t.text,
+		other => panic!("expected text content, got {other:?}"),
+	}
+}
+
 #[tokio::test]
 async fn test_call_tool_get_simple_success() {
 	let (server, handler) = setup().await;
@@ -170,7 +180,7 @@ async fn test_call_tool_get_simple_success() {
 		.await;
 
 	assert!(result.is_ok());
-	assert_eq!(result.unwrap(), expected_response.to_string());
+	assert_eq!(text(result.unwrap()), expected_response.to_string());
 }
 
 #[tokio::test]
@@ -195,7 +205,7 @@ async fn test_call_tool_get_with_query() {
 		.await;
 
 	assert!(result.is_ok());
-	assert_eq!(result.unwrap(), expected_response.to_string());
+	assert_eq!(text(result.unwrap()), expected_response.to_string());
 }
 
 #[tokio::test]
@@ -219,7 +229,7 @@ async fn test_call_tool_get_with_header() {
 		.await;
 
 	assert!(result.is_ok());
-	assert_eq!(result.unwrap(), expected_response.to_string());
+	assert_eq!(text(result.unwrap()), expected_response.to_string());
 }
 
 #[tokio::test]
@@ -242,7 +252,7 @@ async fn test_call_tool_post_with_body() {
 		.await;
 
 	assert!(result.is_ok());
-	assert_eq!(result.unwrap(), expected_response.to_string());
+	assert_eq!(text(result.unwrap()), expected_response.to_string());
 }
 
 #[tokio::test]
@@ -273,7 +283,7 @@ async fn test_call_tool_post_all_params() {
 		.await;
 
 	assert!(result.is_ok());
-	assert_eq!(result.unwrap(), expected_response.to_string());
+	assert_eq!(text(result.unwrap()), expected_response.to_string());
 }
 
 #[tokio::test]
@@ -318,6 +328,151 @@ async fn test_call_tool_upstream_error() {
 	assert!(err.to_string().contains(&error_response.to_string()));
 }
 
+#[tokio::test]
+async fn test_call_tool_upstream_error_text() {
+	let (server, handler) = setup().await;
+
+	Mock::given(method("GET"))
+		.and(path("/users/1"))
+		.respond_with(ResponseTemplate::new(503).set_body_string("upstream unavailable"))
+		.mount(&server)
+		.await;
+
+	let args = json!({ "path": { "user_id": "1" } });
+	let err = handler
+		.call_tool("get_user", Some(args.as_object().unwrap().clone()))
+		.await
+		.unwrap_err();
+	assert!(err.to_string().ends_with(": upstream unavailable"), "{err}");
+}
+
+#[tokio::test]
+async fn test_call_tool_text_response() {
+	let (server, handler) = setup().await;
+
+	Mock::given(method("GET"))
+		.and(path("/users/1"))
+		.respond_with(
+			ResponseTemplate::new(200).set_body_raw("id,name\n1,Test User\n", "text/csv; charset=utf-8"),
+		)
+		.mount(&server)
+		.await;
+
+	let args = json!({ "path": { "user_id": "1" } });
+	let result = handler
+		.call_tool("get_user", Some(args.as_object().unwrap().clone()))
+		.await
+		.unwrap();
+	assert_eq!(text(result), "id,name\n1,Test User\n");
+}
+
+#[tokio::test]
+async fn test_call_tool_binary_response() {
+	let (server, handler) = setup().await;
+	let pdf = vec![0x25, 0x50, 0x44, 0x46, 0xff, 0x00];
+
+	Mock::given(method("GET"))
+		.and(path("/users/1"))
+		.respond_with(ResponseTemplate::new(200).set_body_raw(pdf.clone(), "application/pdf"))
+		.mount(&server)
+		.await;
+
+	let args = json!({ "path": { "user_id": "1" } });
+	let result = handler
+		.call_tool("get_user", Some(args.as_object().unwrap().clone()))
+		.await
+		.unwrap();
+	let RawContent::Resource(res) = result.raw else {
+		panic!("expected an embedded resource");
+	};
+	let ResourceContents::BlobResourceContents {
+		mime_type, blob, ..
+	} = res.resource
+	else {
+		panic!("expected a blob");
+	};
+	assert_eq!(mime_type.as_deref(), Some("application/pdf"));
+	use base64::Engine;
+	assert_eq!(
+		base64::engine::general_purpose::STANDARD
+			.decode(blob)
+			.unwrap(),
+		pdf
+	);
+}
+
+#[tokio::test]
+async fn test_call_tool_pretty_json() {
+	let (server, mut handler) = setup().await;
+	handler.pretty_json = true;
+	let expected_response = json!({ "id": "1" });
+
+	Mock::given(method("GET"))
+		.and(path("/users/1"))
+		.respond_with(ResponseTemplate::new(200).set_body_json(&expected_response))
+		.mount(&server)
+		.await;
+
+	let args = json!({ "path": { "user_id": "1" } });
+	let result = handler
+		.call_tool("get_user", Some(args.as_object().unwrap().clone()))
+		.await
+		.unwrap();
+	assert_eq!(
+		text(result),
+		serde_json::to_string_pretty(&expected_response).unwrap()
+	);
+}
+
+#[tokio::test]
+async fn test_call_tool_missing_required_path_param() {
+	let (server, handler) = setup().await;
+
+	Mock::given(method("GET"))
+		.respond_with(ResponseTemplate::new(200))
+		.expect(0)
+		.mount(&server)
+		.await;
+
+	let err = handler
+		.call_tool("get_user", Some(json!({}).as_object().unwrap().clone()))
+		.await
+		.unwrap_err();
+	assert!(matches!(
+		err.downcast_ref::<ArgumentError>(),
+		Some(ArgumentError::MissingGroup(g)) if g == "path"
+	));
+
+	let args = json!({ "path": {}, "query": { "verbose": "true" } });
+	let err = handler
+		.call_tool("get_user", Some(args.as_object().unwrap().clone()))
+		.await
+		.unwrap_err();
+	assert_eq!(err.to_string(), "missing required path parameter 'user_id'");
+}
+
+#[tokio::test]
+async fn test_call_tool_applies_default_query_param() {
+	let (server, mut handler) = setup().await;
+	let schema = Arc::make_mut(&mut handler.tools[0].0.input_schema);
+	schema["properties"]["query"]["properties"]["verbose"]["default"] = json!("false");
+
+	Mock::given(method("GET"))
+		.and(path("/users/1"))
+		.and(query_param("verbose", "false"))
+		.respond_with(ResponseTemplate::new(200).set_body_json(json!({ "id": "1" })))
+		.expect(1)
+		.mount(&server)
+		.await;
+
+	let args = json!({ "path": { "user_id": "1" } });
+	let result = handler
+		.call_tool("get_user", Some(args.as_object().unwrap().clone()))
+		.await
+		.unwrap();
+	assert_eq!(text(result), json!({ "id": "1" }).to_string());
+}
+
 #[tokio::test]
 async fn test_call_tool_invalid_header_value() {
 	let (server, handler) = setup().await;
@@ -342,10 +497,193 @@ async fn test_call_tool_invalid_header_value() {
 		.call_tool("get_user", Some(args.as_object().unwrap().clone()))
 		.await;
 	assert!(result.is_ok()); // Check that the call still succeeds despite the bad header
-	assert_eq!(result.unwrap(), json!({ "id": user_id }).to_string());
+	assert_eq!(text(result.unwrap()), json!({ "id": user_id }).to_string());
 	// We can't easily assert the log message here, but manual inspection of logs would show the warning.
 }
 
+#[tokio::test]
+async fn test_call_tool_with_cookies() {
+	let (server, handler) = setup().await;
+
+	Mock::given(method("GET"))
+		.and(path("/users/1"))
+		.and(header(
+			"cookie",
+			"session=abc123; theme=dark%3B%20admin=true",
+		))
+		.respond_with(ResponseTemplate::new(200).set_body_json(json!({ "id": "1" })))
+		.expect(1)
+		.mount(&server)
+		.await;
+
+	let args = json!({
+		"path": { "user_id": "1" },
+		// The non-string value is skipped, and the separator in theme is encoded
+		"cookie": { "session": "abc123", "theme": "dark; admin=true", "visits": 3 }
+	});
+	let result = handler
+		.call_tool("get_user", Some(args.as_object().unwrap().clone()))
+		.await
+		.unwrap();
+	assert_eq!(text(result), json!({ "id": "1" }).to_string());
+}
+
+#[tokio::test]
+async fn test_preview_tool() {
+	let (server, handler) = setup().await;
+
+	Mock::given(method("GET"))
+		.respond_with(ResponseTemplate::new(200))
+		.expect(0)
+		.mount(&server)
+		.await;
+
+	let args = json!({
+		"path": { "user_id": "123" },
+		"query": { "verbose": "true" },
+		"header": { "X-Request-ID": "abc" }
+	});
+	let preview = handler
+		.preview_tool("get_user", Some(args.as_object().unwrap().clone()))
+		.unwrap();
+	assert_eq!(preview.method, "GET");
+	assert_eq!(
+		preview.url,
+		format!(
+			"http://{}/users/123?verbose=true",
+			handler.backend.hostport()
+		)
+	);
+	assert_eq!(
+		preview.headers.get("x-request-id").map(String::as_str),
+		Some("abc")
+	);
+	assert_eq!(preview.body, None);
+
+	// Validation runs as it would for a real call
+	assert!(
+		handler
+			.preview_tool("get_user", Some(json!({}).as_object().unwrap().clone()))
+			.is_err()
+	);
+}
+
+#[tokio::test]
+async fn test_call_tool_coalesces_concurrent_gets() {
+	let (server, handler) = setup().await;
+
+	Mock::given(method("GET"))
+		.and(path("/users/1"))
+		.respond_with(
+			ResponseTemplate::new(200)
+				.set_body_json(json!({ "id": "1" }))
+				.set_delay(std::time::Duration::from_millis(200)),
+		)
+		.expect(1)
+		.mount(&server)
+		.await;
+
+	let args = json!({ "path": { "user_id": "1" } });
+	let calls =
+		(0..5).map(|_| handler.call_tool("get_user", Some(args.as_object().unwrap().clone())));
+	for result in futures::future::join_all(calls).await {
+		assert_eq!(text(result.unwrap()), json!({ "id": "1" }).to_string());
+	}
+}
+
+#[tokio::test]
+async fn test_call_tool_coalesced_errors() {
+	let (server, handler) = setup().await;
+
+	Mock::given(method("GET"))
+		.and(path("/users/1"))
+		.respond_with(
+			ResponseTemplate::new(500)
+				.set_body_string("boom")
+				.set_delay(std::time::Duration::from_millis(200)),
+		)
+		.expect(1)
+		.mount(&server)
+		.await;
+
+	let args = json!({ "path": { "user_id": "1" } });
+	let calls =
+		(0..3).map(|_| handler.call_tool("get_user", Some(args.as_object().unwrap().clone())));
+	for result in futures::future::join_all(calls).await {
+		assert!(
+			result
+				.unwrap_err()
+				.to_string()
+				.contains("failed with status 500")
+		);
+	}
+}
+
+#[tokio::test]
+async fn test_call_tool_does_not_coalesce_across_policies() {
+	let (server, handler) = setup().await;
+
+	for key in ["a", "b"] {
+		Mock::given(method("GET"))
+			.and(path("/users/1"))
+			.and(header("authorization", format!("Bearer {key}")))
+			.respond_with(
+				ResponseTemplate::new(200)
+					.set_body_json(json!({ "key": key }))
+					.set_delay(std::time::Duration::from_millis(200)),
+			)
+			.expect(1)
+			.mount(&server)
+			.await;
+	}
+	// Same backend and arguments, but each handler authenticates with its own key
+	let with_key = |key: &str| Handler {
+		prefix: handler.prefix.clone(),
+		client: handler.client.clone(),
+		tools: handler.tools.clone(),
+		default_policies: BackendPolicies {
+			backend_auth: Some(crate::http::auth::BackendAuth::Key(
+				secrecy::SecretString::new(key.into()),
+			)),
+			..Default::default()
+		},
+		backend: handler.backend.clone(),
+		pretty_json: false,
+	};
+	let (a, b) = (with_key("a"), with_key("b"));
+
+	let args = json!({ "path": { "user_id": "1" } });
+	let (ra, rb) = tokio::join!(
+		a.call_tool("get_user", Some(args.as_object().unwrap().clone())),
+		b.call_tool("get_user", Some(args.as_object().unwrap().clone())),
+	);
+	assert_eq!(text(ra.unwrap()), json!({ "key": "a" }).to_string());
+	assert_eq!(text(rb.unwrap()), json!({ "key": "b" }).to_string());
+}
+
+#[tokio::test]
+async fn test_call_tool_does_not_coalesce_posts() {
+	let (server, handler) = setup().await;
+
+	Mock::given(method("POST"))
+		.and(path("/users"))
+		.respond_with(
+			ResponseTemplate::new(201)
+				.set_body_json(json!({ "id": "new" }))
+				.set_delay(std::time::Duration::from_millis(100)),
+		)
+		.expect(3)
+		.mount(&server)
+		.await;
+
+	let args = json!({ "body": { "name": "a", "email": "a@example.com" } });
+	let calls =
+		(0..3).map(|_| handler.call_tool("create_user", Some(args.as_object().unwrap().clone())));
+	for result in futures::future::join_all(calls).await {
+		assert!(result.is_ok());
+	}
+}
+
 #[tokio::test]
 async fn test_call_tool_invalid_query_param_value() {
 	let (server, handler) = setup().await;
@@ -370,45 +708,135 @@ async fn test_call_tool_invalid_query_param_value() {
 		.call_tool("get_user", Some(args.as_object().unwrap().clone()))
 		.await;
 	assert!(result.is_ok());
-	assert_eq!(result.unwrap(), json!({ "id": user_id }).to_string());
+	assert_eq!(text(result.unwrap()), json!({ "id": user_id }).to_string());
 }
 
 #[tokio::test]
 async fn test_call_tool_invalid_path_param_value() {
 	let (server, handler) = setup().await;
 
-	let invalid_user_id = json!(12345); // Not a string
-	// Mock is set up for the *literal* path, as substitution will fail
+	// user_id is declared as a string, so a number is rejected before any request is sent
 	Mock::given(method("GET"))
-		.and(path("/users/{user_id}")) // Path doesn't get substituted
-		.respond_with(
-			ResponseTemplate::new(404) // Or whatever the server does with a literal {user_id}
-				.set_body_string("Not Found - Literal Path"),
-		)
+		.respond_with(ResponseTemplate::new(200))
+		.expect(0)
 		.mount(&server)
 		.await;
 
 	let args = json!({
-			"path": { "user_id": invalid_user_id }
+			"path": { "user_id": 12345 }
 	});
 
-	// The call might succeed at the HTTP level but might return an error from the server,
-	// or potentially fail if the path is fundamentally invalid after non-substitution.
-	// Here we assume the server returns 404 for the literal path.
 	let result = handler
 		.call_tool("get_user", Some(args.as_object().unwrap().clone()))
 		.await;
 
-	// Depending on server behavior for the literal path, this might be Ok or Err.
-	// If server returns 404 for the literal path:
-	assert!(result.is_err());
+	let err = result.unwrap_err();
 	assert!(
-		result
-			.unwrap_err()
-			.to_string()
-			.contains("failed with status 404 Not Found")
+		matches!(
+			err.downcast_ref::<ArgumentError>(),
+			Some(ArgumentError::InvalidPathParameter(p)) if p == "user_id"
+		),
+		"{err}"
+	);
+}
+
+fn create_user_spec(body_schema: serde_json::Value, schemas: serde_json::Value) -> OpenAPI {
+	serde_json::from_value(json!({
+		"openapi": "3.0.0",
+		"info": {"title": "users", "version": "1.0"},
+		"paths": {
+			"/users": {
+				"post": {
+					"operationId": "create_user",
+					"requestBody": {
+						"required": true,
+						"content": {
+							"application/json": {"schema": body_schema}
+						}
+					},
+					"responses": {}
+				}
+			}
+		},
+		"components": {"schemas": schemas}
+	}))
+	.unwrap()
+}
+
+#[test]
+fn test_parse_schema_ref_matches_inline() {
+	let address = json!({
+		"type": "object",
+		"properties": {"city": {"type": "string"}},
+		"required": ["city"]
+	});
+	let inline = create_user_spec(
+		json!({
+			"type": "object",
+			"properties": {
+				"name": {"type": "string"},
+				"address": address.clone(),
+			},
+			"required": ["name"]
+		}),
+		json!({}),
+	);
+	let referenced = create_user_spec(
+		json!({"$ref": "#/components/schemas/User"}),
+		json!({
+			"User": {
+				"type": "object",
+				"properties": {
+					"name": {"type": "string"},
+					"address": {"$ref": "#/components/schemas/Address"},
+				},
+				"required": ["name"]
+			},
+			"Address": address,
+		}),
 	);
 
-	// If the request *itself* failed before sending (e.g., invalid URL formed),
-	// the error might be different.
+	let inline = parse_openapi_schema(&inline).unwrap();
+	let referenced = parse_openapi_schema(&referenced).unwrap();
+	assert_eq!(inline.len(), 1);
+	assert_eq!(referenced.len(), 1);
+	assert_eq!(inline[0].0.input_schema, referenced[0].0.input_schema);
+	let schema = serde_json::to_string(&referenced[0].0.input_schema).unwrap();
+	assert!(!schema.contains("$ref"), "{schema}");
+}
+
+#[test]
+fn test_parse_schema_recursive_ref() {
+	let spec = create_user_spec(
+		json!({"$ref": "#/components/schemas/Node"}),
+		json!({
+			"Node": {
+				"type": "object",
+				"properties": {
+					"name": {"type": "string"},
+					"children": {
+						"type": "array",
+						"items": {"$ref": "#/components/schemas/Node"}
+					},
+				}
+			}
+		}),
+	);
+	let tools = parse_openapi_schema(&spec).unwrap();
+	let schema = serde_json::to_value(&tools[0].0.input_schema).unwrap();
+	// The first level is expanded, the recursive reference is left unconstrained
+	assert_eq!(
+		schema["properties"]["body"]["properties"]["children"]["items"],
+		json!({})
+	);
+	assert_eq!(
+		schema["properties"]["body"]["properties"]["name"],
+		json!({"type": "string"})
+	);
+}
+
+#[test]
+fn test_parse_schema_missing_ref() {
+	let spec = create_user_spec(json!({"$ref": "#/components/schemas/Missing"}), json!({}));
+	assert!(parse_openapi_schema(&spec).is_err());
 }
@@ -26,25 +26,34 @@ use tracing::instrument;
 
 use crate::cel::ContextBuilder;
 use crate::http::jwt::Claims;
+use crate::mcp::openapi::ArgumentError;
 use crate::mcp::rbac;
 use crate::mcp::rbac::{Identity, RuleSets};
-use crate::mcp::sse::{MCPInfo, McpBackendGroup};
+use crate::mcp::sse::{MCPInfo, McpBackendGroup, MergedPrompt, MergedResource, MergedTool};
 use crate::proxy::httpproxy::PolicyClient;
 use crate::store::Stores;
 use crate::telemetry::log::AsyncLog;
 use crate::telemetry::trc::TraceParent;
 use crate::transport::stream::{TCPConnectionInfo, TLSConnectionInfo};
-use crate::types::agent::{McpAuthorization, McpBackend};
+use crate::types::agent::{McpAuthorization, McpBackend, McpDelimiter, McpTargetSpec};
 use crate::{ProxyInputs, client};
 
 type McpError = ErrorData;
 
+/// The maximum number of tools returned by a single tools/list call.
+const TOOLS_PAGE_SIZE: usize = 100;
+/// The maximum number of resources returned by a single resources/list call.
+const RESOURCES_PAGE_SIZE: usize = 100;
+/// The maximum number of prompts returned by a single prompts/list call.
+const PROMPTS_PAGE_SIZE: usize = 100;
+/// The protocol versions the gateway serves, oldest first.
+const SUPPORTED_PROTOCOL_VERSIONS: &[ProtocolVersion] =
+	&[ProtocolVersion::V_2024_11_05, ProtocolVersion::V_2025_03_26];
+
 pub mod metrics;
 mod pool;
 pub mod upstream;
 
-const DELIMITER: &str = "_";
-
 #[derive(Clone, Debug)]
 pub struct RqCtx {
 	identity: Identity,
@@ -66,14 +75,55 @@ impl RqCtx {
 	}
 }
 
+/// ListCache holds a merged list of all targets, such as their tools, so listing does not fan out to
+/// every target on each call. It is cleared when a target reports that the list changed, or is removed.
+/// A configuration change creates a new Relay, and with it empty caches.
+#[derive(Debug)]
+pub(crate) struct ListCache<T>(Arc<std::sync::RwLock<Option<Arc<Vec<T>>>>>);
+
+pub(crate) type ToolCache = ListCache<MergedTool>;
+pub(crate) type PromptCache = ListCache<MergedPrompt>;
+
+impl<T> Default for ListCache<T> {
+	fn default() -> Self {
+		Self(Default::default())
+	}
+}
+
+impl<T> Clone for ListCache<T> {
+	fn clone(&self) -> Self {
+		Self(self.0.clone())
+	}
+}
+
+impl<T> ListCache<T> {
+	fn get(&self) -> Option<Arc<Vec<T>>> {
+		self.0.read().expect("mutex poisoned").clone()
+	}
+
+	fn set(&self, items: Arc<Vec<T>>) {
+		*self.0.write().expect("mutex poisoned") = Some(items);
+	}
+
+	pub(crate) fn invalidate(&self) {
+		*self.0.write().expect("mutex poisoned") = None;
+	}
+}
+
 #[derive(Clone)]
 pub struct Relay {
 	pool: Arc<RwLock<pool::ConnectionPool>>,
+	backend: McpBackendGroup,
+	tools: ToolCache,
+	prompts: PromptCache,
 	metrics: Arc<metrics::Metrics>,
 	policies: RuleSets,
 	// If we have 1 target only, we don't prefix everything with 'target_'.
 	// Else this is empty
 	default_target_name: Option<String>,
+	// All target names, used to map a prefixed resource name back to its target
+	target_names: Vec<String>,
+	delimiter: McpDelimiter,
 }
 
 impl Relay {
@@ -89,11 +139,26 @@ impl Relay {
 		} else {
 			Some(backend.targets[0].name.to_string())
 		};
+		let target_names = backend.targets.iter().map(|t| t.name.to_string()).collect();
+		let delimiter = backend.delimiter;
+		let tools = ToolCache::default();
+		let prompts = PromptCache::default();
 		Self {
-			pool: Arc::new(RwLock::new(pool::ConnectionPool::new(pi, client, backend))),
+			pool: Arc::new(RwLock::new(pool::ConnectionPool::new(
+				pi,
+				client,
+				backend.clone(),
+				tools.clone(),
+				prompts.clone(),
+			))),
+			backend,
+			tools,
+			prompts,
 			metrics,
 			policies,
 			default_target_name,
+			target_names,
+			delimiter,
 		}
 	}
 
@@ -104,18 +169,129 @@ impl Relay {
 		if let Some(default) = self.default_target_name.as_ref() {
 			Ok((default.as_str(), res))
 		} else {
-			res
-				.split_once(DELIMITER)
+			split_resource_name(&self.target_names, self.delimiter, res)
 				.ok_or(McpError::invalid_request("invalid resource name", None))
 		}
 	}
 
 	fn resource_name(&self, target: &str, name: &str) -> String {
-		if self.default_target_name.is_none() {
-			format!("{target}{DELIMITER}{name}")
-		} else {
-			name.to_string()
+		self.backend.resource_name(target, name)
+	}
+
+	/// Lists the tools of every connected target, following each target's pagination, and merges
+	/// them. The second value is false if any target failed to list its tools.
+	async fn fetch_tools(&self, rq_ctx: &RqCtx) -> Result<(Vec<MergedTool>, bool), McpError> {
+		let mut pool = self.pool.write().await;
+		let connections = pool
+			.list()
+			.await
+			.map_err(|e| McpError::internal_error(format!("Failed to list connections: {e}"), None))?;
+		let all = connections.into_iter().map(|(name, svc_arc)| async move {
+			let mut tools = vec![];
+			let mut cursor = None;
+			loop {
+				let request = cursor.map(|cursor| PaginatedRequestParam {
+					cursor: Some(cursor),
+				});
+				let r = svc_arc.list_tools(request, rq_ctx).await?;
+				tools.extend(r.tools);
+				cursor = r.next_cursor;
+				if cursor.is_none() {
+					break;
+				}
+			}
+			Ok::<_, upstream::UpstreamError>((name, tools))
+		});
+
+		let (results, errors): (Vec<_>, Vec<_>) = futures::future::join_all(all)
+			.await
+			.into_iter()
+			.partition_result();
+		Ok((self.backend.merge_tools(results), errors.is_empty()))
+	}
+
+	/// Lists the resources of every connected target, following each target's pagination, and merges
+	/// them. Targets that fail to list their resources are left out.
+	async fn fetch_resources(&self, rq_ctx: &RqCtx) -> Result<Vec<MergedResource>, McpError> {
+		let mut pool = self.pool.write().await;
+		let connections = pool
+			.list()
+			.await
+			.map_err(|e| McpError::internal_error(format!("Failed to list connections: {e}"), None))?;
+		let all = connections.into_iter().map(|(name, svc_arc)| async move {
+			let mut resources = vec![];
+			let mut cursor = None;
+			loop {
+				let request = cursor.map(|cursor| PaginatedRequestParam {
+					cursor: Some(cursor),
+				});
+				match svc_arc.list_resources(request, rq_ctx).await {
+					Ok(r) => {
+						resources.extend(r.resources);
+						cursor = r.next_cursor;
+					},
+					Err(e) => {
+						tracing::warn!(
+							"failed to list resources of target {name}: {}",
+							e.error_code()
+						);
+						return Err(e);
+					},
+				}
+				if cursor.is_none() {
+					break;
+				}
+			}
+			Ok::<_, upstream::UpstreamError>((name, resources))
+		});
+
+		let (results, _errors): (Vec<_>, Vec<_>) = futures::future::join_all(all)
+			.await
+			.into_iter()
+			.partition_result();
+		Ok(self.backend.merge_resources(results))
+	}
+
+	/// Lists the prompts of every connected target and merges them. Targets that fail to list their
+	/// prompts are left out.
+	async fn fetch_prompts(&self, rq_ctx: &RqCtx) -> Result<(Vec<MergedPrompt>, bool), McpError> {
+		let mut pool = self.pool.write().await;
+		let connections = pool
+			.list()
+			.await
+			.map_err(|e| McpError::internal_error(format!("Failed to list connections: {e}"), None))?;
+		let all = connections.into_iter().map(|(name, svc)| async move {
+			match list_target_prompts(svc, rq_ctx).await {
+				Ok(prompts) => Ok((name, prompts)),
+				Err(e) => {
+					tracing::warn!(
+						"failed to list prompts of target {name}: {}",
+						e.error_code()
+					);
+					Err(e)
+				},
+			}
+		});
+
+		let (results, errors): (Vec<_>, Vec<_>) = futures::future::join_all(all)
+			.await
+			.into_iter()
+			.partition_result();
+		Ok((self.backend.merge_prompts(results), errors.is_empty()))
+	}
+
+	/// Returns the merged prompts of all targets, from the cache when possible.
+	async fn cached_prompts(&self, rq_ctx: &RqCtx) -> Result<Arc<Vec<MergedPrompt>>, McpError> {
+		if let Some(prompts) = self.prompts.get() {
+			return Ok(prompts);
 		}
+		let (prompts, complete) = self.fetch_prompts(rq_ctx).await?;
+		let prompts = Arc::new(prompts);
+		// Don't cache a partial list; a failing target may recover on the next call.
+		if complete {
+			self.prompts.set(prompts.clone());
+		}
+		Ok(prompts)
 	}
 
 	fn setup_request(
@@ -182,6 +358,8 @@ impl Relay {
 		let mut pool = self.pool.write().await;
 		match pool.remove(name).await {
 			Some(target) => {
+				self.tools.invalidate();
+				self.prompts.invalidate();
 				match target.spec {
 					upstream::UpstreamTargetSpec::Mcp(m) => {
 						m.cancel().await?;
@@ -197,20 +375,200 @@ impl Relay {
 	}
 }
 
+/// Splits a prefixed resource name into its target and the name on that target.
+/// Target names may themselves contain the delimiter, so the longest matching target wins.
+fn split_resource_name<'a, 'b>(
+	targets: &'a [String],
+	delimiter: McpDelimiter,
+	res: &'b str,
+) -> Option<(&'a str, &'b str)> {
+	targets
+		.iter()
+		.filter_map(|t| {
+			res
+				.strip_prefix(t.as_str())
+				.and_then(|rest| rest.strip_prefix(delimiter.as_str()))
+				.map(|rest| (t.as_str(), rest))
+		})
+		.max_by_key(|(t, _)| t.len())
+}
+
+/// Picks the protocol version to speak with a client. A supported version is used as requested. A
+/// client newer than the gateway is offered the latest supported version, which it may decline by
+/// disconnecting; older or unknown versions are rejected.
+fn negotiate_protocol_version(requested: &ProtocolVersion) -> Result<ProtocolVersion, McpError> {
+	if let Some(v) = SUPPORTED_PROTOCOL_VERSIONS
+		.iter()
+		.find(|v| **v == *requested)
+	{
+		return Ok(v.clone());
+	}
+	let latest = SUPPORTED_PROTOCOL_VERSIONS
+		.last()
+		.expect("at least one protocol version is supported");
+	let requested = requested.to_string();
+	// Versions are dates, so they order lexically
+	let is_date = requested.len() == 10
+		&& requested.chars().enumerate().all(|(i, c)| {
+			if i == 4 || i == 7 {
+				c == '-'
+			} else {
+				c.is_ascii_digit()
+			}
+		});
+	if is_date && requested > latest.to_string() {
+		return Ok(latest.clone());
+	}
+	let supported = SUPPORTED_PROTOCOL_VERSIONS
+		.iter()
+		.map(|v| v.to_string())
+		.collect_vec();
+	Err(McpError::invalid_params(
+		format!(
+			"unsupported protocol version {requested}; supported versions are {}",
+			supported.join(", ")
+		),
+		Some(serde_json::json!({
+			"supported": supported,
+			"requested": requested,
+		})),
+	))
+}
+
+/// The capabilities the gateway can serve for a backend. OpenAPI targets only provide tools, so
+/// resources and prompts are only advertised when some target is an MCP server.
+fn capabilities(backend: &McpBackendGroup) -> ServerCapabilities {
+	let mcp_targets = backend
+		.targets
+		.iter()
+		.any(|t| !matches!(t.spec, McpTargetSpec::OpenAPI(_)));
+	ServerCapabilities {
+		completions: None,
+		experimental: None,
+		logging: None,
+		prompts: mcp_targets.then(PromptsCapability::default),
+		resources: mcp_targets.then(ResourcesCapability::default),
+		tools: Some(ToolsCapability::default()),
+	}
+}
+
+/// Lists all prompts of a target, following its pagination.
+async fn list_target_prompts(
+	svc: &upstream::UpstreamTarget,
+	rq_ctx: &RqCtx,
+) -> Result<Vec<Prompt>, upstream::UpstreamError> {
+	let mut prompts = vec![];
+	let mut cursor = None;
+	loop {
+		let request = cursor.map(|cursor| PaginatedRequestParam {
+			cursor: Some(cursor),
+		});
+		let r = svc.list_prompts(request, rq_ctx).await?;
+		prompts.extend(r.prompts);
+		cursor = r.next_cursor;
+		if cursor.is_none() {
+			return Ok(prompts);
+		}
+	}
+}
+
+/// Checks the arguments of a prompts/get call against the arguments the prompt declares, so a
+/// missing argument is reported to the client rather than substituted upstream as an empty value.
+/// Prompt arguments are strings; numbers and booleans are converted to their string form.
+fn bind_prompt_arguments(
+	prompt: &Prompt,
+	args: Option<JsonObject>,
+) -> Result<Option<JsonObject>, ArgumentError> {
+	let mut args = args.unwrap_or_default();
+	for (name, value) in args.iter_mut() {
+		match value {
+			serde_json::Value::String(_) => {},
+			serde_json::Value::Number(n) => *value = n.to_string().into(),
+			serde_json::Value::Bool(b) => *value = b.to_string().into(),
+			_ => return Err(ArgumentError::InvalidPromptArgument(name.clone())),
+		}
+	}
+	for arg in prompt.arguments.iter().flatten() {
+		if arg.required.unwrap_or(false) && !args.contains_key(&arg.name) {
+			return Err(ArgumentError::MissingPromptArgument(arg.name.clone()));
+		}
+	}
+	Ok((!args.is_empty()).then_some(args))
+}
+
+/// An item of a paginated list, identified by a key. Keys may repeat when targets expose the same
+/// item, so the position in the list is also tracked.
+trait Paginated {
+	fn page_key(&self) -> &str;
+}
+
+impl Paginated for Tool {
+	fn page_key(&self) -> &str {
+		&self.name
+	}
+}
+
+impl Paginated for Prompt {
+	fn page_key(&self) -> &str {
+		&self.name
+	}
+}
+
+impl Paginated for Resource {
+	fn page_key(&self) -> &str {
+		&self.raw.uri
+	}
+}
+
+/// Returns the page of `items` following `cursor`, and the cursor of the next page if there is one.
+/// The cursor encodes the index and key of the last item of the previous page, so it is stable as
+/// long as the list does not change, and the key check rejects cursors for a list that has.
+fn paginate<T: Paginated>(
+	items: Vec<T>,
+	cursor: Option<&str>,
+	page_size: usize,
+) -> Result<(Vec<T>, Option<String>), McpError> {
+	use base64::Engine;
+	use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+
+	let start = match cursor {
+		None => 0,
+		Some(cursor) => {
+			let (idx, last) = URL_SAFE_NO_PAD
+				.decode(cursor)
+				.ok()
+				.and_then(|b| String::from_utf8(b).ok())
+				.and_then(|c| {
+					let (idx, last) = c.split_once(':')?;
+					Some((idx.parse::<usize>().ok()?, last.to_string()))
+				})
+				.ok_or_else(|| McpError::invalid_params("invalid cursor", None))?;
+			if items.get(idx).map(|t| t.page_key()) != Some(last.as_str()) {
+				return Err(McpError::invalid_params("cursor no longer valid", None));
+			}
+			idx + 1
+		},
+	};
+	let mut page: Vec<T> = items.into_iter().skip(start).collect();
+	let next = if page.len() > page_size {
+		page.truncate(page_size);
+		page.last().map(|t| {
+			let idx = start + page_size - 1;
+			URL_SAFE_NO_PAD.encode(format!("{idx}:{}", t.page_key()))
+		})
+	} else {
+		None
+	};
+	Ok((page, next))
+}
+
 // TODO: lists and gets can be macros
 impl ServerHandler for Relay {
 	#[instrument(level = "debug", skip_all)]
 	fn get_info(&self) -> ServerInfo {
 		ServerInfo {
             protocol_version: ProtocolVersion::V_2025_03_26,
-            capabilities: ServerCapabilities {
-                completions: None,
-                experimental: None,
-                logging: None,
-                prompts: Some(PromptsCapability::default()),
-                resources: Some(ResourcesCapability::default()),
-                tools: Some(ToolsCapability::default()),
-            },
+            capabilities: capabilities(&self.backend),
             server_info: Implementation::from_build_env(),
             instructions: Some(
                 "This server is a gateway to a set of mcp servers. It is responsible for routing requests to the correct server and aggregating the results.".to_string(),
@@ -218,13 +576,16 @@ impl ServerHandler for Relay {
         }
 	}
 
-	// The client will send an initialize request with their parameters. We will return our own static support
+	// The client will send an initialize request with their parameters. We agree on a protocol version,
+	// which is also used to initialize the targets, and return the capabilities of our targets.
 	async fn initialize(
 		&self,
-		request: InitializeRequestParam,
+		mut request: InitializeRequestParam,
 		context: RequestContext<RoleServer>,
 	) -> Result<InitializeResult, McpError> {
 		let (_span, ref rq_ctx) = Self::setup_request(&context.extensions, "initialize")?;
+		let protocol_version = negotiate_protocol_version(&request.protocol_version)?;
+		request.protocol_version = protocol_version.clone();
 
 		// List servers and initialize the ones that are not initialized
 		let mut pool = self.pool.write().await;
@@ -234,10 +595,10 @@ impl ServerHandler for Relay {
 			.await
 			.map_err(|e| McpError::internal_error(format!("Failed to list connections: {e}"), None))?;
 
-		// Return static server info about ourselves
-		// TODO: we should actually perform an intersection of what the downstream and we support. The problem
-		// is we may connect to many upstream servers, how do expose what exactly we can and cannot support?
-		Ok(self.get_info())
+		Ok(ServerInfo {
+			protocol_version,
+			..self.get_info()
+		})
 	}
 
 	#[instrument(level = "debug", skip_all)]
@@ -246,32 +607,36 @@ impl ServerHandler for Relay {
 		request: Option<PaginatedRequestParam>,
 		context: RequestContext<RoleServer>,
 	) -> std::result::Result<ListResourcesResult, McpError> {
-		let (_span, ref rq_ctx) = Self::setup_request(&context.extensions, "list_resources")?;
+		let (_span, ref rq_ctx, _, cel) =
+			Self::setup_request_log(&context.extensions, "list_resources")?;
+		let resources = self.fetch_resources(rq_ctx).await?;
 
-		let mut pool = self.pool.write().await;
-		let connections = pool
-			.list()
-			.await
-			.map_err(|e| McpError::internal_error(format!("Failed to list connections: {e}"), None))?;
-		let all = connections.into_iter().map(|(_name, svc)| {
-			let request = request.clone();
-			async move {
-				match svc.list_resources(request, rq_ctx).await {
-					Ok(r) => Ok(r.resources),
-					Err(e) => Err(e),
-				}
-			}
-		});
+		self.metrics.clone().record(
+			metrics::ListCall {
+				resource_type: "resource".to_string(),
+				params: vec![],
+			},
+			(),
+		);
 
-		// TODO: Handle errors
-		let (results, _errors): (Vec<_>, Vec<_>) = futures::future::join_all(all)
-			.await
+		let resources = resources
 			.into_iter()
-			.partition_result();
-
+			.filter(|r| {
+				self.policies.validate(
+					&rbac::ResourceType::Resource(rbac::ResourceId::new(
+						r.target.to_string(),
+						r.upstream_uri.clone(),
+					)),
+					cel.as_ref(),
+				)
+			})
+			.map(|r| r.resource)
+			.collect();
+		let cursor = request.and_then(|r| r.cursor);
+		let (resources, next_cursor) = paginate(resources, cursor.as_deref(), RESOURCES_PAGE_SIZE)?;
 		Ok(ListResourcesResult {
-			resources: results.into_iter().flatten().collect(),
-			next_cursor: None,
+			resources,
+			next_cursor,
 		})
 	}
 
@@ -323,37 +688,8 @@ impl ServerHandler for Relay {
 		request: Option<PaginatedRequestParam>,
 		context: RequestContext<RoleServer>,
 	) -> std::result::Result<ListPromptsResult, McpError> {
-		let (_span, ref rq_ctx) = Self::setup_request(&context.extensions, "list_prompts")?;
-
-		let mut pool = self.pool.write().await;
-		let connections = pool
-			.list()
-			.await
-			.map_err(|e| McpError::internal_error(format!("Failed to list connections: {e}"), None))?;
-
-		let all = connections.into_iter().map(|(_name, svc)| {
-			let request = request.clone();
-			async move {
-				match svc.list_prompts(request, rq_ctx).await {
-					Ok(r) => Ok(
-						r.prompts
-							.into_iter()
-							.map(|p| Prompt {
-								name: self.resource_name(_name.as_str(), &p.name),
-								description: p.description,
-								arguments: p.arguments,
-							})
-							.collect::<Vec<_>>(),
-					),
-					Err(e) => Err(e),
-				}
-			}
-		});
-
-		let (results, _errors): (Vec<_>, Vec<_>) = futures::future::join_all(all)
-			.await
-			.into_iter()
-			.partition_result();
+		let (_span, ref rq_ctx, _, cel) = Self::setup_request_log(&context.extensions, "list_prompts")?;
+		let prompts = self.cached_prompts(rq_ctx).await?;
 
 		self.metrics.record(
 			metrics::ListCall {
@@ -362,9 +698,25 @@ impl ServerHandler for Relay {
 			},
 			(),
 		);
+
+		let prompts = prompts
+			.iter()
+			.filter(|p| {
+				self.policies.validate(
+					&rbac::ResourceType::Prompt(rbac::ResourceId::new(
+						p.target.to_string(),
+						p.upstream_name.clone(),
+					)),
+					cel.as_ref(),
+				)
+			})
+			.map(|p| p.prompt.clone())
+			.collect();
+		let cursor = request.and_then(|r| r.cursor);
+		let (prompts, next_cursor) = paginate(prompts, cursor.as_deref(), PROMPTS_PAGE_SIZE)?;
 		Ok(ListPromptsResult {
-			prompts: results.into_iter().flatten().collect(),
-			next_cursor: None,
+			prompts,
+			next_cursor,
 		})
 	}
 
@@ -443,6 +795,18 @@ impl ServerHandler for Relay {
 		) {
 			return Err(McpError::invalid_request("not allowed", None));
 		}
+		// Validate the arguments against the prompt the target declares. Prompts the target does not list,
+		// or that could not be listed, are passed through and left for the target to reject.
+		let prompts = self.cached_prompts(rq_ctx).await.ok();
+		let declared = prompts
+			.iter()
+			.flat_map(|p| p.iter())
+			.find(|p| p.target.as_str() == service_name && p.upstream_name == prompt);
+		let arguments = match declared {
+			Some(declared) => bind_prompt_arguments(&declared.prompt, request.arguments)
+				.map_err(|e| McpError::invalid_params(e.to_string(), None))?,
+			None => request.arguments,
+		};
 		let mut pool = self.pool.write().await;
 		let svc = pool
 			.get(rq_ctx, &context.peer, service_name)
@@ -450,7 +814,7 @@ impl ServerHandler for Relay {
 			.map_err(|_e| McpError::invalid_request(format!("Service {service_name} not found"), None))?;
 		let req = GetPromptRequestParam {
 			name: prompt.to_string(),
-			arguments: request.arguments,
+			arguments,
 		};
 
 		self.metrics.clone().record(
@@ -474,46 +838,18 @@ impl ServerHandler for Relay {
 		mut context: RequestContext<RoleServer>,
 	) -> std::result::Result<ListToolsResult, McpError> {
 		let (_span, ref rq_ctx, _, cel) = Self::setup_request_log(&context.extensions, "list_tools")?;
-		let mut pool = self.pool.write().await;
-		let connections = pool
-			.list()
-			.await
-			.map_err(|e| McpError::internal_error(format!("Failed to list connections: {e}"), None))?;
-		let multi = connections.len() > 1;
-		let all = connections.into_iter().map(|(_name, svc_arc)| {
-			let request = request.clone();
-			let cel = cel.clone();
-			async move {
-				match svc_arc.list_tools(request, rq_ctx).await {
-					Ok(r) => Ok(
-						r.tools
-							.into_iter()
-							.filter(|t| {
-								self.policies.validate(
-									&rbac::ResourceType::Tool(rbac::ResourceId::new(
-										_name.to_string(),
-										t.name.to_string(),
-									)),
-									cel.as_ref(),
-								)
-							})
-							.map(|t| Tool {
-								annotations: None,
-								name: Cow::Owned(self.resource_name(_name.as_str(), &t.name)),
-								description: t.description,
-								input_schema: t.input_schema,
-							})
-							.collect::<Vec<_>>(),
-					),
-					Err(e) => Err(e),
+		let tools = match self.tools.get() {
+			Some(tools) => tools,
+			None => {
+				let (tools, complete) = self.fetch_tools(rq_ctx).await?;
+				let tools = Arc::new(tools);
+				// Don't cache a partial list; a failing target may recover on the next call.
+				if complete {
+					self.tools.set(tools.clone());
 				}
-			}
-		});
-
-		let (results, _errors): (Vec<_>, Vec<_>) = futures::future::join_all(all)
-			.await
-			.into_iter()
-			.partition_result();
+				tools
+			},
+		};
 
 		self.metrics.clone().record(
 			metrics::ListCall {
@@ -523,10 +859,22 @@ impl ServerHandler for Relay {
 			(),
 		);
 
-		Ok(ListToolsResult {
-			tools: results.into_iter().flatten().collect(),
-			next_cursor: None,
-		})
+		let tools = tools
+			.iter()
+			.filter(|t| {
+				self.policies.validate(
+					&rbac::ResourceType::Tool(rbac::ResourceId::new(
+						t.target.to_string(),
+						t.upstream_name.clone(),
+					)),
+					cel.as_ref(),
+				)
+			})
+			.map(|t| t.tool.clone())
+			.collect();
+		let cursor = request.and_then(|r| r.cursor);
+		let (tools, next_cursor) = paginate(tools, cursor.as_deref(), TOOLS_PAGE_SIZE)?;
+		Ok(ListToolsResult { tools, next_cursor })
 	}
 
 	#[instrument(
@@ -598,3 +946,7 @@ impl ServerHandler for Relay {
 		})
 	}
 }
+
+#[cfg(test)]
+#[path = "tests.rs"]
+mod tests;
@@ -1,3 +1,5 @@
+use std::sync::OnceLock;
+
 use agent_core::prelude::*;
 use anyhow::anyhow;
 use futures::future::BoxFuture;
@@ -38,14 +40,24 @@ pub(crate) struct ConnectionPool {
 	pi: Arc<ProxyInputs>,
 	backend: McpBackendGroup,
 	client: PolicyClient,
+	tools: ToolCache,
+	prompts: PromptCache,
 	by_name: HashMap<Strng, upstream::UpstreamTarget>,
 }
 
 impl ConnectionPool {
-	pub(crate) fn new(pi: Arc<ProxyInputs>, client: PolicyClient, backend: McpBackendGroup) -> Self {
+	pub(crate) fn new(
+		pi: Arc<ProxyInputs>,
+		client: PolicyClient,
+		backend: McpBackendGroup,
+		tools: ToolCache,
+		prompts: PromptCache,
+	) -> Self {
 		Self {
 			backend,
 			client,
+			tools,
+			prompts,
 			pi,
 			by_name: HashMap::new(),
 		}
@@ -161,6 +173,8 @@ impl ConnectionPool {
 								peer: peer.clone(),
 								peer_client: None,
 								init_request,
+								tools: self.tools.clone(),
+								prompts: self.prompts.clone(),
 							},
 							transport,
 							ct.child_token(),
@@ -179,29 +193,59 @@ impl ConnectionPool {
 					_ => mcp.path.as_str(),
 				};
 				let be = crate::proxy::resolve_simple_backend(&mcp.backend, &self.pi)?;
+				let hostport = be.hostport();
 				let client =
 					ClientWrapper::new_with_client(be, self.client.clone(), target.backend_policies.clone());
-				let mut transport = StreamableHttpClientTransport::with_client(
-					client,
+				let transport = StreamableHttpClientTransport::with_client(
+					client.clone(),
 					StreamableHttpClientTransportConfig {
 						uri: path.into(),
 						..Default::default()
 					},
 				);
+				let handler = PeerClientHandler {
+					peer: peer.clone(),
+					peer_client: None,
+					init_request,
+					tools: self.tools.clone(),
+					prompts: self.prompts.clone(),
+				};
 
-				upstream::UpstreamTarget {
-					spec: upstream::UpstreamTargetSpec::Mcp(
-						serve_client_with_ct(
-							PeerClientHandler {
-								peer: peer.clone(),
-								peer_client: None,
-								init_request,
+				let service = match serve_client_with_ct(handler.clone(), transport, ct.child_token()).await
+				{
+					Ok(service) => service,
+					// Servers that only implement the older HTTP+SSE transport reject the initialize POST with
+					// a 400, 404 or 405. As the specification suggests for backwards compatibility, retry by
+					// opening an SSE stream on the same URL, which announces the endpoint to post messages to.
+					Err(streamable_err) if client.rejected_streamable_http() => {
+						debug!(
+							"streamable http initialize rejected for target {}, trying sse: {}",
+							target.name, streamable_err
+						);
+						let service = match SseClientTransport::start_with_client(
+							client,
+							SseClientConfig {
+								sse_endpoint: format!("http://{hostport}{path}").into(),
+								..Default::default()
 							},
-							transport,
-							ct.child_token(),
 						)
-						.await?,
-					),
+						.await
+						{
+							Ok(transport) => serve_client_with_ct(handler, transport, ct.child_token())
+								.await
+								.map_err(anyhow::Error::new),
+							Err(e) => Err(anyhow::Error::new(e)),
+						};
+						service.map_err(|sse_err| {
+							anyhow!(
+								"streamable http initialize failed: {streamable_err}; sse fallback failed: {sse_err}"
+							)
+						})?
+					},
+					Err(streamable_err) => return Err(streamable_err.into()),
+				};
+				upstream::UpstreamTarget {
+					spec: upstream::UpstreamTargetSpec::Mcp(service),
 				}
 			},
 			McpTargetSpec::Stdio { cmd, args, env } => {
@@ -218,6 +262,8 @@ impl ConnectionPool {
 								peer: peer.clone(),
 								peer_client: None,
 								init_request,
+								tools: self.tools.clone(),
+								prompts: self.prompts.clone(),
 							},
 							TokioChildProcess::new(c).context(format!("failed to run command '{cmd}'"))?,
 							ct.child_token(),
@@ -253,6 +299,7 @@ impl ConnectionPool {
 						default_policies: target.backend_policies.clone(),
 						tools,  // From parse_openapi_schema
 						prefix, // From get_server_prefix
+						pretty_json: open.pretty_json,
 					})),
 				}
 			},
@@ -267,6 +314,8 @@ pub(crate) struct PeerClientHandler {
 	peer: Peer<RoleServer>,
 	peer_client: Option<Peer<RoleClient>>,
 	init_request: InitializeRequestParam,
+	tools: ToolCache,
+	prompts: PromptCache,
 }
 
 impl ClientHandler for PeerClientHandler {
@@ -326,6 +375,7 @@ impl ClientHandler for PeerClientHandler {
 	}
 
 	async fn on_prompt_list_changed(&self, _context: NotificationContext<RoleClient>) {
+		self.prompts.invalidate();
 		let _ = self
 			.peer
 			.notify_prompt_list_changed()
@@ -346,6 +396,7 @@ impl ClientHandler for PeerClientHandler {
 	}
 
 	async fn on_tool_list_changed(&self, _context: NotificationContext<RoleClient>) {
+		self.tools.invalidate();
 		let _ = self.peer.notify_tool_list_changed().await.inspect_err(|e| {
 			error!("Failed to notify tool list changed: {}", e);
 		});
@@ -375,6 +426,8 @@ pub struct ClientWrapper {
 	backend: Arc<SimpleBackend>,
 	client: PolicyClient,
 	policies: BackendPolicies,
+	// The error status the server returned for a streamable HTTP initialize request, if any.
+	init_rejection: Arc<OnceLock<http::StatusCode>>,
 }
 
 impl ClientWrapper {
@@ -387,9 +440,23 @@ impl ClientWrapper {
 			backend: Arc::new(backend),
 			client,
 			policies,
+			init_rejection: Default::default(),
 		}
 	}
 
+	/// Returns true if the server rejected the streamable HTTP initialize request in the way a server
+	/// that only implements the older HTTP+SSE transport would.
+	fn rejected_streamable_http(&self) -> bool {
+		matches!(
+			self.init_rejection.get().copied(),
+			Some(
+				http::StatusCode::BAD_REQUEST
+					| http::StatusCode::NOT_FOUND
+					| http::StatusCode::METHOD_NOT_ALLOWED
+			)
+		)
+	}
+
 	fn parse_uri(
 		uri: Arc<str>,
 	) -> Result<String, StreamableHttpError<<ClientWrapper as StreamableHttpClient>::Error>> {
@@ -418,6 +485,10 @@ impl StreamableHttpClient for ClientWrapper {
 
 		let uri = "http://".to_string() + &self.backend.hostport() + &Self::parse_uri(uri)?;
 
+		let initialize = matches!(
+			&message,
+			JsonRpcMessage::Request(r) if matches!(r.request, ClientRequest::InitializeRequest(_))
+		);
 		let body =
 			serde_json::to_vec(&message).map_err(|e| StreamableHttpError::Client(HttpError::new(e)))?;
 
@@ -449,6 +520,9 @@ impl StreamableHttpClient for ClientWrapper {
 		}
 
 		if resp.status().is_client_error() || resp.status().is_server_error() {
+			if initialize {
+				let _ = self.init_rejection.set(resp.status());
+			}
 			return Err(StreamableHttpError::Client(HttpError::new(anyhow!(
 				"received status code {}",
 				resp.status()
@@ -0,0 +1,460 @@
+use std::sync::Arc;
+
+use agent_core::strng;
+use openapiv3::OpenAPI;
+use serde_json::json;
+
+use super::*;
+use crate::types::agent::{McpTarget, McpTargetSpec, OpenAPITarget, SimpleBackendReference};
+
+fn openapi_target(name: &str, operation_id: &str) -> Arc<McpTarget> {
+	let schema: OpenAPI = serde_json::from_value(json!({
+		"openapi": "3.0.0",
+		"info": {"title": name, "version": "1.0"},
+		"paths": {
+			"/users/{user_id}": {
+				"get": {
+					"operationId": operation_id,
+					"parameters": [{
+						"name": "user_id",
+						"in": "path",
+						"required": true,
+						"schema": {"type": "string"}
+					}],
+					"responses": {}
+				}
+			}
+		}
+	}))
+	.unwrap();
+	Arc::new(McpTarget {
+		name: strng::new(name),
+		spec: McpTargetSpec::OpenAPI(OpenAPITarget {
+			backend: SimpleBackendReference::Invalid,
+			schema: Arc::new(schema),
+			pretty_json: false,
+		}),
+	})
+}
+
+fn names(targets: &[&str]) -> Vec<String> {
+	targets.iter().map(|t| t.to_string()).collect()
+}
+
+#[test]
+fn test_same_tool_on_two_targets() {
+	let targets = names(&["users", "admins"]);
+	for delimiter in [McpDelimiter::Underscore, McpDelimiter::Slash] {
+		let users = delimiter.prefixed("users", "get_user");
+		let admins = delimiter.prefixed("admins", "get_user");
+		assert_ne!(users, admins);
+		assert_eq!(
+			split_resource_name(&targets, delimiter, &users),
+			Some(("users", "get_user"))
+		);
+		assert_eq!(
+			split_resource_name(&targets, delimiter, &admins),
+			Some(("admins", "get_user"))
+		);
+	}
+	assert_eq!(
+		McpDelimiter::Slash.prefixed("users", "get_user"),
+		"users/get_user"
+	);
+}
+
+#[test]
+fn test_split_prefers_longest_target() {
+	let targets = names(&["a", "a_b"]);
+	assert_eq!(
+		split_resource_name(&targets, McpDelimiter::Underscore, "a_b_tool"),
+		Some(("a_b", "tool"))
+	);
+	assert_eq!(
+		split_resource_name(&targets, McpDelimiter::Underscore, "a_tool"),
+		Some(("a", "tool"))
+	);
+	assert_eq!(
+		split_resource_name(&targets, McpDelimiter::Underscore, "c_tool"),
+		None
+	);
+	assert_eq!(
+		split_resource_name(&targets, McpDelimiter::Slash, "a_tool"),
+		None
+	);
+}
+
+#[test]
+fn test_tool_name_collisions() {
+	let backend = McpBackend {
+		targets: vec![
+			openapi_target("users", "get_user"),
+			openapi_target("admins", "get_user"),
+		],
+		delimiter: McpDelimiter::Underscore,
+	};
+	assert!(backend.tool_name_collisions().is_empty());
+
+	// `a` + `b_get` and `a_b` + `get` both become `a_b_get`
+	let backend = McpBackend {
+		targets: vec![openapi_target("a", "b_get"), openapi_target("a_b", "get")],
+		delimiter: McpDelimiter::Underscore,
+	};
+	assert_eq!(backend.tool_name_collisions().len(), 1);
+
+	// A different delimiter removes the ambiguity
+	let backend = McpBackend {
+		targets: vec![openapi_target("a", "b_get"), openapi_target("a_b", "get")],
+		delimiter: McpDelimiter::Slash,
+	};
+	assert!(backend.tool_name_collisions().is_empty());
+
+	let backend = McpBackend {
+		targets: vec![openapi_target("a", "get"), openapi_target("a", "list")],
+		delimiter: McpDelimiter::Underscore,
+	};
+	assert_eq!(backend.tool_name_collisions().len(), 1);
+}
+
+fn group(targets: Vec<Arc<McpTarget>>, delimiter: McpDelimiter) -> McpBackendGroup {
+	McpBackendGroup {
+		name: strng::new("backend"),
+		targets: targets
+			.into_iter()
+			.map(|t| {
+				Arc::new(crate::mcp::sse::McpTarget {
+					name: t.name.clone(),
+					spec: t.spec.clone(),
+					backend_policies: Default::default(),
+				})
+			})
+			.collect(),
+		delimiter,
+	}
+}
+
+fn openapi_tools(target: &McpTarget) -> Vec<Tool> {
+	let McpTargetSpec::OpenAPI(open) = &target.spec else {
+		panic!("not an OpenAPI target");
+	};
+	crate::mcp::openapi::parse_openapi_schema(&open.schema)
+		.unwrap()
+		.into_iter()
+		.map(|(t, _)| t)
+		.collect()
+}
+
+#[test]
+fn test_merge_tools() {
+	let users = openapi_target("users", "get_user");
+	let search = openapi_target("search", "unused");
+	let backend = group(vec![users.clone(), search], McpDelimiter::Underscore);
+	let native = Tool::new(
+		"query",
+		"search everything",
+		Arc::new(serde_json::Map::new()),
+	);
+
+	// Lists are merged in target order, regardless of the order they were listed in
+	let merged = backend.merge_tools(vec![
+		(strng::new("search"), vec![native]),
+		(strng::new("users"), openapi_tools(&users)),
+	]);
+	let got: Vec<_> = merged
+		.iter()
+		.map(|t| {
+			(
+				t.target.as_str(),
+				t.upstream_name.as_str(),
+				t.tool.name.as_ref(),
+			)
+		})
+		.collect();
+	assert_eq!(
+		got,
+		vec![
+			("users", "get_user", "users_get_user"),
+			("search", "query", "search_query"),
+		]
+	);
+
+	// A single target is not prefixed
+	let backend = group(vec![users.clone()], McpDelimiter::Underscore);
+	let merged = backend.merge_tools(vec![(strng::new("users"), openapi_tools(&users))]);
+	assert_eq!(merged.len(), 1);
+	assert_eq!(merged[0].tool.name, "get_user");
+}
+
+fn catalog(n: usize) -> Vec<Tool> {
+	(0..n)
+		.map(|i| {
+			Tool::new(
+				format!("tool_{i}"),
+				"a tool",
+				Arc::new(serde_json::Map::new()),
+			)
+		})
+		.collect()
+}
+
+#[test]
+fn test_paginate_tools() {
+	for n in [0, 1, 9, 10, 11, 35] {
+		let tools = catalog(n);
+		let mut seen = vec![];
+		let mut cursor: Option<String> = None;
+		loop {
+			let (page, next) = paginate(tools.clone(), cursor.as_deref(), 10).unwrap();
+			assert!(page.len() <= 10);
+			seen.extend(page.into_iter().map(|t| t.name.to_string()));
+			match next {
+				Some(next) => cursor = Some(next),
+				None => break,
+			}
+		}
+		let want: Vec<_> = tools.iter().map(|t| t.name.to_string()).collect();
+		assert_eq!(seen, want, "catalog of {n} tools");
+	}
+}
+
+#[test]
+fn test_paginate_cursor_is_stable() {
+	let (_, first) = paginate(catalog(25), None, 10).unwrap();
+	let (_, again) = paginate(catalog(25), None, 10).unwrap();
+	assert_eq!(first, again);
+	let (page, _) = paginate(catalog(25), first.as_deref(), 10).unwrap();
+	assert_eq!(page[0].name, "tool_10");
+
+	assert!(paginate(catalog(25), Some("not base64!"), 10).is_err());
+	// The tool the cursor points at was removed
+	assert!(paginate(catalog(5), first.as_deref(), 10).is_err());
+}
+
+#[test]
+fn test_paginate_duplicate_keys() {
+	// Every item has the same key, as when several targets expose an identically named resource
+	let resources: Vec<_> = (0..25).map(|_| resource("file:///same")).collect();
+	let mut seen = 0;
+	let mut cursor: Option<String> = None;
+	for _ in 0..5 {
+		let (page, next) = paginate(resources.clone(), cursor.as_deref(), 10).unwrap();
+		seen += page.len();
+		match next {
+			Some(next) => cursor = Some(next),
+			None => break,
+		}
+	}
+	assert_eq!(seen, 25);
+}
+
+fn resource(uri: &str) -> Resource {
+	RawResource::new(uri, uri.rsplit('/').next().unwrap()).no_annotation()
+}
+
+#[test]
+fn test_merge_resources() {
+	let users = openapi_target("users", "get_user");
+	let docs = openapi_target("docs", "unused");
+	let backend = group(vec![users, docs], McpDelimiter::Slash);
+
+	let merged = backend.merge_resources(vec![
+		(strng::new("docs"), vec![resource("file:///readme.md")]),
+		(
+			strng::new("users"),
+			vec![resource("file:///a.txt"), resource("file:///b.txt")],
+		),
+		(strng::new("unknown"), vec![resource("file:///c.txt")]),
+	]);
+	let got: Vec<_> = merged
+		.iter()
+		.map(|r| {
+			(
+				r.target.as_str(),
+				r.upstream_uri.as_str(),
+				r.resource.raw.uri.as_str(),
+			)
+		})
+		.collect();
+	assert_eq!(
+		got,
+		vec![
+			("users", "file:///a.txt", "users/file:///a.txt"),
+			("users", "file:///b.txt", "users/file:///b.txt"),
+			("docs", "file:///readme.md", "docs/file:///readme.md"),
+		]
+	);
+	// Other fields are passed through untouched
+	assert_eq!(merged[2].resource.raw.name, "readme.md");
+
+	// A listed URI is read from the target that listed it
+	let targets = names(&["users", "docs"]);
+	for r in &merged {
+		assert_eq!(
+			split_resource_name(&targets, McpDelimiter::Slash, &r.resource.raw.uri),
+			Some((r.target.as_str(), r.upstream_uri.as_str()))
+		);
+	}
+
+	// A single target is not prefixed
+	let backend = group(vec![openapi_target("docs", "unused")], McpDelimiter::Slash);
+	let merged = backend.merge_resources(vec![(
+		strng::new("docs"),
+		vec![resource("file:///readme.md")],
+	)]);
+	assert_eq!(merged[0].resource.raw.uri, "file:///readme.md");
+}
+
+#[test]
+fn test_paginate_resources() {
+	let resources: Vec<_> = (0..15)
+		.map(|i| resource(&format!("file:///{i}.txt")))
+		.collect();
+	let (page, next) = paginate(resources.clone(), None, 10).unwrap();
+	assert_eq!(page.len(), 10);
+	let (page, next) = paginate(resources, next.as_deref(), 10).unwrap();
+	assert_eq!(page[0].raw.uri, "file:///10.txt");
+	assert_eq!(page.len(), 5);
+	assert!(next.is_none());
+}
+
+fn prompt(name: &str, args: &[(&str, bool)]) -> Prompt {
+	Prompt::new(
+		name,
+		Some("a prompt"),
+		Some(
+			args
+				.iter()
+				.map(|(name, required)| PromptArgument {
+					name: name.to_string(),
+					description: None,
+					required: Some(*required),
+				})
+				.collect(),
+		),
+	)
+}
+
+#[test]
+fn test_merge_prompts() {
+	let backend = group(
+		vec![
+			openapi_target("code", "unused"),
+			openapi_target("docs", "unused"),
+		],
+		McpDelimiter::Underscore,
+	);
+	let merged = backend.merge_prompts(vec![
+		(strng::new("docs"), vec![prompt("summarize", &[])]),
+		(
+			strng::new("code"),
+			vec![prompt("review", &[("file", true)])],
+		),
+	]);
+	let got: Vec<_> = merged
+		.iter()
+		.map(|p| {
+			(
+				p.target.as_str(),
+				p.upstream_name.as_str(),
+				p.prompt.name.as_str(),
+			)
+		})
+		.collect();
+	assert_eq!(
+		got,
+		vec![
+			("code", "review", "code_review"),
+			("docs", "summarize", "docs_summarize"),
+		]
+	);
+	assert_eq!(merged[0].prompt.arguments.as_ref().unwrap().len(), 1);
+	let (page, next) = paginate(merged.into_iter().map(|p| p.prompt).collect(), None, 1).unwrap();
+	assert_eq!(page[0].name, "code_review");
+	assert!(next.is_some());
+}
+
+#[test]
+fn test_bind_prompt_arguments() {
+	let p = prompt("review", &[("file", true), ("lines", false)]);
+	let args = |v: serde_json::Value| v.as_object().cloned();
+
+	let bound = bind_prompt_arguments(&p, args(json!({"file": "main.rs", "lines": 10}))).unwrap();
+	assert_eq!(
+		serde_json::Value::Object(bound.unwrap()),
+		json!({"file": "main.rs", "lines": "10"})
+	);
+
+	assert!(matches!(
+		bind_prompt_arguments(&p, args(json!({"lines": "10"}))),
+		Err(ArgumentError::MissingPromptArgument(a)) if a == "file"
+	));
+	assert!(matches!(
+		bind_prompt_arguments(&p, None),
+		Err(ArgumentError::MissingPromptArgument(_))
+	));
+	assert!(matches!(
+		bind_prompt_arguments(&p, args(json!({"file": ["a", "b"]}))),
+		Err(ArgumentError::InvalidPromptArgument(a)) if a == "file"
+	));
+
+	// Prompts without required arguments accept none
+	let p = prompt("summarize", &[]);
+	assert_eq!(bind_prompt_arguments(&p, None).unwrap(), None);
+}
+
+#[test]
+fn test_negotiate_protocol_version() {
+	for v in [ProtocolVersion::V_2024_11_05, ProtocolVersion::V_2025_03_26] {
+		assert_eq!(negotiate_protocol_version(&v).unwrap(), v);
+	}
+
+	// A newer client is offered our latest version
+	let newer: ProtocolVersion = serde_json::from_value(json!("2099-01-01")).unwrap();
+	assert_eq!(
+		negotiate_protocol_version(&newer).unwrap(),
+		ProtocolVersion::V_2025_03_26
+	);
+
+	for unsupported in ["2024-01-01", "draft"] {
+		let v: ProtocolVersion = serde_json::from_value(json!(unsupported)).unwrap();
+		let err = negotiate_protocol_version(&v).unwrap_err();
+		assert_eq!(err.code, ErrorCode::INVALID_PARAMS);
+		assert!(err.message.contains(unsupported), "{}", err.message);
+		assert_eq!(
+			err.data,
+			Some(json!({
+				"supported": ["2024-11-05", "2025-03-26"],
+				"requested": unsupported,
+			}))
+		);
+	}
+}
+
+#[test]
+fn test_capabilities() {
+	let openapi = group(
+		vec![openapi_target("users", "get_user")],
+		McpDelimiter::Underscore,
+	);
+	let caps = capabilities(&openapi);
+	assert!(caps.tools.is_some());
+	assert!(caps.resources.is_none());
+	assert!(caps.prompts.is_none());
+
+	let stdio = Arc::new(McpTarget {
+		name: strng::new("local"),
+		spec: McpTargetSpec::Stdio {
+			cmd: "mcp-server".to_string(),
+			args: vec![],
+			env: Default::default(),
+		},
+	});
+	let mixed = group(
+		vec![openapi_target("users", "get_user"), stdio],
+		McpDelimiter::Underscore,
+	);
+	let caps = capabilities(&mixed);
+	assert!(caps.tools.is_some());
+	assert!(caps.resources.is_some());
+	assert!(caps.prompts.is_some());
+}
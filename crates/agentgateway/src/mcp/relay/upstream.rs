@@ -39,7 +39,13 @@ impl From<anyhow::Error> for UpstreamError {
 impl From<UpstreamError> for ErrorData {
 	fn from(value: UpstreamError) -> Self {
 		match value {
-			UpstreamError::OpenAPIError(e) => ErrorData::internal_error(e.to_string(), None),
+			UpstreamError::OpenAPIError(e) => {
+				if e.is::<crate::mcp::openapi::ArgumentError>() {
+					ErrorData::invalid_params(e.to_string(), None)
+				} else {
+					ErrorData::internal_error(e.to_string(), None)
+				}
+			},
 			UpstreamError::ServiceError(e) => match e {
 				rmcp::ServiceError::McpError(e) => e,
 				rmcp::ServiceError::Timeout { timeout } => {
@@ -329,11 +335,19 @@ impl UpstreamTarget {
 				}
 			},
 			UpstreamTargetSpec::OpenAPI(m) => {
-				let res = m
-					.call_tool(request.name.as_ref(), request.arguments)
-					.await?;
+				let mut args = request.arguments;
+				let dry_run = args
+					.as_mut()
+					.and_then(|a| a.remove(crate::mcp::openapi::DRY_RUN_ARGUMENT))
+					.is_some_and(|v| v == serde_json::Value::Bool(true));
+				let res = if dry_run {
+					let preview = m.preview_tool(request.name.as_ref(), args)?;
+					Content::text(serde_json::to_string(&preview).map_err(anyhow::Error::from)?)
+				} else {
+					m.call_tool(request.name.as_ref(), args).await?
+				};
 				Ok(CallToolResult {
-					content: vec![Content::text(res)],
+					content: vec![res],
 					is_error: None,
 				})
 			},
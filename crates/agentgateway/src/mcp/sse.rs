@@ -28,7 +28,7 @@ use http::Method;
 use http_body_util::BodyExt;
 use itertools::Itertools;
 use rmcp::RoleServer;
-use rmcp::model::{ClientJsonRpcMessage, GetExtensions};
+use rmcp::model::{ClientJsonRpcMessage, GetExtensions, Prompt, Resource, Tool};
 use rmcp::service::{TxJsonRpcMessage, serve_server_with_ct};
 use rmcp::transport::async_rw::JsonRpcMessageCodec;
 use rmcp::transport::common::server_side_http::session_id as generate_streamable_session_id;
@@ -57,8 +57,8 @@ use crate::proxy::httpproxy::PolicyClient;
 use crate::store::{BackendPolicies, Stores};
 use crate::telemetry::log::AsyncLog;
 use crate::types::agent::{
-	BackendName, McpAuthentication, McpBackend, McpIDP, McpTarget as TypeMcpTarget, McpTargetSpec,
-	PolicyTarget, Target,
+	BackendName, McpAuthentication, McpBackend, McpDelimiter, McpIDP, McpTarget as TypeMcpTarget,
+	McpTargetSpec, PolicyTarget, Target,
 };
 use crate::{ProxyInputs, client, json, mcp};
 
@@ -120,6 +120,7 @@ impl App {
 				McpBackendGroup {
 					name: name.clone(),
 					targets: nt,
+					delimiter: backends.delimiter,
 				},
 				authorization_policies,
 				authn,
@@ -210,6 +211,7 @@ impl App {
 pub struct McpBackendGroup {
 	pub name: BackendName,
 	pub targets: Vec<Arc<McpTarget>>,
+	pub delimiter: McpDelimiter,
 }
 
 impl McpBackendGroup {
@@ -220,6 +222,115 @@ impl McpBackendGroup {
 			.find(|target| target.name.as_str() == name)
 			.cloned()
 	}
+
+	/// The name a resource of `target` is exposed to clients as. Names are only prefixed with the
+	/// target name when there is more than one target.
+	pub fn resource_name(&self, target: &str, name: &str) -> String {
+		if self.targets.len() == 1 {
+			name.to_string()
+		} else {
+			self.delimiter.prefixed(target, name)
+		}
+	}
+
+	/// Merges the tools listed by each target into a single list, ordered by target, with each tool
+	/// renamed to the name it is exposed as. Lists for unknown targets are dropped.
+	pub fn merge_tools(&self, lists: Vec<(Strng, Vec<Tool>)>) -> Vec<MergedTool> {
+		self
+			.by_target(lists)
+			.flat_map(|(target, tools)| {
+				tools.into_iter().map(move |t| {
+					let upstream_name = t.name.to_string();
+					MergedTool {
+						target: target.clone(),
+						tool: Tool {
+							annotations: None,
+							name: self.resource_name(target, &upstream_name).into(),
+							description: t.description,
+							input_schema: t.input_schema,
+						},
+						upstream_name,
+					}
+				})
+			})
+			.collect()
+	}
+
+	/// Merges the resources listed by each target into a single list, ordered by target, with each
+	/// resource URI prefixed the same way tool names are. Lists for unknown targets are dropped.
+	pub fn merge_resources(&self, lists: Vec<(Strng, Vec<Resource>)>) -> Vec<MergedResource> {
+		self
+			.by_target(lists)
+			.flat_map(|(target, resources)| {
+				resources.into_iter().map(move |mut r| {
+					let upstream_uri = std::mem::take(&mut r.raw.uri);
+					r.raw.uri = self.resource_name(target, &upstream_uri);
+					MergedResource {
+						target: target.clone(),
+						upstream_uri,
+						resource: r,
+					}
+				})
+			})
+			.collect()
+	}
+
+	/// Merges the prompts listed by each target into a single list, ordered by target, with each prompt
+	/// renamed to the name it is exposed as. Lists for unknown targets are dropped.
+	pub fn merge_prompts(&self, lists: Vec<(Strng, Vec<Prompt>)>) -> Vec<MergedPrompt> {
+		self
+			.by_target(lists)
+			.flat_map(|(target, prompts)| {
+				prompts.into_iter().map(move |mut p| {
+					let upstream_name = std::mem::take(&mut p.name);
+					p.name = self.resource_name(target, &upstream_name);
+					MergedPrompt {
+						target: target.clone(),
+						upstream_name,
+						prompt: p,
+					}
+				})
+			})
+			.collect()
+	}
+
+	fn by_target<'a, T: 'a>(
+		&'a self,
+		lists: Vec<(Strng, Vec<T>)>,
+	) -> impl Iterator<Item = (&'a Strng, Vec<T>)> + 'a {
+		let mut lists: HashMap<Strng, Vec<T>> = lists.into_iter().collect();
+		self
+			.targets
+			.iter()
+			.filter_map(move |target| lists.remove(&target.name).map(|l| (&target.name, l)))
+	}
+}
+
+/// A tool listed by one of the targets of a backend.
+#[derive(Debug, Clone)]
+pub struct MergedTool {
+	pub target: Strng,
+	/// The name of the tool on the target; `tool` carries the name it is exposed as.
+	pub upstream_name: String,
+	pub tool: Tool,
+}
+
+/// A prompt listed by one of the targets of a backend.
+#[derive(Debug, Clone)]
+pub struct MergedPrompt {
+	pub target: Strng,
+	/// The name of the prompt on the target; `prompt` carries the name it is exposed as.
+	pub upstream_name: String,
+	pub prompt: Prompt,
+}
+
+/// A resource listed by one of the targets of a backend.
+#[derive(Debug, Clone)]
+pub struct MergedResource {
+	pub target: Strng,
+	/// The URI of the resource on the target; `resource` carries the URI it is exposed as.
+	pub upstream_uri: String,
+	pub resource: Resource,
 }
 
 #[derive(Debug)]
@@ -0,0 +1,189 @@
+use std::io;
+
+use bytes::{Buf, Bytes, BytesMut};
+use serde::Serialize;
+use tokio_sse_codec::{Frame, SseEncoder};
+use tokio_util::codec::Decoder;
+
+use super::sse;
+use super::transform::parser as transform_parser;
+use crate::*;
+
+// https://docs.aws.amazon.com/transcribe/latest/dg/streaming-setting-up.html#streaming-event-stream
+// Each message is a prelude (total length, headers length, prelude CRC), the headers, the payload,
+// and a CRC of the whole message.
+const PRELUDE_LEN: usize = 12;
+const CRC_LEN: usize = 4;
+const MAX_MESSAGE_LEN: usize = 16 * 1024 * 1024;
+
+/// A message in the AWS event stream encoding.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+	// Only string headers are kept, as those are all that is needed to interpret the payload
+	pub headers: Vec<(String, String)>,
+	pub payload: Bytes,
+}
+
+impl Message {
+	pub fn header(&self, name: &str) -> Option<&str> {
+		self
+			.headers
+			.iter()
+			.find(|(k, _)| k == name)
+			.map(|(_, v)| v.as_str())
+	}
+}
+
+/// Decodes AWS event stream messages, such as those used by Bedrock for streaming responses.
+#[derive(Debug, Default)]
+pub struct EventStreamDecoder;
+
+impl Decoder for EventStreamDecoder {
+	type Item = Message;
+	type Error = io::Error;
+
+	fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+		if src.len() < PRELUDE_LEN {
+			return Ok(None);
+		}
+		let total_len = u32::from_be_bytes(src[0..4].try_into().unwrap()) as usize;
+		let headers_len = u32::from_be_bytes(src[4..8].try_into().unwrap()) as usize;
+		let prelude_crc = u32::from_be_bytes(src[8..12].try_into().unwrap());
+		if crc32fast::hash(&src[..8]) != prelude_crc {
+			return Err(invalid("prelude checksum mismatch"));
+		}
+		if total_len < PRELUDE_LEN + headers_len + CRC_LEN || total_len > MAX_MESSAGE_LEN {
+			return Err(invalid("invalid message length"));
+		}
+		if src.len() < total_len {
+			// Wait for the rest of the message
+			src.reserve(total_len - src.len());
+			return Ok(None);
+		}
+		let msg = src.split_to(total_len).freeze();
+		let message_crc = u32::from_be_bytes(msg[total_len - CRC_LEN..].try_into().unwrap());
+		if crc32fast::hash(&msg[..total_len - CRC_LEN]) != message_crc {
+			return Err(invalid("message checksum mismatch"));
+		}
+		let headers = decode_headers(msg.slice(PRELUDE_LEN..PRELUDE_LEN + headers_len))?;
+		let payload = msg.slice(PRELUDE_LEN + headers_len..total_len - CRC_LEN);
+		Ok(Some(Message { headers, payload }))
+	}
+}
+
+fn decode_headers(mut b: Bytes) -> Result<Vec<(String, String)>, io::Error> {
+	let mut headers = vec![];
+	while b.has_remaining() {
+		let name_len = b.get_u8() as usize;
+		let name = take(&mut b, name_len)?;
+		if !b.has_remaining() {
+			return Err(invalid("truncated header"));
+		}
+		// Skip over values other than strings, which have a fixed or prefixed length
+		let value_len = match b.get_u8() {
+			// Boolean true and false, encoded in the type alone
+			0 | 1 => 0,
+			// Byte, short, integer, long
+			2 => 1,
+			3 => 2,
+			4 => 4,
+			5 | 8 => 8,
+			// Byte array and string
+			6 | 7 => {
+				if b.remaining() < 2 {
+					return Err(invalid("truncated header"));
+				}
+				let len = b.get_u16() as usize;
+				let value = take(&mut b, len)?;
+				if let (Ok(name), Ok(value)) = (
+					String::from_utf8(name.to_vec()),
+					String::from_utf8(value.to_vec()),
+				) {
+					headers.push((name, value));
+				}
+				continue;
+			},
+			// UUID
+			9 => 16,
+			_ => return Err(invalid("unknown header type")),
+		};
+		take(&mut b, value_len)?;
+	}
+	Ok(headers)
+}
+
+fn take(b: &mut Bytes, len: usize) -> Result<Bytes, io::Error> {
+	if b.remaining() < len {
+		return Err(invalid("truncated header"));
+	}
+	Ok(b.split_to(len))
+}
+
+fn invalid(msg: &'static str) -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Transforms an AWS event stream into SSE, with each message becoming a JSON event. The stream ends
+/// with a `[DONE]` event, as with OpenAI.
+pub fn transform_to_sse<O: Serialize>(
+	b: http::Body,
+	mut f: impl FnMut(Message) -> Option<O> + Send + 'static,
+) -> http::Body {
+	let decoder = WithEnd {
+		inner: EventStreamDecoder,
+		ended: false,
+	};
+	let encoder = SseEncoder::new();
+	transform_parser(b, decoder, encoder, move |m| match m {
+		Some(m) => sse::json_event(&f(m)?),
+		None => Some(sse::done_event()),
+	})
+}
+
+// Wraps a decoder to yield a final None once the stream ends.
+struct WithEnd<D> {
+	inner: D,
+	ended: bool,
+}
+
+impl<D: Decoder> Decoder for WithEnd<D> {
+	type Item = Option<D::Item>;
+	type Error = D::Error;
+
+	fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+		Ok(self.inner.decode(src)?.map(Some))
+	}
+
+	fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+		match self.inner.decode_eof(src)? {
+			Some(item) => Ok(Some(Some(item))),
+			None if !self.ended => {
+				self.ended = true;
+				Ok(Some(None))
+			},
+			None => Ok(None),
+		}
+	}
+}
+
+/// Encodes a message in the AWS event stream encoding, with string headers.
+#[cfg(test)]
+pub fn encode(headers: &[(&str, &str)], payload: &[u8]) -> Bytes {
+	let mut h = vec![];
+	for (k, v) in headers {
+		h.push(k.len() as u8);
+		h.extend_from_slice(k.as_bytes());
+		h.push(7);
+		h.extend_from_slice(&(v.len() as u16).to_be_bytes());
+		h.extend_from_slice(v.as_bytes());
+	}
+	let total_len = PRELUDE_LEN + h.len() + payload.len() + CRC_LEN;
+	let mut msg = vec![];
+	msg.extend_from_slice(&(total_len as u32).to_be_bytes());
+	msg.extend_from_slice(&(h.len() as u32).to_be_bytes());
+	msg.extend_from_slice(&crc32fast::hash(&msg).to_be_bytes());
+	msg.extend_from_slice(&h);
+	msg.extend_from_slice(payload);
+	msg.extend_from_slice(&crc32fast::hash(&msg).to_be_bytes());
+	Bytes::from(msg)
+}
@@ -1,3 +1,4 @@
+pub mod eventstream;
 pub mod passthrough;
 pub mod sse;
 pub mod transform;
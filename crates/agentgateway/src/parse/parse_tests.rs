@@ -2,11 +2,12 @@ use std::collections::HashMap;
 use std::convert::Infallible;
 
 use ::http::HeaderMap;
+use bytes::BytesMut;
 use http_body::Body;
 use http_body_util::{BodyExt, Full};
 use itertools::Itertools;
 use tokio_sse_codec::{Event, Frame, SseDecoder};
-use tokio_util::codec::{BytesCodec, LinesCodec};
+use tokio_util::codec::{BytesCodec, Decoder, LinesCodec};
 
 use super::*;
 use crate::*;
@@ -157,3 +158,102 @@ data: [DONE]
 "#
 	);
 }
+
+#[tokio::test]
+async fn test_sse_json_transform_with_done() {
+	#[derive(Deserialize, Serialize)]
+	struct Msg {
+		msg: u8,
+	}
+	let transform = |input: &'static str| async move {
+		let body = http::Body::from(input);
+		let body = sse::json_transform_with_done::<Msg, Msg>(body, |m| m.ok());
+		let result = body.collect().await.unwrap().to_bytes();
+		String::from_utf8_lossy(&result)
+			.lines()
+			.filter_map(|l| l.strip_prefix("data: "))
+			.map(str::to_string)
+			.collect::<Vec<_>>()
+	};
+	// A missing [DONE] is added at the end of the stream
+	assert_eq!(
+		transform("data: {\"msg\": 1}\n\n").await,
+		vec![r#"{"msg":1}"#, "[DONE]"]
+	);
+	// An existing [DONE] is not repeated
+	assert_eq!(
+		transform("data: {\"msg\": 1}\n\ndata: [DONE]\n\n").await,
+		vec![r#"{"msg":1}"#, "[DONE]"]
+	);
+}
+
+#[test]
+fn test_eventstream_decoder() {
+	let mut input = BytesMut::new();
+	input.extend_from_slice(&eventstream::encode(
+		&[(":message-type", "event"), (":event-type", "messageStart")],
+		br#"{"role":"assistant"}"#,
+	));
+	input.extend_from_slice(&eventstream::encode(&[], b""));
+	let mut decoder = eventstream::EventStreamDecoder;
+
+	// Partial messages are not decoded until the rest arrives
+	let mut buf = BytesMut::from(&input[..5]);
+	assert_eq!(decoder.decode(&mut buf).unwrap(), None);
+	buf.extend_from_slice(&input[5..20]);
+	assert_eq!(decoder.decode(&mut buf).unwrap(), None);
+	buf.extend_from_slice(&input[20..]);
+	let msg = decoder.decode(&mut buf).unwrap().unwrap();
+	assert_eq!(msg.header(":event-type"), Some("messageStart"));
+	assert_eq!(msg.header(":message-type"), Some("event"));
+	assert_eq!(msg.header(":content-type"), None);
+	assert_eq!(msg.payload, Bytes::from_static(br#"{"role":"assistant"}"#));
+	let msg = decoder.decode(&mut buf).unwrap().unwrap();
+	assert_eq!(msg.headers, vec![]);
+	assert_eq!(msg.payload, Bytes::new());
+	assert!(buf.is_empty());
+
+	// Corrupt messages are rejected
+	let mut corrupt = BytesMut::from(&eventstream::encode(&[], b"hello")[..]);
+	let last = corrupt.len() - 1;
+	corrupt[last] ^= 0xff;
+	assert!(decoder.decode(&mut corrupt).is_err());
+	let mut corrupt = BytesMut::from(&eventstream::encode(&[], b"hello")[..]);
+	corrupt[0] ^= 0xff;
+	assert!(decoder.decode(&mut corrupt).is_err());
+}
+
+#[tokio::test]
+async fn test_eventstream_transform_to_sse() {
+	#[derive(Deserialize, Serialize)]
+	struct Msg {
+		msg: u8,
+	}
+	let mut input = BytesMut::new();
+	for i in 1..=2 {
+		input.extend_from_slice(&eventstream::encode(
+			&[(":event-type", "msg")],
+			format!(r#"{{"msg":{i}}}"#).as_bytes(),
+		));
+	}
+	input.extend_from_slice(&eventstream::encode(&[(":event-type", "skip")], b"{}"));
+	// Split the input into small chunks, so messages span multiple reads
+	let chunks = input
+		.chunks(7)
+		.map(|c| Ok::<_, std::io::Error>(Bytes::copy_from_slice(c)))
+		.collect_vec();
+	let body = http::Body::from_stream(futures_util::stream::iter(chunks));
+	let body = eventstream::transform_to_sse(body, |m| {
+		if m.header(":event-type") != Some("msg") {
+			return None;
+		}
+		serde_json::from_slice::<Msg>(&m.payload).ok()
+	});
+	let result = body.collect().await.unwrap().to_bytes();
+	let got = String::from_utf8_lossy(&result)
+		.lines()
+		.filter_map(|l| l.strip_prefix("data: "))
+		.map(str::to_string)
+		.collect::<Vec<_>>();
+	assert_eq!(got, vec![r#"{"msg":1}"#, r#"{"msg":2}"#, "[DONE]"]);
+}
@@ -41,32 +41,105 @@ pub fn json_passthrough<F: DeserializeOwned>(
 
 pub fn json_transform<I: DeserializeOwned, O: Serialize>(
 	b: http::Body,
-	mut f: impl FnMut(anyhow::Result<I>) -> Option<O> + Send + 'static,
+	f: impl FnMut(anyhow::Result<I>) -> Option<O> + Send + 'static,
 ) -> http::Body {
 	let decoder = SseDecoder::<Bytes>::with_max_size(2_097_152);
+	transform_json(b, decoder, f)
+}
+
+/// Like json_transform, for streams that end without a `[DONE]` event. One is sent once the stream
+/// ends, so clients see the same end of stream as with OpenAI.
+pub fn json_transform_with_done<I: DeserializeOwned, O: Serialize>(
+	b: http::Body,
+	f: impl FnMut(anyhow::Result<I>) -> Option<O> + Send + 'static,
+) -> http::Body {
+	let decoder = EndWithDone {
+		inner: SseDecoder::<Bytes>::with_max_size(2_097_152),
+		done: false,
+	};
+	transform_json(b, decoder, f)
+}
+
+fn transform_json<D, I: DeserializeOwned, O: Serialize>(
+	b: http::Body,
+	decoder: D,
+	mut f: impl FnMut(anyhow::Result<I>) -> Option<O> + Send + 'static,
+) -> http::Body
+where
+	D: Decoder<Item = Frame<Bytes>> + Send + 'static,
+	D::Error: Send + Into<axum_core::BoxError> + 'static,
+{
 	let encoder = SseEncoder::new();
 
 	transform_parser(b, decoder, encoder, move |o| {
 		let data = unwrap_sse_data(o)?;
 		// Pass through [DONE] events unchanged
 		if data.as_ref() == b"[DONE]" {
-			return Some(Frame::Event(Event::<Bytes> {
-				data: Bytes::copy_from_slice(b"[DONE]"),
-				name: std::borrow::Cow::Borrowed(""),
-				id: None,
-			}));
+			return Some(done_event());
 		}
 		let obj = serde_json::from_slice::<I>(&data);
 		let transformed = f(obj.map_err(anyhow::Error::from))?;
-		let json_bytes = serde_json::to_vec(&transformed).ok()?;
-		Some(Frame::Event(Event::<Bytes> {
-			data: Bytes::from(json_bytes),
-			name: std::borrow::Cow::Borrowed(""),
-			id: None,
-		}))
+		json_event(&transformed)
 	})
 }
 
+pub(super) fn json_event<O: Serialize>(o: &O) -> Option<Frame<Bytes>> {
+	let json_bytes = serde_json::to_vec(o).ok()?;
+	Some(Frame::Event(Event::<Bytes> {
+		data: Bytes::from(json_bytes),
+		name: std::borrow::Cow::Borrowed(""),
+		id: None,
+	}))
+}
+
+pub(super) fn done_event() -> Frame<Bytes> {
+	Frame::Event(Event::<Bytes> {
+		data: Bytes::copy_from_slice(b"[DONE]"),
+		name: std::borrow::Cow::Borrowed(""),
+		id: None,
+	})
+}
+
+// Decodes SSE frames, adding a final `[DONE]` event if the stream did not include one.
+struct EndWithDone<D> {
+	inner: D,
+	done: bool,
+}
+
+impl<D> EndWithDone<D> {
+	fn observe(&mut self, frame: &Option<Frame<Bytes>>) {
+		if let Some(Frame::Event(Event::<Bytes> { data, .. })) = frame
+			&& data.as_ref() == b"[DONE]"
+		{
+			self.done = true;
+		}
+	}
+}
+
+impl<D: Decoder<Item = Frame<Bytes>>> Decoder for EndWithDone<D> {
+	type Item = Frame<Bytes>;
+	type Error = D::Error;
+
+	fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+		let frame = self.inner.decode(src)?;
+		self.observe(&frame);
+		Ok(frame)
+	}
+
+	fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+		match self.inner.decode_eof(src)? {
+			None if !self.done => {
+				self.done = true;
+				Ok(Some(done_event()))
+			},
+			frame => {
+				self.observe(&frame);
+				Ok(frame)
+			},
+		}
+	}
+}
+
 fn unwrap_sse_data(frame: Frame<Bytes>) -> Option<Bytes> {
 	let Frame::Event(Event::<Bytes> { data, .. }) = frame else {
 		return None;
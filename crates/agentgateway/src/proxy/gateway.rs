@@ -1,6 +1,8 @@
 use std::collections::HashMap;
 use std::convert::Infallible;
 use std::net::SocketAddr;
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Instant;
 
@@ -13,7 +15,7 @@ use http::StatusCode;
 use hyper_util::rt::TokioIo;
 use hyper_util::server::conn::auto;
 use net2::unix::UnixTcpBuilderExt;
-use tokio::net::{TcpListener, TcpStream};
+use tokio::net::{TcpListener, UnixListener};
 use tokio::sync::watch;
 use tokio::task::{AbortHandle, JoinSet};
 use tokio_stream::StreamExt;
@@ -22,7 +24,9 @@ use tracing::{Instrument, debug, event, info, info_span, warn};
 use crate::store::Event;
 use crate::telemetry::metrics::TCPLabels;
 use crate::transport::stream::{BytesCounter, Extension, LoggingMode, Socket};
-use crate::types::agent::{Bind, BindName, BindProtocol, Listener, ListenerProtocol};
+use crate::types::agent::{
+	Bind, BindName, BindProtocol, Listener, ListenerAddress, ListenerProtocol,
+};
 use crate::{ProxyInputs, client};
 
 #[cfg(test)]
@@ -34,6 +38,78 @@ pub struct Gateway {
 	drain: drain::DrainWatcher,
 }
 
+/// BindListener accepts connections for a bind, over TCP or a Unix domain socket.
+enum BindListener {
+	Tcp(TcpListener),
+	Unix(UnixListener, UnixSocketFile),
+}
+
+impl BindListener {
+	async fn bind(address: &ListenerAddress) -> anyhow::Result<Self> {
+		Ok(match address {
+			ListenerAddress::Tcp(addr) => BindListener::Tcp(TcpListener::bind(addr).await?),
+			ListenerAddress::Unix(path) => {
+				// A socket file left behind by a previous run would make the bind fail. Anything else at
+				// the path is left alone.
+				match std::fs::symlink_metadata(path) {
+					Ok(m) if m.file_type().is_socket() => std::fs::remove_file(path)?,
+					Ok(_) => anyhow::bail!("{} exists and is not a socket", path.display()),
+					Err(e) if e.kind() == std::io::ErrorKind::NotFound => {},
+					Err(e) => return Err(e.into()),
+				}
+				let listener = UnixListener::bind(path)?;
+				let file = UnixSocketFile::new(path)?;
+				BindListener::Unix(listener, file)
+			},
+		})
+	}
+
+	async fn accept(&self) -> std::io::Result<Socket> {
+		match self {
+			BindListener::Tcp(listener) => {
+				let (stream, _peer) = listener.accept().await?;
+				Socket::from_tcp(stream).map_err(std::io::Error::other)
+			},
+			BindListener::Unix(listener, _) => {
+				let (stream, _peer) = listener.accept().await?;
+				Ok(Socket::from_unix(stream))
+			},
+		}
+	}
+}
+
+/// UnixSocketFile removes the socket file of a Unix listener once the listener shuts down.
+struct UnixSocketFile {
+	path: PathBuf,
+	// The device and inode of the socket, so a socket bound at the same path since is left alone.
+	id: (u64, u64),
+}
+
+impl UnixSocketFile {
+	fn new(path: &Path) -> anyhow::Result<Self> {
+		let id = socket_id(path).ok_or_else(|| anyhow!("{} is not a socket", path.display()))?;
+		Ok(UnixSocketFile {
+			path: path.to_path_buf(),
+			id,
+		})
+	}
+}
+
+impl Drop for UnixSocketFile {
+	fn drop(&mut self) {
+		if socket_id(&self.path) == Some(self.id)
+			&& let Err(e) = std::fs::remove_file(&self.path)
+		{
+			warn!("failed to remove socket {}: {e}", self.path.display());
+		}
+	}
+}
+
+fn socket_id(path: &Path) -> Option<(u64, u64)> {
+	let m = std::fs::symlink_metadata(path).ok()?;
+	m.file_type().is_socket().then(|| (m.dev(), m.ino()))
+}
+
 impl Gateway {
 	pub fn new(pi: Arc<ProxyInputs>, drain: DrainWatcher) -> Gateway {
 		Gateway { drain, pi }
@@ -47,7 +123,7 @@ impl Gateway {
 			let binds = self.pi.stores.read_binds();
 			(binds.all(), binds.subscribe())
 		};
-		let mut active: HashMap<SocketAddr, AbortHandle> = HashMap::new();
+		let mut active: HashMap<ListenerAddress, AbortHandle> = HashMap::new();
 		let mut handle_bind = |js: &mut JoinSet<anyhow::Result<()>>, b: Event<Arc<Bind>>| {
 			let b = match b {
 				Event::Add(b) => b,
@@ -92,7 +168,7 @@ impl Gateway {
 			} else {
 				let task =
 					js.spawn(Self::run_bind(self.pi.clone(), subdrain.clone(), b.clone()).in_current_span());
-				active.insert(b.address, task);
+				active.insert(b.address.clone(), task);
 			}
 		};
 		for bind in initial_binds {
@@ -135,21 +211,24 @@ impl Gateway {
 		let max_deadline = pi.cfg.termination_max_deadline;
 		let name = b.key.clone();
 		let (pi, listener) = if pi.cfg.threading_mode == crate::ThreadingMode::ThreadPerCore {
+			let ListenerAddress::Tcp(address) = b.address else {
+				anyhow::bail!("unix socket binds are not supported in thread per core mode");
+			};
 			let mut pi = Arc::unwrap_or_clone(pi);
 			let client = client::Client::new(&pi.cfg.dns, None);
 			pi.upstream = client;
 			let pi = Arc::new(pi);
-			let mut builder = if b.address.is_ipv4() {
+			let mut builder = if address.is_ipv4() {
 				net2::TcpBuilder::new_v4()
 			} else {
 				net2::TcpBuilder::new_v6()
 			};
-			let listener = builder?.reuse_port(true)?.bind(b.address)?.listen(1024)?;
+			let listener = builder?.reuse_port(true)?.bind(address)?.listen(1024)?;
 			listener.set_nonblocking(true)?;
 			let listener = tokio::net::TcpListener::from_std(listener)?;
-			(pi, listener)
+			(pi, BindListener::Tcp(listener))
 		} else {
-			(pi, TcpListener::bind(b.address).await?)
+			(pi, BindListener::bind(&b.address).await?)
 		};
 		info!(bind = name.as_str(), "started bind");
 		let component = format!("bind {name}");
@@ -175,8 +254,7 @@ impl Gateway {
 			// Having a weak reference allows us to listen() forever without blocking, but create blockers for accepted connections.
 			let (mut upgrader, weak) = drain.into_weak();
 			let (inner_trigger, inner_drain) = drain::new();
-			let handle_stream = |stream: TcpStream, upgrader: &DrainUpgrader| {
-				let mut stream = Socket::from_tcp(stream).expect("todo");
+			let handle_stream = |mut stream: Socket, upgrader: &DrainUpgrader| {
 				stream.with_logging(LoggingMode::Downstream);
 				let pi = pi.clone();
 				// We got the connection; make a strong drain blocker.
@@ -184,8 +262,15 @@ impl Gateway {
 				let start = Instant::now();
 				let mut force_shutdown = force_shutdown.clone();
 				let name = name.clone();
+				let b = b.clone();
 				tokio::spawn(async move {
 					debug!(bind=?name, "connection started");
+					if let Some(pp) = &b.proxy_protocol
+						&& let Err(e) = pp.apply(&mut stream).await
+					{
+						warn!(bind=?name, "failed to read PROXY protocol header: {e}");
+						return;
+					}
 					tokio::select! {
 						// We took too long; shutdown now.
 						_ = force_shutdown.changed() => {
@@ -201,7 +286,7 @@ impl Gateway {
 			// First, accept new connections until a drain is triggered
 			let drain_mode = loop {
 				tokio::select! {
-					Ok((stream, _peer)) = listener.accept() => handle_stream(stream, &upgrader),
+					Ok(stream) = listener.accept() => handle_stream(stream, &upgrader),
 					res = &mut wait => {
 						break res;
 					}
@@ -222,7 +307,7 @@ impl Gateway {
 			// We still need to accept new connections during this time though, so race them
 			loop {
 				tokio::select! {
-					Ok((stream, _peer)) = listener.accept() => handle_stream(stream, &upgrader),
+					Ok(stream) = listener.accept() => handle_stream(stream, &upgrader),
 					res = &mut drained_for_minimum => {
 						// We are done! exit.
 						// This will stop accepting new connections
@@ -302,6 +387,7 @@ impl Gateway {
 			inputs,
 			selected_listener,
 			target_address,
+			drain: drain.clone(),
 		};
 		let server = auto_server();
 		let connection = Arc::new(stream.get_ext());
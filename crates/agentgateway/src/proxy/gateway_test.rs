@@ -4,26 +4,36 @@ use std::sync::Arc;
 use std::time::Instant;
 
 use ::http::{Method, Request, Uri, Version};
-use agent_core::drain::{DrainTrigger, DrainWatcher};
+use agent_core::drain::{DrainMode, DrainTrigger, DrainWatcher};
 use agent_core::{drain, metrics, strng};
 use axum::body::to_bytes;
+use base64::Engine;
+use base64::prelude::BASE64_STANDARD;
+use http_body_util::{BodyExt, StreamBody};
 use hyper_util::client::legacy::Client;
 use hyper_util::client::legacy::connect::Connected;
 use hyper_util::rt::tokio::WithHyperIo;
 use hyper_util::rt::{TokioExecutor, TokioIo, TokioTimer};
 use prometheus_client::registry::Registry;
-use tokio::io::DuplexStream;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream};
 use wiremock::{Mock, MockServer, ResponseTemplate};
 
+use crate::http::backendtls::BackendTLSConfig;
+use crate::http::maintenance::MaintenancePage;
 use crate::http::{Body, Response};
+use crate::llm::{AIBackend, AIProvider};
 use crate::proxy::Gateway;
 use crate::proxy::request_builder::RequestBuilder;
 use crate::store::Stores;
+use crate::telemetry::metrics::{MirrorComparison, MirrorLabels};
 use crate::transport::stream::{Socket, TCPConnectionInfo};
 use crate::types::agent::{
-	Backend, BackendReference, Bind, BindName, Listener, ListenerProtocol, ListenerSet, PathMatch,
-	Policy, PolicyTarget, Route, RouteBackend, RouteBackendReference, RouteMatch, RouteSet, Target,
-	TargetedPolicy,
+	Backend, BackendReference, Bind, BindName, Listener, ListenerAddress, ListenerProtocol,
+	ListenerSet, McpBackend, McpTarget, McpTargetSpec, OpenAPITarget, PathMatch, Policy,
+	PolicyTarget, Route, RouteBackend, RouteBackendReference, RouteFilter, RouteMatch, RouteSet,
+	SimpleBackendReference, SseTargetSpec, StreamableHTTPTargetSpec, TCPRoute,
+	TCPRouteBackendReference, TCPRouteSet, TLSConfig, Target, TargetedPolicy, TrafficPolicy,
+	parse_certified_key,
 };
 use crate::{ProxyInputs, client, mcp, *};
 
@@ -36,6 +46,20 @@ async fn basic_handling() {
 	assert_eq!(body.method, Method::POST);
 }
 
+#[tokio::test]
+async fn timeout_header_stripped_without_policy() {
+	// The route has no policies, so cannot allow a timeout override, but the header is still removed
+	let (_mock, _bind, io) = basic_setup().await;
+	let res = RequestBuilder::new(Method::GET, "http://lo")
+		.header(http::timeout::TIMEOUT_HEADER, "5000")
+		.send(io)
+		.await
+		.unwrap();
+	assert_eq!(res.status(), 200);
+	let body = read_body(res.into_body()).await;
+	assert!(!body.headers.contains_key(http::timeout::TIMEOUT_HEADER));
+}
+
 #[tokio::test]
 async fn multiple_requests() {
 	let (_mock, _bind, io) = basic_setup().await;
@@ -61,6 +85,230 @@ async fn basic_http2() {
 	assert_eq!(res.status(), 200);
 }
 
+#[tokio::test]
+async fn unix_socket_bind() {
+	let mock = simple_mock().await;
+	let dir = tempfile::tempdir().unwrap();
+	let path = dir.path().join("gateway.sock");
+	let mut bind = simple_bind(basic_route(*mock.address()));
+	bind.address = ListenerAddress::Unix(path.clone());
+	let t = setup()
+		.unwrap()
+		.with_backend(*mock.address())
+		.with_bind(bind.clone());
+	tokio::spawn(Gateway::run_bind(
+		t.pi.clone(),
+		t.drain_rx.clone(),
+		Arc::new(bind),
+	));
+
+	// The listener is bound asynchronously, so retry until it is up
+	let stream = loop {
+		match tokio::net::UnixStream::connect(&path).await {
+			Ok(stream) => break stream,
+			Err(_) => tokio::time::sleep(Duration::from_millis(10)).await,
+		}
+	};
+	let (mut sender, conn) = hyper::client::conn::http1::handshake(TokioIo::new(stream))
+		.await
+		.unwrap();
+	tokio::spawn(conn);
+	let req = ::http::Request::builder()
+		.uri("/")
+		.header("host", "lo")
+		.body(Body::empty())
+		.unwrap();
+	let res = sender.send_request(req).await.unwrap();
+	assert_eq!(res.status(), 200);
+	let body = read_body(Body::new(res.into_body())).await;
+	assert_eq!(body.method, Method::GET);
+}
+
+#[tokio::test]
+async fn unix_socket_bind_cleanup() {
+	let mock = simple_mock().await;
+	let dir = tempfile::tempdir().unwrap();
+	let path = dir.path().join("gateway.sock");
+	let mut bind = simple_bind(basic_route(*mock.address()));
+	bind.address = ListenerAddress::Unix(path.clone());
+	let bind = Arc::new(bind);
+	let t = setup()
+		.unwrap()
+		.with_backend(*mock.address())
+		.with_bind((*bind).clone());
+
+	// Only a stale socket is replaced; other files at the path are left alone
+	std::fs::write(&path, "not a socket").unwrap();
+	let res = Gateway::run_bind(t.pi.clone(), t.drain_rx.clone(), bind.clone()).await;
+	assert!(res.is_err());
+	assert_eq!(std::fs::read_to_string(&path).unwrap(), "not a socket");
+	std::fs::remove_file(&path).unwrap();
+
+	let task = tokio::spawn(Gateway::run_bind(
+		t.pi.clone(),
+		t.drain_rx.clone(),
+		bind.clone(),
+	));
+	while tokio::net::UnixStream::connect(&path).await.is_err() {
+		tokio::time::sleep(Duration::from_millis(10)).await;
+	}
+	// The socket is removed once the listener shuts down
+	task.abort();
+	let _ = task.await;
+	assert!(!path.exists());
+}
+
+// A route that only matches /api, so other paths fall through to the listener default.
+fn api_route(target: SocketAddr) -> Route {
+	let mut route = basic_route(target);
+	route.matches[0].path = PathMatch::PathPrefix("/api".into());
+	route
+}
+
+#[tokio::test]
+async fn listener_default_response() {
+	let mock = simple_mock().await;
+	let mut default = basic_route(*mock.address());
+	default.key = "default".into();
+	default.backends = vec![];
+	default.filters = vec![RouteFilter::DirectResponse(http::filters::DirectResponse {
+		body: Bytes::from_static(b"nothing here"),
+		status: ::http::StatusCode::NOT_FOUND,
+	})];
+	let t = setup()
+		.unwrap()
+		.with_backend(*mock.address())
+		.with_bind(bind_with_default(api_route(*mock.address()), Some(default)));
+	let io = t.serve_http(strng::new("bind"));
+
+	let res = send_request(io.clone(), Method::GET, "http://lo/api/users").await;
+	assert_eq!(res.status(), 200);
+	let res = send_request(io, Method::GET, "http://lo/other").await;
+	assert_eq!(res.status(), 404);
+	assert_eq!(
+		read_body_raw(res.into_body()).await.as_ref(),
+		b"nothing here"
+	);
+}
+
+#[tokio::test]
+async fn listener_default_backend() {
+	let api = simple_mock().await;
+	let fallback = simple_mock().await;
+	let mut default = basic_route(*fallback.address());
+	default.key = "default".into();
+	let t = setup()
+		.unwrap()
+		.with_backend(*api.address())
+		.with_backend(*fallback.address())
+		.with_bind(bind_with_default(api_route(*api.address()), Some(default)));
+	let io = t.serve_http(strng::new("bind"));
+
+	let res = send_request(io, Method::GET, "http://lo/other").await;
+	assert_eq!(res.status(), 200);
+	let body = read_body(res.into_body()).await;
+	assert_eq!(body.uri.path(), "/other");
+	assert_eq!(fallback.received_requests().await.unwrap().len(), 1);
+	assert!(api.received_requests().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn otlp_span_per_request() {
+	let mock = simple_mock().await;
+	let exporter = opentelemetry_sdk::trace::InMemorySpanExporter::default();
+	let mut t = setup()
+		.unwrap()
+		.with_backend(*mock.address())
+		.with_bind(simple_bind(basic_route(*mock.address())));
+	let tracer = trc::Tracer::with_exporter(&t.pi.cfg.tracing, exporter.clone());
+	Arc::get_mut(&mut t.pi).unwrap().tracer = Some(tracer.clone());
+	let io = t.serve_http(strng::new("bind"));
+
+	let parent = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+	for _ in 0..2 {
+		let res = RequestBuilder::new(Method::GET, "http://lo")
+			.header("traceparent", parent)
+			.send(io.clone())
+			.await
+			.unwrap();
+		assert_eq!(res.status(), 200);
+	}
+
+	// Spans are reported once the request log is complete, which may be after the response is sent
+	let mut spans = vec![];
+	for _ in 0..50 {
+		tracer.provider.force_flush().unwrap();
+		spans = exporter.get_finished_spans().unwrap();
+		if spans.len() == 2 {
+			break;
+		}
+		tokio::time::sleep(Duration::from_millis(20)).await;
+	}
+	assert_eq!(spans.len(), 2);
+	for span in &spans {
+		assert_eq!(
+			format!("{:032x}", span.span_context.trace_id()),
+			"4bf92f3577b34da6a3ce929d0e0e4736"
+		);
+		assert_eq!(format!("{:016x}", span.parent_span_id), "00f067aa0ba902b7");
+		let attr = |k: &str| {
+			span
+				.attributes
+				.iter()
+				.find(|kv| kv.key.as_str() == k)
+				.map(|kv| kv.value.to_string())
+		};
+		assert_eq!(attr("route").as_deref(), Some("route"));
+		assert_eq!(attr("http.response.status_code").as_deref(), Some("200"));
+		assert!(attr("backend").is_some());
+		assert!(attr("duration").is_some());
+	}
+	// Each backend request is a child of the span we reported for it
+	for req in mock.received_requests().await.unwrap() {
+		let tp = req.headers.get("traceparent").unwrap().to_str().unwrap();
+		assert!(
+			spans
+				.iter()
+				.any(|s| tp.contains(&format!("-{:016x}-", s.span_context.span_id()))),
+			"{tp}"
+		);
+	}
+}
+
+#[tokio::test]
+async fn server_timing() {
+	let (_mock, _bind, io) = basic_setup().await;
+	let res = RequestBuilder::new(Method::GET, "http://lo")
+		.header("x-agentgateway-server-timing", "1")
+		.send(io.clone())
+		.await
+		.unwrap();
+	assert_eq!(res.status(), 200);
+	let timing = res
+		.headers()
+		.get("server-timing")
+		.unwrap()
+		.to_str()
+		.unwrap()
+		.to_string();
+	let phases = timing
+		.split(", ")
+		.map(|m| m.split_once(";dur=").unwrap().0)
+		.collect::<Vec<_>>();
+	// The first request to the backend opens a new connection
+	assert_eq!(
+		phases,
+		vec!["route", "policy", "connect", "upstream", "total"],
+		"{timing}"
+	);
+	let body = read_body(res.into_body()).await;
+	assert!(!body.headers.contains_key("x-agentgateway-server-timing"));
+
+	// Without the request header, nothing is added
+	let res = send_request(io.clone(), Method::GET, "http://lo").await;
+	assert!(!res.headers().contains_key("server-timing"));
+}
+
 #[tokio::test]
 async fn local_ratelimit() {
 	let (_mock, mut bind, io) = basic_setup().await;
@@ -73,6 +321,7 @@ async fn local_ratelimit() {
 				tokens_per_fill: 1,
 				fill_interval: Duration::from_secs(1),
 				limit_type: Default::default(),
+				key: Default::default(),
 			}
 			.try_into()
 			.unwrap(),
@@ -85,6 +334,1866 @@ async fn local_ratelimit() {
 	assert_eq!(res.status(), 429);
 }
 
+#[tokio::test]
+async fn ext_authz_http() {
+	let authz = wiremock::MockServer::start().await;
+	Mock::given(wiremock::matchers::path("/authz/allowed"))
+		.and(wiremock::matchers::header("x-token", "good"))
+		.respond_with(
+			ResponseTemplate::new(200)
+				.insert_header("x-user", "alice")
+				.insert_header("x-internal", "leaked"),
+		)
+		.mount(&authz)
+		.await;
+	Mock::given(wiremock::matchers::any())
+		.respond_with(
+			ResponseTemplate::new(401)
+				.insert_header("www-authenticate", "Bearer")
+				.set_body_string("denied by authz"),
+		)
+		.mount(&authz)
+		.await;
+	let backend = wiremock::MockServer::start().await;
+	Mock::given(wiremock::matchers::header("x-user", "alice"))
+		.respond_with(ResponseTemplate::new(200))
+		// Only the allowed request should reach the backend
+		.expect(1)
+		.mount(&backend)
+		.await;
+
+	let t = setup()
+		.unwrap()
+		.with_backend(*backend.address())
+		.with_bind(simple_bind(basic_route(*backend.address())))
+		.with_policy(TargetedPolicy {
+			name: strng::new("authz"),
+			target: PolicyTarget::Route("route".into()),
+			policy: Policy::ExtAuthz(http::ext_authz::ExtAuthz {
+				target: Target::Address(*authz.address()),
+				context: None,
+				protocol: http::ext_authz::Protocol::Http(http::ext_authz::HttpProtocol {
+					path_prefix: Some("/authz".to_string()),
+					allowed_upstream_headers: vec![http::HeaderName::from_static("x-user")],
+				}),
+			}),
+		});
+	let io = t.serve_http(strng::new("bind"));
+
+	let res = RequestBuilder::new(Method::GET, "http://lo/allowed")
+		.header("x-token", "good")
+		.header("x-forwarded-client-identity", "spiffe://spoofed")
+		.send(io.clone())
+		.await
+		.unwrap();
+	assert_eq!(res.status(), 200);
+	let received = backend.received_requests().await.unwrap();
+	assert!(!received[0].headers.contains_key("x-internal"));
+	// The client is not using mTLS, so no identity may be forwarded
+	let checked = authz.received_requests().await.unwrap();
+	assert!(
+		!checked[0]
+			.headers
+			.contains_key("x-forwarded-client-identity")
+	);
+
+	let res = RequestBuilder::new(Method::GET, "http://lo/allowed")
+		.header("x-token", "bad")
+		.send(io.clone())
+		.await
+		.unwrap();
+	assert_eq!(res.status(), 401);
+	assert_eq!(res.headers().get("www-authenticate").unwrap(), "Bearer");
+	assert_eq!(
+		read_body_raw(res.into_body()).await.as_ref(),
+		b"denied by authz"
+	);
+}
+
+#[tokio::test]
+async fn header_filter() {
+	let mock = wiremock::MockServer::start().await;
+	Mock::given(wiremock::matchers::any())
+		.respond_with(|req: &wiremock::Request| {
+			let r = RequestDump {
+				method: req.method.clone(),
+				uri: req.url.to_string().parse().unwrap(),
+				headers: req.headers.clone(),
+				body: Bytes::copy_from_slice(&req.body),
+			};
+			ResponseTemplate::new(200)
+				.set_body_json(r)
+				.insert_header("server", "internal/1.2")
+				.insert_header("x-upstream", "ok")
+				.insert_header("connection", "x-upstream-hop")
+				.insert_header("x-upstream-hop", "1")
+				.insert_header("keep-alive", "timeout=5")
+		})
+		.mount(&mock)
+		.await;
+	let filter = serde_json::from_value(serde_json::json!({
+		"request": {"deny": ["x-internal-*"]},
+		"response": {"deny": ["server"]},
+	}))
+	.unwrap();
+	let t = setup()
+		.unwrap()
+		.with_backend(*mock.address())
+		.with_bind(simple_bind(basic_route(*mock.address())))
+		.with_policy(TargetedPolicy {
+			name: strng::new("filter"),
+			target: PolicyTarget::Backend(mock.address().to_string().into()),
+			policy: Policy::HeaderFilter(filter),
+		});
+	let io = t.serve_http(strng::new("bind"));
+	let res = RequestBuilder::new(Method::GET, "http://lo")
+		.header("x-internal-user", "alice")
+		.header("x-user", "alice")
+		.header("connection", "x-hop")
+		.header("x-hop", "1")
+		.header("keep-alive", "timeout=5")
+		.send(io)
+		.await
+		.unwrap();
+	assert_eq!(res.status(), 200);
+	assert!(res.headers().get("server").is_none());
+	assert!(res.headers().get("x-upstream-hop").is_none());
+	assert!(res.headers().get("keep-alive").is_none());
+	assert_eq!(res.headers().get("x-upstream").unwrap(), "ok");
+
+	let body = read_body(res.into_body()).await;
+	assert!(body.headers.get("x-internal-user").is_none());
+	assert!(body.headers.get("x-hop").is_none());
+	assert!(body.headers.get("keep-alive").is_none());
+	assert_eq!(body.headers.get("x-user").unwrap(), "alice");
+}
+
+#[tokio::test]
+async fn listener_header_filter() {
+	let mock = wiremock::MockServer::start().await;
+	Mock::given(wiremock::matchers::any())
+		.respond_with(|req: &wiremock::Request| {
+			let r = RequestDump {
+				method: req.method.clone(),
+				uri: req.url.to_string().parse().unwrap(),
+				headers: req.headers.clone(),
+				body: Bytes::copy_from_slice(&req.body),
+			};
+			ResponseTemplate::new(200)
+				.set_body_json(r)
+				.insert_header("x-debug-trace", "abc")
+				.insert_header("x-upstream", "ok")
+		})
+		.mount(&mock)
+		.await;
+	let filter = serde_json::from_value(serde_json::json!({
+		"request": {"deny": ["x-internal-*"]},
+		"response": {"deny": ["x-debug-*"]},
+	}))
+	.unwrap();
+	let t = setup()
+		.unwrap()
+		.with_backend(*mock.address())
+		.with_bind(simple_bind(basic_route(*mock.address())))
+		.with_policy(TargetedPolicy {
+			name: strng::new("filter"),
+			// The listener of simple_bind has an empty key
+			target: PolicyTarget::Listener(Default::default()),
+			policy: Policy::HeaderFilter(filter),
+		});
+	let io = t.serve_http(strng::new("bind"));
+	let res = RequestBuilder::new(Method::GET, "http://lo")
+		.header("x-internal-user", "alice")
+		.header("x-user", "alice")
+		.send(io)
+		.await
+		.unwrap();
+	assert_eq!(res.status(), 200);
+	assert!(res.headers().get("x-debug-trace").is_none());
+	assert_eq!(res.headers().get("x-upstream").unwrap(), "ok");
+
+	let body = read_body(res.into_body()).await;
+	assert!(body.headers.get("x-internal-user").is_none());
+	assert_eq!(body.headers.get("x-user").unwrap(), "alice");
+}
+
+/// A minimal MCP server exposing a single tool.
+#[derive(Clone)]
+struct EchoServer;
+
+impl rmcp::ServerHandler for EchoServer {
+	fn get_info(&self) -> rmcp::model::ServerInfo {
+		rmcp::model::ServerInfo {
+			capabilities: rmcp::model::ServerCapabilities {
+				tools: Some(Default::default()),
+				..Default::default()
+			},
+			..Default::default()
+		}
+	}
+
+	async fn list_tools(
+		&self,
+		_request: Option<rmcp::model::PaginatedRequestParam>,
+		_context: rmcp::service::RequestContext<rmcp::RoleServer>,
+	) -> Result<rmcp::model::ListToolsResult, rmcp::model::ErrorData> {
+		Ok(rmcp::model::ListToolsResult {
+			tools: vec![rmcp::model::Tool::new(
+				"echo",
+				"echoes its input",
+				Arc::new(Default::default()),
+			)],
+			next_cursor: None,
+		})
+	}
+}
+
+/// An MCP server whose prompts can be fetched, but not listed.
+#[derive(Clone)]
+struct UnlistedPromptServer;
+
+impl rmcp::ServerHandler for UnlistedPromptServer {
+	fn get_info(&self) -> rmcp::model::ServerInfo {
+		rmcp::model::ServerInfo {
+			capabilities: rmcp::model::ServerCapabilities {
+				prompts: Some(Default::default()),
+				..Default::default()
+			},
+			..Default::default()
+		}
+	}
+
+	async fn list_prompts(
+		&self,
+		_request: Option<rmcp::model::PaginatedRequestParam>,
+		_context: rmcp::service::RequestContext<rmcp::RoleServer>,
+	) -> Result<rmcp::model::ListPromptsResult, rmcp::model::ErrorData> {
+		Err(rmcp::model::ErrorData::internal_error(
+			"listing is down",
+			None,
+		))
+	}
+
+	async fn get_prompt(
+		&self,
+		request: rmcp::model::GetPromptRequestParam,
+		_context: rmcp::service::RequestContext<rmcp::RoleServer>,
+	) -> Result<rmcp::model::GetPromptResult, rmcp::model::ErrorData> {
+		Ok(rmcp::model::GetPromptResult {
+			description: None,
+			messages: vec![rmcp::model::PromptMessage::new_text(
+				rmcp::model::PromptMessageRole::User,
+				format!("prompt {}", request.name),
+			)],
+		})
+	}
+}
+
+/// Starts an MCP server speaking the HTTP+SSE transport, with its stream served on /sse.
+async fn sse_mcp_server() -> SocketAddr {
+	serve_sse_mcp(|| EchoServer).await
+}
+
+async fn serve_sse_mcp<S: rmcp::ServerHandler>(
+	service: impl Fn() -> S + Send + 'static,
+) -> SocketAddr {
+	let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+	let addr = listener.local_addr().unwrap();
+	let (server, router) =
+		rmcp::transport::SseServer::new(rmcp::transport::sse_server::SseServerConfig {
+			bind: addr,
+			sse_path: "/sse".to_string(),
+			post_path: "/message".to_string(),
+			ct: Default::default(),
+			sse_keep_alive: None,
+		});
+	server.with_service(service);
+	tokio::spawn(async move { axum::serve(listener, router).await });
+	addr
+}
+
+/// Sends a JSON-RPC message to a Streamable HTTP MCP endpoint, and returns the response along with
+/// its body, which is either JSON or a stream of SSE events.
+async fn mcp_post(
+	io: Client<MemoryConnector, Body>,
+	session: Option<&str>,
+	message: serde_json::Value,
+) -> (Response, String) {
+	let mut rb = RequestBuilder::new(Method::POST, "http://lo/mcp")
+		.header("accept", "application/json, text/event-stream")
+		.json(&message);
+	if let Some(session) = session {
+		rb = rb.header("mcp-session-id", session);
+	}
+	let res = rb.send(io).await.unwrap();
+	let (parts, body) = res.into_parts();
+	let body = tokio::time::timeout(Duration::from_secs(5), read_body_raw(body))
+		.await
+		.expect("response stream should end after the response");
+	(
+		Response::from_parts(parts, Body::empty()),
+		String::from_utf8_lossy(&body).to_string(),
+	)
+}
+
+#[tokio::test]
+async fn mcp_get_prompt_when_listing_fails() {
+	let upstream = serve_sse_mcp(|| UnlistedPromptServer).await;
+	let mcp = Backend::MCP(
+		strng::new("mcp"),
+		McpBackend {
+			targets: vec![Arc::new(McpTarget {
+				name: strng::new("prompts"),
+				spec: McpTargetSpec::Sse(SseTargetSpec {
+					backend: SimpleBackendReference::Backend(strng::format!("{upstream}")),
+					path: "/sse".to_string(),
+				}),
+			})],
+			delimiter: Default::default(),
+		},
+	);
+	let t = setup().unwrap().with_backend(upstream);
+	t.pi.stores.binds.write().insert_backend(mcp);
+	let mut route = basic_route(upstream);
+	route.backends[0].backend = BackendReference::Backend(strng::new("mcp"));
+	let t = t.with_bind(simple_bind(route));
+	let io = t.serve_http(strng::new("bind"));
+
+	let (res, body) = mcp_post(
+		io.clone(),
+		None,
+		serde_json::json!({
+			"jsonrpc": "2.0",
+			"id": 1,
+			"method": "initialize",
+			"params": {
+				"protocolVersion": "2025-03-26",
+				"capabilities": {},
+				"clientInfo": {"name": "test", "version": "1.0"},
+			},
+		}),
+	)
+	.await;
+	assert_eq!(res.status(), 200, "{body}");
+	let session = res
+		.headers()
+		.get("mcp-session-id")
+		.unwrap()
+		.to_str()
+		.unwrap()
+		.to_string();
+	mcp_post(
+		io.clone(),
+		Some(&session),
+		serde_json::json!({"jsonrpc": "2.0", "method": "notifications/initialized"}),
+	)
+	.await;
+
+	// The arguments cannot be checked, so the call is passed through to the target
+	let (res, body) = mcp_post(
+		io,
+		Some(&session),
+		serde_json::json!({
+			"jsonrpc": "2.0",
+			"id": 2,
+			"method": "prompts/get",
+			"params": {"name": "review"},
+		}),
+	)
+	.await;
+	assert_eq!(res.status(), 200, "{body}");
+	assert!(body.contains("prompt review"), "{body}");
+}
+
+#[tokio::test]
+async fn mcp_openapi_dry_run() {
+	let upstream = wiremock::MockServer::start().await;
+	// A dry run only describes the request, so nothing is sent upstream
+	Mock::given(wiremock::matchers::any())
+		.respond_with(ResponseTemplate::new(200))
+		.expect(0)
+		.mount(&upstream)
+		.await;
+	let schema = serde_json::from_value(serde_json::json!({
+		"openapi": "3.0.0",
+		"info": {"title": "users", "version": "1.0"},
+		"paths": {
+			"/users/{user_id}": {
+				"get": {
+					"operationId": "get_user",
+					"parameters": [{
+						"name": "user_id",
+						"in": "path",
+						"required": true,
+						"schema": {"type": "string"}
+					}],
+					"responses": {}
+				}
+			}
+		}
+	}))
+	.unwrap();
+	let mcp = Backend::MCP(
+		strng::new("mcp"),
+		McpBackend {
+			targets: vec![Arc::new(McpTarget {
+				name: strng::new("users"),
+				spec: McpTargetSpec::OpenAPI(OpenAPITarget {
+					backend: SimpleBackendReference::Backend(strng::format!("{}", upstream.address())),
+					schema: Arc::new(schema),
+					pretty_json: false,
+				}),
+			})],
+			delimiter: Default::default(),
+		},
+	);
+	let t = setup().unwrap().with_backend(*upstream.address());
+	t.pi.stores.binds.write().insert_backend(mcp);
+	let mut route = basic_route(*upstream.address());
+	route.backends[0].backend = BackendReference::Backend(strng::new("mcp"));
+	let t = t.with_bind(simple_bind(route));
+	let io = t.serve_http(strng::new("bind"));
+
+	let (res, body) = mcp_post(
+		io.clone(),
+		None,
+		serde_json::json!({
+			"jsonrpc": "2.0",
+			"id": 1,
+			"method": "initialize",
+			"params": {
+				"protocolVersion": "2025-03-26",
+				"capabilities": {},
+				"clientInfo": {"name": "test", "version": "1.0"},
+			},
+		}),
+	)
+	.await;
+	assert_eq!(res.status(), 200, "{body}");
+	let session = res
+		.headers()
+		.get("mcp-session-id")
+		.unwrap()
+		.to_str()
+		.unwrap()
+		.to_string();
+	mcp_post(
+		io.clone(),
+		Some(&session),
+		serde_json::json!({"jsonrpc": "2.0", "method": "notifications/initialized"}),
+	)
+	.await;
+
+	let call = |id: u64, args: serde_json::Value| {
+		mcp_post(
+			io.clone(),
+			Some(&session),
+			serde_json::json!({
+				"jsonrpc": "2.0",
+				"id": id,
+				"method": "tools/call",
+				"params": {"name": "users_get_user", "arguments": args},
+			}),
+		)
+	};
+	let (res, body) = call(
+		2,
+		serde_json::json!({"path": {"user_id": "1"}, "dry_run": true}),
+	)
+	.await;
+	assert_eq!(res.status(), 200, "{body}");
+	assert!(body.contains(r#"\"method\":\"GET\""#), "{body}");
+	let url = format!(r#"\"url\":\"http://{}/users/1\""#, upstream.address());
+	assert!(body.contains(&url), "{body}");
+
+	// Arguments are still validated
+	let (_, body) = call(3, serde_json::json!({"dry_run": true})).await;
+	assert!(body.contains("error"), "{body}");
+}
+
+#[tokio::test]
+async fn mcp_sse_upstream_over_streamable_http() {
+	let upstream = sse_mcp_server().await;
+	let upstream_ref = SimpleBackendReference::Backend(strng::format!("{upstream}"));
+	// An `mcp` target pointing at an SSE-only server falls back to the SSE transport
+	for spec in [
+		McpTargetSpec::Sse(SseTargetSpec {
+			backend: upstream_ref.clone(),
+			path: "/sse".to_string(),
+		}),
+		McpTargetSpec::Mcp(StreamableHTTPTargetSpec {
+			backend: upstream_ref.clone(),
+			path: "/sse".to_string(),
+		}),
+	] {
+		let mcp = Backend::MCP(
+			strng::new("mcp"),
+			McpBackend {
+				targets: vec![Arc::new(McpTarget {
+					name: strng::new("echo"),
+					spec,
+				})],
+				delimiter: Default::default(),
+			},
+		);
+		let t = setup().unwrap().with_backend(upstream);
+		t.pi.stores.binds.write().insert_backend(mcp);
+		let mut route = basic_route(upstream);
+		route.backends[0].backend = BackendReference::Backend(strng::new("mcp"));
+		let t = t.with_bind(simple_bind(route));
+		let io = t.serve_http(strng::new("bind"));
+
+		let (res, body) = mcp_post(
+			io.clone(),
+			None,
+			serde_json::json!({
+				"jsonrpc": "2.0",
+				"id": 1,
+				"method": "initialize",
+				"params": {
+					"protocolVersion": "2025-03-26",
+					"capabilities": {},
+					"clientInfo": {"name": "test", "version": "1.0"},
+				},
+			}),
+		)
+		.await;
+		assert_eq!(res.status(), 200, "{body}");
+		assert!(body.contains(r#""protocolVersion":"2025-03-26""#), "{body}");
+		let session = res
+			.headers()
+			.get("mcp-session-id")
+			.unwrap()
+			.to_str()
+			.unwrap()
+			.to_string();
+
+		let (res, _) = mcp_post(
+			io.clone(),
+			Some(&session),
+			serde_json::json!({"jsonrpc": "2.0", "method": "notifications/initialized"}),
+		)
+		.await;
+		assert_eq!(res.status(), 202);
+
+		let (res, body) = mcp_post(
+			io,
+			Some(&session),
+			serde_json::json!({"jsonrpc": "2.0", "id": 2, "method": "tools/list"}),
+		)
+		.await;
+		assert_eq!(res.status(), 200, "{body}");
+		assert!(body.contains(r#""name":"echo""#), "{body}");
+	}
+}
+
+// Initializes an MCP session through a streamable HTTP target, and returns the response body.
+async fn mcp_initialize_streamable_http(upstream: SocketAddr) -> String {
+	let mcp = Backend::MCP(
+		strng::new("mcp"),
+		McpBackend {
+			targets: vec![Arc::new(McpTarget {
+				name: strng::new("echo"),
+				spec: McpTargetSpec::Mcp(StreamableHTTPTargetSpec {
+					backend: SimpleBackendReference::Backend(strng::format!("{upstream}")),
+					path: "/mcp".to_string(),
+				}),
+			})],
+			delimiter: Default::default(),
+		},
+	);
+	let t = setup().unwrap().with_backend(upstream);
+	t.pi.stores.binds.write().insert_backend(mcp);
+	let mut route = basic_route(upstream);
+	route.backends[0].backend = BackendReference::Backend(strng::new("mcp"));
+	let t = t.with_bind(simple_bind(route));
+	let io = t.serve_http(strng::new("bind"));
+	let (_, body) = mcp_post(
+		io,
+		None,
+		serde_json::json!({
+			"jsonrpc": "2.0",
+			"id": 1,
+			"method": "initialize",
+			"params": {
+				"protocolVersion": "2025-03-26",
+				"capabilities": {},
+				"clientInfo": {"name": "test", "version": "1.0"},
+			},
+		}),
+	)
+	.await;
+	body
+}
+
+#[tokio::test]
+async fn mcp_streamable_http_sse_fallback_errors() {
+	// A server error is not a sign of an SSE-only server, so there is no fallback
+	let upstream = wiremock::MockServer::start().await;
+	Mock::given(wiremock::matchers::method("POST"))
+		.respond_with(ResponseTemplate::new(500))
+		.expect(1)
+		.mount(&upstream)
+		.await;
+	Mock::given(wiremock::matchers::method("GET"))
+		.respond_with(ResponseTemplate::new(200))
+		.expect(0)
+		.mount(&upstream)
+		.await;
+	let body = mcp_initialize_streamable_http(*upstream.address()).await;
+	assert!(!body.contains("protocolVersion"), "{body}");
+	assert!(!body.contains("sse fallback"), "{body}");
+
+	// When the fallback fails too, both errors are reported
+	let upstream = wiremock::MockServer::start().await;
+	Mock::given(wiremock::matchers::any())
+		.respond_with(ResponseTemplate::new(404))
+		.mount(&upstream)
+		.await;
+	let body = mcp_initialize_streamable_http(*upstream.address()).await;
+	assert!(body.contains("streamable http initialize failed"), "{body}");
+	assert!(body.contains("sse fallback failed"), "{body}");
+}
+
+#[tokio::test]
+async fn maintenance_mode() {
+	let (_mock, t, io) = basic_setup().await;
+	let route = strng::new("route");
+	t.pi.stores.binds.write().set_maintenance(
+		route.clone(),
+		MaintenancePage {
+			retry_after: Some(Duration::from_secs(30)),
+			..Default::default()
+		},
+	);
+	let res = send_request(io.clone(), Method::GET, "http://lo").await;
+	assert_eq!(res.status(), 503);
+	assert_eq!(res.headers().get("retry-after").unwrap(), "30");
+	let body = read_body_raw(res.into_body()).await;
+	assert!(String::from_utf8_lossy(&body).contains("maintenance"));
+
+	assert!(t.pi.stores.binds.write().clear_maintenance(&route));
+	let res = send_request(io, Method::GET, "http://lo").await;
+	assert_eq!(res.status(), 200);
+}
+
+#[tokio::test]
+async fn connection_pool_limit() {
+	let mock = wiremock::MockServer::start().await;
+	Mock::given(wiremock::matchers::any())
+		.respond_with(ResponseTemplate::new(200).set_delay(Duration::from_millis(200)))
+		.mount(&mock)
+		.await;
+	let t = setup()
+		.unwrap()
+		.with_backend(*mock.address())
+		.with_bind(simple_bind(basic_route(*mock.address())))
+		.with_policy(TargetedPolicy {
+			name: strng::new("pool"),
+			target: PolicyTarget::Backend(mock.address().to_string().into()),
+			policy: Policy::ConnectionPool(client::ConnectionPool {
+				max_connections: Some(1),
+				..Default::default()
+			}),
+		});
+	// HTTP/2 so the requests reach the gateway concurrently
+	let io = t.serve_http2(strng::new("bind"));
+
+	let start = Instant::now();
+	let reqs = (0..3).map(|_| {
+		RequestBuilder::new(Method::GET, "http://lo")
+			.version(Version::HTTP_2)
+			.send(io.clone())
+	});
+	for res in futures_util::future::join_all(reqs).await {
+		assert_eq!(res.unwrap().status(), 200);
+	}
+	// Only one upstream connection may be used at a time, so the requests are served one by one
+	assert!(start.elapsed() >= Duration::from_millis(600));
+	assert_eq!(mock.received_requests().await.unwrap().len(), 3);
+}
+
+#[tokio::test]
+async fn connection_pool_limit_timeout() {
+	let mock = wiremock::MockServer::start().await;
+	Mock::given(wiremock::matchers::any())
+		.respond_with(ResponseTemplate::new(200).set_delay(Duration::from_millis(200)))
+		.mount(&mock)
+		.await;
+	let pool_test = |pool: client::ConnectionPool| {
+		setup()
+			.unwrap()
+			.with_backend(*mock.address())
+			.with_bind(simple_bind(basic_route(*mock.address())))
+			.with_policy(TargetedPolicy {
+				name: strng::new("pool"),
+				target: PolicyTarget::Backend(mock.address().to_string().into()),
+				policy: Policy::ConnectionPool(pool),
+			})
+	};
+	let statuses = async |t: &TestBind| {
+		let io = t.serve_http2(strng::new("bind"));
+		let reqs = (0..2).map(|_| {
+			RequestBuilder::new(Method::GET, "http://lo")
+				.version(Version::HTTP_2)
+				.send(io.clone())
+		});
+		let mut statuses: Vec<u16> = futures_util::future::join_all(reqs)
+			.await
+			.into_iter()
+			.map(|r| r.unwrap().status().as_u16())
+			.collect();
+		statuses.sort();
+		statuses
+	};
+
+	// The second request gives up waiting before the first completes
+	let t = pool_test(client::ConnectionPool {
+		max_connections: Some(1),
+		max_connections_timeout: Some(Duration::from_millis(50)),
+		..Default::default()
+	});
+	assert_eq!(statuses(&t).await, vec![200, 503]);
+
+	// Zero means no limit
+	let t = pool_test(client::ConnectionPool {
+		max_connections: Some(0),
+		max_connections_timeout: Some(Duration::from_millis(50)),
+		..Default::default()
+	});
+	assert_eq!(statuses(&t).await, vec![200, 200]);
+}
+
+#[tokio::test]
+async fn mirror_compare_match() {
+	// Formatting and key order differences are not mismatches
+	let result = mirror_compare(200, r#"{ "b": [1, 2], "a": "x" }"#).await;
+	assert_eq!(result, MirrorComparison::matched);
+}
+
+#[tokio::test]
+async fn mirror_compare_mismatch() {
+	let result = mirror_compare(200, r#"{"a": "y", "b": [1, 2]}"#).await;
+	assert_eq!(result, MirrorComparison::mismatched);
+	let result = mirror_compare(500, r#"{"a": "x", "b": [1, 2]}"#).await;
+	assert_eq!(result, MirrorComparison::mismatched);
+}
+
+#[tokio::test]
+async fn backend_tls_sni_override() {
+	let upstream = tls_mock("backend.example.com").await;
+	let (status, sni) = backend_tls_call(
+		&upstream,
+		BackendTLSConfig {
+			root: Some(upstream.cert.cert.pem().into_bytes()),
+			hostname: Some(strng::new("backend.example.com")),
+			..Default::default()
+		},
+	)
+	.await;
+	assert_eq!(status, 200);
+	assert_eq!(sni.as_deref(), Some("backend.example.com"));
+}
+
+#[tokio::test]
+async fn backend_tls_verification() {
+	let upstream = tls_mock("backend.example.com").await;
+	let root = Some(upstream.cert.cert.pem().into_bytes());
+
+	// The backend is addressed by IP, which its certificate is not valid for
+	let (status, _) = backend_tls_call(
+		&upstream,
+		BackendTLSConfig {
+			root: root.clone(),
+			..Default::default()
+		},
+	)
+	.await;
+	assert_eq!(status, 503);
+	// Unless only the certificate is verified
+	let (status, _) = backend_tls_call(
+		&upstream,
+		BackendTLSConfig {
+			root,
+			insecure_host: true,
+			..Default::default()
+		},
+	)
+	.await;
+	assert_eq!(status, 200);
+
+	// The system roots do not trust the certificate
+	let (status, _) = backend_tls_call(
+		&upstream,
+		BackendTLSConfig {
+			hostname: Some(strng::new("backend.example.com")),
+			..Default::default()
+		},
+	)
+	.await;
+	assert_eq!(status, 503);
+	// Unless verification is skipped entirely
+	let (status, _) = backend_tls_call(
+		&upstream,
+		BackendTLSConfig {
+			insecure: true,
+			..Default::default()
+		},
+	)
+	.await;
+	assert_eq!(status, 200);
+}
+
+struct TlsMock {
+	address: SocketAddr,
+	cert: rcgen::CertifiedKey<rcgen::KeyPair>,
+	// The SNI sent on each accepted connection
+	sni: Arc<Mutex<Vec<Option<String>>>>,
+}
+
+// Starts an HTTPS server with a self-signed certificate for `hostname`.
+async fn tls_mock(hostname: &str) -> TlsMock {
+	let cert = rcgen::generate_simple_self_signed(vec![hostname.to_string()]).unwrap();
+	let server_config =
+		rustls::ServerConfig::builder_with_provider(crate::transport::tls::provider())
+			.with_protocol_versions(crate::transport::tls::ALL_TLS_VERSIONS)
+			.unwrap()
+			.with_no_client_auth()
+			.with_single_cert(
+				vec![cert.cert.der().clone()],
+				rustls::pki_types::PrivateKeyDer::Pkcs8(cert.signing_key.serialize_der().into()),
+			)
+			.unwrap();
+	let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(server_config));
+	let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+	let address = listener.local_addr().unwrap();
+	let sni: Arc<Mutex<Vec<Option<String>>>> = Default::default();
+	let seen = sni.clone();
+	tokio::spawn(async move {
+		loop {
+			let (tcp, _) = listener.accept().await.unwrap();
+			let acceptor = acceptor.clone();
+			let seen = seen.clone();
+			tokio::spawn(async move {
+				let Ok(tls) = acceptor.accept(tcp).await else {
+					return;
+				};
+				let server_name = tls.get_ref().1.server_name().map(str::to_string);
+				seen.lock().unwrap().push(server_name);
+				let svc = hyper::service::service_fn(|_| async {
+					Ok::<_, Infallible>(::http::Response::new(Body::from("hello")))
+				});
+				let _ = hyper_util::server::conn::auto::Builder::new(TokioExecutor::new())
+					.serve_connection(TokioIo::new(tls), svc)
+					.await;
+			});
+		}
+	});
+	TlsMock { address, cert, sni }
+}
+
+// Sends a request to the TLS mock with the given backend TLS policy, returning the response status
+// and the SNI the mock saw.
+async fn backend_tls_call(upstream: &TlsMock, tls: BackendTLSConfig) -> (u16, Option<String>) {
+	let t = setup()
+		.unwrap()
+		.with_backend(upstream.address)
+		.with_bind(simple_bind(basic_route(upstream.address)))
+		.with_policy(TargetedPolicy {
+			name: strng::new("tls"),
+			target: PolicyTarget::Backend(upstream.address.to_string().into()),
+			policy: Policy::BackendTLS(tls.build().unwrap()),
+		});
+	let io = t.serve_http(strng::new("bind"));
+	let res = send_request(io, Method::GET, "http://lo").await;
+	let sni = upstream.sni.lock().unwrap().last().cloned().flatten();
+	(res.status().as_u16(), sni)
+}
+
+#[tokio::test]
+async fn tls_tcp_route_sni() {
+	let (t, cert) = tls_tcp_setup().await;
+	assert_eq!(tls_tcp_call(&t, &cert, "sni.example.com", &[]).await, "sni");
+	// The hostname match takes precedence over ALPN matches on other hostnames
+	assert_eq!(
+		tls_tcp_call(&t, &cert, "sni.example.com", &["h2"]).await,
+		"sni"
+	);
+	assert_eq!(
+		tls_tcp_call(&t, &cert, "other.example.com", &[]).await,
+		"fallback"
+	);
+}
+
+#[tokio::test]
+async fn tls_tcp_route_alpn() {
+	let (t, cert) = tls_tcp_setup().await;
+	assert_eq!(tls_tcp_call(&t, &cert, "example.com", &["h2"]).await, "h2");
+	assert_eq!(
+		tls_tcp_call(&t, &cert, "example.com", &["custom"]).await,
+		"custom"
+	);
+	assert_eq!(
+		tls_tcp_call(&t, &cert, "example.com", &["http/1.1"]).await,
+		"fallback"
+	);
+}
+
+// Sets up a TLS listener with TCP routes that each forward to a backend replying with the route name.
+async fn tls_tcp_setup() -> (TestBind, rcgen::CertifiedKey<rcgen::KeyPair>) {
+	let cert = rcgen::generate_simple_self_signed(vec![
+		"example.com".to_string(),
+		"*.example.com".to_string(),
+	])
+	.unwrap();
+	let mut t = setup().unwrap();
+	let mut routes = vec![];
+	for (name, hostnames, alpn) in [
+		("h2", vec![], vec!["h2"]),
+		("custom", vec![], vec!["custom"]),
+		("sni", vec!["sni.example.com"], vec![]),
+		("fallback", vec![], vec![]),
+	] {
+		let backend = tcp_name_mock(name).await;
+		t = t.with_backend(backend);
+		routes.push(TCPRoute {
+			key: name.into(),
+			route_name: name.into(),
+			hostnames: hostnames.into_iter().map(Into::into).collect(),
+			alpn_protocols: alpn.into_iter().map(Into::into).collect(),
+			rule_name: None,
+			backends: vec![TCPRouteBackendReference {
+				weight: 1,
+				backend: SimpleBackendReference::Backend(backend.to_string().into()),
+			}],
+		});
+	}
+	let key = parse_certified_key(
+		cert.cert.pem().as_bytes(),
+		cert.signing_key.serialize_pem().as_bytes(),
+	)
+	.unwrap();
+	let alpn = vec![b"h2".to_vec(), b"custom".to_vec(), b"http/1.1".to_vec()];
+	let bind = Bind {
+		key: strng::new("bind"),
+		address: ListenerAddress::Tcp("127.0.0.1:0".parse().unwrap()),
+		listeners: ListenerSet::from_list([Listener {
+			key: Default::default(),
+			name: Default::default(),
+			gateway_name: Default::default(),
+			hostname: Default::default(),
+			protocol: ListenerProtocol::TLS(TLSConfig::new(key, vec![], alpn).unwrap()),
+			tcp_routes: TCPRouteSet::from_list(routes),
+			routes: Default::default(),
+			default_route: None,
+			log_sampling: None,
+		}]),
+		proxy_protocol: None,
+	};
+	(t.with_bind(bind), cert)
+}
+
+// Starts a TCP server that writes `name` to each connection and closes it.
+async fn tcp_name_mock(name: &'static str) -> SocketAddr {
+	let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+	let address = listener.local_addr().unwrap();
+	tokio::spawn(async move {
+		loop {
+			let (mut tcp, _) = listener.accept().await.unwrap();
+			let _ = tcp.write_all(name.as_bytes()).await;
+			let _ = tcp.shutdown().await;
+		}
+	});
+	address
+}
+
+// Connects to the TLS listener with the given SNI and ALPN protocols, returning what the selected
+// backend sent.
+async fn tls_tcp_call(
+	t: &TestBind,
+	cert: &rcgen::CertifiedKey<rcgen::KeyPair>,
+	sni: &str,
+	alpn: &[&str],
+) -> String {
+	let mut roots = rustls::RootCertStore::empty();
+	roots.add(cert.cert.der().clone()).unwrap();
+	let mut config = rustls::ClientConfig::builder_with_provider(crate::transport::tls::provider())
+		.with_protocol_versions(crate::transport::tls::ALL_TLS_VERSIONS)
+		.unwrap()
+		.with_root_certificates(roots)
+		.with_no_client_auth();
+	config.alpn_protocols = alpn.iter().map(|p| p.as_bytes().to_vec()).collect();
+	let connector = tokio_rustls::TlsConnector::from(Arc::new(config));
+	let io = t.serve(strng::new("bind"));
+	let server_name = rustls::pki_types::ServerName::try_from(sni.to_string()).unwrap();
+	let mut tls = connector.connect(server_name, io).await.unwrap();
+	let mut buf = vec![];
+	// The backend closes the connection without a TLS close_notify, which may surface as an error
+	let _ = tls.read_to_end(&mut buf).await;
+	String::from_utf8(buf).unwrap()
+}
+
+#[tokio::test]
+async fn metrics_scrape() {
+	let mock = simple_mock().await;
+	let mut registry = Registry::default();
+	let t = setup_with_registry(&mut registry)
+		.unwrap()
+		.with_backend(*mock.address())
+		.with_bind(simple_bind(basic_route(*mock.address())));
+	for _ in 0..2 {
+		let io = t.serve_http(strng::new("bind"));
+		let res = send_request(io, Method::GET, "http://lo").await;
+		assert_eq!(res.status(), 200);
+		read_body_raw(res.into_body()).await;
+	}
+
+	let server = crate::management::metrics_server::Server::new(
+		Address::SocketAddr("127.0.0.1:0".parse().unwrap()),
+		t.drain_rx.clone(),
+		registry,
+	)
+	.await
+	.unwrap();
+	let url = format!("http://{}/metrics", server.address());
+	server.spawn();
+	let backend = mock.address().to_string();
+	let route_labels = format!(r#"route="route",backend="{backend}",status="200""#);
+	let backend_labels = format!(r#"backend="{backend}""#);
+	// Requests are recorded once the response body is done, which can be just after the client
+	// has read it.
+	let mut metrics = String::new();
+	for _ in 0..100 {
+		metrics = reqwest::get(&url).await.unwrap().text().await.unwrap();
+		let value = |name: &str, labels: &str| {
+			metrics
+				.lines()
+				.filter(|l| l.starts_with(&format!("{name}{{")) && l.contains(labels))
+				.find_map(|l| l.rsplit_once(' '))
+				.and_then(|(_, v)| v.parse::<f64>().ok())
+		};
+		let recorded = value("agentgateway_requests_total", r#"route="route""#) == Some(2.0)
+			&& value("agentgateway_request_duration_seconds_count", &route_labels) == Some(2.0)
+			&& value("agentgateway_response_bytes_total", &route_labels).is_some_and(|b| b > 0.0)
+			&& value("agentgateway_upstream_requests_active", &backend_labels) == Some(0.0);
+		if recorded {
+			return;
+		}
+		tokio::time::sleep(Duration::from_millis(10)).await;
+	}
+	panic!("requests were never recorded:\n{metrics}");
+}
+
+#[tokio::test]
+async fn gemini_streaming() {
+	let mock = wiremock::MockServer::start().await;
+	let chunks = [
+		r#"{"candidates":[{"content":{"parts":[{"text":"Hello"}],"role":"model"},"index":0}],"usageMetadata":{"promptTokenCount":5,"candidatesTokenCount":1,"totalTokenCount":6},"modelVersion":"gemini-2.0-flash","responseId":"resp"}"#,
+		r#"{"candidates":[{"content":{"parts":[{"text":" world"}],"role":"model"},"finishReason":"STOP","index":0}],"usageMetadata":{"promptTokenCount":5,"candidatesTokenCount":2,"totalTokenCount":7},"modelVersion":"gemini-2.0-flash","responseId":"resp"}"#,
+	];
+	let sse = chunks.map(|c| format!("data: {c}\n\n")).concat();
+	Mock::given(wiremock::matchers::method("POST"))
+		.and(wiremock::matchers::path(
+			"/v1beta/models/gemini-2.0-flash:streamGenerateContent",
+		))
+		.and(wiremock::matchers::query_param("alt", "sse"))
+		.and(wiremock::matchers::header("x-goog-api-key", "AIzaTestKey"))
+		.and(wiremock::matchers::body_partial_json(serde_json::json!({
+			"contents": [{"role": "user", "parts": [{"text": "hi"}]}],
+			"systemInstruction": {"parts": [{"text": "be brief"}]},
+		})))
+		.respond_with(ResponseTemplate::new(200).set_body_raw(sse, "text/event-stream"))
+		.mount(&mock)
+		.await;
+	// The first attempt fails, so the request is only answered if the retry is still streaming
+	Mock::given(wiremock::matchers::method("POST"))
+		.respond_with(ResponseTemplate::new(503))
+		.up_to_n_times(1)
+		.with_priority(1)
+		.mount(&mock)
+		.await;
+
+	let mut route = gemini_route(&mock);
+	route.policies = Some(TrafficPolicy {
+		timeout: Default::default(),
+		retry: Some(http::retry::Policy {
+			attempts: std::num::NonZeroU8::new(1).unwrap(),
+			backoff: None,
+			codes: Box::new([::http::StatusCode::SERVICE_UNAVAILABLE]),
+			budget: None,
+		}),
+		load_balancer: None,
+		canary: None,
+	});
+	let res = gemini_streaming_request(&mock, route, "Bearer AIzaTestKey").await;
+	assert_eq!(res.status(), 200);
+	let body = read_body_raw(res.into_body()).await;
+	let events = std::str::from_utf8(&body)
+		.unwrap()
+		.lines()
+		.filter_map(|l| l.strip_prefix("data: "))
+		.collect::<Vec<_>>();
+	assert_eq!(events.last(), Some(&"[DONE]"), "{events:?}");
+	let chunks = events[..events.len() - 1]
+		.iter()
+		.map(|e| serde_json::from_str::<serde_json::Value>(e).unwrap())
+		.collect::<Vec<_>>();
+	assert_eq!(chunks.len(), 2);
+	assert_eq!(chunks[0]["object"], "chat.completion.chunk");
+	assert_eq!(chunks[0]["model"], "gemini-2.0-flash");
+	assert_eq!(chunks[0]["choices"][0]["delta"]["content"], "Hello");
+	assert!(chunks[0]["usage"].is_null());
+	assert_eq!(chunks[1]["choices"][0]["delta"]["content"], " world");
+	assert_eq!(chunks[1]["choices"][0]["finish_reason"], "stop");
+	assert_eq!(
+		chunks[1]["usage"],
+		serde_json::json!({"prompt_tokens": 5, "completion_tokens": 2, "total_tokens": 7})
+	);
+}
+
+#[tokio::test]
+async fn gemini_streaming_error() {
+	let mock = wiremock::MockServer::start().await;
+	let error = r#"{"error":{"code":400,"message":"bad request","status":"INVALID_ARGUMENT"}}"#;
+	// OAuth access tokens are not API keys, so they stay in the authorization header
+	Mock::given(wiremock::matchers::method("POST"))
+		.and(wiremock::matchers::header(
+			"authorization",
+			"Bearer ya29.token",
+		))
+		.respond_with(ResponseTemplate::new(400).set_body_raw(error, "application/json"))
+		.mount(&mock)
+		.await;
+
+	let res = gemini_streaming_request(&mock, gemini_route(&mock), "Bearer ya29.token").await;
+	assert_eq!(res.status(), 400);
+	assert_eq!(
+		res.headers().get(::http::header::CONTENT_TYPE).unwrap(),
+		"application/json"
+	);
+	let body = read_body_raw(res.into_body()).await;
+	assert_eq!(body.as_ref(), error.as_bytes());
+}
+
+fn gemini_route(mock: &MockServer) -> Route {
+	let mut route = basic_route(*mock.address());
+	route.backends[0].backend = BackendReference::Backend(strng::new("gemini"));
+	route
+}
+
+async fn gemini_streaming_request(mock: &MockServer, route: Route, authz: &str) -> Response {
+	let t = setup().unwrap();
+	t.pi.stores.binds.write().insert_backend(Backend::AI(
+		strng::new("gemini"),
+		AIBackend {
+			provider: AIProvider::Gemini(Default::default()),
+			host_override: Some(Target::Address(*mock.address())),
+		},
+	));
+	let t = t.with_bind(simple_bind(route));
+	let io = t.serve_http(strng::new("bind"));
+	RequestBuilder::new(Method::POST, "http://lo/v1/chat/completions")
+		.header("authorization", authz)
+		.json(&serde_json::json!({
+			"model": "gemini-2.0-flash",
+			"stream": true,
+			"messages": [
+				{"role": "system", "content": "be brief"},
+				{"role": "user", "content": "hi"},
+			],
+		}))
+		.send(io)
+		.await
+		.unwrap()
+}
+
+#[tokio::test]
+async fn llm_guardrails() {
+	let mock = wiremock::MockServer::start().await;
+	Mock::given(wiremock::matchers::method("POST"))
+		.and(wiremock::matchers::path("/v1/chat/completions"))
+		.respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+			"id": "chatcmpl-1",
+			"object": "chat.completion",
+			"created": 0,
+			"model": "gpt-4o",
+			"choices": [{
+				"index": 0,
+				"message": {"role": "assistant", "content": "the password is hunter2"},
+				"finish_reason": "stop"
+			}],
+			"usage": {"prompt_tokens": 5, "completion_tokens": 5, "total_tokens": 10}
+		})))
+		// Only the clean prompt should reach the provider
+		.expect(1)
+		.mount(&mock)
+		.await;
+
+	let t = setup().unwrap();
+	t.pi.stores.binds.write().insert_backend(Backend::AI(
+		strng::new("openai"),
+		AIBackend {
+			provider: AIProvider::OpenAI(crate::llm::openai::Provider { model: None }),
+			host_override: Some(Target::Address(*mock.address())),
+		},
+	));
+	let mut route = basic_route(*mock.address());
+	route.backends[0].backend = BackendReference::Backend(strng::new("openai"));
+	let t = t.with_bind(simple_bind(route)).with_policy(TargetedPolicy {
+		name: strng::new("guardrails"),
+		target: PolicyTarget::Backend(strng::new("openai")),
+		policy: Policy::AI(
+			serde_json::from_str(
+				r#"{"guardrails": {
+					"checks": [
+						{"denylist": {"patterns": ["(?i)secret"]}},
+						{"denylist": {"patterns": ["hunter\\d"], "action": "redact"}}
+					],
+					"rejection": {"status": "451", "body": "blocked by guardrail"}
+				}}"#,
+			)
+			.unwrap(),
+		),
+	});
+	let io = t.serve_http(strng::new("bind"));
+	let send = |prompt: &str| {
+		RequestBuilder::new(Method::POST, "http://lo/v1/chat/completions")
+			.json(&serde_json::json!({
+				"model": "gpt-4o",
+				"messages": [{"role": "user", "content": prompt}],
+			}))
+			.send(io.clone())
+	};
+
+	let res = send("Tell me a SECRET").await.unwrap();
+	assert_eq!(res.status(), 451);
+	assert_eq!(
+		read_body_raw(res.into_body()).await.as_ref(),
+		b"blocked by guardrail"
+	);
+
+	let res = send("hello").await.unwrap();
+	assert_eq!(res.status(), 200);
+	let body: serde_json::Value =
+		serde_json::from_slice(&read_body_raw(res.into_body()).await).unwrap();
+	assert_eq!(
+		body["choices"][0]["message"]["content"],
+		"the password is <redacted>"
+	);
+}
+
+#[tokio::test]
+async fn llm_guardrails_streaming() {
+	let mock = wiremock::MockServer::start().await;
+	let stream = |contents: [&str; 2]| {
+		let chunk = |content: &str, finish_reason: Option<&str>| {
+			serde_json::json!({
+				"id": "chatcmpl-1",
+				"object": "chat.completion.chunk",
+				"created": 0,
+				"model": "gpt-4o",
+				"choices": [{"index": 0, "delta": {"content": content}, "finish_reason": finish_reason}]
+			})
+		};
+		let sse = format!(
+			"data: {}\n\ndata: {}\n\ndata: [DONE]\n\n",
+			chunk(contents[0], None),
+			chunk(contents[1], Some("stop"))
+		);
+		ResponseTemplate::new(200).set_body_raw(sse, "text/event-stream")
+	};
+	let prompt = |prompt: &str| {
+		wiremock::matchers::body_partial_json(serde_json::json!({
+			"messages": [{"role": "user", "content": prompt}],
+		}))
+	};
+	Mock::given(prompt("password"))
+		.respond_with(stream(["the password", " is hunter2"]))
+		.mount(&mock)
+		.await;
+	Mock::given(prompt("what is it?"))
+		.respond_with(stream(["it is a", " Secret"]))
+		.mount(&mock)
+		.await;
+
+	let t = setup().unwrap();
+	t.pi.stores.binds.write().insert_backend(Backend::AI(
+		strng::new("openai"),
+		AIBackend {
+			provider: AIProvider::OpenAI(crate::llm::openai::Provider { model: None }),
+			host_override: Some(Target::Address(*mock.address())),
+		},
+	));
+	let mut route = basic_route(*mock.address());
+	route.backends[0].backend = BackendReference::Backend(strng::new("openai"));
+	let t = t.with_bind(simple_bind(route)).with_policy(TargetedPolicy {
+		name: strng::new("guardrails"),
+		target: PolicyTarget::Backend(strng::new("openai")),
+		policy: Policy::AI(
+			serde_json::from_str(
+				r#"{"guardrails": {
+					"checks": [
+						{"denylist": {"patterns": ["(?i)secret"]}},
+						{"denylist": {"patterns": ["hunter\\d"], "action": "redact"}}
+					],
+					"rejection": {"status": "451", "body": "blocked by guardrail"}
+				}}"#,
+			)
+			.unwrap(),
+		),
+	});
+	let io = t.serve_http(strng::new("bind"));
+	let send = |prompt: &str| {
+		RequestBuilder::new(Method::POST, "http://lo/v1/chat/completions")
+			.json(&serde_json::json!({
+				"model": "gpt-4o",
+				"stream": true,
+				"messages": [{"role": "user", "content": prompt}],
+			}))
+			.send(io.clone())
+	};
+
+	// Only the response is blocked here, as the prompt itself is allowed
+	let res = send("what is it?").await.unwrap();
+	assert_eq!(res.status(), 451);
+
+	let res = send("password").await.unwrap();
+	assert_eq!(res.status(), 200);
+	let body = read_body_raw(res.into_body()).await;
+	let events = std::str::from_utf8(&body)
+		.unwrap()
+		.lines()
+		.filter_map(|l| l.strip_prefix("data: "))
+		.collect::<Vec<_>>();
+	assert_eq!(events.last(), Some(&"[DONE]"), "{events:?}");
+	let contents = events[..events.len() - 1]
+		.iter()
+		.map(|e| serde_json::from_str::<serde_json::Value>(e).unwrap())
+		.map(|c| c["choices"][0]["delta"]["content"].clone())
+		.collect::<Vec<_>>();
+	// The whole redacted completion is sent in the first chunk
+	assert_eq!(contents, vec!["the password is <redacted>", ""]);
+}
+
+#[tokio::test]
+async fn llm_guardrails_streaming_too_large() {
+	let mock = wiremock::MockServer::start().await;
+	let chunk = serde_json::json!({
+		"id": "chatcmpl-1",
+		"object": "chat.completion.chunk",
+		"created": 0,
+		"model": "gpt-4o",
+		"choices": [{"index": 0, "delta": {"content": "a".repeat(1024)}, "finish_reason": null}]
+	});
+	// More than the 2mb that can be buffered for the guardrails
+	let sse = format!("data: {chunk}\n\n").repeat(2048) + "data: [DONE]\n\n";
+	Mock::given(wiremock::matchers::method("POST"))
+		.respond_with(ResponseTemplate::new(200).set_body_raw(sse, "text/event-stream"))
+		.mount(&mock)
+		.await;
+
+	let t = setup().unwrap();
+	t.pi.stores.binds.write().insert_backend(Backend::AI(
+		strng::new("openai"),
+		AIBackend {
+			provider: AIProvider::OpenAI(crate::llm::openai::Provider { model: None }),
+			host_override: Some(Target::Address(*mock.address())),
+		},
+	));
+	let mut route = basic_route(*mock.address());
+	route.backends[0].backend = BackendReference::Backend(strng::new("openai"));
+	let t = t.with_bind(simple_bind(route)).with_policy(TargetedPolicy {
+		name: strng::new("guardrails"),
+		target: PolicyTarget::Backend(strng::new("openai")),
+		policy: Policy::AI(
+			serde_json::from_str(
+				r#"{"guardrails": {"checks": [{"denylist": {"patterns": ["secret"]}}]}}"#,
+			)
+			.unwrap(),
+		),
+	});
+	let io = t.serve_http(strng::new("bind"));
+	let res = RequestBuilder::new(Method::POST, "http://lo/v1/chat/completions")
+		.json(&serde_json::json!({
+			"model": "gpt-4o",
+			"stream": true,
+			"messages": [{"role": "user", "content": "hello"}],
+		}))
+		.send(io)
+		.await
+		.unwrap();
+	assert_eq!(res.status(), 503);
+	let body = read_body_raw(res.into_body()).await;
+	assert!(
+		String::from_utf8_lossy(&body).contains("response was too large"),
+		"{body:?}"
+	);
+}
+
+#[tokio::test]
+async fn llm_moderation() {
+	let moderation = wiremock::MockServer::start().await;
+	Mock::given(wiremock::matchers::path("/v1/moderations"))
+		.and(wiremock::matchers::header(
+			"authorization",
+			"Bearer sk-test",
+		))
+		.and(wiremock::matchers::body_partial_json(
+			serde_json::json!({"input": ["something bad"]}),
+		))
+		.respond_with(
+			ResponseTemplate::new(200).set_body_json(serde_json::json!({"results": [{"flagged": true}]})),
+		)
+		.expect(1)
+		.mount(&moderation)
+		.await;
+	let mock = wiremock::MockServer::start().await;
+	// Flagged prompts never reach the provider
+	Mock::given(wiremock::matchers::method("POST"))
+		.respond_with(ResponseTemplate::new(200))
+		.expect(0)
+		.mount(&mock)
+		.await;
+
+	let t = setup().unwrap();
+	t.pi.stores.binds.write().insert_backend(Backend::AI(
+		strng::new("openai"),
+		AIBackend {
+			provider: AIProvider::OpenAI(crate::llm::openai::Provider { model: None }),
+			host_override: Some(Target::Address(*mock.address())),
+		},
+	));
+	let mut route = basic_route(*mock.address());
+	route.backends[0].backend = BackendReference::Backend(strng::new("openai"));
+	let policy = serde_json::json!({"guardrails": {
+		"checks": [{"moderation": {
+			"url": format!("http://{}/v1/moderations", moderation.address()),
+			"auth": {"key": "sk-test"}
+		}}],
+		"rejection": {"status": "451", "body": "blocked by guardrail"}
+	}});
+	let t = t.with_bind(simple_bind(route)).with_policy(TargetedPolicy {
+		name: strng::new("guardrails"),
+		target: PolicyTarget::Backend(strng::new("openai")),
+		policy: Policy::AI(serde_json::from_value(policy).unwrap()),
+	});
+	let io = t.serve_http(strng::new("bind"));
+	let res = RequestBuilder::new(Method::POST, "http://lo/v1/chat/completions")
+		.json(&serde_json::json!({
+			"model": "gpt-4o",
+			"messages": [{"role": "user", "content": "something bad"}],
+		}))
+		.send(io)
+		.await
+		.unwrap();
+	assert_eq!(res.status(), 451);
+}
+
+#[tokio::test]
+async fn llm_rate_limit_retry() {
+	let mock = wiremock::MockServer::start().await;
+	Mock::given(wiremock::matchers::method("POST"))
+		.respond_with(
+			ResponseTemplate::new(429)
+				.insert_header("retry-after-ms", "10")
+				.set_body_json(serde_json::json!({
+					"error": {"message": "rate limited", "type": "rate_limit_error"}
+				})),
+		)
+		.up_to_n_times(1)
+		.expect(1)
+		.mount(&mock)
+		.await;
+	Mock::given(wiremock::matchers::method("POST"))
+		.respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+			"id": "chatcmpl-1",
+			"object": "chat.completion",
+			"created": 0,
+			"model": "gpt-4o",
+			"choices": [{
+				"index": 0,
+				"message": {"role": "assistant", "content": "hi"},
+				"finish_reason": "stop"
+			}],
+			"usage": {"prompt_tokens": 5, "completion_tokens": 1, "total_tokens": 6}
+		})))
+		.expect(1)
+		.mount(&mock)
+		.await;
+
+	let t = setup().unwrap();
+	t.pi.stores.binds.write().insert_backend(Backend::AI(
+		strng::new("openai"),
+		AIBackend {
+			provider: AIProvider::OpenAI(crate::llm::openai::Provider { model: None }),
+			host_override: Some(Target::Address(*mock.address())),
+		},
+	));
+	let mut route = basic_route(*mock.address());
+	route.backends[0].backend = BackendReference::Backend(strng::new("openai"));
+	let t = t.with_bind(simple_bind(route)).with_policy(TargetedPolicy {
+		name: strng::new("retry"),
+		target: PolicyTarget::Backend(strng::new("openai")),
+		policy: Policy::AI(serde_json::from_str(r#"{"rateLimitRetry": {"maxWait": "1s"}}"#).unwrap()),
+	});
+	let io = t.serve_http(strng::new("bind"));
+	let res = RequestBuilder::new(Method::POST, "http://lo/v1/chat/completions")
+		.json(&serde_json::json!({
+			"model": "gpt-4o",
+			"messages": [{"role": "user", "content": "hello"}],
+		}))
+		.send(io)
+		.await
+		.unwrap();
+	assert_eq!(res.status(), 200);
+	assert_eq!(
+		res
+			.headers()
+			.get(crate::llm::WAIT_REMAINING_HEADER)
+			.unwrap()
+			.to_str()
+			.unwrap(),
+		"990"
+	);
+}
+
+#[tokio::test]
+async fn llm_coalesce_with_tracing() {
+	let mock = wiremock::MockServer::start().await;
+	Mock::given(wiremock::matchers::method("POST"))
+		.respond_with(
+			ResponseTemplate::new(200)
+				.set_body_json(serde_json::json!({
+					"id": "chatcmpl-1",
+					"object": "chat.completion",
+					"created": 0,
+					"model": "gpt-4o",
+					"choices": [{
+						"index": 0,
+						"message": {"role": "assistant", "content": "hi"},
+						"finish_reason": "stop"
+					}],
+					"usage": {"prompt_tokens": 5, "completion_tokens": 1, "total_tokens": 6}
+				}))
+				.set_delay(Duration::from_millis(200)),
+		)
+		// Each request gets its own traceparent, but they should still share one call
+		.expect(1)
+		.mount(&mock)
+		.await;
+
+	let mut t = setup().unwrap();
+	let mut cfg = (*t.pi.cfg).clone();
+	cfg.tracing.always_trace = true;
+	t.pi = Arc::new(ProxyInputs {
+		cfg: Arc::new(cfg),
+		..(*t.pi).clone()
+	});
+	t.pi.stores.binds.write().insert_backend(Backend::AI(
+		strng::new("openai"),
+		AIBackend {
+			provider: AIProvider::OpenAI(crate::llm::openai::Provider { model: None }),
+			host_override: Some(Target::Address(*mock.address())),
+		},
+	));
+	let mut route = basic_route(*mock.address());
+	route.backends[0].backend = BackendReference::Backend(strng::new("openai"));
+	let t = t.with_bind(simple_bind(route));
+	let io = t.serve_http(strng::new("bind"));
+	let send = || {
+		RequestBuilder::new(Method::POST, "http://lo/v1/chat/completions")
+			.json(&serde_json::json!({
+				"model": "gpt-4o",
+				"temperature": 0.0,
+				"messages": [{"role": "user", "content": "hello"}],
+			}))
+			.send(io.clone())
+	};
+	for res in futures::future::join_all((0..3).map(|_| send())).await {
+		assert_eq!(res.unwrap().status(), 200);
+	}
+}
+
+// Sends a request to a route mirrored to a backend returning the given response, and returns the
+// comparison recorded against the primary response.
+async fn mirror_compare(mirror_status: u16, mirror_body: &str) -> MirrorComparison {
+	let json_mock = async |status: u16, body: &str| {
+		let mock = wiremock::MockServer::start().await;
+		Mock::given(wiremock::matchers::any())
+			.respond_with(ResponseTemplate::new(status).set_body_raw(body, "application/json"))
+			.mount(&mock)
+			.await;
+		mock
+	};
+	let primary = json_mock(200, r#"{"a":"x","b":[1,2]}"#).await;
+	let mirror = json_mock(mirror_status, mirror_body).await;
+	let mut route = basic_route(*primary.address());
+	route.filters = vec![RouteFilter::RequestMirror(http::filters::RequestMirror {
+		backend: SimpleBackendReference::Backend(mirror.address().to_string().into()),
+		percentage: 1.0,
+		compare: true,
+	})];
+	let t = setup()
+		.unwrap()
+		.with_backend(*primary.address())
+		.with_backend(*mirror.address())
+		.with_bind(simple_bind(route));
+	let io = t.serve_http(strng::new("bind"));
+	let res = send_request(io, Method::GET, "http://lo").await;
+	assert_eq!(res.status(), 200);
+	// The client gets the primary response untouched
+	assert_eq!(
+		read_body_raw(res.into_body()).await.as_ref(),
+		br#"{"a":"x","b":[1,2]}"#
+	);
+
+	// The comparison happens in the background
+	let count = |result| {
+		t.pi
+			.metrics
+			.mirror_comparisons
+			.get_or_create(&MirrorLabels {
+				route: strng::new("route").into(),
+				backend: strng::new(mirror.address().to_string()).into(),
+				result,
+			})
+			.get()
+	};
+	for _ in 0..100 {
+		match (
+			count(MirrorComparison::matched),
+			count(MirrorComparison::mismatched),
+		) {
+			(0, 0) => tokio::time::sleep(Duration::from_millis(10)).await,
+			(1, 0) => return MirrorComparison::matched,
+			(0, 1) => return MirrorComparison::mismatched,
+			counts => panic!("unexpected comparisons {counts:?}"),
+		}
+	}
+	panic!("mirror response was never compared")
+}
+
+#[tokio::test]
+async fn grpc_unreachable_backend() {
+	// Reserve a port, then close it so nothing is listening
+	let target = tokio::net::TcpListener::bind("127.0.0.1:0")
+		.await
+		.unwrap()
+		.local_addr()
+		.unwrap();
+	let t = setup()
+		.unwrap()
+		.with_backend(target)
+		.with_bind(simple_bind(basic_route(target)));
+	let io = t.serve_http(strng::new("bind"));
+
+	let res = send_grpc_request(io.clone()).await;
+	assert_eq!(res.status(), 200);
+	assert_eq!(
+		res.headers().get("content-type").unwrap(),
+		"application/grpc"
+	);
+	assert_eq!(res.headers().get("grpc-status").unwrap(), "14");
+	assert!(res.headers().get("grpc-message").is_some());
+
+	// Plain HTTP requests still get an HTTP error
+	let res = send_request(io, Method::POST, "http://lo").await;
+	assert_eq!(res.status(), 503);
+}
+
+#[tokio::test]
+async fn grpc_timeout() {
+	let mock = wiremock::MockServer::start().await;
+	Mock::given(wiremock::matchers::path_regex("/.*"))
+		.respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(5)))
+		.mount(&mock)
+		.await;
+	let mut route = basic_route(*mock.address());
+	route.policies = Some(TrafficPolicy {
+		timeout: http::timeout::Policy {
+			request_timeout: Some(Duration::from_millis(50)),
+			..Default::default()
+		},
+		retry: None,
+		load_balancer: None,
+		canary: None,
+	});
+	let t = setup()
+		.unwrap()
+		.with_backend(*mock.address())
+		.with_bind(simple_bind(route));
+	let io = t.serve_http(strng::new("bind"));
+
+	let res = send_grpc_request(io).await;
+	assert_eq!(res.status(), 200);
+	assert_eq!(res.headers().get("grpc-status").unwrap(), "4");
+	assert_eq!(
+		res.headers().get("grpc-message").unwrap(),
+		"request timeout"
+	);
+}
+
+#[tokio::test]
+async fn grpc_web() {
+	let backend = grpc_echo_mock().await;
+	let mut route = basic_route(backend);
+	route.filters = vec![RouteFilter::GrpcWeb(http::grpcweb::GrpcWeb {})];
+	let t = setup()
+		.unwrap()
+		.with_backend(backend)
+		.with_bind(simple_bind(route));
+	let io = t.serve_http(strng::new("bind"));
+
+	// A unary call: one length-prefixed message, base64 encoded
+	let msg = b"\x00\x00\x00\x00\x05hello";
+	let res = RequestBuilder::new(Method::POST, "http://lo/helloworld.Greeter/SayHello")
+		.header("content-type", "application/grpc-web-text+proto")
+		.body(BASE64_STANDARD.encode(msg))
+		.send(io)
+		.await
+		.unwrap();
+	assert_eq!(res.status(), 200);
+	assert_eq!(
+		res.headers().get("content-type").unwrap(),
+		"application/grpc-web-text+proto"
+	);
+	let body = read_body_raw(res.into_body()).await;
+	let body = BASE64_STANDARD.decode(body).unwrap();
+	let (message, trailers) = body.split_at(msg.len());
+	assert_eq!(message, msg);
+	assert_eq!(trailers[0], 0x80);
+	assert_eq!(&trailers[5..], b"grpc-status: 0\r\n");
+}
+
+#[tokio::test]
+async fn websocket() {
+	let backend = websocket_echo_mock().await;
+	let t = setup()
+		.unwrap()
+		.with_backend(backend)
+		.with_bind(simple_bind(basic_route(backend)));
+	let io = t.serve_http(strng::new("bind"));
+	let mut res = RequestBuilder::new(Method::GET, "http://lo/chat")
+		.header("connection", "Upgrade")
+		.header("upgrade", "websocket")
+		.header("sec-websocket-version", "13")
+		.header("sec-websocket-key", "dGhlIHNhbXBsZSBub25jZQ==")
+		.header("sec-websocket-protocol", "chat, superchat")
+		.send(io)
+		.await
+		.unwrap();
+	assert_eq!(res.status(), 101);
+	assert_eq!(
+		res.headers()["sec-websocket-accept"],
+		"s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+	);
+	assert_eq!(res.headers()["sec-websocket-protocol"], "chat");
+	let mut ws = TokioIo::new(hyper::upgrade::on(&mut res).await.unwrap());
+
+	// A masked text frame containing "hello"; frames are opaque to the proxy.
+	let mask = [1, 2, 3, 4];
+	let mut frame = vec![0x81, 0x80 | 5];
+	frame.extend_from_slice(&mask);
+	frame.extend(b"hello".iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+	ws.write_all(&frame).await.unwrap();
+	let mut echoed = vec![0; frame.len()];
+	ws.read_exact(&mut echoed).await.unwrap();
+	assert_eq!(echoed, frame);
+
+	// Draining the proxy closes the upgraded connection
+	tokio::spawn(t.drain_tx.start_drain_and_wait(DrainMode::Immediate));
+	let mut buf = [0; 1];
+	let read = tokio::time::timeout(Duration::from_secs(5), ws.read(&mut buf))
+		.await
+		.unwrap();
+	assert_eq!(read.unwrap(), 0);
+}
+
+/// A WebSocket server that accepts the first offered subprotocol, then echoes everything it receives.
+async fn websocket_echo_mock() -> SocketAddr {
+	let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+	let addr = listener.local_addr().unwrap();
+	tokio::spawn(async move {
+		loop {
+			let (stream, _) = listener.accept().await.unwrap();
+			let svc = hyper::service::service_fn(
+				|mut req: ::http::Request<hyper::body::Incoming>| async move {
+					let protocol = req
+						.headers()
+						.get("sec-websocket-protocol")
+						.and_then(|p| p.to_str().ok())
+						.and_then(|p| p.split(',').next())
+						.map(|p| p.trim().to_string());
+					let upgrade = hyper::upgrade::on(&mut req);
+					tokio::spawn(async move {
+						let io = TokioIo::new(upgrade.await.unwrap());
+						let (mut r, mut w) = tokio::io::split(io);
+						tokio::io::copy(&mut r, &mut w).await
+					});
+					let mut resp = ::http::Response::builder()
+						.status(::http::StatusCode::SWITCHING_PROTOCOLS)
+						.header("connection", "upgrade")
+						// Protocol names are case-insensitive
+						.header("upgrade", "WebSocket")
+						.header("sec-websocket-accept", "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+					if let Some(protocol) = protocol {
+						resp = resp.header("sec-websocket-protocol", protocol);
+					}
+					resp.body(http_body_util::Empty::<Bytes>::new())
+				},
+			);
+			tokio::spawn(
+				hyper::server::conn::http1::Builder::new()
+					.serve_connection(TokioIo::new(stream), svc)
+					.with_upgrades(),
+			);
+		}
+	});
+	addr
+}
+
+/// A gRPC server that echoes the request body and content type back, followed by an OK status.
+async fn grpc_echo_mock() -> SocketAddr {
+	let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+	let addr = listener.local_addr().unwrap();
+	tokio::spawn(async move {
+		loop {
+			let (stream, _) = listener.accept().await.unwrap();
+			let svc =
+				hyper::service::service_fn(|req: ::http::Request<hyper::body::Incoming>| async move {
+					let content_type = req.headers()[::http::header::CONTENT_TYPE].clone();
+					let body = req.into_body().collect().await?.to_bytes();
+					let mut trailers = ::http::HeaderMap::new();
+					trailers.insert("grpc-status", ::http::HeaderValue::from_static("0"));
+					let frames = [
+						http_body::Frame::data(body),
+						http_body::Frame::trailers(trailers),
+					];
+					let body = StreamBody::new(futures_util::stream::iter(
+						frames.into_iter().map(Ok::<_, Infallible>),
+					));
+					::http::Response::builder()
+						.header(::http::header::CONTENT_TYPE, content_type)
+						.body(body)
+						.map_err(|e| anyhow::anyhow!(e))
+				});
+			tokio::spawn(
+				hyper::server::conn::http2::Builder::new(TokioExecutor::new())
+					.serve_connection(TokioIo::new(stream), svc),
+			);
+		}
+	});
+	addr
+}
+
+async fn send_grpc_request(io: Client<MemoryConnector, Body>) -> Response {
+	RequestBuilder::new(Method::POST, "http://lo/helloworld.Greeter/SayHello")
+		.header("content-type", "application/grpc")
+		.send(io)
+		.await
+		.unwrap()
+}
+
 async fn send_request(io: Client<MemoryConnector, Body>, method: Method, url: &str) -> Response {
 	RequestBuilder::new(method, url).send(io).await.unwrap()
 }
@@ -136,10 +2245,14 @@ fn basic_route(target: SocketAddr) -> Route {
 }
 
 fn simple_bind(route: Route) -> Bind {
+	bind_with_default(route, None)
+}
+
+fn bind_with_default(route: Route, default_route: Option<Route>) -> Bind {
 	Bind {
 		key: strng::new("bind"),
 		// not really used
-		address: "127.0.0.1:0".parse().unwrap(),
+		address: ListenerAddress::Tcp("127.0.0.1:0".parse().unwrap()),
 		listeners: ListenerSet::from_list([Listener {
 			key: Default::default(),
 			name: Default::default(),
@@ -148,7 +2261,10 @@ fn simple_bind(route: Route) -> Bind {
 			protocol: ListenerProtocol::HTTP,
 			tcp_routes: Default::default(),
 			routes: RouteSet::from_list(vec![route]),
+			default_route,
+			log_sampling: None,
 		}]),
+		proxy_protocol: None,
 	}
 }
 
@@ -262,6 +2378,10 @@ impl TestBind {
 }
 
 fn setup() -> anyhow::Result<TestBind> {
+	setup_with_registry(&mut Registry::default())
+}
+
+fn setup_with_registry(registry: &mut Registry) -> anyhow::Result<TestBind> {
 	agent_core::telemetry::testing::setup_test_logging();
 	let config = crate::config::parse_config("{}".to_string(), None)?;
 	let stores = Stores::new();
@@ -272,10 +2392,11 @@ fn setup() -> anyhow::Result<TestBind> {
 		stores: stores.clone(),
 		tracer: None,
 		metrics: Arc::new(crate::metrics::Metrics::new(metrics::sub_registry(
-			&mut Registry::default(),
+			registry,
 		))),
 		upstream: client.clone(),
 		ca: None,
+		coalescer: Default::default(),
 
 		mcp_state: mcp::sse::App::new(
 			stores.clone(),
@@ -53,22 +53,50 @@ use crate::http::{
 };
 use crate::llm::{LLMRequest, LLMResponse, RequestResult};
 use crate::proxy::ProxyError;
+use crate::proxy::mirror;
 use crate::store::{BackendPolicies, Event, LLMRoutePolicies, RoutePolicies};
 use crate::telemetry::log;
 use crate::telemetry::log::{AsyncLog, DropOnLog, LogBody, RequestLog};
-use crate::telemetry::metrics::TCPLabels;
+use crate::telemetry::metrics::{
+	BackendLabels, GaugeGuard, MirrorComparison, MirrorLabels, RateLimitLabels, RateLimiter,
+	TCPLabels,
+};
 use crate::telemetry::trc::TraceParent;
-use crate::transport::stream::{Extension, Socket, TCPConnectionInfo, TLSConnectionInfo};
+use crate::transport::stream::{
+	Extension, Socket, TCPConnectionInfo, TLSConnectionInfo, UpstreamConnectionInfo,
+};
 use crate::types::agent;
 use crate::types::proto::ProtoError;
 use crate::{ProxyInputs, *};
 
-fn select_backend(route: &Route, _req: &Request) -> Option<RouteBackendReference> {
+// Returns the backend for the request, along with a Set-Cookie header to return if the request was
+// newly assigned a canary variant.
+fn select_backend(
+	route: &Route,
+	req: &Request,
+) -> Option<(RouteBackendReference, Option<HeaderValue>)> {
+	if let Some(canary) = route.policies.as_ref().and_then(|p| p.canary.as_ref()) {
+		let (variant, set_cookie) = canary.assign(req);
+		let backend = canary
+			.backends(variant, &route.backends)
+			.choose_weighted(&mut rand::rng(), |b| b.weight)
+			.ok()?
+			.clone();
+		return Some((backend, set_cookie));
+	}
+	if let Some(lb) = route
+		.policies
+		.as_ref()
+		.and_then(|p| p.load_balancer.as_ref())
+		&& let Some(b) = lb.select(&route.backends, req)
+	{
+		return Some((b.clone(), None));
+	}
 	route
 		.backends
 		.choose_weighted(&mut rand::rng(), |b| b.weight)
 		.ok()
-		.cloned()
+		.map(|b| (b.clone(), None))
 }
 
 async fn apply_request_policies(
@@ -82,8 +110,12 @@ async fn apply_request_policies(
 			.await
 			.map_err(ProxyError::JwtAuthenticationFailure)?;
 	}
+	if let Some(a) = &policies.authorization {
+		a.apply(req)?;
+	}
 	let ext_auth = if let Some(x) = &policies.ext_authz {
-		x.check(client.clone(), req).await?
+		x.check(client.clone(), policies.ext_authz_tls.clone().into(), req)
+			.await?
 	} else {
 		http::PolicyResponse::default()
 	};
@@ -93,6 +125,14 @@ async fn apply_request_policies(
 
 	for lrl in &policies.local_rate_limit {
 		if !lrl.check_request(req) {
+			record_rate_limit(Some(log), RateLimiter::local);
+			return Err(ProxyError::RateLimitExceeded);
+		}
+	}
+
+	for grl in &policies.global_rate_limit {
+		if !grl.check_request(req).await? {
+			record_rate_limit(Some(log), RateLimiter::global);
 			return Err(ProxyError::RateLimitExceeded);
 		}
 	}
@@ -102,6 +142,9 @@ async fn apply_request_policies(
 	} else {
 		http::PolicyResponse::default()
 	};
+	if lrl.should_short_circuit() {
+		record_rate_limit(Some(log), RateLimiter::remote);
+	}
 	let policy_resp = ext_auth.merge(lrl);
 
 	if let Some(j) = &policies.transformation {
@@ -112,18 +155,41 @@ async fn apply_request_policies(
 	Ok(policy_resp)
 }
 
-fn apply_llm_request_policies(
+async fn apply_llm_request_policies(
 	policies: &store::LLMRoutePolicies,
-	req: &LLMRequest,
+	req: &Request,
+	llm_req: &LLMRequest,
+	log: Option<&RequestLog>,
 ) -> Result<(), ProxyError> {
 	for lrl in &policies.local_rate_limit {
-		if !lrl.check_llm_request(req) {
+		if !lrl.check_llm_request(llm_req) {
+			record_rate_limit(log, RateLimiter::local);
+			return Err(ProxyError::RateLimitExceeded);
+		}
+	}
+	for grl in &policies.global_rate_limit {
+		if !grl.check_llm_request(req, llm_req).await? {
+			record_rate_limit(log, RateLimiter::global);
 			return Err(ProxyError::RateLimitExceeded);
 		}
 	}
 	Ok(())
 }
 
+fn record_rate_limit(log: Option<&RequestLog>, limiter: RateLimiter) {
+	let Some(log) = log else {
+		return;
+	};
+	log
+		.metrics
+		.rate_limit_rejections
+		.get_or_create(&RateLimitLabels {
+			route: (&log.route_name).into(),
+			limiter,
+		})
+		.inc();
+}
+
 fn apply_request_filters(
 	filters: &[RouteFilter],
 	path_match: &PathMatch,
@@ -134,6 +200,7 @@ fn apply_request_filters(
 	for filter in filters {
 		match filter {
 			RouteFilter::RequestHeaderModifier(hm) => hm.apply(req.headers_mut())?,
+			RouteFilter::DynamicHeader(dh) => dh.apply(req)?,
 			RouteFilter::UrlRewrite(rw) => rw.apply(req, path_match)?,
 			RouteFilter::RequestRedirect(red) => {
 				return Ok((Some(red.apply(req, path_match)?), header_map));
@@ -147,6 +214,9 @@ fn apply_request_filters(
 					header_map = Some(hm)
 				}
 			},
+			RouteFilter::ResponseBodyTransform(t) => t.apply_request(req),
+			RouteFilter::GrpcWeb(g) => g.apply(req),
+			RouteFilter::Compression(c) => c.apply(req),
 			// Response only
 			RouteFilter::ResponseHeaderModifier { .. } => {},
 			// This is handled elsewhere
@@ -174,11 +244,29 @@ fn apply_response_filters(
 		match filter {
 			RouteFilter::ResponseHeaderModifier(rh) => rh.apply(resp.headers_mut())?,
 			RouteFilter::RequestHeaderModifier { .. } => {},
+			RouteFilter::DynamicHeader(_) => {},
 			RouteFilter::UrlRewrite { .. } => {},
 			RouteFilter::RequestRedirect { .. } => {},
 			RouteFilter::RequestMirror(_) => {},
 			RouteFilter::DirectResponse(_) => {},
 			RouteFilter::CORS(_) => {},
+			// Applied to the body, in apply_response_body_filters
+			RouteFilter::ResponseBodyTransform(_) => {},
+			// Applied based on the request, in attempt_upstream
+			RouteFilter::GrpcWeb(_) => {},
+			RouteFilter::Compression(_) => {},
+		}
+	}
+	Ok(())
+}
+
+async fn apply_response_body_filters(
+	filters: &[RouteFilter],
+	resp: &mut Response,
+) -> Result<(), filters::Error> {
+	for filter in filters {
+		if let RouteFilter::ResponseBodyTransform(t) = filter {
+			t.apply(resp).await?;
 		}
 	}
 	Ok(())
@@ -241,6 +329,7 @@ pub struct HTTPProxy {
 	pub(super) inputs: Arc<ProxyInputs>,
 	pub(super) selected_listener: Option<Arc<Listener>>,
 	pub(super) target_address: SocketAddr,
+	pub(super) drain: DrainWatcher,
 }
 
 impl HTTPProxy {
@@ -254,6 +343,10 @@ impl HTTPProxy {
 		// Copy connection level attributes into request level attributes
 		connection.copy::<TCPConnectionInfo>(req.extensions_mut());
 		connection.copy::<TLSConnectionInfo>(req.extensions_mut());
+		let client_ip = http::client_ip(&req, &self.inputs.cfg.trusted_proxies);
+		if let Some(ip) = client_ip {
+			req.extensions_mut().insert(http::ClientIp(ip));
+		}
 
 		let tcp = connection
 			.get::<TCPConnectionInfo>()
@@ -265,12 +358,39 @@ impl HTTPProxy {
 			tcp.clone(),
 		)
 		.into();
+		log.with(|l| l.client_ip = client_ip);
+		let grpc = is_grpc(req.headers());
+		let server_timing = http::servertiming::ServerTiming::from_request(&mut req);
+		let mut mirror_comparisons = Vec::new();
+		let mut listener_response_filter = None;
 		let ret = self
-			.proxy_internal(connection, req, log.as_mut().unwrap())
+			.proxy_internal(
+				connection,
+				req,
+				log.as_mut().unwrap(),
+				&mut mirror_comparisons,
+				&mut listener_response_filter,
+			)
 			.await;
 
 		log.with(|l| l.error = ret.as_ref().err().map(|e| e.to_string()));
-		let resp = ret.unwrap_or_else(|err| err.as_response());
+		let mut resp = ret.unwrap_or_else(|err| {
+			if grpc {
+				let resp = err.as_grpc_response();
+				log.with(|l| maybe_set_grpc_status(&l.grpc_status, resp.headers()));
+				resp
+			} else {
+				err.as_response()
+			}
+		});
+		if let Some(st) = server_timing {
+			st.record("total", start.elapsed());
+			st.apply(&mut resp);
+		}
+		// Errors returned once the listener was selected are filtered as well
+		if let Some(hf) = listener_response_filter {
+			hf.apply(resp.headers_mut());
+		}
 
 		// Pass the log into the body so it finishes once the stream is entirely complete.
 		// We will also record trailer info there.
@@ -279,6 +399,7 @@ impl HTTPProxy {
 			l.cel.ctx().with_response(&resp)
 		});
 
+		let resp = mirror::observe(resp, mirror_comparisons);
 		resp.map(move |b| http::Body::new(LogBody::new(b, log)))
 	}
 	async fn proxy_internal(
@@ -286,6 +407,8 @@ impl HTTPProxy {
 		connection: Arc<Extension>,
 		mut req: ::http::Request<Incoming>,
 		log: &mut RequestLog,
+		mirror_comparisons: &mut Vec<mirror::PrimarySender>,
+		listener_response_filter: &mut Option<http::headerfilter::HeaderRules>,
 	) -> Result<Response, ProxyError> {
 		log.tls_info = connection.get::<TLSConnectionInfo>().cloned();
 		let selected_listener = self.selected_listener.clone();
@@ -322,26 +445,13 @@ impl HTTPProxy {
 		sensitive_headers(&mut req);
 		let mut req_upgrade = hop_by_hop_headers(&mut req);
 
-		const ALWAYS_TRACE: bool = false; // todo configurable percentage
-		if let Some(tp) = trc::TraceParent::from_request(&req) {
-			// User has a span
-			// We will make a new span and report
-			// TODO: allow us to start a span from scratch
-			log.tracer = self.inputs.tracer.clone();
-			let ns = tp.new_span();
-
-			log.incoming_span = Some(tp);
-			ns.insert_header(&mut req);
-			req.extensions_mut().insert(ns.clone());
-			log.outgoing_span = Some(ns);
-		} else if ALWAYS_TRACE {
+		let (incoming_span, outgoing_span) = trc::propagate(&mut req, &self.inputs.cfg.tracing);
+		if let Some(ns) = outgoing_span {
 			log.tracer = self.inputs.tracer.clone();
-			let mut ns = TraceParent::new();
-			ns.flags = 1;
-			ns.insert_header(&mut req);
 			req.extensions_mut().insert(ns.clone());
 			log.outgoing_span = Some(ns);
 		}
+		log.incoming_span = incoming_span;
 		if let Some(tracer) = &log.tracer {
 			log.cel.register(tracer.fields.as_ref());
 		}
@@ -363,8 +473,17 @@ impl HTTPProxy {
 			.ok_or(ProxyError::ListenerNotFound)?;
 		log.gateway_name = Some(selected_listener.gateway_name.clone());
 		log.listener_name = Some(selected_listener.name.clone());
+		log.log_sampling = selected_listener.log_sampling.clone();
 
 		debug!(bind=%bind_name, listener=%selected_listener.key, "selected listener");
+		let listener_policies = inputs.stores.read_binds().listener_policies(
+			selected_listener.key.clone(),
+			selected_listener.gateway_name.clone(),
+		);
+		if let Some(hf) = listener_policies.header_filter {
+			hf.request.apply(req.headers_mut());
+			*listener_response_filter = Some(hf.response);
+		}
 
 		let (selected_route, path_match) = http::route::select_best_route(
 			inputs.stores.clone(),
@@ -379,6 +498,22 @@ impl HTTPProxy {
 		log.route_name = Some(selected_route.route_name.clone());
 
 		debug!(bind=%bind_name, listener=%selected_listener.key, route=%selected_route.key, "selected route");
+		if let Some(page) = inputs
+			.stores
+			.read_binds()
+			.maintenance(&selected_route.route_name)
+		{
+			debug!(route=%selected_route.route_name, "route is in maintenance mode");
+			return Ok(page.apply());
+		}
+		let server_timing = req
+			.extensions()
+			.get::<http::servertiming::ServerTiming>()
+			.cloned();
+		if let Some(st) = &server_timing {
+			st.record("route", log.start.elapsed());
+		}
+		let policy_start = Instant::now();
 
 		let route_policies = inputs.stores.read_binds().route_policies(
 			selected_route.key.clone(),
@@ -403,6 +538,13 @@ impl HTTPProxy {
 		if let Some(dr) = ext_authz_response.direct_response {
 			return Ok(dr);
 		}
+		// The timeout header is stripped on every route, including those without a timeout policy
+		let default_timeout = http::timeout::Policy::default();
+		let timeout = selected_route
+			.policies
+			.as_ref()
+			.map_or(&default_timeout, |p| &p.timeout);
+		timeout.apply_header_override(&mut req);
 		let mut response_polices = ResponsePolicies::from(route_policies.transformation.clone());
 		merge_in_headers(
 			ext_authz_response.response_headers,
@@ -422,9 +564,15 @@ impl HTTPProxy {
 			&mut response_polices.response_headers,
 		);
 
-		let selected_backend =
+		let (selected_backend, canary_cookie) =
 			select_backend(selected_route.as_ref(), &req).ok_or(ProxyError::NoValidBackends)?;
+		if let Some(cookie) = canary_cookie {
+			response_polices
+				.response_headers
+				.append(header::SET_COOKIE, cookie);
+		}
 		let selected_backend = resolve_backend(selected_backend, self.inputs.as_ref())?;
+		log.backend_name = Some(selected_backend.backend.name());
 		let (direct_response, response_headers_backend) =
 			apply_request_filters(selected_backend.filters.as_slice(), &path_match, &mut req)?;
 		if let Some(resp) = direct_response {
@@ -435,6 +583,10 @@ impl HTTPProxy {
 			&mut response_polices.response_headers,
 		);
 
+		if let Some(st) = &server_timing {
+			st.record("policy", policy_start.elapsed());
+		}
+
 		let mut mirrors = get_mirrors(selected_route.as_ref().filters.as_slice());
 		mirrors.extend(get_mirrors(selected_backend.filters.as_slice()));
 		let (head, body) = req.into_parts();
@@ -451,9 +603,25 @@ impl HTTPProxy {
 			let upstream = self.inputs.upstream.clone();
 			let inputs = inputs.clone();
 			let policy_client = self.policy_client();
+			let primary = mirror.compare.then(|| {
+				let (tx, rx) = tokio::sync::oneshot::channel();
+				mirror_comparisons.push(tx);
+				rx
+			});
+			let labels = MirrorLabels {
+				route: Some(&selected_route.route_name).into(),
+				backend: mirror.backend.name().into(),
+				result: MirrorComparison::matched,
+			};
+			let metrics = self.inputs.metrics.clone();
 			tokio::task::spawn(async move {
-				if let Err(e) = send_mirror(inputs, policy_client, mirror, req).await {
-					warn!("error sending mirror request: {}", e);
+				match send_mirror(inputs, policy_client, mirror, req).await {
+					Ok(resp) => {
+						if let Some(primary) = primary {
+							mirror::compare(primary, resp, labels, metrics).await;
+						}
+					},
+					Err(e) => warn!("error sending mirror request: {}", e),
 				}
 			});
 		}
@@ -465,6 +633,10 @@ impl HTTPProxy {
 		};
 		let late_route_policies: Arc<LLMRoutePolicies> = Arc::new(route_policies.into());
 		let response_polices = Arc::new(response_polices);
+		let budget = retries.as_ref().and_then(|r| r.budget.as_ref());
+		if let Some(budget) = budget {
+			budget.record_request();
+		}
 		// attempts is the total number of attempts, not the retries
 		let attempts = retries.as_ref().map(|r| r.attempts.get() + 1).unwrap_or(1);
 		let body = if attempts > 1 {
@@ -507,6 +679,10 @@ impl HTTPProxy {
 				debug!("buffered too much to attempt a retry");
 				return last_res.expect("should only be capped if we had a previous attempt");
 			}
+			if n > 0 && budget.is_some_and(|b| !b.try_retry()) {
+				debug!("retry budget exhausted");
+				return last_res.expect("retries always have a previous attempt");
+			}
 			// If we don't need
 			last_res = None;
 			if !last {
@@ -569,6 +745,31 @@ impl HTTPProxy {
 		let override_dest = maybe_inference.mutate_request(&mut req).await?;
 		log.inference_pool = override_dest;
 
+		let idle_timeout = match &selected_route.policies {
+			Some(TrafficPolicy { timeout, .. }) => timeout.idle_timeout,
+			_ => None,
+		};
+		if let Some(idle) = idle_timeout {
+			req = http::timeout::BodyTimeout::Idle(idle).apply_request(req);
+		}
+		let grpc_web = req
+			.extensions()
+			.get::<http::grpcweb::GrpcWebRequest>()
+			.copied();
+		let compression = req
+			.extensions()
+			.get::<http::compression::CompressionRequest>()
+			.cloned();
+		let timeout_override = req
+			.extensions()
+			.get::<http::timeout::TimeoutOverride>()
+			.copied();
+		let server_timing = req
+			.extensions()
+			.get::<http::servertiming::ServerTiming>()
+			.cloned();
+		let upstream_start = Instant::now();
+
 		let call = make_backend_call(
 			self.inputs.clone(),
 			&route_policies,
@@ -580,8 +781,9 @@ impl HTTPProxy {
 		)
 		.await?;
 
-		let timeout = match &selected_route.policies {
-			Some(TrafficPolicy { timeout, .. }) => timeout.effective_timeout(),
+		let timeout = match (timeout_override, &selected_route.policies) {
+			(Some(http::timeout::TimeoutOverride(timeout)), _) => Some(timeout),
+			(None, Some(TrafficPolicy { timeout, .. })) => timeout.effective_timeout(),
 			_ => None,
 		};
 
@@ -604,19 +806,42 @@ impl HTTPProxy {
 				return Err(ProxyError::RequestTimeout);
 			},
 		};
+		if let Some(st) = &server_timing {
+			// Only report the connection time if this request had to wait for a new connection
+			if let Some(c) = resp.extensions().get::<UpstreamConnectionInfo>() {
+				if c.established >= upstream_start {
+					st.record("connect", c.connect_duration);
+				}
+			}
+			st.record("upstream", upstream_start.elapsed());
+		}
 		if resp.status() == StatusCode::SWITCHING_PROTOCOLS {
-			return handle_upgrade(req_upgrade, resp).await;
+			return handle_upgrade(req_upgrade, resp, idle_timeout, self.drain.clone()).await;
 		}
+		remove_hop_headers(resp.headers_mut());
 
 		maybe_inference.mutate_response(&mut resp).await?;
 
 		// Handle response filters
 		apply_response_filters(selected_route.filters.as_slice(), &mut resp)?;
 		apply_response_filters(selected_backend.filters.as_slice(), &mut resp)?;
+		apply_response_body_filters(selected_route.filters.as_slice(), &mut resp).await?;
+		apply_response_body_filters(selected_backend.filters.as_slice(), &mut resp).await?;
+		if let Some(grpc_web) = grpc_web {
+			grpc_web.apply_response(&mut resp);
+		}
+		if let Some(compression) = compression {
+			compression.apply_response(&mut resp).await?;
+		}
 		response_policies.apply(&mut resp, log)?;
 
-		// for now we do not have any body timeout. Maybe we should add it
+		// The request timeout only covers the response headers, so long-running streams are not cut off
+		// by the deadline. The idle timeout bounds the body instead, failing it once it stops making
+		// progress.
 		// let resp = body_timeout.apply(resp);
+		if let Some(idle) = idle_timeout {
+			resp = http::timeout::BodyTimeout::Idle(idle).apply(resp);
+		}
 
 		// gRPC status can be in the initial headers or a trailer, add if they are here
 		maybe_set_grpc_status(&log.grpc_status, resp.headers());
@@ -649,9 +874,14 @@ fn resolve_backend(b: RouteBackendReference, pi: &ProxyInputs) -> Result<RouteBa
 	})
 }
 
+/// Complete a protocol upgrade, such as a WebSocket handshake, once the backend accepted it. The
+/// upgraded connection is copied in both directions until either side closes it, it is idle for
+/// longer than `idle_timeout`, or the proxy starts draining.
 async fn handle_upgrade(
 	req_upgrade_type: &mut Option<RequestUpgrade>,
 	mut resp: Response,
+	idle_timeout: Option<Duration>,
+	drain: DrainWatcher,
 ) -> Result<Response, ProxyError> {
 	let Some(RequestUpgrade {
 		upgade_type,
@@ -661,7 +891,11 @@ async fn handle_upgrade(
 		return Err(ProxyError::UpgradeFailed(None, None));
 	};
 	let resp_upgrade_type = upgrade_type(resp.headers());
-	if Some(&upgade_type) != resp_upgrade_type.as_ref() {
+	// Protocol names are case-insensitive, for example `websocket` and `WebSocket`.
+	let matches = resp_upgrade_type
+		.as_ref()
+		.is_some_and(|t| t.as_bytes().eq_ignore_ascii_case(upgade_type.as_bytes()));
+	if !matches {
 		return Err(ProxyError::UpgradeFailed(
 			Some(upgade_type),
 			resp_upgrade_type,
@@ -681,12 +915,26 @@ async fn handle_upgrade(
 				return;
 			},
 		};
-		let _ = agent_core::copy::copy_bidirectional(
-			&mut TokioIo::new(req),
-			&mut TokioIo::new(response_upgraded),
-			&agent_core::copy::ConnectionResult {},
-		)
-		.await;
+		let tracker = http::timeout::IdleTracker::default();
+		let stats = agent_core::copy::ConnectionResult {};
+		let copy = agent_core::copy::copy_bidirectional(
+			tracker.wrap(TokioIo::new(req)),
+			TokioIo::new(response_upgraded),
+			&stats,
+		);
+		let idle = async {
+			match idle_timeout {
+				Some(idle) => tracker.idle(idle).await,
+				None => std::future::pending().await,
+			}
+		};
+		// Upgraded connections are no longer tracked by the HTTP server, so hold the drain here.
+		// There is no way to ask the client to reconnect, so the connection is closed once draining starts.
+		tokio::select! {
+			res = copy => trace!(?res, "upgraded connection complete"),
+			_ = idle => debug!("upgraded connection idle, closing"),
+			_ = drain.wait_for_drain() => debug!("drain started, closing upgraded connection"),
+		}
 	});
 	Ok(resp)
 }
@@ -736,6 +984,7 @@ async fn make_backend_call(
 ) -> Result<Pin<Box<dyn Future<Output = Result<Response, ProxyError>> + Send>>, ProxyError> {
 	let client = inputs.upstream.clone();
 	let policy_target = PolicyTarget::Backend(backend.name());
+	let coalesce_requested = req.extensions().get::<http::coalesce::Coalesce>().is_some();
 	let backend_call = match backend {
 		Backend::AI(_, ai) => {
 			let (target, default_policies) = match &ai.host_override {
@@ -748,6 +997,8 @@ async fn make_backend_call(
 						llm: None,
 						// Attach LLM provider, but don't use default setup
 						llm_provider: Some((ai.provider.clone(), false)),
+						connection_pool: None,
+						header_filter: None,
 					}),
 				),
 				None => {
@@ -849,6 +1100,9 @@ async fn make_backend_call(
 		Some(def) => def.merge(policies),
 		None => policies,
 	};
+	if let Some(hf) = &policies.header_filter {
+		hf.request.apply(req.headers_mut());
+	}
 
 	// Apply auth before LLM request setup, so the providers can assume auth is in standardized header
 	auth::apply_backend_auth(policies.backend_auth.as_ref(), &mut req).await?;
@@ -870,7 +1124,7 @@ async fn make_backend_call(
 			RequestResult::Success(r, lr) => (r, lr),
 			RequestResult::Rejected(dr) => return Ok(Box::pin(async move { Ok(dr) })),
 		};
-		apply_llm_request_policies(route_policies, &llm_request)?;
+		apply_llm_request_policies(route_policies, &req, &llm_request, log.as_deref()).await?;
 		log.add(|l| l.llm_request = Some(llm_request.clone()));
 		(req, Some(llm_request))
 	} else {
@@ -879,10 +1133,39 @@ async fn make_backend_call(
 	// Some auth types (AWS) need to be applied after all request processing
 	auth::apply_late_backend_auth(policies.backend_auth.as_ref(), &mut req).await?;
 	let transport = build_transport(&inputs, &backend_call, policies.backend_tls.clone()).await?;
+	// Identical concurrent deterministic completions, and requests the caller marked, share a single
+	// upstream call. The key is taken after all policies have been applied to the request.
+	let coalesce = coalesce_requested
+		|| llm_request
+			.as_ref()
+			.is_some_and(|r| r.deterministic && !r.streaming);
+	let (req, coalesce_key) = if coalesce {
+		let (parts, body) = req.into_parts();
+		let body = body
+			.collect()
+			.await
+			.map_err(|e| ProxyError::Processing(e.into()))?
+			.to_bytes();
+		let key = http::coalesce::RequestKey::new(
+			backend.name(),
+			backend_call.target.clone(),
+			transport.clone(),
+			&parts,
+			body.clone(),
+		);
+		(
+			Request::from_parts(parts, http::Body::from(body)),
+			Some(key),
+		)
+	} else {
+		(req, None)
+	};
+	let coalescer = inputs.coalescer.clone();
 	let call = client::Call {
 		req,
 		target: backend_call.target,
 		transport,
+		connection_pool: policies.connection_pool.clone(),
 	};
 	let mut upstream = inputs.upstream.clone();
 	let llm_response_log = log.as_ref().map(|l| l.llm_response.clone());
@@ -891,14 +1174,45 @@ async fn make_backend_call(
 		.map(|l| l.cel.cel_context.needs_llm_completion())
 		.unwrap_or_default();
 	let rate_limit = route_policies.local_rate_limit.clone();
+	// Streaming responses are returned as they arrive, so they are never retried
+	let rate_limit_retry = policies
+		.llm
+		.as_ref()
+		.and_then(|p| p.rate_limit_retry())
+		.filter(|_| llm_request.as_ref().is_some_and(|r| !r.streaming))
+		.cloned();
+	log.add(|l| {
+		let gauge = l
+			.metrics
+			.upstream_requests_active
+			.get_or_create(&BackendLabels {
+				backend: (&l.backend_name).into(),
+			})
+			.clone();
+		l.upstream_active = Some(GaugeGuard::new(gauge));
+	});
 	Ok(Box::pin(async move {
-		let mut resp = upstream.call(call).await?;
+		let send = async {
+			match rate_limit_retry {
+				Some(retry) => retry.call(&upstream, call).await,
+				None => upstream.call(call).await,
+			}
+		};
+		let mut resp = match coalesce_key {
+			Some(key) => coalescer.call(key, || send).await?,
+			None => send.await?,
+		};
+		if let Some(hf) = &policies.header_filter {
+			hf.response.apply(resp.headers_mut());
+		}
 		a2a::apply_to_response(policies.a2a.as_ref(), a2a_type, &mut resp)
 			.await
 			.map_err(ProxyError::Processing)?;
 		let resp = if let (Some((llm, _)), Some(llm_request)) = (policies.llm_provider, llm_request) {
 			llm
 				.process_response(
+					upstream.clone(),
+					policies.llm.as_ref(),
 					llm_request,
 					rate_limit,
 					llm_response_log.expect("must be set"),
@@ -921,6 +1235,18 @@ fn should_retry(res: &Result<Response, ProxyError>, pol: &retry::Policy) -> bool
 	}
 }
 
+// is_grpc returns true for gRPC requests. gRPC-Web is excluded, as it carries trailers in the body.
+fn is_grpc(headers: &HeaderMap) -> bool {
+	let Some(ct) = headers
+		.get(header::CONTENT_TYPE)
+		.and_then(|v| v.to_str().ok())
+	else {
+		return false;
+	};
+	ct.strip_prefix("application/grpc")
+		.is_some_and(|rest| rest.is_empty() || rest.starts_with('+') || rest.starts_with(';'))
+}
+
 pub fn maybe_set_grpc_status(status: &AsyncLog<u8>, headers: &HeaderMap) {
 	if let Some(s) = headers.get("grpc-status") {
 		let parsed = std::str::from_utf8(s.as_bytes())
@@ -935,14 +1261,13 @@ async fn send_mirror(
 	upstream: PolicyClient,
 	mirror: filters::RequestMirror,
 	mut req: Request,
-) -> Result<(), ProxyError> {
+) -> Result<Response, ProxyError> {
 	req.headers_mut().remove(http::header::CONTENT_LENGTH);
 	let backend = super::resolve_simple_backend(&mirror.backend, inputs.as_ref())?;
-	let _ = upstream.call(req, backend).await?;
-	Ok(())
+	upstream.call(req, backend).await
 }
 
-// Hop-by-hop headers. These are removed when sent to the backend.
+// Hop-by-hop headers. These are removed when sent to the backend, and from its responses.
 // As of RFC 7230, hop-by-hop headers are required to appear in the
 // Connection header field. These are the headers defined by the
 // obsoleted RFC 2616 (section 13.5.1) and are used for backward
@@ -960,6 +1285,20 @@ static HOP_HEADERS: [HeaderName; 9] = [
 	header::UPGRADE,
 ];
 
+fn remove_hop_headers(headers: &mut HeaderMap) {
+	// Headers named in the Connection header are hop-by-hop as well
+	let nominated: Vec<HeaderName> = headers
+		.get_all(header::CONNECTION)
+		.iter()
+		.filter_map(|v| v.to_str().ok())
+		.flat_map(|v| v.split(','))
+		.filter_map(|h| HeaderName::from_bytes(h.trim().as_bytes()).ok())
+		.collect();
+	for h in nominated.iter().chain(HOP_HEADERS.iter()) {
+		headers.remove(h);
+	}
+}
+
 struct RequestUpgrade {
 	upgade_type: HeaderValue,
 	upgrade: OnUpgrade,
@@ -973,9 +1312,7 @@ fn hop_by_hop_headers(req: &mut Request) -> Option<RequestUpgrade> {
 		.map(|s| s.contains("trailers"))
 		.unwrap_or(false);
 	let upgrade_type = upgrade_type(req.headers());
-	for h in HOP_HEADERS.iter() {
-		req.headers_mut().remove(h);
-	}
+	remove_hop_headers(req.headers_mut());
 	// If the incoming request supports trailers, the downstream one will as well
 	if trailers {
 		req.headers_mut().typed_insert(headers::Te::trailers());
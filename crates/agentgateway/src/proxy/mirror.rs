@@ -0,0 +1,162 @@
+use bytes::BytesMut;
+use http_body::{Body, Frame, SizeHint};
+use pin_project_lite::pin_project;
+use tokio::sync::oneshot;
+
+use crate::http::{Response, StatusCode};
+use crate::telemetry::metrics::{Metrics, MirrorComparison, MirrorLabels};
+use crate::*;
+
+// Bodies larger than this are not compared; only the status is.
+const MAX_COMPARED_BODY: usize = 64 * 1024;
+
+/// The parts of a response that are compared between the primary and the mirror.
+#[derive(Debug)]
+pub struct Observed {
+	status: StatusCode,
+	// None if the body was too large to buffer
+	body: Option<Bytes>,
+}
+
+pub type PrimarySender = oneshot::Sender<Observed>;
+
+/// Wraps the primary response so that, once its body is fully sent to the client, it is passed on to
+/// the mirrors comparing against it. The client sees the response unchanged.
+pub fn observe(resp: Response, senders: Vec<PrimarySender>) -> Response {
+	if senders.is_empty() {
+		return resp;
+	}
+	let status = resp.status();
+	resp.map(|body| {
+		http::Body::new(ObservedBody {
+			body,
+			status,
+			buffer: Some(BytesMut::new()),
+			senders,
+		})
+	})
+}
+
+/// Compares the mirrored response against the primary one, once the primary is complete.
+pub async fn compare(
+	primary: oneshot::Receiver<Observed>,
+	mirror: Response,
+	labels: MirrorLabels,
+	metrics: Arc<Metrics>,
+) {
+	let status = mirror.status();
+	let body = axum::body::to_bytes(mirror.into_body(), MAX_COMPARED_BODY)
+		.await
+		.ok();
+	let mirror = Observed { status, body };
+	let Ok(primary) = primary.await else {
+		// The primary response never completed, so there is nothing to compare against
+		trace!("primary response did not complete, skipping mirror comparison");
+		return;
+	};
+	let result = if matches(&primary, &mirror) {
+		MirrorComparison::matched
+	} else {
+		info!(
+			primary.status=%primary.status,
+			mirror.status=%mirror.status,
+			"mirrored response differs from primary response"
+		);
+		MirrorComparison::mismatched
+	};
+	metrics
+		.mirror_comparisons
+		.get_or_create(&MirrorLabels { result, ..labels })
+		.inc();
+}
+
+fn matches(primary: &Observed, mirror: &Observed) -> bool {
+	if primary.status != mirror.status {
+		return false;
+	}
+	let (Some(a), Some(b)) = (&primary.body, &mirror.body) else {
+		// At least one body is too large to compare, so only the status counts
+		return true;
+	};
+	// Normalize JSON, so formatting and key order do not count as differences
+	match (
+		serde_json::from_slice::<serde_json::Value>(a),
+		serde_json::from_slice::<serde_json::Value>(b),
+	) {
+		(Ok(a), Ok(b)) => a == b,
+		_ => a.trim_ascii() == b.trim_ascii(),
+	}
+}
+
+pin_project! {
+	struct ObservedBody {
+		#[pin]
+		body: http::Body,
+		status: StatusCode,
+		// None once the body exceeds MAX_COMPARED_BODY
+		buffer: Option<BytesMut>,
+		senders: Vec<PrimarySender>,
+	}
+
+	impl PinnedDrop for ObservedBody {
+		fn drop(this: Pin<&mut Self>) {
+			let this = this.project();
+			// An empty body may never be polled, so also treat a finished body as complete here
+			if this.body.is_end_stream() {
+				send(this.senders, this.status, this.buffer);
+			}
+		}
+	}
+}
+
+fn send(senders: &mut Vec<PrimarySender>, status: &StatusCode, buffer: &mut Option<BytesMut>) {
+	let body = buffer.take().map(BytesMut::freeze);
+	for tx in senders.drain(..) {
+		let _ = tx.send(Observed {
+			status: *status,
+			body: body.clone(),
+		});
+	}
+}
+
+impl Body for ObservedBody {
+	type Data = Bytes;
+	type Error = axum_core::Error;
+
+	fn poll_frame(
+		self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+	) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+		let this = self.project();
+		let result = ready!(this.body.poll_frame(cx));
+		match &result {
+			Some(Ok(frame)) => {
+				if let Some(data) = frame.data_ref() {
+					if this
+						.buffer
+						.as_ref()
+						.is_some_and(|b| b.len() + data.len() > MAX_COMPARED_BODY)
+					{
+						*this.buffer = None;
+					} else if let Some(buffer) = this.buffer.as_mut() {
+						buffer.extend_from_slice(data);
+					}
+				}
+			},
+			None => send(this.senders, this.status, this.buffer),
+			Some(Err(_)) => {
+				// The response failed; drop the senders so the comparison is skipped
+				this.senders.clear();
+			},
+		}
+		Poll::Ready(result)
+	}
+
+	fn is_end_stream(&self) -> bool {
+		self.body.is_end_stream()
+	}
+
+	fn size_hint(&self) -> SizeHint {
+		self.body.size_hint()
+	}
+}
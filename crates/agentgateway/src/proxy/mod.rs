@@ -1,5 +1,6 @@
 mod gateway;
 pub mod httpproxy;
+mod mirror;
 #[cfg(test)]
 pub mod request_builder;
 pub mod tcpproxy;
@@ -62,6 +63,26 @@ pub enum ProxyError {
 	InvalidRequest,
 	#[error("request upgrade failed, backend tried {1:?} but {0:?} was requested")]
 	UpgradeFailed(Option<HeaderValue>, Option<HeaderValue>),
+	#[error("timed out waiting for a connection to the backend")]
+	ConnectionPoolTimeout,
+	#[error("{0}")]
+	Shared(SharedError),
+}
+
+/// SharedError is a copy of a ProxyError that can be returned to several requests, such as the
+/// waiters of a coalesced call. It keeps the message, status and retryability of the original.
+#[derive(Debug, Clone)]
+pub struct SharedError {
+	message: String,
+	status: StatusCode,
+	grpc_code: tonic::Code,
+	retryable: bool,
+}
+
+impl std::fmt::Display for SharedError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_str(&self.message)
+	}
 }
 
 impl ProxyError {
@@ -71,11 +92,66 @@ impl ProxyError {
 			ProxyError::UpstreamCallFailed(_) => true,
 			ProxyError::RequestTimeout => true,
 			ProxyError::DnsResolution => true,
+			ProxyError::Shared(e) => e.retryable,
 			_ => false,
 		}
 	}
+	pub fn to_shared(&self) -> SharedError {
+		SharedError {
+			message: self.to_string(),
+			status: self.status_code(),
+			grpc_code: self.grpc_code(),
+			retryable: self.is_retryable(),
+		}
+	}
+
 	pub fn as_response(&self) -> Response {
-		let code = match self {
+		let code = self.status_code();
+		let msg = self.to_string();
+		::http::Response::builder()
+			.status(code)
+			.header(hyper::header::CONTENT_TYPE, "text/plain")
+			.body(http::Body::from(msg))
+			.unwrap()
+	}
+
+	/// as_grpc_response returns the error as a gRPC "Trailers-Only" response: a 200 with the status
+	/// carried in grpc-status and grpc-message, which is what gRPC clients expect.
+	pub fn as_grpc_response(&self) -> Response {
+		let code = self.grpc_code();
+		let msg = self.to_string();
+		let msg = percent_encoding::utf8_percent_encode(&msg, GRPC_MESSAGE_ENCODE_SET).to_string();
+		::http::Response::builder()
+			.status(StatusCode::OK)
+			.header(hyper::header::CONTENT_TYPE, "application/grpc")
+			.header("grpc-status", code as i32)
+			.header("grpc-message", msg)
+			.body(http::Body::empty())
+			.unwrap()
+	}
+
+	fn grpc_code(&self) -> tonic::Code {
+		match self {
+			ProxyError::RequestTimeout => tonic::Code::DeadlineExceeded,
+			ProxyError::RateLimitExceeded => tonic::Code::ResourceExhausted,
+			ProxyError::Shared(e) => e.grpc_code,
+			// Otherwise, follow https://github.com/grpc/grpc/blob/master/doc/http-grpc-status-mapping.md
+			_ => match self.status_code() {
+				StatusCode::BAD_REQUEST => tonic::Code::Internal,
+				StatusCode::UNAUTHORIZED => tonic::Code::Unauthenticated,
+				StatusCode::FORBIDDEN => tonic::Code::PermissionDenied,
+				StatusCode::NOT_FOUND => tonic::Code::Unimplemented,
+				StatusCode::TOO_MANY_REQUESTS
+				| StatusCode::BAD_GATEWAY
+				| StatusCode::SERVICE_UNAVAILABLE
+				| StatusCode::GATEWAY_TIMEOUT => tonic::Code::Unavailable,
+				_ => tonic::Code::Unknown,
+			},
+		}
+	}
+
+	fn status_code(&self) -> StatusCode {
+		match self {
 			ProxyError::BindNotFound => StatusCode::NOT_FOUND,
 			ProxyError::ListenerNotFound => StatusCode::NOT_FOUND,
 			ProxyError::RouteNotFound => StatusCode::NOT_FOUND,
@@ -93,28 +169,28 @@ impl ProxyError {
 			ProxyError::FilterError(_) => StatusCode::INTERNAL_SERVER_ERROR,
 			ProxyError::InvalidRequest => StatusCode::BAD_REQUEST,
 
-			ProxyError::JwtAuthenticationFailure(_) => StatusCode::FORBIDDEN,
+			ProxyError::JwtAuthenticationFailure(_) => StatusCode::UNAUTHORIZED,
 			ProxyError::AuthorizationFailed => StatusCode::FORBIDDEN,
 
 			ProxyError::DnsResolution => StatusCode::SERVICE_UNAVAILABLE,
 			ProxyError::NoHealthyEndpoints => StatusCode::SERVICE_UNAVAILABLE,
 			ProxyError::UpstreamCallFailed(_) => StatusCode::SERVICE_UNAVAILABLE,
+			ProxyError::ConnectionPoolTimeout => StatusCode::SERVICE_UNAVAILABLE,
 
 			ProxyError::RequestTimeout => StatusCode::GATEWAY_TIMEOUT,
 			ProxyError::Processing(_) => StatusCode::SERVICE_UNAVAILABLE,
 			ProxyError::ProcessingString(_) => StatusCode::SERVICE_UNAVAILABLE,
 			ProxyError::RateLimitExceeded => StatusCode::TOO_MANY_REQUESTS,
 			ProxyError::RateLimitFailed => StatusCode::TOO_MANY_REQUESTS,
-		};
-		let msg = self.to_string();
-		::http::Response::builder()
-			.status(code)
-			.header(hyper::header::CONTENT_TYPE, "text/plain")
-			.body(http::Body::from(msg))
-			.unwrap()
+
+			ProxyError::Shared(e) => e.status,
+		}
 	}
 }
 
+// grpc-message is percent-encoded, leaving printable ASCII other than '%' as is.
+const GRPC_MESSAGE_ENCODE_SET: &percent_encoding::AsciiSet = &percent_encoding::CONTROLS.add(b'%');
+
 pub fn resolve_backend(b: &BackendReference, pi: &ProxyInputs) -> Result<Backend, ProxyError> {
 	let backend = match b {
 		BackendReference::Service { name, port } => {
@@ -14,7 +14,7 @@ use crate::telemetry::log;
 use crate::telemetry::log::{DropOnLog, RequestLog};
 use crate::telemetry::metrics::TCPLabels;
 use crate::transport::stream;
-use crate::transport::stream::{Socket, TCPConnectionInfo, TLSConnectionInfo};
+use crate::transport::stream::{Alpn, Socket, TCPConnectionInfo, TLSConnectionInfo};
 use crate::types::agent;
 use crate::types::agent::{
 	Backend, BackendReference, BindName, BindProtocol, HeaderMatch, HeaderValueMatch, Listener,
@@ -79,6 +79,11 @@ impl TCPProxy {
 			.tls_info
 			.as_ref()
 			.and_then(|tls| tls.server_name.as_deref());
+		let alpn = log
+			.tls_info
+			.as_ref()
+			.and_then(|tls| tls.negotiated_alpn.as_ref())
+			.map(Alpn::as_str);
 
 		let selected_listener = self.selected_listener.clone();
 		let upstream = self.inputs.upstream.clone();
@@ -91,7 +96,7 @@ impl TCPProxy {
 		debug!(bind=%bind_name, listener=%selected_listener.key, "selected listener");
 
 		let (selected_route) =
-			select_best_route(sni, selected_listener.clone()).ok_or(ProxyError::RouteNotFound)?;
+			select_best_route(sni, alpn, selected_listener.clone()).ok_or(ProxyError::RouteNotFound)?;
 		log.route_rule_name = selected_route.rule_name.clone();
 		log.route_name = Some(selected_route.route_name.clone());
 
@@ -133,9 +138,14 @@ impl TCPProxy {
 	}
 }
 
-fn select_best_route(host: Option<&str>, listener: Arc<Listener>) -> Option<Arc<TCPRoute>> {
+fn select_best_route(
+	host: Option<&str>,
+	alpn: Option<&str>,
+	listener: Arc<Listener>,
+) -> Option<Arc<TCPRoute>> {
 	// TCP matching is much simpler than HTTP.
-	// We pick the best matching hostname, else fallback to precedence:
+	// We pick the best matching hostname, then prefer routes matching the negotiated ALPN protocol
+	// over those matching any protocol, else fallback to precedence:
 	//
 	//  * The oldest Route based on creation timestamp.
 	//  * The Route appearing first in alphabetical order by "{namespace}/{name}".
@@ -146,7 +156,7 @@ fn select_best_route(host: Option<&str>, listener: Arc<Listener>) -> Option<Arc<
 		return None;
 	}
 	for hnm in agent::HostnameMatch::all_matches_or_none(host) {
-		if let Some(r) = listener.tcp_routes.get_hostname(&hnm) {
+		if let Some(r) = listener.tcp_routes.get_hostname(&hnm, alpn) {
 			return Some(Arc::new(r.clone()));
 		}
 	}
@@ -1,4 +1,4 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::marker::PhantomData;
 use std::path::Path;
 use std::sync::{Arc, mpsc};
@@ -34,6 +34,9 @@ pub struct StateManager {
 
 	#[serde(skip_serializing)]
 	xds_client: Option<agent_xds::AdsClient>,
+
+	#[serde(skip_serializing)]
+	local_client: Option<LocalClient>,
 }
 
 pub const ADDRESS_TYPE: Strng =
@@ -67,21 +70,34 @@ impl StateManager {
 		} else {
 			None
 		};
-		if let Some(cfg) = &config.local_config {
+		let local_client = if let Some(cfg) = &config.local_config {
 			let local_client = LocalClient {
 				stores: stores.clone(),
 				cfg: cfg.clone(),
 				client,
+				state: Default::default(),
 			};
-			local_client.run().await?;
-		}
-		Ok(Self { stores, xds_client })
+			local_client.clone().run().await?;
+			Some(local_client)
+		} else {
+			None
+		};
+		Ok(Self {
+			stores,
+			xds_client,
+			local_client,
+		})
 	}
 
 	pub fn stores(&self) -> Stores {
 		self.stores.clone()
 	}
 
+	/// The client reading local configuration, if there is one. This can be used to reload it on demand.
+	pub fn local_client(&self) -> Option<LocalClient> {
+		self.local_client.clone()
+	}
+
 	pub async fn run(self) -> anyhow::Result<()> {
 		match self.xds_client {
 			Some(xds) => xds.run().await.map_err(|e| anyhow::anyhow!(e)),
@@ -96,6 +112,9 @@ pub struct LocalClient {
 	pub cfg: ConfigSource,
 	pub stores: Stores,
 	pub client: Client,
+	// What the last successful load applied, so the next one can remove what is gone. This is shared
+	// between file watch and on-demand reloads, which also serializes them.
+	pub state: Arc<AsyncMutex<PreviousState>>,
 }
 
 impl LocalClient {
@@ -105,7 +124,7 @@ impl LocalClient {
 			self.watch_config_file(path).await?;
 		} else {
 			// Load it once
-			self.reload_config(PreviousState::default()).await?;
+			self.reload_config().await?;
 		}
 
 		Ok(())
@@ -131,7 +150,7 @@ impl LocalClient {
 		info!("Watching config file: {}", path.display());
 
 		let lc: LocalClient = self.to_owned();
-		let mut next_state = lc.reload_config(PreviousState::default()).await?;
+		lc.reload_config().await?;
 		tokio::task::spawn(async move {
 			use notify_debouncer_full::DebouncedEvent;
 			// Handle file change events
@@ -142,11 +161,8 @@ impl LocalClient {
 					.any(|e| matches!(e.kind, EventKind::Modify(_) | EventKind::Create(_)))
 				{
 					info!("Config file changed, reloading...");
-					match lc.reload_config(next_state.clone()).await {
-						Ok(nxt) => {
-							next_state = nxt;
-							info!("Config reloaded successfully")
-						},
+					match lc.reload_config().await {
+						Ok(_) => info!("Config reloaded successfully"),
 						Err(e) => {
 							error!("Failed to reload config: {}", e)
 						},
@@ -159,7 +175,10 @@ impl LocalClient {
 		Ok(())
 	}
 
-	async fn reload_config(&self, prev: PreviousState) -> anyhow::Result<PreviousState> {
+	/// Reads and applies the configuration again. The whole configuration is validated before any of it
+	/// is applied, so on error the running configuration is left untouched.
+	pub async fn reload_config(&self) -> anyhow::Result<ConfigChanges> {
+		let mut state = self.state.lock().await;
 		let config_content = self.cfg.read_to_string().await?;
 		let config = crate::types::local::NormalizedLocalConfig::from(
 			self.client.clone(),
@@ -168,7 +187,11 @@ impl LocalClient {
 		.await?;
 		info!("loaded config from {:?}", self.cfg);
 
+		let names = ResourceNames::from(&config);
+		let changes = ConfigChanges::new(&state.names, &names);
+
 		// Sync the state
+		let prev = std::mem::take(&mut *state);
 		let next_binds =
 			self
 				.stores
@@ -180,10 +203,12 @@ impl LocalClient {
 				.discovery
 				.sync_local(config.services, config.workloads, prev.discovery);
 
-		Ok(PreviousState {
+		*state = PreviousState {
 			binds: next_binds,
 			discovery: next_discovery,
-		})
+			names,
+		};
+		Ok(changes)
 	}
 }
 
@@ -191,4 +216,74 @@ impl LocalClient {
 pub struct PreviousState {
 	pub binds: store::BindPreviousState,
 	pub discovery: store::DiscoveryPreviousState,
+	names: ResourceNames,
+}
+
+// Names of the user facing resources in a configuration, to report what a reload changed.
+#[derive(Clone, Debug, Default)]
+struct ResourceNames {
+	listeners: BTreeSet<Strng>,
+	routes: BTreeSet<Strng>,
+	backends: BTreeSet<Strng>,
+	policies: BTreeSet<Strng>,
+}
+
+impl From<&crate::types::local::NormalizedLocalConfig> for ResourceNames {
+	fn from(config: &crate::types::local::NormalizedLocalConfig) -> Self {
+		let listeners = config
+			.binds
+			.iter()
+			.flat_map(|b| b.listeners.iter())
+			.collect_vec();
+		ResourceNames {
+			listeners: listeners.iter().map(|l| l.key.clone()).collect(),
+			routes: listeners
+				.iter()
+				.flat_map(|l| {
+					let routes = l.routes.iter().map(|r| r.key.clone());
+					let tcp_routes = l.tcp_routes.iter().map(|r| r.key.clone());
+					routes.chain(tcp_routes)
+				})
+				.collect(),
+			backends: config.backends.iter().map(|b| b.name()).collect(),
+			policies: config.policies.iter().map(|p| p.name.clone()).collect(),
+		}
+	}
+}
+
+/// What a configuration reload added and removed.
+#[derive(Debug, Default, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigChanges {
+	pub listeners: Changes,
+	pub routes: Changes,
+	pub backends: Changes,
+	pub policies: Changes,
+}
+
+#[derive(Debug, Default, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Changes {
+	pub added: Vec<Strng>,
+	pub removed: Vec<Strng>,
+}
+
+impl Changes {
+	fn new(prev: &BTreeSet<Strng>, next: &BTreeSet<Strng>) -> Self {
+		Changes {
+			added: next.difference(prev).cloned().collect(),
+			removed: prev.difference(next).cloned().collect(),
+		}
+	}
+}
+
+impl ConfigChanges {
+	fn new(prev: &ResourceNames, next: &ResourceNames) -> Self {
+		ConfigChanges {
+			listeners: Changes::new(&prev.listeners, &next.listeners),
+			routes: Changes::new(&prev.routes, &next.routes),
+			backends: Changes::new(&prev.backends, &next.backends),
+			policies: Changes::new(&prev.policies, &next.policies),
+		}
+	}
 }
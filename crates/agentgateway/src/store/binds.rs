@@ -1,4 +1,4 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::sync::Arc;
 
 use ::http::Request;
@@ -13,7 +13,8 @@ use tracing::{Level, instrument};
 use crate::cel::ContextBuilder;
 use crate::http::auth::BackendAuth;
 use crate::http::backendtls::BackendTLS;
-use crate::http::{ext_authz, remoteratelimit};
+use crate::http::maintenance::MaintenancePage;
+use crate::http::{ext_authz, localratelimit, remoteratelimit};
 use crate::mcp::rbac::{RuleSet, RuleSets};
 use crate::store::Event;
 use crate::types::agent::{
@@ -22,13 +23,14 @@ use crate::types::agent::{
 	RouteName, TargetedPolicy,
 };
 use crate::types::discovery::{NamespacedHostname, Service, Workload};
+use crate::types::proto::agent::Resource as ADPResource;
 use crate::types::proto::agent::resource::Kind as XdsKind;
-use crate::types::proto::agent::{
-	Backend as XdsBackend, Bind as XdsBind, Listener as XdsListener, Policy as XdsPolicy,
-	Resource as ADPResource, Route as XdsRoute,
-};
 use crate::*;
 
+#[cfg(test)]
+#[path = "binds_test.rs"]
+mod tests;
+
 #[derive(Debug)]
 pub struct Store {
 	/// Allows for lookup of services by network address, the service's xds secondary key.
@@ -43,6 +45,10 @@ pub struct Store {
 	staged_listeners: HashMap<BindName, HashMap<ListenerKey, Listener>>,
 	staged_routes: HashMap<ListenerKey, HashMap<RouteKey, Route>>,
 
+	// Routes toggled into maintenance mode at runtime. These are not part of the configuration, so
+	// they are kept across config updates until explicitly cleared.
+	maintenance: HashMap<RouteName, MaintenancePage>,
+
 	tx: tokio::sync::broadcast::Sender<Event<Arc<Bind>>>,
 }
 
@@ -54,6 +60,8 @@ pub struct BackendPolicies {
 	// bool represents "should use default settings for provider"
 	pub llm_provider: Option<(llm::AIProvider, bool)>,
 	pub llm: Option<llm::Policy>,
+	pub connection_pool: Option<client::ConnectionPool>,
+	pub header_filter: Option<http::headerfilter::HeaderFilter>,
 }
 
 impl BackendPolicies {
@@ -65,6 +73,8 @@ impl BackendPolicies {
 			a2a: other.a2a.or(self.a2a),
 			llm: other.llm.or(self.llm),
 			llm_provider: other.llm_provider.or(self.llm_provider),
+			connection_pool: other.connection_pool.or(self.connection_pool),
+			header_filter: other.header_filter.or(self.header_filter),
 		}
 	}
 }
@@ -73,8 +83,12 @@ impl BackendPolicies {
 pub struct RoutePolicies {
 	pub local_rate_limit: Vec<http::localratelimit::RateLimit>,
 	pub remote_rate_limit: Option<remoteratelimit::RemoteRateLimit>,
+	pub global_rate_limit: Vec<http::globalratelimit::GlobalRateLimit>,
 	pub jwt: Option<http::jwt::Jwt>,
+	pub authorization: Option<http::authorization::Authorization>,
 	pub ext_authz: Option<ext_authz::ExtAuthz>,
+	// TLS settings for calls to the ext_authz service, from the policies on its backend
+	pub ext_authz_tls: Option<BackendTLS>,
 	pub transformation: Option<http::transformation_cel::Transformation>,
 }
 
@@ -98,13 +112,25 @@ impl From<RoutePolicies> for LLMRoutePolicies {
 				.filter(|r| r.limit_type == http::localratelimit::RateLimitType::Tokens)
 				.cloned()
 				.collect(),
+			global_rate_limit: value
+				.global_rate_limit
+				.iter()
+				.filter(|r| r.limit_type() == &http::localratelimit::RateLimitType::Tokens)
+				.cloned()
+				.collect(),
 		}
 	}
 }
 
+#[derive(Debug, Default)]
+pub struct ListenerPolicies {
+	pub header_filter: Option<http::headerfilter::HeaderFilter>,
+}
+
 #[derive(Debug, Default)]
 pub struct LLMRoutePolicies {
 	pub local_rate_limit: Vec<http::localratelimit::RateLimit>,
+	pub global_rate_limit: Vec<http::globalratelimit::GlobalRateLimit>,
 }
 
 impl Default for Store {
@@ -122,6 +148,7 @@ impl Store {
 			backends_by_name: Default::default(),
 			staged_routes: Default::default(),
 			staged_listeners: Default::default(),
+			maintenance: Default::default(),
 			tx,
 		}
 	}
@@ -132,6 +159,25 @@ impl Store {
 		tokio_stream::wrappers::BroadcastStream::new(sub)
 	}
 
+	pub fn listener_policies(&self, listener: ListenerKey, gateway: GatewayName) -> ListenerPolicies {
+		let listener = self
+			.policies_by_target
+			.get(&PolicyTarget::Listener(listener));
+		let gateway = self.policies_by_target.get(&PolicyTarget::Gateway(gateway));
+		let rules = listener
+			.iter()
+			.copied()
+			.flatten()
+			.chain(gateway.iter().copied().flatten())
+			.filter_map(|n| self.policies_by_name.get(n))
+			.collect_vec();
+		let header_filter = rules.iter().find_map(|n| match &n.policy {
+			Policy::HeaderFilter(hf) => Some(hf.clone()),
+			_ => None,
+		});
+		ListenerPolicies { header_filter }
+	}
+
 	pub fn route_policies(
 		&self,
 		route_rule: RouteKey,
@@ -165,14 +211,27 @@ impl Store {
 			Policy::JwtAuth(lrl) => Some(lrl.clone()),
 			_ => None,
 		});
+		let authorization = rules.iter().find_map(|n| match &n.policy {
+			Policy::Authorization(a) => Some(a.clone()),
+			_ => None,
+		});
 		let ext_authz = rules.iter().find_map(|n| match &n.policy {
 			Policy::ExtAuthz(lrl) => Some(lrl.clone()),
 			_ => None,
 		});
+		let ext_authz_tls = ext_authz.as_ref().and_then(|ea| {
+			self
+				.backend_policies(PolicyTarget::Backend(ea.backend_name()))
+				.backend_tls
+		});
 		let remote_rate_limit = rules.iter().find_map(|n| match &n.policy {
 			Policy::RemoteRateLimit(lrl) => Some(lrl.clone()),
 			_ => None,
 		});
+		let global_rate_limit = rules.iter().find_map(|n| match &n.policy {
+			Policy::GlobalRateLimit(grl) => Some(grl.clone()),
+			_ => None,
+		});
 		let transformation = rules.iter().find_map(|n| match &n.policy {
 			Policy::Transformation(lrl) => Some(lrl.clone()),
 			_ => None,
@@ -180,8 +239,11 @@ impl Store {
 		RoutePolicies {
 			local_rate_limit: local_rate_limit.unwrap_or_default(),
 			remote_rate_limit,
+			global_rate_limit: global_rate_limit.unwrap_or_default(),
 			jwt,
+			authorization,
 			ext_authz,
+			ext_authz_tls,
 			transformation,
 		}
 	}
@@ -243,11 +305,41 @@ impl Store {
 				}
 			})
 			.next();
+		let connection_pool = self
+			// This is a terrible approach!
+			.policies_by_name
+			.values()
+			.filter_map(|p| {
+				if p.target != tgt {
+					return None;
+				};
+				match &p.policy {
+					Policy::ConnectionPool(cp) => Some(cp.clone()),
+					_ => None,
+				}
+			})
+			.next();
+		let header_filter = self
+			// This is a terrible approach!
+			.policies_by_name
+			.values()
+			.filter_map(|p| {
+				if p.target != tgt {
+					return None;
+				};
+				match &p.policy {
+					Policy::HeaderFilter(hf) => Some(hf.clone()),
+					_ => None,
+				}
+			})
+			.next();
 		BackendPolicies {
 			backend_tls: tls,
 			backend_auth: auth,
 			a2a,
 			llm,
+			connection_pool,
+			header_filter,
 			// These are not attached policies but are represented in this struct for code organization
 			llm_provider: None,
 		}
@@ -300,6 +392,42 @@ impl Store {
 		self.backends_by_name.get(r).cloned()
 	}
 
+	/// Returns the current state of every local rate limit, by the name of the policy defining it.
+	pub fn local_rate_limits(&self) -> BTreeMap<PolicyName, Vec<localratelimit::Status>> {
+		self
+			.policies_by_name
+			.iter()
+			.filter_map(|(name, p)| match &p.policy {
+				Policy::LocalRateLimit(lrl) => {
+					Some((name.clone(), lrl.iter().map(|rl| rl.status()).collect()))
+				},
+				_ => None,
+			})
+			.collect()
+	}
+
+	/// Returns the maintenance page to serve for the route, if it is in maintenance mode.
+	pub fn maintenance(&self, route: &RouteName) -> Option<MaintenancePage> {
+		self.maintenance.get(route).cloned()
+	}
+
+	pub fn maintenance_routes(&self) -> BTreeMap<RouteName, MaintenancePage> {
+		self
+			.maintenance
+			.iter()
+			.map(|(k, v)| (k.clone(), v.clone()))
+			.collect()
+	}
+
+	pub fn set_maintenance(&mut self, route: RouteName, page: MaintenancePage) {
+		self.maintenance.insert(route, page);
+	}
+
+	/// Takes the route out of maintenance mode, returning whether it was in maintenance mode.
+	pub fn clear_maintenance(&mut self, route: &RouteName) -> bool {
+		self.maintenance.remove(route).is_some()
+	}
+
 	#[instrument(
         level = Level::INFO,
         name="remove_bind",
@@ -504,47 +632,60 @@ impl Store {
 		}
 	}
 
-	fn insert_xds(&mut self, res: ADPResource) -> anyhow::Result<()> {
-		trace!("insert resource {res:?}");
-		match res.kind {
-			Some(XdsKind::Bind(w)) => self.insert_xds_bind(w),
-			Some(XdsKind::Listener(w)) => self.insert_xds_listener(w),
-			Some(XdsKind::Route(w)) => self.insert_xds_route(w),
-			Some(XdsKind::Backend(w)) => self.insert_xds_backend(w),
-			_ => Err(anyhow::anyhow!("unknown resource type")),
+	fn apply_xds(&mut self, res: XdsResource) {
+		match res {
+			XdsResource::Bind(mut bind) => {
+				// If XDS server pushes the same bind twice (which it shouldn't really do, but oh well),
+				// we need to copy the listeners over.
+				if let Some(old) = self.by_name.remove(&bind.key) {
+					debug!("bind update, copy old listeners over");
+					bind.listeners = Arc::unwrap_or_clone(old).listeners;
+				}
+				self.insert_bind(bind);
+			},
+			XdsResource::Listener(lis, bind_name) => self.insert_listener(lis, bind_name),
+			XdsResource::Route(route, listener_name) => self.insert_route(route, listener_name),
+			XdsResource::Backend(backend) => self.insert_backend(backend),
+			XdsResource::Policy(policy) => self.insert_policy(policy),
 		}
 	}
+}
 
-	fn insert_xds_bind(&mut self, raw: XdsBind) -> anyhow::Result<()> {
-		let mut bind = Bind::try_from(&raw)?;
-		// If XDS server pushes the same bind twice (which it shouldn't really do, but oh well),
-		// we need to copy the listeners over.
-		if let Some(old) = self.by_name.remove(&bind.key) {
-			debug!("bind update, copy old listeners over");
-			bind.listeners = Arc::unwrap_or_clone(old).listeners;
-		}
-		self.insert_bind(bind);
-		Ok(())
-	}
-	fn insert_xds_listener(&mut self, raw: XdsListener) -> anyhow::Result<()> {
-		let (lis, bind_name): (Listener, BindName) = (&raw).try_into()?;
-		self.insert_listener(lis, bind_name);
-		Ok(())
-	}
-	fn insert_xds_route(&mut self, raw: XdsRoute) -> anyhow::Result<()> {
-		let (route, listener_name): (Route, ListenerKey) = (&raw).try_into()?;
-		self.insert_route(route, listener_name);
-		Ok(())
-	}
-	fn insert_xds_backend(&mut self, raw: XdsBackend) -> anyhow::Result<()> {
-		let backend: (Backend) = (&raw).try_into()?;
-		self.insert_backend(backend);
-		Ok(())
-	}
-	fn insert_xds_policy(&mut self, raw: XdsPolicy) -> anyhow::Result<()> {
-		let policy: (TargetedPolicy) = (&raw).try_into()?;
-		self.insert_policy(policy);
-		Ok(())
+/// XdsResource is an XDS resource that has been fully converted, but not yet applied to the store.
+/// Converting everything up front lets an update be rejected as a whole, rather than leaving the
+/// store partially updated.
+enum XdsResource {
+	Bind(Bind),
+	Listener(Listener, BindName),
+	Route(Route, ListenerKey),
+	Backend(Backend),
+	Policy(TargetedPolicy),
+}
+
+enum PendingUpdate {
+	Insert(XdsResource),
+	Remove(Strng),
+}
+
+impl TryFrom<&ADPResource> for XdsResource {
+	type Error = anyhow::Error;
+
+	fn try_from(res: &ADPResource) -> anyhow::Result<Self> {
+		trace!("convert resource {res:?}");
+		Ok(match &res.kind {
+			Some(XdsKind::Bind(w)) => XdsResource::Bind(Bind::try_from(w)?),
+			Some(XdsKind::Listener(w)) => {
+				let (lis, bind_name) = w.try_into()?;
+				XdsResource::Listener(lis, bind_name)
+			},
+			Some(XdsKind::Route(w)) => {
+				let (route, listener_name) = w.try_into()?;
+				XdsResource::Route(route, listener_name)
+			},
+			Some(XdsKind::Backend(w)) => XdsResource::Backend(w.try_into()?),
+			Some(XdsKind::Policy(w)) => XdsResource::Policy(w.try_into()?),
+			None => anyhow::bail!("unknown resource type"),
+		})
 	}
 }
 
@@ -558,6 +699,7 @@ pub struct Dump {
 	binds: Vec<Arc<Bind>>,
 	policies: Vec<Arc<TargetedPolicy>>,
 	backends: Vec<Arc<Backend>>,
+	maintenance: BTreeMap<RouteName, MaintenancePage>,
 }
 
 impl StoreUpdater {
@@ -595,6 +737,7 @@ impl StoreUpdater {
 			binds,
 			policies,
 			backends,
+			maintenance: store.maintenance_routes(),
 		}
 	}
 	pub fn sync_local(
@@ -653,17 +796,40 @@ impl agent_xds::Handler<ADPResource> for StoreUpdater {
 		&self,
 		updates: Box<&mut dyn Iterator<Item = XdsUpdate<ADPResource>>>,
 	) -> Result<(), Vec<RejectedConfig>> {
+		// Convert the entire update before touching the store. If any resource is invalid, the whole
+		// update is rejected and the previous configuration stays in place.
+		let mut converted = Vec::new();
+		let mut rejects = Vec::new();
+		for res in updates {
+			match res {
+				XdsUpdate::Update(w) => match XdsResource::try_from(&w.resource) {
+					Ok(r) => converted.push(PendingUpdate::Insert(r)),
+					Err(e) => {
+						warn!(resource=%w.name, "rejecting resource: {e}");
+						rejects.push(RejectedConfig::new(w.name, e));
+					},
+				},
+				XdsUpdate::Remove(name) => converted.push(PendingUpdate::Remove(name)),
+			}
+		}
+		if !rejects.is_empty() {
+			warn!(
+				rejected = rejects.len(),
+				"rejecting update, keeping previous configuration"
+			);
+			return Err(rejects);
+		}
+
 		let mut state = self.state.write().unwrap();
-		let handle = |res: XdsUpdate<ADPResource>| {
+		for res in converted {
 			match res {
-				XdsUpdate::Update(w) => state.insert_xds(w.resource)?,
-				XdsUpdate::Remove(name) => {
+				PendingUpdate::Insert(r) => state.apply_xds(r),
+				PendingUpdate::Remove(name) => {
 					debug!("handling delete {}", name);
-					state.remove_resource(&strng::new(name))
+					state.remove_resource(&name)
 				},
 			}
-			Ok(())
-		};
-		agent_xds::handle_single_resource(updates, handle)
+		}
+		Ok(())
 	}
 }
@@ -0,0 +1,121 @@
+use agent_xds::{Handler, XdsResource as RawResource, XdsUpdate};
+
+use super::*;
+use crate::types::agent::HostnameMatch;
+use crate::types::proto::agent::{
+	Bind as XdsBind, Listener as XdsListener, PathMatch as XdsPathMatch, Protocol, Route as XdsRoute,
+	RouteMatch as XdsRouteMatch, path_match,
+};
+
+fn resource(name: &str, kind: XdsKind) -> XdsUpdate<ADPResource> {
+	XdsUpdate::Update(RawResource {
+		name: strng::new(name),
+		resource: ADPResource { kind: Some(kind) },
+	})
+}
+
+fn route(key: &str, route_name: &str, path_regex: &str) -> XdsUpdate<ADPResource> {
+	resource(
+		&format!("route/{key}"),
+		XdsKind::Route(XdsRoute {
+			key: key.to_string(),
+			listener_key: "listener".to_string(),
+			route_name: route_name.to_string(),
+			matches: vec![XdsRouteMatch {
+				path: Some(XdsPathMatch {
+					kind: Some(path_match::Kind::Regex(path_regex.to_string())),
+				}),
+				..Default::default()
+			}],
+			..Default::default()
+		}),
+	)
+}
+
+fn handle(store: &StoreUpdater, updates: Vec<XdsUpdate<ADPResource>>) -> Result<(), Vec<String>> {
+	store
+		.handle(Box::new(&mut updates.into_iter()))
+		.map_err(|rejects| rejects.iter().map(|r| r.to_string()).collect())
+}
+
+fn route_names(store: &StoreUpdater) -> Vec<String> {
+	let listeners = store.read().listeners(strng::new("bind")).unwrap();
+	let listener = listeners.get(&strng::new("listener")).unwrap();
+	listener
+		.routes
+		.get_hostname(&HostnameMatch::None)
+		.map(|(r, _)| r.route_name.to_string())
+		.sorted()
+		.collect()
+}
+
+#[test]
+fn invalid_update_keeps_previous_config() {
+	let store = StoreUpdater::new(Arc::new(RwLock::new(Store::new())));
+	handle(
+		&store,
+		vec![
+			resource(
+				"bind/bind",
+				XdsKind::Bind(XdsBind {
+					key: "bind".to_string(),
+					port: 8080,
+					..Default::default()
+				}),
+			),
+			resource(
+				"listener/listener",
+				XdsKind::Listener(XdsListener {
+					key: "listener".to_string(),
+					bind_key: "bind".to_string(),
+					protocol: Protocol::Http as i32,
+					..Default::default()
+				}),
+			),
+			route("a", "a-v1", "/a/.*"),
+		],
+	)
+	.unwrap();
+	assert_eq!(route_names(&store), vec!["a-v1"]);
+
+	// One valid change and one invalid route: nothing from the batch should be applied.
+	let rejects = handle(
+		&store,
+		vec![route("a", "a-v2", "/a/.*"), route("b", "b-v1", "/b/(")],
+	)
+	.unwrap_err();
+	assert_eq!(rejects.len(), 1);
+	assert!(rejects[0].starts_with("route/b: "), "{rejects:?}");
+	assert_eq!(route_names(&store), vec!["a-v1"]);
+
+	// Once the bad route is fixed, the batch applies in full.
+	handle(
+		&store,
+		vec![route("a", "a-v2", "/a/.*"), route("b", "b-v1", "/b/.*")],
+	)
+	.unwrap();
+	assert_eq!(route_names(&store), vec!["a-v2", "b-v1"]);
+}
+
+#[test]
+fn maintenance_kept_across_updates() {
+	let store = StoreUpdater::new(Arc::new(RwLock::new(Store::new())));
+	let name = strng::new("a");
+	store
+		.write()
+		.set_maintenance(name.clone(), MaintenancePage::default());
+	handle(&store, vec![route("a", "a", "/a/.*")]).unwrap();
+	handle(
+		&store,
+		vec![
+			XdsUpdate::Remove(strng::new("route/a")),
+			route("a", "a", "/a/.*"),
+		],
+	)
+	.unwrap();
+	assert!(store.read().maintenance(&name).is_some());
+
+	assert!(store.write().clear_maintenance(&name));
+	assert!(store.read().maintenance(&name).is_none());
+	assert!(!store.write().clear_maintenance(&name));
+}
@@ -1,13 +1,16 @@
 use std::collections::{BTreeMap, HashSet};
 use std::fmt::Debug;
 use std::hash::BuildHasher;
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
+use std::num::NonZeroU64;
 use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex, MutexGuard};
 use std::task::{Context, Poll, ready};
-use std::time::{Instant, SystemTime};
+use std::time::{Duration, Instant, SystemTime};
 
 use agent_core::telemetry::{OptionExt, ValueBag, debug, display};
+use bytes::Buf;
 use crossbeam::atomic::AtomicCell;
 use frozen_collections::maps::Values;
 use frozen_collections::{FzHashSet, FzOrderedMap, FzStringMap, MapIteration};
@@ -19,7 +22,7 @@ use tracing::log::Log;
 use tracing::{Level, event, log, trace};
 
 use crate::cel::{ContextBuilder, Expression};
-use crate::telemetry::metrics::{HTTPLabels, Metrics};
+use crate::telemetry::metrics::{GaugeGuard, HTTPLabels, Metrics, RouteLabels};
 use crate::telemetry::trc;
 use crate::transport::stream::{TCPConnectionInfo, TLSConnectionInfo};
 use crate::types::agent::{
@@ -79,11 +82,65 @@ impl<T: Debug> Debug for AsyncLog<T> {
 pub struct Config {
 	pub filter: Option<Arc<cel::Expression>>,
 	pub fields: Arc<LoggingFields>,
+	/// The format of access logs. If unset, the process-wide LOG_FORMAT is used.
+	pub format: Option<Format>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Copy, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum Format {
+	Text,
+	Json,
+}
+
+/// LogSampling logs only a fraction of successful requests. Failed and slow requests are always
+/// logged.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+#[cfg_attr(feature = "schema", derive(crate::JsonSchema))]
+pub struct LogSampling {
+	/// Log one in every `rate` successful requests.
+	pub rate: NonZeroU64,
+	/// Requests taking at least this long are always logged.
+	#[serde(default, with = "crate::serde_dur_option")]
+	#[cfg_attr(feature = "schema", schemars(with = "Option<String>"))]
+	pub slow_threshold: Option<Duration>,
+	#[serde(skip)]
+	count: Arc<AtomicU64>,
+}
+
+impl LogSampling {
+	pub fn new(rate: NonZeroU64, slow_threshold: Option<Duration>) -> Self {
+		LogSampling {
+			rate,
+			slow_threshold,
+			count: Default::default(),
+		}
+	}
+
+	/// Returns whether the request should be logged.
+	pub fn sample(
+		&self,
+		status: Option<crate::http::StatusCode>,
+		failed: bool,
+		duration: Duration,
+	) -> bool {
+		let success = !failed && status.is_some_and(|s| !s.is_client_error() && !s.is_server_error());
+		if !success {
+			return true;
+		}
+		if self.slow_threshold.is_some_and(|t| duration >= t) {
+			return true;
+		}
+		self.count.fetch_add(1, Ordering::Relaxed) % self.rate.get() == 0
+	}
 }
 
 #[derive(serde::Serialize, Default, Clone, Debug)]
 pub struct LoggingFields {
 	pub remove: FzHashSet<String>,
+	/// If set, only these built-in fields are logged.
+	pub include: Option<FzHashSet<String>>,
 	pub add: OrderedStringMap<Arc<cel::Expression>>,
 }
 
@@ -145,7 +202,9 @@ where
 
 impl LoggingFields {
 	pub fn has(&self, k: &str) -> bool {
-		self.remove.contains(k) || self.add.contains_key(k)
+		self.remove.contains(k)
+			|| self.add.contains_key(k)
+			|| self.include.as_ref().is_some_and(|i| !i.contains(k))
 	}
 }
 
@@ -154,6 +213,7 @@ pub struct CelLogging {
 	pub cel_context: cel::ContextBuilder,
 	pub filter: Option<Arc<cel::Expression>>,
 	pub fields: Arc<LoggingFields>,
+	pub format: Option<Format>,
 }
 
 pub struct CelLoggingExecutor<'a> {
@@ -205,6 +265,7 @@ impl CelLogging {
 			cel_context,
 			filter: cfg.filter,
 			fields: cfg.fields,
+			format: cfg.format,
 		}
 	}
 
@@ -223,6 +284,7 @@ impl CelLogging {
 			cel_context,
 			filter,
 			fields,
+			format: _,
 		} = self;
 		let executor = cel_context.build()?;
 		Ok(CelLoggingExecutor {
@@ -281,6 +343,7 @@ impl RequestLog {
 			path: None,
 			version: None,
 			status: None,
+			response_bytes: None,
 			jwt_sub: None,
 			retry_attempt: None,
 			error: None,
@@ -292,6 +355,9 @@ impl RequestLog {
 			llm_response: Default::default(),
 			a2a_method: None,
 			inference_pool: None,
+			upstream_active: None,
+			log_sampling: None,
+			client_ip: None,
 		}
 	}
 }
@@ -322,6 +388,8 @@ pub struct RequestLog {
 	pub path: Option<String>,
 	pub version: Option<::http::Version>,
 	pub status: Option<crate::http::StatusCode>,
+	// Bytes of the response body sent to the client
+	pub response_bytes: Option<u64>,
 
 	pub jwt_sub: Option<String>,
 
@@ -340,6 +408,15 @@ pub struct RequestLog {
 	pub a2a_method: Option<&'static str>,
 
 	pub inference_pool: Option<SocketAddr>,
+
+	// Held while a backend is serving the request, for the upstream_requests_active gauge
+	pub upstream_active: Option<GaugeGuard>,
+
+	// Set by the listener, if only some successful requests should be logged
+	pub log_sampling: Option<LogSampling>,
+
+	// The originating client, which differs from the peer when the request came through trusted proxies
+	pub client_ip: Option<IpAddr>,
 }
 
 impl Drop for DropOnLog {
@@ -362,10 +439,33 @@ impl Drop for DropOnLog {
 				status: log.status.as_ref().map(|s| s.as_u16()).into(),
 			})
 			.inc();
+		let route_labels = RouteLabels {
+			route: (&log.route_name).into(),
+			backend: (&log.backend_name).into(),
+			status: log.status.as_ref().map(|s| s.as_u16()).into(),
+		};
+		log
+			.metrics
+			.request_duration
+			.get_or_create(&route_labels)
+			.observe(log.start.elapsed().as_secs_f64());
+		if let Some(bytes) = log.response_bytes {
+			log
+				.metrics
+				.response_bytes
+				.get_or_create(&route_labels)
+				.inc_by(bytes);
+		}
+		// The backend is done once the response is complete
+		log.upstream_active.take();
 
 		let enable_trace = log.tracer.is_some();
 		// We will later check it also matches a filter, but filter is slower
-		let maybe_enable_log = agent_core::telemetry::enabled("request", &Level::INFO);
+		let maybe_enable_log = agent_core::telemetry::enabled("request", &Level::INFO)
+			&& log
+				.log_sampling
+				.as_ref()
+				.is_none_or(|s| s.sample(log.status, log.error.is_some(), log.start.elapsed()));
 		if !maybe_enable_log && !enable_trace {
 			return;
 		}
@@ -411,8 +511,10 @@ impl Drop for DropOnLog {
 			("listener", log.listener_name.display()),
 			("route_rule", log.route_rule_name.display()),
 			("route", log.route_name.display()),
+			("backend", log.backend_name.display()),
 			("endpoint", log.endpoint.display()),
 			("src.addr", Some(display(&log.tcp_info.peer_addr))),
+			("client.addr", log.client_ip.display()),
 			("http.method", log.method.display()),
 			("http.host", log.host.display()),
 			("http.path", log.path.display()),
@@ -422,6 +524,7 @@ impl Drop for DropOnLog {
 				"http.status",
 				log.status.as_ref().map(|s| s.as_u16().into()),
 			),
+			("http.response.bytes", log.response_bytes.map(Into::into)),
 			("grpc.status", grpc.map(Into::into)),
 			("trace.id", trace_id.display()),
 			("span.id", span_id.display()),
@@ -493,7 +596,8 @@ impl Drop for DropOnLog {
 				kv.push((k, eval));
 			}
 
-			agent_core::telemetry::log("info", "request", &kv);
+			let json = log.cel.format.map(|f| f == Format::Json);
+			agent_core::telemetry::log_as("info", "request", &kv, json);
 		}
 	}
 }
@@ -535,6 +639,9 @@ where
 		let result = ready!(this.body.poll_frame(cx));
 		match result {
 			Some(Ok(frame)) => {
+				if let (Some(data), Some(log)) = (frame.data_ref(), this.log.as_mut()) {
+					*log.response_bytes.get_or_insert(0) += data.remaining() as u64;
+				}
 				if let Some(trailer) = frame.trailers_ref() {
 					if let Some(grpc) = this.log.as_mut().map(|log| log.grpc_status.clone()) {
 						crate::proxy::httpproxy::maybe_set_grpc_status(&grpc, trailer);
@@ -554,3 +661,7 @@ where
 		self.body.size_hint()
 	}
 }
+
+#[cfg(test)]
+#[path = "log_test.rs"]
+mod tests;
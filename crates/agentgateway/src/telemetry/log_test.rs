@@ -0,0 +1,97 @@
+use ::http::StatusCode;
+
+use super::*;
+
+fn sampling(rate: u64, slow_threshold: Option<Duration>) -> LogSampling {
+	LogSampling::new(NonZeroU64::new(rate).unwrap(), slow_threshold)
+}
+
+fn count_logged(s: &LogSampling, status: Option<StatusCode>, failed: bool, dur: Duration) -> usize {
+	(0..100).filter(|_| s.sample(status, failed, dur)).count()
+}
+
+#[test]
+fn success_is_sampled() {
+	let s = sampling(10, Some(Duration::from_secs(1)));
+	let fast = Duration::from_millis(5);
+	assert_eq!(count_logged(&s, Some(StatusCode::OK), false, fast), 10);
+	assert_eq!(count_logged(&s, Some(StatusCode::FOUND), false, fast), 10);
+
+	let every = sampling(1, None);
+	assert_eq!(count_logged(&every, Some(StatusCode::OK), false, fast), 100);
+}
+
+#[test]
+fn errors_are_always_logged() {
+	let s = sampling(1000, None);
+	let fast = Duration::from_millis(5);
+	for status in [
+		StatusCode::NOT_FOUND,
+		StatusCode::TOO_MANY_REQUESTS,
+		StatusCode::SERVICE_UNAVAILABLE,
+	] {
+		assert_eq!(count_logged(&s, Some(status), false, fast), 100);
+	}
+	// Requests that failed before a response status was set, or with a successful status but an
+	// error, such as a failed body
+	assert_eq!(count_logged(&s, None, false, fast), 100);
+	assert_eq!(count_logged(&s, Some(StatusCode::OK), true, fast), 100);
+}
+
+#[test]
+fn slow_requests_are_always_logged() {
+	let s = sampling(1000, Some(Duration::from_millis(500)));
+	assert_eq!(
+		count_logged(&s, Some(StatusCode::OK), false, Duration::from_millis(500)),
+		100
+	);
+	assert_eq!(
+		count_logged(&s, Some(StatusCode::OK), false, Duration::from_millis(499)),
+		1
+	);
+}
+
+#[test]
+fn included_fields_hide_the_rest() {
+	let fields = LoggingFields {
+		remove: ["http.status".to_string()].into_iter().collect(),
+		include: Some(
+			["http.method".to_string(), "http.status".to_string()]
+				.into_iter()
+				.collect(),
+		),
+		add: Default::default(),
+	};
+	// `has` reports fields that must not be logged as built-in fields
+	assert!(!fields.has("http.method"));
+	assert!(fields.has("http.path"));
+	// remove takes precedence over include
+	assert!(fields.has("http.status"));
+
+	let all = LoggingFields::default();
+	assert!(!all.has("http.path"));
+}
+
+#[test]
+fn logging_format_config() {
+	let cfg = crate::config::parse_config(
+		r#"{"config": {"logging": {"format": "json", "fields": {"include": ["http.method"]}}}}"#
+			.to_string(),
+		None,
+	)
+	.unwrap();
+	assert_eq!(cfg.logging.format, Some(Format::Json));
+	assert!(cfg.logging.fields.has("http.path"));
+	assert!(!cfg.logging.fields.has("http.method"));
+
+	let cfg = crate::config::parse_config("{}".to_string(), None).unwrap();
+	assert_eq!(cfg.logging.format, None);
+
+	assert!(
+		crate::config::parse_config(
+			r#"{"config": {"logging": {"format": "xml"}}}"#.to_string(),
+			None
+		)
+		.is_err()
+	);
+}
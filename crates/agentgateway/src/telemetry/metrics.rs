@@ -3,11 +3,13 @@ use std::fmt::Debug;
 use agent_core::metrics::{DefaultedUnknown, EncodeDisplay};
 use agent_core::strng::RichStrng;
 use agent_core::version;
-use prometheus_client::encoding::EncodeLabelSet;
+use prometheus_client::encoding::{EncodeLabelSet, EncodeLabelValue};
 use prometheus_client::metrics::family::Family;
+use prometheus_client::metrics::gauge::Gauge;
+use prometheus_client::metrics::histogram::{Histogram, exponential_buckets};
 use prometheus_client::metrics::info::Info;
 use prometheus_client::registry;
-use prometheus_client::registry::Registry;
+use prometheus_client::registry::{Registry, Unit};
 
 use crate::types::agent::BindProtocol;
 
@@ -24,6 +26,33 @@ pub struct HTTPLabels {
 	pub status: DefaultedUnknown<EncodeDisplay<u16>>,
 }
 
+// A smaller set of labels than HTTPLabels, for metrics that are more costly per series.
+#[derive(Clone, Hash, Default, Debug, PartialEq, Eq, EncodeLabelSet)]
+pub struct RouteLabels {
+	pub route: DefaultedUnknown<RichStrng>,
+	pub backend: DefaultedUnknown<RichStrng>,
+	pub status: DefaultedUnknown<EncodeDisplay<u16>>,
+}
+
+#[derive(Clone, Hash, Debug, PartialEq, Eq, EncodeLabelSet)]
+pub struct BackendLabels {
+	pub backend: DefaultedUnknown<RichStrng>,
+}
+
+#[derive(Clone, Hash, Debug, PartialEq, Eq, EncodeLabelSet)]
+pub struct RateLimitLabels {
+	pub route: DefaultedUnknown<RichStrng>,
+	pub limiter: RateLimiter,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, EncodeLabelValue)]
+#[allow(non_camel_case_types)]
+pub enum RateLimiter {
+	local,
+	global,
+	remote,
+}
+
 #[derive(Clone, Hash, Debug, PartialEq, Eq, EncodeLabelSet)]
 pub struct TCPLabels {
 	pub bind: DefaultedUnknown<RichStrng>,
@@ -32,8 +61,27 @@ pub struct TCPLabels {
 	pub protocol: BindProtocol,
 }
 
+#[derive(Clone, Hash, Debug, PartialEq, Eq, EncodeLabelSet)]
+pub struct MirrorLabels {
+	pub route: DefaultedUnknown<RichStrng>,
+	pub backend: DefaultedUnknown<RichStrng>,
+	pub result: MirrorComparison,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, EncodeLabelValue)]
+#[allow(non_camel_case_types)]
+pub enum MirrorComparison {
+	matched,
+	mismatched,
+}
+
 type Counter = Family<HTTPLabels, prometheus_client::metrics::counter::Counter>;
 type TCPCounter = Family<TCPLabels, prometheus_client::metrics::counter::Counter>;
+type MirrorCounter = Family<MirrorLabels, prometheus_client::metrics::counter::Counter>;
+type RouteCounter = Family<RouteLabels, prometheus_client::metrics::counter::Counter>;
+type RateLimitCounter = Family<RateLimitLabels, prometheus_client::metrics::counter::Counter>;
+type DurationHistogram = Family<RouteLabels, Histogram, fn() -> Histogram>;
+type BackendGauge = Family<BackendLabels, Gauge>;
 
 #[derive(Clone, Hash, Debug, PartialEq, Eq, EncodeLabelSet)]
 pub struct BuildLabel {
@@ -44,6 +92,11 @@ pub struct BuildLabel {
 pub struct Metrics {
 	pub requests: Counter,
 	pub downstream_connection: TCPCounter,
+	pub mirror_comparisons: MirrorCounter,
+	pub request_duration: DurationHistogram,
+	pub response_bytes: RouteCounter,
+	pub upstream_requests_active: BackendGauge,
+	pub rate_limit_rejections: RateLimitCounter,
 }
 
 impl Metrics {
@@ -55,6 +108,30 @@ impl Metrics {
 				tag: version::BuildInfo::new().git_tag,
 			}),
 		);
+		let request_duration = DurationHistogram::new_with_constructor(|| {
+			// 1ms to ~65s
+			Histogram::new(exponential_buckets(0.001, 2.0, 17))
+		});
+		registry.register_with_unit(
+			"request_duration",
+			"The duration of HTTP requests, until the response is fully sent",
+			Unit::Seconds,
+			request_duration.clone(),
+		);
+		let response_bytes = RouteCounter::default();
+		// Exposed as response_bytes_total, as the unit is appended to the name
+		registry.register_with_unit(
+			"response",
+			"The total number of bytes sent in HTTP response bodies",
+			Unit::Bytes,
+			response_bytes.clone(),
+		);
+		let upstream_requests_active = BackendGauge::default();
+		registry.register(
+			"upstream_requests_active",
+			"The number of requests currently using a connection to a backend",
+			upstream_requests_active.clone(),
+		);
 		Metrics {
 			requests: build(
 				registry,
@@ -66,6 +143,19 @@ impl Metrics {
 				"downstream_connections",
 				"The total number of downstream connections established",
 			),
+			mirror_comparisons: build(
+				registry,
+				"mirror_comparisons",
+				"The total number of mirrored responses compared to the primary response",
+			),
+			request_duration,
+			response_bytes,
+			upstream_requests_active,
+			rate_limit_rejections: build(
+				registry,
+				"rate_limit_rejections",
+				"The total number of HTTP requests rejected by a rate limit",
+			),
 		}
 	}
 }
@@ -79,3 +169,20 @@ fn build<T: Clone + std::hash::Hash + Eq + Send + Sync + Debug + EncodeLabelSet
 	registry.register(name, help, m.clone());
 	m
 }
+
+/// Keeps a gauge incremented for as long as it is held.
+#[derive(Debug)]
+pub struct GaugeGuard(Gauge);
+
+impl GaugeGuard {
+	pub fn new(gauge: Gauge) -> Self {
+		gauge.inc();
+		GaugeGuard(gauge)
+	}
+}
+
+impl Drop for GaugeGuard {
+	fn drop(&mut self) {
+		self.0.dec();
+	}
+}
@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::ops::Sub;
 use std::sync::Arc;
 use std::time::{Duration, SystemTime};
@@ -5,17 +6,23 @@ use std::time::{Duration, SystemTime};
 use agent_core::telemetry::{OptionExt, ValueBag};
 use http::Version;
 use itertools::Itertools;
-use opentelemetry::trace::{Span, SpanContext, SpanKind, TraceState, Tracer as _, TracerProvider};
+use opentelemetry::trace::{
+	Span, SpanContext, SpanKind, TraceContextExt, TraceState, Tracer as _, TracerProvider,
+};
 use opentelemetry::{Key, KeyValue, TraceFlags};
 use opentelemetry_otlp::WithExportConfig;
 use opentelemetry_sdk::Resource;
-use opentelemetry_sdk::trace::SdkTracerProvider;
+use opentelemetry_sdk::trace::{
+	BatchConfigBuilder, BatchSpanProcessor, SdkTracerProvider, SpanExporter,
+};
 use tokio::io::AsyncWriteExt;
-pub use traceparent::TraceParent;
+pub use traceparent::{TRACEPARENT_HEADER, TRACESTATE_HEADER, TraceParent};
 
 use crate::http::Request;
 use crate::telemetry::log::{CelLoggingExecutor, LoggingFields, RequestLog};
 
+const MAX_QUEUED_SPANS: usize = 4096;
+
 #[derive(Clone, Debug)]
 pub struct Tracer {
 	pub tracer: Arc<opentelemetry_sdk::trace::SdkTracer>,
@@ -26,6 +33,12 @@ pub struct Tracer {
 #[derive(serde::Serialize, Clone, Debug)]
 pub struct Config {
 	pub endpoint: Option<String>,
+	/// Start a new trace for requests that do not carry a trace context.
+	pub always_trace: bool,
+	/// The fraction of new traces that are sampled. Requests continuing an existing trace follow the
+	/// caller's sampling decision.
+	pub sampling_ratio: f64,
+	pub resource_attributes: BTreeMap<String, String>,
 	pub fields: Arc<LoggingFields>,
 }
 
@@ -42,6 +55,7 @@ mod semconv {
 	pub static URL_QUERY: Key = Key::from_static_str("url.query");
 	pub static USER_AGENT: Key = Key::from_static_str("user_agent.original");
 	pub static PEER_ADDRESS: Key = Key::from_static_str("network.peer.address");
+	pub static CLIENT_ADDRESS: Key = Key::from_static_str("client.address");
 }
 
 // Convert log keys to semconv
@@ -51,6 +65,7 @@ fn to_key(k: &str) -> Key {
 		"http.status" => semconv::STATUS_CODE.clone(),
 		"http.method" => semconv::REQUEST_METHOD.clone(),
 		"src.addr" => semconv::PEER_ADDRESS.clone(),
+		"client.addr" => semconv::CLIENT_ADDRESS.clone(),
 		// TODO: should we do http.version as well?
 		_ => Key::new(k.to_string()),
 	}
@@ -61,6 +76,23 @@ impl Tracer {
 		let Some(ep) = &cfg.endpoint else {
 			return Ok(None);
 		};
+		let exporter = opentelemetry_otlp::SpanExporter::builder()
+			.with_tonic()
+			.with_endpoint(ep)
+			.build()?;
+		Ok(Some(Self::with_exporter(cfg, exporter)))
+	}
+
+	pub fn with_exporter(cfg: &Config, exporter: impl SpanExporter + 'static) -> Tracer {
+		// Spans are queued and exported from a background thread. Once the queue is full, new spans
+		// are dropped rather than slowing down requests.
+		let processor = BatchSpanProcessor::builder(exporter)
+			.with_batch_config(
+				BatchConfigBuilder::default()
+					.with_max_queue_size(MAX_QUEUED_SPANS)
+					.build(),
+			)
+			.build();
 		let result = opentelemetry_sdk::trace::SdkTracerProvider::builder()
 			.with_resource(
 				Resource::builder()
@@ -69,21 +101,22 @@ impl Tracer {
 						"service.version",
 						agent_core::version::BuildInfo::new().version,
 					))
+					.with_attributes(
+						cfg
+							.resource_attributes
+							.iter()
+							.map(|(k, v)| KeyValue::new(k.clone(), v.clone())),
+					)
 					.build(),
 			)
-			.with_batch_exporter(
-				opentelemetry_otlp::SpanExporter::builder()
-					.with_tonic()
-					.with_endpoint(ep)
-					.build()?, //
-			)
+			.with_span_processor(processor)
 			.build();
 		let tracer = result.tracer("agentgateway");
-		Ok(Some(Tracer {
+		Tracer {
 			tracer: Arc::new(tracer),
 			provider: result,
 			fields: cfg.fields.clone(),
-		}))
+		}
 	}
 
 	pub fn shutdown(&self) {
@@ -99,7 +132,7 @@ impl Tracer {
 		let mut attributes = attrs
 			.iter()
 			.filter_map(|(k, v)| v.as_ref().map(|v| (k, v)))
-			.map(|(k, v)| KeyValue::new(Key::new(k.to_string()), to_otel(v)))
+			.map(|(k, v)| KeyValue::new(to_key(k), to_otel(v)))
 			.collect_vec();
 		let out_span = request.outgoing_span.as_ref().unwrap();
 		if !out_span.is_sampled() {
@@ -142,7 +175,7 @@ impl Tracer {
 		};
 
 		let out_span = request.outgoing_span.as_ref().unwrap();
-		let mut sb = self
+		let sb = self
 			.tracer
 			.span_builder(span_name)
 			.with_start_time(end.sub(elapsed))
@@ -152,19 +185,46 @@ impl Tracer {
 			.with_trace_id(out_span.trace_id.into())
 			.with_span_id(out_span.span_id.into());
 
-		if let Some(in_span) = &request.incoming_span {
-			let parent = SpanContext::new(
+		let parent = match &request.incoming_span {
+			Some(in_span) => opentelemetry::Context::new().with_remote_span_context(SpanContext::new(
 				in_span.trace_id.into(),
 				in_span.span_id.into(),
 				TraceFlags::new(in_span.flags),
 				true,
 				TraceState::default(),
-			);
-		}
-		sb.start(self.tracer.as_ref()).end()
+			)),
+			None => opentelemetry::Context::new(),
+		};
+		self.tracer.build_with_context(sb, &parent).end()
 	}
 }
 
+/// Updates the W3C trace context of a request for this proxy hop, returning the incoming and
+/// outgoing trace parents.
+///
+/// A valid incoming `traceparent` is continued with a new span id. A malformed one is replaced with
+/// a new trace, as is a missing one if `always_trace` is set; new traces are sampled according to
+/// `sampling_ratio`. `tracestate` is only forwarded alongside the `traceparent` it was received with.
+pub fn propagate(req: &mut Request, cfg: &Config) -> (Option<TraceParent>, Option<TraceParent>) {
+	if let Some(tp) = TraceParent::from_request(req) {
+		let ns = tp.new_span();
+		ns.insert_header(req);
+		return (Some(tp), Some(ns));
+	}
+	let malformed = req.headers().contains_key(TRACEPARENT_HEADER);
+	req.headers_mut().remove(TRACEPARENT_HEADER);
+	req.headers_mut().remove(TRACESTATE_HEADER);
+	if !malformed && !cfg.always_trace {
+		return (None, None);
+	}
+	let mut ns = TraceParent::new();
+	if cfg.sampling_ratio >= 1.0 || rand::random::<f64>() < cfg.sampling_ratio {
+		ns.flags = 1;
+	}
+	ns.insert_header(req);
+	(None, Some(ns))
+}
+
 fn to_otel(v: &ValueBag) -> opentelemetry::Value {
 	use value_bag::visit::Visit;
 	use value_bag::{Error, ValueBag};
@@ -197,6 +257,7 @@ mod traceparent {
 	}
 
 	pub const TRACEPARENT_HEADER: &str = "traceparent";
+	pub const TRACESTATE_HEADER: &str = "tracestate";
 
 	impl Default for TraceParent {
 		fn default() -> Self {
@@ -219,10 +280,15 @@ mod traceparent {
 			req.headers_mut().insert(TRACEPARENT_HEADER, hv);
 		}
 		pub fn from_request(req: &Request) -> Option<Self> {
-			req
-				.headers()
-				.get(TRACEPARENT_HEADER)
-				.and_then(|b| b.to_str().ok())
+			let mut values = req.headers().get_all(TRACEPARENT_HEADER).iter();
+			let value = values.next()?;
+			if values.next().is_some() {
+				// Multiple traceparent headers are invalid
+				return None;
+			}
+			value
+				.to_str()
+				.ok()
 				.and_then(|b| TraceParent::try_from(b).ok())
 		}
 		pub fn new_span(&self) -> Self {
@@ -267,13 +333,33 @@ mod traceparent {
 			}
 
 			let segs: Vec<&str> = value.split('-').collect();
+			if segs.iter().map(|s| s.len()).collect::<Vec<_>>() != [2, 32, 16, 2] {
+				anyhow::bail!("traceparent malformed segments")
+			}
+			if !value
+				.bytes()
+				.all(|b| b == b'-' || b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+			{
+				anyhow::bail!("traceparent must be lowercase hex")
+			}
 
-			Ok(Self {
+			let tp = Self {
 				version: u8::from_str_radix(segs[0], 16)?,
 				trace_id: u128::from_str_radix(segs[1], 16)?,
 				span_id: u64::from_str_radix(segs[2], 16)?,
 				flags: u8::from_str_radix(segs[3], 16)?,
-			})
+			};
+			if tp.version == 0xff {
+				anyhow::bail!("traceparent has invalid version")
+			}
+			if tp.trace_id == 0 || tp.span_id == 0 {
+				anyhow::bail!("traceparent has all-zero id")
+			}
+			Ok(tp)
 		}
 	}
 }
+
+#[cfg(test)]
+#[path = "trc_test.rs"]
+mod tests;
@@ -0,0 +1,125 @@
+use super::*;
+
+const TRACEPARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+fn config(always_trace: bool) -> Config {
+	Config {
+		endpoint: None,
+		always_trace,
+		sampling_ratio: 1.0,
+		resource_attributes: Default::default(),
+		fields: Default::default(),
+	}
+}
+
+fn request(headers: &[(&str, &str)]) -> Request {
+	let mut req = ::http::Request::builder().uri("http://lo/");
+	for (k, v) in headers {
+		req = req.header(*k, *v);
+	}
+	req.body(crate::http::Body::empty()).unwrap()
+}
+
+fn header<'a>(req: &'a Request, k: &str) -> Option<&'a str> {
+	req.headers().get(k).map(|v| v.to_str().unwrap())
+}
+
+#[test]
+fn parse() {
+	let tp = TraceParent::try_from(TRACEPARENT).unwrap();
+	assert_eq!(tp.trace_id(), "4bf92f3577b34da6a3ce929d0e0e4736");
+	assert_eq!(tp.span_id(), "00f067aa0ba902b7");
+	assert!(tp.is_sampled());
+	assert_eq!(format!("{tp:?}"), TRACEPARENT);
+
+	for invalid in [
+		"",
+		"00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+		"00-4BF92F3577B34DA6A3CE929D0E0E4736-00F067AA0BA902B7-01",
+		"00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+		"00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+		"ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+		"00-4bf92f3577b34da6a3ce929d0e0e47360-0f067aa0ba902b7-01",
+		"00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7+01",
+		"00-+bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+	] {
+		assert!(TraceParent::try_from(invalid).is_err(), "{invalid}");
+	}
+}
+
+#[test]
+fn propagate_existing() {
+	let mut req = request(&[
+		(TRACEPARENT_HEADER, TRACEPARENT),
+		(TRACESTATE_HEADER, "vendor=value"),
+	]);
+	let (incoming, outgoing) = propagate(&mut req, &config(false));
+	let incoming = incoming.unwrap();
+	let outgoing = outgoing.unwrap();
+	assert_eq!(format!("{incoming:?}"), TRACEPARENT);
+	assert_eq!(outgoing.trace_id, incoming.trace_id);
+	assert_eq!(outgoing.flags, incoming.flags);
+	assert_ne!(outgoing.span_id, incoming.span_id);
+	assert_eq!(
+		header(&req, TRACEPARENT_HEADER),
+		Some(format!("{outgoing:?}").as_str())
+	);
+	assert_eq!(header(&req, TRACESTATE_HEADER), Some("vendor=value"));
+}
+
+#[test]
+fn propagate_missing() {
+	let mut req = request(&[]);
+	assert_eq!(propagate(&mut req, &config(false)), (None, None));
+	assert!(header(&req, TRACEPARENT_HEADER).is_none());
+
+	let (incoming, outgoing) = propagate(&mut req, &config(true));
+	assert!(incoming.is_none());
+	let outgoing = outgoing.unwrap();
+	assert!(outgoing.is_sampled());
+	assert_eq!(
+		header(&req, TRACEPARENT_HEADER),
+		Some(format!("{outgoing:?}").as_str())
+	);
+}
+
+#[test]
+fn propagate_malformed() {
+	let malformed = "00-00000000000000000000000000000000-00f067aa0ba902b7-01";
+	for headers in [
+		vec![
+			(TRACEPARENT_HEADER, malformed),
+			(TRACESTATE_HEADER, "vendor=value"),
+		],
+		vec![
+			(TRACEPARENT_HEADER, TRACEPARENT),
+			(TRACEPARENT_HEADER, TRACEPARENT),
+		],
+	] {
+		let mut req = request(&headers);
+		let (incoming, outgoing) = propagate(&mut req, &config(false));
+		assert!(incoming.is_none());
+		let outgoing = outgoing.unwrap();
+		assert_ne!(outgoing.trace_id(), "4bf92f3577b34da6a3ce929d0e0e4736");
+		assert_eq!(req.headers().get_all(TRACEPARENT_HEADER).iter().count(), 1);
+		assert_eq!(
+			header(&req, TRACEPARENT_HEADER),
+			Some(format!("{outgoing:?}").as_str())
+		);
+		// The trace state belonged to the discarded trace
+		assert!(header(&req, TRACESTATE_HEADER).is_none());
+	}
+}
+
+#[test]
+fn propagate_unsampled_root() {
+	let mut req = request(&[]);
+	let cfg = Config {
+		sampling_ratio: 0.0,
+		..config(true)
+	};
+	let (_, outgoing) = propagate(&mut req, &cfg);
+	// The trace is still propagated, but marked as not sampled
+	assert!(!outgoing.unwrap().is_sampled());
+	assert!(header(&req, TRACEPARENT_HEADER).unwrap().ends_with("-00"));
+}
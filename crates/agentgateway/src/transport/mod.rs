@@ -1,3 +1,4 @@
 pub mod hbone;
+pub mod proxy_protocol;
 pub mod stream;
 pub mod tls;
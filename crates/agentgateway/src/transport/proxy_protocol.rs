@@ -0,0 +1,124 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use anyhow::{anyhow, bail};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::transport::stream::Socket;
+use crate::*;
+
+/// Signature that starts every PROXY protocol v2 header.
+const V2_SIGNATURE: &[u8] = b"\r\n\r\n\0\r\nQUIT\n";
+/// The longest possible v1 header, including the trailing CRLF.
+const V1_MAX_LENGTH: usize = 107;
+const HEADER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// ProxyProtocol reads a PROXY protocol (v1 or v2) header at the start of each connection, so the
+/// client address is preserved behind an L4 load balancer. Headers are only read from trusted
+/// peers; connections from anywhere else are handled as if PROXY protocol was not enabled.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+pub struct ProxyProtocol {
+	/// Peers that are allowed to send a PROXY header, typically the load balancer address range.
+	#[cfg_attr(feature = "schema", schemars(with = "Vec<String>"))]
+	pub trusted_sources: Vec<ipnet::IpNet>,
+}
+
+impl ProxyProtocol {
+	pub fn is_trusted(&self, peer: IpAddr) -> bool {
+		self.trusted_sources.iter().any(|n| n.contains(&peer))
+	}
+
+	/// Read the PROXY header sent by a trusted peer, and use the advertised source address as the
+	/// client address of the connection.
+	pub async fn apply(&self, socket: &mut Socket) -> anyhow::Result<()> {
+		let peer = socket.tcp().peer_addr.ip();
+		if !self.is_trusted(peer) {
+			return Ok(());
+		}
+		let source = tokio::time::timeout(HEADER_TIMEOUT, read_header(socket))
+			.await
+			.map_err(|_| anyhow!("timed out reading PROXY header from {peer}"))??;
+		if let Some(source) = source {
+			socket.set_peer_addr(source);
+		}
+		Ok(())
+	}
+}
+
+/// Read a PROXY header, without consuming any of the data that follows it. Returns the advertised
+/// source address, or None if the header does not carry one (such as health checks sent by the
+/// load balancer itself).
+async fn read_header<R: AsyncRead + Unpin>(r: &mut R) -> anyhow::Result<Option<SocketAddr>> {
+	// The versions can be told apart from the first 6 bytes, which is shorter than any v1 header.
+	let mut buf = vec![0; 6];
+	r.read_exact(&mut buf).await?;
+	if buf == b"PROXY " {
+		while !buf.ends_with(b"\r\n") {
+			if buf.len() >= V1_MAX_LENGTH {
+				bail!("PROXY v1 header too long");
+			}
+			buf.push(r.read_u8().await?);
+		}
+		return parse_v1(&buf);
+	}
+	buf.resize(16, 0);
+	r.read_exact(&mut buf[6..]).await?;
+	if &buf[..12] != V2_SIGNATURE {
+		bail!("missing PROXY header");
+	}
+	let len = u16::from_be_bytes([buf[14], buf[15]]) as usize;
+	buf.resize(16 + len, 0);
+	r.read_exact(&mut buf[16..]).await?;
+	parse_v2(&buf)
+}
+
+fn parse_v1(header: &[u8]) -> anyhow::Result<Option<SocketAddr>> {
+	let line = std::str::from_utf8(header)?
+		.strip_suffix("\r\n")
+		.ok_or_else(|| anyhow!("PROXY v1 header must end with CRLF"))?;
+	let parts = line.split(' ').collect::<Vec<_>>();
+	match parts.as_slice() {
+		["PROXY", "UNKNOWN", ..] => Ok(None),
+		["PROXY", "TCP4" | "TCP6", src, _dst, src_port, _dst_port] => {
+			Ok(Some(SocketAddr::new(src.parse()?, src_port.parse()?)))
+		},
+		_ => bail!("invalid PROXY v1 header {line:?}"),
+	}
+}
+
+fn parse_v2(header: &[u8]) -> anyhow::Result<Option<SocketAddr>> {
+	let version = header[12] >> 4;
+	if version != 2 {
+		bail!("unsupported PROXY protocol version {version}");
+	}
+	match header[12] & 0x0f {
+		// LOCAL: the connection was made by the proxy itself, so keep the real peer address
+		0x0 => return Ok(None),
+		// PROXY
+		0x1 => {},
+		cmd => bail!("unknown PROXY v2 command {cmd}"),
+	}
+	let addrs = &header[16..];
+	let port = |at: usize| u16::from_be_bytes([addrs[at], addrs[at + 1]]);
+	// The high nibble is the address family; the transport protocol in the low nibble is not needed.
+	match header[13] >> 4 {
+		// AF_INET
+		0x1 if addrs.len() >= 12 => {
+			let ip = Ipv4Addr::from(<[u8; 4]>::try_from(&addrs[..4])?);
+			Ok(Some(SocketAddr::new(ip.into(), port(8))))
+		},
+		// AF_INET6
+		0x2 if addrs.len() >= 36 => {
+			let ip = Ipv6Addr::from(<[u8; 16]>::try_from(&addrs[..16])?);
+			Ok(Some(SocketAddr::new(ip.into(), port(32))))
+		},
+		// AF_UNSPEC and AF_UNIX carry no IP address
+		0x0 | 0x3 => Ok(None),
+		_ => bail!("invalid PROXY v2 address block"),
+	}
+}
+
+#[cfg(test)]
+#[path = "proxy_protocol_tests.rs"]
+mod tests;
@@ -0,0 +1,109 @@
+use std::time::Instant;
+
+use tokio::io::AsyncWriteExt;
+
+use super::*;
+use crate::transport::stream::TCPConnectionInfo;
+
+// A v2 PROXY header for a TCP over IPv4 connection from 192.0.2.1:5000 to 198.51.100.1:443.
+fn v2_header() -> Vec<u8> {
+	let mut h = V2_SIGNATURE.to_vec();
+	h.push(0x21); // v2, PROXY
+	h.push(0x11); // AF_INET, STREAM
+	h.extend_from_slice(&12u16.to_be_bytes());
+	h.extend_from_slice(&[192, 0, 2, 1]);
+	h.extend_from_slice(&[198, 51, 100, 1]);
+	h.extend_from_slice(&5000u16.to_be_bytes());
+	h.extend_from_slice(&443u16.to_be_bytes());
+	h
+}
+
+async fn socket_with(peer: &str, data: &[u8]) -> Socket {
+	let (mut client, server) = tokio::io::duplex(1024);
+	client.write_all(data).await.unwrap();
+	Socket::from_memory(
+		server,
+		TCPConnectionInfo {
+			peer_addr: peer.parse().unwrap(),
+			local_addr: "127.0.0.1:80".parse().unwrap(),
+			start: Instant::now(),
+		},
+	)
+}
+
+fn trusting(cidr: &str) -> ProxyProtocol {
+	ProxyProtocol {
+		trusted_sources: vec![cidr.parse().unwrap()],
+	}
+}
+
+#[tokio::test]
+async fn v2() {
+	let mut data = v2_header();
+	data.extend_from_slice(b"GET /");
+	let mut r = data.as_slice();
+	let source = read_header(&mut r).await.unwrap();
+	assert_eq!(source, Some("192.0.2.1:5000".parse().unwrap()));
+	// The request that follows the header is left unread
+	assert_eq!(r, b"GET /");
+}
+
+#[tokio::test]
+async fn v2_local() {
+	let mut data = v2_header();
+	data[12] = 0x20; // v2, LOCAL
+	let source = read_header(&mut data.as_slice()).await.unwrap();
+	assert_eq!(source, None);
+}
+
+#[tokio::test]
+async fn v1() {
+	let mut r: &[u8] = b"PROXY TCP4 192.0.2.1 198.51.100.1 5000 443\r\nGET /";
+	let source = read_header(&mut r).await.unwrap();
+	assert_eq!(source, Some("192.0.2.1:5000".parse().unwrap()));
+	assert_eq!(r, b"GET /");
+
+	let mut r: &[u8] = b"PROXY TCP6 2001:db8::1 2001:db8::2 5000 443\r\n";
+	let source = read_header(&mut r).await.unwrap();
+	assert_eq!(source, Some("[2001:db8::1]:5000".parse().unwrap()));
+
+	let mut r: &[u8] = b"PROXY UNKNOWN\r\nGET /";
+	assert_eq!(read_header(&mut r).await.unwrap(), None);
+	assert_eq!(r, b"GET /");
+}
+
+#[tokio::test]
+async fn invalid() {
+	let mut r: &[u8] = b"GET / HTTP/1.1\r\nHost: example.com\r\n\r\n";
+	assert!(read_header(&mut r).await.is_err());
+	let mut r: &[u8] = b"PROXY TCP4 not-an-ip 198.51.100.1 5000 443\r\n";
+	assert!(read_header(&mut r).await.is_err());
+	let mut data = v2_header();
+	data[13] = 0x41; // unknown address family
+	assert!(read_header(&mut data.as_slice()).await.is_err());
+}
+
+#[tokio::test]
+async fn trusted_source() {
+	let mut data = v2_header();
+	data.extend_from_slice(b"GET /");
+	let mut socket = socket_with("10.0.0.1:12345", &data).await;
+	trusting("10.0.0.0/8").apply(&mut socket).await.unwrap();
+	assert_eq!(socket.tcp().peer_addr, "192.0.2.1:5000".parse().unwrap());
+	assert_eq!(socket.tcp().local_addr, "127.0.0.1:80".parse().unwrap());
+	let mut rest = [0; 5];
+	socket.read_exact(&mut rest).await.unwrap();
+	assert_eq!(&rest, b"GET /");
+}
+
+#[tokio::test]
+async fn untrusted_source() {
+	let data = v2_header();
+	let mut socket = socket_with("203.0.113.7:12345", &data).await;
+	trusting("10.0.0.0/8").apply(&mut socket).await.unwrap();
+	// The header is not parsed, so the client cannot spoof its address
+	assert_eq!(socket.tcp().peer_addr, "203.0.113.7:12345".parse().unwrap());
+	let mut rest = vec![0; data.len()];
+	socket.read_exact(&mut rest).await.unwrap();
+	assert_eq!(rest, data);
+}
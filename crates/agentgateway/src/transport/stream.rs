@@ -5,13 +5,15 @@ use std::pin::Pin;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::task::{Context, Poll};
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
+use agent_core::strng;
+use agent_core::strng::Strng;
 use agent_hbone::RWStream;
 use hyper_util::client::legacy::connect::{Connected, Connection};
 use prometheus_client::metrics::counter::Atomic;
 use tokio::io::{AsyncRead, AsyncWrite, DuplexStream, ReadBuf};
-use tokio::net::TcpStream;
+use tokio::net::{TcpStream, UnixStream};
 use tokio_rustls::TlsStream;
 use tracing::event;
 
@@ -31,11 +33,21 @@ impl Display for Identity {
 	}
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Copy)]
+#[derive(Debug, Clone, Eq, PartialEq)]
 pub enum Alpn {
 	Http11,
 	H2,
-	Other,
+	Other(Strng),
+}
+
+impl Alpn {
+	pub fn as_str(&self) -> &str {
+		match self {
+			Alpn::Http11 => "http/1.1",
+			Alpn::H2 => "h2",
+			Alpn::Other(p) => p.as_str(),
+		}
+	}
 }
 
 impl From<&[u8]> for Alpn {
@@ -45,7 +57,7 @@ impl From<&[u8]> for Alpn {
 		} else if value == b"http/1.1" {
 			Alpn::Http11
 		} else {
-			Alpn::Other
+			Alpn::Other(strng::new(String::from_utf8_lossy(value)))
 		}
 	}
 }
@@ -55,6 +67,22 @@ pub struct TLSConnectionInfo {
 	pub src_identity: Option<Identity>,
 	pub server_name: Option<String>,
 	pub negotiated_alpn: Option<Alpn>,
+	/// The subject common name of the certificate presented by the peer, if any.
+	pub peer_common_name: Option<String>,
+}
+
+fn common_name(cert: &[u8]) -> Option<String> {
+	let (_, cert) = x509_parser::parse_x509_certificate(cert).ok()?;
+	let cn = cert.subject().iter_common_name().next()?;
+	cn.as_str().ok().map(str::to_string)
+}
+
+/// UpstreamConnectionInfo records how long an upstream connection took to establish, including any
+/// TLS handshake. It is attached to every response served over the connection.
+#[derive(Debug, Clone, Copy)]
+pub struct UpstreamConnectionInfo {
+	pub established: Instant,
+	pub connect_duration: Duration,
 }
 
 #[derive(Debug, Clone)]
@@ -113,11 +141,13 @@ impl hyper_util_fork::client::legacy::connect::Connection for Socket {
 		if self
 			.ext
 			.get::<TLSConnectionInfo>()
-			.and_then(|c| c.negotiated_alpn)
-			== Some(Alpn::H2)
+			.is_some_and(|c| c.negotiated_alpn == Some(Alpn::H2))
 		{
 			con = con.negotiated_h2()
 		}
+		if let Some(info) = self.ext.get::<UpstreamConnectionInfo>() {
+			con = con.extra(*info)
+		}
 		con
 	}
 }
@@ -152,6 +182,22 @@ impl Socket {
 		})
 	}
 
+	/// Unix domain sockets have no IP addresses, so the connection info reports unspecified ones.
+	pub fn from_unix(stream: UnixStream) -> Self {
+		let unspecified = SocketAddr::from(([0, 0, 0, 0], 0));
+		let mut ext = Extension::new();
+		ext.insert(TCPConnectionInfo {
+			peer_addr: unspecified,
+			local_addr: unspecified,
+			start: Instant::now(),
+		});
+		Socket {
+			ext,
+			inner: SocketType::Unix(stream),
+			metrics: Metrics::with_counter(),
+		}
+	}
+
 	pub fn from_tls(
 		mut ext: Extension,
 		metrics: Metrics,
@@ -171,6 +217,10 @@ impl Socket {
 				src_identity: None, // TODO
 				negotiated_alpn: ssl.alpn_protocol().map(Alpn::from),
 				server_name,
+				peer_common_name: ssl
+					.peer_certificates()
+					.and_then(|certs| certs.first())
+					.and_then(|cert| common_name(cert)),
 			}
 		};
 		ext.insert(info);
@@ -193,6 +243,14 @@ impl Socket {
 		}
 	}
 
+	/// Record that this upstream connection was just established, after `connect_duration`.
+	pub fn set_connect_duration(&mut self, connect_duration: Duration) {
+		self.ext.insert(UpstreamConnectionInfo {
+			established: Instant::now(),
+			connect_duration,
+		});
+	}
+
 	pub fn with_logging(&mut self, l: LoggingMode) {
 		self.metrics.logging = l;
 	}
@@ -212,6 +270,15 @@ impl Socket {
 	pub fn tcp(&self) -> &TCPConnectionInfo {
 		self.ext.get::<TCPConnectionInfo>().unwrap()
 	}
+
+	/// Override the client address, when the original one was reported by a proxy in front of us.
+	pub fn set_peer_addr(&mut self, peer_addr: SocketAddr) {
+		let info = TCPConnectionInfo {
+			peer_addr: to_canonical(peer_addr),
+			..self.tcp().clone()
+		};
+		self.ext.insert(info);
+	}
 	/// target_address returns the HBONE destination or the L4 destination
 	pub fn target_address(&self) -> SocketAddr {
 		if let Some(hci) = self.ext.get::<HBONEConnectionInfo>() {
@@ -234,6 +301,7 @@ impl Socket {
 
 pub enum SocketType {
 	Tcp(TcpStream),
+	Unix(UnixStream),
 	Tls(Box<TlsStream<Box<SocketType>>>),
 	Hbone(RWStream),
 	Memory(DuplexStream),
@@ -248,6 +316,7 @@ impl AsyncRead for SocketType {
 	) -> Poll<std::io::Result<()>> {
 		match self.get_mut() {
 			SocketType::Tcp(inner) => Pin::new(inner).poll_read(cx, buf),
+			SocketType::Unix(inner) => Pin::new(inner).poll_read(cx, buf),
 			SocketType::Tls(inner) => Pin::new(inner).poll_read(cx, buf),
 			SocketType::Hbone(inner) => Pin::new(inner).poll_read(cx, buf),
 			SocketType::Memory(inner) => Pin::new(inner).poll_read(cx, buf),
@@ -263,6 +332,7 @@ impl AsyncWrite for SocketType {
 	) -> Poll<Result<usize, std::io::Error>> {
 		match self.get_mut() {
 			SocketType::Tcp(inner) => Pin::new(inner).poll_write(cx, buf),
+			SocketType::Unix(inner) => Pin::new(inner).poll_write(cx, buf),
 			SocketType::Tls(inner) => Pin::new(inner).poll_write(cx, buf),
 			SocketType::Hbone(inner) => Pin::new(inner).poll_write(cx, buf),
 			SocketType::Memory(inner) => Pin::new(inner).poll_write(cx, buf),
@@ -273,6 +343,7 @@ impl AsyncWrite for SocketType {
 	fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), std::io::Error>> {
 		match self.get_mut() {
 			SocketType::Tcp(inner) => Pin::new(inner).poll_flush(cx),
+			SocketType::Unix(inner) => Pin::new(inner).poll_flush(cx),
 			SocketType::Tls(inner) => Pin::new(inner).poll_flush(cx),
 			SocketType::Hbone(inner) => Pin::new(inner).poll_flush(cx),
 			SocketType::Memory(inner) => Pin::new(inner).poll_flush(cx),
@@ -283,6 +354,7 @@ impl AsyncWrite for SocketType {
 	fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), std::io::Error>> {
 		match self.get_mut() {
 			SocketType::Tcp(inner) => Pin::new(inner).poll_shutdown(cx),
+			SocketType::Unix(inner) => Pin::new(inner).poll_shutdown(cx),
 			SocketType::Tls(inner) => Pin::new(inner).poll_shutdown(cx),
 			SocketType::Hbone(inner) => Pin::new(inner).poll_shutdown(cx),
 			SocketType::Memory(inner) => Pin::new(inner).poll_shutdown(cx),
@@ -297,6 +369,7 @@ impl AsyncWrite for SocketType {
 	) -> Poll<Result<usize, std::io::Error>> {
 		match self.get_mut() {
 			SocketType::Tcp(inner) => Pin::new(inner).poll_write_vectored(cx, bufs),
+			SocketType::Unix(inner) => Pin::new(inner).poll_write_vectored(cx, bufs),
 			SocketType::Tls(inner) => Pin::new(inner).poll_write_vectored(cx, bufs),
 			SocketType::Hbone(inner) => Pin::new(inner).poll_write_vectored(cx, bufs),
 			SocketType::Memory(inner) => Pin::new(inner).poll_write_vectored(cx, bufs),
@@ -307,6 +380,7 @@ impl AsyncWrite for SocketType {
 	fn is_write_vectored(&self) -> bool {
 		match &self {
 			SocketType::Tcp(inner) => inner.is_write_vectored(),
+			SocketType::Unix(inner) => inner.is_write_vectored(),
 			SocketType::Tls(inner) => inner.is_write_vectored(),
 			SocketType::Hbone(inner) => inner.is_write_vectored(),
 			SocketType::Memory(inner) => inner.is_write_vectored(),
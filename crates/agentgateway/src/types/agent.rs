@@ -1,11 +1,12 @@
 use std::cmp::Ordering;
-use std::collections::HashMap;
 use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Display;
 use std::io::Cursor;
 use std::marker::PhantomData;
 use std::net::{IpAddr, SocketAddr};
 use std::num::NonZeroU16;
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::{cmp, net};
 
@@ -17,6 +18,8 @@ use openapiv3::OpenAPI;
 use prometheus_client::encoding::EncodeLabelValue;
 use regex::Regex;
 use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
 use rustls::{ClientConfig, ServerConfig};
 use rustls_pemfile::Item;
 use secrecy::SecretString;
@@ -28,11 +31,12 @@ use crate::http::auth::BackendAuth;
 use crate::http::jwt::Jwt;
 use crate::http::localratelimit::RateLimit;
 use crate::http::{
-	HeaderName, HeaderValue, StatusCode, ext_authz, filters, remoteratelimit, retry, status, timeout,
-	uri,
+	HeaderName, HeaderValue, StatusCode, ext_authz, filters, loadbalancer, remoteratelimit, retry,
+	status, timeout, uri,
 };
 use crate::mcp::rbac::RuleSet;
 use crate::proxy::ProxyError;
+use crate::transport::proxy_protocol::ProxyProtocol;
 use crate::transport::tls;
 use crate::types::discovery::{NamespacedHostname, Service};
 use crate::types::proto;
@@ -43,8 +47,27 @@ use crate::*;
 #[serde(rename_all = "camelCase")]
 pub struct Bind {
 	pub key: BindName,
-	pub address: SocketAddr,
+	pub address: ListenerAddress,
 	pub listeners: ListenerSet,
+	pub proxy_protocol: Option<ProxyProtocol>,
+}
+
+/// ListenerAddress is where a bind accepts connections.
+#[derive(Debug, Clone, Hash, Eq, PartialEq, serde::Serialize)]
+#[serde(untagged)]
+pub enum ListenerAddress {
+	Tcp(SocketAddr),
+	/// A Unix domain socket path, typically used when running as a sidecar.
+	Unix(PathBuf),
+}
+
+impl Display for ListenerAddress {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			ListenerAddress::Tcp(addr) => write!(f, "{addr}"),
+			ListenerAddress::Unix(path) => write!(f, "unix:{}", path.display()),
+		}
+	}
 }
 
 pub type BindName = Strng;
@@ -63,6 +86,13 @@ pub struct Listener {
 	pub protocol: ListenerProtocol,
 	pub routes: RouteSet,
 	pub tcp_routes: TCPRouteSet,
+	/// Handles requests that match none of the routes, such as with a custom 404 response or a
+	/// catch-all backend.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub default_route: Option<Route>,
+	/// Log only some of the successful requests.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub log_sampling: Option<crate::telemetry::log::LogSampling>,
 }
 
 pub type GatewayName = Strng;
@@ -82,6 +112,87 @@ impl serde::Serialize for TLSConfig {
 	}
 }
 
+impl TLSConfig {
+	/// Build a server config that serves `default`, unless the SNI sent by the client matches one of
+	/// the hostnames in `sni`.
+	pub fn new(
+		default: CertifiedKey,
+		sni: Vec<(Vec<Strng>, CertifiedKey)>,
+		alpn_protocols: Vec<Vec<u8>>,
+	) -> anyhow::Result<TLSConfig> {
+		let mut by_hostname = Vec::new();
+		for (hostnames, cert) in sni {
+			if hostnames.is_empty() {
+				anyhow::bail!("SNI certificate must have at least one hostname");
+			}
+			let cert = Arc::new(cert);
+			// Hostnames are case-insensitive; both sides are lowercased for matching.
+			by_hostname.extend(
+				hostnames
+					.into_iter()
+					.map(|h| (strng::new(h.to_ascii_lowercase()), cert.clone())),
+			);
+		}
+		let resolver = SniResolver {
+			default: Arc::new(default),
+			by_hostname,
+		};
+		let mut sc = ServerConfig::builder_with_provider(tls::provider())
+			.with_protocol_versions(tls::ALL_TLS_VERSIONS)
+			.expect("server config must be valid")
+			.with_no_client_auth()
+			.with_cert_resolver(Arc::new(resolver));
+		sc.alpn_protocols = alpn_protocols;
+		Ok(TLSConfig {
+			config: Arc::new(sc),
+		})
+	}
+}
+
+/// SniResolver selects the server certificate based on the SNI sent by the client, using the same
+/// precedence as listener selection: an exact hostname, then the longest matching wildcard, then the
+/// default certificate.
+#[derive(Debug)]
+struct SniResolver {
+	default: Arc<CertifiedKey>,
+	by_hostname: Vec<(Strng, Arc<CertifiedKey>)>,
+}
+
+impl SniResolver {
+	fn resolve_sni(&self, sni: Option<&str>) -> Arc<CertifiedKey> {
+		let Some(sni) = sni.map(|s| s.to_ascii_lowercase()) else {
+			return self.default.clone();
+		};
+		if let Some((_, cert)) = self.by_hostname.iter().find(|(h, _)| h.as_str() == sni) {
+			return cert.clone();
+		}
+		self
+			.by_hostname
+			.iter()
+			.filter(|(h, _)| h.starts_with('*') && sni.ends_with(&h.as_str()[1..]))
+			.max_by_key(|(h, _)| h.len())
+			.map(|(_, cert)| cert.clone())
+			.unwrap_or_else(|| self.default.clone())
+	}
+}
+
+impl ResolvesServerCert for SniResolver {
+	fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+		Some(self.resolve_sni(client_hello.server_name()))
+	}
+}
+
+/// Parse a PEM encoded certificate chain and private key into a key that can be served.
+pub fn parse_certified_key(cert: &[u8], key: &[u8]) -> anyhow::Result<CertifiedKey> {
+	let cert_chain = parse_cert(cert)?;
+	let private_key = parse_key(key)?;
+	Ok(CertifiedKey::from_der(
+		cert_chain,
+		private_key,
+		&tls::provider(),
+	)?)
+}
+
 pub fn parse_cert(mut cert: &[u8]) -> Result<Vec<CertificateDer<'static>>, anyhow::Error> {
 	let mut reader = std::io::BufReader::new(Cursor::new(&mut cert));
 	let parsed: Result<Vec<_>, _> = rustls_pemfile::read_all(&mut reader).collect();
@@ -175,6 +286,10 @@ pub struct TCPRoute {
 	// Can be a wildcard. Not applicable for TCP, only for TLS
 	#[serde(default, skip_serializing_if = "Vec::is_empty")]
 	pub hostnames: Vec<Strng>,
+	// Protocols negotiated with ALPN that this route matches. Empty matches any protocol.
+	// Not applicable for TCP, only for TLS
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub alpn_protocols: Vec<Strng>,
 	// User facing name of the rule
 	#[serde(default, skip_serializing_if = "Option::is_none")]
 	pub rule_name: Option<RouteRuleName>,
@@ -182,6 +297,13 @@ pub struct TCPRoute {
 	pub backends: Vec<TCPRouteBackendReference>,
 }
 
+impl TCPRoute {
+	pub fn matches_alpn(&self, alpn: Option<&str>) -> bool {
+		self.alpn_protocols.is_empty()
+			|| alpn.is_some_and(|a| self.alpn_protocols.iter().any(|p| p.as_str() == a))
+	}
+}
+
 #[derive(Debug, Clone, serde::Serialize)]
 #[serde(rename_all = "camelCase")]
 #[cfg_attr(feature = "schema", derive(JsonSchema))]
@@ -219,6 +341,56 @@ pub struct MethodMatch {
 	pub method: Strng,
 }
 
+/// GrpcRouteMatch matches gRPC requests by service and method. It compiles down to a RouteMatch on
+/// the `/{service}/{method}` path.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+pub struct GrpcRouteMatch {
+	/// The fully qualified service name, such as `helloworld.Greeter`. If unset, any service matches.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub service: Option<Strng>,
+	/// The method name, such as `SayHello`. If unset, any method of the service matches.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub method: Option<Strng>,
+	/// gRPC metadata to match, which is sent as request headers.
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub headers: Vec<HeaderMatch>,
+}
+
+impl GrpcRouteMatch {
+	pub fn into_route_match(self) -> anyhow::Result<RouteMatch> {
+		for part in [&self.service, &self.method].into_iter().flatten() {
+			if part.is_empty() || part.contains('/') {
+				anyhow::bail!("invalid gRPC service or method {part:?}");
+			}
+		}
+		let path = match (&self.service, &self.method) {
+			(Some(service), Some(method)) => PathMatch::Exact(strng::format!("/{service}/{method}")),
+			(Some(service), None) => PathMatch::PathPrefix(strng::format!("/{service}")),
+			(None, Some(method)) => {
+				let re = format!("/[^/]+/{}", regex::escape(method));
+				PathMatch::Regex(regex::Regex::new(&re)?, re.len())
+			},
+			(None, None) => PathMatch::PathPrefix(strng::literal!("/")),
+		};
+		let mut headers = self.headers;
+		// Only match gRPC requests; this covers application/grpc, application/grpc+proto, etc.
+		headers.push(HeaderMatch {
+			name: http::header::CONTENT_TYPE,
+			value: HeaderValueMatch::Regex(regex::Regex::new(r"application/grpc([+;].*)?")?),
+		});
+		Ok(RouteMatch {
+			headers,
+			path,
+			method: Some(MethodMatch {
+				method: strng::literal!("POST"),
+			}),
+			query: vec![],
+		})
+	}
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "camelCase")]
 #[cfg_attr(feature = "schema", derive(JsonSchema))]
@@ -248,6 +420,10 @@ pub enum QueryValueMatch {
 		#[cfg_attr(feature = "schema", schemars(with = "String"))]
 		regex::Regex,
 	),
+	/// The parameter is set, with any value. A key without a value, such as `?beta`, is present.
+	Present,
+	/// The parameter is not set.
+	Absent,
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -292,6 +468,10 @@ pub enum RouteFilter {
 	DirectResponse(filters::DirectResponse),
 	#[serde(rename = "cors")]
 	CORS(http::cors::Cors),
+	ResponseBodyTransform(filters::ResponseBodyTransform),
+	DynamicHeader(filters::DynamicHeader),
+	GrpcWeb(http::grpcweb::GrpcWeb),
+	Compression(http::compression::Compression),
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -300,6 +480,10 @@ pub enum RouteFilter {
 pub struct TrafficPolicy {
 	pub timeout: timeout::Policy,
 	pub retry: Option<retry::Policy>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub load_balancer: Option<loadbalancer::Policy>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub canary: Option<http::canary::Canary>,
 }
 
 #[derive(Debug, Eq, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
@@ -475,6 +659,8 @@ pub type BackendName = Strng;
 #[cfg_attr(feature = "schema", derive(JsonSchema))]
 pub struct McpBackend {
 	pub targets: Vec<Arc<McpTarget>>,
+	#[serde(default)]
+	pub delimiter: McpDelimiter,
 }
 
 impl McpBackend {
@@ -485,6 +671,67 @@ impl McpBackend {
 			.find(|target| target.name.as_str() == name)
 			.cloned()
 	}
+
+	/// Returns a description of each name that would be ambiguous once tools are prefixed with their
+	/// target name. Only OpenAPI targets have a statically known tool list; other targets are only checked
+	/// for duplicate target names.
+	pub fn tool_name_collisions(&self) -> Vec<String> {
+		let mut collisions = vec![];
+		let mut seen_targets = HashSet::new();
+		for t in &self.targets {
+			if !seen_targets.insert(t.name.as_str()) {
+				collisions.push(format!("duplicate target name {}", t.name));
+			}
+		}
+		if self.targets.len() == 1 {
+			// A single target is not prefixed, so there is nothing to collide with.
+			return collisions;
+		}
+		let mut exposed: HashMap<String, &str> = HashMap::new();
+		for t in &self.targets {
+			let McpTargetSpec::OpenAPI(open) = &t.spec else {
+				continue;
+			};
+			let Ok(tools) = crate::mcp::openapi::parse_openapi_schema(&open.schema) else {
+				// Parse errors are reported when the target is connected
+				continue;
+			};
+			for (tool, _) in tools {
+				let name = self.delimiter.prefixed(&t.name, &tool.name);
+				if let Some(other) = exposed.insert(name.clone(), t.name.as_str()) {
+					collisions.push(format!(
+						"tool {name} is exposed by both target {other} and target {}",
+						t.name
+					));
+				}
+			}
+		}
+		collisions
+	}
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+pub enum McpDelimiter {
+	/// Tools are exposed as `{target}_{tool}`
+	#[default]
+	Underscore,
+	/// Tools are exposed as `{target}/{tool}`
+	Slash,
+}
+
+impl McpDelimiter {
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			McpDelimiter::Underscore => "_",
+			McpDelimiter::Slash => "/",
+		}
+	}
+
+	pub fn prefixed(&self, target: &str, name: &str) -> String {
+		format!("{target}{}{name}", self.as_str())
+	}
 }
 
 #[derive(Debug, Clone, serde::Serialize)]
@@ -542,6 +789,9 @@ pub struct OpenAPITarget {
 	#[serde(deserialize_with = "de_openapi")]
 	#[cfg_attr(feature = "schema", schemars(with = "serde_json::value::RawValue"))]
 	pub schema: Arc<OpenAPI>,
+	/// Pretty print JSON responses returned to the client.
+	#[serde(default)]
+	pub pretty_json: bool,
 }
 
 pub fn de_openapi<'a, D>(deserializer: D) -> Result<Arc<OpenAPI>, D::Error>
@@ -720,6 +970,10 @@ impl RouteSet {
 		rs
 	}
 
+	pub fn iter(&self) -> impl Iterator<Item = &Route> {
+		self.all.values()
+	}
+
 	pub fn get_hostname(&self, hnm: &HostnameMatch) -> impl Iterator<Item = (&Route, &RouteMatch)> {
 		self.inner.get(hnm).into_iter().flatten().flat_map(|rl| {
 			self
@@ -741,8 +995,8 @@ impl RouteSet {
 					let have_match = have.matches.get(existing.index).expect("corrupted state");
 
 					cmp::Ordering::reverse(Self::compare_route(
-						(m, &r.key),
-						(have_match, &existing.key),
+						(m, &r.key, idx),
+						(have_match, &existing.key, existing.index),
 					))
 				});
 				// TODO: replace old route
@@ -758,9 +1012,12 @@ impl RouteSet {
 		}
 	}
 
-	fn compare_route(a: (&RouteMatch, &RouteKey), b: (&RouteMatch, &RouteKey)) -> Ordering {
-		let (a, a_key) = a;
-		let (b, b_key) = b;
+	fn compare_route(
+		a: (&RouteMatch, &RouteKey, usize),
+		b: (&RouteMatch, &RouteKey, usize),
+	) -> Ordering {
+		let (a, a_key, a_index) = a;
+		let (b, b_key, b_index) = b;
 		// Compare RouteMatch according to Gateway API sorting requirements
 		// 1. Path match type (Exact > PathPrefix > Regex)
 		let path_rank1 = get_path_rank(&a.path);
@@ -792,8 +1049,9 @@ impl RouteSet {
 		if query_count1 != query_count2 {
 			return cmp::Ordering::reverse(query_count1.cmp(&query_count2));
 		}
-		// Finally, by order in the route list. This is the tie-breaker
-		a_key.cmp(b_key)
+		// Finally, by order in the route list, then by order within the route. Each match has a unique
+		// key and index, so no two matches compare equal.
+		a_key.cmp(b_key).then(a_index.cmp(&b_index))
 	}
 
 	pub fn contains(&self, key: &RouteKey) -> bool {
@@ -863,12 +1121,18 @@ impl TCPRouteSet {
 		rs
 	}
 
-	pub fn get_hostname(&self, hnm: &HostnameMatch) -> Option<&TCPRoute> {
+	pub fn iter(&self) -> impl Iterator<Item = &TCPRoute> {
+		self.all.values()
+	}
+
+	/// Returns the first route for the hostname that matches the negotiated ALPN protocol.
+	pub fn get_hostname(&self, hnm: &HostnameMatch, alpn: Option<&str>) -> Option<&TCPRoute> {
 		self
 			.inner
-			.get(hnm)
-			.and_then(|r| r.first())
-			.and_then(|rl| self.all.get(rl))
+			.get(hnm)?
+			.iter()
+			.filter_map(|rl| self.all.get(rl))
+			.find(|r| r.matches_alpn(alpn))
 	}
 
 	pub fn insert(&mut self, r: TCPRoute) {
@@ -879,8 +1143,13 @@ impl TCPRouteSet {
 			let mut v = self.inner.entry(hostname_match).or_default();
 			let to_insert = v.binary_search_by(|existing| {
 				let have = self.all.get(existing).expect("corrupted state");
-				// TODO: not sure that is right
-				Ordering::reverse(r.key.cmp(existing))
+				// Routes that match specific ALPN protocols take precedence over those matching any.
+				// TODO: not sure the key ordering is right
+				have
+					.alpn_protocols
+					.is_empty()
+					.cmp(&r.alpn_protocols.is_empty())
+					.then_with(|| Ordering::reverse(r.key.cmp(existing)))
 			});
 			// TODO: replace old route
 			let insert_idx = to_insert.unwrap_or_else(|pos| pos);
@@ -966,9 +1235,9 @@ fn get_path_rank(path: &PathMatch) -> i32 {
 	match path {
 		// Best match: exact
 		PathMatch::Exact(_) => 3,
-		// Prefix/Regex -- we will defer to the length
 		PathMatch::PathPrefix(_) => 2,
-		PathMatch::Regex(_, _) => 2,
+		// Regex is always the least specific; ties between regexes defer to the length
+		PathMatch::Regex(_, _) => 1,
 	}
 }
 
@@ -1025,6 +1294,10 @@ pub enum Policy {
 	// Supported targets: Backend; single policy allowed
 	#[serde(rename = "ai")]
 	AI(llm::Policy),
+	// Supported targets: Backend; single policy allowed
+	ConnectionPool(crate::client::ConnectionPool),
+	// Supported targets: Gateway < Listener, Backend; single policy allowed
+	HeaderFilter(http::headerfilter::HeaderFilter),
 
 	// Supported targets: Gateway < Route < RouteRule; single policy allowed
 	// Transformation(),
@@ -1035,10 +1308,14 @@ pub enum Policy {
 	// Supported targets: Gateway < Route < RouteRule; single policy allowed
 	RemoteRateLimit(remoteratelimit::RemoteRateLimit),
 	// Supported targets: Gateway < Route < RouteRule; single policy allowed
+	GlobalRateLimit(Vec<crate::http::globalratelimit::GlobalRateLimit>),
+	// Supported targets: Gateway < Route < RouteRule; single policy allowed
 	// ExtProc(),
 	// Supported targets: Gateway < Route < RouteRule; single policy allowed
 	JwtAuth(crate::http::jwt::Jwt),
 	// Supported targets: Gateway < Route < RouteRule; single policy allowed
+	Authorization(crate::http::authorization::Authorization),
+	// Supported targets: Gateway < Route < RouteRule; single policy allowed
 	// ExtProc(),
 	// Supported targets: Gateway < Route < RouteRule; single policy allowed
 	Transformation(crate::http::transformation_cel::Transformation),
@@ -1066,7 +1343,7 @@ pub struct McpAuthentication {
 
 impl McpAuthentication {
 	pub fn as_jwt(&self) -> anyhow::Result<http::jwt::LocalJwtConfig> {
-		Ok(http::jwt::LocalJwtConfig {
+		let provider = http::jwt::LocalJwtProvider {
 			issuer: self.issuer.clone(),
 			audiences: vec![self.audience.clone()],
 			jwks: FileInlineOrRemote::Remote {
@@ -1080,7 +1357,10 @@ impl McpAuthentication {
 					// Some(McpIDP::Keycloak { realm }) => format!("{}/realms/{realm}/protocol/openid-connect/certs", self.issuer).parse()?,
 				},
 			},
-		})
+			algorithms: vec![],
+			claim_headers: vec![],
+		};
+		Ok(http::jwt::LocalJwtConfig::Single(provider))
 	}
 }
 
@@ -5,7 +5,8 @@ use std::fmt::Display;
 use std::io::Cursor;
 use std::marker::PhantomData;
 use std::net::{IpAddr, SocketAddr};
-use std::num::NonZeroU16;
+use std::num::{NonZeroU16, NonZeroU64};
+use std::path::PathBuf;
 use std::str::FromStr;
 use std::sync::Arc;
 use std::{cmp, net};
@@ -30,9 +31,12 @@ use crate::http::auth::BackendAuth;
 use crate::http::jwt::Jwt;
 use crate::http::localratelimit::RateLimit;
 use crate::http::{
-	HeaderName, HeaderValue, StatusCode, filters, localratelimit, retry, status, timeout, uri,
+	HeaderName, HeaderValue, StatusCode, authorization, backendtls, canary, compression, ext_authz,
+	filters, grpcweb, headerfilter, loadbalancer, localratelimit, retry, status, timeout, uri,
 };
 use crate::mcp::rbac::RuleSet;
+use crate::telemetry::log::LogSampling;
+use crate::transport::proxy_protocol::ProxyProtocol;
 use crate::transport::tls;
 use crate::types::agent::Backend::Opaque;
 use crate::types::discovery::NamespacedHostname;
@@ -46,18 +50,17 @@ impl TryFrom<&proto::agent::TlsConfig> for TLSConfig {
 	type Error = anyhow::Error;
 
 	fn try_from(value: &proto::agent::TlsConfig) -> Result<Self, Self::Error> {
-		let cert_chain = parse_cert(&value.cert)?;
-		let private_key = parse_key(&value.private_key)?;
-		let mut sc = ServerConfig::builder_with_provider(transport::tls::provider())
-			.with_protocol_versions(transport::tls::ALL_TLS_VERSIONS)
-			.expect("server config must be valid")
-			.with_no_client_auth()
-			.with_single_cert(cert_chain, private_key)?;
+		let default = parse_certified_key(&value.cert, &value.private_key)?;
+		let sni = value
+			.sni_certificates
+			.iter()
+			.map(|c| {
+				let cert = parse_certified_key(&c.cert, &c.private_key)?;
+				Ok((c.hostnames.iter().map(strng::new).collect(), cert))
+			})
+			.collect::<anyhow::Result<Vec<_>>>()?;
 		// TODO: support h2
-		sc.alpn_protocols = vec![b"http/1.1".into()];
-		Ok(TLSConfig {
-			config: Arc::new(sc),
-		})
+		TLSConfig::new(default, sni, vec![b"http/1.1".into()])
 	}
 }
 
@@ -71,17 +74,8 @@ impl TryFrom<&proto::agent::RouteBackend> for RouteBackendReference {
 				BackendReference::Backend(backend_key.into())
 			},
 			Some(proto::agent::route_backend::Kind::Service(svc_key)) => {
-				let ns = match svc_key.split_once('/') {
-					Some((namespace, hostname)) => Ok(NamespacedHostname {
-						namespace: namespace.into(),
-						hostname: hostname.into(),
-					}),
-					None => Err(ProtoError::NamespacedHostnameParse(svc_key.clone())),
-				}?;
-				BackendReference::Service {
-					name: ns,
-					port: s.port as u16,
-				}
+				let (name, port) = parse_namespaced_service(svc_key, s.port, "route backend")?;
+				BackendReference::Service { name, port }
 			},
 		};
 		let filters = s
@@ -106,14 +100,149 @@ impl TryFrom<proto::agent::TrafficPolicy> for TrafficPolicy {
 			.backend_request_timeout
 			.map(|v| v.try_into())
 			.transpose()?;
+		let idle = s.idle_timeout.map(|v| v.try_into()).transpose()?;
+		let header_override = s
+			.timeout_header_override
+			.map(crate::http::timeout::HeaderOverride::try_from)
+			.transpose()?;
+		let retry = s.retry.map(retry::Policy::try_from).transpose()?;
+		let load_balancer = s
+			.load_balancer
+			.map(loadbalancer::Policy::try_from)
+			.transpose()?;
+		let canary = s.canary.map(canary::Canary::try_from).transpose()?;
 
 		Ok(Self {
 			timeout: crate::http::timeout::Policy {
 				request_timeout: req,
 				backend_request_timeout: backend,
+				idle_timeout: idle,
+				header_override,
 			},
-			// TODO: pass in XDS
-			retry: None,
+			retry,
+			load_balancer,
+			canary,
+		})
+	}
+}
+
+impl TryFrom<proto::agent::TimeoutHeaderOverride> for crate::http::timeout::HeaderOverride {
+	type Error = ProtoError;
+
+	fn try_from(s: proto::agent::TimeoutHeaderOverride) -> Result<Self, Self::Error> {
+		let max = s.max.ok_or(ProtoError::MissingRequiredField)?.try_into()?;
+		let trusted_sources = s
+			.trusted_sources
+			.iter()
+			.map(|cidr| {
+				cidr
+					.parse()
+					.map_err(|e| ProtoError::Generic(format!("invalid trusted source {cidr:?}: {e}")))
+			})
+			.collect::<Result<_, _>>()?;
+		Ok(Self {
+			max,
+			trusted_sources,
+			trusted_roles: s.trusted_roles,
+		})
+	}
+}
+
+impl TryFrom<proto::agent::LoadBalancer> for loadbalancer::Policy {
+	type Error = ProtoError;
+
+	fn try_from(s: proto::agent::LoadBalancer) -> Result<Self, Self::Error> {
+		use proto::agent::load_balancer::{Algorithm, HashOn};
+		let algorithm = match Algorithm::try_from(s.algorithm)? {
+			Algorithm::RingHash => loadbalancer::Algorithm::RingHash,
+			Algorithm::Maglev => loadbalancer::Algorithm::Maglev,
+		};
+		let hash_on = match s.hash_on {
+			Some(HashOn::Header(h)) => {
+				loadbalancer::HashOn::Header(HeaderName::from_bytes(h.as_bytes())?)
+			},
+			Some(HashOn::Cookie(c)) => loadbalancer::HashOn::Cookie(c.into()),
+			Some(HashOn::SourceIp(_)) => loadbalancer::HashOn::SourceIp,
+			None => return Err(ProtoError::MissingRequiredField),
+		};
+		Ok(loadbalancer::Policy::new(algorithm, hash_on))
+	}
+}
+
+impl TryFrom<proto::agent::Canary> for canary::Canary {
+	type Error = ProtoError;
+
+	fn try_from(s: proto::agent::Canary) -> Result<Self, Self::Error> {
+		use proto::agent::canary::StickyKey;
+		if !(0.0..=100.0).contains(&s.percentage) {
+			return Err(ProtoError::Generic(format!(
+				"invalid canary percentage {}",
+				s.percentage
+			)));
+		}
+		let sticky_key = match s.sticky_key {
+			Some(StickyKey::Header(h)) => Some(loadbalancer::HashOn::Header(HeaderName::from_bytes(
+				h.as_bytes(),
+			)?)),
+			Some(StickyKey::Cookie(c)) => Some(loadbalancer::HashOn::Cookie(c.into())),
+			Some(StickyKey::SourceIp(_)) => Some(loadbalancer::HashOn::SourceIp),
+			None => None,
+		};
+		Ok(canary::Canary {
+			backend: s.backend.into(),
+			percentage: s.percentage,
+			sticky_key,
+			cookie_name: default_as_none(s.cookie_name)
+				.map(Strng::from)
+				.unwrap_or_else(canary::default_cookie_name),
+		})
+	}
+}
+
+impl TryFrom<proto::agent::Retry> for retry::Policy {
+	type Error = ProtoError;
+
+	fn try_from(s: proto::agent::Retry) -> Result<Self, Self::Error> {
+		let codes = s
+			.codes
+			.iter()
+			.map(|c| {
+				u16::try_from(*c)
+					.ok()
+					.and_then(|c| StatusCode::from_u16(c).ok())
+					.ok_or_else(|| ProtoError::Generic(format!("invalid status code {c}")))
+			})
+			.collect::<Result<Vec<_>, _>>()?;
+		// Unset budget fields take the same defaults as the local configuration.
+		let budget = s
+			.budget
+			.map(|b| -> Result<_, ProtoError> {
+				let window = match b.window {
+					Some(w) => w.try_into()?,
+					None => retry::budget::default_window(),
+				};
+				Ok(retry::Budget::new(
+					b.percent.unwrap_or_else(retry::budget::default_percent),
+					b.min_retries
+						.unwrap_or_else(retry::budget::default_min_retries),
+					window,
+				))
+			})
+			.transpose()?;
+		let attempts = u8::try_from(s.attempts)
+			.ok()
+			.and_then(std::num::NonZeroU8::new)
+			.ok_or_else(|| {
+				ProtoError::Generic(format!(
+					"invalid retry attempts {}: must be between 1 and 255",
+					s.attempts
+				))
+			})?;
+		Ok(retry::Policy {
+			attempts,
+			backoff: s.backoff.map(|v| v.try_into()).transpose()?,
+			codes: codes.into_boxed_slice(),
+			budget,
 		})
 	}
 }
@@ -158,8 +287,31 @@ impl TryFrom<&proto::agent::Bind> for Bind {
 	fn try_from(s: &proto::agent::Bind) -> Result<Self, Self::Error> {
 		Ok(Self {
 			key: s.key.clone().into(),
-			address: SocketAddr::from((IpAddr::from([0, 0, 0, 0]), s.port as u16)),
+			address: if s.unix_path.is_empty() {
+				ListenerAddress::Tcp(SocketAddr::from((
+					IpAddr::from([0, 0, 0, 0]),
+					s.port as u16,
+				)))
+			} else {
+				ListenerAddress::Unix(PathBuf::from(&s.unix_path))
+			},
 			listeners: Default::default(),
+			proxy_protocol: s
+				.proxy_protocol
+				.as_ref()
+				.map(|pp| {
+					let trusted_sources = pp
+						.trusted_sources
+						.iter()
+						.map(|cidr| {
+							cidr
+								.parse()
+								.map_err(|e| ProtoError::Generic(format!("invalid trusted source {cidr:?}: {e}")))
+						})
+						.collect::<Result<_, _>>()?;
+					Ok::<_, ProtoError>(ProxyProtocol { trusted_sources })
+				})
+				.transpose()?,
 		})
 	}
 }
@@ -171,6 +323,55 @@ impl TryFrom<&proto::agent::Listener> for (Listener, BindName) {
 		let proto = proto::agent::Protocol::try_from(s.protocol)?;
 		let protocol = ListenerProtocol::try_from((proto, s.tls.as_ref()))
 			.map_err(|e| ProtoError::Generic(format!("{e}")))?;
+		let default_route = match (&s.default_response, &s.default_backend) {
+			(None, None) => None,
+			(Some(_), Some(_)) => {
+				return Err(ProtoError::Generic(
+					"only one of default_response and default_backend may be set".to_string(),
+				));
+			},
+			(response, backend) => {
+				let filters = response
+					.as_ref()
+					.map(|dr| {
+						let status = u16::try_from(dr.status)
+							.ok()
+							.and_then(|c| StatusCode::from_u16(c).ok())
+							.ok_or_else(|| ProtoError::Generic(format!("invalid status code {}", dr.status)))?;
+						Ok::<_, ProtoError>(RouteFilter::DirectResponse(filters::DirectResponse {
+							body: Bytes::copy_from_slice(&dr.body),
+							status,
+						}))
+					})
+					.transpose()?;
+				let backends = backend
+					.as_ref()
+					.map(RouteBackendReference::try_from)
+					.transpose()?;
+				Some(Route {
+					key: strng::format!("{}/$default", s.key),
+					route_name: strng::new("default"),
+					rule_name: None,
+					hostnames: vec![],
+					matches: vec![],
+					filters: filters.into_iter().collect(),
+					backends: backends.into_iter().collect(),
+					policies: None,
+				})
+			},
+		};
+		let log_sampling = s
+			.log_sampling
+			.as_ref()
+			.map(|ls| {
+				let rate = NonZeroU64::new(ls.rate)
+					.ok_or_else(|| ProtoError::Generic("log sampling rate must be positive".to_string()))?;
+				Ok::<_, ProtoError>(LogSampling::new(
+					rate,
+					ls.slow_threshold.map(TryInto::try_into).transpose()?,
+				))
+			})
+			.transpose()?;
 		let l = Listener {
 			key: strng::new(&s.key),
 			name: strng::new(&s.name),
@@ -179,6 +380,8 @@ impl TryFrom<&proto::agent::Listener> for (Listener, BindName) {
 			gateway_name: strng::new(&s.gateway_name),
 			routes: Default::default(),
 			tcp_routes: Default::default(),
+			default_route,
+			log_sampling,
 		};
 		Ok((l, strng::new(&s.bind_key)))
 	}
@@ -211,10 +414,64 @@ impl TryFrom<&proto::agent::Route> for (Route, ListenerKey) {
 				.collect::<Result<Vec<_>, _>>()?,
 			policies: s.traffic_policy.map(TrafficPolicy::try_from).transpose()?,
 		};
+		if let Some(canary) = r.policies.as_ref().and_then(|p| p.canary.as_ref()) {
+			canary
+				.validate(&r.backends)
+				.map_err(|e| ProtoError::Generic(e.to_string()))?;
+		}
 		Ok((r, strng::new(&s.listener_key)))
 	}
 }
 
+impl TryFrom<&proto::agent::AiBackend> for llm::AIBackend {
+	type Error = ProtoError;
+
+	fn try_from(s: &proto::agent::AiBackend) -> Result<Self, Self::Error> {
+		use proto::agent::ai_backend::Provider as P;
+		let model = |m: &str| (!m.is_empty()).then(|| strng::new(m));
+		let provider = match &s.provider {
+			Some(P::Openai(p)) => llm::AIProvider::OpenAI(llm::openai::Provider {
+				model: model(&p.model),
+			}),
+			Some(P::Gemini(p)) => llm::AIProvider::Gemini(llm::gemini::Provider {
+				model: model(&p.model),
+			}),
+			Some(P::Vertex(p)) => {
+				if p.region.is_empty() {
+					return Err(ProtoError::Generic("vertex region is required".to_string()));
+				}
+				let provider = llm::vertex::Provider::new(
+					model(&p.model),
+					Some(strng::new(&p.region)),
+					strng::new(&p.project_id),
+				)
+				.map_err(|e| ProtoError::Generic(e.to_string()))?;
+				llm::AIProvider::Vertex(provider)
+			},
+			Some(P::Anthropic(p)) => llm::AIProvider::Anthropic(llm::anthropic::Provider {
+				model: model(&p.model),
+			}),
+			Some(P::Bedrock(p)) => llm::AIProvider::Bedrock(llm::bedrock::Provider {
+				model: strng::new(&p.model),
+				region: strng::new(&p.region),
+			}),
+			None => return Err(ProtoError::MissingRequiredField),
+		};
+		let host_override = s
+			.r#override
+			.as_ref()
+			.map(|o| {
+				Target::try_from((o.host.as_str(), o.port as u16))
+					.map_err(|e| ProtoError::Generic(e.to_string()))
+			})
+			.transpose()?;
+		Ok(llm::AIBackend {
+			provider,
+			host_override,
+		})
+	}
+}
+
 impl TryFrom<&proto::agent::Backend> for Backend {
 	type Error = ProtoError;
 
@@ -226,21 +483,25 @@ impl TryFrom<&proto::agent::Backend> for Backend {
 				Target::try_from((s.host.as_str(), s.port as u16))
 					.map_err(|e| ProtoError::Generic(e.to_string()))?,
 			),
-			Some(proto::agent::backend::Kind::Ai(a)) => {
-				return Err(ProtoError::Generic(
-					"AI backend is not currently supported".to_string(),
-				));
-			},
-			Some(proto::agent::backend::Kind::Mcp(m)) => Backend::MCP(
-				name,
-				McpBackend {
+			Some(proto::agent::backend::Kind::Ai(a)) => Backend::AI(name, llm::AIBackend::try_from(a)?),
+			Some(proto::agent::backend::Kind::Mcp(m)) => {
+				let delimiter = match proto::agent::mcp_backend::Delimiter::try_from(m.delimiter)? {
+					proto::agent::mcp_backend::Delimiter::Underscore => McpDelimiter::Underscore,
+					proto::agent::mcp_backend::Delimiter::Slash => McpDelimiter::Slash,
+				};
+				let mcp = McpBackend {
 					targets: m
 						.targets
 						.iter()
 						.map(|t| McpTarget::try_from(t).map(Arc::new))
 						.collect::<Result<Vec<_>, _>>()?,
-				},
-			),
+					delimiter,
+				};
+				for collision in mcp.tool_name_collisions() {
+					warn!("mcp backend {name}: {collision}");
+				}
+				Backend::MCP(name, mcp)
+			},
 			_ => {
 				return Err(ProtoError::Generic("unknown backend".to_string()));
 			},
@@ -259,30 +520,30 @@ impl TryFrom<&proto::agent::McpTarget> for McpTarget {
 				SimpleBackendReference::Backend(name.into())
 			},
 			Some(proto::agent::mcp_target::Kind::Service(svc_key)) => {
-				let ns = match svc_key.split_once('/') {
-					Some((namespace, hostname)) => Ok(NamespacedHostname {
-						namespace: namespace.into(),
-						hostname: hostname.into(),
-					}),
-					None => Err(ProtoError::NamespacedHostnameParse(svc_key.clone())),
-				}?;
-				SimpleBackendReference::Service {
-					name: ns,
-					port: s.port as u16,
-				}
+				let (name, port) = parse_namespaced_service(svc_key, s.port, "MCP target")?;
+				SimpleBackendReference::Service { name, port }
 			},
 		};
+		let path = match default_as_none(s.path.as_str()) {
+			Some(p) if !p.starts_with('/') => {
+				return Err(ProtoError::Generic(format!(
+					"mcp target {} path must start with '/': {p}",
+					s.name
+				)));
+			},
+			p => p,
+		};
 		Ok(Self {
 			name: strng::new(&s.name),
 			spec: match proto {
 				Protocol::Sse => McpTargetSpec::Sse(SseTargetSpec {
 					backend,
-					path: "/sse".to_string(),
+					path: path.unwrap_or("/sse").to_string(),
 				}),
 				Protocol::Undefined | Protocol::StreamableHttp => {
 					McpTargetSpec::Mcp(StreamableHTTPTargetSpec {
 						backend,
-						path: "/mcp".to_string(),
+						path: path.unwrap_or("/mcp").to_string(),
 					})
 				},
 			},
@@ -343,8 +604,31 @@ impl TryFrom<&proto::agent::RouteMatch> for RouteMatch {
 					name: strng::new(&h.name),
 					value: QueryValueMatch::Regex(regex::Regex::new(e)?),
 				}),
+				Some(proto::agent::query_match::Value::Present(present)) => Ok(QueryMatch {
+					name: strng::new(&h.name),
+					value: if *present {
+						QueryValueMatch::Present
+					} else {
+						QueryValueMatch::Absent
+					},
+				}),
 			})
 			.collect::<Result<Vec<_>, _>>()?;
+		if let Some(g) = &s.grpc {
+			if s.path.is_some() || s.method.is_some() || !query.is_empty() {
+				return Err(ProtoError::Generic(
+					"grpc match cannot be combined with path, method, or query matches".to_string(),
+				));
+			}
+			let non_empty = |v: &str| (!v.is_empty()).then(|| strng::new(v));
+			return GrpcRouteMatch {
+				service: non_empty(&g.service),
+				method: non_empty(&g.method),
+				headers,
+			}
+			.into_route_match()
+			.map_err(|e| ProtoError::Generic(e.to_string()));
+		}
 		Ok(Self {
 			headers,
 			path,
@@ -434,20 +718,55 @@ impl TryFrom<&proto::agent::RouteFilter> for RouteFilter {
 					backend: match &m.kind {
 						None => SimpleBackendReference::Invalid,
 						Some(proto::agent::request_mirror::Kind::Service(svc_key)) => {
-							let ns = match svc_key.split_once('/') {
-								Some((namespace, hostname)) => Ok(NamespacedHostname {
-									namespace: namespace.into(),
-									hostname: hostname.into(),
-								}),
-								None => Err(ProtoError::NamespacedHostnameParse(svc_key.clone())),
-							}?;
-							SimpleBackendReference::Service {
-								name: ns,
-								port: m.port as u16,
-							}
+							let (name, port) = parse_namespaced_service(svc_key, m.port, "request mirror")?;
+							SimpleBackendReference::Service { name, port }
 						},
 					},
 					percentage: m.percentage / 100.0,
+					compare: m.compare,
+				})
+			},
+			Some(proto::agent::route_filter::Kind::ResponseBodyTransform(t)) => {
+				RouteFilter::ResponseBodyTransform(filters::ResponseBodyTransform {
+					remove: t.remove.iter().map(strng::new).collect(),
+					mask: t.mask.iter().map(strng::new).collect(),
+					mask_value: default_as_none(t.mask_value.as_str()).map(strng::new),
+					max_body_size: match t.max_body_size {
+						0 => filters::default_max_body_size(),
+						n => n as usize,
+					},
+				})
+			},
+			Some(proto::agent::route_filter::Kind::DynamicHeader(dh)) => {
+				RouteFilter::DynamicHeader(filters::DynamicHeader {
+					set: dh
+						.set
+						.iter()
+						.map(|h| {
+							let template = h
+								.value
+								.parse()
+								.map_err(|e| ProtoError::Generic(format!("{e}")))?;
+							Ok((strng::new(&h.name), template))
+						})
+						.collect::<Result<Vec<_>, ProtoError>>()?,
+				})
+			},
+			Some(proto::agent::route_filter::Kind::GrpcWeb(_)) => {
+				RouteFilter::GrpcWeb(grpcweb::GrpcWeb {})
+			},
+			Some(proto::agent::route_filter::Kind::Compression(c)) => {
+				RouteFilter::Compression(compression::Compression {
+					min_size: match c.min_size {
+						0 => 1024,
+						n => n as usize,
+					},
+					content_types: c.content_types.iter().map(strng::new).collect(),
+					decompress_requests: c.decompress_requests,
+					max_decompressed_size: match c.max_decompressed_size {
+						0 => compression::default_max_decompressed_size(),
+						n => n as usize,
+					},
 				})
 			},
 		})
@@ -492,11 +811,100 @@ impl TryFrom<&proto::agent::Policy> for TargetedPolicy {
 							Type::Request => localratelimit::RateLimitType::Requests,
 							Type::Token => localratelimit::RateLimitType::Tokens,
 						},
+						key: Default::default(),
 					}
 					.try_into()
 					.map_err(|e| ProtoError::Generic(format!("invalid rate limit: {e}")))?,
 				])
 			},
+			Some(proto::agent::policy_spec::Kind::ConnectionPool(cp)) => {
+				Policy::ConnectionPool(client::ConnectionPool {
+					max_idle_per_host: default_as_none(cp.max_idle_per_host as usize),
+					max_connections: default_as_none(cp.max_connections as usize),
+					idle_timeout: cp.idle_timeout.map(TryInto::try_into).transpose()?,
+					connect_timeout: cp.connect_timeout.map(TryInto::try_into).transpose()?,
+					max_connections_timeout: cp
+						.max_connections_timeout
+						.map(TryInto::try_into)
+						.transpose()?,
+					..Default::default()
+				})
+			},
+			Some(proto::agent::policy_spec::Kind::BackendTls(btls)) => {
+				use proto::agent::policy_spec::backend_tls::Verification;
+				let verification = Verification::try_from(btls.verification)?;
+				let client_cert = match (
+					default_as_none(btls.cert.clone()),
+					default_as_none(btls.private_key.clone()),
+				) {
+					(Some(cert), Some(key)) => Some((cert, key)),
+					_ => None,
+				};
+				Policy::BackendTLS(
+					backendtls::BackendTLSConfig {
+						root: default_as_none(btls.root.clone()),
+						client_cert,
+						insecure: verification == Verification::Insecure,
+						insecure_host: verification == Verification::InsecureHost,
+						hostname: default_as_none(btls.hostname.as_str()).map(Strng::from),
+					}
+					.build()
+					.map_err(|e| ProtoError::Generic(format!("invalid backend TLS: {e}")))?,
+				)
+			},
+			Some(proto::agent::policy_spec::Kind::Authorization(authz)) => {
+				Policy::Authorization(authorization::Authorization {
+					rules: authz
+						.rules
+						.iter()
+						.map(|r| authorization::Rule {
+							roles: r.roles.clone(),
+							claims: r.claims.clone().into_iter().collect(),
+						})
+						.collect(),
+				})
+			},
+			Some(proto::agent::policy_spec::Kind::ExtAuthz(ea)) => {
+				use proto::agent::policy_spec::ext_authz::Protocol;
+				let protocol = match Protocol::try_from(ea.protocol)? {
+					Protocol::Grpc => ext_authz::Protocol::Grpc,
+					Protocol::Http => ext_authz::Protocol::Http(ext_authz::HttpProtocol {
+						path_prefix: default_as_none(ea.path_prefix.clone()),
+						allowed_upstream_headers: ea
+							.allowed_upstream_headers
+							.iter()
+							.map(|h| HeaderName::from_bytes(h.as_bytes()))
+							.collect::<Result<_, _>>()?,
+					}),
+				};
+				Policy::ExtAuthz(ext_authz::ExtAuthz {
+					target: Target::try_from((ea.host.as_str(), ea.port as u16))
+						.map_err(|e| ProtoError::Generic(e.to_string()))?,
+					context: default_as_none(ea.context.clone()),
+					protocol,
+				})
+			},
+			Some(proto::agent::policy_spec::Kind::HeaderFilter(hf)) => {
+				let rules = |r: &Option<proto::agent::policy_spec::header_filter::Rules>| {
+					let Some(r) = r else {
+						return Ok(headerfilter::HeaderRules::default());
+					};
+					let parse = |v: &[String]| {
+						v.iter()
+							.map(|p| p.parse())
+							.collect::<Result<Vec<_>, _>>()
+							.map_err(|e| ProtoError::Generic(format!("invalid header pattern: {e}")))
+					};
+					Ok(headerfilter::HeaderRules {
+						allow: parse(&r.allow)?,
+						deny: parse(&r.deny)?,
+					})
+				};
+				Policy::HeaderFilter(headerfilter::HeaderFilter {
+					request: rules(&hf.request)?,
+					response: rules(&hf.response)?,
+				})
+			},
 			_ => return Err(ProtoError::EnumParse("unknown spec kind".to_string())),
 		};
 		Ok(TargetedPolicy {
@@ -506,3 +914,38 @@ impl TryFrom<&proto::agent::Policy> for TargetedPolicy {
 		})
 	}
 }
+
+/// Parse a `namespace/hostname` service key and its port. `field` names where the reference is used,
+/// so a misconfigured resource can be found from the error.
+fn parse_namespaced_service(
+	key: &str,
+	port: u32,
+	field: &'static str,
+) -> Result<(NamespacedHostname, u16), ProtoError> {
+	let invalid = |reason: String| ProtoError::InvalidServiceReference {
+		field,
+		key: key.to_string(),
+		reason,
+	};
+	let Some((namespace, hostname)) = key.split_once('/') else {
+		return Err(invalid("expected namespace/hostname".to_string()));
+	};
+	if namespace.is_empty() {
+		return Err(invalid("namespace is empty".to_string()));
+	}
+	if hostname.is_empty() {
+		return Err(invalid("hostname is empty".to_string()));
+	}
+	let port = u16::try_from(port).map_err(|_| invalid(format!("port {port} is out of range")))?;
+	Ok((
+		NamespacedHostname {
+			namespace: namespace.into(),
+			hostname: hostname.into(),
+		},
+		port,
+	))
+}
+
+#[cfg(test)]
+#[path = "agent_xds_tests.rs"]
+mod tests;
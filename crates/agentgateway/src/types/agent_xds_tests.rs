@@ -0,0 +1,375 @@
+use assert_matches::assert_matches;
+
+use super::*;
+
+fn mcp_target(protocol: Protocol, path: &str) -> proto::agent::McpTarget {
+	proto::agent::McpTarget {
+		name: "target".to_string(),
+		kind: Some(Kind::Service("ns/svc.example.com".to_string())),
+		port: 8080,
+		protocol: protocol as i32,
+		path: path.to_string(),
+	}
+}
+
+#[test]
+fn test_mcp_target_default_path() {
+	let t = McpTarget::try_from(&mcp_target(Protocol::Sse, "")).unwrap();
+	assert_matches!(t.spec, McpTargetSpec::Sse(s) if s.path == "/sse");
+	let t = McpTarget::try_from(&mcp_target(Protocol::StreamableHttp, "")).unwrap();
+	assert_matches!(t.spec, McpTargetSpec::Mcp(s) if s.path == "/mcp");
+	let t = McpTarget::try_from(&mcp_target(Protocol::Undefined, "")).unwrap();
+	assert_matches!(t.spec, McpTargetSpec::Mcp(s) if s.path == "/mcp");
+}
+
+#[test]
+fn test_mcp_target_custom_path() {
+	let t = McpTarget::try_from(&mcp_target(Protocol::Sse, "/v1/events")).unwrap();
+	assert_matches!(t.spec, McpTargetSpec::Sse(s) if s.path == "/v1/events");
+	let t = McpTarget::try_from(&mcp_target(Protocol::StreamableHttp, "/api/mcp")).unwrap();
+	assert_matches!(t.spec, McpTargetSpec::Mcp(s) if s.path == "/api/mcp");
+}
+
+#[test]
+fn test_mcp_target_invalid_path() {
+	assert_matches!(
+		McpTarget::try_from(&mcp_target(Protocol::Sse, "sse")),
+		Err(ProtoError::Generic(_))
+	);
+	assert_matches!(
+		McpTarget::try_from(&mcp_target(Protocol::StreamableHttp, "mcp")),
+		Err(ProtoError::Generic(_))
+	);
+}
+
+#[test]
+fn test_parse_namespaced_service() {
+	let (name, port) = parse_namespaced_service("ns/svc.example.com", 8080, "route backend").unwrap();
+	assert_eq!(name.namespace, "ns");
+	assert_eq!(name.hostname, "svc.example.com");
+	assert_eq!(port, 8080);
+
+	for (key, port, reason) in [
+		("svc.example.com", 80, "expected namespace/hostname"),
+		("/svc.example.com", 80, "namespace is empty"),
+		("ns/", 80, "hostname is empty"),
+		("ns/svc.example.com", 70000, "port 70000 is out of range"),
+	] {
+		let err = parse_namespaced_service(key, port, "MCP target").unwrap_err();
+		assert_matches!(
+			&err,
+			ProtoError::InvalidServiceReference { field: "MCP target", key: k, reason: r }
+				if k == key && r == reason
+		);
+	}
+}
+
+#[test]
+fn test_mcp_target_invalid_service() {
+	let mut t = mcp_target(Protocol::Sse, "");
+	t.kind = Some(Kind::Service("svc.example.com".to_string()));
+	assert_eq!(
+		McpTarget::try_from(&t).unwrap_err().to_string(),
+		"invalid service reference \"svc.example.com\" in MCP target: expected namespace/hostname"
+	);
+}
+
+fn self_signed(hostname: &str) -> rcgen::CertifiedKey<rcgen::KeyPair> {
+	rcgen::generate_simple_self_signed(vec![hostname.to_string()]).unwrap()
+}
+
+// Perform a TLS handshake with the given SNI and return the certificate the server presented.
+async fn served_cert(
+	tls_config: &TLSConfig,
+	roots: &rustls::RootCertStore,
+	sni: &str,
+) -> CertificateDer<'static> {
+	let client_config = ClientConfig::builder_with_provider(tls::provider())
+		.with_protocol_versions(tls::ALL_TLS_VERSIONS)
+		.unwrap()
+		.with_root_certificates(roots.clone())
+		.with_no_client_auth();
+	let (client, server) = tokio::io::duplex(64 * 1024);
+	let acceptor = tokio_rustls::TlsAcceptor::from(tls_config.config.clone());
+	let server = tokio::spawn(async move { acceptor.accept(server).await.map(|_| ()) });
+	let connector = tokio_rustls::TlsConnector::from(Arc::new(client_config));
+	let server_name = rustls::pki_types::ServerName::try_from(sni.to_string()).unwrap();
+	let stream = connector.connect(server_name, client).await.unwrap();
+	server.await.unwrap().unwrap();
+	stream.get_ref().1.peer_certificates().unwrap()[0].clone()
+}
+
+#[tokio::test]
+async fn test_tls_sni_certificates() {
+	let default = self_signed("default.example.com");
+	let a = self_signed("a.example.com");
+	let b = self_signed("*.b.example.com");
+	let tls_config = TLSConfig::try_from(&proto::agent::TlsConfig {
+		cert: default.cert.pem().into_bytes(),
+		private_key: default.signing_key.serialize_pem().into_bytes(),
+		sni_certificates: vec![
+			proto::agent::SniCertificate {
+				hostnames: vec!["A.Example.com".to_string()],
+				cert: a.cert.pem().into_bytes(),
+				private_key: a.signing_key.serialize_pem().into_bytes(),
+			},
+			proto::agent::SniCertificate {
+				hostnames: vec!["*.B.example.com".to_string()],
+				cert: b.cert.pem().into_bytes(),
+				private_key: b.signing_key.serialize_pem().into_bytes(),
+			},
+		],
+	})
+	.unwrap();
+
+	let mut roots = rustls::RootCertStore::empty();
+	for c in [&default, &a, &b] {
+		roots.add(c.cert.der().clone()).unwrap();
+	}
+	assert_eq!(
+		&served_cert(&tls_config, &roots, "a.example.com").await,
+		a.cert.der()
+	);
+	assert_eq!(
+		&served_cert(&tls_config, &roots, "api.b.example.com").await,
+		b.cert.der()
+	);
+	// SNI matching is case-insensitive
+	assert_eq!(
+		&served_cert(&tls_config, &roots, "API.b.Example.com").await,
+		b.cert.der()
+	);
+	assert_eq!(
+		&served_cert(&tls_config, &roots, "default.example.com").await,
+		default.cert.der()
+	);
+}
+
+#[test]
+fn test_tls_sni_certificate_requires_hostname() {
+	let cert = self_signed("a.example.com");
+	let pem = cert.cert.pem().into_bytes();
+	let key = cert.signing_key.serialize_pem().into_bytes();
+	let err = TLSConfig::try_from(&proto::agent::TlsConfig {
+		cert: pem.clone(),
+		private_key: key.clone(),
+		sni_certificates: vec![proto::agent::SniCertificate {
+			hostnames: vec![],
+			cert: pem,
+			private_key: key,
+		}],
+	})
+	.unwrap_err();
+	assert_eq!(
+		err.to_string(),
+		"SNI certificate must have at least one hostname"
+	);
+}
+
+#[test]
+fn test_listener_default() {
+	let listener = |default_response, default_backend| proto::agent::Listener {
+		key: "listener".to_string(),
+		bind_key: "bind".to_string(),
+		protocol: proto::agent::Protocol::Http as i32,
+		default_response,
+		default_backend,
+		..Default::default()
+	};
+	let response = proto::agent::DirectResponse {
+		status: 404,
+		body: b"not found".to_vec(),
+	};
+	let backend = proto::agent::RouteBackend {
+		kind: Some(proto::agent::route_backend::Kind::Backend(
+			"ns/fallback".to_string(),
+		)),
+		weight: 1,
+		..Default::default()
+	};
+
+	let (l, _) = <(Listener, BindName)>::try_from(&listener(None, None)).unwrap();
+	assert!(l.default_route.is_none());
+
+	let (l, _) = <(Listener, BindName)>::try_from(&listener(Some(response.clone()), None)).unwrap();
+	let route = l.default_route.unwrap();
+	assert_eq!(route.key.as_str(), "listener/$default");
+	assert!(route.backends.is_empty());
+	assert_matches!(
+		route.filters.as_slice(),
+		[RouteFilter::DirectResponse(dr)] if dr.status == StatusCode::NOT_FOUND && dr.body.as_ref() == b"not found"
+	);
+
+	let (l, _) = <(Listener, BindName)>::try_from(&listener(None, Some(backend.clone()))).unwrap();
+	let route = l.default_route.unwrap();
+	assert!(route.filters.is_empty());
+	assert_matches!(
+		route.backends.as_slice(),
+		[RouteBackendReference { backend: BackendReference::Backend(b), .. }] if b == "ns/fallback"
+	);
+
+	assert_matches!(
+		<(Listener, BindName)>::try_from(&listener(Some(response), Some(backend))),
+		Err(ProtoError::Generic(_))
+	);
+
+	// A status that does not fit in a u16 is rejected, rather than truncated to a valid one
+	let response = proto::agent::DirectResponse {
+		status: 65536 + 404,
+		body: vec![],
+	};
+	assert_matches!(
+		<(Listener, BindName)>::try_from(&listener(Some(response), None)),
+		Err(ProtoError::Generic(_))
+	);
+}
+
+fn vertex_backend(region: &str, project_id: &str) -> proto::agent::AiBackend {
+	proto::agent::AiBackend {
+		provider: Some(proto::agent::ai_backend::Provider::Vertex(
+			proto::agent::ai_backend::Vertex {
+				model: "gemini-2.0-flash".to_string(),
+				region: region.to_string(),
+				project_id: project_id.to_string(),
+			},
+		)),
+		..Default::default()
+	}
+}
+
+#[test]
+fn test_vertex_backend() {
+	let b = llm::AIBackend::try_from(&vertex_backend("us-central1", "my-project")).unwrap();
+	assert_matches!(b.provider, llm::AIProvider::Vertex(p) if p.endpoint().unwrap() ==
+		"https://us-central1-aiplatform.googleapis.com/v1beta1/projects/my-project/locations/us-central1/endpoints/openapi/chat/completions");
+	let b = llm::AIBackend::try_from(&vertex_backend("global", "my-project")).unwrap();
+	assert_matches!(b.provider, llm::AIProvider::Vertex(p) if p.endpoint().unwrap() ==
+		"https://aiplatform.googleapis.com/v1beta1/projects/my-project/locations/global/endpoints/openapi/chat/completions");
+}
+
+#[test]
+fn test_vertex_backend_missing_project() {
+	let err = llm::AIBackend::try_from(&vertex_backend("us-central1", "")).unwrap_err();
+	assert_matches!(err, ProtoError::Generic(e) if e.contains("projectId is required"));
+	let err = llm::AIBackend::try_from(&vertex_backend("us-central1", "my/project")).unwrap_err();
+	assert_matches!(err, ProtoError::Generic(e) if e.contains("projectId"));
+}
+
+#[test]
+fn test_vertex_backend_bad_region() {
+	let err = llm::AIBackend::try_from(&vertex_backend("", "my-project")).unwrap_err();
+	assert_matches!(err, ProtoError::Generic(e) if e.contains("region is required"));
+	let err = llm::AIBackend::try_from(&vertex_backend("us-nowhere1", "my-project")).unwrap_err();
+	assert_matches!(err, ProtoError::Generic(e) if e.contains("not a known region"));
+}
+
+#[test]
+fn test_authorization_policy() {
+	let p = proto::agent::Policy {
+		name: "ns/authz".to_string(),
+		target: Some(proto::agent::PolicyTarget {
+			kind: Some(proto::agent::policy_target::Kind::Route(
+				"ns/route".to_string(),
+			)),
+		}),
+		spec: Some(proto::agent::PolicySpec {
+			kind: Some(proto::agent::policy_spec::Kind::Authorization(
+				proto::agent::policy_spec::Authorization {
+					rules: vec![proto::agent::policy_spec::authorization::Rule {
+						roles: vec!["admin".to_string()],
+						claims: [("org.team".to_string(), "platform".to_string())].into(),
+					}],
+				},
+			)),
+		}),
+	};
+	let p = TargetedPolicy::try_from(&p).unwrap();
+	assert_matches!(p.target, PolicyTarget::Route(_));
+	assert_matches!(p.policy, Policy::Authorization(a) if a.rules.len() == 1
+		&& a.rules[0].roles == vec!["admin".to_string()]
+		&& a.rules[0].claims.get("org.team").map(String::as_str) == Some("platform"));
+}
+
+#[test]
+fn test_ext_authz_policy() {
+	use proto::agent::policy_spec::ext_authz::Protocol;
+	let p = proto::agent::Policy {
+		name: "ns/authz".to_string(),
+		target: Some(proto::agent::PolicyTarget {
+			kind: Some(proto::agent::policy_target::Kind::Gateway(
+				"ns/gw".to_string(),
+			)),
+		}),
+		spec: Some(proto::agent::PolicySpec {
+			kind: Some(proto::agent::policy_spec::Kind::ExtAuthz(
+				proto::agent::policy_spec::ExtAuthz {
+					host: "authz.example.com".to_string(),
+					port: 8080,
+					protocol: Protocol::Http as i32,
+					path_prefix: "/check".to_string(),
+					allowed_upstream_headers: vec!["x-user".to_string()],
+					..Default::default()
+				},
+			)),
+		}),
+	};
+	let p = TargetedPolicy::try_from(&p).unwrap();
+	assert_matches!(p.policy, Policy::ExtAuthz(ea) => {
+		assert_eq!(ea.target.to_string(), "authz.example.com:8080");
+		assert_eq!(ea.context, None);
+		assert_matches!(ea.protocol, ext_authz::Protocol::Http(h) => {
+			assert_eq!(h.path_prefix.as_deref(), Some("/check"));
+			assert_eq!(h.allowed_upstream_headers, vec![HeaderName::from_static("x-user")]);
+		});
+	});
+}
+
+#[test]
+fn test_retry() {
+	let proto_retry =
+		|attempts: u32, budget: Option<proto::agent::RetryBudget>| proto::agent::Retry {
+			attempts,
+			backoff: None,
+			codes: vec![503],
+			budget,
+		};
+
+	let p = retry::Policy::try_from(proto_retry(3, None)).unwrap();
+	assert_eq!(p.attempts.get(), 3);
+	assert_eq!(p.codes.as_ref(), &[StatusCode::SERVICE_UNAVAILABLE]);
+	assert!(p.budget.is_none());
+
+	// An empty budget takes the local defaults rather than disabling retries
+	let p = retry::Policy::try_from(proto_retry(3, Some(Default::default()))).unwrap();
+	let b = p.budget.unwrap();
+	assert_eq!(b.percent, 20.0);
+	assert_eq!(b.min_retries, 3);
+	assert_eq!(b.window, Duration::from_secs(10));
+
+	let p = retry::Policy::try_from(proto_retry(
+		3,
+		Some(proto::agent::RetryBudget {
+			percent: Some(0.0),
+			min_retries: Some(1),
+			window: None,
+		}),
+	))
+	.unwrap();
+	let b = p.budget.unwrap();
+	assert_eq!(b.percent, 0.0);
+	assert_eq!(b.min_retries, 1);
+
+	for attempts in [0, 256] {
+		assert_matches!(
+			retry::Policy::try_from(proto_retry(attempts, None)),
+			Err(ProtoError::Generic(_))
+		);
+	}
+	assert_eq!(
+		retry::Policy::try_from(proto_retry(255, None))
+			.unwrap()
+			.attempts
+			.get(),
+		255
+	);
+}
@@ -12,26 +12,28 @@ use jsonwebtoken::jwk::{AlgorithmParameters, JwkSet, KeyAlgorithm};
 use jsonwebtoken::{DecodingKey, Validation};
 use openapiv3::OpenAPI;
 use rmcp::handler::server::router::tool::CallToolHandlerExt;
-use rustls::{ClientConfig, ServerConfig};
+use rustls::ClientConfig;
 use serde::de::DeserializeOwned;
 use serde_with::{TryFromInto, serde_as};
 
 use crate::http::auth::BackendAuth;
 use crate::http::backendtls::{BackendTLS, LocalBackendTLS};
 use crate::http::jwt::{JwkError, Jwt};
-use crate::http::{filters, retry, timeout};
+use crate::http::{compression, filters, grpcweb, loadbalancer, retry, timeout};
 use crate::llm::AIProvider;
 use crate::store::LocalWorkload;
+use crate::transport::proxy_protocol::ProxyProtocol;
 use crate::transport::tls;
 use crate::types::agent::PolicyTarget::RouteRule;
 use crate::types::agent::{
-	A2aPolicy, Backend, BackendName, BackendReference, Bind, BindName, GatewayName, Listener,
-	ListenerKey, ListenerProtocol, ListenerSet, McpAuthentication, McpAuthorization, McpBackend,
-	McpTarget, McpTargetName, McpTargetSpec, OpenAPITarget, PathMatch, Policy, PolicyTarget, Route,
-	RouteBackend, RouteBackendReference, RouteFilter, RouteMatch, RouteName, RouteRuleName, RouteSet,
-	SimpleBackend, SimpleBackendReference, SseTargetSpec, StreamableHTTPTargetSpec, TCPRoute,
+	A2aPolicy, Backend, BackendName, BackendReference, Bind, BindName, GatewayName, GrpcRouteMatch,
+	Listener, ListenerAddress, ListenerKey, ListenerProtocol, ListenerSet, McpAuthentication,
+	McpAuthorization, McpBackend, McpDelimiter, McpTarget, McpTargetName, McpTargetSpec,
+	OpenAPITarget, PathMatch, Policy, PolicyTarget, Route, RouteBackend, RouteBackendReference,
+	RouteFilter, RouteKey, RouteMatch, RouteName, RouteRuleName, RouteSet, SimpleBackend,
+	SimpleBackendReference, SseTargetSpec, StreamableHTTPTargetSpec, TCPRoute,
 	TCPRouteBackendReference, TCPRouteSet, TLSConfig, Target, TargetedPolicy, TrafficPolicy,
-	parse_cert, parse_key,
+	parse_certified_key,
 };
 use crate::types::discovery::{NamespacedHostname, Service};
 use crate::*;
@@ -80,6 +82,10 @@ pub struct LocalConfig {
 struct LocalBind {
 	port: u16,
 	listeners: Vec<LocalListener>,
+	/// Read a PROXY protocol header from trusted peers, such as an L4 load balancer, to learn the
+	/// original client address.
+	#[serde(default)]
+	proxy_protocol: Option<ProxyProtocol>,
 }
 
 #[derive(Debug, Clone, serde::Deserialize)]
@@ -97,6 +103,18 @@ struct LocalListener {
 	tls: Option<LocalTLSServerConfig>,
 	routes: Option<Vec<LocalRoute>>,
 	tcp_routes: Option<Vec<LocalTCPRoute>>,
+	/// Respond directly to requests that match none of the routes, for example with a custom 404 page.
+	#[serde(default)]
+	default_response: Option<filters::DirectResponse>,
+	/// Send requests that match none of the routes to this backend.
+	#[serde(default)]
+	default_backend: Option<LocalRouteBackend>,
+	/// Log only some of the successful requests. Failed and slow requests are always logged.
+	#[serde(default)]
+	log_sampling: Option<crate::telemetry::log::LogSampling>,
+	/// Remove headers from every request the listener accepts, and from every response it returns.
+	#[serde(default)]
+	header_filter: Option<http::headerfilter::HeaderFilter>,
 }
 
 #[derive(Debug, Clone, Default, serde::Deserialize)]
@@ -118,6 +136,20 @@ enum LocalListenerProtocol {
 struct LocalTLSServerConfig {
 	cert: PathBuf,
 	key: PathBuf,
+	/// Additional certificates, selected by the SNI sent by the client. Connections that match none of
+	/// them are served `cert`.
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	sni: Vec<LocalSNICertificate>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+struct LocalSNICertificate {
+	/// Hostnames to serve this certificate for. May be a wildcard, such as `*.example.com`.
+	hostnames: Vec<Strng>,
+	cert: PathBuf,
+	key: PathBuf,
 }
 
 #[derive(Debug, Clone, serde::Deserialize)]
@@ -134,7 +166,7 @@ struct LocalRoute {
 	#[serde(default, skip_serializing_if = "Vec::is_empty")]
 	hostnames: Vec<Strng>,
 	#[serde(default = "default_matches")]
-	matches: Vec<RouteMatch>,
+	matches: Vec<LocalRouteMatch>,
 	#[serde(default, skip_serializing_if = "Option::is_none")]
 	policies: Option<FilterOrPolicy>,
 	#[serde(default, skip_serializing_if = "Vec::is_empty")]
@@ -208,12 +240,17 @@ impl LocalBackend {
 							})
 						},
 						LocalMcpTargetSpec::Stdio { cmd, args, env } => McpTargetSpec::Stdio { cmd, args, env },
-						LocalMcpTargetSpec::OpenAPI { backend, schema } => {
+						LocalMcpTargetSpec::OpenAPI {
+							backend,
+							schema,
+							pretty_json,
+						} => {
 							let (bref, be) = to_simple_backend_and_ref(name.clone(), &backend);
 							be.into_iter().for_each(|b| backends.push(b));
 							McpTargetSpec::OpenAPI(OpenAPITarget {
 								backend: bref,
 								schema,
+								pretty_json,
 							})
 						},
 					};
@@ -223,7 +260,13 @@ impl LocalBackend {
 					};
 					targets.push(Arc::new(t));
 				}
-				let m = McpBackend { targets };
+				let m = McpBackend {
+					targets,
+					delimiter: tgt.delimiter,
+				};
+				for collision in m.tool_name_collisions() {
+					warn!("mcp backend {name}: {collision}");
+				}
 				backends.push(Backend::MCP(name, m));
 				backends
 			},
@@ -238,6 +281,9 @@ impl LocalBackend {
 #[cfg_attr(feature = "schema", derive(JsonSchema))]
 pub struct LocalMcpBackend {
 	pub targets: Vec<Arc<LocalMcpTarget>>,
+	/// Delimiter between the target name and the tool name when multiple targets are configured.
+	#[serde(default)]
+	pub delimiter: McpDelimiter,
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -302,16 +348,60 @@ pub enum LocalMcpTargetSpec {
 		#[serde(deserialize_with = "types::agent::de_openapi")]
 		#[cfg_attr(feature = "schema", schemars(with = "serde_json::value::RawValue"))]
 		schema: Arc<OpenAPI>,
+		/// Pretty print JSON responses returned to the client.
+		#[serde(default)]
+		pretty_json: bool,
 	},
 }
 
-fn default_matches() -> Vec<RouteMatch> {
-	vec![RouteMatch {
+fn default_matches() -> Vec<LocalRouteMatch> {
+	vec![LocalRouteMatch::Http(RouteMatch {
 		headers: vec![],
 		path: PathMatch::PathPrefix("/".into()),
 		method: None,
 		query: vec![],
-	}]
+	})]
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(untagged)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+enum LocalRouteMatch {
+	Grpc(LocalGrpcRouteMatch),
+	Http(RouteMatch),
+}
+
+impl<'de> serde::Deserialize<'de> for LocalRouteMatch {
+	fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+	where
+		D: serde::Deserializer<'de>,
+	{
+		// An untagged enum only reports that no variant matched, so pick the variant by the `grpc` key
+		// and surface that variant's error.
+		let v = serde_json::Value::deserialize(deserializer)?;
+		let res = if v.get("grpc").is_some() {
+			serde_json::from_value(v).map(LocalRouteMatch::Grpc)
+		} else {
+			serde_json::from_value(v).map(LocalRouteMatch::Http)
+		};
+		res.map_err(serde::de::Error::custom)
+	}
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+struct LocalGrpcRouteMatch {
+	grpc: GrpcRouteMatch,
+}
+
+impl LocalRouteMatch {
+	fn into_route_match(self) -> anyhow::Result<RouteMatch> {
+		match self {
+			LocalRouteMatch::Grpc(g) => g.grpc.into_route_match(),
+			LocalRouteMatch::Http(m) => Ok(m),
+		}
+	}
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -327,6 +417,10 @@ struct LocalTCPRoute {
 	/// Can be a wildcard
 	#[serde(default, skip_serializing_if = "Vec::is_empty")]
 	hostnames: Vec<Strng>,
+	/// Protocols negotiated with ALPN, such as `h2`, that this route matches. Only applies to TLS
+	/// listeners. If empty, any protocol matches.
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	alpn_protocols: Vec<Strng>,
 	#[serde(default, skip_serializing_if = "Option::is_none")]
 	policies: Option<TCPFilterOrPolicy>,
 	#[serde(default, skip_serializing_if = "Vec::is_empty")]
@@ -379,6 +473,18 @@ struct FilterOrPolicy {
 	#[serde(default)]
 	response_header_modifier: Option<filters::HeaderModifier>,
 
+	/// Headers to be set in the request, from values such as the client IP or JWT claims.
+	#[serde(default)]
+	dynamic_header: Option<filters::DynamicHeader>,
+
+	/// Translate gRPC-Web requests from browsers into gRPC for the backend.
+	#[serde(default)]
+	grpc_web: Option<grpcweb::GrpcWeb>,
+
+	/// Compress responses with gzip, for clients that accept it.
+	#[serde(default)]
+	compression: Option<compression::Compression>,
+
 	/// Directly respond to the request with a redirect.
 	#[serde(default)]
 	request_redirect: Option<filters::RequestRedirect>,
@@ -399,6 +505,10 @@ struct FilterOrPolicy {
 	#[serde(default)]
 	cors: Option<http::cors::Cors>,
 
+	/// Remove or mask fields in JSON responses.
+	#[serde(default)]
+	response_body_transform: Option<filters::ResponseBodyTransform>,
+
 	// Policy
 	/// Authorization policies for MCP access.
 	#[serde(default)]
@@ -419,6 +529,12 @@ struct FilterOrPolicy {
 	/// Authenticate to the backend.
 	#[serde(default)]
 	backend_auth: Option<BackendAuth>,
+	/// Configure how connections to the backend are pooled.
+	#[serde(default)]
+	connection_pool: Option<client::ConnectionPool>,
+	/// Remove headers from requests sent to the backend and from its responses.
+	#[serde(default)]
+	header_filter: Option<http::headerfilter::HeaderFilter>,
 	/// Rate limit incoming requests. State is kept local.
 	#[serde(default)]
 	#[cfg_attr(feature = "schema", schemars(with = "serde_json::value::RawValue"))]
@@ -427,9 +543,16 @@ struct FilterOrPolicy {
 	#[serde(default)]
 	#[cfg_attr(feature = "schema", schemars(with = "serde_json::value::RawValue"))]
 	remote_rate_limit: Option<crate::http::remoteratelimit::RemoteRateLimit>,
+	/// Rate limit incoming requests. State is shared between instances through Redis.
+	#[serde(default)]
+	#[cfg_attr(feature = "schema", schemars(with = "serde_json::value::RawValue"))]
+	global_rate_limit: Vec<crate::http::globalratelimit::GlobalRateLimit>,
 	/// Authenticate incoming JWT requests.
 	#[serde(default)]
 	jwt_auth: Option<crate::http::jwt::LocalJwtConfig>,
+	/// Require requests to be authenticated with a JWT carrying the configured roles or claims.
+	#[serde(default)]
+	authorization: Option<crate::http::authorization::Authorization>,
 	/// Authenticate incoming requests by calling an external authorization server.
 	#[serde(default)]
 	#[cfg_attr(feature = "schema", schemars(with = "serde_json::value::RawValue"))]
@@ -455,6 +578,12 @@ struct FilterOrPolicy {
 	/// Retry matching requests.
 	#[serde(default)]
 	retry: Option<retry::Policy>,
+	/// Pick backends by hashing a request key, so the same client keeps reaching the same backend.
+	#[serde(default)]
+	load_balancer: Option<loadbalancer::Policy>,
+	/// Send a percentage of users to a canary backend, keeping each user on their assigned variant.
+	#[serde(default)]
+	canary: Option<http::canary::Canary>,
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -486,8 +615,9 @@ async fn convert(client: client::Client, i: LocalConfig) -> anyhow::Result<Norma
 		}
 		let b = Bind {
 			key: bind_name,
-			address: SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), b.port),
+			address: ListenerAddress::Tcp(SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), b.port)),
 			listeners: ls,
+			proxy_protocol: b.proxy_protocol,
 		};
 		all_binds.push(b)
 	}
@@ -514,6 +644,10 @@ async fn convert_listener(
 		tls,
 		routes,
 		tcp_routes,
+		default_response,
+		default_backend,
+		log_sampling,
+		header_filter,
 	} = l;
 
 	let protocol = match protocol {
@@ -535,8 +669,9 @@ async fn convert_listener(
 			if tcp_routes.is_none() {
 				bail!("protocol TLS requires 'tcpRoutes'")
 			}
-			ListenerProtocol::TLS(convert_tls_server(
+			ListenerProtocol::TLS(convert_tls_server_with_alpn(
 				tls.ok_or(anyhow!("TLS listener requires 'tls'"))?,
+				tcp_route_alpn(tcp_routes.iter().flatten()),
 			)?)
 		},
 		LocalListenerProtocol::TCP => {
@@ -557,9 +692,18 @@ async fn convert_listener(
 	let mut all_policies = vec![];
 	let mut all_backends = vec![];
 
+	if let Some(hf) = header_filter {
+		all_policies.push(TargetedPolicy {
+			name: format!("{key}/header-filter").into(),
+			target: PolicyTarget::Listener(key.clone()),
+			policy: Policy::HeaderFilter(hf),
+		});
+	}
+
 	let mut rs = RouteSet::default();
 	for (idx, l) in routes.into_iter().flatten().enumerate() {
-		let (route, policies, backends) = convert_route(client.clone(), l, idx, key.clone()).await?;
+		let (route, policies, backends) =
+			convert_route(client.clone(), l, idx, key.clone(), None).await?;
 		all_policies.extend_from_slice(&policies);
 		all_backends.extend_from_slice(&backends);
 		rs.insert(route)
@@ -567,11 +711,38 @@ async fn convert_listener(
 
 	let mut trs = TCPRouteSet::default();
 	for (idx, l) in tcp_routes.into_iter().flatten().enumerate() {
-		let (route, policies) = convert_tcp_route(l, idx, key.clone()).await?;
+		let (route, policies, backends) = convert_tcp_route(l, idx, key.clone()).await?;
 		all_policies.extend_from_slice(&policies);
+		all_backends.extend_from_slice(&backends);
 		trs.insert(route)
 	}
 
+	let default_route = match (default_response, default_backend) {
+		(None, None) => None,
+		(Some(_), Some(_)) => bail!("only 'defaultResponse' or 'defaultBackend' may be set"),
+		(response, backend) => {
+			let lr = LocalRoute {
+				route_name: Some(strng::new("default")),
+				rule_name: None,
+				hostnames: vec![],
+				matches: vec![],
+				policies: None,
+				backends: backend.into_iter().collect(),
+			};
+			// User route keys always have a rule segment, so this key cannot collide with one, even for a
+			// route named "default".
+			let default_key = strng::format!("{key}/$default");
+			let (mut route, policies, backends) =
+				convert_route(client.clone(), lr, 0, key.clone(), Some(default_key)).await?;
+			route
+				.filters
+				.extend(response.map(RouteFilter::DirectResponse));
+			all_policies.extend_from_slice(&policies);
+			all_backends.extend_from_slice(&backends);
+			Some(route)
+		},
+	};
+
 	let l = Listener {
 		key,
 		name,
@@ -580,6 +751,8 @@ async fn convert_listener(
 		protocol,
 		routes: rs,
 		tcp_routes: trs,
+		default_route,
+		log_sampling,
 	};
 	Ok((l, all_policies, all_backends))
 }
@@ -589,6 +762,7 @@ async fn convert_route(
 	lr: LocalRoute,
 	idx: usize,
 	listener_key: ListenerKey,
+	route_key: Option<RouteKey>,
 ) -> anyhow::Result<(Route, Vec<TargetedPolicy>, Vec<Backend>)> {
 	let LocalRoute {
 		route_name,
@@ -600,12 +774,18 @@ async fn convert_route(
 	} = lr;
 
 	let route_name = route_name.unwrap_or_else(|| strng::format!("route{}", idx));
-	let key = strng::format!(
-		"{}/{}/{}",
-		listener_key,
-		route_name,
-		rule_name.clone().unwrap_or_else(|| strng::new("default"))
-	);
+	let matches = matches
+		.into_iter()
+		.map(LocalRouteMatch::into_route_match)
+		.collect::<anyhow::Result<Vec<_>>>()?;
+	let key = route_key.unwrap_or_else(|| {
+		strng::format!(
+			"{}/{}/{}",
+			listener_key,
+			route_name,
+			rule_name.clone().unwrap_or_else(|| strng::new("default"))
+		)
+	});
 	let mut filters = vec![];
 	let mut traffic_policy: Option<TrafficPolicy> = None;
 	let mut external_policies = vec![];
@@ -658,29 +838,41 @@ async fn convert_route(
 	let mut traffic_policy = TrafficPolicy {
 		timeout: timeout::Policy::default(),
 		retry: None,
+		load_balancer: None,
+		canary: None,
 	};
 	if let Some(pol) = policies {
 		let FilterOrPolicy {
 			request_header_modifier,
 			response_header_modifier,
+			dynamic_header,
+			grpc_web,
+			compression,
 			request_redirect,
 			url_rewrite,
 			request_mirror,
 			direct_response,
 			cors,
+			response_body_transform,
 			mcp_authorization,
 			mcp_authentication,
 			a2a,
 			ai,
 			backend_tls,
 			backend_auth,
+			connection_pool,
+			header_filter,
 			local_rate_limit,
 			remote_rate_limit,
+			global_rate_limit,
 			jwt_auth,
+			authorization,
 			transformations,
 			ext_authz,
 			timeout,
 			retry,
+			load_balancer,
+			canary,
 		} = pol;
 		if let Some(p) = request_header_modifier {
 			filters.push(RouteFilter::RequestHeaderModifier(p));
@@ -688,6 +880,15 @@ async fn convert_route(
 		if let Some(p) = response_header_modifier {
 			filters.push(RouteFilter::ResponseHeaderModifier(p));
 		}
+		if let Some(p) = dynamic_header {
+			filters.push(RouteFilter::DynamicHeader(p));
+		}
+		if let Some(p) = grpc_web {
+			filters.push(RouteFilter::GrpcWeb(p));
+		}
+		if let Some(p) = compression {
+			filters.push(RouteFilter::Compression(p));
+		}
 		if let Some(p) = request_redirect {
 			filters.push(RouteFilter::RequestRedirect(p));
 		}
@@ -699,6 +900,7 @@ async fn convert_route(
 			let pol = filters::RequestMirror {
 				backend: bref,
 				percentage: p.percentage,
+				compare: p.compare,
 			};
 			backend
 				.into_iter()
@@ -711,6 +913,9 @@ async fn convert_route(
 		if let Some(p) = cors {
 			filters.push(RouteFilter::CORS(p));
 		}
+		if let Some(p) = response_body_transform {
+			filters.push(RouteFilter::ResponseBodyTransform(p));
+		}
 
 		if let Some(p) = mcp_authorization {
 			external_policies.push(backend_tgt(Policy::McpAuthorization(p))?)
@@ -732,9 +937,18 @@ async fn convert_route(
 		if let Some(p) = backend_auth {
 			external_policies.push(backend_tgt(Policy::BackendAuth(p))?)
 		}
+		if let Some(p) = connection_pool {
+			external_policies.push(backend_tgt(Policy::ConnectionPool(p))?)
+		}
+		if let Some(p) = header_filter {
+			external_policies.push(backend_tgt(Policy::HeaderFilter(p))?)
+		}
 		if let Some(p) = jwt_auth {
 			external_policies.push(tgt(Policy::JwtAuth(p.try_into(client.clone()).await?)))
 		}
+		if let Some(p) = authorization {
+			external_policies.push(tgt(Policy::Authorization(p)))
+		}
 		if let Some(p) = transformations {
 			external_policies.push(tgt(Policy::Transformation(p)))
 		}
@@ -747,6 +961,9 @@ async fn convert_route(
 		if let Some(p) = remote_rate_limit {
 			external_policies.push(tgt(Policy::RemoteRateLimit(p)))
 		}
+		if !global_rate_limit.is_empty() {
+			external_policies.push(tgt(Policy::GlobalRateLimit(global_rate_limit)))
+		}
 
 		if let Some(p) = timeout {
 			traffic_policy.timeout = p;
@@ -754,6 +971,13 @@ async fn convert_route(
 		if let Some(p) = retry {
 			traffic_policy.retry = Some(p);
 		}
+		if let Some(p) = load_balancer {
+			traffic_policy.load_balancer = Some(p);
+		}
+		if let Some(p) = canary {
+			p.validate(&refs)?;
+			traffic_policy.canary = Some(p);
+		}
 	}
 	let route = Route {
 		key,
@@ -772,11 +996,12 @@ async fn convert_tcp_route(
 	lr: LocalTCPRoute,
 	idx: usize,
 	listener_key: ListenerKey,
-) -> anyhow::Result<(TCPRoute, Vec<TargetedPolicy>)> {
+) -> anyhow::Result<(TCPRoute, Vec<TargetedPolicy>, Vec<Backend>)> {
 	let LocalTCPRoute {
 		route_name,
 		rule_name,
 		hostnames,
+		alpn_protocols,
 		policies,
 		backends,
 	} = lr;
@@ -799,23 +1024,23 @@ async fn convert_tcp_route(
 			policy: p,
 		}
 	};
+	let (refs, external_backends): (Vec<_>, Vec<Option<Backend>>) = backends
+		.into_iter()
+		.map(|b| {
+			let (bref, backend) = to_simple_backend_and_ref(key.clone(), &b.backend);
+			let bref = TCPRouteBackendReference {
+				weight: b.weight,
+				backend: bref,
+			};
+			(bref, backend)
+		})
+		.unzip();
+	let external_backends = external_backends.into_iter().flatten().collect_vec();
 	let mut be_pol = 0;
 	let mut backend_tgt = |p: Policy| {
-		if backends.len() != 1 {
+		if refs.len() != 1 {
 			anyhow::bail!("backend policies currently only work with exactly 1 backend")
 		}
-
-		let (refs, to_add): (Vec<_>, Vec<Option<Backend>>) = backends
-			.into_iter()
-			.map(|b| {
-				let (bref, backend) = to_simple_backend_and_ref(key.clone(), &b.backend);
-				let bref = TCPRouteBackendReference {
-					weight: b.weight,
-					backend: bref,
-				};
-				(bref, backend)
-			})
-			.unzip();
 		let be = refs.first().unwrap();
 		be_pol += 1;
 		Ok(TargetedPolicy {
@@ -828,6 +1053,8 @@ async fn convert_tcp_route(
 	let mut traffic_policy = TrafficPolicy {
 		timeout: timeout::Policy::default(),
 		retry: None,
+		load_balancer: None,
+		canary: None,
 	};
 	if let Some(pol) = policies {
 		let TCPFilterOrPolicy { backend_tls } = pol;
@@ -840,9 +1067,10 @@ async fn convert_tcp_route(
 		route_name,
 		rule_name,
 		hostnames,
-		backends: todo!(),
+		alpn_protocols,
+		backends: refs,
 	};
-	Ok((route, external_policies))
+	Ok((route, external_policies, external_backends))
 }
 
 fn to_simple_backend_and_ref(
@@ -862,20 +1090,37 @@ fn to_simple_backend_and_ref(
 }
 
 fn convert_tls_server(tls: LocalTLSServerConfig) -> anyhow::Result<TLSConfig> {
-	let cert = fs_err::read(tls.cert)?;
-	let cert_chain = crate::types::agent::parse_cert(&cert)?;
-	let key = fs_err::read(tls.key)?;
-	let private_key = crate::types::agent::parse_key(&key)?;
-
-	let mut ccb = ServerConfig::builder_with_provider(transport::tls::provider())
-		.with_protocol_versions(transport::tls::ALL_TLS_VERSIONS)
-		.expect("server config must be valid")
-		.with_no_client_auth()
-		.with_single_cert(cert_chain, private_key)?;
-	ccb.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
-	Ok(TLSConfig {
-		config: Arc::new(ccb),
-	})
+	convert_tls_server_with_alpn(tls, vec![b"h2".to_vec(), b"http/1.1".to_vec()])
+}
+
+// The ALPN protocols a TLS listener offers, so that clients can negotiate the protocols its routes
+// match on. Routes matching any protocol keep the default HTTP protocols available.
+fn tcp_route_alpn<'a>(routes: impl Iterator<Item = &'a LocalTCPRoute>) -> Vec<Vec<u8>> {
+	let mut protocols: Vec<Vec<u8>> = vec![];
+	let mut any = false;
+	for r in routes {
+		any |= r.alpn_protocols.is_empty();
+		protocols.extend(r.alpn_protocols.iter().map(|p| p.as_bytes().to_vec()));
+	}
+	if any {
+		protocols.extend([b"h2".to_vec(), b"http/1.1".to_vec()]);
+	}
+	protocols.into_iter().unique().collect()
+}
+
+fn convert_tls_server_with_alpn(
+	tls: LocalTLSServerConfig,
+	alpn_protocols: Vec<Vec<u8>>,
+) -> anyhow::Result<TLSConfig> {
+	let read_key =
+		|cert: &PathBuf, key: &PathBuf| parse_certified_key(&fs_err::read(cert)?, &fs_err::read(key)?);
+	let default = read_key(&tls.cert, &tls.key)?;
+	let sni = tls
+		.sni
+		.iter()
+		.map(|c| Ok((c.hostnames.clone(), read_key(&c.cert, &c.key)?)))
+		.collect::<anyhow::Result<Vec<_>>>()?;
+	TLSConfig::new(default, sni, alpn_protocols)
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -885,4 +1130,11 @@ pub struct LocalRequestMirror {
 	pub backend: SimpleLocalBackend,
 	// 0.0-1.0
 	pub percentage: f64,
+	/// Compare the mirrored response to the primary response, and record whether they match.
+	#[serde(default)]
+	pub compare: bool,
 }
+
+#[cfg(test)]
+#[path = "local_tests.rs"]
+mod tests;
@@ -0,0 +1,59 @@
+use assert_matches::assert_matches;
+use serde_json::json;
+
+use super::*;
+
+#[test]
+fn test_route_match_errors() {
+	let m: LocalRouteMatch = serde_json::from_value(json!({"path": {"exact": "/a"}})).unwrap();
+	assert_matches!(m, LocalRouteMatch::Http(_));
+	let m: LocalRouteMatch =
+		serde_json::from_value(json!({"grpc": {"service": "helloworld.Greeter"}})).unwrap();
+	assert_matches!(m, LocalRouteMatch::Grpc(_));
+
+	// Errors come from the selected variant, rather than a generic untagged enum error
+	let err = serde_json::from_value::<LocalRouteMatch>(json!({"grpc": {"sevice": "a"}}))
+		.unwrap_err()
+		.to_string();
+	assert!(err.contains("unknown field `sevice`"), "{err}");
+	let err = serde_json::from_value::<LocalRouteMatch>(json!({"path": {"exactly": "/a"}}))
+		.unwrap_err()
+		.to_string();
+	assert!(err.contains("unknown variant `exactly`"), "{err}");
+}
+
+#[tokio::test]
+async fn test_default_route_key() {
+	let config = crate::config::parse_config("{}".to_string(), None).unwrap();
+	let client = client::Client::new(&config.dns, None);
+	let normalized = NormalizedLocalConfig::from(
+		client,
+		r#"
+binds:
+- port: 8080
+  listeners:
+  - routes:
+    - name: default
+      backends:
+      - host: 127.0.0.1:9000
+    defaultBackend:
+      host: 127.0.0.1:9001
+"#,
+	)
+	.await
+	.unwrap();
+	let listener = normalized.binds[0].listeners.get_exactly_one().unwrap();
+	let route = listener.routes.iter().next().unwrap();
+	let default_route = listener.default_route.as_ref().unwrap();
+	// A route named "default" must not collide with the listener's default route
+	assert_ne!(route.key, default_route.key);
+	assert!(
+		default_route.key.ends_with("/$default"),
+		"{}",
+		default_route.key
+	);
+	// Inline backends are named after the route, so they must not collide either
+	let backends = normalized.backends.iter().map(|b| b.name()).collect_vec();
+	assert_eq!(backends.len(), 2);
+	assert_ne!(backends[0], backends[1]);
+}
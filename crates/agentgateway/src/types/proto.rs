@@ -19,6 +19,12 @@ pub mod agent {
 pub enum ProtoError {
 	#[error("failed to parse namespaced hostname: {0}")]
 	NamespacedHostnameParse(String),
+	#[error("invalid service reference {key:?} in {field}: {reason}")]
+	InvalidServiceReference {
+		field: &'static str,
+		key: String,
+		reason: String,
+	},
 	#[error("failed to parse address: {0}")]
 	AddressParse(#[from] net::AddrParseError),
 	#[error("failed to parse address, had {0} bytes")]
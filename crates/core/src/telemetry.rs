@@ -76,9 +76,15 @@ pub fn enabled(target: &'static str, level: &tracing::Level) -> bool {
 // log is like using tracing macros, but allows arbitrary k/v pairs. Tracing requires compile-time keys!
 // This does NOT respect tracing enable/log level; users can do that themselves before calling this function.
 pub fn log(level: &str, target: &str, kv: &[(&str, Option<ValueBag>)]) {
-	let Some((nb, json)) = NON_BLOCKING.get() else {
+	log_as(level, target, kv, None)
+}
+
+// log_as is like log, but allows overriding whether the line is written as JSON.
+pub fn log_as(level: &str, target: &str, kv: &[(&str, Option<ValueBag>)], json: Option<bool>) {
+	let Some((nb, default_json)) = NON_BLOCKING.get() else {
 		return;
 	};
+	let json = json.unwrap_or(*default_json);
 	thread_local! {
 		static BUF: RefCell<String> = const { RefCell::new(String::new()) };
 	}
@@ -99,7 +105,7 @@ pub fn log(level: &str, target: &str, kv: &[(&str, Option<ValueBag>)]) {
 			},
 		};
 
-		if *json {
+		if json {
 			let mut sx = serde_json::Serializer::new(StringWriteAdaptor::new(buf));
 			let mut s = sx.serialize_map(Some(kv.len() + 3))?;
 			s.serialize_entry("level", level)?;
@@ -1,8 +1,11 @@
 use std::collections::HashMap;
 use std::net::SocketAddr;
 
-use axum::body::Bytes;
-use axum::http::{HeaderMap, Method, Uri};
+use axum::body::{Body, Bytes};
+use axum::extract::{DefaultBodyLimit, Query};
+use axum::http::header::CONTENT_TYPE;
+use axum::http::{HeaderMap, Method, StatusCode, Uri};
+use axum::response::IntoResponse;
 use axum::{Json, Router};
 use serde::{Deserialize, Serialize};
 use tokio::net::TcpListener;
@@ -26,6 +29,7 @@ impl Server {
 		Self::run_with_port(0).await
 	}
 
+	/// Serves HTTP/1.1 and, with prior knowledge, cleartext HTTP/2 on the same port.
 	pub async fn run_with_port(port: u16) -> Self {
 		let listener = TcpListener::bind(("127.0.0.1", port))
 			.await
@@ -33,7 +37,17 @@ impl Server {
 		let address = listener.local_addr().expect("Failed to get local addr");
 		let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel();
 
-		let app = Router::new().route("/echo", axum::routing::any(echo_handler));
+		let app = Router::new()
+			.route("/echo", axum::routing::any(echo_handler))
+			.route("/payload", axum::routing::get(payload_handler))
+			.route(
+				"/echo/raw",
+				axum::routing::post(raw_echo_handler).layer(DefaultBodyLimit::disable()),
+			)
+			.route(
+				"/discard",
+				axum::routing::post(discard_handler).layer(DefaultBodyLimit::disable()),
+			);
 		let server = async move {
 			axum::serve(listener, app)
 				.with_graceful_shutdown(async move {
@@ -85,3 +99,47 @@ async fn echo_handler(
 	};
 	Json(resp)
 }
+
+// Size of the /payload response when no valid `size` is requested
+const DEFAULT_PAYLOAD_SIZE: usize = 1024;
+const MAX_PAYLOAD_SIZE: usize = 16 * 1024 * 1024;
+
+#[derive(Debug, Deserialize)]
+struct PayloadQuery {
+	// Kept as a string so an invalid size falls back to the default rather than rejecting the request
+	size: Option<String>,
+}
+
+/// Responds with a body of exactly `?size=` bytes (capped at MAX_PAYLOAD_SIZE).
+async fn payload_handler(Query(query): Query<PayloadQuery>) -> impl IntoResponse {
+	let size = query
+		.size
+		.and_then(|s| s.parse::<usize>().ok())
+		.unwrap_or(DEFAULT_PAYLOAD_SIZE)
+		.min(MAX_PAYLOAD_SIZE);
+	(
+		[(CONTENT_TYPE, "application/octet-stream")],
+		Bytes::from(vec![b'x'; size]),
+	)
+}
+
+/// Responds with the request body as-is, preserving its content type.
+async fn raw_echo_handler(headers: HeaderMap, body: Bytes) -> impl IntoResponse {
+	let mut resp = body.into_response();
+	if let Some(ct) = headers.get(CONTENT_TYPE) {
+		resp.headers_mut().insert(CONTENT_TYPE, ct.clone());
+	}
+	resp
+}
+
+/// Reads the full request body and drops it.
+async fn discard_handler(body: Body) -> StatusCode {
+	match axum::body::to_bytes(body, usize::MAX).await {
+		Ok(_) => StatusCode::NO_CONTENT,
+		Err(_) => StatusCode::BAD_REQUEST,
+	}
+}
+
+#[cfg(test)]
+#[path = "tests.rs"]
+mod tests;
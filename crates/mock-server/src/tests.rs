@@ -0,0 +1,118 @@
+use super::*;
+
+fn url(s: &Server, path: &str) -> String {
+	format!("http://{}{}", s.address(), path)
+}
+
+#[tokio::test]
+async fn payload_size() {
+	let s = Server::run().await;
+	let client = reqwest::Client::new();
+	for (query, want) in [
+		("", DEFAULT_PAYLOAD_SIZE),
+		("?size=0", 0),
+		("?size=10", 10),
+		("?size=abc", DEFAULT_PAYLOAD_SIZE),
+		("?size=-1", DEFAULT_PAYLOAD_SIZE),
+	] {
+		let resp = client
+			.get(url(&s, &format!("/payload{query}")))
+			.send()
+			.await
+			.unwrap();
+		assert_eq!(resp.status(), StatusCode::OK);
+		assert_eq!(
+			resp.headers().get(CONTENT_TYPE).unwrap(),
+			"application/octet-stream"
+		);
+		let body = resp.bytes().await.unwrap();
+		assert_eq!(body.len(), want, "query {query:?}");
+		assert!(body.iter().all(|b| *b == b'x'));
+	}
+	s.shutdown().await;
+}
+
+#[tokio::test]
+async fn payload_size_is_capped() {
+	let s = Server::run().await;
+	let body = reqwest::get(url(&s, &format!("/payload?size={}", MAX_PAYLOAD_SIZE + 1)))
+		.await
+		.unwrap()
+		.bytes()
+		.await
+		.unwrap();
+	assert_eq!(body.len(), MAX_PAYLOAD_SIZE);
+	s.shutdown().await;
+}
+
+#[tokio::test]
+async fn raw_echo() {
+	let s = Server::run().await;
+	let client = reqwest::Client::new();
+	let body: Vec<u8> = (0..=255).collect();
+	let resp = client
+		.post(url(&s, "/echo/raw"))
+		.header(CONTENT_TYPE, "application/x-test")
+		.body(body.clone())
+		.send()
+		.await
+		.unwrap();
+	assert_eq!(resp.status(), StatusCode::OK);
+	assert_eq!(
+		resp.headers().get(CONTENT_TYPE).unwrap(),
+		"application/x-test"
+	);
+	assert_eq!(resp.bytes().await.unwrap().as_ref(), body.as_slice());
+
+	// Larger than axum's default body limit
+	let large = vec![b'y'; 4 * 1024 * 1024];
+	let resp = client
+		.post(url(&s, "/echo/raw"))
+		.body(large.clone())
+		.send()
+		.await
+		.unwrap();
+	assert_eq!(resp.status(), StatusCode::OK);
+	assert!(resp.headers().get(CONTENT_TYPE).is_none());
+	assert_eq!(resp.bytes().await.unwrap().len(), large.len());
+	s.shutdown().await;
+}
+
+#[tokio::test]
+async fn discard() {
+	let s = Server::run().await;
+	let resp = reqwest::Client::new()
+		.post(url(&s, "/discard"))
+		.body(vec![b'z'; 4 * 1024 * 1024])
+		.send()
+		.await
+		.unwrap();
+	assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+	assert!(resp.bytes().await.unwrap().is_empty());
+	s.shutdown().await;
+}
+
+#[tokio::test]
+async fn http2_prior_knowledge() {
+	let s = Server::run().await;
+	let client = reqwest::Client::builder()
+		.http2_prior_knowledge()
+		.build()
+		.unwrap();
+	let resp = client
+		.post(url(&s, "/echo"))
+		.body("hello")
+		.send()
+		.await
+		.unwrap();
+	assert_eq!(resp.version(), reqwest::Version::HTTP_2);
+	let echo: EchoResponse = resp.json().await.unwrap();
+	assert_eq!(echo.method, "POST");
+	assert_eq!(echo.path, "/echo");
+	assert_eq!(echo.body, "hello");
+
+	// HTTP/1.1 keeps working on the same port
+	let resp = reqwest::get(url(&s, "/payload?size=1")).await.unwrap();
+	assert_eq!(resp.version(), reqwest::Version::HTTP_11);
+	s.shutdown().await;
+}